@@ -4,11 +4,79 @@ use std::{
 };
 
 fn main() {
+    #[cfg(all(
+        feature = "embed-frontend",
+        any(feature = "embed-resource", feature = "external-resource")
+    ))]
+    compile_error!(
+        "feature \"embed-frontend\" cannot be enabled with \"embed-resource\" or \"external-resource\""
+    );
+    #[cfg(feature = "embed-frontend")]
+    frontend();
     if env::var("CARGO_CFG_TARGET_OS").unwrap() == "android" {
         android();
     }
 }
 
+/// Copies the built admin UI to `$OUT_DIR/frontend` for `rust-embed`,
+/// with `.gz` and `.br` siblings of compressible files served to clients that accept them
+#[cfg(feature = "embed-frontend")]
+fn frontend() {
+    use std::io::Write;
+
+    const COMPRESSIBLE: [&str; 7] = ["html", "js", "css", "svg", "json", "txt", "map"];
+
+    fn walk(from: &Path, to: &Path) {
+        std::fs::create_dir_all(to).unwrap();
+        let Ok(entries) = std::fs::read_dir(from) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let target = to.join(entry.file_name());
+            if path.is_dir() {
+                walk(&path, &target);
+                continue;
+            }
+            let data = std::fs::read(&path).unwrap();
+            std::fs::write(&target, &data).unwrap();
+            let compressible = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| COMPRESSIBLE.contains(&e));
+            if !compressible || data.len() < 1024 {
+                continue;
+            }
+            let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+            gz.write_all(&data).unwrap();
+            let mut name = target.clone().into_os_string();
+            name.push(".gz");
+            std::fs::write(name, gz.finish().unwrap()).unwrap();
+            let mut br = Vec::new();
+            brotli::BrotliCompress(
+                &mut data.as_slice(),
+                &mut br,
+                &brotli::enc::BrotliEncoderParams::default(),
+            )
+            .unwrap();
+            let mut name = target.into_os_string();
+            name.push(".br");
+            std::fs::write(name, br).unwrap();
+        }
+    }
+
+    println!("cargo:rerun-if-changed=static");
+    let from = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("static");
+    let to = Path::new(&env::var("OUT_DIR").unwrap()).join("frontend");
+    if !from.exists() {
+        println!(
+            "cargo:warning=static/ not found, build the frontend first, the admin UI will be empty"
+        );
+    }
+    let _ = std::fs::remove_dir_all(&to);
+    walk(&from, &to);
+}
+
 fn android() {
     #[cfg(all(feature = "mimalloc", feature = "dhat-heap"))]
     compile_error!(
@@ -18,8 +86,14 @@ fn android() {
     compile_error!(
         "feature \"embed-resource\" and feature \"external-resource\" cannot be enabled at the same time"
     );
-    #[cfg(not(any(feature = "embed-resource", feature = "external-resource")))]
-    compile_error!("feature \"embed-resource\" or feature \"external-resource\" must be enabled");
+    #[cfg(not(any(
+        feature = "embed-resource",
+        feature = "external-resource",
+        feature = "embed-frontend"
+    )))]
+    compile_error!(
+        "feature \"embed-resource\", \"external-resource\" or \"embed-frontend\" must be enabled"
+    );
     #[cfg(not(any(feature = "portable", feature = "xdg")))]
     compile_error!("feature \"portable\" or feature \"xdg\" must be enabled");
     #[cfg(all(feature = "portable", feature = "xdg"))]
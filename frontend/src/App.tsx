@@ -13,6 +13,7 @@ import GeminiTabs from "./components/gemini";
 import StatusMessage from "./components/common/StatusMessage";
 import ErrorBoundary from "./components/common/ErrorBoundary";
 import { useAppContext } from "./context/AppContext";
+import { logout } from "./api";
 
 function App() {
   const { t } = useTranslation();
@@ -43,7 +44,7 @@ function App() {
 
   // Function to handle logout
   const handleLogout = () => {
-    localStorage.removeItem("authToken");
+    logout();
     setIsAuthenticated(false);
   };
 
@@ -23,6 +23,66 @@ export async function validateAuthToken(token: string) {
   return response.ok;
 }
 
+/**
+ * Exchanges the admin or viewer password for a short-lived session token
+ * and stores the token instead of the password
+ * @param password The password to log in with
+ * @returns Whether the password was accepted
+ */
+export async function login(password: string) {
+  const response = await fetch("/api/auth/login", {
+    method: "POST",
+    headers: {
+      "Content-Type": "application/json",
+    },
+    body: JSON.stringify({ password }),
+  });
+
+  if (!response.ok) {
+    return false;
+  }
+  const session = await response.json();
+  localStorage.setItem("authToken", session.token);
+  return true;
+}
+
+/**
+ * Renews the stored session token before it expires
+ * @returns Whether the token was renewed
+ */
+export async function refreshSession() {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/auth/refresh", {
+    method: "POST",
+    headers: {
+      Authorization: `Bearer ${token}`,
+    },
+  });
+
+  if (!response.ok) {
+    return false;
+  }
+  const session = await response.json();
+  localStorage.setItem("authToken", session.token);
+  return true;
+}
+
+/**
+ * Invalidates the stored session token on the server and forgets it
+ */
+export async function logout() {
+  const token = localStorage.getItem("authToken") || "";
+  localStorage.removeItem("authToken");
+  if (token) {
+    await fetch("/api/auth/logout", {
+      method: "POST",
+      headers: {
+        Authorization: `Bearer ${token}`,
+      },
+    }).catch(() => undefined);
+  }
+}
+
 /**
  * Sends a cookie to the server.
  * @param cookie The cookie string to send
@@ -159,6 +219,7 @@ export async function getConfig() {
  * @param configData The config data to save
  */
 import type { ConfigData } from "../types/config.types";
+import type { EffectiveSetting, RecentError } from "../types/api.types";
 
 export async function saveConfig(configData: ConfigData) {
   const token = localStorage.getItem("authToken") || "";
@@ -178,13 +239,25 @@ export async function saveConfig(configData: ConfigData) {
 
   if (!response.ok) {
     // Try to include server error message when available for easier debugging
+    let serverMsg = "";
     try {
       const data = await response.json();
-      const serverMsg = typeof data?.error === "string" ? ` - ${data.error}` : "";
-      throw new Error(`Failed to save config: ${response.status}${serverMsg}`);
+      if (typeof data?.error === "string") {
+        serverMsg = ` - ${data.error}`;
+      }
+      // invalid configs come with the settings that failed validation
+      if (Array.isArray(data?.errors) && data.errors.length > 0) {
+        const fields = data.errors
+          .map((e: { field: string; message: string }) =>
+            e.field ? `${e.field}: ${e.message}` : e.message
+          )
+          .join("; ");
+        serverMsg += ` (${fields})`;
+      }
     } catch (_) {
-      throw new Error(`Failed to save config: ${response.status}`);
+      // not a JSON error body
     }
+    throw new Error(`Failed to save config: ${response.status}${serverMsg}`);
   }
 
   return response;
@@ -284,6 +357,49 @@ export async function storageExport() {
   return await response.json();
 }
 
+export async function storageBackup() {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/storage/backup", {
+    method: "POST",
+    headers: {
+      Authorization: `Bearer ${token}`,
+    },
+  });
+  if (!response.ok) {
+    throw new Error(`Backup failed: ${response.status}`);
+  }
+  return await response.json();
+}
+
+export async function poolSnapshot() {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/pool/snapshot", {
+    headers: {
+      Authorization: `Bearer ${token}`,
+    },
+  });
+  if (!response.ok) {
+    throw new Error(`Snapshot failed: ${response.status}`);
+  }
+  return await response.json();
+}
+
+export async function poolRestore(snapshot: unknown) {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/pool/restore", {
+    method: "POST",
+    headers: {
+      "Content-Type": "application/json",
+      Authorization: `Bearer ${token}`,
+    },
+    body: JSON.stringify(snapshot),
+  });
+  if (!response.ok) {
+    throw new Error(`Restore failed: ${response.status}`);
+  }
+  return await response.json();
+}
+
 export async function storageStatus() {
   const token = localStorage.getItem("authToken") || "";
   const response = await fetch("/api/storage/status", {
@@ -296,3 +412,37 @@ export async function storageStatus() {
   }
   return await response.json();
 }
+
+/**
+ * Fetches the last failed upstream attempts, newest first
+ */
+export async function getRecentErrors(): Promise<RecentError[]> {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/errors/recent", {
+    headers: {
+      Authorization: `Bearer ${token}`,
+    },
+  });
+  if (!response.ok) {
+    throw new Error(`Recent errors failed: ${response.status}`);
+  }
+  return await response.json();
+}
+
+/**
+ * Fetches every setting of the running config with the layer it comes from
+ */
+export async function getEffectiveConfig(): Promise<
+  Record<string, EffectiveSetting>
+> {
+  const token = localStorage.getItem("authToken") || "";
+  const response = await fetch("/api/config/effective", {
+    headers: {
+      Authorization: `Bearer ${token}`,
+    },
+  });
+  if (!response.ok) {
+    throw new Error(`Failed to fetch effective config: ${response.status}`);
+  }
+  return await response.json();
+}
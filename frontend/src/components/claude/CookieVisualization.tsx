@@ -96,6 +96,8 @@ const CookieVisualization: React.FC = () => {
         | "sonnet_output_tokens"
         | "opus_input_tokens"
         | "opus_output_tokens"
+        | "cache_read_input_tokens"
+        | "cache_creation_input_tokens"
       >>;
       showSonnet: boolean;
       showOpus: boolean;
@@ -108,6 +110,8 @@ const CookieVisualization: React.FC = () => {
       sonnet_output_tokens: x.sonnet_output_tokens ?? 0,
       opus_input_tokens: x.opus_input_tokens ?? 0,
       opus_output_tokens: x.opus_output_tokens ?? 0,
+      cache_read_input_tokens: x.cache_read_input_tokens ?? 0,
+      cache_creation_input_tokens: x.cache_creation_input_tokens ?? 0,
     });
 
     const sReq = toReq(s);
@@ -189,6 +193,12 @@ const CookieVisualization: React.FC = () => {
                 <Row label={t("cookieStatus.usage.opusOutput") as string} value={b.opus_output_tokens} />
               </div>
             )}
+            {(b.cache_read_input_tokens > 0 || b.cache_creation_input_tokens > 0) && (
+              <div className="flex gap-3 flex-wrap pl-1 text-gray-500">
+                <Row label={t("cookieStatus.usage.cacheRead") as string} value={b.cache_read_input_tokens} />
+                <Row label={t("cookieStatus.usage.cacheCreation") as string} value={b.cache_creation_input_tokens} />
+              </div>
+            )}
           </div>
         ))}
       </div>
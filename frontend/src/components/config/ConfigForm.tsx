@@ -63,6 +63,26 @@ const ConfigForm: React.FC<ConfigFormProps> = ({ config, onChange }) => {
             label={t("config.sections.app.autoUpdate")}
           />
         </div>
+        <div className="space-y-2 mt-4">
+          <label
+            htmlFor="update_channel"
+            className="block text-sm font-medium text-gray-300 mb-1"
+          >
+            {t("config.sections.app.updateChannel")}
+          </label>
+          <select
+            id="update_channel"
+            name="update_channel"
+            value={config.update_channel ?? "stable"}
+            onChange={onChange}
+            className="w-full p-4 bg-gray-700 border border-gray-600 rounded-md focus:ring-2 focus:ring-cyan-500 focus:border-cyan-500 text-sm text-gray-200"
+          >
+            <option value="stable">
+              {t("config.sections.app.channelStable")}
+            </option>
+            <option value="beta">{t("config.sections.app.channelBeta")}</option>
+          </select>
+        </div>
       </ConfigSection>
 
       {/* Network Settings Section */}
@@ -95,6 +115,36 @@ const ConfigForm: React.FC<ConfigFormProps> = ({ config, onChange }) => {
           placeholder={t("config.sections.network.proxyPlaceholder")}
         />
 
+        <FormInput
+          id="claude_proxy"
+          name="claude_proxy"
+          type="text"
+          value={config.claude_proxy || ""}
+          onChange={onChange}
+          label={t("config.sections.network.claudeProxy")}
+          placeholder={t("config.sections.network.providerProxyPlaceholder")}
+        />
+
+        <FormInput
+          id="gemini_proxy"
+          name="gemini_proxy"
+          type="text"
+          value={config.gemini_proxy || ""}
+          onChange={onChange}
+          label={t("config.sections.network.geminiProxy")}
+          placeholder={t("config.sections.network.providerProxyPlaceholder")}
+        />
+
+        <FormInput
+          id="vertex_proxy"
+          name="vertex_proxy"
+          type="text"
+          value={config.vertex_proxy || ""}
+          onChange={onChange}
+          label={t("config.sections.network.vertexProxy")}
+          placeholder={t("config.sections.network.providerProxyPlaceholder")}
+        />
+
         <FormInput
           id="rproxy"
           name="rproxy"
@@ -133,12 +183,26 @@ const ConfigForm: React.FC<ConfigFormProps> = ({ config, onChange }) => {
             label={t("config.sections.api.webSearch")}
           />
 
+          <ConfigCheckbox
+            name="extract_artifacts"
+            checked={!!config.extract_artifacts}
+            onChange={onChange}
+            label={t("config.sections.api.extractArtifacts")}
+          />
+
           <ConfigCheckbox
             name="enable_web_count_tokens"
             checked={!!config.enable_web_count_tokens}
             onChange={onChange}
             label={t("config.sections.api.webCountTokens")}
           />
+
+          <ConfigCheckbox
+            name="offline_count_tokens"
+            checked={!!config.offline_count_tokens}
+            onChange={onChange}
+            label={t("config.sections.api.offlineCountTokens")}
+          />
         </div>
       </ConfigSection>
 
@@ -1,6 +1,13 @@
 import React, { useState, useEffect } from "react";
 import { useTranslation } from "react-i18next";
-import { getConfig, saveConfig, storageImport, storageExport, storageStatus } from "../../api";
+import {
+  getConfig,
+  saveConfig,
+  storageBackup,
+  storageImport,
+  storageExport,
+  storageStatus,
+} from "../../api";
 import { toast } from "react-hot-toast";
 import { ConfigData, StorageStatus } from "../../types/config.types";
 import Button from "../common/Button";
@@ -139,7 +146,15 @@ const ConfigTab: React.FC = () => {
       return;
     } // Handle empty strings for nullable fields
     if (
-      ["proxy", "rproxy", "custom_h", "custom_a"].includes(name) &&
+      [
+        "proxy",
+        "claude_proxy",
+        "gemini_proxy",
+        "vertex_proxy",
+        "rproxy",
+        "custom_h",
+        "custom_a",
+      ].includes(name) &&
       value === ""
     ) {
       setConfig({ ...config, [name]: null });
@@ -246,6 +261,25 @@ const ConfigTab: React.FC = () => {
             >
               {t("config.storage.export")}
             </Button>
+            <Button
+              onClick={async () => {
+                try {
+                  const res = await storageBackup();
+                  toast.success(
+                    t("config.storage.backupSuccess", {
+                      name: res.name,
+                      location: res.location,
+                    }),
+                  );
+                } catch (e) {
+                  toast.error((e as Error).message);
+                }
+              }}
+              variant="secondary"
+              className="py-1 px-3"
+            >
+              {t("config.storage.backup")}
+            </Button>
           </div>
         </div>
       </div>
@@ -12,6 +12,7 @@ import DeleteButton from "./DeleteButton";
 // Default empty state
 const emptyKeyStatus: KeyStatusInfo = {
   valid: [],
+  invalid: [],
 };
 
 const KeyVisualization: React.FC = () => {
@@ -31,6 +32,7 @@ const KeyVisualization: React.FC = () => {
       const data = await getKeyStatus();
       const safeData: KeyStatusInfo = {
         valid: Array.isArray(data?.valid) ? data.valid : [],
+        invalid: Array.isArray(data?.invalid) ? data.invalid : [],
       };
       setKeyStatus(safeData);
     } catch (err) {
@@ -88,7 +90,7 @@ const KeyVisualization: React.FC = () => {
   };
 
   // Calculate total key count
-  const totalKeys = keyStatus.valid.length;
+  const totalKeys = keyStatus.valid.length + keyStatus.invalid.length;
 
   return (
     <div className="space-y-6 w-full">
@@ -185,6 +187,23 @@ const KeyVisualization: React.FC = () => {
                     <KeyValue keyString={status.key} />
                   </div>
                   <div className="flex items-center space-x-3">
+                    {status.quarantined_until ? (
+                      <span className="text-yellow-300 bg-yellow-900/30 px-2 py-0.5 rounded text-xs">
+                        {t("keyStatus.quarantined", {
+                          time: new Date(
+                            status.quarantined_until * 1000
+                          ).toLocaleString(),
+                        })}
+                      </span>
+                    ) : null}
+                    {(status.input_tokens || status.output_tokens) ? (
+                      <span className="text-blue-300 bg-blue-900/30 px-2 py-0.5 rounded text-xs">
+                        {t("keyStatus.tokens", {
+                          input: status.input_tokens || 0,
+                          output: status.output_tokens || 0,
+                        })}
+                      </span>
+                    ) : null}
                     {typeof status.count_403 === "number" && (
                       <span className="text-orange-400 bg-orange-900/30 px-2 py-0.5 rounded text-xs">
                         403: {status.count_403}
@@ -202,6 +221,39 @@ const KeyVisualization: React.FC = () => {
         )}
       </div>
 
+      {/* Invalid Keys Section */}
+      {keyStatus.invalid.length > 0 && (
+        <div className="rounded-lg border border-red-800 bg-red-900/20 p-4">
+          <h4 className="text-red-300 font-medium mb-3">
+            {t("keyStatus.sections.invalid")}
+          </h4>
+          <div className="space-y-2">
+            {keyStatus.invalid.map((status, index) => (
+              <div
+                key={index}
+                className="py-2 text-sm text-gray-300 flex flex-wrap justify-between items-start border-b border-red-800/30 last:border-0"
+              >
+                <div className="text-red-300 flex-grow mr-4 min-w-0 mb-1 sm:mb-0">
+                  <KeyValue keyString={status.key} />
+                </div>
+                <div className="flex items-center space-x-3">
+                  <span className="text-red-400 bg-red-900/30 px-2 py-0.5 rounded text-xs">
+                    {t("keyStatus.failedProbes", {
+                      count: status.failed_probes || 0,
+                    })}
+                  </span>
+                  <DeleteButton
+                    keyString={status.key}
+                    onDelete={handleDeleteKey}
+                    isDeleting={deletingKey === status.key}
+                  />
+                </div>
+              </div>
+            ))}
+          </div>
+        </div>
+      )}
+
       {/* No Keys Help Text */}
       {!loading && totalKeys === 0 && (
         <div className="mt-4 px-4 py-3 bg-gray-800/50 border border-gray-700 rounded-md">
@@ -6,7 +6,10 @@ import React, {
   useEffect,
   ReactNode,
 } from "react";
-import { getVersion } from "../api";
+import { getVersion, refreshSession } from "../api";
+
+// Session tokens live 15 minutes, renew them well before that
+const SESSION_REFRESH_MS = 5 * 60 * 1000;
 
 interface AppContextType {
   version: string;
@@ -69,6 +72,21 @@ export const AppProvider: React.FC<AppProviderProps> = ({ children }) => {
     checkAuth();
   }, []);
 
+  useEffect(() => {
+    if (!isAuthenticated) {
+      return;
+    }
+    const timer = setInterval(async () => {
+      // a stored password from older versions cannot be refreshed and stays as is
+      const token = localStorage.getItem("authToken") || "";
+      if (token.split(".").length === 3 && !(await refreshSession())) {
+        localStorage.removeItem("authToken");
+        setIsAuthenticated(false);
+      }
+    }, SESSION_REFRESH_MS);
+    return () => clearInterval(timer);
+  }, [isAuthenticated]);
+
   return (
     <AppContext.Provider
       value={{
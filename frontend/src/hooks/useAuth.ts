@@ -1,6 +1,10 @@
 // frontend/src/hooks/useAuth.ts
 import { useState, useEffect } from "react";
-import { validateAuthToken } from "../api";
+import {
+  login as loginSession,
+  logout as logoutSession,
+  validateAuthToken,
+} from "../api";
 import { maskToken } from "../utils/formatters";
 
 export const useAuth = (onAuthenticated?: (status: boolean) => void) => {
@@ -65,13 +69,11 @@ export const useAuth = (onAuthenticated?: (status: boolean) => void) => {
     setError("");
 
     try {
-      // Use the centralized API function instead of direct fetch
-      const isValid = await validateAuthToken(token);
+      // Only the session token is kept, never the password itself
+      const isValid = await loginSession(token);
 
       if (isValid) {
-        // Save token to localStorage immediately upon successful validation
-        localStorage.setItem("authToken", token);
-        setSavedToken(maskToken(token));
+        setSavedToken(maskToken(localStorage.getItem("authToken") || ""));
         setAuthToken("");
         setIsAuthenticated(true);
         if (onAuthenticated) {
@@ -96,7 +98,7 @@ export const useAuth = (onAuthenticated?: (status: boolean) => void) => {
   };
 
   const logout = () => {
-    localStorage.removeItem("authToken");
+    logoutSession();
     setSavedToken("");
     setIsAuthenticated(false);
     if (onAuthenticated) {
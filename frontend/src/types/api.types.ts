@@ -11,3 +11,24 @@ export interface CookieSubmitRequest {
 export interface VersionResponse {
   version: string;
 }
+
+export interface RecentError {
+  at: number;
+  provider:
+    | "claude_web"
+    | "claude_code"
+    | "gemini_ai_studio"
+    | "vertex"
+    | "vertex_claude"
+    | "bedrock"
+    | "openai_upstream";
+  credential: string | null;
+  kind: string;
+  code: number | null;
+  message: string;
+}
+
+export interface EffectiveSetting {
+  value: unknown;
+  source: "default" | "file" | "env" | "db" | "runtime";
+}
@@ -9,18 +9,24 @@ export interface ConfigData {
   // App settings
   check_update: boolean;
   auto_update: boolean;
+  update_channel?: "stable" | "beta";
 
   // Network settings
   password: string;
   admin_password: string;
   proxy: string | null;
+  claude_proxy?: string | null;
+  gemini_proxy?: string | null;
+  vertex_proxy?: string | null;
   rproxy: string | null;
 
   // API settings
   max_retries: number;
   preserve_chats: boolean;
   web_search: boolean;
+  extract_artifacts?: boolean;
   enable_web_count_tokens: boolean;
+  offline_count_tokens?: boolean;
 
   // Cookie settings
   skip_first_warning: boolean;
@@ -74,6 +80,7 @@ export interface PersistenceConfig {
   mode: PersistenceMode;
   database_url?: string | null;
   sqlite_path?: string | null;
+  auto_migrate?: boolean;
 }
 
 export interface ConfigState {
@@ -2,10 +2,13 @@
 export interface UsageBreakdown {
   total_input_tokens?: number;
   total_output_tokens?: number;
+  requests?: number;
   sonnet_input_tokens?: number;
   sonnet_output_tokens?: number;
   opus_input_tokens?: number;
   opus_output_tokens?: number;
+  cache_read_input_tokens?: number;
+  cache_creation_input_tokens?: number;
 }
 
 export interface CookieStatus {
@@ -13,6 +16,18 @@ export interface CookieStatus {
   reset_time: number | null;
   supports_claude_1m?: boolean | null;
   count_tokens_allowed?: boolean | null;
+  // Chat capable organizations, and the one pinned via /api/cookies/org
+  organizations?: ClaudeOrg[];
+  pinned_org?: string | null;
+  // Operator tags, set via /api/cookies/tags
+  tags?: string[];
+  // Latest health events, oldest first
+  health?: HealthEvent[];
+  // Flags found and dismissed on the account, set via clear_flags
+  cleared_flags?: ClearedFlags | null;
+  // Lifetime 403 and 429 answers
+  count_403?: number;
+  count_429?: number;
   // New usage buckets
   session_usage?: UsageBreakdown;
   weekly_usage?: UsageBreakdown;
@@ -28,6 +43,24 @@ export interface CookieStatus {
   seven_day_opus_resets_at?: string | null;
 }
 
+export interface HealthEvent {
+  at: number;
+  kind: "token_refresh_failed";
+  message: string;
+}
+
+export interface ClearedFlags {
+  at: number;
+  present: string[];
+  dismissed: string[];
+}
+
+export interface ClaudeOrg {
+  uuid: string;
+  name?: string;
+  capabilities?: string[];
+}
+
 export interface UselessCookie {
   cookie: string;
   reason: unknown;
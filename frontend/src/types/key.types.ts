@@ -2,10 +2,18 @@
 export interface KeyStatus {
   key: string;
   count_403: number;
+  input_tokens?: number;
+  output_tokens?: number;
+  total_requests?: number;
+  total_429?: number;
+  consecutive_failures?: number;
+  quarantined_until?: number | null;
+  failed_probes?: number;
 }
 
 export interface KeyStatusInfo {
   valid: KeyStatus[];
+  invalid: KeyStatus[];
 }
 
 export interface KeyFormState {
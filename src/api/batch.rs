@@ -0,0 +1,109 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::{
+        HeaderMap, HeaderName, HeaderValue, StatusCode,
+        header::{AUTHORIZATION, CONTENT_TYPE},
+    },
+};
+use serde_json::{Value, json};
+
+use crate::{
+    error::ClewdrError,
+    middleware::{
+        claude::{COOKIE_TAGS_HEADER, PRIORITY_HEADER, TEMPLATE_HEADER},
+        tenant::Tenant,
+    },
+    persistence::{BatchJob, BatchStatus},
+    services::batch::{self, BatchTarget, MAX_BATCH_ITEMS},
+};
+
+/// Headers of the submission passed on to every request of the job
+const FORWARDED_HEADERS: [&str; 4] = [
+    "authorization",
+    TEMPLATE_HEADER,
+    COOKIE_TAGS_HEADER,
+    PRIORITY_HEADER,
+];
+
+/// Owner of the jobs of a caller, by its tenant and bearer token
+fn caller(tenant: Option<Extension<Tenant>>, headers: &HeaderMap) -> String {
+    let key = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    batch::owner(tenant.map(|Extension(Tenant(t))| t).as_deref(), key)
+}
+
+/// Job in OpenAI batch object style, with the outcome of each request when `results`
+fn batch_object(job: &BatchJob, results: bool) -> Value {
+    let count = |status: BatchStatus| job.items.iter().filter(|i| i.status == status).count();
+    let mut object = json!({
+        "id": job.id,
+        "object": "batch",
+        "status": job.status,
+        "created_at": job.created_at,
+        "completed_at": job.completed_at,
+        "request_counts": {
+            "total": job.items.len(),
+            "completed": count(BatchStatus::Completed),
+            "failed": count(BatchStatus::Failed),
+        },
+    });
+    if results {
+        object["results"] = json!(job.items);
+    }
+    object
+}
+
+/// Submits OpenAI chat completion requests to run in the background
+///
+/// The body is a JSON array of requests, which are sent without streaming and with the
+/// `batch` priority unless `x-clewdr-priority` says otherwise. Answers with the job to poll
+/// at `GET /v1/batch/{id}` with the same key.
+pub async fn api_post_batch(
+    State(target): State<BatchTarget>,
+    tenant: Option<Extension<Tenant>>,
+    headers: HeaderMap,
+    Json(requests): Json<Vec<Value>>,
+) -> Result<(StatusCode, Json<Value>), ClewdrError> {
+    if requests.is_empty() {
+        return Err(ClewdrError::BadRequest {
+            msg: "A batch needs at least one request",
+        });
+    }
+    if requests.len() > MAX_BATCH_ITEMS {
+        return Err(ClewdrError::BadRequest {
+            msg: "A batch holds at most 1000 requests",
+        });
+    }
+    let mut forwarded = HeaderMap::new();
+    for name in FORWARDED_HEADERS {
+        if let Some(value) = headers.get(name) {
+            forwarded.insert(HeaderName::from_static(name), value.to_owned());
+        }
+    }
+    forwarded
+        .entry(PRIORITY_HEADER)
+        .or_insert(HeaderValue::from_static("batch"));
+    forwarded.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    let job = batch::submit(target, caller(tenant, &headers), forwarded, requests);
+    Ok((StatusCode::ACCEPTED, Json(batch_object(&job, false))))
+}
+
+/// Status of a batch job and the outcome of each of its requests, in submission order
+///
+/// Jobs submitted with another key or for another tenant are not found.
+pub async fn api_get_batch(
+    Path(id): Path<String>,
+    tenant: Option<Extension<Tenant>>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, ClewdrError> {
+    let job = batch::get(&id, &caller(tenant, &headers))
+        .await?
+        .ok_or_else(|| ClewdrError::PathNotFound {
+            msg: format!("Batch job {id} not found"),
+        })?;
+    Ok(Json(batch_object(&job, true)))
+}
@@ -0,0 +1,23 @@
+use std::sync::Arc;
+
+use axum::{Json, extract::State, http::HeaderMap, response::Response};
+use serde_json::Value;
+
+use crate::{
+    error::ClewdrError,
+    middleware::claude::anthropic_betas,
+    providers::{LLMProvider, bedrock::BedrockProvider, passthrough::AnthropicInvocation},
+};
+
+pub async fn api_bedrock(
+    State(provider): State<Arc<BedrockProvider>>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<Response, ClewdrError> {
+    provider
+        .invoke(AnthropicInvocation {
+            body,
+            betas: anthropic_betas(&headers),
+        })
+        .await
+}
@@ -0,0 +1,214 @@
+use std::time::{Duration, Instant};
+
+use async_stream::stream;
+use axum::{
+    Extension, Router,
+    body::{Body, to_bytes},
+    extract::{
+        Query, Request, State, WebSocketUpgrade,
+        ws::{Message, WebSocket},
+    },
+    http::{
+        HeaderMap, HeaderName,
+        header::{AUTHORIZATION, CONTENT_TYPE},
+    },
+    response::Response,
+};
+use eventsource_stream::Eventsource;
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tower::ServiceExt;
+
+use crate::{
+    claude_web_state::conversation::CONVERSATION_HEADER,
+    config::CLEWDR_CONFIG,
+    error::ClewdrError,
+    middleware::{
+        claude::{COOKIE_TAGS_HEADER, PRIORITY_HEADER, TEMPLATE_HEADER},
+        tenant::Tenant,
+    },
+};
+
+/// Interval between keepalive pings
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// Sockets that answered no ping for this long are closed
+const PONG_TIMEOUT: Duration = Duration::from_secs(90);
+/// Headers of the upgrade request passed on to every chat request
+const FORWARDED_HEADERS: [&str; 4] = [
+    CONVERSATION_HEADER,
+    TEMPLATE_HEADER,
+    COOKIE_TAGS_HEADER,
+    PRIORITY_HEADER,
+];
+
+/// Chat completions endpoint served over a WebSocket
+///
+/// Requests are dispatched to the endpoint's regular HTTP service, so they pass
+/// the same parameter handling, format conversion and response rules as SSE clients.
+#[derive(Clone)]
+pub struct ChatSocketTarget {
+    service: Router,
+    path: &'static str,
+}
+
+impl ChatSocketTarget {
+    /// # Arguments
+    /// * `service` - Router serving the chat completions endpoint
+    /// * `path` - Path of the chat completions endpoint within `service`
+    pub fn new(service: Router, path: &'static str) -> Self {
+        Self { service, path }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatSocketQuery {
+    /// API key, browsers cannot set headers on WebSocket requests
+    #[serde(default)]
+    token: Option<String>,
+}
+
+/// OpenAI chat completions over WebSocket
+///
+/// Accepts the API key either as a Bearer header or a `token` query parameter.
+/// Each text frame from the client is an OpenAI chat completion request, which is
+/// always streamed: every chunk is sent as a text frame, followed by a `[DONE]` frame.
+/// Failed requests are answered with a single frame holding the error body.
+/// One request is served at a time per socket.
+pub async fn api_chat_ws(
+    State(target): State<ChatSocketTarget>,
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    tenant: Option<Extension<Tenant>>,
+    Query(query): Query<ChatSocketQuery>,
+) -> Result<Response, ClewdrError> {
+    let tenant = tenant.map(|Extension(Tenant(name))| name);
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.to_string())
+        .or(query.token)
+        .filter(|t| CLEWDR_CONFIG.load().api_auth(tenant.as_deref(), t))
+        .ok_or(ClewdrError::InvalidAuth)?;
+    let mut forwarded = HeaderMap::new();
+    for name in FORWARDED_HEADERS {
+        if let Some(value) = headers.get(name) {
+            forwarded.insert(HeaderName::from_static(name), value.to_owned());
+        }
+    }
+    forwarded.insert(AUTHORIZATION, format!("Bearer {token}").parse()?);
+    forwarded.insert(CONTENT_TYPE, "application/json".parse()?);
+    Ok(ws.on_upgrade(move |socket| serve_chat(socket, target, forwarded)))
+}
+
+async fn serve_chat(mut socket: WebSocket, target: ChatSocketTarget, headers: HeaderMap) {
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    let mut last_pong = Instant::now();
+    let mut frames = None;
+    loop {
+        tokio::select! {
+            _ = ping.tick() => {
+                if last_pong.elapsed() > PONG_TIMEOUT
+                    || socket.send(Message::Ping(Default::default())).await.is_err()
+                {
+                    break;
+                }
+            }
+            frame = next_frame(&mut frames) => {
+                let text = match frame {
+                    Some(text) => text,
+                    None => {
+                        frames = None;
+                        continue;
+                    }
+                };
+                if socket.send(Message::Text(text.into())).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Pong(_))) => {
+                        last_pong = Instant::now();
+                        continue;
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let reply = if frames.is_some() {
+                    Err("A request is already in progress on this socket")
+                } else {
+                    chat_request(&target, &headers, text.as_str())
+                };
+                match reply {
+                    Ok(req) => frames = Some(Box::pin(chat_frames(target.service.to_owned(), req))),
+                    Err(msg) => {
+                        let error = json!({ "error": { "type": "BadRequest", "message": msg } });
+                        if socket.send(Message::Text(error.to_string().into())).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Next frame of the request in progress, pending forever while there is none
+async fn next_frame<S>(frames: &mut Option<S>) -> Option<String>
+where
+    S: Stream<Item = String> + Unpin,
+{
+    match frames {
+        Some(frames) => frames.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Builds the streaming HTTP request for a chat request received on the socket
+fn chat_request(
+    target: &ChatSocketTarget,
+    headers: &HeaderMap,
+    text: &str,
+) -> Result<Request, &'static str> {
+    let Ok(Value::Object(mut body)) = serde_json::from_str::<Value>(text) else {
+        return Err("Requests must be JSON objects");
+    };
+    body.insert("stream".to_string(), Value::Bool(true));
+    let mut req = Request::post(target.path)
+        .body(Body::from(Value::Object(body).to_string()))
+        .map_err(|_| "Invalid request")?;
+    req.headers_mut().extend(headers.to_owned());
+    Ok(req)
+}
+
+/// Runs a chat request and yields the text frames for the client
+fn chat_frames(service: Router, req: Request) -> impl Stream<Item = String> + Send {
+    stream! {
+        let resp = service.oneshot(req).await.unwrap_or_else(|e| match e {});
+        let is_stream = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("text/event-stream"));
+        if !resp.status().is_success() || !is_stream {
+            let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap_or_default();
+            yield String::from_utf8_lossy(&body).into_owned();
+            return;
+        }
+        for await event in resp.into_body().into_data_stream().eventsource() {
+            match event {
+                Ok(event) if event.data == "[DONE]" => break,
+                Ok(event) => yield event.data,
+                Err(e) => {
+                    yield json!({ "error": { "type": "EventSourceError", "message": e.to_string() } })
+                        .to_string();
+                    return;
+                }
+            }
+        }
+        yield "[DONE]".to_string();
+    }
+}
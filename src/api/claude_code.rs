@@ -1,24 +1,25 @@
 use std::sync::Arc;
 
-use axum::{Extension, extract::State, response::Response};
+use axum::{extract::State, response::Response};
 
 use crate::{
     error::ClewdrError,
-    middleware::claude::{ClaudeCodePreprocess, ClaudeContext},
+    middleware::claude::ClaudeCodePreprocess,
     providers::{
         LLMProvider,
-        claude::{ClaudeCodeProvider, ClaudeInvocation, ClaudeProviderResponse},
+        claude::{ClaudeCodeProvider, ClaudeInvocation, ClaudeProviderResponse, invoke_candidates},
     },
 };
 
 pub async fn api_claude_code(
     State(provider): State<Arc<ClaudeCodeProvider>>,
     ClaudeCodePreprocess(params, context): ClaudeCodePreprocess,
-) -> Result<(Extension<ClaudeContext>, Response), ClewdrError> {
-    let ClaudeProviderResponse { context, response } = provider
-        .invoke(ClaudeInvocation::messages(params, context.clone()))
-        .await?;
-    Ok((Extension(context), response))
+) -> Result<Response, ClewdrError> {
+    invoke_candidates(
+        provider.as_ref(),
+        ClaudeInvocation::messages(params, context),
+    )
+    .await
 }
 
 pub async fn api_claude_code_count_tokens(
@@ -1,14 +1,16 @@
 use std::sync::Arc;
 
-use axum::{Extension, extract::State, response::Response};
+use axum::{
+    Json,
+    extract::{Multipart, State},
+    response::Response,
+};
 
 use crate::{
+    claude_web_state::files::FileObject,
     error::ClewdrError,
-    middleware::claude::{ClaudeContext, ClaudeWebPreprocess},
-    providers::{
-        LLMProvider,
-        claude::{ClaudeInvocation, ClaudeProviderResponse, ClaudeWebProvider},
-    },
+    middleware::claude::ClaudeWebPreprocess,
+    providers::claude::{ClaudeInvocation, ClaudeWebProvider, invoke_candidates},
 };
 /// Axum handler for the API messages
 /// Main API endpoint for handling message requests to Claude
@@ -24,9 +26,47 @@ use crate::{
 pub async fn api_claude_web(
     State(provider): State<Arc<ClaudeWebProvider>>,
     ClaudeWebPreprocess(params, context): ClaudeWebPreprocess,
-) -> Result<(Extension<ClaudeContext>, Response), ClewdrError> {
-    let ClaudeProviderResponse { context, response } = provider
-        .invoke(ClaudeInvocation::messages(params, context.clone()))
-        .await?;
-    Ok((Extension(context), response))
+) -> Result<Response, ClewdrError> {
+    invoke_candidates(
+        provider.as_ref(),
+        ClaudeInvocation::messages(params, context),
+    )
+    .await
+}
+
+/// OpenAI files API
+/// Uploads a document to Claude.ai, the returned id can be referenced by `file` content parts
+///
+/// # Arguments
+/// * `provider` - Claude web provider used to pick a cookie and organization
+/// * `multipart` - Form with a `file` field and an optional `purpose`
+///
+/// # Returns
+/// * `FileObject` - The uploaded file in OpenAI format
+pub async fn api_post_file(
+    State(provider): State<Arc<ClaudeWebProvider>>,
+    mut multipart: Multipart,
+) -> Result<Json<FileObject>, ClewdrError> {
+    let invalid = |_| ClewdrError::BadRequest {
+        msg: "Invalid multipart body",
+    };
+    let mut file = None;
+    let mut purpose = "assistants".to_string();
+    while let Some(field) = multipart.next_field().await.map_err(invalid)? {
+        match field.name() {
+            Some("file") => {
+                let name = field.file_name().unwrap_or("file").to_string();
+                let bytes = field.bytes().await.map_err(invalid)?;
+                file = Some((name, bytes));
+            }
+            Some("purpose") => purpose = field.text().await.map_err(invalid)?,
+            _ => {}
+        }
+    }
+    let Some((name, bytes)) = file.filter(|(_, b)| !b.is_empty()) else {
+        return Err(ClewdrError::BadRequest {
+            msg: "Missing file",
+        });
+    };
+    provider.upload_file(name, bytes, purpose).await.map(Json)
 }
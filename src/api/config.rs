@@ -1,10 +1,19 @@
-use axum::Json;
+use std::collections::BTreeMap;
+
+use axum::{Json, extract::Path, http::StatusCode};
 use axum_auth::AuthBearer;
 use serde_json::json;
 
-// no direct StatusCode usage here; ApiError handles responses
 use super::error::ApiError;
-use crate::config::{CLEWDR_CONFIG, ClewdrConfig};
+use crate::{
+    config::{
+        AccessControlConfig, CLEWDR_CONFIG, ClewdrConfig, ConfigIssue, EffectiveSetting,
+        PromptTemplate, ResponseRule, RoutingRule, effective_config,
+    },
+    middleware::rules::rule_hits,
+    persistence,
+    services::session,
+};
 
 /// API endpoint to retrieve the application configuration
 /// Returns the config as JSON with sensitive fields removed
@@ -27,6 +36,24 @@ pub async fn api_get_config(
         obj.remove("cookie_array");
         obj.remove("wasted_cookie");
         obj.remove("gemini_keys");
+        obj.remove("invalid_keys");
+        if let Some(tenants) = obj.get_mut("tenants").and_then(|t| t.as_array_mut()) {
+            for tenant in tenants.iter_mut().filter_map(|t| t.as_object_mut()) {
+                tenant.remove("cookie_array");
+                tenant.remove("wasted_cookie");
+                tenant.remove("gemini_keys");
+                tenant.remove("invalid_keys");
+            }
+        }
+        if let Some(upstreams) = obj
+            .get_mut("openai_upstreams")
+            .and_then(|u| u.as_array_mut())
+        {
+            for upstream in upstreams.iter_mut().filter_map(|u| u.as_object_mut()) {
+                upstream.remove("keys");
+                upstream.remove("invalid_keys");
+            }
+        }
         if let Some(vertex) = obj.get_mut("vertex").and_then(|v| v.as_object_mut()) {
             // Do not leak sensitive fields to the frontend. Use null instead of a string
             // placeholder so that round-tripping the config back to the server deserializes
@@ -39,6 +66,74 @@ pub async fn api_get_config(
     Ok(Json(config_json))
 }
 
+/// API endpoint to explain where every setting of the running config comes from
+///
+/// Each dotted setting path maps to its value and source: `default`, `file`, `env`, `db` or
+/// `runtime`. Secrets, passwords and URL credentials are redacted, the pools are left out.
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<BTreeMap<String, EffectiveSetting>>, ApiError>` - Settings with their source
+pub async fn api_get_config_effective(
+    AuthBearer(t): AuthBearer,
+) -> Result<Json<BTreeMap<String, EffectiveSetting>>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    Ok(Json(effective_config(&CLEWDR_CONFIG.load())))
+}
+
+/// Parses a candidate config and lists the settings that fail validation
+fn parse_candidate(c: serde_json::Value) -> Result<ClewdrConfig, Vec<ConfigIssue>> {
+    let c = serde_json::from_value::<ClewdrConfig>(c).map_err(|e| {
+        vec![ConfigIssue {
+            field: String::new(),
+            message: e.to_string(),
+        }]
+    })?;
+    let issues = c.issues();
+    if issues.is_empty() {
+        Ok(c)
+    } else {
+        Err(issues)
+    }
+}
+
+/// API endpoint to check a configuration without applying it
+///
+/// Besides the checks of a real save, the database of the candidate is connected to.
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `c` - Candidate configuration data as JSON
+///
+/// # Returns
+/// * `Result<Json<serde_json::Value>, ApiError>` - `valid` and the per-field `errors`
+pub async fn api_post_config_validate(
+    AuthBearer(t): AuthBearer,
+    Json(c): Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let errors = match parse_candidate(c) {
+        Ok(c) => match persistence::probe(&c).await {
+            Ok(()) => vec![],
+            Err(e) => vec![ConfigIssue {
+                field: "persistence.database_url".to_string(),
+                message: format!("Database unreachable: {e}"),
+            }],
+        },
+        Err(issues) => issues,
+    };
+    Ok(Json(json!({
+        "valid": errors.is_empty(),
+        "errors": errors,
+    })))
+}
+
 /// API endpoint to update the application configuration
 /// Validates and stores the provided configuration
 ///
@@ -47,15 +142,19 @@ pub async fn api_get_config(
 /// * `c` - New configuration data as JSON
 ///
 /// # Returns
-/// * `Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)>` - Success message on success, error response on failure
+/// * `Result<Json<serde_json::Value>, ApiError>` - Success message on success, 422 with the
+///   per-field `errors` if the config is invalid
 pub async fn api_post_config(
     AuthBearer(t): AuthBearer,
-    Json(c): Json<ClewdrConfig>,
+    Json(c): Json<serde_json::Value>,
 ) -> Result<Json<serde_json::Value>, ApiError> {
     if !CLEWDR_CONFIG.load().admin_auth(&t) {
         return Err(ApiError::unauthorized());
     }
-    let c = c.validate();
+    let c = parse_candidate(c)
+        .map_err(ApiError::invalid_config)?
+        .validate();
+    let old = CLEWDR_CONFIG.load_full();
     // update config
     CLEWDR_CONFIG.rcu(|old_c| {
         let mut new_c = ClewdrConfig::clone(&c);
@@ -63,6 +162,13 @@ pub async fn api_post_config(
         new_c.cookie_array = old_c.cookie_array.to_owned();
         new_c.wasted_cookie = old_c.wasted_cookie.to_owned();
         new_c.gemini_keys = old_c.gemini_keys.to_owned();
+        new_c.invalid_keys = old_c.invalid_keys.to_owned();
+        for tenant in new_c.tenants.iter_mut() {
+            tenant.keep_pools(&old_c.tenants);
+        }
+        for upstream in new_c.openai_upstreams.iter_mut() {
+            upstream.keep_pools(&old_c.openai_upstreams);
+        }
         // Vertex is not managed by the config page anymore. Always preserve existing vertex config.
         new_c.vertex = old_c.vertex.clone();
         new_c
@@ -70,9 +176,231 @@ pub async fn api_post_config(
     if let Err(e) = CLEWDR_CONFIG.load().save().await {
         return Err(ApiError::internal(format!("Failed to save config: {}", e)));
     }
+    // sessions opened with a replaced password must not outlive it
+    old.changed_roles(&c)
+        .into_iter()
+        .for_each(session::end_sessions);
 
     Ok(Json(serde_json::json!({
         "message": "Config updated successfully",
         "config": c
     })))
 }
+
+/// API endpoint to retrieve the IP access control lists
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<AccessControlConfig>, ApiError>` - Current allowlist, denylist and proxy header setting
+pub async fn api_get_access_control(
+    AuthBearer(t): AuthBearer,
+) -> Result<Json<AccessControlConfig>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    Ok(Json(CLEWDR_CONFIG.load().access_control.to_owned()))
+}
+
+/// API endpoint to replace the IP access control lists at runtime
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `acl` - New access control settings, networks in CIDR notation or bare addresses
+///
+/// # Returns
+/// * `Result<Json<AccessControlConfig>, ApiError>` - The stored settings
+pub async fn api_post_access_control(
+    AuthBearer(t): AuthBearer,
+    Json(acl): Json<AccessControlConfig>,
+) -> Result<Json<AccessControlConfig>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    CLEWDR_CONFIG.rcu(|old_c| {
+        let mut new_c = ClewdrConfig::clone(old_c);
+        new_c.access_control = acl.to_owned();
+        new_c
+    });
+    if let Err(e) = CLEWDR_CONFIG.load().save().await {
+        return Err(ApiError::internal(format!("Failed to save config: {}", e)));
+    }
+    Ok(Json(acl))
+}
+
+/// API endpoint to list the response rules with the number of times each matched
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<serde_json::Value>, ApiError>` - Rules in order, each with a `hits` counter
+pub async fn api_get_response_rules(
+    AuthBearer(t): AuthBearer,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let hits = rule_hits();
+    let rules = CLEWDR_CONFIG
+        .load()
+        .response_rules
+        .iter()
+        .map(|rule| {
+            let mut value = json!(rule);
+            value["hits"] = json!(hits.get(rule.name()).copied().unwrap_or_default());
+            value
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(json!(rules)))
+}
+
+/// API endpoint to replace the response rules at runtime
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `rules` - New rules, applied in order
+///
+/// # Returns
+/// * `Result<Json<Vec<ResponseRule>>, ApiError>` - The stored rules
+pub async fn api_put_response_rules(
+    AuthBearer(t): AuthBearer,
+    Json(rules): Json<Vec<ResponseRule>>,
+) -> Result<Json<Vec<ResponseRule>>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    CLEWDR_CONFIG.rcu(|old_c| {
+        let mut new_c = ClewdrConfig::clone(old_c);
+        new_c.response_rules = rules.to_owned();
+        new_c
+    });
+    if let Err(e) = CLEWDR_CONFIG.load().save().await {
+        return Err(ApiError::internal(format!("Failed to save config: {}", e)));
+    }
+    Ok(Json(rules))
+}
+
+/// API endpoint to get the request routing rules
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<Vec<RoutingRule>>, ApiError>` - Rules in order, the first match applies
+pub async fn api_get_routing_rules(
+    AuthBearer(t): AuthBearer,
+) -> Result<Json<Vec<RoutingRule>>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    Ok(Json(CLEWDR_CONFIG.load().routing_rules.to_owned()))
+}
+
+/// API endpoint to replace the request routing rules at runtime
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `rules` - New rules, checked in order
+///
+/// # Returns
+/// * `Result<Json<Vec<RoutingRule>>, ApiError>` - The stored rules
+pub async fn api_put_routing_rules(
+    AuthBearer(t): AuthBearer,
+    Json(rules): Json<Vec<RoutingRule>>,
+) -> Result<Json<Vec<RoutingRule>>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    CLEWDR_CONFIG.rcu(|old_c| {
+        let mut new_c = ClewdrConfig::clone(old_c);
+        new_c.routing_rules = rules.to_owned();
+        new_c
+    });
+    if let Err(e) = CLEWDR_CONFIG.load().save().await {
+        return Err(ApiError::internal(format!("Failed to save config: {}", e)));
+    }
+    Ok(Json(rules))
+}
+
+/// API endpoint to list the named prompt templates
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<BTreeMap<String, PromptTemplate>>, ApiError>` - All templates by name
+pub async fn api_get_templates(
+    AuthBearer(t): AuthBearer,
+) -> Result<Json<BTreeMap<String, PromptTemplate>>, ApiError> {
+    if !CLEWDR_CONFIG.load().viewer_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    Ok(Json(CLEWDR_CONFIG.load().prompt_templates.to_owned()))
+}
+
+/// API endpoint to create or replace a named prompt template
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `name` - Template name, used in the `x-clewdr-template` header or as `model@name`
+/// * `template` - Template texts, may contain `{date}`, `{model}` and `{user_name}`
+///
+/// # Returns
+/// * `Result<Json<PromptTemplate>, ApiError>` - The stored template
+pub async fn api_put_template(
+    AuthBearer(t): AuthBearer,
+    Path(name): Path<String>,
+    Json(template): Json<PromptTemplate>,
+) -> Result<Json<PromptTemplate>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let name = name.trim().to_string();
+    if name.is_empty() || name.contains('@') {
+        return Err(ApiError::bad_request(
+            "Template name must be non-empty and must not contain '@'",
+        ));
+    }
+    CLEWDR_CONFIG.rcu(|old_c| {
+        let mut new_c = ClewdrConfig::clone(old_c);
+        new_c
+            .prompt_templates
+            .insert(name.to_owned(), template.to_owned());
+        new_c
+    });
+    if let Err(e) = CLEWDR_CONFIG.load().save().await {
+        return Err(ApiError::internal(format!("Failed to save config: {}", e)));
+    }
+    Ok(Json(template))
+}
+
+/// API endpoint to delete a named prompt template
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `name` - Template name
+///
+/// # Returns
+/// * `Result<StatusCode, ApiError>` - 204 on success, 404 if no such template exists
+pub async fn api_delete_template(
+    AuthBearer(t): AuthBearer,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    if !CLEWDR_CONFIG.load().prompt_templates.contains_key(&name) {
+        return Err(ApiError::not_found("Prompt template not found"));
+    }
+    CLEWDR_CONFIG.rcu(|old_c| {
+        let mut new_c = ClewdrConfig::clone(old_c);
+        new_c.prompt_templates.remove(&name);
+        new_c
+    });
+    if let Err(e) = CLEWDR_CONFIG.load().save().await {
+        return Err(ApiError::internal(format!("Failed to save config: {}", e)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
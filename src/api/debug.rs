@@ -0,0 +1,75 @@
+use axum::Json;
+use axum_auth::AuthBearer;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::error::ApiError;
+use crate::{config::CLEWDR_CONFIG, services::capture, utils::tokenizer::Tokenizer};
+
+/// Captures kept at most, asking for more is capped
+const MAX_ARMED: u32 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct CaptureRequest {
+    /// Number of upstream requests to capture, 0 stops capturing
+    count: u32,
+}
+
+/// Captured upstream requests and responses, newest first
+///
+/// Cookie and key values are redacted.
+pub async fn api_get_captures(AuthBearer(t): AuthBearer) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    Ok(Json(capture::status()))
+}
+
+/// Starts capturing the next `count` upstream requests
+///
+/// Captures are also written to `captures/` in the log directory unless `no_fs` is set.
+pub async fn api_post_captures(
+    AuthBearer(t): AuthBearer,
+    Json(req): Json<CaptureRequest>,
+) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    capture::arm(req.count.min(MAX_ARMED));
+    Ok(Json(capture::status()))
+}
+
+/// Stops capturing and drops the stored captures
+pub async fn api_delete_captures(AuthBearer(t): AuthBearer) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    capture::arm(0);
+    capture::clear();
+    Ok(Json(capture::status()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenizeRequest {
+    text: String,
+    /// Model whose tokenizer is used
+    #[serde(default)]
+    model: String,
+    /// Tokenizer used instead of the model's
+    #[serde(default)]
+    tokenizer: Option<Tokenizer>,
+}
+
+/// Tokens of a text as ClewdR estimates them, with the pieces of the encoding
+///
+/// Shows how `count_tokens` fallbacks, usage estimates and `model_limits` see a prompt.
+pub async fn api_post_tokenize(Json(req): Json<TokenizeRequest>) -> Json<Value> {
+    let tokenizer = req
+        .tokenizer
+        .unwrap_or_else(|| Tokenizer::for_model(&req.model));
+    Json(json!({
+        "tokenizer": tokenizer,
+        "tokens": tokenizer.count(&req.text),
+        "pieces": tokenizer.split(&req.text),
+    }))
+}
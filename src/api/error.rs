@@ -23,6 +23,12 @@ impl ApiError {
             body: serde_json::json!({"error": msg.into()}),
         }
     }
+    pub fn not_found(msg: impl Into<String>) -> Self {
+        Self {
+            code: StatusCode::NOT_FOUND,
+            body: serde_json::json!({"error": msg.into()}),
+        }
+    }
     pub fn internal(msg: impl Into<String>) -> Self {
         Self {
             code: StatusCode::INTERNAL_SERVER_ERROR,
@@ -35,6 +41,13 @@ impl ApiError {
             body: serde_json::json!({"error": msg.into()}),
         }
     }
+    /// Settings that failed validation, each with its field and a message
+    pub fn invalid_config(errors: impl serde::Serialize) -> Self {
+        Self {
+            code: StatusCode::UNPROCESSABLE_ENTITY,
+            body: serde_json::json!({"error": "Invalid config", "errors": errors}),
+        }
+    }
     pub fn service_unavailable(msg: impl Into<String>) -> Self {
         Self {
             code: StatusCode::SERVICE_UNAVAILABLE,
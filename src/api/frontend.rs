@@ -0,0 +1,116 @@
+use axum::{
+    body::Body,
+    http::{
+        HeaderMap, HeaderValue, StatusCode, Uri,
+        header::{
+            ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, ETAG, IF_NONE_MATCH,
+            VARY,
+        },
+    },
+    response::{IntoResponse, Response},
+};
+use rust_embed::Embed;
+
+/// Admin UI copied into the binary by `build.rs`, with precompressed `.br` and `.gz` variants
+#[derive(Embed)]
+#[folder = "$OUT_DIR/frontend"]
+struct Frontend;
+
+/// Vite emits content hashed file names here, so they can be cached forever
+const HASHED_DIR: &str = "assets/";
+
+/// Serves the embedded admin UI
+///
+/// Paths without a file extension that match no file get `index.html`, so client side
+/// routes survive a reload. Precompressed variants are picked by `Accept-Encoding`.
+pub async fn serve_frontend(uri: Uri, headers: HeaderMap) -> Response {
+    let path = uri.path().trim_start_matches('/');
+    let path = match path {
+        "" => "index.html",
+        p if Frontend::get(p).is_some() => p,
+        p if p.rsplit('/').next().is_some_and(|name| name.contains('.')) => {
+            return StatusCode::NOT_FOUND.into_response();
+        }
+        _ => "index.html",
+    };
+    let Some(original) = Frontend::get(path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let accept = headers
+        .get(ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let (encoding, data) = [("br", "br"), ("gzip", "gz")]
+        .into_iter()
+        .filter(|(encoding, _)| accepts(accept, encoding))
+        .find_map(|(encoding, ext)| {
+            Frontend::get(&format!("{path}.{ext}")).map(|f| (Some(encoding), f.data))
+        })
+        .unwrap_or((None, original.data));
+
+    let hash = original.metadata.sha256_hash();
+    let etag = format!(
+        "\"{}{}\"",
+        hash[..8]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>(),
+        encoding.map(|e| format!("-{e}")).unwrap_or_default()
+    );
+    let cache_control = if path.starts_with(HASHED_DIR) {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    };
+    let mut res = if headers
+        .get(IF_NONE_MATCH)
+        .is_some_and(|v| v.as_bytes() == etag.as_bytes())
+    {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        let mut res = Response::new(Body::from(data.into_owned()));
+        if let Ok(mime) = HeaderValue::from_str(original.metadata.mimetype()) {
+            res.headers_mut().insert(CONTENT_TYPE, mime);
+        }
+        if let Some(encoding) = encoding {
+            res.headers_mut()
+                .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+        }
+        res
+    };
+    let headers = res.headers_mut();
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+    headers.insert(VARY, HeaderValue::from_static("accept-encoding"));
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        headers.insert(ETAG, etag);
+    }
+    res
+}
+
+/// Whether an `Accept-Encoding` value allows `encoding`, `q=0` opts out
+fn accepts(accept: &str, encoding: &str) -> bool {
+    accept.split(',').any(|part| {
+        let mut params = part.split(';').map(str::trim);
+        params
+            .next()
+            .is_some_and(|name| name.eq_ignore_ascii_case(encoding))
+            && !params.any(|p| {
+                p.strip_prefix("q=")
+                    .is_some_and(|q| q.parse::<f32>() == Ok(0.0))
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::accepts;
+
+    #[test]
+    fn accept_encoding_is_parsed() {
+        assert!(accepts("gzip, deflate, br", "br"));
+        assert!(accepts("gzip;q=0.8, br;q=1.0", "gzip"));
+        assert!(!accepts("gzip, br;q=0", "br"));
+        assert!(!accepts("identity", "gzip"));
+        assert!(!accepts("", "br"));
+    }
+}
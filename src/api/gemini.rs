@@ -1,18 +1,35 @@
-use axum::{extract::State, response::Response};
+use axum::{
+    Json,
+    extract::{FromRequest, Query, Request, State},
+    http::{HeaderMap, header::HOST},
+    response::Response,
+};
+use bytes::Bytes;
+use serde::Deserialize;
+use serde_json::Value;
 
 use crate::{
     error::ClewdrError,
-    middleware::gemini::{GeminiOaiPreprocess, GeminiPreprocess},
+    gemini_state::{GeminiApiFormat, files::GeminiUpload},
+    middleware::gemini::{
+        GeminiArgs, GeminiAuxPreprocess, GeminiContext, GeminiOaiPreprocess, GeminiPreprocess,
+    },
     providers::{
         LLMProvider,
         gemini::{GeminiInvocation, GeminiPayload, GeminiProviders},
     },
+    types::gemini::image::ImageGenerationRequest,
 };
 
 pub async fn api_post_gemini(
     State(providers): State<GeminiProviders>,
-    GeminiPreprocess(body, ctx): GeminiPreprocess,
+    req: Request,
 ) -> Result<Response, ClewdrError> {
+    if req.uri().path().ends_with(":countTokens") {
+        let GeminiAuxPreprocess(body, ctx) = GeminiAuxPreprocess::from_request(req, &()).await?;
+        return invoke_auxiliary(providers, body, ctx).await;
+    }
+    let GeminiPreprocess(body, ctx) = GeminiPreprocess::from_request(req, &()).await?;
     if ctx.vertex {
         providers
             .vertex()
@@ -54,3 +71,119 @@ pub async fn api_post_gemini_oai(
             .await
     }
 }
+
+/// OpenAI images API backed by Gemini / Imagen image generation
+pub async fn api_post_gemini_image(
+    State(providers): State<GeminiProviders>,
+    Json(body): Json<ImageGenerationRequest>,
+) -> Result<Response, ClewdrError> {
+    let ctx = GeminiContext {
+        model: body.model().to_string(),
+        vertex: false,
+        stream: false,
+        path: String::new(),
+        query: Default::default(),
+        api_format: GeminiApiFormat::OpenAI,
+    };
+    providers
+        .ai_studio()
+        .invoke(GeminiInvocation {
+            payload: GeminiPayload::Image(body),
+            context: ctx,
+        })
+        .await
+}
+
+/// `countTokens` for clients authenticating with a bearer token
+///
+/// Takes a native `countTokens` body with an extra `model` field naming the model.
+pub async fn api_post_gemini_count_tokens(
+    State(providers): State<GeminiProviders>,
+    Json(mut body): Json<Value>,
+) -> Result<Response, ClewdrError> {
+    let model = body
+        .as_object_mut()
+        .and_then(|b| b.remove("model"))
+        .and_then(|m| {
+            m.as_str()
+                .map(|m| m.trim_start_matches("models/").to_string())
+        })
+        .filter(|m| !m.is_empty())
+        .ok_or(ClewdrError::BadRequest {
+            msg: "Model not found in request body",
+        })?;
+    let ctx = GeminiContext {
+        path: format!("models/{model}:countTokens"),
+        model,
+        vertex: false,
+        stream: false,
+        query: Default::default(),
+        api_format: GeminiApiFormat::Gemini,
+    };
+    invoke_auxiliary(providers, Some(body), ctx).await
+}
+
+/// `models` list and `models/{model}` get, used by the official SDKs
+pub async fn api_get_gemini(
+    State(providers): State<GeminiProviders>,
+    GeminiAuxPreprocess(body, ctx): GeminiAuxPreprocess,
+) -> Result<Response, ClewdrError> {
+    invoke_auxiliary(providers, body, ctx).await
+}
+
+#[derive(Deserialize)]
+pub struct UploadQuery {
+    upload_id: Option<String>,
+}
+
+/// Resumable uploads of the Files API, `upload/v1beta/files`
+///
+/// Uploads go to AI Studio only, Vertex AI reads files from Cloud Storage.
+pub async fn api_post_gemini_upload(
+    State(providers): State<GeminiProviders>,
+    query: GeminiArgs,
+    Query(UploadQuery { upload_id }): Query<UploadQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ClewdrError> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let scheme = header("x-forwarded-proto").unwrap_or("http");
+    let host =
+        header("x-forwarded-host")
+            .or(header(HOST.as_str()))
+            .ok_or(ClewdrError::BadRequest {
+                msg: "Missing Host header",
+            })?;
+    let base = format!("{scheme}://{host}/v1");
+    let ctx = GeminiContext {
+        model: String::new(),
+        vertex: false,
+        stream: false,
+        path: "upload/v1beta/files".to_string(),
+        query,
+        api_format: GeminiApiFormat::Gemini,
+    };
+    let upload = GeminiUpload {
+        upload_id,
+        headers,
+        body,
+        base,
+    };
+    providers.ai_studio().upload(&ctx, upload).await
+}
+
+async fn invoke_auxiliary(
+    providers: GeminiProviders,
+    body: Option<Value>,
+    ctx: GeminiContext,
+) -> Result<Response, ClewdrError> {
+    let request = GeminiInvocation {
+        payload: GeminiPayload::Auxiliary(body),
+        context: ctx,
+    };
+    if request.context.vertex {
+        providers.vertex().invoke(request).await
+    } else {
+        providers.ai_studio().invoke(request).await
+    }
+}
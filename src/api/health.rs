@@ -0,0 +1,87 @@
+use axum::{Json, extract::State, http::StatusCode};
+use serde_json::{Value, json};
+
+use crate::{
+    config::CLEWDR_CONFIG,
+    persistence,
+    services::{
+        breaker,
+        cookie_actor::CookieActorHandle,
+        endpoints,
+        key_actor::KeyActorHandle,
+        recent_errors::{self, RecentError},
+    },
+};
+
+/// Actors the readiness probe inspects
+#[derive(Clone)]
+pub struct HealthState {
+    pub cookie_actor_handle: CookieActorHandle,
+    pub key_actor_handle: KeyActorHandle,
+}
+
+/// Liveness probe, answers as long as the server loop is running
+pub async fn api_healthz() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+/// Readiness probe
+///
+/// Checks that the database is reachable in DB mode, and that every provider with
+/// credentials configured has at least one usable cookie or key.
+///
+/// # Returns
+/// * `(StatusCode, Json<Value>)` - 200 when ready, 503 otherwise, with the result of each check
+pub async fn api_readyz(State(s): State<HealthState>) -> (StatusCode, Json<Value>) {
+    let storage = persistence::storage();
+    let storage_ok = !storage.is_enabled()
+        || storage
+            .status()
+            .await
+            .is_ok_and(|v| v["healthy"].as_bool().unwrap_or_default());
+    // a provider without credentials is not in use and cannot make the instance unready
+    let claude_ok = match s.cookie_actor_handle.get_status().await {
+        Ok(status) => {
+            !status.valid.is_empty() || (status.exhausted.is_empty() && status.invalid.is_empty())
+        }
+        Err(_) => false,
+    };
+    let gemini_ok = match s.key_actor_handle.get_status().await {
+        Ok(status) => !status.valid.is_empty() || CLEWDR_CONFIG.load().gemini_keys.is_empty(),
+        Err(_) => false,
+    };
+    let ready = storage_ok && claude_ok && gemini_ok;
+    let code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        code,
+        Json(json!({
+            "status": if ready { "ok" } else { "unavailable" },
+            "checks": {
+                // reading the config here would have panicked if it failed to load
+                "config": true,
+                "storage": storage_ok,
+                "claude": claude_ok,
+                "gemini": gemini_ok,
+            }
+        })),
+    )
+}
+
+/// State of the circuit breaker of each provider
+pub async fn api_get_breakers() -> Json<Value> {
+    Json(breaker::status())
+}
+
+/// Last failed upstream attempts, newest first, for the dashboard
+pub async fn api_get_recent_errors() -> Json<Vec<RecentError>> {
+    Json(recent_errors::recent())
+}
+
+/// Health of the upstream endpoints of each provider
+pub async fn api_get_endpoints() -> Json<Value> {
+    Json(endpoints::status())
+}
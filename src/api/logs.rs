@@ -0,0 +1,135 @@
+use axum::{
+    Json,
+    extract::{
+        Query, WebSocketUpgrade,
+        ws::{Message, WebSocket},
+    },
+    http::{HeaderMap, header::AUTHORIZATION},
+    response::Response,
+};
+use axum_auth::AuthBearer;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{Level, info};
+
+use super::error::ApiError;
+use crate::{
+    config::CLEWDR_CONFIG,
+    services::{log_broadcast, log_level},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct LogStreamQuery {
+    /// Admin token, browsers cannot set headers on WebSocket requests
+    #[serde(default)]
+    token: Option<String>,
+    /// Most verbose level to forward, defaults to `info`
+    #[serde(default)]
+    level: Option<String>,
+}
+
+/// Live log stream over WebSocket
+///
+/// Accepts the admin token either as a Bearer header or a `token` query parameter.
+/// Each message is a JSON encoded log entry with timestamp, level, target and message.
+pub async fn api_logs_stream(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    Query(query): Query<LogStreamQuery>,
+) -> Result<Response, ApiError> {
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.to_string())
+        .or(query.token);
+    if !token.is_some_and(|t| CLEWDR_CONFIG.load().admin_auth(&t)) {
+        return Err(ApiError::unauthorized());
+    }
+    let level = match query.level {
+        Some(l) => l
+            .parse::<Level>()
+            .map_err(|_| ApiError::bad_request(format!("Invalid log level: {l}")))?,
+        None => Level::INFO,
+    };
+    Ok(ws.on_upgrade(move |socket| stream_logs(socket, level)))
+}
+
+async fn stream_logs(mut socket: WebSocket, level: Level) {
+    let mut rx = log_broadcast::subscribe();
+    loop {
+        tokio::select! {
+            entry = rx.recv() => {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                // more verbose levels compare greater
+                if entry.level.parse::<Level>().is_ok_and(|l| l > level) {
+                    continue;
+                }
+                let Ok(text) = serde_json::to_string(&entry) else {
+                    continue;
+                };
+                if socket.send(Message::Text(text.into())).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogLevel {
+    /// Filter directives in `RUST_LOG` syntax, e.g. `info,clewdr::claude_code_state=trace`
+    directives: String,
+}
+
+/// API endpoint to get the log filter directives in effect
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+///
+/// # Returns
+/// * `Result<Json<LogLevel>, ApiError>` - Current directives, empty when only the default level applies
+pub async fn api_get_log_level(AuthBearer(t): AuthBearer) -> Result<Json<LogLevel>, ApiError> {
+    if !CLEWDR_CONFIG.load().viewer_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    Ok(Json(LogLevel {
+        directives: log_level::current(),
+    }))
+}
+
+/// API endpoint to change the log filter of the console and file outputs at runtime
+///
+/// The change is not persisted, a restart falls back to `RUST_LOG`.
+///
+/// # Arguments
+/// * `t` - Auth bearer token for admin authentication
+/// * `level` - New filter directives
+///
+/// # Returns
+/// * `Result<Json<LogLevel>, ApiError>` - The applied directives
+pub async fn api_put_log_level(
+    AuthBearer(t): AuthBearer,
+    Json(level): Json<LogLevel>,
+) -> Result<Json<LogLevel>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let directives = level.directives.trim();
+    log_level::set(directives)
+        .map_err(|e| ApiError::bad_request(format!("Invalid log directives: {e}")))?;
+    info!("Log filter changed to `{}`", directives);
+    Ok(Json(LogLevel {
+        directives: directives.to_string(),
+    }))
+}
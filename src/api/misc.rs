@@ -5,13 +5,13 @@ use std::{
 
 use axum::{
     Json,
-    extract::{Query, State},
+    extract::{Path, Query, State},
     http::HeaderMap,
 };
 use axum_auth::AuthBearer;
 use moka::sync::Cache;
 use serde::{Deserialize, Serialize};
-use serde_json::{Value, json};
+use serde_json::{Map, Value, json};
 use tracing::{error, info, warn};
 use wreq::StatusCode;
 use yup_oauth2::ServiceAccountKey;
@@ -19,43 +19,46 @@ use yup_oauth2::ServiceAccountKey;
 use super::error::ApiError;
 use crate::{
     VERSION_INFO,
+    claude_code_state::ClaudeCodeState,
+    claude_web_state::{ClaudeWebState, settings::AccountSettings},
     config::{
-        CLAUDE_CONSOLE_ENDPOINT, CLAUDE_ENDPOINT, CLEWDR_CONFIG, ClewdrConfig, CookieStatus,
-        KeyStatus,
+        CLAUDE_CONSOLE_ENDPOINT, CLAUDE_ENDPOINT, CLEWDR_CONFIG, ClearedFlags, ClewdrConfig,
+        CookieStatus, GeminiKey, KeyRejection, KeyStatus, ProxyTarget, parse_tags,
     },
-    persistence,
+    error::ClewdrError,
+    gemini_state, persistence,
     services::{
-        cookie_actor::CookieActorHandle,
-        key_actor::{KeyActorHandle, KeyStatusInfo},
+        cookie_actor::CookieActorHandle, key_actor::KeyActorHandle, models::ModelRegistry, quota,
     },
 };
 
 const DB_UNAVAILABLE_MESSAGE: &str = "Database storage is unavailable";
 
-/// Cache entry for cookie status responses
+/// Utilization last probed from Claude.ai for a cookie
 #[derive(Clone)]
-struct CookieStatusCache {
-    data: Value,
+struct ProbedUtilization {
+    fields: Map<String, Value>,
     timestamp: u64,
 }
 
 /// Query parameters for cookie status endpoint
 #[derive(Deserialize)]
 pub struct CookieStatusQuery {
+    /// Probe Claude.ai for live utilization instead of serving the stored view
     #[serde(default)]
     refresh: bool,
 }
 
-/// Global cache for cookie status responses (TTL: 5 minutes)
-static COOKIES_CACHE: LazyLock<Cache<String, CookieStatusCache>> = LazyLock::new(|| {
+/// Probed utilization per cookie, merged into the stored view until it expires
+static UTILIZATION_CACHE: LazyLock<Cache<String, ProbedUtilization>> = LazyLock::new(|| {
     Cache::builder()
-        .max_capacity(1)
-        .time_to_live(Duration::from_secs(300)) // 5 minutes
+        .max_capacity(10_000)
+        .time_to_live(Duration::from_secs(60 * 60))
         .build()
 });
 
-/// Cache key for cookie status
-const COOKIE_STATUS_CACHE_KEY: &str = "all_cookies";
+/// Cookies probed at the same time on `?refresh=true`
+const USAGE_PROBE_CONCURRENCY: usize = 5;
 
 #[derive(Deserialize)]
 pub struct VertexCredentialPayload {
@@ -74,7 +77,8 @@ pub struct VertexCredentialInfo {
     pub private_key_id: Option<String>,
 }
 
-async fn ensure_db_writable() -> Result<(), ApiError> {
+/// Refuses admin changes while database storage is enabled but unhealthy
+pub(crate) async fn ensure_db_writable() -> Result<(), ApiError> {
     let storage = persistence::storage();
     if !storage.is_enabled() {
         return Ok(());
@@ -130,9 +134,6 @@ pub async fn api_post_cookie(
     match s.submit(c).await {
         Ok(_) => {
             info!("Cookie submitted successfully");
-            // Clear cache to ensure fresh data on next request
-            COOKIES_CACHE.invalidate(COOKIE_STATUS_CACHE_KEY);
-            info!("Cookie status cache invalidated after adding new cookie");
             Ok(StatusCode::OK)
         }
         Err(e) => {
@@ -158,6 +159,9 @@ pub async fn api_post_key(
         return Err(ApiError::bad_request("Invalid key"));
     }
     ensure_db_writable().await?;
+    if CLEWDR_CONFIG.load().validate_gemini_keys {
+        check_gemini_key(&c.key).await?;
+    }
     info!("Key accepted: {}", c.key);
     match s.submit(c).await {
         Ok(_) => {
@@ -171,10 +175,41 @@ pub async fn api_post_key(
     }
 }
 
+/// Checks a submitted key against Gemini
+///
+/// # Returns
+/// * `Err(ApiError)` - 422 with the rejection `reason` and the upstream status and error
+///   when Gemini refused the key, 502 when Gemini could not be reached
+async fn check_gemini_key(key: &GeminiKey) -> Result<(), ApiError> {
+    match gemini_state::check_key(key).await {
+        Ok(()) => Ok(()),
+        Err(ClewdrError::GeminiHttpError { code, inner }) => {
+            let reason = KeyRejection::classify(&inner);
+            warn!("Key rejected by Gemini ({:?}): {}", reason, key.ellipse());
+            Err(ApiError {
+                code: StatusCode::UNPROCESSABLE_ENTITY,
+                body: json!({
+                    "error": "Key rejected by Gemini",
+                    "reason": reason,
+                    "upstream_status": code.as_u16(),
+                    "upstream_error": inner,
+                }),
+            })
+        }
+        Err(e) => {
+            error!("Failed to check key: {}", e);
+            Err(ApiError {
+                code: StatusCode::BAD_GATEWAY,
+                body: json!({ "error": format!("Failed to check key: {}", e) }),
+            })
+        }
+    }
+}
+
 pub async fn api_get_vertex_credentials(
     AuthBearer(t): AuthBearer,
 ) -> Result<Json<Vec<VertexCredentialInfo>>, ApiError> {
-    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+    if !CLEWDR_CONFIG.load().viewer_auth(&t) {
         return Err(ApiError::unauthorized());
     }
 
@@ -281,6 +316,9 @@ pub async fn api_delete_vertex_credential(
 /// API endpoint to retrieve all cookies and their status
 /// Gets information about valid, exhausted, and invalid cookies
 ///
+/// By default no upstream call is made: cookies carry their stored usage buckets and the
+/// utilization of the last probe, if still cached. `?refresh=true` probes every cookie live.
+///
 /// # Arguments
 /// * `s` - Application state containing event sender
 /// * `t` - Auth bearer token for admin authentication
@@ -293,92 +331,84 @@ pub async fn api_get_cookies(
     AuthBearer(t): AuthBearer,
     Query(query): Query<CookieStatusQuery>,
 ) -> Result<(HeaderMap, Json<Value>), ApiError> {
-    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+    let config = CLEWDR_CONFIG.load();
+    if !config.viewer_auth(&t) {
         return Err(ApiError::unauthorized());
     }
+    let viewer = !config.admin_auth(&t);
 
-    let mut headers = HeaderMap::new();
-
-    // Check cache if not force refreshing
-    if !query.refresh {
-        if let Some(cached) = COOKIES_CACHE.get(COOKIE_STATUS_CACHE_KEY) {
-            headers.insert("X-Cache-Status", HeaderValue::from_static("HIT"));
-            headers.insert(
-                "X-Cache-Timestamp",
-                HeaderValue::from_str(&cached.timestamp.to_string())
-                    .unwrap_or_else(|_| HeaderValue::from_static("0")),
-            );
-            info!("Cookie status served from cache");
-            return Ok((headers, Json(cached.data)));
-        }
+    let status = s
+        .get_status()
+        .await
+        .map_err(|e| ApiError::internal(format!("Failed to get cookie status: {}", e)))?;
+    let cookies = status.valid.len() + status.exhausted.len();
+    let (valid, exhausted) = if query.refresh {
+        (
+            probe_utilization(status.valid).await,
+            probe_utilization(status.exhausted).await,
+        )
+    } else {
+        (
+            cached_utilization(status.valid),
+            cached_utilization(status.exhausted),
+        )
+    };
+    let invalid = status
+        .invalid
+        .into_iter()
+        .map(|u| serde_json::to_value(u).unwrap_or(json!({})))
+        .collect::<Vec<_>>();
+    // oldest probe the view is built from
+    let timestamp = valid
+        .iter()
+        .chain(exhausted.iter())
+        .filter_map(|(_, t)| *t)
+        .min()
+        .unwrap_or_else(now_secs);
+    let strip = |v: Vec<(Value, Option<u64>)>| v.into_iter().map(|(c, _)| c).collect::<Vec<_>>();
+    let mut response_data = json!({
+        "valid": strip(valid),
+        "exhausted": strip(exhausted),
+        "invalid": invalid,
+    });
+    if viewer {
+        mask_secrets(&mut response_data, "cookie", COOKIE_VISIBLE_CHARS);
     }
 
-    // Cache miss or force refresh - fetch fresh data
-    match s.get_status().await {
-        Ok(status) => {
-            let valid = augment_utilization(status.valid).await;
-            let exhausted = augment_utilization(status.exhausted).await;
-            let invalid = status
-                .invalid
-                .into_iter()
-                .map(|u| serde_json::to_value(u).unwrap_or(json!({})))
-                .collect::<Vec<_>>();
-
-            let response_data = json!({
-                "valid": valid,
-                "exhausted": exhausted,
-                "invalid": invalid,
-            });
-
-            // Store in cache
-            let timestamp = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_else(|e| {
-                    warn!("System time error: {}, using fallback timestamp", e);
-                    Duration::from_secs(0)
-                })
-                .as_secs();
-
-            COOKIES_CACHE.insert(
-                COOKIE_STATUS_CACHE_KEY.to_string(),
-                CookieStatusCache {
-                    data: response_data.clone(),
-                    timestamp,
-                },
-            );
-
-            headers.insert("X-Cache-Status", HeaderValue::from_static("MISS"));
-            headers.insert(
-                "X-Cache-Timestamp",
-                HeaderValue::from_str(&timestamp.to_string())
-                    .unwrap_or_else(|_| HeaderValue::from_static("0")),
-            );
-
-            if query.refresh {
-                info!("Cookie status force refreshed");
-            } else {
-                info!("Cookie status fetched and cached");
-            }
-
-            Ok((headers, Json(response_data)))
-        }
-        Err(e) => Err(ApiError::internal(format!(
-            "Failed to get cookie status: {}",
-            e
-        ))),
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "X-Cache-Status",
+        HeaderValue::from_static(if query.refresh { "MISS" } else { "HIT" }),
+    );
+    headers.insert(
+        "X-Cache-Timestamp",
+        HeaderValue::from_str(&timestamp.to_string())
+            .unwrap_or_else(|_| HeaderValue::from_static("0")),
+    );
+    if query.refresh {
+        info!("Cookie utilization probed for {} cookies", cookies);
     }
+    Ok((headers, Json(response_data)))
 }
 
 pub async fn api_get_keys(
     State(s): State<KeyActorHandle>,
     AuthBearer(t): AuthBearer,
-) -> Result<Json<KeyStatusInfo>, ApiError> {
-    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+) -> Result<Json<Value>, ApiError> {
+    let config = CLEWDR_CONFIG.load();
+    if !config.viewer_auth(&t) {
         return Err(ApiError::unauthorized());
     }
+    let viewer = !config.admin_auth(&t);
 
     match s.get_status().await {
-        Ok(status) => Ok(Json(status)),
+        Ok(status) => {
+            let mut status = json!(status);
+            if viewer {
+                mask_secrets(&mut status, "key", KEY_VISIBLE_CHARS);
+            }
+            Ok(Json(status))
+        }
         Err(e) => Err(ApiError::internal(format!(
             "Failed to get keys status: {}",
             e
@@ -386,6 +416,31 @@ pub async fn api_get_keys(
     }
 }
 
+/// Leading characters of a cookie shown to viewers, past the `sessionKey=sk-ant-sid01-` prefix
+pub(super) const COOKIE_VISIBLE_CHARS: usize = 34;
+/// Leading characters of a Gemini key shown to viewers
+pub(super) const KEY_VISIBLE_CHARS: usize = 10;
+
+/// Truncates `field` and drops OAuth tokens in every item of a status listing, for viewers
+fn mask_secrets(status: &mut Value, field: &str, visible: usize) {
+    let Some(groups) = status.as_object_mut() else {
+        return;
+    };
+    for item in groups
+        .values_mut()
+        .filter_map(Value::as_array_mut)
+        .flatten()
+    {
+        let Some(item) = item.as_object_mut() else {
+            continue;
+        };
+        item.remove("token");
+        if let Some(Value::String(secret)) = item.get_mut(field) {
+            *secret = format!("{}...", secret.chars().take(visible).collect::<String>());
+        }
+    }
+}
+
 /// API endpoint to delete a specific cookie
 /// Removes the cookie from all collections in the cookie manager
 ///
@@ -410,9 +465,7 @@ pub async fn api_delete_cookie(
     match s.delete_cookie(c.to_owned()).await {
         Ok(_) => {
             info!("Cookie deleted successfully: {}", c.cookie);
-            // Clear cache to ensure fresh data on next request
-            COOKIES_CACHE.invalidate(COOKIE_STATUS_CACHE_KEY);
-            info!("Cookie status cache invalidated");
+            UTILIZATION_CACHE.invalidate(&c.cookie.to_string());
             Ok(StatusCode::NO_CONTENT)
         }
         Err(e) => {
@@ -425,6 +478,232 @@ pub async fn api_delete_cookie(
     }
 }
 
+/// API endpoint to pause a cookie
+/// The cookie stays listed with its usage but is skipped by dispatch until resumed
+pub async fn api_disable_cookie(
+    State(s): State<CookieActorHandle>,
+    AuthBearer(t): AuthBearer,
+    Json(c): Json<CookieStatus>,
+) -> Result<StatusCode, ApiError> {
+    set_cookie_disabled(s, t, c, true).await
+}
+
+/// API endpoint to resume a paused cookie
+pub async fn api_enable_cookie(
+    State(s): State<CookieActorHandle>,
+    AuthBearer(t): AuthBearer,
+    Json(c): Json<CookieStatus>,
+) -> Result<StatusCode, ApiError> {
+    set_cookie_disabled(s, t, c, false).await
+}
+
+/// API endpoint to pin the organization a cookie uses
+/// Takes the cookie with `pinned_org` set to an organization UUID, or null to unpin
+pub async fn api_pin_cookie_org(
+    State(s): State<CookieActorHandle>,
+    AuthBearer(t): AuthBearer,
+    Json(c): Json<CookieStatus>,
+) -> Result<StatusCode, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    ensure_db_writable().await?;
+
+    let org = c
+        .pinned_org
+        .as_deref()
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .map(ToString::to_string);
+    match s.pin_org(c, org).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(ClewdrError::UnexpectedNone { msg }) => Err(ApiError::not_found(msg)),
+        Err(e) => {
+            error!("Failed to pin organization: {}", e);
+            Err(ApiError::internal(format!(
+                "Failed to pin organization: {}",
+                e
+            )))
+        }
+    }
+}
+
+/// API endpoint to tag a cookie
+/// Takes the cookie with `tags` set to the new tags, an empty list removes them
+pub async fn api_tag_cookie(
+    State(s): State<CookieActorHandle>,
+    AuthBearer(t): AuthBearer,
+    Json(c): Json<CookieStatus>,
+) -> Result<StatusCode, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    ensure_db_writable().await?;
+
+    let tags = parse_tags(c.tags.iter().map(|t| t.as_str()));
+    match s.set_tags(c, tags).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(ClewdrError::UnexpectedNone { msg }) => Err(ApiError::not_found(msg)),
+        Err(e) => {
+            error!("Failed to tag cookie: {}", e);
+            Err(ApiError::internal(format!("Failed to tag cookie: {}", e)))
+        }
+    }
+}
+
+async fn set_cookie_disabled(
+    s: CookieActorHandle,
+    t: String,
+    c: CookieStatus,
+    disabled: bool,
+) -> Result<StatusCode, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    ensure_db_writable().await?;
+
+    match s.set_disabled(c, disabled).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(ClewdrError::UnexpectedNone { msg }) => Err(ApiError::not_found(msg)),
+        Err(e) => {
+            error!("Failed to update cookie: {}", e);
+            Err(ApiError::internal(format!(
+                "Failed to update cookie: {}",
+                e
+            )))
+        }
+    }
+}
+
+/// Forces a Claude Code OAuth token refresh for a single cookie
+///
+/// Uses the refresh token when possible and falls back to a full code exchange.
+/// Returns the new expiry so stuck tokens can be fixed without waiting for a chat request.
+pub async fn api_refresh_cookie_token(
+    State(s): State<CookieActorHandle>,
+    AuthBearer(t): AuthBearer,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let cookie = pooled_cookie(&s, &id).await?;
+    let mut state = ClaudeCodeState::new(s);
+    state
+        .use_cookie(cookie.to_owned())
+        .map_err(|e| ApiError::internal(format!("Failed to prepare cookie: {}", e)))?;
+    match state.force_refresh_token().await {
+        Ok(token) => {
+            info!("Token refreshed for cookie: {}", cookie.cookie.ellipse());
+            Ok(Json(json!({
+                "expires_at": token.expires_at.timestamp(),
+                "expires_in": token.expires_in.as_secs(),
+            })))
+        }
+        Err(e) => {
+            error!(
+                "Failed to refresh token for {}: {}",
+                cookie.cookie.ellipse(),
+                e
+            );
+            Err(ApiError::internal(format!(
+                "Failed to refresh token: {}",
+                e
+            )))
+        }
+    }
+}
+
+/// Valid or exhausted cookie whose value is `id`
+async fn pooled_cookie(s: &CookieActorHandle, id: &str) -> Result<CookieStatus, ApiError> {
+    let status = s
+        .get_status()
+        .await
+        .map_err(|e| ApiError::internal(format!("Failed to get cookie status: {}", e)))?;
+    status
+        .valid
+        .into_iter()
+        .chain(status.exhausted)
+        .find(|c| c.cookie.to_string() == id)
+        .ok_or_else(|| ApiError::bad_request("Cookie not found"))
+}
+
+/// Changes the Claude.ai account settings of a single cookie
+///
+/// Takes the settings to change, `artifacts` and `paprika_mode` (extended thinking), and
+/// returns the account settings as stored by Claude.ai, so accounts can be normalized
+/// without logging into each one.
+pub async fn api_cookie_settings(
+    State(s): State<CookieActorHandle>,
+    AuthBearer(t): AuthBearer,
+    Path(id): Path<String>,
+    Json(changes): Json<AccountSettings>,
+) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    if changes.is_empty() {
+        return Err(ApiError::bad_request("No settings to change"));
+    }
+
+    let cookie = pooled_cookie(&s, &id).await?;
+    let mut state = ClaudeWebState::new(s);
+    state
+        .use_cookie(cookie.to_owned())
+        .map_err(|e| ApiError::internal(format!("Failed to prepare cookie: {}", e)))?;
+    match state.update_account_settings(&changes).await {
+        Ok(settings) => {
+            info!(
+                "Account settings updated for cookie: {}",
+                cookie.cookie.ellipse()
+            );
+            Ok(Json(settings))
+        }
+        Err(e) => {
+            error!(
+                "Failed to update account settings for {}: {}",
+                cookie.cookie.ellipse(),
+                e
+            );
+            Err(ApiError::internal(format!(
+                "Failed to update account settings: {}",
+                e
+            )))
+        }
+    }
+}
+
+/// Dismisses the flags of a cookie's Claude.ai account, like the legacy `clear_flags`
+///
+/// Returns the flags found and the ones dismissed, which are also recorded on the cookie.
+pub async fn api_clear_cookie_flags(
+    State(s): State<CookieActorHandle>,
+    AuthBearer(t): AuthBearer,
+    Path(id): Path<String>,
+) -> Result<Json<ClearedFlags>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let cookie = pooled_cookie(&s, &id).await?;
+    let mut state = ClaudeWebState::new(s);
+    state
+        .use_cookie(cookie.to_owned())
+        .map_err(|e| ApiError::internal(format!("Failed to prepare cookie: {}", e)))?;
+    match state.clear_flags().await {
+        Ok(cleared) => Ok(Json(cleared)),
+        Err(e) => {
+            error!(
+                "Failed to clear flags for {}: {}",
+                cookie.cookie.ellipse(),
+                e
+            );
+            Err(ApiError::internal(format!("Failed to clear flags: {}", e)))
+        }
+    }
+}
+
 pub async fn api_delete_key(
     State(s): State<KeyActorHandle>,
     AuthBearer(t): AuthBearer,
@@ -452,6 +731,46 @@ pub async fn api_delete_key(
     }
 }
 
+/// API endpoint to pause a key
+/// The key stays listed with its counters but is skipped by dispatch until resumed
+pub async fn api_disable_key(
+    State(s): State<KeyActorHandle>,
+    AuthBearer(t): AuthBearer,
+    Json(c): Json<KeyStatus>,
+) -> Result<StatusCode, ApiError> {
+    set_key_disabled(s, t, c, true).await
+}
+
+/// API endpoint to resume a paused key
+pub async fn api_enable_key(
+    State(s): State<KeyActorHandle>,
+    AuthBearer(t): AuthBearer,
+    Json(c): Json<KeyStatus>,
+) -> Result<StatusCode, ApiError> {
+    set_key_disabled(s, t, c, false).await
+}
+
+async fn set_key_disabled(
+    s: KeyActorHandle,
+    t: String,
+    c: KeyStatus,
+    disabled: bool,
+) -> Result<StatusCode, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    ensure_db_writable().await?;
+
+    match s.set_disabled(c, disabled).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(ClewdrError::UnexpectedNone { msg }) => Err(ApiError::not_found(msg)),
+        Err(e) => {
+            error!("Failed to update key: {}", e);
+            Err(ApiError::internal(format!("Failed to update key: {}", e)))
+        }
+    }
+}
+
 /// API endpoint to get the application version information
 ///
 /// # Returns
@@ -461,51 +780,33 @@ pub async fn api_version() -> String {
 }
 
 /// API endpoint to verify authentication
-/// Checks if the provided token is valid for admin access
+/// Checks if the provided token is valid for admin or viewer access
 ///
 /// # Arguments
 /// * `t` - Auth bearer token to verify
 ///
 /// # Returns
-/// * `StatusCode` - OK if authorized, UNAUTHORIZED otherwise
-pub async fn api_auth(AuthBearer(t): AuthBearer) -> StatusCode {
-    if !CLEWDR_CONFIG.load().admin_auth(&t) {
-        return StatusCode::UNAUTHORIZED;
+/// * `Result<Json<Value>, StatusCode>` - The role of the token, UNAUTHORIZED if it has none
+pub async fn api_auth(AuthBearer(t): AuthBearer) -> Result<Json<Value>, StatusCode> {
+    let config = CLEWDR_CONFIG.load();
+    if !config.viewer_auth(&t) {
+        return Err(StatusCode::UNAUTHORIZED);
     }
-    info!("Auth token accepted,");
-    StatusCode::OK
+    let role = if config.admin_auth(&t) {
+        "admin"
+    } else {
+        "viewer"
+    };
+    info!("Auth token accepted, role: {}", role);
+    Ok(Json(json!({ "role": role })))
 }
 
-const MODEL_LIST: [&str; 10] = [
-    "claude-3-7-sonnet-20250219",
-    "claude-3-7-sonnet-20250219-thinking",
-    "claude-sonnet-4-20250514",
-    "claude-sonnet-4-20250514-thinking",
-    "claude-sonnet-4-5-20250929",
-    "claude-sonnet-4-5-20250929-thinking",
-    "claude-opus-4-20250514",
-    "claude-opus-4-20250514-thinking",
-    "claude-opus-4-1-20250805",
-    "claude-opus-4-1-20250805-thinking",
-];
-
 /// API endpoint to get the list of available models
-/// Retrieves the list of models from the configuration
-pub async fn api_get_models() -> Json<Value> {
-    let data: Vec<Value> = MODEL_LIST
-        .iter()
-        .map(|model| {
-            json!({
-                "id": model,
-                "object": "model",
-                "created": 0,
-                "owned_by": "clewdr",
-            })
-        })
-        .collect::<Vec<_>>();
+/// Lists the models of every provider with their availability, followed by the aliases
+pub async fn api_get_models(State(registry): State<ModelRegistry>) -> Json<Value> {
     Json(json!({
         "object": "list",
-        "data": data,
+        "data": registry.list().await,
     }))
 }
 
@@ -515,30 +816,70 @@ pub async fn api_get_models() -> Json<Value> {
 use futures::{StreamExt, stream};
 use http::HeaderValue;
 use wreq::{
-    ClientBuilder, Method, Url,
-    header::{ORIGIN, REFERER},
+    Method, Url,
+    header::{COOKIE, ORIGIN, REFERER},
 };
-use wreq_util::Emulation;
 
-async fn augment_utilization(cookies: Vec<CookieStatus>) -> Vec<Value> {
-    let concurrency = 5usize;
-    stream::iter(cookies.into_iter().map(|c| async move {
-        let base = serde_json::to_value(&c).unwrap_or(json!({}));
-        match fetch_usage_percent(&c.cookie).await {
-            Some((five_hour, five_reset, seven_day, seven_reset, seven_day_opus, opus_reset)) => {
-                let mut obj = base;
-                obj["session_utilization"] = json!(five_hour);
-                obj["session_resets_at"] = json!(five_reset);
-                obj["seven_day_utilization"] = json!(seven_day);
-                obj["seven_day_resets_at"] = json!(seven_reset);
-                obj["seven_day_opus_utilization"] = json!(seven_day_opus);
-                obj["seven_day_opus_resets_at"] = json!(opus_reset);
-                obj
+use crate::services::client_pool::{self, ClientKind};
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_else(|e| {
+            warn!("System time error: {}, using fallback timestamp", e);
+            Duration::from_secs(0)
+        })
+        .as_secs()
+}
+
+/// Serializes cookies with the utilization of their last probe, without any upstream call
+///
+/// # Returns
+/// * `Vec<(Value, Option<u64>)>` - Cookie and the time it was probed, if it was
+fn cached_utilization(cookies: Vec<CookieStatus>) -> Vec<(Value, Option<u64>)> {
+    cookies
+        .into_iter()
+        .map(|c| {
+            let mut obj = serde_json::to_value(&c).unwrap_or(json!({}));
+            let probed = UTILIZATION_CACHE.get(&c.cookie.to_string());
+            let timestamp = probed.as_ref().map(|p| p.timestamp);
+            if let (Some(probed), Some(fields)) = (probed, obj.as_object_mut()) {
+                fields.extend(probed.fields);
             }
-            None => base,
+            (obj, timestamp)
+        })
+        .collect()
+}
+
+/// Probes Claude.ai for the utilization of each cookie and caches the result
+///
+/// # Returns
+/// * `Vec<(Value, Option<u64>)>` - Cookie and the time it was probed, if the probe succeeded
+async fn probe_utilization(cookies: Vec<CookieStatus>) -> Vec<(Value, Option<u64>)> {
+    stream::iter(cookies.into_iter().map(|c| async move {
+        if let Some((five_hour, five_reset, seven_day, seven_reset, seven_day_opus, opus_reset)) =
+            fetch_usage_percent(&c.cookie).await
+        {
+            quota::record(&c.cookie.to_string(), [five_hour, seven_day]);
+            let mut fields = Map::new();
+            fields.insert("session_utilization".into(), json!(five_hour));
+            fields.insert("session_resets_at".into(), json!(five_reset));
+            fields.insert("seven_day_utilization".into(), json!(seven_day));
+            fields.insert("seven_day_resets_at".into(), json!(seven_reset));
+            fields.insert("seven_day_opus_utilization".into(), json!(seven_day_opus));
+            fields.insert("seven_day_opus_resets_at".into(), json!(opus_reset));
+            UTILIZATION_CACHE.insert(
+                c.cookie.to_string(),
+                ProbedUtilization {
+                    fields,
+                    timestamp: now_secs(),
+                },
+            );
         }
+        // a failed probe falls back to the previous result
+        cached_utilization(vec![c]).remove(0)
     }))
-    .buffer_unordered(concurrency)
+    .buffer_unordered(USAGE_PROBE_CONCURRENCY)
     .collect::<Vec<_>>()
     .await
 }
@@ -553,25 +894,25 @@ async fn fetch_usage_percent(
     u32,
     Option<String>,
 )> {
-    let mut builder = ClientBuilder::new()
-        .cookie_store(true)
-        .emulation(Emulation::Chrome136);
-    if let Some(proxy) = CLEWDR_CONFIG.load().wreq_proxy.clone() {
-        builder = builder.proxy(proxy);
-    }
-    let client = builder.build().ok()?;
-
-    // Attach cookie for both api and console domains
-    let endpoint: Url = CLEWDR_CONFIG.load().endpoint();
+    let config = CLEWDR_CONFIG.load();
+    let client = client_pool::client(
+        ClientKind::Claude,
+        config.upstream_proxy(ProxyTarget::Claude),
+        &config.timeouts.for_target(ProxyTarget::Claude),
+    )
+    .ok()?;
+
+    // The cookie goes to both api and console domains
+    let endpoint: Url = config.endpoint();
     let cookie_header = HeaderValue::from_str(&cookie.to_string()).ok()?;
-    client.set_cookie(&endpoint, &cookie_header);
     let console_url = Url::parse(CLAUDE_CONSOLE_ENDPOINT).ok()?;
-    client.set_cookie(&console_url, &cookie_header);
+    drop(config);
 
     // Discover organization UUID (prefer chat-capable org)
     let orgs_url = endpoint.join("api/organizations").ok()?;
     let orgs_res = client
         .request(Method::GET, orgs_url)
+        .header(COOKIE, &cookie_header)
         .header(ORIGIN, CLAUDE_ENDPOINT)
         .header(REFERER, String::from(endpoint.join("new").ok()?))
         .send()
@@ -606,7 +947,12 @@ async fn fetch_usage_percent(
     let usage_url = console_url
         .join(&format!("api/organizations/{org_uuid}/usage"))
         .ok()?;
-    let usage_res = client.request(Method::GET, usage_url).send().await.ok()?;
+    let usage_res = client
+        .request(Method::GET, usage_url)
+        .header(COOKIE, &cookie_header)
+        .send()
+        .await
+        .ok()?;
     let usage: Value = usage_res.json().await.ok()?;
     let five = usage
         .get("five_hour")
@@ -3,25 +3,84 @@
 /// This module serves as the main entry point for all API requests, providing endpoints
 /// for configuration management, message handling, authentication, and OpenAI-compatible
 /// interfaces. It also implements response transformation between different API formats.
+mod batch;
+mod bedrock;
+mod chat_ws;
 mod claude_code;
 mod claude_web;
 mod config;
+mod debug;
 mod error;
+#[cfg(feature = "embed-frontend")]
+mod frontend;
 mod gemini;
+mod health;
+mod logs;
 mod misc;
+mod openai_upstream;
+mod pool;
+mod session;
 mod storage;
+mod transcripts;
+mod usage;
+mod vertex_claude;
+mod webhooks;
+/// Chat completion requests run in the background, for offline jobs
+pub use batch::{api_get_batch, api_post_batch};
+/// Anthropic API served by the Claude models of AWS Bedrock
+pub use bedrock::api_bedrock;
+/// Chat completions over WebSocket for clients that cannot use SSE
+pub use chat_ws::{ChatSocketTarget, api_chat_ws};
 pub use claude_code::{api_claude_code, api_claude_code_count_tokens};
 /// Message handling endpoints for creating and managing chat conversations
-pub use claude_web::api_claude_web;
+pub use claude_web::{api_claude_web, api_post_file};
 /// Configuration related endpoints for retrieving and updating Clewdr settings
-pub use config::{api_get_config, api_post_config};
+pub use config::{
+    api_delete_template, api_get_access_control, api_get_config, api_get_config_effective,
+    api_get_response_rules, api_get_routing_rules, api_get_templates, api_post_access_control,
+    api_post_config, api_post_config_validate, api_put_response_rules, api_put_routing_rules,
+    api_put_template,
+};
+/// Capture of upstream HTTP exchanges and local token counts for debugging
+pub use debug::{api_delete_captures, api_get_captures, api_post_captures, api_post_tokenize};
 pub use error::ApiError;
-pub use gemini::{api_post_gemini, api_post_gemini_oai};
+/// Admin UI built into the binary, with SPA fallback
+#[cfg(feature = "embed-frontend")]
+pub use frontend::serve_frontend;
+pub use gemini::{
+    api_get_gemini, api_post_gemini, api_post_gemini_count_tokens, api_post_gemini_image,
+    api_post_gemini_oai, api_post_gemini_upload,
+};
+/// Liveness and readiness probes for orchestrators, circuit breaker and upstream endpoint state,
+/// recent upstream failures
+pub use health::{
+    HealthState, api_get_breakers, api_get_endpoints, api_get_recent_errors, api_healthz,
+    api_readyz,
+};
+/// Live log streaming for the admin frontend
+pub use logs::{api_get_log_level, api_logs_stream, api_put_log_level};
+pub(crate) use misc::ensure_db_writable;
 /// Miscellaneous endpoints for authentication, cookies, and version information
 pub use misc::{
-    api_auth, api_delete_cookie, api_delete_key, api_delete_vertex_credential, api_get_cookies,
-    api_get_keys, api_get_models, api_get_vertex_credentials, api_post_cookie, api_post_key,
-    api_post_vertex_credential, api_version,
+    api_auth, api_clear_cookie_flags, api_cookie_settings, api_delete_cookie, api_delete_key,
+    api_delete_vertex_credential, api_disable_cookie, api_disable_key, api_enable_cookie,
+    api_enable_key, api_get_cookies, api_get_keys, api_get_models, api_get_vertex_credentials,
+    api_pin_cookie_org, api_post_cookie, api_post_key, api_post_vertex_credential,
+    api_refresh_cookie_token, api_tag_cookie, api_version,
 };
-pub use storage::{api_storage_export, api_storage_import, api_storage_status};
+/// OpenAI compatible upstreams passed through with their key pools
+pub use openai_upstream::api_openai_upstream;
+/// Snapshot and restore of the cookie, key and Vertex credential pools
+pub use pool::{PoolSnapshot, api_get_pool_snapshot, api_post_pool_restore};
+/// Session tokens for the admin web UI
+pub use session::{api_login, api_logout, api_refresh};
+pub use storage::{api_storage_backup, api_storage_export, api_storage_import, api_storage_status};
+/// Stored prompts and completions for debugging
+pub use transcripts::api_get_transcripts;
+/// Per cookie and per key usage as JSON or CSV, for invoicing and analysis
+pub use usage::api_usage_export;
+/// Anthropic API served by the Claude models of Vertex AI
+pub use vertex_claude::{api_vertex_claude, api_vertex_claude_count_tokens};
+/// Test delivery of the configured webhooks
+pub use webhooks::api_test_webhooks;
 // merged above
@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    response::Response,
+};
+use serde_json::Value;
+
+use crate::{
+    config::upstream_api_path,
+    error::ClewdrError,
+    providers::{
+        LLMProvider,
+        openai_upstream::{OpenAiUpstreamInvocation, OpenAiUpstreamProvider},
+    },
+};
+
+pub async fn api_openai_upstream(
+    State(provider): State<Arc<OpenAiUpstreamProvider>>,
+    Path((upstream, path)): Path<(String, String)>,
+    Json(body): Json<Value>,
+) -> Result<Response, ClewdrError> {
+    let Some(path) = upstream_api_path(&path) else {
+        return Err(ClewdrError::PathNotFound {
+            msg: format!("Path not passed through to upstreams: {path}"),
+        });
+    };
+    provider
+        .invoke(OpenAiUpstreamInvocation {
+            upstream,
+            path: path.to_string(),
+            body,
+        })
+        .await
+}
@@ -0,0 +1,139 @@
+use axum::{Json, extract::State};
+use axum_auth::AuthBearer;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::info;
+use yup_oauth2::ServiceAccountKey;
+
+use super::{error::ApiError, misc::ensure_db_writable};
+use crate::{
+    config::{CLEWDR_CONFIG, ClewdrConfig, CookieStatus, KeyStatus, UselessCookie},
+    persistence::{self, StorageBatch},
+    services::{
+        cookie_actor::{CookieActorHandle, CookieStatusInfo},
+        key_actor::{KeyActorHandle, KeyStatusInfo},
+    },
+};
+
+/// Version of the snapshot document
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// All credentials of the shared pool with their runtime state, tokens and counters included
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PoolSnapshot {
+    #[serde(default)]
+    pub version: u32,
+    /// Unix timestamp the snapshot was taken at
+    #[serde(default)]
+    pub created_at: i64,
+    /// Valid and exhausted cookies, told apart by their reset time
+    #[serde(default)]
+    pub cookies: Vec<CookieStatus>,
+    #[serde(default)]
+    pub wasted_cookies: Vec<UselessCookie>,
+    #[serde(default)]
+    pub keys: Vec<KeyStatus>,
+    #[serde(default)]
+    pub invalid_keys: Vec<KeyStatus>,
+    #[serde(default)]
+    pub vertex_credentials: Vec<ServiceAccountKey>,
+}
+
+/// Take a snapshot of the cookie, key and Vertex credential pools
+pub async fn api_get_pool_snapshot(
+    State((cookies, keys)): State<(CookieActorHandle, KeyActorHandle)>,
+    AuthBearer(t): AuthBearer,
+) -> Result<Json<PoolSnapshot>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let cookie_status = cookies
+        .get_status()
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    let key_status = keys
+        .get_status()
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    Ok(Json(PoolSnapshot {
+        version: SNAPSHOT_VERSION,
+        created_at: chrono::Utc::now().timestamp(),
+        cookies: cookie_status
+            .valid
+            .into_iter()
+            .chain(cookie_status.exhausted)
+            .collect(),
+        wasted_cookies: cookie_status.invalid,
+        keys: key_status.valid,
+        invalid_keys: key_status.invalid,
+        vertex_credentials: CLEWDR_CONFIG.load().vertex.credential_list(),
+    }))
+}
+
+/// Replace the cookie, key and Vertex credential pools with a snapshot
+///
+/// The storage is written first in a single batch holding the cookies, valid and dropped
+/// keys, so a failed restore leaves the running and stored pools untouched. The S3 backend
+/// writes cookies and keys as two objects, one after the other. Requests queued for a
+/// cookie or key are served from the restored pools.
+pub async fn api_post_pool_restore(
+    State((cookies, keys)): State<(CookieActorHandle, KeyActorHandle)>,
+    AuthBearer(t): AuthBearer,
+    Json(snapshot): Json<PoolSnapshot>,
+) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    ensure_db_writable().await?;
+    if snapshot.version > SNAPSHOT_VERSION {
+        return Err(ApiError::bad_request("Unsupported snapshot version"));
+    }
+    if snapshot
+        .vertex_credentials
+        .iter()
+        .any(|c| c.client_email.trim().is_empty())
+    {
+        return Err(ApiError::bad_request("client_email is required"));
+    }
+    let storage = persistence::storage();
+    if storage.is_enabled() {
+        storage
+            .persist_batch(&StorageBatch {
+                replace_keys: true,
+                keys: snapshot.keys.to_owned(),
+                invalid_keys: snapshot.invalid_keys.to_owned(),
+                ..StorageBatch::snapshot(&snapshot.cookies, &[], &snapshot.wasted_cookies)
+            })
+            .await
+            .map_err(|e| ApiError::internal(format!("Failed to store the pools: {e}")))?;
+    }
+    let counts = json!({
+        "cookies": snapshot.cookies.len(),
+        "wasted_cookies": snapshot.wasted_cookies.len(),
+        "keys": snapshot.keys.len(),
+        "invalid_keys": snapshot.invalid_keys.len(),
+        "vertex_credentials": snapshot.vertex_credentials.len(),
+    });
+    CLEWDR_CONFIG.rcu(|config| {
+        let mut config = ClewdrConfig::clone(config);
+        config.vertex.credentials = snapshot.vertex_credentials.to_owned();
+        config.vertex.credential = None;
+        config
+    });
+    cookies
+        .restore(CookieStatusInfo {
+            valid: snapshot.cookies,
+            exhausted: vec![],
+            invalid: snapshot.wasted_cookies,
+        })
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    keys.restore(KeyStatusInfo {
+        valid: snapshot.keys,
+        invalid: snapshot.invalid_keys,
+    })
+    .await
+    .map_err(|e| ApiError::internal(e.to_string()))?;
+    info!("Pool restored from snapshot: {}", counts);
+    Ok(Json(counts))
+}
@@ -0,0 +1,44 @@
+use axum::{Json, http::StatusCode};
+use axum_auth::AuthBearer;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use super::error::ApiError;
+use crate::{
+    config::CLEWDR_CONFIG,
+    services::session::{self, Session},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    password: String,
+}
+
+/// Exchanges the admin or viewer password for a short-lived session token
+///
+/// The web UI keeps the token instead of the password, and renews it through
+/// `/api/auth/refresh` before it expires.
+pub async fn api_login(Json(req): Json<LoginRequest>) -> Result<Json<Session>, ApiError> {
+    let Some(role) = CLEWDR_CONFIG.load().password_role(&req.password) else {
+        warn!("Admin login with an invalid password");
+        return Err(ApiError::unauthorized());
+    };
+    info!("Admin login, role: {:?}", role);
+    Ok(Json(session::issue(role)))
+}
+
+/// Swaps a session token that has not expired yet for a new one
+pub async fn api_refresh(AuthBearer(t): AuthBearer) -> Result<Json<Session>, ApiError> {
+    session::refresh(&t)
+        .map(Json)
+        .ok_or_else(ApiError::unauthorized)
+}
+
+/// Invalidates a session token
+pub async fn api_logout(AuthBearer(t): AuthBearer) -> StatusCode {
+    if session::revoke(&t) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::UNAUTHORIZED
+    }
+}
@@ -4,10 +4,15 @@ use serde_json::json;
 
 // StatusCode not needed; using ApiError for responses
 use super::error::ApiError;
-use crate::{config::CLEWDR_CONFIG, persistence};
+use crate::{
+    config::CLEWDR_CONFIG,
+    persistence,
+    services::backup::{Backup, backup_now},
+};
 
 /// Import configuration and runtime state from file into the database
 /// Only available when compiled with `db` feature and DB mode enabled.
+/// The imported config is applied at once, without waiting for the sync loop to reload it.
 pub async fn api_storage_import(
     AuthBearer(t): AuthBearer,
 ) -> Result<Json<serde_json::Value>, ApiError> {
@@ -15,10 +20,16 @@ pub async fn api_storage_import(
         return Err(ApiError::unauthorized());
     }
     if persistence::storage().is_enabled() {
-        match persistence::storage().import_from_file().await {
-            Ok(v) => Ok(Json(v)),
-            Err(e) => Err(ApiError::internal(e.to_string())),
-        }
+        let storage = persistence::storage();
+        let v = storage
+            .import_from_file()
+            .await
+            .map_err(|e| ApiError::internal(e.to_string()))?;
+        storage
+            .spawn_bootstrap()
+            .await
+            .map_err(|e| ApiError::internal(e.to_string()))?;
+        Ok(Json(v))
     } else {
         Err(ApiError::not_implemented("DB feature not enabled"))
     }
@@ -42,6 +53,17 @@ pub async fn api_storage_export(
     }
 }
 
+/// Take a backup of the config, cookies and keys now, outside the `backup` schedule
+pub async fn api_storage_backup(AuthBearer(t): AuthBearer) -> Result<Json<Backup>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    backup_now()
+        .await
+        .map(Json)
+        .map_err(|e| ApiError::internal(e.to_string()))
+}
+
 /// DB status: enabled/mode/healthy/details/metrics
 pub async fn api_storage_status() -> Json<serde_json::Value> {
     if persistence::storage().is_enabled()
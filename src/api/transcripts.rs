@@ -0,0 +1,49 @@
+use axum::{Json, extract::Query};
+use axum_auth::AuthBearer;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::error::ApiError;
+use crate::{config::CLEWDR_CONFIG, persistence};
+
+/// Largest page of the transcript listing
+const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct TranscriptQuery {
+    #[serde(default)]
+    offset: u64,
+    /// Page size, defaults to 20 and is capped at 100
+    #[serde(default)]
+    limit: Option<u64>,
+    /// Only transcripts whose model, prompt or completion contains this text
+    #[serde(default)]
+    q: Option<String>,
+}
+
+/// Stored request transcripts, newest first
+///
+/// Only available in DB mode with `transcripts` enabled.
+pub async fn api_get_transcripts(
+    AuthBearer(t): AuthBearer,
+    Query(query): Query<TranscriptQuery>,
+) -> Result<Json<Value>, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    if !CLEWDR_CONFIG.load().transcripts_enabled() {
+        return Err(ApiError::not_implemented("Transcripts not enabled"));
+    }
+    let limit = query.limit.unwrap_or(20).clamp(1, MAX_PAGE_SIZE);
+    let search = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+    let (items, total) = persistence::storage()
+        .list_transcripts(query.offset, limit, search)
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    Ok(Json(json!({
+        "items": items,
+        "total": total,
+        "offset": query.offset,
+        "limit": limit,
+    })))
+}
@@ -0,0 +1,213 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{
+        HeaderMap, HeaderValue,
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    },
+    response::{IntoResponse, Response},
+};
+use axum_auth::AuthBearer;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::ApiError,
+    misc::{COOKIE_VISIBLE_CHARS, KEY_VISIBLE_CHARS},
+};
+use crate::{
+    config::{CLEWDR_CONFIG, CookieStatus, KeyStatus, UsageBreakdown},
+    services::{cookie_actor::CookieActorHandle, key_actor::KeyActorHandle},
+};
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// Period of the exported counters
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum ExportRange {
+    /// Current session window of cookies, current UTC day of keys
+    #[serde(rename = "1d")]
+    Day,
+    /// Current weekly window of cookies, keys have no weekly counters and report their lifetime
+    #[serde(rename = "7d")]
+    Week,
+    #[default]
+    #[serde(rename = "all")]
+    All,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+    #[serde(default)]
+    pub range: ExportRange,
+}
+
+/// Usage of a single cookie or key
+///
+/// Token and request counts cover `period`, 403 and 429 counts are lifetime totals.
+/// Keys count their daily tokens without an input and output split, and do not
+/// split by model family.
+#[derive(Debug, Serialize)]
+struct UsageRow {
+    kind: &'static str,
+    id: String,
+    period: &'static str,
+    requests: u64,
+    tokens: u64,
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+    sonnet_input_tokens: u64,
+    sonnet_output_tokens: u64,
+    opus_input_tokens: u64,
+    opus_output_tokens: u64,
+    cache_read_input_tokens: u64,
+    cache_creation_input_tokens: u64,
+    count_403: u32,
+    count_429: u32,
+}
+
+const CSV_HEADER: &str = "kind,id,period,requests,tokens,input_tokens,output_tokens,\
+sonnet_input_tokens,sonnet_output_tokens,opus_input_tokens,opus_output_tokens,\
+cache_read_input_tokens,cache_creation_input_tokens,count_403,count_429";
+
+impl UsageRow {
+    fn cookie(c: &CookieStatus, range: ExportRange) -> Self {
+        let (period, usage): (_, &UsageBreakdown) = match range {
+            ExportRange::Day => ("session", &c.session_usage),
+            ExportRange::Week => ("weekly", &c.weekly_usage),
+            ExportRange::All => ("lifetime", &c.lifetime_usage),
+        };
+        Self {
+            kind: "cookie",
+            id: truncate(&c.cookie.to_string(), COOKIE_VISIBLE_CHARS),
+            period,
+            requests: usage.requests,
+            tokens: usage
+                .total_input_tokens
+                .saturating_add(usage.total_output_tokens),
+            input_tokens: Some(usage.total_input_tokens),
+            output_tokens: Some(usage.total_output_tokens),
+            sonnet_input_tokens: usage.sonnet_input_tokens,
+            sonnet_output_tokens: usage.sonnet_output_tokens,
+            opus_input_tokens: usage.opus_input_tokens,
+            opus_output_tokens: usage.opus_output_tokens,
+            cache_read_input_tokens: usage.cache_read_input_tokens,
+            cache_creation_input_tokens: usage.cache_creation_input_tokens,
+            count_403: c.count_403,
+            count_429: c.count_429,
+        }
+    }
+
+    fn key(k: &KeyStatus, range: ExportRange) -> Self {
+        let mut row = Self {
+            kind: "key",
+            id: truncate(&k.key.to_string(), KEY_VISIBLE_CHARS),
+            period: "lifetime",
+            requests: k.total_requests,
+            tokens: k.input_tokens.saturating_add(k.output_tokens),
+            input_tokens: Some(k.input_tokens),
+            output_tokens: Some(k.output_tokens),
+            sonnet_input_tokens: 0,
+            sonnet_output_tokens: 0,
+            opus_input_tokens: 0,
+            opus_output_tokens: 0,
+            cache_read_input_tokens: 0,
+            cache_creation_input_tokens: 0,
+            count_403: k.count_403,
+            count_429: k.total_429,
+        };
+        if let ExportRange::Day = range {
+            // counters of a past day read as nothing used today
+            let today = k.quota_day == KeyStatus::today();
+            row.period = "day";
+            row.requests = if today { k.count_requests } else { 0 };
+            row.tokens = if today { k.count_tokens } else { 0 };
+            row.input_tokens = None;
+            row.output_tokens = None;
+        }
+        row
+    }
+
+    fn csv_line(&self) -> String {
+        let opt = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_default();
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.kind,
+            self.id,
+            self.period,
+            self.requests,
+            self.tokens,
+            opt(self.input_tokens),
+            opt(self.output_tokens),
+            self.sonnet_input_tokens,
+            self.sonnet_output_tokens,
+            self.opus_input_tokens,
+            self.opus_output_tokens,
+            self.cache_read_input_tokens,
+            self.cache_creation_input_tokens,
+            self.count_403,
+            self.count_429,
+        )
+    }
+}
+
+/// Leading characters of a secret, enough to tell cookies and keys apart in a spreadsheet
+fn truncate(secret: &str, visible: usize) -> String {
+    format!("{}...", secret.chars().take(visible).collect::<String>())
+}
+
+/// Exports the usage of every pooled cookie and key, for invoicing or analysis
+///
+/// `format` is `json` (default) or `csv`, `range` is `1d`, `7d` or `all` (default).
+/// Cookies report the Claude session, weekly or lifetime bucket, which follow the
+/// Claude.ai usage windows rather than exact calendar ranges.
+pub async fn api_usage_export(
+    State((cookies, keys)): State<(CookieActorHandle, KeyActorHandle)>,
+    AuthBearer(t): AuthBearer,
+    Query(query): Query<UsageExportQuery>,
+) -> Result<Response, ApiError> {
+    if !CLEWDR_CONFIG.load().admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    let cookies = cookies
+        .get_status()
+        .await
+        .map_err(|e| ApiError::internal(format!("Failed to get cookie status: {}", e)))?;
+    let keys = keys
+        .get_status()
+        .await
+        .map_err(|e| ApiError::internal(format!("Failed to get keys status: {}", e)))?;
+    let rows = cookies
+        .valid
+        .iter()
+        .chain(cookies.exhausted.iter())
+        .map(|c| UsageRow::cookie(c, query.range))
+        .chain(keys.valid.iter().map(|k| UsageRow::key(k, query.range)))
+        .collect::<Vec<_>>();
+    let ExportFormat::Csv = query.format else {
+        return Ok(Json(rows).into_response());
+    };
+    let mut body = String::from(CSV_HEADER);
+    body.push('\n');
+    for row in &rows {
+        body.push_str(&row.csv_line());
+        body.push('\n');
+    }
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/csv; charset=utf-8"),
+    );
+    headers.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("attachment; filename=\"clewdr-usage.csv\""),
+    );
+    Ok((headers, body).into_response())
+}
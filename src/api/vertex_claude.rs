@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use axum::{Json, extract::State, http::HeaderMap, response::Response};
+use serde_json::Value;
+
+use crate::{
+    error::ClewdrError,
+    middleware::claude::anthropic_betas,
+    providers::{
+        LLMProvider,
+        passthrough::AnthropicInvocation,
+        vertex_claude::{VertexClaudeInvocation, VertexClaudeOperation, VertexClaudeProvider},
+    },
+};
+
+pub async fn api_vertex_claude(
+    State(provider): State<Arc<VertexClaudeProvider>>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<Response, ClewdrError> {
+    provider
+        .invoke(VertexClaudeInvocation {
+            invocation: AnthropicInvocation {
+                body,
+                betas: anthropic_betas(&headers),
+            },
+            operation: VertexClaudeOperation::Messages,
+        })
+        .await
+}
+
+pub async fn api_vertex_claude_count_tokens(
+    State(provider): State<Arc<VertexClaudeProvider>>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<Response, ClewdrError> {
+    provider
+        .invoke(VertexClaudeInvocation {
+            invocation: AnthropicInvocation {
+                body,
+                betas: anthropic_betas(&headers),
+            },
+            operation: VertexClaudeOperation::CountTokens,
+        })
+        .await
+}
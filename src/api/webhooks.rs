@@ -0,0 +1,21 @@
+use axum::Json;
+use axum_auth::AuthBearer;
+
+use super::error::ApiError;
+use crate::{
+    config::CLEWDR_CONFIG,
+    services::webhook::{self, Delivery},
+};
+
+/// Sends a test event to every configured webhook and reports how each one answered
+pub async fn api_test_webhooks(AuthBearer(t): AuthBearer) -> Result<Json<Vec<Delivery>>, ApiError> {
+    let config = CLEWDR_CONFIG.load();
+    if !config.admin_auth(&t) {
+        return Err(ApiError::unauthorized());
+    }
+    if config.webhooks.is_empty() {
+        return Err(ApiError::bad_request("No webhooks configured"));
+    }
+    drop(config);
+    Ok(Json(webhook::test_fire().await))
+}
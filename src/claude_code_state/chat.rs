@@ -8,20 +8,32 @@ use futures::TryStreamExt;
 use snafu::{GenerateImplicitData, ResultExt};
 use tracing::{Instrument, error, info, warn};
 use wreq::{
-    ClientBuilder, Method, Url,
-    header::{ORIGIN, REFERER},
+    Method, Url,
+    header::{COOKIE, ORIGIN, REFERER},
 };
-use wreq_util::Emulation;
 
 use crate::{
     claude_code_state::{ClaudeCodeState, TokenStatus},
-    config::{CLAUDE_CONSOLE_ENDPOINT, CLAUDE_ENDPOINT, CLEWDR_CONFIG, ModelFamily},
+    config::{CLAUDE_CONSOLE_ENDPOINT, CLAUDE_ENDPOINT, CLEWDR_CONFIG, ModelFamily, ProxyTarget},
     error::{CheckClaudeErr, ClewdrError, WreqSnafu},
-    types::claude::{CountMessageTokensResponse, CreateMessageParams},
+    middleware::{
+        proxy::current_proxy,
+        timeout::{current_timeouts, first_byte},
+    },
+    services::{
+        breaker::Provider,
+        capture::CaptureExt,
+        client_pool::{self, ClientKind},
+        endpoints::{self, Upstream},
+        quota, recent_errors,
+        retry::{self, Failure, RetryPolicy},
+    },
+    types::claude::{CountMessageTokensResponse, CreateMessageParams, Usage},
+    utils::{backpressure, throttle},
 };
 
 const CLAUDE_BETA_BASE: &str = "oauth-2025-04-20";
-const CLAUDE_BETA_CONTEXT_1M: &str = "oauth-2025-04-20,context-1m-2025-08-07";
+const CLAUDE_BETA_CONTEXT_1M: &str = "context-1m-2025-08-07";
 
 impl ClaudeCodeState {
     /// Attempts to send a chat message to Claude API with retry mechanism
@@ -33,9 +45,8 @@ impl ClaudeCodeState {
     /// - Response transformation according to the specified API format
     /// - Error handling and cleanup
     ///
-    /// The method implements a sophisticated retry mechanism to handle transient failures,
-    /// and manages conversation cleanup to prevent resource leaks. It also includes
-    /// performance tracking to measure response times.
+    /// Retries follow the configured `RetryPolicy`, a rejected cookie is returned with its
+    /// reason before the next attempt.
     ///
     /// # Arguments
     /// * `p` - The client request body containing messages and configuration
@@ -46,66 +57,71 @@ impl ClaudeCodeState {
         &mut self,
         p: CreateMessageParams,
     ) -> Result<axum::response::Response, ClewdrError> {
-        for i in 0..CLEWDR_CONFIG.load().max_retries + 1 {
-            if i > 0 {
-                info!("[RETRY] attempt: {}", i.to_string().green());
-            }
-            let mut state = self.to_owned();
-            let p = p.to_owned();
-
-            let cookie = state.request_cookie().await?;
-            let retry = async {
-                match state.check_token() {
-                    TokenStatus::None => {
-                        info!("No token found, requesting new token");
-                        let org = state.get_organization().await?;
-                        let code_res = state.exchange_code(&org).await?;
-                        state.exchange_token(code_res).await?;
-                        state.return_cookie(None).await;
-                    }
-                    TokenStatus::Expired => {
-                        info!("Token expired, refreshing token");
-                        state.refresh_token().await?;
-                        state.return_cookie(None).await;
-                    }
-                    TokenStatus::Valid => {
-                        info!("Token is valid, proceeding with request");
-                    }
-                }
-                let Some(access_token) = state.cookie.as_ref().and_then(|c| c.token.to_owned())
-                else {
-                    return Err(ClewdrError::UnexpectedNone {
-                        msg: "No access token found in cookie",
-                    });
+        let p = &p;
+        RetryPolicy::from_config()
+            .executor(retry::invalid_cookie, Self::return_rejected)
+            .run(self, |mut state| async move {
+                let cookie = match state.request_cookie().await {
+                    Ok(cookie) => cookie,
+                    Err(e) => return (state, Err(e)),
                 };
-                state
-                    .send_chat(access_token.access_token.to_owned(), p)
-                    .await
-            }
-            .instrument(tracing::info_span!(
-                "claude_code",
-                "cookie" = cookie.cookie.ellipse()
-            ));
-            match retry.await {
-                Ok(res) => {
-                    return Ok(res);
+                let res = async {
+                    let access_token = state.access_token("request").await?;
+                    state.send_chat(access_token, p.to_owned()).await
                 }
-                Err(e) => {
-                    error!(
-                        "[{}] {}",
-                        state.cookie.as_ref().unwrap().cookie.ellipse().green(),
-                        e
-                    );
-                    // 429 error
-                    if let ClewdrError::InvalidCookie { reason } = e {
-                        state.return_cookie(Some(reason.to_owned())).await;
-                        continue;
+                .instrument(tracing::info_span!(
+                    "claude_code",
+                    "cookie" = cookie.cookie.ellipse()
+                ))
+                .await;
+                if let Err(ref e) = res {
+                    error!("[{}] {}", cookie.cookie.ellipse().green(), e);
+                    recent_errors::record(Provider::ClaudeCode, Some(cookie.cookie.ellipse()), e);
+                    // aborted attempts keep their cookie, return it for a 403 to be counted
+                    if retry::invalid_cookie(e) == Failure::Abort
+                        && state.cookie.as_mut().is_some_and(|c| c.record_rejection(e))
+                    {
+                        state.return_cookie(None).await;
                     }
-                    return Err(e);
                 }
+                endpoints::report(Upstream::Claude, &state.endpoint, &res);
+                (state, res)
+            })
+            .await
+    }
+
+    /// Makes sure the cookie holds a valid token, exchanging or refreshing it when needed
+    ///
+    /// # Arguments
+    /// * `action` - What the token is for, used in logs
+    ///
+    /// # Returns
+    /// * `Result<String, ClewdrError>` - The access token, after the organization throttle let it through
+    async fn access_token(&mut self, action: &str) -> Result<String, ClewdrError> {
+        match self.check_token() {
+            TokenStatus::None => {
+                info!("No token found, requesting new token");
+                let org = self.get_organization().await?;
+                let code_res = self.exchange_code(&org).await?;
+                self.exchange_token(code_res).await?;
+                self.return_cookie(None).await;
+            }
+            TokenStatus::Expired => {
+                info!("Token expired, refreshing token");
+                self.refresh_token().await?;
+                self.return_cookie(None).await;
+            }
+            TokenStatus::Valid => {
+                info!("Token is valid, proceeding with {}", action);
             }
         }
-        Err(ClewdrError::TooManyRetries)
+        let Some(access_token) = self.cookie.as_ref().and_then(|c| c.token.to_owned()) else {
+            return Err(ClewdrError::UnexpectedNone {
+                msg: "No access token found in cookie",
+            });
+        };
+        throttle::acquire(&access_token.organization.uuid).await?;
+        Ok(access_token.access_token)
     }
 
     pub async fn send_chat(
@@ -176,25 +192,43 @@ impl ClaudeCodeState {
         body: &CreateMessageParams,
         use_context_1m: bool,
     ) -> Result<wreq::Response, ClewdrError> {
-        let beta_header = if use_context_1m {
-            CLAUDE_BETA_CONTEXT_1M
-        } else {
-            CLAUDE_BETA_BASE
-        };
-
-        self.client
+        let (beta_header, version) = self.anthropic_headers(use_context_1m);
+        let req = self
+            .client
             .post(self.endpoint.join("v1/messages").expect("Url parse error"))
             .bearer_auth(access_token)
             .header("anthropic-beta", beta_header)
-            .header("anthropic-version", "2023-06-01")
-            .json(body)
-            .send()
-            .await
-            .context(WreqSnafu {
+            .header("anthropic-version", version)
+            .json(body);
+        first_byte(&self.timeouts, async {
+            req.send_captured().await.context(WreqSnafu {
                 msg: "Failed to send chat message",
-            })?
-            .check_claude()
-            .await
+            })
+        })
+        .await?
+        .check_claude()
+        .await
+    }
+
+    /// `anthropic-beta` and `anthropic-version` of an upstream request
+    ///
+    /// The OAuth flag is always sent, the 1M context flag when it is tried, followed by
+    /// the client flags the `anthropic_betas` allowlist let through.
+    fn anthropic_headers(&self, use_context_1m: bool) -> (String, String) {
+        let mut betas = vec![CLAUDE_BETA_BASE];
+        if use_context_1m {
+            betas.push(CLAUDE_BETA_CONTEXT_1M);
+        }
+        for beta in &self.anthropic_betas {
+            if !betas.contains(&beta.as_str()) {
+                betas.push(beta);
+            }
+        }
+        let version = self
+            .anthropic_version
+            .to_owned()
+            .unwrap_or_else(|| CLEWDR_CONFIG.load().anthropic_version.to_owned());
+        (betas.join(","), version)
     }
 
     async fn persist_claude_1m_support(&mut self, value: bool) {
@@ -228,73 +262,41 @@ impl ClaudeCodeState {
         p: CreateMessageParams,
         for_web: bool,
     ) -> Result<axum::response::Response, ClewdrError> {
-        for i in 0..CLEWDR_CONFIG.load().max_retries + 1 {
-            if i > 0 {
-                info!("[TOKENS][RETRY] attempt: {}", i.to_string().green());
-            }
-            let mut state = self.to_owned();
-            let p = p.to_owned();
-
-            let cookie = state.request_cookie().await?;
-            let web_attempt_allowed = CLEWDR_CONFIG.load().enable_web_count_tokens;
-            let cookie_disallows = matches!(cookie.count_tokens_allowed, Some(false));
-            if cookie_disallows || (for_web && !web_attempt_allowed) {
-                if cookie_disallows {
-                    state.persist_count_tokens_allowed(false).await;
-                }
-                return Ok(Self::local_count_tokens_response(&p));
-            }
-            let retry = async {
-                match state.check_token() {
-                    TokenStatus::None => {
-                        info!("No token found, requesting new token");
-                        let org = state.get_organization().await?;
-                        let code_res = state.exchange_code(&org).await?;
-                        state.exchange_token(code_res).await?;
-                        state.return_cookie(None).await;
-                    }
-                    TokenStatus::Expired => {
-                        info!("Token expired, refreshing token");
-                        state.refresh_token().await?;
-                        state.return_cookie(None).await;
-                    }
-                    TokenStatus::Valid => {
-                        info!("Token is valid, proceeding with count_tokens");
+        let p = &p;
+        RetryPolicy::from_config()
+            .without_backoff()
+            .label("[TOKENS][RETRY]")
+            .executor(retry::invalid_cookie, Self::return_rejected)
+            .run(self, |mut state| async move {
+                let cookie = match state.request_cookie().await {
+                    Ok(cookie) => cookie,
+                    Err(e) => return (state, Err(e)),
+                };
+                let web_attempt_allowed = CLEWDR_CONFIG.load().enable_web_count_tokens;
+                let cookie_disallows = matches!(cookie.count_tokens_allowed, Some(false));
+                if cookie_disallows || (for_web && !web_attempt_allowed) {
+                    if cookie_disallows {
+                        state.persist_count_tokens_allowed(false).await;
                     }
+                    return (state, Ok(Self::local_count_tokens_response(p)));
                 }
-                let Some(access_token) = state.cookie.as_ref().and_then(|c| c.token.to_owned())
-                else {
-                    return Err(ClewdrError::UnexpectedNone {
-                        msg: "No access token found in cookie",
-                    });
-                };
-                state
-                    .perform_count_tokens(access_token.access_token.to_owned(), p, for_web)
-                    .await
-            }
-            .instrument(tracing::info_span!(
-                "claude_code_tokens",
-                "cookie" = cookie.cookie.ellipse()
-            ));
-            match retry.await {
-                Ok(res) => {
-                    return Ok(res);
+                let res = async {
+                    let access_token = state.access_token("count_tokens").await?;
+                    state
+                        .perform_count_tokens(access_token, p.to_owned(), for_web)
+                        .await
                 }
-                Err(e) => {
-                    error!(
-                        "[{}][TOKENS] {}",
-                        state.cookie.as_ref().unwrap().cookie.ellipse().green(),
-                        e
-                    );
-                    if let ClewdrError::InvalidCookie { reason } = e {
-                        state.return_cookie(Some(reason.to_owned())).await;
-                        continue;
-                    }
-                    return Err(e);
+                .instrument(tracing::info_span!(
+                    "claude_code_tokens",
+                    "cookie" = cookie.cookie.ellipse()
+                ))
+                .await;
+                if let Err(ref e) = res {
+                    error!("[{}][TOKENS] {}", cookie.cookie.ellipse().green(), e);
                 }
-            }
-        }
-        Err(ClewdrError::TooManyRetries)
+                (state, res)
+            })
+            .await
     }
 
     async fn perform_count_tokens(
@@ -340,7 +342,7 @@ impl ClaudeCodeState {
                     if is_sonnet && use_1m {
                         self.persist_claude_1m_support(true).await;
                     }
-                    let (resp, _) = Self::materialize_non_stream_response(response).await?;
+                    let (resp, ..) = Self::materialize_non_stream_response(response).await?;
                     return Ok(resp);
                 }
                 Err(err) => {
@@ -380,9 +382,10 @@ impl ClaudeCodeState {
         model_family: ModelFamily,
     ) -> Result<axum::response::Response, ClewdrError> {
         if !self.stream {
-            let (resp, usage_pair) = Self::materialize_non_stream_response(response).await?;
+            let (resp, usage_pair, cache) = Self::materialize_non_stream_response(response).await?;
             let (input, output) = usage_pair.unwrap_or((self.usage.input_tokens as u64, 0));
-            self.persist_usage_totals(input, output, model_family).await;
+            self.persist_usage_totals(input, output, cache, model_family)
+                .await;
             if mark_support_true {
                 self.persist_claude_1m_support(true).await;
             }
@@ -396,14 +399,22 @@ impl ClaudeCodeState {
         }
     }
 
-    async fn persist_usage_totals(&mut self, input: u64, output: u64, family: ModelFamily) {
+    async fn persist_usage_totals(
+        &mut self,
+        input: u64,
+        output: u64,
+        cache: (u64, u64),
+        family: ModelFamily,
+    ) {
         if input == 0 && output == 0 {
             return;
         }
+        Self::log_cache_usage(cache);
         if let Some(cookie) = self.cookie.as_mut() {
             // Lazy boundary refresh if due, then reset period counters and start fresh
             Self::update_cookie_boundaries_if_due(cookie).await;
             cookie.add_and_bucket_usage(input, output, family);
+            cookie.add_cache_usage(cache.0, cache.1);
             let cloned = cookie.clone();
             if let Err(err) = self.cookie_actor_handle.return_cookie(cloned, None).await {
                 warn!("Failed to persist usage statistics: {}", err);
@@ -418,13 +429,27 @@ impl ClaudeCodeState {
     ) -> Result<axum::response::Response, ClewdrError> {
         use std::sync::{
             Arc,
-            atomic::{AtomicU64, Ordering},
+            atomic::{AtomicBool, AtomicU64, Ordering},
         };
 
         let input_tokens = self.usage.input_tokens as u64;
         let output_sum = Arc::new(AtomicU64::new(0));
+        let cache_read = Arc::new(AtomicU64::new(0));
+        let cache_creation = Arc::new(AtomicU64::new(0));
         let handle = self.cookie_actor_handle.clone();
         let cookie = self.cookie.clone();
+        let returned = Arc::new(AtomicBool::new(false));
+        // a client too slow to read the stream gets its upstream request dropped
+        let on_abort = {
+            let (handle, cookie, returned) = (handle.clone(), cookie.clone(), returned.clone());
+            async move {
+                if let Some(cookie) = cookie
+                    && !returned.load(Ordering::Relaxed)
+                {
+                    let _ = handle.return_cookie(cookie, None).await;
+                }
+            }
+        };
 
         let osum = output_sum.clone();
         let stream = response.bytes_stream().eventsource().map_ok(move |event| {
@@ -433,18 +458,40 @@ impl ClaudeCodeState {
                 serde_json::from_str::<crate::types::claude::StreamEvent>(&event.data)
             {
                 match parsed {
+                    crate::types::claude::StreamEvent::MessageStart { message } => {
+                        // prompt cache usage is reported when the message starts, and again
+                        // as running totals in message_delta by newer API versions
+                        if let Some(ref u) = message.usage {
+                            let (read, creation) = Self::cache_tokens(u);
+                            cache_read.store(read, Ordering::Relaxed);
+                            cache_creation.store(creation, Ordering::Relaxed);
+                        }
+                    }
                     crate::types::claude::StreamEvent::MessageDelta { usage: Some(u), .. } => {
                         osum.fetch_add(u.output_tokens as u64, Ordering::Relaxed);
+                        if let Some(read) = u.cache_read_input_tokens {
+                            cache_read.fetch_max(read as u64, Ordering::Relaxed);
+                        }
+                        if let Some(creation) = u.cache_creation_input_tokens {
+                            cache_creation.fetch_max(creation as u64, Ordering::Relaxed);
+                        }
                     }
                     crate::types::claude::StreamEvent::MessageStop => {
                         // on stream completion, persist totals asynchronously
                         if let (Some(cookie), handle) = (cookie.clone(), handle.clone()) {
+                            returned.store(true, Ordering::Relaxed);
                             let total_out = osum.load(Ordering::Relaxed);
+                            let cache = (
+                                cache_read.load(Ordering::Relaxed),
+                                cache_creation.load(Ordering::Relaxed),
+                            );
+                            Self::log_cache_usage(cache);
                             let mut c = cookie.clone();
                             tokio::spawn(async move {
                                 // Update period boundaries if needed, then accumulate
                                 ClaudeCodeState::update_cookie_boundaries_if_due(&mut c).await;
                                 c.add_and_bucket_usage(input_tokens, total_out, family);
+                                c.add_cache_usage(cache.0, cache.1);
                                 let _ = handle.return_cookie(c, None).await;
                             });
                         }
@@ -462,20 +509,25 @@ impl ClaudeCodeState {
             e.data(event.data)
         });
 
-        Ok(Sse::new(stream)
+        Ok(Sse::new(backpressure::relay(stream, on_abort))
             .keep_alive(Default::default())
             .into_response())
     }
 
     async fn materialize_non_stream_response(
         response: wreq::Response,
-    ) -> Result<(axum::response::Response, Option<(u64, u64)>), ClewdrError> {
+    ) -> Result<(axum::response::Response, Option<(u64, u64)>, (u64, u64)), ClewdrError> {
         let status = response.status();
         let headers = response.headers().clone();
         let bytes = response.bytes().await.context(WreqSnafu {
             msg: "Failed to read Claude response body",
         })?;
-        let usage = Self::extract_usage_from_bytes(&bytes);
+        let usage = crate::types::claude::parse_usage(&bytes);
+        let cache = serde_json::from_slice::<serde_json::Value>(&bytes)
+            .ok()
+            .and_then(|v| serde_json::from_value::<Usage>(v.get("usage")?.to_owned()).ok())
+            .map(|u| Self::cache_tokens(&u))
+            .unwrap_or_default();
 
         let mut builder = http::Response::builder().status(status);
         for (key, value) in headers.iter() {
@@ -488,34 +540,28 @@ impl ClaudeCodeState {
                     loc: snafu::Location::generate(),
                     source: e,
                 })?;
-        Ok((response, usage))
+        Ok((response, usage, cache))
     }
 
-    fn extract_usage_from_bytes(bytes: &[u8]) -> Option<(u64, u64)> {
-        // Prefer explicit usage if present
-        if let Ok(value) = serde_json::from_slice::<serde_json::Value>(bytes)
-            && let Some(usage) = value.get("usage")
-        {
-            let input = usage
-                .get("input_tokens")
-                .and_then(|v| v.as_u64().or_else(|| v.as_i64().map(|n| n.max(0) as u64)));
-            let output = usage
-                .get("output_tokens")
-                .and_then(|v| v.as_u64().or_else(|| v.as_i64().map(|n| n.max(0) as u64)));
-            if let (Some(i), Some(o)) = (input, output) {
-                return Some((i, o));
-            }
-        }
+    /// Prompt cache tokens reported in a usage block
+    ///
+    /// # Returns
+    /// * `(u64, u64)` - Tokens read from and written to the cache
+    fn cache_tokens(usage: &Usage) -> (u64, u64) {
+        (
+            usage.cache_read_input_tokens.unwrap_or_default() as u64,
+            usage.cache_creation_input_tokens.unwrap_or_default() as u64,
+        )
+    }
 
-        // Fallback: estimate output tokens from the Claude response content
-        if let Ok(parsed) =
-            serde_json::from_slice::<crate::types::claude::CreateMessageResponse>(bytes)
-        {
-            let output_tokens = parsed.count_tokens() as u64;
-            // Input tokens already computed earlier and present in self.usage; only estimate output here
-            return Some((0, output_tokens));
+    fn log_cache_usage((read, creation): (u64, u64)) {
+        if read > 0 || creation > 0 {
+            info!(
+                "[CACHE] read: {}, written: {}",
+                read.to_string().green(),
+                creation.to_string().yellow()
+            );
         }
-        None
     }
 
     async fn execute_claude_count_tokens_request(
@@ -524,12 +570,7 @@ impl ClaudeCodeState {
         body: &CreateMessageParams,
         use_context_1m: bool,
     ) -> Result<wreq::Response, ClewdrError> {
-        let beta_header = if use_context_1m {
-            CLAUDE_BETA_CONTEXT_1M
-        } else {
-            CLAUDE_BETA_BASE
-        };
-
+        let (beta_header, version) = self.anthropic_headers(use_context_1m);
         self.client
             .post(
                 self.endpoint
@@ -538,9 +579,9 @@ impl ClaudeCodeState {
             )
             .bearer_auth(access_token)
             .header("anthropic-beta", beta_header)
-            .header("anthropic-version", "2023-06-01")
+            .header("anthropic-version", version)
             .json(body)
-            .send()
+            .send_captured()
             .await
             .context(WreqSnafu {
                 msg: "Failed to call Claude count_tokens",
@@ -666,26 +707,22 @@ impl ClaudeCodeState {
     async fn fetch_usage_resets(
         cookie: &crate::config::ClewdrCookie,
     ) -> Option<(Option<i64>, Option<i64>, Option<i64>)> {
-        // Build a fresh client (mirrors misc.rs behavior)
-        let mut builder = ClientBuilder::new()
-            .cookie_store(true)
-            .emulation(Emulation::Chrome136);
-        if let Some(proxy) = CLEWDR_CONFIG.load().wreq_proxy.clone() {
-            builder = builder.proxy(proxy);
-        }
-        let client = builder.build().ok()?;
-
-        // Attach cookie for both api and console domains
+        // Pooled client (mirrors misc.rs behavior), the cookie goes to both api and console domains
+        let client = client_pool::client(
+            ClientKind::Claude,
+            current_proxy(ProxyTarget::Claude),
+            &current_timeouts(ProxyTarget::Claude),
+        )
+        .ok()?;
         let endpoint: Url = CLEWDR_CONFIG.load().endpoint();
         let cookie_header = http::HeaderValue::from_str(&cookie.to_string()).ok()?;
-        client.set_cookie(&endpoint, &cookie_header);
         let console_url = Url::parse(CLAUDE_CONSOLE_ENDPOINT).ok()?;
-        client.set_cookie(&console_url, &cookie_header);
 
         // Discover organization UUID (prefer chat-capable org)
         let orgs_url = endpoint.join("api/organizations").ok()?;
         let orgs_res = client
             .request(Method::GET, orgs_url)
+            .header(COOKIE, &cookie_header)
             .header(ORIGIN, crate::config::CLAUDE_ENDPOINT)
             .header(REFERER, format!("{CLAUDE_ENDPOINT}new"))
             .send()
@@ -720,8 +757,24 @@ impl ClaudeCodeState {
         let usage_url = console_url
             .join(&format!("api/organizations/{}/usage", org_uuid))
             .ok()?;
-        let usage_res = client.request(Method::GET, usage_url).send().await.ok()?;
+        let usage_res = client
+            .request(Method::GET, usage_url)
+            .header(COOKIE, &cookie_header)
+            .send()
+            .await
+            .ok()?;
         let usage: serde_json::Value = usage_res.json().await.ok()?;
+        let utilization = |obj_key: &str| {
+            usage
+                .get(obj_key)
+                .and_then(|o| o.get("utilization"))
+                .and_then(|v| v.as_u64())
+                .unwrap_or_default() as u32
+        };
+        quota::record(
+            &cookie.to_string(),
+            [utilization("five_hour"), utilization("seven_day")],
+        );
 
         let parse_reset = |obj_key: &str| -> Option<i64> {
             usage
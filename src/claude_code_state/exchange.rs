@@ -12,7 +12,9 @@ use oauth2::{
 };
 use serde_json::Value;
 use snafu::{OptionExt, ResultExt};
+use tracing::warn;
 use url::Url;
+use wreq::header::COOKIE;
 
 use crate::{
     claude_code_state::ClaudeCodeState,
@@ -93,9 +95,8 @@ fn setup_client(cc_client_id: String) -> Result<ClaudeOauthClient, ClewdrError>
 impl ClaudeCodeState {
     pub async fn exchange_code(&self, org_uuid: &str) -> Result<ExchangeResult, ClewdrError> {
         // Build OAuth authorization URL using Url::join for proper URL construction
-        let authorize_url = CLEWDR_CONFIG
-            .load()
-            .endpoint()
+        let authorize_url = self
+            .endpoint
             .join(&format!("v1/oauth/{}/authorize", org_uuid))
             .expect("Url parse error");
         let cc_client_id = CLEWDR_CONFIG.load().cc_client_id();
@@ -121,9 +122,11 @@ impl ClaudeCodeState {
         let wreq_client = self.get_wreq_client();
         let redirect_json = wreq_client
             .post(auth_url)
+            .header(COOKIE, self.cookie_header())
             .json(&query_params)
             .send()
             .await
+            .inspect(|res| self.jar.keep(res))
             .context(WreqSnafu {
                 msg: "Failed to send authorization request",
             })?
@@ -187,9 +190,8 @@ impl ClaudeCodeState {
     }
 
     pub async fn refresh_token(&mut self) -> Result<(), ClewdrError> {
-        let wreq_client = self.get_wreq_client();
         let Some(CookieStatus {
-            token: Some(ref mut token),
+            token: Some(ref token),
             ..
         }) = self.cookie
         else {
@@ -200,6 +202,21 @@ impl ClaudeCodeState {
         if !token.is_expired() {
             return Ok(());
         }
+        self.renew_token().await
+    }
+
+    /// Refreshes the OAuth token regardless of its expiry
+    async fn renew_token(&mut self) -> Result<(), ClewdrError> {
+        let wreq_client = self.get_wreq_client();
+        let Some(CookieStatus {
+            token: Some(ref mut token),
+            ..
+        }) = self.cookie
+        else {
+            return Err(ClewdrError::UnexpectedNone {
+                msg: "No token found to refresh token",
+            });
+        };
 
         let cc_client_id = CLEWDR_CONFIG.load().cc_client_id();
 
@@ -224,6 +241,37 @@ impl ClaudeCodeState {
         Ok(())
     }
 
+    /// Forces a new OAuth token for the current cookie
+    ///
+    /// Tries the refresh token first and falls back to a full code exchange,
+    /// then hands the updated cookie back to the cookie manager for persistence.
+    ///
+    /// # Returns
+    /// * `Result<TokenInfo, ClewdrError>` - The new token
+    pub async fn force_refresh_token(&mut self) -> Result<TokenInfo, ClewdrError> {
+        let has_token = self.cookie.as_ref().is_some_and(|c| c.token.is_some());
+        let refreshed = if has_token {
+            self.renew_token()
+                .await
+                .inspect_err(|e| warn!("Refresh token rejected, exchanging a new one: {}", e))
+                .is_ok()
+        } else {
+            false
+        };
+        if !refreshed {
+            let org = self.get_organization().await?;
+            let code_res = self.exchange_code(&org).await?;
+            self.exchange_token(code_res).await?;
+        }
+        self.return_cookie(None).await;
+        self.cookie
+            .as_ref()
+            .and_then(|c| c.token.to_owned())
+            .ok_or(ClewdrError::UnexpectedNone {
+                msg: "No token found after refresh",
+            })
+    }
+
     fn get_wreq_client(&self) -> wreq::Client {
         self.client.clone()
     }
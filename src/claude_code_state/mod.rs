@@ -3,19 +3,21 @@ mod exchange;
 mod organization;
 use http::{
     HeaderValue, Method,
-    header::{ORIGIN, REFERER},
+    header::{COOKIE, ORIGIN, REFERER},
 };
-use snafu::ResultExt;
 use tracing::error;
-use wreq::{ClientBuilder, IntoUrl, RequestBuilder};
-use wreq_util::Emulation;
+use wreq::{IntoUrl, RequestBuilder};
 
 use crate::{
     claude_web_state::SUPER_CLIENT,
-    config::{CLAUDE_ENDPOINT, CLEWDR_CONFIG, CookieStatus, Reason},
-    error::{ClewdrError, WreqSnafu},
-    middleware::claude::ClaudeApiFormat,
-    services::cookie_actor::CookieActorHandle,
+    config::{CLAUDE_ENDPOINT, CookieStatus, Priority, ProxyTarget, Reason, Timeouts},
+    error::ClewdrError,
+    middleware::{claude::ClaudeApiFormat, proxy::current_proxy, timeout::current_timeouts},
+    services::{
+        client_pool::{self, ClientKind, SessionJar},
+        cookie_actor::{CookieActorHandle, CookieRequest},
+        endpoints::{self, Upstream},
+    },
     types::claude::Usage,
 };
 
@@ -24,12 +26,25 @@ pub struct ClaudeCodeState {
     pub cookie_actor_handle: CookieActorHandle,
     pub cookie: Option<CookieStatus>,
     pub cookie_header_value: HeaderValue,
-    pub proxy: Option<wreq::Proxy>,
+    /// URL of the proxy
+    pub proxy: Option<String>,
+    /// Cookies claude.ai set for the current cookie
+    pub jar: SessionJar,
+    /// Timeouts of the upstream requests, resolved when the state is created
+    pub timeouts: Timeouts,
     pub endpoint: url::Url,
     pub client: wreq::Client,
     pub api_format: ClaudeApiFormat,
     pub stream: bool,
     pub system_prompt_hash: Option<u64>,
+    /// Tags the requested cookie must carry
+    pub cookie_tags: Vec<String>,
+    /// Priority of the cookie request when it has to wait
+    pub priority: Priority,
+    /// `anthropic-version` of upstream requests, the configured one when `None`
+    pub anthropic_version: Option<String>,
+    /// Client `anthropic-beta` flags sent next to the ones ClewdR needs
+    pub anthropic_betas: Vec<String>,
     pub usage: Usage,
 }
 
@@ -40,12 +55,18 @@ impl ClaudeCodeState {
             cookie_actor_handle,
             cookie: None,
             cookie_header_value: HeaderValue::from_static(""),
-            proxy: CLEWDR_CONFIG.load().wreq_proxy.to_owned(),
-            endpoint: CLEWDR_CONFIG.load().endpoint(),
+            proxy: current_proxy(ProxyTarget::Claude),
+            jar: SessionJar::default(),
+            timeouts: current_timeouts(ProxyTarget::Claude),
+            endpoint: endpoints::pick(Upstream::Claude),
             client: SUPER_CLIENT.to_owned(),
             api_format: ClaudeApiFormat::Claude,
             stream: false,
             system_prompt_hash: None,
+            cookie_tags: Vec::new(),
+            priority: Priority::Normal,
+            anthropic_version: None,
+            anthropic_betas: Vec::new(),
             usage: Usage::default(),
         }
     }
@@ -64,13 +85,23 @@ impl ClaudeCodeState {
         }
     }
 
+    /// Returns the cookie of a failed attempt with the reason it was rejected
+    pub fn return_rejected(mut self, e: &ClewdrError) -> impl Future<Output = ()> + use<> {
+        if let Some(cookie) = self.cookie.as_mut() {
+            cookie.record_rejection(e);
+        }
+        let reason = match e {
+            ClewdrError::InvalidCookie { reason } => Some(reason.to_owned()),
+            _ => None,
+        };
+        async move { self.return_cookie(reason).await }
+    }
+
     /// Build a request with the current cookie and proxy settings
     pub fn build_request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
-        // let r = SUPER_CLIENT.cloned();
-        self.client
-            .set_cookie(&self.endpoint, &self.cookie_header_value);
         self.client
             .request(method, url)
+            .header(COOKIE, self.cookie_header())
             .header(ORIGIN, CLAUDE_ENDPOINT)
             .header(REFERER, format!("{CLAUDE_ENDPOINT}new"))
     }
@@ -80,30 +111,44 @@ impl ClaudeCodeState {
         self.cookie_header_value = value;
     }
 
+    /// `Cookie` header of requests to claude.ai, the account cookie with the ones set meanwhile
+    pub fn cookie_header(&self) -> HeaderValue {
+        self.jar.header(&self.cookie_header_value, &self.endpoint)
+    }
+
     /// Requests a new cookie from the cookie manager
     /// Updates the internal state with the new cookie and proxy configuration
     pub async fn request_cookie(&mut self) -> Result<CookieStatus, ClewdrError> {
         let res = self
             .cookie_actor_handle
-            .request(self.system_prompt_hash)
+            .request(CookieRequest {
+                hash: self.system_prompt_hash,
+                tags: self.cookie_tags.to_owned(),
+                priority: self.priority,
+                capability: None,
+            })
             .await?;
-        self.cookie = Some(res.to_owned());
-        self.cookie_header_value = HeaderValue::from_str(res.cookie.to_string().as_str())?;
-        // Always pull latest proxy/endpoint before building the client
-        self.proxy = CLEWDR_CONFIG.load().wreq_proxy.to_owned();
-        self.endpoint = CLEWDR_CONFIG.load().endpoint();
-        let mut client = ClientBuilder::new()
-            .cookie_store(true)
-            .emulation(Emulation::Chrome136);
-        if let Some(ref proxy) = self.proxy {
-            client = client.proxy(proxy.to_owned());
-        }
-        self.client = client.build().context(WreqSnafu {
-            msg: "Failed to build client with new cookie",
-        })?;
+        tracing::Span::current()
+            .record("provider", "claude_code")
+            .record("cookie", res.cookie.ellipse());
+        self.use_cookie(res.to_owned())?;
         Ok(res)
     }
 
+    /// Binds a specific cookie to this state without going through the cookie manager
+    /// Picks the pooled client of the latest proxy and endpoint configuration
+    pub fn use_cookie(&mut self, cookie: CookieStatus) -> Result<(), ClewdrError> {
+        self.cookie_header_value = HeaderValue::from_str(cookie.cookie.to_string().as_str())?;
+        self.cookie = Some(cookie);
+        self.jar = SessionJar::default();
+        // Always pull latest proxy/endpoint before picking the client
+        self.proxy = current_proxy(ProxyTarget::Claude);
+        self.endpoint = endpoints::pick(Upstream::Claude);
+        self.client =
+            client_pool::client(ClientKind::Claude, self.proxy.to_owned(), &self.timeouts)?;
+        Ok(())
+    }
+
     pub fn check_token(&self) -> TokenStatus {
         let Some(CookieStatus {
             token: Some(token_info),
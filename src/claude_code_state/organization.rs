@@ -5,12 +5,13 @@ use snafu::ResultExt;
 
 use super::ClaudeCodeState;
 use crate::{
-    config::Reason,
+    config::{ClaudeOrg, Reason},
     error::{CheckClaudeErr, ClewdrError, WreqSnafu},
     utils::print_out_json,
 };
 
 impl ClaudeCodeState {
+    /// Finds the organization to exchange a token for, the pinned one if the cookie has it
     pub async fn get_organization(&self) -> Result<String, ClewdrError> {
         let end_point = self
             .endpoint
@@ -20,6 +21,7 @@ impl ClaudeCodeState {
             .build_request(Method::GET, end_point)
             .send()
             .await
+            .inspect(|res| self.jar.keep(res))
             .context(WreqSnafu {
                 msg: "Failed to bootstrap",
             })?
@@ -35,14 +37,25 @@ impl ClaudeCodeState {
         let memberships = bootstrap["account"]["memberships"]
             .as_array()
             .ok_or(Reason::Null)?;
-        let boot_acc_info = memberships
+        let chat_orgs = memberships
             .iter()
-            .find(|m| {
-                m["organization"]["capabilities"]
+            .map(|m| &m["organization"])
+            .filter(|o| {
+                o["capabilities"]
                     .as_array()
                     .is_some_and(|c| c.iter().any(|c| c.as_str() == Some("chat")))
             })
-            .and_then(|m| m["organization"].as_object())
+            .collect::<Vec<_>>();
+        let orgs = chat_orgs
+            .iter()
+            .filter_map(|o| ClaudeOrg::from_json(o))
+            .collect::<Vec<_>>();
+        let pinned = self.cookie.as_ref().and_then(|c| c.pinned_org.as_deref());
+        let pinned = ClaudeOrg::select(&orgs, None, pinned).map(|o| o.uuid.as_str());
+        let boot_acc_info = chat_orgs
+            .iter()
+            .find(|o| pinned.is_none_or(|p| o["uuid"].as_str() == Some(p)))
+            .and_then(|o| o.as_object())
             .ok_or(Reason::Null)?;
         let capabilities = boot_acc_info["capabilities"]
             .as_array()
@@ -0,0 +1,250 @@
+use std::{mem, sync::LazyLock};
+
+use eventsource_stream::Event;
+use regex::Regex;
+use serde_json::{Value, json};
+
+use crate::types::claude::{ContentBlock, ContentBlockDelta, StreamEvent};
+
+/// Name of the tool artifacts are reported as, in `tool_use` blocks and OpenAI `tool_calls`
+pub const ARTIFACT_TOOL: &str = "artifact";
+
+const OPEN_TAG: &str = "<antArtifact";
+const CLOSE_TAG: &str = "</antArtifact>";
+
+static ATTRIBUTE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"([\w-]+)\s*=\s*"([^"]*)""#).expect("valid regex"));
+
+/// Artifact Claude.ai wrote into the text, such as a code file or a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    /// Attributes of the opening tag: `identifier`, `type`, `title` and `language`
+    pub attributes: Vec<(String, String)>,
+    pub content: String,
+}
+
+impl Artifact {
+    /// Tool use block carrying the attributes and the content as its input
+    pub fn to_tool_use(&self) -> ContentBlock {
+        let mut input = self
+            .attributes
+            .iter()
+            .map(|(k, v)| (k.to_owned(), Value::from(v.as_str())))
+            .collect::<serde_json::Map<_, _>>();
+        input.insert("content".into(), self.content.as_str().into());
+        ContentBlock::ToolUse {
+            id: format!("toolu_{}", uuid::Uuid::new_v4().simple()),
+            name: ARTIFACT_TOOL.into(),
+            input: input.into(),
+        }
+    }
+}
+
+/// Length of the end of `text` that may be the start of `tag`, held back until more text arrives
+fn partial_tag(text: &str, tag: &str) -> usize {
+    text.rfind('<')
+        .filter(|&i| tag.starts_with(&text[i..]))
+        .map_or(0, |i| text.len() - i)
+}
+
+/// Splits `<antArtifact>` blocks out of text that may arrive in pieces
+#[derive(Debug, Default)]
+pub struct ArtifactExtractor {
+    /// Text not yet classified, a possibly partial tag
+    pending: String,
+    /// Opening tag of the artifact being read
+    open: Option<String>,
+    content: String,
+    artifacts: Vec<Artifact>,
+}
+
+impl ArtifactExtractor {
+    /// Feeds a piece of text, returns the text outside artifacts that can be sent on
+    pub fn push(&mut self, text: &str) -> String {
+        let mut buf = mem::take(&mut self.pending) + text;
+        let mut out = String::new();
+        loop {
+            match self.open {
+                None => {
+                    let Some(start) = buf.find(OPEN_TAG) else {
+                        let keep = partial_tag(&buf, OPEN_TAG);
+                        self.pending = buf.split_off(buf.len() - keep);
+                        out.push_str(&buf);
+                        return out;
+                    };
+                    let Some(end) = buf[start..].find('>').map(|i| start + i + 1) else {
+                        self.pending = buf.split_off(start);
+                        out.push_str(&buf);
+                        return out;
+                    };
+                    out.push_str(&buf[..start]);
+                    self.open = Some(buf[start..end].to_string());
+                    buf.drain(..end);
+                }
+                Some(ref open) => {
+                    let Some(end) = buf.find(CLOSE_TAG) else {
+                        let keep = partial_tag(&buf, CLOSE_TAG);
+                        self.pending = buf.split_off(buf.len() - keep);
+                        self.content.push_str(&buf);
+                        return out;
+                    };
+                    self.content.push_str(&buf[..end]);
+                    let attributes = ATTRIBUTE
+                        .captures_iter(open)
+                        .map(|c| (c[1].to_string(), c[2].to_string()))
+                        .collect();
+                    self.artifacts.push(Artifact {
+                        attributes,
+                        content: mem::take(&mut self.content).trim_matches('\n').to_string(),
+                    });
+                    self.open = None;
+                    buf.drain(..end + CLOSE_TAG.len());
+                }
+            }
+        }
+    }
+
+    /// Ends the text, returns what was held back, an unterminated artifact stays text
+    pub fn finish(&mut self) -> String {
+        let open = self.open.take().unwrap_or_default();
+        open + &mem::take(&mut self.content) + &mem::take(&mut self.pending)
+    }
+
+    /// Artifacts completed so far
+    pub fn take_artifacts(&mut self) -> Vec<Artifact> {
+        mem::take(&mut self.artifacts)
+    }
+}
+
+/// Text of a whole response with its artifacts removed, and the artifacts
+pub fn extract(text: &str) -> (String, Vec<Artifact>) {
+    let mut extractor = ArtifactExtractor::default();
+    let text = extractor.push(text) + &extractor.finish();
+    (text, extractor.take_artifacts())
+}
+
+/// Moves artifacts out of the text blocks of a Claude.ai event stream
+///
+/// Text deltas are sent without the artifacts, which follow as `tool_use` blocks after the
+/// last content block, right before the `message_delta` event.
+#[derive(Debug, Default)]
+pub struct ArtifactStream {
+    extractor: ArtifactExtractor,
+    /// Block the last text delta belonged to
+    text_index: Option<usize>,
+    /// First block index not used by the upstream
+    next_index: usize,
+}
+
+impl ArtifactStream {
+    /// Events to send before `event`, and whether `event` itself is sent
+    pub fn process(&mut self, event: &StreamEvent) -> (Vec<StreamEvent>, bool) {
+        let text_delta = |index, text: String| StreamEvent::ContentBlockDelta {
+            index,
+            delta: ContentBlockDelta::TextDelta { text },
+        };
+        match event {
+            StreamEvent::ContentBlockStart { index, .. } => {
+                self.next_index = self.next_index.max(index + 1);
+                (vec![], true)
+            }
+            StreamEvent::ContentBlockDelta {
+                index,
+                delta: ContentBlockDelta::TextDelta { text },
+            } => {
+                self.text_index = Some(*index);
+                let text = self.extractor.push(text);
+                let events = (!text.is_empty())
+                    .then(|| text_delta(*index, text))
+                    .into_iter()
+                    .collect();
+                (events, false)
+            }
+            StreamEvent::ContentBlockStop { index } if self.text_index == Some(*index) => {
+                let text = self.extractor.finish();
+                let events = (!text.is_empty())
+                    .then(|| text_delta(*index, text))
+                    .into_iter()
+                    .collect();
+                (events, true)
+            }
+            StreamEvent::MessageDelta { .. } => {
+                let mut events = vec![];
+                for artifact in self.extractor.take_artifacts() {
+                    let index = self.next_index;
+                    self.next_index += 1;
+                    let ContentBlock::ToolUse { id, name, input } = artifact.to_tool_use() else {
+                        continue;
+                    };
+                    events.push(StreamEvent::ContentBlockStart {
+                        index,
+                        content_block: ContentBlock::ToolUse {
+                            id,
+                            name,
+                            input: json!({}),
+                        },
+                    });
+                    events.push(StreamEvent::ContentBlockDelta {
+                        index,
+                        delta: ContentBlockDelta::InputJsonDelta {
+                            partial_json: input.to_string(),
+                        },
+                    });
+                    events.push(StreamEvent::ContentBlockStop { index });
+                }
+                (events, true)
+            }
+            _ => (vec![], true),
+        }
+    }
+
+    /// Events to send for the upstream Claude.ai event `event`
+    ///
+    /// Artifact events are data only and come first, `event` follows unless it was taken
+    /// over. Events that are not Claude stream events pass unchanged.
+    pub fn relay(&mut self, event: Event) -> Vec<Event> {
+        let Ok(parsed) = serde_json::from_str::<StreamEvent>(&event.data) else {
+            return vec![event];
+        };
+        let (before, forward) = self.process(&parsed);
+        before
+            .iter()
+            .map(|e| Event {
+                data: serde_json::to_string(e).unwrap_or_default(),
+                ..Default::default()
+            })
+            .chain(forward.then_some(event))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn artifacts_split_across_pieces() {
+        let text = "Here it is:\n<antArtifact identifier=\"hello\" type=\"application/vnd.ant.code\" language=\"python\" title=\"Hello\">\nprint('<hi>')\n</antArtifact>\nDone <b>.";
+        let (rest, artifacts) = extract(text);
+        assert_eq!(rest, "Here it is:\n\nDone <b>.");
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].content, "print('<hi>')");
+        assert_eq!(
+            artifacts[0].attributes[2],
+            ("language".to_string(), "python".to_string())
+        );
+
+        let mut extractor = ArtifactExtractor::default();
+        let mut out = String::new();
+        for piece in text.as_bytes().chunks(5) {
+            out += &extractor.push(std::str::from_utf8(piece).unwrap());
+        }
+        out += &extractor.finish();
+        assert_eq!(out, rest);
+        assert_eq!(extractor.take_artifacts(), artifacts);
+
+        let (rest, artifacts) = extract("<antArtifact title=\"cut\">unfinished");
+        assert_eq!(rest, "<antArtifact title=\"cut\">unfinished");
+        assert!(artifacts.is_empty());
+    }
+}
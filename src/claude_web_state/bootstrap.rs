@@ -3,15 +3,21 @@ use std::fmt::Write;
 use colored::Colorize;
 use serde_json::Value;
 use snafu::ResultExt;
+use tracing::{info, warn};
 use wreq::Method;
 
 use crate::{
     claude_web_state::ClaudeWebState,
-    config::{CLEWDR_CONFIG, Reason},
+    config::{CLEWDR_CONFIG, ClaudeOrg, ClearedFlags, Reason},
     error::{CheckClaudeErr, ClewdrError, WreqSnafu},
     utils::print_out_json,
 };
 
+/// Header selecting the organization of accounts with several workspaces, by UUID or name
+pub const ORG_HEADER: &str = "x-clewdr-org";
+/// Metadata field selecting the organization, like `x-clewdr-org`
+pub const ORG_FIELD: &str = "org";
+
 impl ClaudeWebState {
     /// Bootstraps the application state by initializing connections to Claude.ai
     ///
@@ -19,7 +25,7 @@ impl ClaudeWebState {
     /// 1. Sends a request to get the bootstrap data from Claude.ai
     /// 2. Validates the cookie and account information
     /// 3. Collects capabilities and checks if the account is pro
-    /// 4. Retrieves organization information
+    /// 4. Retrieves organization information, picking the requested, pinned or most capable one
     /// 5. Checks for account flags (restrictions, warnings, bans)
     ///
     /// # Returns
@@ -33,6 +39,7 @@ impl ClaudeWebState {
             .build_request(Method::GET, end_point)
             .send()
             .await
+            .inspect(|res| self.jar.keep(res))
             .context(WreqSnafu {
                 msg: "Failed to bootstrap",
             })?
@@ -87,6 +94,78 @@ impl ClaudeWebState {
         )?;
 
         // Bootstrap complete
+        let chat_orgs = self.chat_organizations().await?;
+        let orgs = chat_orgs
+            .iter()
+            .filter_map(|v| ClaudeOrg::from_json(v))
+            .collect::<Vec<_>>();
+        let pinned = self.cookie.as_ref().and_then(|c| c.pinned_org.to_owned());
+        let selected = ClaudeOrg::select(&orgs, self.requested_org.as_deref(), pinned.as_deref())
+            .map(|o| o.uuid.to_owned());
+        let acc_info = selected
+            .and_then(|uuid| {
+                chat_orgs
+                    .iter()
+                    .find(|v| v["uuid"].as_str() == Some(uuid.as_str()))
+            })
+            .or_else(|| {
+                chat_orgs.iter().max_by_key(|v| {
+                    v.get("capabilities")
+                        .and_then(|c| c.as_array())
+                        .map(|c| c.len())
+                        .unwrap_or_default()
+                })
+            })
+            .ok_or(ClewdrError::UnexpectedNone {
+                msg: "Failed to find a valid organization in response",
+            })?;
+        if orgs.len() > 1 {
+            writeln!(
+                w,
+                "organization: {}",
+                acc_info["name"].as_str().unwrap_or_default().blue()
+            )?;
+        }
+        if let Some(cookie) = self.cookie.as_mut() {
+            cookie.organizations = orgs;
+        }
+
+        // dismiss the flags once, the first time the cookie is used
+        let mut acc_info = acc_info.to_owned();
+        if CLEWDR_CONFIG.load().clear_flags
+            && self
+                .cookie
+                .as_ref()
+                .is_some_and(|c| c.cleared_flags.is_none())
+        {
+            let cleared = self.dismiss_flags(&acc_info).await;
+            if let Some(flags) = acc_info["active_flags"].as_array_mut() {
+                flags.retain(|f| {
+                    !f["type"]
+                        .as_str()
+                        .is_some_and(|t| cleared.dismissed.iter().any(|d| d == t))
+                });
+            }
+            if let Some(cookie) = self.cookie.as_mut() {
+                cookie.cleared_flags = Some(cleared);
+            }
+        }
+
+        self.check_flags(&acc_info, w)?;
+
+        let u =
+            acc_info
+                .get("uuid")
+                .and_then(|u| u.as_str())
+                .ok_or(ClewdrError::UnexpectedNone {
+                    msg: "Failed to find UUID in organization response",
+                })?;
+        self.org_uuid = Some(u.to_string());
+        Ok(())
+    }
+
+    /// Organizations of the account with chat capability
+    async fn chat_organizations(&self) -> Result<Vec<Value>, ClewdrError> {
         let end_point = self
             .endpoint
             .join("api/organizations")
@@ -95,6 +174,7 @@ impl ClaudeWebState {
             .build_request(Method::GET, end_point)
             .send()
             .await
+            .inspect(|res| self.jar.keep(res))
             .context(WreqSnafu {
                 msg: "Failed to get organizations",
             })?
@@ -104,39 +184,106 @@ impl ClaudeWebState {
             msg: "Failed to parse organizations response",
         })?;
         print_out_json(&ret_json, "org.json");
-        let acc_info = ret_json
-            .as_array()
-            .and_then(|a| {
-                a.iter()
-                    .filter(|v| {
-                        v.get("capabilities")
-                            .and_then(|c| c.as_array())
-                            .is_some_and(|c| c.iter().any(|c| c.as_str() == Some("chat")))
-                    })
-                    .max_by_key(|v| {
-                        v.get("capabilities")
-                            .and_then(|c| c.as_array())
-                            .map(|c| c.len())
-                            .unwrap_or_default()
-                    })
+        let Value::Array(orgs) = ret_json else {
+            return Ok(Vec::new());
+        };
+        Ok(orgs
+            .into_iter()
+            .filter(|v| {
+                v.get("capabilities")
+                    .and_then(|c| c.as_array())
+                    .is_some_and(|c| c.iter().any(|c| c.as_str() == Some("chat")))
             })
-            .ok_or(ClewdrError::UnexpectedNone {
-                msg: "Failed to find a valid organization in response",
-            })?;
+            .collect())
+    }
 
-        self.check_flags(acc_info, w)?;
+    /// Dismisses the unexpired flags of an organization, like the legacy `clear_flags`
+    ///
+    /// Bans cannot be dismissed and are left alone, flags Claude.ai refuses to dismiss are logged.
+    async fn dismiss_flags(&self, org: &Value) -> ClearedFlags {
+        let now = chrono::Utc::now();
+        let present = org["active_flags"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|f| {
+                f["expires_at"]
+                    .as_str()
+                    .and_then(|e| chrono::DateTime::parse_from_rfc3339(e).ok())
+                    .is_none_or(|e| e > now)
+            })
+            .filter_map(|f| f["type"].as_str().map(String::from))
+            .collect::<Vec<_>>();
+        let mut dismissed = Vec::new();
+        if let Some(uuid) = org["uuid"].as_str() {
+            for flag in present.iter().filter(|f| !f.contains("banned")) {
+                match self.dismiss_flag(uuid, flag).await {
+                    Ok(()) => dismissed.push(flag.to_owned()),
+                    Err(e) => warn!("Failed to dismiss flag {}: {}", flag, e),
+                }
+            }
+        }
+        if !dismissed.is_empty() {
+            info!(
+                "[{}] dismissed flags: {}",
+                self.cookie
+                    .as_ref()
+                    .map(|c| c.cookie.ellipse())
+                    .unwrap_or_default()
+                    .green(),
+                dismissed.join(", ").yellow()
+            );
+        }
+        ClearedFlags {
+            at: now.timestamp(),
+            present,
+            dismissed,
+        }
+    }
 
-        let u =
-            acc_info
-                .get("uuid")
-                .and_then(|u| u.as_str())
-                .ok_or(ClewdrError::UnexpectedNone {
-                    msg: "Failed to find UUID in organization response",
-                })?;
-        self.org_uuid = Some(u.to_string());
+    async fn dismiss_flag(&self, org_uuid: &str, flag: &str) -> Result<(), ClewdrError> {
+        let end_point = self
+            .endpoint
+            .join(&format!(
+                "api/organizations/{org_uuid}/flags/{flag}/dismiss"
+            ))
+            .expect("Url parse error");
+        self.build_request(Method::POST, end_point)
+            .send()
+            .await
+            .inspect(|res| self.jar.keep(res))
+            .context(WreqSnafu {
+                msg: "Failed to dismiss flag",
+            })?
+            .check_claude()
+            .await?;
         Ok(())
     }
 
+    /// Dismisses the flags of every chat organization of the current cookie
+    ///
+    /// Records what was found on the cookie and hands it back to the cookie manager.
+    ///
+    /// # Returns
+    /// * `Result<ClearedFlags, ClewdrError>` - The flags found and dismissed
+    pub async fn clear_flags(&mut self) -> Result<ClearedFlags, ClewdrError> {
+        let mut cleared = ClearedFlags {
+            at: chrono::Utc::now().timestamp(),
+            present: Vec::new(),
+            dismissed: Vec::new(),
+        };
+        for org in self.chat_organizations().await? {
+            let flags = self.dismiss_flags(&org).await;
+            cleared.present.extend(flags.present);
+            cleared.dismissed.extend(flags.dismissed);
+        }
+        if let Some(cookie) = self.cookie.as_mut() {
+            cookie.cleared_flags = Some(cleared.to_owned());
+        }
+        self.return_cookie(None).await;
+        Ok(cleared)
+    }
+
     /// Checks if the account has any restrictions, warnings or bans
     ///
     /// Examines the account flags to determine if the account can be used:
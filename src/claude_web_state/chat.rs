@@ -1,16 +1,23 @@
-use colored::Colorize;
 use futures::TryFutureExt;
 use serde_json::json;
 use snafu::ResultExt;
-use tracing::{Instrument, debug, error, info, info_span, warn};
+use tracing::{Instrument, debug, error, info_span, warn};
 use wreq::{Method, Response, header::ACCEPT};
 
-use super::ClaudeWebState;
+use super::{ClaudeWebState, conversation::CONVERSATION_NAME_PREFIX};
 use crate::{
-    config::CLEWDR_CONFIG,
+    config::Capability,
     error::{CheckClaudeErr, ClewdrError, WreqSnafu},
+    middleware::timeout::first_byte,
+    services::{
+        breaker::Provider,
+        capture::CaptureExt,
+        endpoints::{self, Upstream},
+        recent_errors,
+        retry::{self, Failure, RetryPolicy},
+    },
     types::claude::CreateMessageParams,
-    utils::print_out_json,
+    utils::{print_out_json, throttle},
 };
 
 impl ClaudeWebState {
@@ -23,8 +30,8 @@ impl ClaudeWebState {
     /// - Response transformation according to the specified API format
     /// - Error handling and cleanup
     ///
-    /// The method implements a sophisticated retry mechanism to handle transient failures,
-    /// and manages conversation cleanup to prevent resource leaks. It also includes
+    /// Retries follow the configured `RetryPolicy`, a failed cookie is returned with its reason,
+    /// and the method manages conversation cleanup to prevent resource leaks. It also includes
     /// performance tracking to measure response times.
     ///
     /// # Arguments
@@ -36,44 +43,61 @@ impl ClaudeWebState {
         &mut self,
         p: CreateMessageParams,
     ) -> Result<axum::response::Response, ClewdrError> {
-        for i in 0..CLEWDR_CONFIG.load().max_retries + 1 {
-            if i > 0 {
-                info!("[RETRY] attempt: {}", i.to_string().green());
-            }
-            let mut state = self.to_owned();
-            let p = p.to_owned();
-
-            let cookie = state.request_cookie().await?;
-            // check if request is successful
-            let web_res = async { state.bootstrap().await.and(state.send_chat(p).await) };
-            let transform_res = web_res
-                .and_then(async |r| self.transform_response(r).await)
-                .instrument(info_span!("claude_web", "cookie" = cookie.cookie.ellipse()));
-
-            match transform_res.await {
-                Ok(b) => {
-                    if let Err(e) = state.clean_chat().await {
-                        warn!("Failed to clean chat: {}", e);
-                    }
-                    return Ok(b);
-                }
-                Err(e) => {
-                    // delete chat after an error
-                    if let Err(e) = state.clean_chat().await {
-                        warn!("Failed to clean chat: {}", e);
+        // free accounts cannot serve every model, leave them to other requests
+        self.capability = Capability::for_model(&p.model);
+        let this = &*self;
+        let p = &p;
+        RetryPolicy::from_config()
+            .executor(retry::invalid_cookie, Self::return_rejected)
+            .run(this, |mut state| async move {
+                let cookie = match state.request_cookie().await {
+                    Ok(cookie) => cookie,
+                    Err(e) => return (state, Err(e)),
+                };
+                // check if request is successful
+                let web_res = async {
+                    state
+                        .bootstrap()
+                        .await
+                        .and(state.send_chat(p.to_owned()).await)
+                };
+                let res = web_res
+                    .and_then(async |r| this.transform_response(r).await)
+                    .instrument(info_span!("claude_web", "cookie" = cookie.cookie.ellipse()))
+                    .await;
+                match res {
+                    Ok(_) => {
+                        if state.conversation_id.is_some() {
+                            // keep the conversation for the next turn of the client
+                            state.save_conversation(p);
+                        } else if let Err(e) = state.clean_chat().await {
+                            warn!("Failed to clean chat: {}", e);
+                        }
                     }
-                    error!("{e}");
-                    // 429 error
-                    if let ClewdrError::InvalidCookie { reason } = e {
-                        state.return_cookie(Some(reason.to_owned())).await;
-                        continue;
+                    Err(ref e) => {
+                        // delete chat after an error
+                        state.forget_conversation();
+                        if let Err(e) = state.clean_chat().await {
+                            warn!("Failed to clean chat: {}", e);
+                        }
+                        error!("{e}");
+                        recent_errors::record(
+                            Provider::ClaudeWeb,
+                            Some(cookie.cookie.ellipse()),
+                            e,
+                        );
+                        // aborted attempts keep their cookie, return it for a 403 to be counted
+                        if retry::invalid_cookie(e) == Failure::Abort
+                            && state.cookie.as_mut().is_some_and(|c| c.record_rejection(e))
+                        {
+                            state.return_cookie(None).await;
+                        }
                     }
-                    return Err(e);
                 }
-            }
-        }
-        error!("Max retries exceeded");
-        Err(ClewdrError::TooManyRetries)
+                endpoints::report(Upstream::Claude, &state.endpoint, &res);
+                (state, res)
+            })
+            .await
     }
 
     /// Sends a message to the Claude API by creating a new conversation and processing the request
@@ -101,32 +125,29 @@ impl ClaudeWebState {
             .ok_or(ClewdrError::UnexpectedNone {
                 msg: "Organization UUID is not set",
             })?;
+        throttle::acquire(&org_uuid).await?;
 
-        // Create a new conversation
-        let new_uuid = uuid::Uuid::new_v4().to_string();
-        let endpoint = self
-            .endpoint
-            .join(&format!(
-                "api/organizations/{}/chat_conversations",
-                org_uuid
-            ))
-            .expect("Url parse error");
-        let body = json!({
-            "uuid": new_uuid,
-            "name": format!("ClewdR-{}", chrono::Utc::now().format("%Y-%m-%d %H:%M:%S")),
-        });
-
-        self.build_request(Method::POST, endpoint)
-            .json(&body)
-            .send()
-            .await
-            .context(WreqSnafu {
-                msg: "Failed to create new conversation",
-            })?
-            .check_claude()
-            .await?;
-        self.conv_uuid = Some(new_uuid.to_string());
-        debug!("New conversation created: {}", new_uuid);
+        // Continue the client's conversation, or create a new one
+        let mut parent_message_uuid = None;
+        let mut send = None;
+        if let Some((conv_uuid, delta)) = self.continue_conversation(&p).await {
+            match self.leaf_message(&conv_uuid).await {
+                Ok(leaf) => {
+                    debug!("Continuing conversation: {}", conv_uuid);
+                    parent_message_uuid = Some(leaf);
+                    send = Some((conv_uuid, delta));
+                }
+                Err(e) => {
+                    warn!("Failed to continue conversation {}: {}", conv_uuid, e);
+                    self.forget_conversation();
+                }
+            }
+        }
+        let (conv_uuid, delta) = match send {
+            Some(send) => send,
+            None => (self.create_conversation(&org_uuid).await?, p.to_owned()),
+        };
+        self.conv_uuid = Some(conv_uuid.to_owned());
 
         // preserve original params for possible post-call token accounting
         self.last_params = Some(p.clone());
@@ -142,25 +163,32 @@ impl ClaudeWebState {
             .endpoint
             .join(&format!(
                 "api/organizations/{}/chat_conversations/{}",
-                org_uuid, new_uuid
+                org_uuid, conv_uuid
             ))
             .expect("Url parse error");
         let _ = self
             .build_request(Method::PUT, endpoint)
             .json(&body)
-            .send()
-            .await;
+            .send_captured()
+            .await
+            .inspect(|res| self.jar.keep(res));
         // generate the request body
         // check if the request is empty
-        let mut body = self.transform_request(p).ok_or(ClewdrError::BadRequest {
-            msg: "Request body is empty",
-        })?;
+        let mut body = self
+            .transform_request(delta)
+            .ok_or(ClewdrError::BadRequest {
+                msg: "Request body is empty",
+            })?;
+        body.parent_message_uuid = parent_message_uuid;
 
         // check images
         let images = body.images.drain(..).collect::<Vec<_>>();
 
         // upload images
-        let files = self.upload_images(images).await;
+        let mut files = self.upload_images(images).await;
+        // attach files uploaded through the files API
+        let file_ids = body.file_ids.drain(..).collect::<Vec<_>>();
+        files.extend(self.resolve_files(file_ids).await);
         body.files = files;
 
         // send the request
@@ -169,19 +197,60 @@ impl ClaudeWebState {
             .endpoint
             .join(&format!(
                 "api/organizations/{}/chat_conversations/{}/completion",
-                org_uuid, new_uuid
+                org_uuid, conv_uuid
             ))
             .expect("Url parse error");
 
+        let req = self
+            .build_request(Method::POST, endpoint)
+            .json(&body)
+            .header_append(ACCEPT, "text/event-stream");
+        first_byte(&self.timeouts, async {
+            req.send_captured()
+                .await
+                .inspect(|res| self.jar.keep(res))
+                .context(WreqSnafu {
+                    msg: "Failed to send chat request",
+                })
+        })
+        .await?
+        .check_claude()
+        .await
+    }
+
+    /// Creates a new conversation in the organization
+    ///
+    /// # Returns
+    /// * `Result<String, ClewdrError>` - UUID of the new conversation
+    async fn create_conversation(&self, org_uuid: &str) -> Result<String, ClewdrError> {
+        let new_uuid = uuid::Uuid::new_v4().to_string();
+        let endpoint = self
+            .endpoint
+            .join(&format!(
+                "api/organizations/{}/chat_conversations",
+                org_uuid
+            ))
+            .expect("Url parse error");
+        let body = json!({
+            "uuid": new_uuid,
+            "name": format!(
+                "{}{}",
+                CONVERSATION_NAME_PREFIX,
+                chrono::Utc::now().format("%Y-%m-%d %H:%M:%S")
+            ),
+        });
+
         self.build_request(Method::POST, endpoint)
             .json(&body)
-            .header_append(ACCEPT, "text/event-stream")
-            .send()
+            .send_captured()
             .await
+            .inspect(|res| self.jar.keep(res))
             .context(WreqSnafu {
-                msg: "Failed to send chat request",
+                msg: "Failed to create new conversation",
             })?
             .check_claude()
-            .await
+            .await?;
+        debug!("New conversation created: {}", new_uuid);
+        Ok(new_uuid)
     }
 }
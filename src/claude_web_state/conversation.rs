@@ -0,0 +1,255 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::LazyLock,
+    time::Duration,
+};
+
+use moka::sync::Cache;
+use serde_json::Value;
+use snafu::ResultExt;
+use tracing::debug;
+use wreq::Method;
+
+use crate::{
+    claude_web_state::ClaudeWebState,
+    error::{CheckClaudeErr, ClewdrError, WreqSnafu},
+    types::claude::{CreateMessageParams, Role},
+};
+
+/// Header carrying a client chosen id for conversation reuse
+pub const CONVERSATION_HEADER: &str = "x-clewdr-conversation-id";
+/// Metadata field carrying a client chosen id for conversation reuse
+pub const CONVERSATION_FIELD: &str = "conversation_id";
+
+/// Name prefix of every conversation ClewdR creates on Claude.ai
+pub const CONVERSATION_NAME_PREFIX: &str = "ClewdR-";
+
+/// Scoped client conversation id -> Claude.ai conversation it is continued in
+static CONVERSATIONS: LazyLock<Cache<String, Conversation>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(1024)
+        // same as the sticky cookie cache, so the conversation stays on its cookie
+        .time_to_idle(Duration::from_secs(60 * 60))
+        .build()
+});
+
+/// A Claude.ai conversation kept open across requests
+#[derive(Clone, Debug)]
+pub struct Conversation {
+    /// Cookie owning the conversation
+    cookie: String,
+    conv_uuid: String,
+    system: u64,
+    /// Hash of every message the conversation already contains
+    history: Vec<u64>,
+}
+
+fn hash_of(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn hash_system(p: &CreateMessageParams) -> u64 {
+    hash_of(&p.system.as_ref().map(Value::to_string))
+}
+
+/// Whether a Claude.ai conversation is kept open for a client to continue
+pub fn is_continued(conv_uuid: &str) -> bool {
+    CONVERSATIONS.iter().any(|(_, c)| c.conv_uuid == conv_uuid)
+}
+
+/// Conversation id of a client scoped to its tenant and API key
+///
+/// Callers with another key sending the same id get a conversation of their own instead of
+/// continuing one they do not own.
+///
+/// # Arguments
+/// * `tenant` - Tenant the request is served for
+/// * `key` - API key the request was made with
+/// * `id` - Conversation id chosen by the client
+pub fn scoped_id(tenant: Option<&str>, key: Option<&str>, id: &str) -> String {
+    format!("{:016x}:{id}", hash_of(&(tenant, key)))
+}
+
+/// Hash used to pick the same cookie for every request of a conversation
+pub fn cookie_hash(id: &str) -> u64 {
+    hash_of(&(CONVERSATION_FIELD, id))
+}
+
+impl Conversation {
+    /// Index of the first message not yet sent to the conversation
+    ///
+    /// The assistant reply that follows the known history was generated by Claude.ai
+    /// itself, so it is skipped as well.
+    ///
+    /// # Returns
+    /// * `Option<usize>` - `None` if the history was edited or nothing new was sent
+    fn delta(&self, p: &CreateMessageParams) -> Option<usize> {
+        let known = self.history.len();
+        if hash_system(p) != self.system
+            || p.messages.len() <= known
+            || p.messages[..known]
+                .iter()
+                .map(hash_of)
+                .ne(self.history.iter().copied())
+        {
+            return None;
+        }
+        let start = if p.messages[known].role == Role::Assistant {
+            known + 1
+        } else {
+            known
+        };
+        p.messages[start..]
+            .iter()
+            .any(|m| m.role == Role::User)
+            .then_some(start)
+    }
+}
+
+impl ClaudeWebState {
+    /// Looks up the conversation to continue for the current request
+    ///
+    /// A mapping that can no longer be continued is dropped, and its conversation
+    /// deleted when it belongs to the current cookie.
+    ///
+    /// # Returns
+    /// * `Option<(String, CreateMessageParams)>` - Claude.ai conversation uuid and the messages still to send
+    pub(super) async fn continue_conversation(
+        &self,
+        p: &CreateMessageParams,
+    ) -> Option<(String, CreateMessageParams)> {
+        let id = self.conversation_id.as_ref()?;
+        let conv = CONVERSATIONS.get(id)?;
+        let cookie = self.cookie.as_ref()?.cookie.to_string();
+        if conv.cookie != cookie {
+            debug!("Conversation {} was created with another cookie", id);
+            CONVERSATIONS.invalidate(id);
+            return None;
+        }
+        let Some(start) = conv.delta(p) else {
+            debug!("History of conversation {} changed, starting over", id);
+            CONVERSATIONS.invalidate(id);
+            self.delete_conversation(&conv.conv_uuid).await;
+            return None;
+        };
+        let mut delta = p.to_owned();
+        delta.system = None;
+        delta.messages = p.messages[start..].to_vec();
+        Some((conv.conv_uuid, delta))
+    }
+
+    /// Remembers the current conversation so the next request of the client continues it
+    pub(super) fn save_conversation(&self, p: &CreateMessageParams) {
+        let (Some(id), Some(cookie), Some(conv_uuid)) = (
+            self.conversation_id.as_ref(),
+            self.cookie.as_ref(),
+            self.conv_uuid.as_ref(),
+        ) else {
+            return;
+        };
+        CONVERSATIONS.insert(
+            id.to_owned(),
+            Conversation {
+                cookie: cookie.cookie.to_string(),
+                conv_uuid: conv_uuid.to_owned(),
+                system: hash_system(p),
+                history: p.messages.iter().map(hash_of).collect(),
+            },
+        );
+    }
+
+    /// Forgets the conversation of the current client, e.g. after a failed request
+    pub(super) fn forget_conversation(&self) {
+        if let Some(ref id) = self.conversation_id {
+            CONVERSATIONS.invalidate(id);
+        }
+    }
+
+    /// Fetches the uuid of the last message in a conversation, new messages are replies to it
+    pub(super) async fn leaf_message(&self, conv_uuid: &str) -> Result<String, ClewdrError> {
+        let org_uuid = self.org_uuid.as_ref().ok_or(ClewdrError::UnexpectedNone {
+            msg: "Organization UUID is not set",
+        })?;
+        let mut endpoint = self
+            .endpoint
+            .join(&format!(
+                "api/organizations/{}/chat_conversations/{}",
+                org_uuid, conv_uuid
+            ))
+            .expect("Url parse error");
+        endpoint
+            .query_pairs_mut()
+            .append_pair("tree", "True")
+            .append_pair("rendering_mode", "messages");
+        let conversation = self
+            .build_request(Method::GET, endpoint)
+            .send()
+            .await
+            .inspect(|res| self.jar.keep(res))
+            .context(WreqSnafu {
+                msg: "Failed to fetch conversation",
+            })?
+            .check_claude()
+            .await?
+            .json::<Value>()
+            .await
+            .context(WreqSnafu {
+                msg: "Failed to parse conversation",
+            })?;
+        conversation["current_leaf_message_uuid"]
+            .as_str()
+            .map(|s| s.to_string())
+            .ok_or(ClewdrError::UnexpectedNone {
+                msg: "Conversation has no messages",
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::claude::Message;
+
+    fn params(messages: Vec<Message>) -> CreateMessageParams {
+        serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "messages": messages,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn delta_skips_known_history() {
+        let first = params(vec![Message::new_text(Role::User, "hello")]);
+        let conv = Conversation {
+            cookie: String::new(),
+            conv_uuid: String::new(),
+            system: hash_system(&first),
+            history: first.messages.iter().map(hash_of).collect(),
+        };
+        let mut next = first.to_owned();
+        next.messages.push(Message::new_text(Role::Assistant, "hi"));
+        next.messages
+            .push(Message::new_text(Role::User, "how are you"));
+        assert_eq!(conv.delta(&next), Some(2));
+        // regenerating the same turn cannot continue
+        assert_eq!(conv.delta(&first), None);
+        // edited history cannot continue
+        let edited = params(vec![
+            Message::new_text(Role::User, "bye"),
+            Message::new_text(Role::User, "how are you"),
+        ]);
+        assert_eq!(conv.delta(&edited), None);
+    }
+
+    #[test]
+    fn conversation_ids_are_scoped_to_the_caller() {
+        let own = scoped_id(None, Some("sk-a"), "chat");
+        assert_eq!(own, scoped_id(None, Some("sk-a"), "chat"));
+        assert_ne!(own, scoped_id(None, Some("sk-b"), "chat"));
+        assert_ne!(own, scoped_id(Some("team"), Some("sk-a"), "chat"));
+    }
+}
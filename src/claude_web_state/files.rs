@@ -0,0 +1,162 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use moka::sync::Cache;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{claude_web_state::ClaudeWebState, error::ClewdrError};
+
+/// Largest file accepted by `POST /v1/files`, same as the Claude.ai upload limit
+pub const MAX_FILE_SIZE: usize = 30 * 1024 * 1024;
+
+/// Bytes of uploaded files kept in memory, least recently used files are evicted past it
+const FILE_STORE_BUDGET: u64 = 512 * 1024 * 1024;
+
+/// Files uploaded through the files API, kept so they can be re-uploaded to
+/// whichever organization a later request lands on
+static FILE_STORE: LazyLock<Cache<String, StoredFile>> = LazyLock::new(|| {
+    Cache::builder()
+        .weigher(|_, file: &StoredFile| u32::try_from(file.bytes.len()).unwrap_or(u32::MAX))
+        .max_capacity(FILE_STORE_BUDGET)
+        .time_to_live(Duration::from_secs(24 * 60 * 60))
+        .build()
+});
+
+#[derive(Clone)]
+struct StoredFile {
+    name: String,
+    bytes: Bytes,
+    /// Organization uuid -> Claude.ai file uuid
+    uploads: Arc<Mutex<HashMap<String, String>>>,
+}
+
+/// File object in OpenAI files API format
+#[derive(Debug, Serialize)]
+pub struct FileObject {
+    pub id: String,
+    pub object: &'static str,
+    pub bytes: usize,
+    pub created_at: i64,
+    pub filename: String,
+    pub purpose: String,
+}
+
+impl ClaudeWebState {
+    /// Uploads a file to the current organization
+    ///
+    /// # Returns
+    /// * `Option<String>` - The Claude.ai file uuid
+    pub async fn upload_file(&self, bytes: Vec<u8>, file_name: &str) -> Option<String> {
+        let part = wreq::multipart::Part::bytes(bytes).file_name(file_name.to_owned());
+        let form = wreq::multipart::Form::new().part("file", part);
+        let endpoint = self
+            .endpoint
+            .join(&format!("api/{}/upload", self.org_uuid.as_ref()?))
+            .expect("Url parse error");
+        let res = self
+            .build_request(http::Method::POST, endpoint)
+            .multipart(form)
+            .send()
+            .await
+            .inspect(|res| self.jar.keep(res))
+            .inspect_err(|e| {
+                warn!("Failed to upload file: {}", e);
+            })
+            .ok()?;
+        #[derive(serde::Deserialize)]
+        struct UploadResponse {
+            file_uuid: String,
+        }
+        let json = res
+            .json::<UploadResponse>()
+            .await
+            .inspect_err(|e| {
+                warn!("Failed to parse upload response: {}", e);
+            })
+            .ok()?;
+        Some(json.file_uuid)
+    }
+
+    /// Uploads a file for the files API and remembers it for later requests
+    pub async fn create_file(
+        &mut self,
+        name: String,
+        bytes: Bytes,
+        purpose: String,
+    ) -> Result<FileObject, ClewdrError> {
+        self.request_cookie().await?;
+        if let Err(e) = self.bootstrap().await {
+            if let ClewdrError::InvalidCookie { ref reason } = e {
+                self.return_cookie(Some(reason.to_owned())).await;
+            }
+            return Err(e);
+        }
+        let file_uuid = self.upload_file(bytes.to_vec(), &name).await;
+        self.return_cookie(None).await;
+        let (Some(file_uuid), Some(org_uuid)) = (file_uuid, self.org_uuid.to_owned()) else {
+            return Err(ClewdrError::UnexpectedNone {
+                msg: "Failed to upload file to Claude.ai",
+            });
+        };
+        let id = format!("file-{}", uuid::Uuid::new_v4().simple());
+        let size = bytes.len();
+        FILE_STORE.insert(
+            id.to_owned(),
+            StoredFile {
+                name: name.to_owned(),
+                bytes,
+                uploads: Arc::new(Mutex::new(HashMap::from([(org_uuid, file_uuid)]))),
+            },
+        );
+        Ok(FileObject {
+            id,
+            object: "file",
+            bytes: size,
+            created_at: chrono::Utc::now().timestamp(),
+            filename: name,
+            purpose,
+        })
+    }
+
+    /// Maps files API ids to Claude.ai file uuids of the current organization
+    ///
+    /// Files uploaded under another organization are uploaded again, unknown ids are skipped.
+    pub async fn resolve_files(&self, ids: Vec<String>) -> Vec<String> {
+        let Some(org_uuid) = self.org_uuid.to_owned() else {
+            return vec![];
+        };
+        let mut files = vec![];
+        for id in ids {
+            let Some(stored) = FILE_STORE.get(&id) else {
+                warn!("Unknown file id: {}", id);
+                continue;
+            };
+            let known = stored
+                .uploads
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(&org_uuid)
+                .cloned();
+            if let Some(file_uuid) = known {
+                files.push(file_uuid);
+                continue;
+            }
+            let Some(file_uuid) = self.upload_file(stored.bytes.to_vec(), &stored.name).await
+            else {
+                continue;
+            };
+            stored
+                .uploads
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(org_uuid.to_owned(), file_uuid.to_owned());
+            files.push(file_uuid);
+        }
+        files
+    }
+}
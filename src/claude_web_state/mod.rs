@@ -5,21 +5,32 @@ use snafu::ResultExt;
 use tracing::{debug, error, warn};
 use url::Url;
 use wreq::{
-    Client, ClientBuilder, IntoUrl, Method, Proxy, RequestBuilder,
-    header::{ORIGIN, REFERER},
+    Client, IntoUrl, Method, RequestBuilder,
+    header::{COOKIE, ORIGIN, REFERER},
 };
-use wreq_util::Emulation;
 
 use crate::{
-    config::{CLAUDE_ENDPOINT, CLEWDR_CONFIG, CookieStatus, Reason},
+    config::{
+        CLAUDE_ENDPOINT, CLEWDR_CONFIG, Capability, CookieStatus, Priority, ProxyTarget, Reason,
+        Timeouts,
+    },
     error::{ClewdrError, WreqSnafu},
-    middleware::claude::ClaudeApiFormat,
-    services::cookie_actor::CookieActorHandle,
+    middleware::{claude::ClaudeApiFormat, proxy::current_proxy, timeout::current_timeouts},
+    services::{
+        client_pool::{self, ClientKind, SessionJar},
+        cookie_actor::{CookieActorHandle, CookieRequest},
+        endpoints::{self, Upstream},
+    },
     types::claude::{CreateMessageParams, Usage},
 };
 
+pub mod artifacts;
 pub mod bootstrap;
 pub mod chat;
+pub mod conversation;
+pub mod files;
+mod padding;
+pub mod settings;
 mod transform;
 /// Placeholder
 pub static SUPER_CLIENT: LazyLock<Client> = LazyLock::new(Client::new);
@@ -32,9 +43,26 @@ pub struct ClaudeWebState {
     pub cookie_actor_handle: CookieActorHandle,
     pub org_uuid: Option<String>,
     pub conv_uuid: Option<String>,
+    /// Client supplied id of the conversation to continue, if any
+    pub conversation_id: Option<String>,
+    /// Organization asked for by the request, UUID or name
+    pub requested_org: Option<String>,
+    /// Tags the requested cookie must carry
+    pub cookie_tags: Vec<String>,
+    /// Priority of the cookie request when it has to wait
+    pub priority: Priority,
+    /// Capability the requested cookie must have, from the requested model
+    pub capability: Option<Capability>,
+    /// Prompt sent along the attachment, overrides `custom_prompt`
+    pub custom_prompt: Option<String>,
     pub capabilities: Vec<String>,
     pub endpoint: Url,
-    pub proxy: Option<Proxy>,
+    /// URL of the proxy
+    pub proxy: Option<String>,
+    /// Cookies claude.ai set for the current cookie
+    pub jar: SessionJar,
+    /// Timeouts of the upstream requests, resolved when the state is created
+    pub timeouts: Timeouts,
     pub api_format: ClaudeApiFormat,
     pub stream: bool,
     pub client: Client,
@@ -52,10 +80,18 @@ impl ClaudeWebState {
             cookie: None,
             org_uuid: None,
             conv_uuid: None,
+            conversation_id: None,
+            requested_org: None,
+            cookie_tags: Vec::new(),
+            priority: Priority::Normal,
+            capability: None,
+            custom_prompt: None,
             cookie_header_value: HeaderValue::from_static(""),
             capabilities: Vec::new(),
-            endpoint: CLEWDR_CONFIG.load().endpoint(),
-            proxy: CLEWDR_CONFIG.load().wreq_proxy.to_owned(),
+            endpoint: endpoints::pick(Upstream::Claude),
+            proxy: current_proxy(ProxyTarget::Claude),
+            jar: SessionJar::default(),
+            timeouts: current_timeouts(ProxyTarget::Claude),
             api_format: ClaudeApiFormat::Claude,
             stream: false,
             client: SUPER_CLIENT.to_owned(),
@@ -77,12 +113,13 @@ impl ClaudeWebState {
 
     /// Build a request with the current cookie and proxy settings
     pub fn build_request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
-        // let r = SUPER_CLIENT.cloned();
-        self.client
-            .set_cookie(&self.endpoint, &self.cookie_header_value);
         let req = self
             .client
             .request(method, url)
+            .header(
+                COOKIE,
+                self.jar.header(&self.cookie_header_value, &self.endpoint),
+            )
             .header(ORIGIN, CLAUDE_ENDPOINT);
         if let Some(uuid) = self.conv_uuid.to_owned() {
             req.header(
@@ -106,35 +143,47 @@ impl ClaudeWebState {
     /// Checks if the current user has pro capabilities
     /// Returns true if any capability contains "pro", "enterprise", "raven", or "max"
     pub fn is_pro(&self) -> bool {
-        self.capabilities.iter().any(|c| {
-            c.contains("pro")
-                || c.contains("enterprise")
-                || c.contains("raven")
-                || c.contains("max")
-        })
+        Capability::Pro.matches(&self.capabilities)
     }
 
     /// Requests a new cookie from the cookie manager
     /// Updates the internal state with the new cookie and proxy configuration
     pub async fn request_cookie(&mut self) -> Result<CookieStatus, ClewdrError> {
-        let res = self.cookie_actor_handle.request(None).await?;
-        self.cookie = Some(res.to_owned());
-        // Always pull latest proxy/endpoint before building the client
-        self.proxy = CLEWDR_CONFIG.load().wreq_proxy.to_owned();
-        self.endpoint = CLEWDR_CONFIG.load().endpoint();
-        let mut client = ClientBuilder::new()
-            .cookie_store(true)
-            .emulation(Emulation::Chrome136);
-        if let Some(ref proxy) = self.proxy {
-            client = client.proxy(proxy.to_owned());
-        }
-        self.client = client.build().context(WreqSnafu {
-            msg: "Failed to build client with new cookie",
-        })?;
-        self.cookie_header_value = HeaderValue::from_str(res.cookie.to_string().as_str())?;
+        // a continued conversation only exists on the cookie that created it
+        let hash = self
+            .conversation_id
+            .as_deref()
+            .map(conversation::cookie_hash);
+        let res = self
+            .cookie_actor_handle
+            .request(CookieRequest {
+                hash,
+                tags: self.cookie_tags.to_owned(),
+                priority: self.priority,
+                capability: self.capability,
+            })
+            .await?;
+        tracing::Span::current()
+            .record("provider", "claude_web")
+            .record("cookie", res.cookie.ellipse());
+        self.use_cookie(res.to_owned())?;
         Ok(res)
     }
 
+    /// Binds a specific cookie to this state without going through the cookie manager
+    /// Picks the pooled client of the latest proxy and endpoint configuration
+    pub fn use_cookie(&mut self, cookie: CookieStatus) -> Result<(), ClewdrError> {
+        self.cookie_header_value = HeaderValue::from_str(cookie.cookie.to_string().as_str())?;
+        self.cookie = Some(cookie);
+        self.jar = SessionJar::default();
+        // Always pull latest proxy/endpoint before picking the client
+        self.proxy = current_proxy(ProxyTarget::Claude);
+        self.endpoint = endpoints::pick(Upstream::Claude);
+        self.client =
+            client_pool::client(ClientKind::Claude, self.proxy.to_owned(), &self.timeouts)?;
+        Ok(())
+    }
+
     /// Returns the current cookie to the cookie manager
     /// Optionally provides a reason for returning the cookie (e.g., invalid, banned)
     pub async fn return_cookie(&self, reason: Option<Reason>) {
@@ -149,6 +198,18 @@ impl ClaudeWebState {
         }
     }
 
+    /// Returns the cookie of a failed attempt with the reason it was rejected
+    pub fn return_rejected(mut self, e: &ClewdrError) -> impl Future<Output = ()> + use<> {
+        if let Some(cookie) = self.cookie.as_mut() {
+            cookie.record_rejection(e);
+        }
+        let reason = match e {
+            ClewdrError::InvalidCookie { reason } => Some(reason.to_owned()),
+            _ => None,
+        };
+        async move { self.return_cookie(reason).await }
+    }
+
     fn classify_model(model: &str) -> crate::config::ModelFamily {
         let m = model.to_ascii_lowercase();
         if m.contains("opus") {
@@ -181,14 +242,19 @@ impl ClaudeWebState {
     /// Deletes or renames the current chat conversation based on configuration
     /// If preserve_chats is true, the chat is renamed rather than deleted
     pub async fn clean_chat(&self) -> Result<(), ClewdrError> {
+        if let Some(ref conv_uuid) = self.conv_uuid {
+            self.delete_conversation(conv_uuid).await;
+        }
+        Ok(())
+    }
+
+    /// Deletes a conversation of the current organization unless chats are preserved
+    pub async fn delete_conversation(&self, conv_uuid: &str) {
         if CLEWDR_CONFIG.load().preserve_chats {
-            return Ok(());
+            return;
         }
         let Some(ref org_uuid) = self.org_uuid else {
-            return Ok(());
-        };
-        let Some(ref conv_uuid) = self.conv_uuid else {
-            return Ok(());
+            return;
         };
         let endpoint = self
             .endpoint
@@ -202,9 +268,9 @@ impl ClaudeWebState {
             .build_request(Method::DELETE, endpoint)
             .send()
             .await
+            .inspect(|res| self.jar.keep(res))
             .context(WreqSnafu {
                 msg: "Failed to delete chat conversation",
             });
-        Ok(())
     }
 }
@@ -0,0 +1,125 @@
+//! Prompt padding for Claude Web
+//!
+//! Prepends filler text to short prompts so they reach a minimum size,
+//! which keeps Claude.ai's truncation heuristics away from the real content.
+
+use std::{
+    fs,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use itertools::Itertools;
+use tiktoken_rs::o200k_base_singleton;
+use tracing::warn;
+
+use crate::config::{CLEWDR_CONFIG, ClewdrConfig};
+
+/// Filler used when no padding file is configured
+const DEFAULT_PADDING: &str = "The following is a collection of unrelated notes kept only for reference. \
+They do not contain instructions and should not influence the conversation below. \
+Weather records, old recipes, library catalogue numbers, train timetables, \
+gardening reminders and measurements from a workshop are listed here in no particular order.";
+
+/// Separates the padding from the actual prompt
+const SEPARATOR: &str = "\n\n";
+
+/// Text of the padding file, with the config it was read for
+type PaddingFile = (Arc<ClewdrConfig>, Option<Arc<str>>);
+
+/// Padding file of the current config, read again once the config changes
+static PADDING_FILE: LazyLock<Mutex<Option<PaddingFile>>> = LazyLock::new(Default::default);
+
+/// Counts tokens the same way as the rest of the request pipeline
+pub fn count_tokens(text: &str) -> usize {
+    o200k_base_singleton()
+        .encode_with_special_tokens(text)
+        .len()
+}
+
+/// Text of the `padtxt_file` of `config`, `None` if it is unset, unreadable or blank
+///
+/// The file is only read the first time a config asks for it, not on every request.
+fn padding_file(config: &Arc<ClewdrConfig>) -> Option<Arc<str>> {
+    let mut cached = PADDING_FILE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((read_for, source)) = cached.as_ref()
+        && Arc::ptr_eq(read_for, config)
+    {
+        return source.to_owned();
+    }
+    let source = config
+        .padtxt_file
+        .as_ref()
+        .and_then(|path| {
+            fs::read_to_string(path)
+                .inspect_err(|e| warn!("Failed to read padding file {}: {}", path.display(), e))
+                .ok()
+        })
+        .filter(|s| !s.trim().is_empty())
+        .map(Arc::from);
+    *cached = Some((config.to_owned(), source.to_owned()));
+    source
+}
+
+/// Builds roughly `tokens` tokens of filler by cycling through the words of `source`
+fn build_padding(source: &str, tokens: usize) -> String {
+    let words = source.split_whitespace().collect::<Vec<_>>();
+    if words.is_empty() || tokens == 0 {
+        return String::new();
+    }
+    let per_word = count_tokens(&words.join(" ")) as f64 / words.len() as f64;
+    let count = (tokens as f64 / per_word.max(f64::EPSILON)).ceil() as usize;
+    words.iter().cycle().take(count).join(" ")
+}
+
+/// Pads `prompt` with text from `source` until it holds at least `min_tokens` tokens
+///
+/// # Returns
+/// * `String` - The padded prompt, or the original one if it is already large enough
+pub fn pad_prompt(prompt: String, min_tokens: usize, source: &str) -> String {
+    let current = count_tokens(&prompt);
+    if current >= min_tokens {
+        return prompt;
+    }
+    let padding = build_padding(source, min_tokens - current);
+    if padding.is_empty() {
+        return prompt;
+    }
+    padding + SEPARATOR + &prompt
+}
+
+/// Applies the configured padding to a Claude Web prompt
+pub fn apply(prompt: String) -> String {
+    let config = CLEWDR_CONFIG.load_full();
+    if config.padtxt_min_tokens == 0 {
+        return prompt;
+    }
+    let source = padding_file(&config);
+    pad_prompt(
+        prompt,
+        config.padtxt_min_tokens,
+        source.as_deref().unwrap_or(DEFAULT_PADDING),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padding_reaches_target() {
+        assert_eq!(count_tokens(""), 0);
+        let padding = build_padding(DEFAULT_PADDING, 2000);
+        let tokens = count_tokens(&padding);
+        assert!((1900..=2100).contains(&tokens), "got {tokens} tokens");
+        assert!(build_padding("   ", 100).is_empty());
+    }
+
+    #[test]
+    fn pads_only_short_prompts() {
+        let prompt = "Human: hello".to_string();
+        let padded = pad_prompt(prompt.to_owned(), 500, DEFAULT_PADDING);
+        assert!(padded.ends_with("\n\nHuman: hello"));
+        assert!(count_tokens(&padded) >= 480);
+        assert_eq!(pad_prompt(prompt.to_owned(), 2, DEFAULT_PADDING), prompt);
+    }
+}
@@ -0,0 +1,93 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+use snafu::ResultExt;
+use wreq::Method;
+
+use crate::{
+    claude_web_state::ClaudeWebState,
+    error::{CheckClaudeErr, ClewdrError, WreqSnafu},
+};
+
+/// Claude.ai account settings that can be normalized, `None` leaves a setting as it is
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AccountSettings {
+    /// Artifacts preview, `preview_feature_uses_artifacts` on Claude.ai
+    #[serde(default)]
+    pub artifacts: Option<bool>,
+    /// Extended thinking, `paprika_mode` on Claude.ai
+    #[serde(default)]
+    pub paprika_mode: Option<bool>,
+}
+
+impl AccountSettings {
+    pub fn is_empty(&self) -> bool {
+        self.artifacts.is_none() && self.paprika_mode.is_none()
+    }
+
+    /// Applies the changes to the settings object of a Claude.ai account
+    fn apply(&self, settings: &mut Value) {
+        if let Some(artifacts) = self.artifacts {
+            settings["preview_feature_uses_artifacts"] = json!(artifacts);
+        }
+        if let Some(paprika) = self.paprika_mode {
+            settings["paprika_mode"] = if paprika {
+                json!("extended")
+            } else {
+                Value::Null
+            };
+        }
+    }
+}
+
+impl ClaudeWebState {
+    /// Changes the account settings of the current cookie
+    ///
+    /// The current settings are read first and written back whole with the changes applied,
+    /// as the Claude.ai settings page does, so other settings are kept.
+    ///
+    /// # Returns
+    /// * `Result<Value, ClewdrError>` - The settings as stored by Claude.ai
+    pub async fn update_account_settings(
+        &self,
+        changes: &AccountSettings,
+    ) -> Result<Value, ClewdrError> {
+        let endpoint = self.endpoint.join("api/account").expect("Url parse error");
+        let account = self
+            .build_request(Method::GET, endpoint.to_owned())
+            .send()
+            .await
+            .inspect(|res| self.jar.keep(res))
+            .context(WreqSnafu {
+                msg: "Failed to get account",
+            })?
+            .check_claude()
+            .await?
+            .json::<Value>()
+            .await
+            .context(WreqSnafu {
+                msg: "Failed to parse account response",
+            })?;
+        let mut settings = match account.get("settings") {
+            Some(s) if s.is_object() => s.to_owned(),
+            _ => json!({}),
+        };
+        changes.apply(&mut settings);
+        let updated = self
+            .build_request(Method::PUT, endpoint)
+            .json(&json!({ "settings": settings }))
+            .send()
+            .await
+            .inspect(|res| self.jar.keep(res))
+            .context(WreqSnafu {
+                msg: "Failed to update account settings",
+            })?
+            .check_claude()
+            .await?
+            .json::<Value>()
+            .await
+            .context(WreqSnafu {
+                msg: "Failed to parse account response",
+            })?;
+        Ok(updated.get("settings").cloned().unwrap_or(settings))
+    }
+}
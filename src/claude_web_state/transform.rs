@@ -1,15 +1,18 @@
-use std::{fmt::Write, mem};
+use std::{collections::HashMap, fmt::Write, mem, net::IpAddr, time::Duration};
 
 use base64::{Engine, prelude::BASE64_STANDARD};
 use futures::{StreamExt, stream};
 use itertools::Itertools;
-use serde_json::Value;
+use serde_json::{Value, json};
 use tracing::warn;
-use wreq::multipart::{Form, Part};
+use url::{Host, Url};
+use wreq::header::{CONTENT_TYPE, LOCATION};
 
 use crate::{
-    claude_web_state::ClaudeWebState,
-    config::CLEWDR_CONFIG,
+    claude_web_state::{ClaudeWebState, padding},
+    config::{CLEWDR_CONFIG, WebToolHistory},
+    middleware::limits::MIB,
+    services::client_pool::{self, ClientKind, is_public},
     types::{
         claude::{ContentBlock, CreateMessageParams, ImageSource, Message, MessageContent, Role},
         claude_web::request::*,
@@ -17,6 +20,30 @@ use crate::{
     utils::{TIME_ZONE, print_out_text},
 };
 
+/// How long downloading an `image_url` part may take
+const IMAGE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Most redirects followed when downloading an `image_url` part
+const MAX_IMAGE_REDIRECTS: usize = 5;
+
+/// Checks that an `image_url` is an HTTP(S) URL and that an address given as its host is public
+///
+/// Host names are checked by the resolver of the image client when it connects, so the
+/// address checked is the address fetched.
+fn check_image_url(url: &Url) -> Result<(), String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("scheme {} is not allowed", url.scheme()));
+    }
+    let ip = match url.host().ok_or("no host")? {
+        Host::Ipv4(ip) => IpAddr::V4(ip),
+        Host::Ipv6(ip) => IpAddr::V6(ip),
+        Host::Domain(_) => return Ok(()),
+    };
+    if !is_public(ip) {
+        return Err(format!("host {ip} is not a public address"));
+    }
+    Ok(())
+}
+
 impl ClaudeWebState {
     pub fn transform_request(&self, mut value: CreateMessageParams) -> Option<WebRequestBody> {
         let system = value.system.take();
@@ -42,32 +69,127 @@ impl ClaudeWebState {
             } else {
                 "raw".to_string()
             },
-            prompt: merged.prompt,
+            prompt: self.custom_prompt.to_owned().unwrap_or(merged.prompt),
             timezone: TIME_ZONE.to_string(),
+            parent_message_uuid: None,
             images: merged.images,
+            file_ids: merged.files,
             tools,
         })
     }
 
+    /// Downloads a remote image referenced by an OpenAI `image_url` part
+    ///
+    /// The request connects directly, never through the configured proxy, and never carries
+    /// the Claude cookie. Only public addresses are fetched, redirects included, within
+    /// `IMAGE_TIMEOUT` and the `max_image_size` limit.
+    async fn download_image(&self, url: &str) -> Option<(Vec<u8>, String)> {
+        let limit = match CLEWDR_CONFIG.load().max_image_size {
+            0 => usize::MAX,
+            mib => mib.saturating_mul(MIB),
+        };
+        let client = client_pool::client(ClientKind::Image, None, &self.timeouts)
+            .inspect_err(|e| {
+                warn!("Failed to download image {}: {}", url, e);
+            })
+            .ok()?;
+        let mut target = Url::parse(url)
+            .inspect_err(|e| {
+                warn!("Refused to download image {}: {}", url, e);
+            })
+            .ok()?;
+        let mut redirects = 0;
+        let res = loop {
+            if let Err(e) = check_image_url(&target) {
+                warn!("Refused to download image {}: {}", target, e);
+                return None;
+            }
+            let res = client
+                .get(target.as_str())
+                .timeout(IMAGE_TIMEOUT)
+                .send()
+                .await
+                .inspect_err(|e| {
+                    warn!("Failed to download image {}: {}", target, e);
+                })
+                .ok()?;
+            if !res.status().is_redirection() {
+                break res;
+            }
+            let Some(next) = res
+                .headers()
+                .get(LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .and_then(|l| target.join(l).ok())
+            else {
+                warn!("Failed to download image {}: {}", target, res.status());
+                return None;
+            };
+            redirects += 1;
+            if redirects > MAX_IMAGE_REDIRECTS {
+                warn!("Image {} redirects too many times", url);
+                return None;
+            }
+            target = next;
+        };
+        if !res.status().is_success() {
+            warn!("Failed to download image {}: {}", url, res.status());
+            return None;
+        }
+        let media_type = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .unwrap_or("image/png")
+            .trim()
+            .to_string();
+        if res.content_length().is_some_and(|l| l > limit as u64) {
+            warn!("Image {} is larger than {} bytes", url, limit);
+            return None;
+        }
+        let mut body = res.bytes_stream();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk
+                .inspect_err(|e| {
+                    warn!("Failed to read image {}: {}", url, e);
+                })
+                .ok()?;
+            if bytes.len() + chunk.len() > limit {
+                warn!("Image {} is larger than {} bytes", url, limit);
+                return None;
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Some((bytes, media_type))
+    }
+
     /// Upload images to the Claude.ai
     pub async fn upload_images(&self, imgs: Vec<ImageSource>) -> Vec<String> {
         // upload images
         stream::iter(imgs)
             .filter_map(async |img| {
-                // check if the image is base64
-                if img.type_ != "base64" {
-                    warn!("Image type is not base64");
-                    return None;
-                }
-                // decode the image
-                let bytes = BASE64_STANDARD
-                    .decode(img.data)
-                    .inspect_err(|e| {
-                        warn!("Failed to decode image: {}", e);
-                    })
-                    .ok()?;
+                let (bytes, media_type) = match img.type_.as_str() {
+                    // decode the image
+                    "base64" => {
+                        let bytes = BASE64_STANDARD
+                            .decode(img.data)
+                            .inspect_err(|e| {
+                                warn!("Failed to decode image: {}", e);
+                            })
+                            .ok()?;
+                        (bytes, img.media_type)
+                    }
+                    // fetch the image
+                    "url" => self.download_image(&img.data).await?,
+                    _ => {
+                        warn!("Unsupported image type: {}", img.type_);
+                        return None;
+                    }
+                };
                 // choose the file name based on the media type
-                let file_name = match img.media_type.to_lowercase().as_str() {
+                let file_name = match media_type.to_lowercase().as_str() {
                     "image/png" => "image.png",
                     "image/jpeg" => "image.jpg",
                     "image/jpg" => "image.jpg",
@@ -76,37 +198,7 @@ impl ClaudeWebState {
                     "application/pdf" => "document.pdf",
                     _ => "file",
                 };
-                // create the part and form
-                let part = Part::bytes(bytes).file_name(file_name);
-                let form = Form::new().part("file", part);
-                let endpoint = self
-                    .endpoint
-                    .join(&format!("api/{}/upload", self.org_uuid.as_ref()?))
-                    .expect("Url parse error");
-                // send the request into future
-                let res = self
-                    .build_request(http::Method::POST, endpoint)
-                    .multipart(form)
-                    .send()
-                    .await
-                    .inspect_err(|e| {
-                        warn!("Failed to upload image: {}", e);
-                    })
-                    .ok()?;
-                #[derive(serde::Deserialize)]
-                struct UploadResponse {
-                    file_uuid: String,
-                }
-                // get the response json
-                let json = res
-                    .json::<UploadResponse>()
-                    .await
-                    .inspect_err(|e| {
-                        warn!("Failed to parse image response: {}", e);
-                    })
-                    .ok()?;
-                // extract the file_uuid
-                Some(json.file_uuid)
+                self.upload_file(bytes, file_name).await
             })
             .collect::<Vec<_>>()
             .await
@@ -119,6 +211,8 @@ struct Merged {
     pub paste: String,
     pub prompt: String,
     pub images: Vec<ImageSource>,
+    /// Files API ids referenced by the messages
+    pub files: Vec<String>,
 }
 
 /// Merges multiple messages into a single text prompt, handling system instructions
@@ -146,6 +240,7 @@ fn merge_messages(msgs: Vec<Message>, system: String) -> Option<Merged> {
         .unwrap_or("Assistant".to_string());
 
     let user_real_roles = CLEWDR_CONFIG.load().use_real_roles;
+    let tool_history = CLEWDR_CONFIG.load().web_tool_history;
     let line_breaks = if user_real_roles { "\n\n\x08" } else { "\n\n" };
     let system = system.trim().to_string();
     let size = size_of_val(&msgs);
@@ -153,6 +248,9 @@ fn merge_messages(msgs: Vec<Message>, system: String) -> Option<Merged> {
     let mut w = String::with_capacity(size);
 
     let mut imgs: Vec<ImageSource> = vec![];
+    let mut files: Vec<String> = vec![];
+    // tool call id -> tool name, results only carry the id
+    let mut tool_names: HashMap<String, String> = HashMap::new();
 
     let chunks = msgs
         .into_iter()
@@ -175,7 +273,24 @@ fn merge_messages(msgs: Vec<Message>, system: String) -> Option<Merged> {
                             }
                             None
                         }
-                        _ => None,
+                        ContentBlock::File { file } => {
+                            // uploaded through the files API
+                            files.extend(file.file_id);
+                            None
+                        }
+                        ContentBlock::ToolUse { id, name, input } => {
+                            let text = tool_use_text(tool_history, &id, &name, &input);
+                            tool_names.insert(id, name);
+                            text
+                        }
+                        ContentBlock::ToolResult {
+                            tool_use_id,
+                            content,
+                        } => {
+                            let name = tool_names.get(&tool_use_id).map(String::as_str);
+                            let output = tool_result_output(&content, &mut imgs);
+                            tool_result_text(tool_history, &tool_use_id, name, &output)
+                        }
                     })
                     .collect::<Vec<_>>()
                     .join("\n");
@@ -220,6 +335,7 @@ fn merge_messages(msgs: Vec<Message>, system: String) -> Option<Merged> {
         };
         write!(w, "{line_breaks}{prefix}{text}").ok()?;
     }
+    let w = padding::apply(w);
     print_out_text(w.to_owned(), "paste.txt");
 
     // prompt polyfill
@@ -229,9 +345,72 @@ fn merge_messages(msgs: Vec<Message>, system: String) -> Option<Merged> {
         paste: w,
         prompt: p,
         images: imgs,
+        files,
     })
 }
 
+/// Writes a tool call of the conversation history into the prompt
+///
+/// # Returns
+/// * `Option<String>` - The call in the configured format, None when tool blocks are stripped
+fn tool_use_text(format: WebToolHistory, id: &str, name: &str, input: &Value) -> Option<String> {
+    match format {
+        WebToolHistory::Xml => Some(format!(
+            "<tool_use id=\"{id}\" name=\"{name}\">\n{input}\n</tool_use>"
+        )),
+        WebToolHistory::Json => {
+            Some(json!({ "type": "tool_use", "id": id, "name": name, "input": input }).to_string())
+        }
+        WebToolHistory::Strip => None,
+    }
+}
+
+/// Writes the result of a tool call into the prompt, like [`tool_use_text`]
+fn tool_result_text(
+    format: WebToolHistory,
+    id: &str,
+    name: Option<&str>,
+    output: &str,
+) -> Option<String> {
+    match format {
+        WebToolHistory::Xml => {
+            let name = name.map(|n| format!(" name=\"{n}\"")).unwrap_or_default();
+            Some(format!(
+                "<tool_result id=\"{id}\"{name}>\n{}\n</tool_result>",
+                output.trim()
+            ))
+        }
+        WebToolHistory::Json => Some(
+            json!({ "type": "tool_result", "tool_use_id": id, "name": name, "content": output })
+                .to_string(),
+        ),
+        WebToolHistory::Strip => None,
+    }
+}
+
+/// Text of a tool result, images in the result are uploaded like message images
+fn tool_result_output(content: &Value, imgs: &mut Vec<ImageSource>) -> String {
+    match content {
+        Value::String(s) => s.to_owned(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|p| match p["type"].as_str() {
+                Some("text") => p["text"].as_str().map(str::to_string),
+                Some("image") => {
+                    if let Ok(source) = serde_json::from_value(p["source"].to_owned()) {
+                        imgs.push(source);
+                    }
+                    None
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
 /// Merges system message content into a single string
 /// Handles both string and array formats for system messages
 ///
@@ -254,8 +433,16 @@ fn merge_system(sys: Value) -> String {
 }
 
 fn extract_image_from_url(url: &str) -> Option<ImageSource> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        // downloaded right before uploading
+        return Some(ImageSource {
+            type_: "url".to_string(),
+            media_type: String::new(),
+            data: url.to_string(),
+        });
+    }
     if !url.starts_with("data:") {
-        return None; // only support data URI
+        return None;
     }
     let (metadata, base64_data) = url.split_once(',')?;
 
@@ -267,3 +454,46 @@ fn extract_image_from_url(url: &str) -> Option<ImageSource> {
         data: base64_data.to_owned(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_urls_need_http_and_public_addresses() {
+        let check = |url: &str| check_image_url(&Url::parse(url).unwrap());
+        assert!(check("https://example.com/cat.png").is_ok());
+        assert!(check("https://1.1.1.1/cat.png").is_ok());
+        assert!(check("file:///etc/passwd").is_err());
+        assert!(check("http://169.254.169.254/latest/meta-data").is_err());
+        assert!(check("http://[::ffff:127.0.0.1]/").is_err());
+    }
+
+    #[test]
+    fn tool_blocks_are_serialized() {
+        let input = json!({ "city": "Paris" });
+        assert_eq!(
+            tool_use_text(WebToolHistory::Xml, "toolu_1", "weather", &input).unwrap(),
+            "<tool_use id=\"toolu_1\" name=\"weather\">\n{\"city\":\"Paris\"}\n</tool_use>"
+        );
+        assert_eq!(
+            tool_result_text(WebToolHistory::Xml, "toolu_1", Some("weather"), "sunny\n").unwrap(),
+            "<tool_result id=\"toolu_1\" name=\"weather\">\nsunny\n</tool_result>"
+        );
+        let json: Value = serde_json::from_str(
+            &tool_use_text(WebToolHistory::Json, "toolu_1", "weather", &input).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(json["input"]["city"], "Paris");
+        assert!(tool_use_text(WebToolHistory::Strip, "toolu_1", "weather", &input).is_none());
+
+        let mut imgs = vec![];
+        let content = json!([
+            { "type": "text", "text": "a" },
+            { "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "AA==" } },
+            { "type": "text", "text": "b" }
+        ]);
+        assert_eq!(tool_result_output(&content, &mut imgs), "a\nb");
+        assert_eq!(imgs.len(), 1);
+    }
+}
@@ -0,0 +1,375 @@
+//! Offline administration, works on the config file or the database without starting the server
+//!
+//! With file storage, changes made while a server is running get overwritten when it saves.
+use std::path::PathBuf;
+
+use clap::Subcommand;
+use colored::Colorize;
+
+use crate::{
+    config::{
+        CLEWDR_CONFIG, ClewdrConfig, CookieStatus, GeminiKey, KeyStatus, MASTER_KEY_ENV,
+        UselessCookie, open_config, secrets_encrypted,
+    },
+    error::ClewdrError,
+    persistence::{self, StorageBatch},
+};
+
+/// Tasks run instead of the server
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Apply pending database migrations and exit
+    Migrate,
+    /// List, add or remove cookies
+    #[command(subcommand)]
+    Cookies(CookieCommand),
+    /// List or add Gemini keys
+    #[command(subcommand)]
+    Keys(KeyCommand),
+    /// Change settings
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Write config, cookies and keys as TOML, to stdout without a path
+    Export { path: Option<PathBuf> },
+    /// Replace config, cookies and keys with a TOML file written by `export`
+    Import { path: PathBuf },
+    /// Rewrite stored cookies, tokens and keys encrypted with `CLEWDR_MASTER_KEY`
+    EncryptSecrets,
+    /// Replay a recorded upstream stream and compare the OpenAI format output with its golden file
+    #[cfg(feature = "test-fixtures")]
+    Replay {
+        fixture: PathBuf,
+        /// Write the output as the golden file instead of comparing
+        #[arg(long)]
+        bless: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CookieCommand {
+    /// Print every cookie with its state
+    List,
+    /// Add cookies, invalid ones are given another chance
+    Add {
+        #[arg(required = true)]
+        cookies: Vec<String>,
+    },
+    /// Remove cookies, whatever their state
+    Remove {
+        #[arg(required = true)]
+        cookies: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KeyCommand {
+    /// Print every key with its state
+    List,
+    /// Add keys, invalid ones are given another chance
+    Add {
+        #[arg(required = true)]
+        keys: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Set settings given as `key=value`, nested ones as `persistence.mode=sqlite`
+    ///
+    /// Values are read as TOML, anything else is taken as a string.
+    Set {
+        #[arg(required = true)]
+        assignments: Vec<String>,
+    },
+}
+
+/// Runs a subcommand against the configured storage
+pub async fn run(command: Command) -> Result<(), ClewdrError> {
+    if let Command::Migrate = command {
+        for name in persistence::migrate().await? {
+            println!("Applied migration {}", name.green());
+        }
+        println!("Database schema is up to date");
+        return Ok(());
+    }
+    #[cfg(feature = "test-fixtures")]
+    if let Command::Replay { fixture, bless } = command {
+        print!("{}", crate::replay::check(&fixture, bless).await?);
+        if bless {
+            println!("Golden file written for {}", fixture.display());
+        } else {
+            println!("Output matches the golden file of {}", fixture.display());
+        }
+        return Ok(());
+    }
+    let storage = persistence::storage();
+    if storage.is_enabled() {
+        storage.spawn_bootstrap().await?;
+    }
+    let mut config = CLEWDR_CONFIG.load().as_ref().to_owned();
+    match command {
+        Command::Migrate => unreachable!("handled above"),
+        #[cfg(feature = "test-fixtures")]
+        Command::Replay { .. } => unreachable!("handled above"),
+        Command::Cookies(CookieCommand::List) => {
+            let (valid, exhausted, invalid) = if storage.is_enabled() {
+                persistence::load_all_cookies().await?
+            } else {
+                let (exhausted, valid) = config
+                    .cookie_array
+                    .iter()
+                    .cloned()
+                    .partition(|c| c.reset_time.is_some());
+                (
+                    valid,
+                    exhausted,
+                    config.wasted_cookie.iter().cloned().collect(),
+                )
+            };
+            for c in valid {
+                println!("{}\t{}", "valid".green(), c.cookie);
+            }
+            for c in exhausted {
+                println!("{}\t{}", "exhausted".yellow(), c.cookie);
+            }
+            for c in invalid {
+                println!("{}\t{}\t{}", "invalid".red(), c.cookie, c.reason);
+            }
+        }
+        Command::Cookies(CookieCommand::Add { cookies }) => {
+            let cookies = cookies
+                .iter()
+                .map(|c| CookieStatus::new(c, None))
+                .collect::<Result<Vec<_>, _>>()?;
+            if storage.is_enabled() {
+                for c in cookies.iter() {
+                    storage.persist_cookie_upsert(c).await?;
+                }
+            } else {
+                for c in cookies.iter() {
+                    config.wasted_cookie.retain(|u| u.cookie != c.cookie);
+                    config.cookie_array.replace(c.to_owned());
+                }
+                config.save().await?;
+            }
+            println!("Added {} cookies", cookies.len());
+        }
+        Command::Cookies(CookieCommand::Remove { cookies }) => {
+            let cookies = cookies
+                .iter()
+                .map(|c| CookieStatus::new(c, None))
+                .collect::<Result<Vec<_>, _>>()?;
+            if storage.is_enabled() {
+                for c in cookies.iter() {
+                    storage.delete_cookie_row(c).await?;
+                }
+            } else {
+                for c in cookies.iter() {
+                    config.cookie_array.remove(c);
+                    config.wasted_cookie.retain(|u| u.cookie != c.cookie);
+                }
+                config.save().await?;
+            }
+            println!("Removed {} cookies", cookies.len());
+        }
+        Command::Keys(KeyCommand::List) => {
+            let (valid, invalid) = if storage.is_enabled() {
+                (
+                    persistence::load_all_keys().await?,
+                    storage.load_invalid_keys().await?,
+                )
+            } else {
+                (
+                    config.gemini_keys.iter().cloned().collect(),
+                    config.invalid_keys.iter().cloned().collect(),
+                )
+            };
+            for k in valid {
+                let state = if k.disabled {
+                    "disabled".yellow()
+                } else {
+                    "valid".green()
+                };
+                println!("{}\t{}", state, k.key);
+            }
+            for k in invalid {
+                println!("{}\t{}", "invalid".red(), k.key);
+            }
+        }
+        Command::Keys(KeyCommand::Add { keys }) => {
+            let keys = keys
+                .iter()
+                .map(|k| {
+                    let key = GeminiKey::from(k.as_str());
+                    if !key.validate() {
+                        return Err(ClewdrError::Whatever {
+                            message: format!("Invalid key: {}", key.ellipse()),
+                            source: None,
+                        });
+                    }
+                    Ok(KeyStatus::from(key))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if storage.is_enabled() {
+                for k in keys.iter() {
+                    storage.persist_key_upsert(k).await?;
+                }
+            } else {
+                for k in keys.iter() {
+                    config.invalid_keys.remove(k);
+                    config.gemini_keys.replace(k.to_owned());
+                }
+                config.save().await?;
+            }
+            println!("Added {} keys", keys.len());
+        }
+        Command::Config(ConfigCommand::Set { assignments }) => {
+            let config = set_values(&config, &assignments)?;
+            config.save().await?;
+            for a in assignments {
+                println!("Set {}", a.green());
+            }
+        }
+        Command::Export { path } => {
+            let toml = if storage.is_enabled() {
+                let exported = storage.export_current_config().await?;
+                exported["toml"].as_str().unwrap_or_default().to_string()
+            } else {
+                toml::to_string_pretty(&config)?
+            };
+            match path {
+                Some(path) => {
+                    tokio::fs::write(&path, toml).await?;
+                    println!("Exported to {}", path.display().to_string().green());
+                }
+                None => print!("{toml}"),
+            }
+        }
+        Command::Import { path } => {
+            let text = tokio::fs::read_to_string(&path).await?;
+            let config = open_config(&text)?.validate();
+            if storage.is_enabled() {
+                storage.persist_config(&config).await?;
+                let (exhausted, valid): (Vec<_>, Vec<_>) = config
+                    .cookie_array
+                    .iter()
+                    .cloned()
+                    .partition(|c| c.reset_time.is_some());
+                let invalid: Vec<UselessCookie> = config.wasted_cookie.iter().cloned().collect();
+                storage
+                    .persist_cookies(&valid, &exhausted, &invalid)
+                    .await?;
+                storage
+                    .persist_batch(&StorageBatch {
+                        replace_keys: true,
+                        keys: config.gemini_keys.iter().cloned().collect(),
+                        invalid_keys: config.invalid_keys.iter().cloned().collect(),
+                        ..Default::default()
+                    })
+                    .await?;
+            } else {
+                config.save().await?;
+            }
+            println!("Imported {}", path.display().to_string().green());
+        }
+        Command::EncryptSecrets => {
+            if !secrets_encrypted() {
+                return Err(ClewdrError::Whatever {
+                    message: format!("Set {MASTER_KEY_ENV} to encrypt secrets"),
+                    source: None,
+                });
+            }
+            // everything was decrypted on load, writing it back encrypts it
+            if storage.is_enabled() {
+                let (valid, exhausted, invalid) = persistence::load_all_cookies().await?;
+                let keys = persistence::load_all_keys().await?;
+                storage.persist_config(&config).await?;
+                storage
+                    .persist_cookies(&valid, &exhausted, &invalid)
+                    .await?;
+                storage.persist_keys(&keys).await?;
+            } else {
+                config.save().await?;
+            }
+            println!("Secrets encrypted");
+        }
+    }
+    Ok(())
+}
+
+/// Applies `key=value` assignments to a copy of the config
+///
+/// Every assignment must round-trip through the config, so misspelled settings
+/// and values of the wrong type are refused instead of silently dropped.
+fn set_values(config: &ClewdrConfig, assignments: &[String]) -> Result<ClewdrConfig, ClewdrError> {
+    let invalid = |message: String| ClewdrError::Whatever {
+        message,
+        source: None,
+    };
+    let mut root = toml::Table::try_from(config)?;
+    let mut expected = vec![];
+    for assignment in assignments {
+        let Some((path, raw)) = assignment.split_once('=') else {
+            return Err(invalid(format!("Expected key=value, got {assignment}")));
+        };
+        let path = path.trim().split('.').collect::<Vec<_>>();
+        let value = parse_value(raw.trim());
+        let (last, parents) = path.split_last().expect("split always yields a segment");
+        let mut table = &mut root;
+        for segment in parents {
+            table = table
+                .entry(segment.to_string())
+                .or_insert_with(|| toml::Value::Table(Default::default()))
+                .as_table_mut()
+                .ok_or_else(|| invalid(format!("{segment} is not a table")))?;
+        }
+        table.insert(last.to_string(), value.to_owned());
+        expected.push((path, value));
+    }
+    let updated: ClewdrConfig = root.try_into()?;
+    let check = toml::Table::try_from(&updated)?;
+    for (path, value) in expected {
+        let (first, rest) = path.split_first().expect("split always yields a segment");
+        let stored = rest.iter().fold(check.get(*first), |v, segment| {
+            v.and_then(|v| v.get(*segment))
+        });
+        if stored != Some(&value) {
+            return Err(invalid(format!(
+                "Unknown setting or invalid value: {}",
+                path.join(".")
+            )));
+        }
+    }
+    Ok(updated.validate())
+}
+
+/// Reads a value as TOML, falling back to a plain string
+fn parse_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("v = {raw}"))
+        .ok()
+        .and_then(|mut t| t.remove("v"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_parse_as_toml_or_string() {
+        assert_eq!(parse_value("8484"), toml::Value::Integer(8484));
+        assert_eq!(parse_value("true"), toml::Value::Boolean(true));
+        assert_eq!(parse_value("\"a b\""), toml::Value::String("a b".into()));
+        assert_eq!(parse_value("sqlite"), toml::Value::String("sqlite".into()));
+    }
+
+    #[test]
+    fn set_refuses_unknown_settings() {
+        let config = ClewdrConfig::default();
+        let updated = set_values(&config, &["max_retries=9".into()]).unwrap();
+        assert_eq!(updated.max_retries, 9);
+        assert!(set_values(&config, &["max_retires=9".into()]).is_err());
+        assert!(set_values(&config, &["max_retries=many".into()]).is_err());
+        assert!(set_values(&config, &["max_retries".into()]).is_err());
+    }
+}
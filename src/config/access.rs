@@ -0,0 +1,84 @@
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// IP based access control, checked before authentication
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AccessControlConfig {
+    /// Only these networks may connect when the list is not empty
+    #[serde(default, deserialize_with = "deserialize_nets")]
+    pub allowlist: Vec<IpNet>,
+    /// These networks are always rejected, even if they are allowlisted
+    #[serde(default, deserialize_with = "deserialize_nets")]
+    pub denylist: Vec<IpNet>,
+    /// Take the client address from `x-forwarded-for` / `x-real-ip`,
+    /// only enable this behind a reverse proxy that sets these headers
+    #[serde(default)]
+    pub trust_proxy_headers: bool,
+    /// Networks of the reverse proxies in front of ClewdR, their hops in `x-forwarded-for`
+    /// are skipped. When empty, only the connected peer is trusted as a proxy.
+    #[serde(default, deserialize_with = "deserialize_nets")]
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+/// Parses a CIDR network, a bare address is treated as a single host
+pub fn parse_net(s: &str) -> Result<IpNet, String> {
+    let s = s.trim();
+    s.parse::<IpNet>()
+        .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("invalid network `{s}`"))
+}
+
+fn deserialize_nets<'de, D>(deserializer: D) -> Result<Vec<IpNet>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| parse_net(s).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+impl AccessControlConfig {
+    /// Whether any rule is configured
+    pub fn is_active(&self) -> bool {
+        !self.allowlist.is_empty() || !self.denylist.is_empty()
+    }
+
+    /// Whether the address belongs to one of the `trusted_proxies`
+    pub fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.trusted_proxies.iter().any(|net| net.contains(&ip))
+    }
+
+    /// Whether a client with this address may connect
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of a dual-stack listener show up as IPv4-mapped IPv6
+        let ip = ip.to_canonical();
+        if self.denylist.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allowlist.is_empty() || self.allowlist.iter().any(|net| net.contains(&ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allow_and_deny() {
+        let acl: AccessControlConfig = serde_json::from_str(
+            r#"{ "allowlist": ["10.0.0.0/8", "::1"], "denylist": ["10.0.0.5"] }"#,
+        )
+        .unwrap();
+        assert!(acl.is_allowed("10.1.2.3".parse().unwrap()));
+        assert!(acl.is_allowed("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(acl.is_allowed("::1".parse().unwrap()));
+        assert!(!acl.is_allowed("10.0.0.5".parse().unwrap()));
+        assert!(!acl.is_allowed("192.168.1.1".parse().unwrap()));
+        assert!(AccessControlConfig::default().is_allowed("192.168.1.1".parse().unwrap()));
+        assert!(parse_net("10.0.0.0/33").is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::{CONFIG_PATH, default_backup_keep, default_object_store_region};
+
+/// S3 compatible bucket, for backups or the `s3` persistence mode
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ObjectStoreConfig {
+    /// Endpoint of the service, e.g. `https://s3.us-east-1.amazonaws.com`,
+    /// buckets are addressed path style below it
+    pub endpoint: String,
+    pub bucket: String,
+    /// Signing region, `auto` for Cloudflare R2
+    #[serde(default = "default_object_store_region")]
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Prepended to the object names, e.g. `clewdr/`
+    #[serde(default)]
+    pub prefix: String,
+}
+
+/// Scheduled snapshots of the config with its cookies and keys, can hot reload
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupConfig {
+    /// Hours between automatic backups, 24 for nightly ones, 0 disables them
+    #[serde(default)]
+    pub interval_hours: u64,
+    /// Directory of the backup files, `backups` next to the config file when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    /// Newest backups kept, older ones are deleted after each backup, 0 keeps all
+    #[serde(default = "default_backup_keep")]
+    pub keep: usize,
+    /// Uploads backups to this bucket instead of writing them to `dir`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_store: Option<ObjectStoreConfig>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            interval_hours: 0,
+            dir: None,
+            keep: default_backup_keep(),
+            object_store: None,
+        }
+    }
+}
+
+impl BackupConfig {
+    /// Directory local backups are written to
+    pub fn dir(&self) -> PathBuf {
+        self.dir.to_owned().unwrap_or_else(|| {
+            CONFIG_PATH
+                .parent()
+                .map(|p| p.join("backups"))
+                .unwrap_or_else(|| PathBuf::from("backups"))
+        })
+    }
+}
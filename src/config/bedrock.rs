@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Suffix of the model ids served by Bedrock, e.g. `claude-sonnet-4-5-bedrock`
+pub const BEDROCK_MODEL_SUFFIX: &str = "-bedrock";
+
+/// Anthropic models on AWS Bedrock
+///
+/// Unset credentials and region are read from the standard `AWS_ACCESS_KEY_ID`,
+/// `AWS_SECRET_ACCESS_KEY`, `AWS_SESSION_TOKEN` and `AWS_REGION` environment variables.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BedrockConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_key_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_access_key: Option<String>,
+    /// Session token of temporary credentials
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_token: Option<String>,
+    /// Bedrock model or inference profile id by model name without the suffix, e.g.
+    /// `claude-sonnet-4-5` to `us.anthropic.claude-sonnet-4-5-20250929-v1:0`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub models: BTreeMap<String, String>,
+}
+
+/// Credentials and region resolved from the config and the environment
+#[derive(Debug, Clone)]
+pub struct BedrockCredentials {
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl BedrockConfig {
+    /// Credentials to sign requests with, `None` when no key or region is configured
+    pub fn credentials(&self) -> Option<BedrockCredentials> {
+        let pick = |value: &Option<String>, env: &str| {
+            value
+                .to_owned()
+                .or_else(|| std::env::var(env).ok())
+                .filter(|v| !v.trim().is_empty())
+        };
+        Some(BedrockCredentials {
+            region: pick(&self.region, "AWS_REGION")?,
+            access_key_id: pick(&self.access_key_id, "AWS_ACCESS_KEY_ID")?,
+            secret_access_key: pick(&self.secret_access_key, "AWS_SECRET_ACCESS_KEY")?,
+            session_token: pick(&self.session_token, "AWS_SESSION_TOKEN"),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.credentials().is_some()
+    }
+
+    /// Bedrock id of a requested model, the suffix dropped and `models` applied
+    ///
+    /// Models without a mapping are sent as named, so Bedrock ids work as they are.
+    pub fn model_id(&self, model: &str) -> String {
+        let model = model.strip_suffix(BEDROCK_MODEL_SUFFIX).unwrap_or(model);
+        self.models
+            .get(model)
+            .map_or_else(|| model.to_string(), ToOwned::to_owned)
+    }
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    default_breaker_cooldown, default_breaker_error_rate, default_breaker_min_requests,
+    default_breaker_window,
+};
+
+/// Circuit breaker of each upstream provider, stops burning cookies and keys while
+/// the provider keeps failing
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BreakerConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Share of failed requests in a window that opens the breaker, from 0 to 1
+    #[serde(default = "default_breaker_error_rate")]
+    pub error_rate: f64,
+    /// Requests a window needs before its error rate is judged
+    #[serde(default = "default_breaker_min_requests")]
+    pub min_requests: u32,
+    /// Length of a window in seconds
+    #[serde(default = "default_breaker_window")]
+    pub window: u64,
+    /// Seconds an open breaker rejects requests before letting a probe through
+    #[serde(default = "default_breaker_cooldown")]
+    pub cooldown: u64,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            error_rate: default_breaker_error_rate(),
+            min_requests: default_breaker_min_requests(),
+            window: default_breaker_window(),
+            cooldown: default_breaker_cooldown(),
+        }
+    }
+}
@@ -135,5 +135,333 @@ pub const fn default_skip_cool_down() -> bool {
     true
 }
 
+/// Default number of cookies bootstrapped per probe round
+///
+/// # Returns
+/// * `usize` - The default value of 3
+pub const fn default_cookie_probe_sample() -> usize {
+    3
+}
+
+/// Default age after which leftover ClewdR conversations are deleted
+///
+/// # Returns
+/// * `u64` - The default value of 86400 seconds (1 day)
+pub const fn default_chat_cleanup_max_age() -> u64 {
+    86400
+}
+
+/// Default time before expiry at which Claude Code tokens are refreshed in the background
+///
+/// # Returns
+/// * `u64` - The default value of 1800 seconds (30 minutes)
+pub const fn default_token_refresh_lead() -> u64 {
+    1800
+}
+
+/// Default `anthropic-version` of Claude Code requests
+///
+/// # Returns
+/// * `String` - The default value of "2023-06-01"
+pub fn default_anthropic_version() -> String {
+    "2023-06-01".to_string()
+}
+
+/// Default setting for marking large system prompts as cacheable in Claude Code
+///
+/// # Returns
+/// * `bool` - The default value of true
+pub const fn default_prompt_caching() -> bool {
+    true
+}
+
+/// Default minimum size of a system prompt worth caching, in tokens
+///
+/// # Returns
+/// * `u32` - The default value of 1024, the smallest prefix Anthropic caches
+pub const fn default_prompt_cache_min_tokens() -> u32 {
+    1024
+}
+
+/// Default number of requests allowed to wait for a cookie or key
+///
+/// # Returns
+/// * `usize` - The default value of 64
+pub const fn default_queue_size() -> usize {
+    64
+}
+
+/// Default time a queued request waits for a cookie or key, in seconds
+///
+/// # Returns
+/// * `u64` - The default value of 30
+pub const fn default_queue_timeout() -> u64 {
+    30
+}
+
+/// Default time a forwarded stream may go without an event, in seconds
+///
+/// # Returns
+/// * `u64` - The default value of 300
+pub const fn default_stream_idle_timeout() -> u64 {
+    300
+}
+
+/// Default number of stream events buffered for a slow client
+///
+/// # Returns
+/// * `usize` - The default value of 64
+pub const fn default_stream_buffer() -> usize {
+    64
+}
+
+/// Default time a client may stop reading a stream, in seconds
+///
+/// # Returns
+/// * `u64` - The default value of 60
+pub const fn default_stream_max_lag() -> u64 {
+    60
+}
+
+/// Default time a queued batch request waits for a cookie, in seconds
+///
+/// # Returns
+/// * `u64` - The default value of 600
+pub const fn default_batch_queue_timeout() -> u64 {
+    600
+}
+
+/// Default quota percentage a cookie must have left to serve batch requests
+///
+/// # Returns
+/// * `u32` - The default value of 50
+pub const fn default_batch_min_quota() -> u32 {
+    50
+}
+
+/// Default number of requests of a batch job in flight at once
+///
+/// # Returns
+/// * `usize` - The default value of 4
+pub const fn default_batch_concurrency() -> usize {
+    4
+}
+
+/// Default delay before the first retry, in milliseconds
+///
+/// # Returns
+/// * `u64` - The default value of 250
+pub const fn default_retry_base_delay() -> u64 {
+    250
+}
+
+/// Default growth factor of the retry delay per attempt
+///
+/// # Returns
+/// * `f64` - The default value of 2.0
+pub const fn default_retry_multiplier() -> f64 {
+    2.0
+}
+
+/// Default upper bound of a single retry delay, in milliseconds
+///
+/// # Returns
+/// * `u64` - The default value of 8000
+pub const fn default_retry_max_delay() -> u64 {
+    8000
+}
+
+/// Default fraction of the retry delay that is randomized
+///
+/// # Returns
+/// * `f64` - The default value of 0.5
+pub const fn default_retry_jitter() -> f64 {
+    0.5
+}
+
+/// Default largest accepted request body, in MiB
+///
+/// # Returns
+/// * `usize` - The default value of 32
+pub const fn default_max_body_size() -> usize {
+    32
+}
+
+/// Default most messages accepted in a single request
+///
+/// # Returns
+/// * `usize` - The default value of 10000
+pub const fn default_max_messages() -> usize {
+    10000
+}
+
+/// Default most stop sequences accepted in a single request
+///
+/// # Returns
+/// * `usize` - The default value of 64
+pub const fn default_max_stop_sequences() -> usize {
+    64
+}
+
+/// Default largest accepted inline image, in MiB
+///
+/// # Returns
+/// * `usize` - The default value of 20
+pub const fn default_max_image_size() -> usize {
+    20
+}
+
+/// Default time a cookie lease outlives its last dispatch, in seconds
+///
+/// # Returns
+/// * `u64` - The default value of 120
+pub const fn default_cluster_lease_ttl() -> u64 {
+    120
+}
+
+/// Default longest wait for an organization's request slot, in seconds
+///
+/// # Returns
+/// * `u64` - The default value of 30
+pub const fn default_org_max_wait() -> u64 {
+    30
+}
+
+/// Default thinking budget of `-thinking` models, in tokens
+///
+/// # Returns
+/// * `u64` - The default value of 4096
+pub const fn default_thinking_budget() -> u64 {
+    4096
+}
+
+/// Default largest `n` accepted by the OpenAI endpoints of Claude
+///
+/// # Returns
+/// * `u32` - The default value of 4
+pub const fn default_max_candidates() -> u32 {
+    4
+}
+
+/// Default number of days transcripts are kept
+///
+/// # Returns
+/// * `u64` - The default value of 7
+pub const fn default_transcript_retention_days() -> u64 {
+    7
+}
+
+/// Default number of 403 and 429 responses in a row before a Gemini key is quarantined
+///
+/// # Returns
+/// * `u32` - The default value of 5
+pub const fn default_key_quarantine_after() -> u32 {
+    5
+}
+
+/// Default number of failed probes before a quarantined Gemini key is dropped
+///
+/// # Returns
+/// * `u32` - The default value of 4
+pub const fn default_key_invalid_after() -> u32 {
+    4
+}
+
+/// Default for applying pending database migrations on startup
+///
+/// # Returns
+/// * `bool` - The default value of true
+pub const fn default_auto_migrate() -> bool {
+    true
+}
+
+/// Default threshold of the Gemini harm categories
+///
+/// # Returns
+/// * `String` - The default value of "OFF"
+pub fn default_safety_threshold() -> String {
+    "OFF".to_string()
+}
+
+/// Default share of failed requests that opens a provider circuit breaker
+///
+/// # Returns
+/// * `f64` - The default value of 0.5
+pub const fn default_breaker_error_rate() -> f64 {
+    0.5
+}
+
+/// Default number of requests a breaker window needs before it may open
+///
+/// # Returns
+/// * `u32` - The default value of 10
+pub const fn default_breaker_min_requests() -> u32 {
+    10
+}
+
+/// Default length of a breaker window in seconds
+///
+/// # Returns
+/// * `u64` - The default value of 60
+pub const fn default_breaker_window() -> u64 {
+    60
+}
+
+/// Default seconds an open breaker waits before a probe request
+///
+/// # Returns
+/// * `u64` - The default value of 30
+pub const fn default_breaker_cooldown() -> u64 {
+    30
+}
+
+/// Default seconds a failed upstream endpoint is skipped before it is tried again
+///
+/// # Returns
+/// * `u64` - The default value of 300
+pub const fn default_endpoint_failback() -> u64 {
+    300
+}
+
+/// Default number of backups kept by retention pruning
+///
+/// # Returns
+/// * `usize` - The default value of 7
+pub const fn default_backup_keep() -> usize {
+    7
+}
+
+/// Default signing region of the backup object store
+///
+/// # Returns
+/// * `String` - The default value of "us-east-1"
+pub fn default_object_store_region() -> String {
+    "us-east-1".to_string()
+}
+
+/// Default seconds alerts are gathered for before a chat notification
+///
+/// # Returns
+/// * `u64` - The default value of 60
+pub const fn default_notify_interval() -> u64 {
+    60
+}
+
+/// Default largest data URL in KiB kept by request scrubbing
+///
+/// # Returns
+/// * `usize` - The default value of 5120
+pub const fn default_max_data_url_size() -> usize {
+    5 * 1024
+}
+
+/// Default number of system messages allowed after the conversation started
+///
+/// # Returns
+/// * `usize` - The default value of 4
+pub const fn default_max_nested_system() -> usize {
+    4
+}
+
 /// Default cookie value for testing purposes
 pub const PLACEHOLDER_COOKIE: &str = "sk-ant-REDACTED";
@@ -12,7 +12,7 @@ use snafu::{GenerateImplicitData, Location};
 use tracing::info;
 
 use crate::{
-    config::{PLACEHOLDER_COOKIE, TokenInfo},
+    config::{PLACEHOLDER_COOKIE, Reason, TokenInfo},
     error::ClewdrError,
 };
 
@@ -32,6 +32,9 @@ pub struct UsageBreakdown {
     pub total_input_tokens: u64,
     #[serde(default)]
     pub total_output_tokens: u64,
+    /// Requests that reported usage
+    #[serde(default)]
+    pub requests: u64,
 
     #[serde(default)]
     pub sonnet_input_tokens: u64,
@@ -42,6 +45,122 @@ pub struct UsageBreakdown {
     pub opus_input_tokens: u64,
     #[serde(default)]
     pub opus_output_tokens: u64,
+
+    /// Input tokens served from the prompt cache
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+    /// Input tokens written to the prompt cache
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+}
+
+impl UsageBreakdown {
+    fn add(&mut self, input: u64, output: u64) {
+        self.requests = self.requests.saturating_add(1);
+        self.total_input_tokens = self.total_input_tokens.saturating_add(input);
+        self.total_output_tokens = self.total_output_tokens.saturating_add(output);
+    }
+
+    fn add_cache(&mut self, read: u64, creation: u64) {
+        self.cache_read_input_tokens = self.cache_read_input_tokens.saturating_add(read);
+        self.cache_creation_input_tokens =
+            self.cache_creation_input_tokens.saturating_add(creation);
+    }
+}
+
+/// A chat capable organization of a Claude account
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct ClaudeOrg {
+    pub uuid: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+impl ClaudeOrg {
+    /// Reads an organization object of the Claude.ai API
+    pub fn from_json(v: &serde_json::Value) -> Option<Self> {
+        Some(Self {
+            uuid: v["uuid"].as_str()?.to_string(),
+            name: v["name"].as_str().unwrap_or_default().to_string(),
+            capabilities: v["capabilities"]
+                .as_array()
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|c| c.as_str())
+                        .map(|c| c.to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Picks the organization a request asked for, or the one pinned by the admin
+    ///
+    /// # Arguments
+    /// * `orgs` - Chat capable organizations of the account
+    /// * `requested` - UUID or name (case insensitive) from the request
+    /// * `pinned` - UUID pinned on the cookie
+    ///
+    /// # Returns
+    /// * `Option<&ClaudeOrg>` - `None` when neither matches, callers fall back to their default
+    pub fn select<'a>(
+        orgs: &'a [ClaudeOrg],
+        requested: Option<&str>,
+        pinned: Option<&str>,
+    ) -> Option<&'a ClaudeOrg> {
+        requested
+            .and_then(|r| {
+                orgs.iter()
+                    .find(|o| o.uuid == r || o.name.eq_ignore_ascii_case(r))
+            })
+            .or_else(|| pinned.and_then(|p| orgs.iter().find(|o| o.uuid == p)))
+    }
+}
+
+/// Account capability a request can require of the cookie it is dispatched
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Capability {
+    /// Paid plan: Pro, Max, Team or Enterprise
+    Pro,
+    /// Opus models, only offered on paid plans
+    Opus,
+}
+
+impl Capability {
+    /// Capability Claude.ai needs to serve `model`, if any
+    pub fn for_model(model: &str) -> Option<Self> {
+        model
+            .to_ascii_lowercase()
+            .contains("opus")
+            .then_some(Self::Opus)
+    }
+
+    /// Whether an organization with `capabilities` has this capability
+    pub fn matches(self, capabilities: &[String]) -> bool {
+        match self {
+            Self::Pro | Self::Opus => capabilities.iter().any(|c| {
+                c.contains("pro")
+                    || c.contains("enterprise")
+                    || c.contains("raven")
+                    || c.contains("max")
+            }),
+        }
+    }
+}
+
+/// Normalizes cookie tags: trimmed, lowercase, sorted, without blanks or duplicates
+pub fn parse_tags<'a>(tags: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut tags = tags
+        .into_iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>();
+    tags.sort();
+    tags.dedup();
+    tags
 }
 
 /// A struct representing a cookie
@@ -69,6 +188,47 @@ impl<'de> Deserialize<'de> for ClewdrCookie {
     }
 }
 
+/// Most health events kept per cookie, older ones are dropped
+pub const MAX_HEALTH_EVENTS: usize = 20;
+
+/// What happened to a cookie outside of a user request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthEventKind {
+    /// A background refresh of the Claude Code token failed
+    TokenRefreshFailed,
+}
+
+/// A timestamped entry of a cookie's health history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthEvent {
+    /// Unix timestamp
+    pub at: i64,
+    pub kind: HealthEventKind,
+    pub message: String,
+}
+
+impl HealthEvent {
+    pub fn new(kind: HealthEventKind, message: impl Into<String>) -> Self {
+        Self {
+            at: chrono::Utc::now().timestamp(),
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+/// Outcome of dismissing the flags of a cookie's Claude.ai account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClearedFlags {
+    /// Unix timestamp
+    pub at: i64,
+    /// Active flags found on the account
+    pub present: Vec<String>,
+    /// Flags Claude.ai accepted to dismiss
+    pub dismissed: Vec<String>,
+}
+
 /// A struct representing a cookie with its information
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct CookieStatus {
@@ -112,6 +272,31 @@ pub struct CookieStatus {
     pub weekly_has_reset: Option<bool>,
     #[serde(default)]
     pub weekly_opus_has_reset: Option<bool>,
+
+    /// Paused by an operator, kept in the pool but never dispatched
+    #[serde(default)]
+    pub disabled: bool,
+    /// Chat capable organizations found when the cookie was last used
+    #[serde(default)]
+    pub organizations: Vec<ClaudeOrg>,
+    /// UUID of the organization used unless a request asks for another one
+    #[serde(default)]
+    pub pinned_org: Option<String>,
+    /// Operator labels such as `team-a` or `opus-only`, requests can be limited to tagged cookies
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Latest health events, oldest first, at most `MAX_HEALTH_EVENTS`
+    #[serde(default)]
+    pub health: Vec<HealthEvent>,
+    /// Flags found and dismissed on the last clearing, `None` if the flags were never cleared
+    #[serde(default)]
+    pub cleared_flags: Option<ClearedFlags>,
+    /// 403 responses received over the lifetime of the cookie
+    #[serde(default)]
+    pub count_403: u32,
+    /// 429 responses received over the lifetime of the cookie
+    #[serde(default)]
+    pub count_429: u32,
 }
 
 impl PartialEq for CookieStatus {
@@ -169,6 +354,14 @@ impl CookieStatus {
             session_has_reset: None,
             weekly_has_reset: None,
             weekly_opus_has_reset: None,
+            disabled: false,
+            organizations: Vec::new(),
+            pinned_org: None,
+            tags: Vec::new(),
+            health: Vec::new(),
+            cleared_flags: None,
+            count_403: 0,
+            count_429: 0,
         })
     }
 
@@ -205,6 +398,47 @@ impl CookieStatus {
         self.count_tokens_allowed = value;
     }
 
+    /// Whether the cookie carries every tag of `tags`
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|t| self.tags.contains(t))
+    }
+
+    /// Whether the cookie may serve a request needing `capability`
+    ///
+    /// Only the pinned organization counts when it is known, otherwise any organization does.
+    /// Cookies whose organizations were never fetched are assumed capable.
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        let orgs = match ClaudeOrg::select(&self.organizations, None, self.pinned_org.as_deref()) {
+            Some(pinned) => std::slice::from_ref(pinned),
+            None => &self.organizations[..],
+        };
+        orgs.is_empty() || orgs.iter().any(|o| capability.matches(&o.capabilities))
+    }
+
+    /// Appends a health event, dropping the oldest ones past `MAX_HEALTH_EVENTS`
+    pub fn record_health(&mut self, event: HealthEvent) {
+        self.health.push(event);
+        let excess = self.health.len().saturating_sub(MAX_HEALTH_EVENTS);
+        self.health.drain(..excess);
+    }
+
+    /// Counts a 403 or 429 answer to a request made with the cookie
+    ///
+    /// # Returns
+    /// * `bool` - Whether the error was counted
+    pub fn record_rejection(&mut self, e: &ClewdrError) -> bool {
+        match e {
+            ClewdrError::InvalidCookie {
+                reason: Reason::TooManyRequest(_),
+            } => self.count_429 = self.count_429.saturating_add(1),
+            ClewdrError::ClaudeHttpError { code, .. } if code.as_u16() == 403 => {
+                self.count_403 = self.count_403.saturating_add(1)
+            }
+            _ => return false,
+        }
+        true
+    }
+
     pub fn reset_window_usage(&mut self) {
         // Legacy window counters removed; reset session buckets conservatively
         self.session_usage = UsageBreakdown::default();
@@ -235,12 +469,7 @@ impl CookieStatus {
         // Legacy totals/windows removed; only bucketed aggregation remains
 
         // session bucket (total + per family)
-        self.session_usage.total_input_tokens =
-            self.session_usage.total_input_tokens.saturating_add(input);
-        self.session_usage.total_output_tokens = self
-            .session_usage
-            .total_output_tokens
-            .saturating_add(output);
+        self.session_usage.add(input, output);
         match family {
             ModelFamily::Sonnet => {
                 self.session_usage.sonnet_input_tokens =
@@ -260,10 +489,7 @@ impl CookieStatus {
         }
 
         // weekly bucket (total + per family)
-        self.weekly_usage.total_input_tokens =
-            self.weekly_usage.total_input_tokens.saturating_add(input);
-        self.weekly_usage.total_output_tokens =
-            self.weekly_usage.total_output_tokens.saturating_add(output);
+        self.weekly_usage.add(input, output);
         match family {
             ModelFamily::Sonnet => {
                 self.weekly_usage.sonnet_input_tokens =
@@ -284,14 +510,7 @@ impl CookieStatus {
 
         // weekly_opus bucket (only opus contributes)
         if matches!(family, ModelFamily::Opus) {
-            self.weekly_opus_usage.total_input_tokens = self
-                .weekly_opus_usage
-                .total_input_tokens
-                .saturating_add(input);
-            self.weekly_opus_usage.total_output_tokens = self
-                .weekly_opus_usage
-                .total_output_tokens
-                .saturating_add(output);
+            self.weekly_opus_usage.add(input, output);
             self.weekly_opus_usage.opus_input_tokens = self
                 .weekly_opus_usage
                 .opus_input_tokens
@@ -303,12 +522,7 @@ impl CookieStatus {
         }
 
         // lifetime bucket (total + per family)
-        self.lifetime_usage.total_input_tokens =
-            self.lifetime_usage.total_input_tokens.saturating_add(input);
-        self.lifetime_usage.total_output_tokens = self
-            .lifetime_usage
-            .total_output_tokens
-            .saturating_add(output);
+        self.lifetime_usage.add(input, output);
         match family {
             ModelFamily::Sonnet => {
                 self.lifetime_usage.sonnet_input_tokens = self
@@ -331,6 +545,16 @@ impl CookieStatus {
             ModelFamily::Other => {}
         }
     }
+
+    /// Records prompt cache hits and writes reported by the API
+    pub fn add_cache_usage(&mut self, read: u64, creation: u64) {
+        if read == 0 && creation == 0 {
+            return;
+        }
+        self.session_usage.add_cache(read, creation);
+        self.weekly_usage.add_cache(read, creation);
+        self.lifetime_usage.add_cache(read, creation);
+    }
 }
 
 impl Deref for ClewdrCookie {
@@ -416,6 +640,42 @@ mod tests {
         assert_eq!(cookie.inner.len(), 95);
     }
 
+    #[test]
+    fn org_selection() {
+        let org = |uuid: &str, name: &str| ClaudeOrg {
+            uuid: uuid.to_string(),
+            name: name.to_string(),
+            capabilities: vec!["chat".to_string()],
+        };
+        let orgs = [org("u1", "Personal"), org("u2", "Team")];
+        let pick = |r, p| ClaudeOrg::select(&orgs, r, p).map(|o| o.uuid.as_str());
+        assert_eq!(pick(Some("team"), Some("u1")), Some("u2"));
+        assert_eq!(pick(Some("u1"), Some("u2")), Some("u1"));
+        assert_eq!(pick(Some("unknown"), Some("u2")), Some("u2"));
+        assert_eq!(pick(None, Some("gone")), None);
+        assert_eq!(pick(None, None), None);
+    }
+
+    #[test]
+    fn capability_gating() {
+        let org = |uuid: &str, capability: &str| ClaudeOrg {
+            uuid: uuid.to_string(),
+            name: String::new(),
+            capabilities: vec!["chat".to_string(), capability.to_string()],
+        };
+        let mut cookie = CookieStatus::default();
+        assert!(cookie.has_capability(Capability::Opus));
+        cookie.organizations = vec![org("free", "claude_free"), org("paid", "claude_max")];
+        assert!(cookie.has_capability(Capability::Opus));
+        cookie.pinned_org = Some("free".to_string());
+        assert!(!cookie.has_capability(Capability::Opus));
+        assert_eq!(
+            Capability::for_model("claude-opus-4-1"),
+            Some(Capability::Opus)
+        );
+        assert_eq!(Capability::for_model("claude-sonnet-4-5"), None);
+    }
+
     #[test]
     fn test_invalid_cookie() {
         let result = ClewdrCookie::from_str("invalid-cookie");
@@ -0,0 +1,49 @@
+use http::HeaderValue;
+use serde::{Deserialize, Serialize};
+
+/// CORS policy of the API routes, lets browser based clients call the proxy directly
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API, e.g. `https://chat.example.com`,
+    /// any origin when empty or containing `*`
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Allow cookies and `Authorization` headers in cross origin requests,
+    /// a wildcard origin then echoes the request origin back
+    #[serde(default)]
+    pub allow_credentials: bool,
+    /// How long browsers may cache preflight responses, in seconds
+    #[serde(default)]
+    pub max_age: Option<u64>,
+}
+
+impl CorsConfig {
+    /// Whether any origin may call the API
+    pub fn any_origin(&self) -> bool {
+        self.allowed_origins.is_empty() || self.allowed_origins.iter().any(|o| o.trim() == "*")
+    }
+
+    /// Configured origins as header values, invalid entries are skipped
+    pub fn origins(&self) -> Vec<HeaderValue> {
+        self.allowed_origins
+            .iter()
+            .filter_map(|o| HeaderValue::from_str(o.trim().trim_end_matches('/')).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_and_listed_origins() {
+        assert!(CorsConfig::default().any_origin());
+        let cors: CorsConfig = serde_json::from_str(
+            r#"{ "allowed_origins": ["https://a.example/", "https://b.example"] }"#,
+        )
+        .unwrap();
+        assert!(!cors.any_origin());
+        assert_eq!(cors.origins(), ["https://a.example", "https://b.example"]);
+    }
+}
@@ -1,6 +1,7 @@
 use std::{fmt::Display, ops::Deref, sync::LazyLock};
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tracing::warn;
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
@@ -34,6 +35,45 @@ impl GeminiKey {
     }
 }
 
+/// Delay before the first probe of a quarantined key, in seconds
+const QUARANTINE_BACKOFF_BASE: i64 = 300;
+/// Longest delay between two probes of a quarantined key, in seconds
+const QUARANTINE_BACKOFF_MAX: i64 = 6 * 3600;
+
+/// Seconds until the next probe of a quarantined key, doubling with every failed probe
+pub fn quarantine_backoff(failed_probes: u32) -> i64 {
+    QUARANTINE_BACKOFF_BASE
+        .saturating_mul(1 << failed_probes.min(16))
+        .min(QUARANTINE_BACKOFF_MAX)
+}
+
+/// Why Gemini refused a submitted key
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyRejection {
+    /// The key is unknown, revoked or has the API disabled
+    Invalid,
+    /// The key works, but not from the region the requests leave from
+    RegionRestricted,
+}
+
+impl KeyRejection {
+    /// Classifies the error body of a failed Gemini call
+    pub fn classify(error: &Value) -> Self {
+        let error = error.get("error").unwrap_or(error);
+        let status = error.get("status").and_then(Value::as_str);
+        let message = error
+            .get("message")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if status == Some("FAILED_PRECONDITION") && message.contains("location is not supported") {
+            Self::RegionRestricted
+        } else {
+            Self::Invalid
+        }
+    }
+}
+
 impl Display for GeminiKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.inner)
@@ -71,6 +111,42 @@ pub struct KeyStatus {
     pub key: GeminiKey,
     #[serde(default)]
     pub count_403: u32,
+    /// Requests dispatched on `quota_day`
+    #[serde(default)]
+    pub count_requests: u64,
+    /// Tokens consumed on `quota_day`
+    #[serde(default)]
+    pub count_tokens: u64,
+    /// 429 responses received on `quota_day`
+    #[serde(default)]
+    pub count_429: u32,
+    /// UTC day (days since epoch) the daily counters belong to
+    #[serde(default)]
+    pub quota_day: i64,
+    /// Prompt tokens consumed over the lifetime of the key
+    #[serde(default)]
+    pub input_tokens: u64,
+    /// Output tokens generated over the lifetime of the key
+    #[serde(default)]
+    pub output_tokens: u64,
+    /// Requests dispatched over the lifetime of the key
+    #[serde(default)]
+    pub total_requests: u64,
+    /// 429 responses received over the lifetime of the key
+    #[serde(default)]
+    pub total_429: u32,
+    /// Paused by an operator, kept in the pool but never dispatched
+    #[serde(default)]
+    pub disabled: bool,
+    /// 403 and 429 responses in a row, reset by a successful request
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    /// Epoch seconds of the next probe while the key is quarantined, not dispatched until then
+    #[serde(default)]
+    pub quarantined_until: Option<i64>,
+    /// Probes failed since the key was quarantined
+    #[serde(default)]
+    pub failed_probes: u32,
 }
 
 impl PartialEq for KeyStatus {
@@ -85,8 +161,162 @@ impl std::hash::Hash for KeyStatus {
     }
 }
 
+impl From<GeminiKey> for KeyStatus {
+    fn from(key: GeminiKey) -> Self {
+        Self {
+            key,
+            count_403: 0,
+            count_requests: 0,
+            count_tokens: 0,
+            count_429: 0,
+            quota_day: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            total_requests: 0,
+            total_429: 0,
+            disabled: false,
+            consecutive_failures: 0,
+            quarantined_until: None,
+            failed_probes: 0,
+        }
+    }
+}
+
 impl KeyStatus {
     pub fn validate(&self) -> bool {
         self.key.validate()
     }
+
+    /// Current UTC day number
+    pub fn today() -> i64 {
+        chrono::Utc::now().timestamp().div_euclid(86400)
+    }
+
+    /// Resets the daily counters if the UTC day has rolled over
+    ///
+    /// # Returns
+    /// * `bool` - Whether the counters were reset
+    pub fn rollover(&mut self) -> bool {
+        let today = Self::today();
+        if self.quota_day == today {
+            return false;
+        }
+        self.quota_day = today;
+        self.count_requests = 0;
+        self.count_tokens = 0;
+        self.count_429 = 0;
+        true
+    }
+
+    /// Counts a 403 or 429, quarantining the key once `threshold` of them came in a row
+    ///
+    /// # Returns
+    /// * `true` - The key was just quarantined
+    pub fn record_failure(&mut self, threshold: u32) -> bool {
+        self.consecutive_failures += 1;
+        if threshold == 0 || self.consecutive_failures < threshold || self.is_quarantined() {
+            return false;
+        }
+        self.failed_probes = 0;
+        self.quarantined_until =
+            Some(chrono::Utc::now().timestamp() + quarantine_backoff(self.failed_probes));
+        true
+    }
+
+    /// Counts a successful request or probe, lifting any quarantine
+    pub fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.quarantined_until = None;
+        self.failed_probes = 0;
+    }
+
+    /// Counts a failed probe of a quarantined key and schedules the next one
+    ///
+    /// # Returns
+    /// * `true` - `invalid_after` probes failed, the key should be dropped
+    pub fn record_failed_probe(&mut self, invalid_after: u32) -> bool {
+        self.failed_probes += 1;
+        if invalid_after > 0 && self.failed_probes >= invalid_after {
+            return true;
+        }
+        self.quarantined_until =
+            Some(chrono::Utc::now().timestamp() + quarantine_backoff(self.failed_probes));
+        false
+    }
+
+    /// Whether the key is held out of dispatch after repeated failures
+    pub fn is_quarantined(&self) -> bool {
+        self.quarantined_until.is_some()
+    }
+
+    /// Whether a quarantined key is due for a probe
+    pub fn probe_due(&self, now: i64) -> bool {
+        self.quarantined_until.is_some_and(|t| t <= now)
+    }
+
+    /// Checks the daily counters against the given quotas, 0 means unlimited
+    pub fn exceeded(&self, request_quota: u64, token_quota: u64) -> bool {
+        (request_quota > 0 && self.count_requests >= request_quota)
+            || (token_quota > 0 && self.count_tokens >= token_quota)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rollover_resets_daily_counters() {
+        let mut key = KeyStatus::from(GeminiKey::from("AIzaSy_test"));
+        key.count_403 = 2;
+        key.count_requests = 10;
+        key.count_tokens = 1000;
+        key.count_429 = 1;
+        key.quota_day = KeyStatus::today() - 1;
+        assert!(key.exceeded(10, 0));
+        assert!(key.rollover());
+        assert!(!key.exceeded(10, 1000));
+        assert_eq!(key.count_403, 2);
+        assert_eq!(key.count_requests, 0);
+        assert!(!key.rollover());
+    }
+
+    #[test]
+    fn quarantine_and_invalidation() {
+        let mut key = KeyStatus::from(GeminiKey::from("AIzaSy_test"));
+        assert!(!key.record_failure(3));
+        key.record_success();
+        assert!(!key.record_failure(3));
+        assert!(!key.record_failure(3));
+        assert!(key.record_failure(3));
+        assert!(key.is_quarantined());
+        assert!(!key.record_failure(3));
+        assert!(!key.record_failed_probe(2));
+        assert!(key.record_failed_probe(2));
+        key.record_success();
+        assert!(!key.is_quarantined());
+        assert_eq!(key.consecutive_failures, 0);
+        assert_eq!(quarantine_backoff(0), 300);
+        assert_eq!(quarantine_backoff(1), 600);
+        assert_eq!(quarantine_backoff(30), 6 * 3600);
+    }
+
+    #[test]
+    fn classifies_key_rejections() {
+        let region = serde_json::json!({ "error": {
+            "code": 400,
+            "message": "User location is not supported for the API use.",
+            "status": "FAILED_PRECONDITION",
+        }});
+        let invalid = serde_json::json!({ "error": {
+            "code": 400,
+            "message": "API key not valid. Please pass a valid API key.",
+            "status": "INVALID_ARGUMENT",
+        }});
+        assert_eq!(
+            KeyRejection::classify(&region),
+            KeyRejection::RegionRestricted
+        );
+        assert_eq!(KeyRejection::classify(&invalid), KeyRejection::Invalid);
+    }
 }
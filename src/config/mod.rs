@@ -1,14 +1,50 @@
 // Re-export all items from submodules
+mod access;
+mod backup;
+mod bedrock;
+mod breaker;
 mod clewdr_config;
 mod constants;
 mod cookie;
+mod cors;
 mod key;
+mod model_limits;
+mod notify;
+mod openai_upstream;
+mod provenance;
 mod reason;
+mod routing;
+mod rules;
+mod safety;
+mod scrub;
+mod secrets;
+mod template;
+mod tenant;
+mod timeout;
 mod token;
+mod webhook;
 
+pub use access::*;
+pub use backup::*;
+pub use bedrock::*;
+pub use breaker::*;
 pub use clewdr_config::*;
 pub use constants::*;
 pub use cookie::*;
+pub use cors::*;
 pub use key::*;
+pub use model_limits::*;
+pub use notify::*;
+pub use openai_upstream::*;
+pub use provenance::*;
 pub use reason::*;
+pub use routing::*;
+pub use rules::*;
+pub use safety::*;
+pub use scrub::*;
+pub use secrets::*;
+pub use template::*;
+pub use tenant::*;
+pub use timeout::*;
 pub use token::*;
+pub use webhook::*;
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::Pattern;
+
+/// Token limits of the models matching a pattern
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModelLimit {
+    /// Most output tokens a request gets, a larger `max_tokens` is lowered to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Most estimated prompt tokens a request may send, larger requests are rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_input_tokens: Option<u32>,
+}
+
+fn lowest(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Model pattern to token limits, written as a TOML table keyed by the pattern
+#[derive(Debug, Clone, Default)]
+pub struct ModelLimits(pub Vec<(Pattern, ModelLimit)>);
+
+impl ModelLimits {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Limits of `model`, the lowest of every pattern it matches
+    pub fn for_model(&self, model: &str) -> ModelLimit {
+        self.0.iter().filter(|(p, _)| p.0.is_match(model)).fold(
+            ModelLimit::default(),
+            |acc, (_, l)| ModelLimit {
+                max_tokens: lowest(acc.max_tokens, l.max_tokens),
+                max_input_tokens: lowest(acc.max_input_tokens, l.max_input_tokens),
+            },
+        )
+    }
+}
+
+impl Serialize for ModelLimits {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.iter().map(|(p, l)| (p.0.as_str(), l)))
+    }
+}
+
+impl<'de> Deserialize<'de> for ModelLimits {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        BTreeMap::<String, ModelLimit>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(pattern, limit)| {
+                Regex::new(&pattern)
+                    .map(|r| (Pattern(r), limit))
+                    .map_err(serde::de::Error::custom)
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lowest_matching_limit_applies() {
+        let limits: ModelLimits = toml::from_str::<toml::Table>(
+            r#"
+            [limits."opus"]
+            max_tokens = 8192
+            max_input_tokens = 150000
+
+            [limits."^claude-opus-4"]
+            max_tokens = 32000
+            "#,
+        )
+        .unwrap()["limits"]
+            .clone()
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            limits.for_model("claude-opus-4-1"),
+            ModelLimit {
+                max_tokens: Some(8192),
+                max_input_tokens: Some(150000),
+            }
+        );
+        assert_eq!(limits.for_model("gemini-2.5-pro"), ModelLimit::default());
+        let table = toml::Table::try_from(&limits).unwrap();
+        assert_eq!(
+            table["^claude-opus-4"]["max_tokens"].as_integer(),
+            Some(32000)
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+use super::default_notify_interval;
+
+/// Discord bot posting alerts to a channel
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DiscordNotifier {
+    pub bot_token: String,
+    pub channel_id: String,
+}
+
+/// Telegram bot posting alerts to a chat
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TelegramNotifier {
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+/// Chat alerts of critical events, can hot reload
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotifyConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discord: Option<DiscordNotifier>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub telegram: Option<TelegramNotifier>,
+    /// Seconds alerts are gathered for before they are sent as one message
+    #[serde(default = "default_notify_interval")]
+    pub interval: u64,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            discord: None,
+            telegram: None,
+            interval: default_notify_interval(),
+        }
+    }
+}
+
+impl NotifyConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.discord.is_some() || self.telegram.is_some()
+    }
+}
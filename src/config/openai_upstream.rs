@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::KeyStatus;
+
+/// OpenAI compatible upstream, such as Azure OpenAI or a self-hosted vLLM
+///
+/// Requests for a `{name}/{model}` model, or sent to `/openai/{name}/v1/...`, are passed
+/// through with a key of its pool. The pool is kept here, next to the config, and rotated,
+/// quarantined and counted like the Gemini keys. An upstream without keys is called without
+/// credentials.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct OpenAiUpstream {
+    /// Name in the model prefix and path, letters, digits, `-` and `_`
+    pub name: String,
+    /// URL the OpenAI paths are appended to, e.g. `http://vllm:8000/v1/` or
+    /// `https://{resource}.openai.azure.com/openai/v1/`
+    pub base_url: String,
+    /// Sends keys in the `api-key` header as Azure OpenAI expects, instead of as a bearer token
+    #[serde(default)]
+    pub azure: bool,
+    /// `api-version` query parameter added to every request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<String>,
+    /// Models listed by `/v1/models`, with the name as prefix
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+    #[serde(default)]
+    pub keys: HashSet<KeyStatus>,
+    /// Keys dropped after failing every probe while quarantined
+    #[serde(default)]
+    pub invalid_keys: HashSet<KeyStatus>,
+}
+
+impl OpenAiUpstream {
+    pub fn has_valid_name(&self) -> bool {
+        !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    /// Whether requests are sent without credentials
+    pub fn is_keyless(&self) -> bool {
+        self.keys.is_empty() && self.invalid_keys.is_empty()
+    }
+
+    /// URL of an OpenAI path relative to the API root, e.g. `chat/completions`
+    pub fn url(&self, path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.base_url.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+
+    /// Model as the upstream names it, without the `{name}/` prefix
+    pub fn upstream_model<'a>(&self, model: &'a str) -> &'a str {
+        model
+            .strip_prefix(self.name.as_str())
+            .and_then(|m| m.strip_prefix('/'))
+            .unwrap_or(model)
+    }
+
+    /// Takes the pool of the upstream with the same name in `old`, the config page does not
+    /// manage it
+    pub fn keep_pools(&mut self, old: &[OpenAiUpstream]) {
+        let Some(old) = old.iter().find(|u| u.name == self.name) else {
+            return;
+        };
+        self.keys = old.keys.to_owned();
+        self.invalid_keys = old.invalid_keys.to_owned();
+    }
+}
+
+/// OpenAI API paths passed through to upstreams, relative to the API root
+const UPSTREAM_API_PATHS: [&str; 3] = ["chat/completions", "completions", "embeddings"];
+
+/// The passed through API path matching `path`, which may carry surrounding slashes
+///
+/// Only exact matches are accepted, so a decoded `..` can never leave `base_url` and spend the
+/// pooled keys on other endpoints of the upstream host.
+///
+/// # Returns
+/// * `None` - The path is not passed through
+pub fn upstream_api_path(path: &str) -> Option<&'static str> {
+    let path = path.trim_matches('/');
+    UPSTREAM_API_PATHS.into_iter().find(|p| *p == path)
+}
+
+/// Path serving an OpenAI API path on an upstream, e.g. `/v1/chat/completions` on `vllm` is
+/// served at `/openai/vllm/v1/chat/completions`
+///
+/// # Returns
+/// * `None` - The path is not a chat completions path
+pub fn upstream_path(name: &str, path: &str) -> Option<String> {
+    path.ends_with("/chat/completions")
+        .then(|| format!("/openai/{name}/v1/chat/completions"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upstream_urls_and_models() {
+        let upstream: OpenAiUpstream = toml::from_str(
+            "name = \"vllm\"\nbase_url = \"http://vllm:8000/v1/\"\n[[keys]]\nkey = \"sk-a\"\n",
+        )
+        .unwrap();
+        assert!(upstream.has_valid_name());
+        assert!(!upstream.is_keyless());
+        assert_eq!(
+            upstream.url("chat/completions"),
+            "http://vllm:8000/v1/chat/completions"
+        );
+        assert_eq!(upstream.upstream_model("vllm/llama-3-70b"), "llama-3-70b");
+        assert_eq!(upstream.upstream_model("llama-3-70b"), "llama-3-70b");
+        assert_eq!(
+            upstream_path("vllm", "/code/v1/chat/completions").as_deref(),
+            Some("/openai/vllm/v1/chat/completions")
+        );
+        assert_eq!(upstream_path("vllm", "/v1/messages"), None);
+        assert_eq!(upstream_api_path("/embeddings"), Some("embeddings"));
+        assert_eq!(upstream_api_path("chat/completions/../../admin"), None);
+        assert_eq!(upstream_api_path("../models"), None);
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use super::Timeouts;
+
+/// Regular expression read from and written to the config as a string
+#[derive(Debug, Clone)]
+pub struct Pattern(pub Regex);
+
+impl Serialize for Pattern {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Provider a routing rule can send a request to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteProvider {
+    ClaudeWeb,
+    ClaudeCode,
+    Gemini,
+    Vertex,
+    /// Claude models hosted by Vertex AI
+    VertexClaude,
+    /// Claude models hosted by AWS Bedrock
+    Bedrock,
+}
+
+/// Paths serving the same API on each provider
+const ROUTE_PATHS: [&[(RouteProvider, &str)]; 2] = [
+    &[
+        (RouteProvider::ClaudeWeb, "/v1/messages"),
+        (RouteProvider::ClaudeCode, "/code/v1/messages"),
+        (RouteProvider::VertexClaude, "/vertex/v1/messages"),
+        (RouteProvider::Bedrock, "/bedrock/v1/messages"),
+    ],
+    &[
+        (RouteProvider::ClaudeWeb, "/v1/chat/completions"),
+        (RouteProvider::ClaudeCode, "/code/v1/chat/completions"),
+        (RouteProvider::Gemini, "/gemini/chat/completions"),
+        (RouteProvider::Vertex, "/gemini/vertex/chat/completions"),
+    ],
+];
+
+impl RouteProvider {
+    /// Path serving the API of `path` on this provider
+    ///
+    /// # Returns
+    /// * `None` - The provider does not serve this API
+    pub fn path_for(self, path: &str) -> Option<String> {
+        if let Some(paths) = ROUTE_PATHS
+            .iter()
+            .find(|paths| paths.iter().any(|(_, p)| *p == path))
+        {
+            return paths
+                .iter()
+                .find(|(provider, _)| *provider == self)
+                .map(|(_, p)| p.to_string());
+        }
+        // native Gemini API, the rest of the path names the model and method
+        let rest = path
+            .strip_prefix("/v1/vertex/v1beta/")
+            .or_else(|| path.strip_prefix("/v1/v1beta/"))?;
+        match self {
+            Self::Gemini => Some(format!("/v1/v1beta/{rest}")),
+            Self::Vertex => Some(format!("/v1/vertex/v1beta/{rest}")),
+            Self::ClaudeWeb | Self::ClaudeCode | Self::VertexClaude | Self::Bedrock => None,
+        }
+    }
+}
+
+/// Conditions of a routing rule, every one that is set must match
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RouteMatch {
+    /// Matched against the request path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<Pattern>,
+    /// Matched against the requested model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<Pattern>,
+    /// User key the request authenticated with, compared exactly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Header name to pattern, a missing header does not match
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, Pattern>,
+    /// JSON pointer into the body, e.g. `/stream`, to pattern, a missing field does not match
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub body: BTreeMap<String, Pattern>,
+}
+
+/// Request as seen by the routing rules
+#[derive(Debug, Default)]
+pub struct RouteRequest<'a> {
+    pub path: &'a str,
+    pub model: Option<&'a str>,
+    pub key: Option<&'a str>,
+    pub headers: Vec<(&'a str, &'a str)>,
+    pub body: Option<&'a Value>,
+}
+
+impl RouteMatch {
+    fn matches(&self, req: &RouteRequest) -> bool {
+        let field = |pointer: &str| {
+            req.body.and_then(|b| b.pointer(pointer)).map(|v| match v {
+                Value::String(s) => s.to_owned(),
+                v => v.to_string(),
+            })
+        };
+        self.path.as_ref().is_none_or(|p| p.0.is_match(req.path))
+            && self
+                .model
+                .as_ref()
+                .is_none_or(|p| req.model.is_some_and(|m| p.0.is_match(m)))
+            && self
+                .key
+                .as_ref()
+                .is_none_or(|k| req.key == Some(k.as_str()))
+            && self.headers.iter().all(|(name, p)| {
+                req.headers
+                    .iter()
+                    .any(|(n, v)| n.eq_ignore_ascii_case(name) && p.0.is_match(v))
+            })
+            && self
+                .body
+                .iter()
+                .all(|(pointer, p)| field(pointer).is_some_and(|v| p.0.is_match(&v)))
+    }
+}
+
+/// Rule choosing where a request goes
+///
+/// Rules are checked in order and the first one that matches applies, later ones are skipped.
+/// A rule without actions lets matching requests through unchanged.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RoutingRule {
+    /// Name reported in logs and rejections, the rule index is used when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, rename = "match")]
+    pub when: RouteMatch,
+    /// Sends the request to the same API of another provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<RouteProvider>,
+    /// Replaces the requested model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Proxy for the upstream calls of the request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Timeouts for the upstream calls of the request, unset phases keep the configured ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeouts: Option<Timeouts>,
+    /// Rejects the request with this message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reject: Option<String>,
+}
+
+/// First rule matching `req`, with its index
+pub fn match_route<'a>(
+    rules: &'a [RoutingRule],
+    req: &RouteRequest,
+) -> Option<(usize, &'a RoutingRule)> {
+    rules.iter().enumerate().find(|(_, r)| r.when.matches(req))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules: Vec<RoutingRule> = toml::from_str::<toml::Table>(
+            r#"
+            [[rules]]
+            match = { key = "team-a", model = "sonnet" }
+
+            [[rules]]
+            name = "team-a sonnet only"
+            match = { key = "team-a" }
+            reject = "only sonnet models"
+
+            [[rules]]
+            match = { path = "^/v1/messages$", headers = { x-agent = "^cli" }, body = { "/stream" = "^true$" } }
+            provider = "claude_code"
+            "#,
+        )
+        .unwrap()["rules"]
+            .clone()
+            .try_into()
+            .unwrap();
+        let body = json!({ "model": "claude-opus-4-1", "stream": true });
+        let mut req = RouteRequest {
+            path: "/v1/messages",
+            model: Some("claude-sonnet-4-5"),
+            key: Some("team-a"),
+            body: Some(&body),
+            ..Default::default()
+        };
+        assert_eq!(match_route(&rules, &req).map(|(i, _)| i), Some(0));
+        req.model = Some("claude-opus-4-1");
+        assert_eq!(match_route(&rules, &req).map(|(i, _)| i), Some(1));
+        req.key = None;
+        assert!(match_route(&rules, &req).is_none());
+        req.headers = vec![("X-Agent", "cli/1.0")];
+        let (_, rule) = match_route(&rules, &req).unwrap();
+        assert_eq!(
+            rule.provider.and_then(|p| p.path_for(req.path)).as_deref(),
+            Some("/code/v1/messages")
+        );
+
+        assert_eq!(
+            RouteProvider::Vertex
+                .path_for("/v1/v1beta/models/gemini-2.5-pro:generateContent")
+                .as_deref(),
+            Some("/v1/vertex/v1beta/models/gemini-2.5-pro:generateContent")
+        );
+        assert_eq!(RouteProvider::Gemini.path_for("/v1/messages"), None);
+        assert_eq!(
+            RouteProvider::VertexClaude
+                .path_for("/code/v1/messages")
+                .as_deref(),
+            Some("/vertex/v1/messages")
+        );
+        assert_eq!(
+            RouteProvider::Bedrock.path_for("/v1/messages").as_deref(),
+            Some("/bedrock/v1/messages")
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Rule applied to generated text before it is returned to the client
+///
+/// Rules run in the order they are configured, each one sees the output of the previous one.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ResponseRule {
+    /// Name reported in the rule metrics, the pattern is used when unset
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Regular expression matched against the generated text
+    #[serde(
+        serialize_with = "serialize_regex",
+        deserialize_with = "deserialize_regex"
+    )]
+    pub pattern: Regex,
+    /// Replacement for every match, `$1` and `${name}` refer to capture groups
+    #[serde(default)]
+    pub replace: String,
+    /// Block the whole response when the pattern matches instead of rewriting it
+    #[serde(default)]
+    pub deny: bool,
+}
+
+fn serialize_regex<S>(re: &Regex, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(re.as_str())
+}
+
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+impl ResponseRule {
+    /// Name of the rule in metrics and errors
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.pattern.as_str())
+    }
+}
+
+/// Outcome of running the rules over a piece of text
+#[derive(Debug, PartialEq, Eq)]
+pub enum RuleOutcome<'a> {
+    /// No rule matched
+    Unchanged,
+    /// Replace rules matched, the text after all of them
+    Rewritten(String),
+    /// A deny rule matched, carries its name
+    Denied(&'a str),
+}
+
+/// Runs the rules over `text` in order
+///
+/// # Arguments
+/// * `rules` - Configured rules
+/// * `text` - Generated text
+/// * `on_match` - Called with the index of every rule that matched
+pub fn run_rules<'a>(
+    rules: &'a [ResponseRule],
+    text: &str,
+    mut on_match: impl FnMut(usize),
+) -> RuleOutcome<'a> {
+    let mut rewritten: Option<String> = None;
+    for (i, rule) in rules.iter().enumerate() {
+        let current = rewritten.as_deref().unwrap_or(text);
+        if !rule.pattern.is_match(current) {
+            continue;
+        }
+        on_match(i);
+        if rule.deny {
+            return RuleOutcome::Denied(rule.name());
+        }
+        let replaced = rule
+            .pattern
+            .replace_all(current, rule.replace.as_str())
+            .into_owned();
+        rewritten = Some(replaced);
+    }
+    match rewritten {
+        Some(text) => RuleOutcome::Rewritten(text),
+        None => RuleOutcome::Unchanged,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_run_in_order() {
+        let rules: Vec<ResponseRule> = serde_json::from_str(
+            r#"[
+                { "pattern": "\\*\\*(\\w+)\\*\\*", "replace": "$1" },
+                { "name": "leak", "pattern": "SECRET-\\d+", "deny": true }
+            ]"#,
+        )
+        .unwrap();
+        let mut hits = vec![];
+        assert_eq!(
+            run_rules(&rules, "a **bold** word", |i| hits.push(i)),
+            RuleOutcome::Rewritten("a bold word".into())
+        );
+        assert_eq!(
+            run_rules(&rules, "plain", |i| hits.push(i)),
+            RuleOutcome::Unchanged
+        );
+        assert_eq!(
+            run_rules(&rules, "**x** SECRET-42", |i| hits.push(i)),
+            RuleOutcome::Denied("leak")
+        );
+        assert_eq!(hits, [0, 0, 1]);
+        assert!(serde_json::from_str::<ResponseRule>(r#"{ "pattern": "(" }"#).is_err());
+    }
+}
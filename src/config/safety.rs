@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::default_safety_threshold;
+
+/// Harm categories Gemini accepts in `safetySettings`
+pub const HARM_CATEGORIES: [&str; 5] = [
+    "HARM_CATEGORY_HARASSMENT",
+    "HARM_CATEGORY_HATE_SPEECH",
+    "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+    "HARM_CATEGORY_DANGEROUS_CONTENT",
+    "HARM_CATEGORY_CIVIC_INTEGRITY",
+];
+
+/// Header overriding the safety settings of one request, a threshold such as `BLOCK_NONE`
+/// applied to every category, or `client` to forward the client's settings untouched
+pub const SAFETY_HEADER: &str = "x-clewdr-safety";
+
+/// When the configured safety settings replace the client's
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyPolicy {
+    /// Only requests without `safetySettings` get the configured ones
+    #[default]
+    Fill,
+    /// The configured settings always replace the client's
+    Force,
+    /// Requests are forwarded with the client's settings, if any
+    Off,
+}
+
+/// Default `safetySettings` injected into Gemini requests
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GeminiSafetyConfig {
+    #[serde(default)]
+    pub policy: SafetyPolicy,
+    /// Threshold of every harm category, e.g. `OFF` or `BLOCK_NONE`
+    #[serde(default = "default_safety_threshold")]
+    pub threshold: String,
+    /// Thresholds of single categories, overriding `threshold`
+    #[serde(default)]
+    pub categories: BTreeMap<String, String>,
+}
+
+impl Default for GeminiSafetyConfig {
+    fn default() -> Self {
+        Self {
+            policy: SafetyPolicy::default(),
+            threshold: default_safety_threshold(),
+            categories: BTreeMap::new(),
+        }
+    }
+}
+
+impl GeminiSafetyConfig {
+    /// The configured settings, in the `safetySettings` array format
+    pub fn settings(&self) -> Value {
+        let categories = HARM_CATEGORIES
+            .iter()
+            .map(|c| c.to_string())
+            .chain(
+                self.categories
+                    .keys()
+                    .filter(|c| !HARM_CATEGORIES.contains(&c.as_str()))
+                    .cloned(),
+            )
+            .map(|category| {
+                let threshold = self
+                    .categories
+                    .get(&category)
+                    .map(String::as_str)
+                    .unwrap_or(&self.threshold);
+                json!({ "category": category, "threshold": threshold })
+            })
+            .collect::<Vec<_>>();
+        json!(categories)
+    }
+
+    /// Applies the policy to the safety settings of a request
+    ///
+    /// # Arguments
+    /// * `current` - Safety settings sent by the client
+    /// * `header` - Value of the `x-clewdr-safety` header, if any
+    pub fn apply(&self, current: &mut Option<Value>, header: Option<&str>) {
+        let header = header.map(str::trim).filter(|h| !h.is_empty());
+        match header {
+            Some(h) if h.eq_ignore_ascii_case("client") => {}
+            Some(h) => {
+                let threshold = h.to_ascii_uppercase();
+                *current = Some(json!(
+                    HARM_CATEGORIES
+                        .iter()
+                        .map(|c| json!({ "category": c, "threshold": threshold }))
+                        .collect::<Vec<_>>()
+                ));
+            }
+            None => match self.policy {
+                SafetyPolicy::Off => {}
+                SafetyPolicy::Fill if current.as_ref().is_some_and(|v| !v.is_null()) => {}
+                SafetyPolicy::Fill | SafetyPolicy::Force => *current = Some(self.settings()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_and_header_decide_settings() {
+        let mut config = GeminiSafetyConfig::default();
+        config
+            .categories
+            .insert("HARM_CATEGORY_CIVIC_INTEGRITY".into(), "BLOCK_NONE".into());
+
+        let mut missing = None;
+        config.apply(&mut missing, None);
+        let settings = missing.unwrap();
+        assert_eq!(settings[0]["threshold"], "OFF");
+        assert_eq!(settings[4]["threshold"], "BLOCK_NONE");
+
+        let client =
+            json!([{ "category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_LOW_AND_ABOVE" }]);
+        let mut kept = Some(client.clone());
+        config.apply(&mut kept, None);
+        assert_eq!(kept, Some(client.clone()));
+
+        config.policy = SafetyPolicy::Force;
+        let mut forced = Some(client.clone());
+        config.apply(&mut forced, None);
+        assert_eq!(forced.unwrap().as_array().unwrap().len(), 5);
+
+        let mut passthrough = Some(client.clone());
+        config.apply(&mut passthrough, Some("client"));
+        assert_eq!(passthrough, Some(client));
+
+        let mut header = None;
+        config.apply(&mut header, Some("block_only_high"));
+        assert_eq!(header.unwrap()[4]["threshold"], "BLOCK_ONLY_HIGH");
+    }
+}
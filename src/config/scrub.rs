@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+use super::{default_max_data_url_size, default_max_nested_system};
+
+/// What to do with a suspicious field of an OpenAI request
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrubPolicy {
+    /// Pass the field through
+    #[default]
+    Off,
+    /// Fix the field and carry on
+    Fix,
+    /// Answer the request with 400
+    Reject,
+}
+
+/// Scrubbing of client metadata fields in OpenAI requests, can hot reload
+///
+/// Guards the shared pools against presets whose messages make the upstream answer 400.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScrubConfig {
+    /// Message `name` fields with control characters, fixed by removing the characters
+    #[serde(default)]
+    pub names: ScrubPolicy,
+    /// Data URLs longer than `max_data_url_size`, fixed by replacing the part with a note
+    #[serde(default)]
+    pub data_urls: ScrubPolicy,
+    /// Largest data URL in KiB
+    #[serde(default = "default_max_data_url_size")]
+    pub max_data_url_size: usize,
+    /// System messages after the first other message beyond `max_nested_system`, fixed by
+    /// turning them into user messages
+    #[serde(default)]
+    pub nested_system: ScrubPolicy,
+    #[serde(default = "default_max_nested_system")]
+    pub max_nested_system: usize,
+}
+
+impl Default for ScrubConfig {
+    fn default() -> Self {
+        Self {
+            names: ScrubPolicy::Off,
+            data_urls: ScrubPolicy::Off,
+            max_data_url_size: default_max_data_url_size(),
+            nested_system: ScrubPolicy::Off,
+            max_nested_system: default_max_nested_system(),
+        }
+    }
+}
+
+impl ScrubConfig {
+    pub fn is_enabled(&self) -> bool {
+        [self.names, self.data_urls, self.nested_system]
+            .iter()
+            .any(|p| *p != ScrubPolicy::Off)
+    }
+}
@@ -0,0 +1,283 @@
+use std::sync::LazyLock;
+
+use aws_lc_rs::{
+    aead::{AES_256_GCM_SIV, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    digest, hmac,
+};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use toml::Value;
+use wreq::Url;
+
+use crate::{config::ClewdrConfig, error::ClewdrError};
+
+/// Environment variable holding the master key, secrets are stored in plaintext without it
+pub const MASTER_KEY_ENV: &str = "CLEWDR_MASTER_KEY";
+/// Marks an encrypted value, followed by base64 of nonce and ciphertext
+const SECRET_PREFIX: &str = "enc:v1:";
+/// Secret strings in the TOML form of the config, `*` walks every array item
+const SECRET_PATHS: [&[&str]; 23] = [
+    &["cookie_array", "*", "cookie"],
+    &["cookie_array", "*", "token", "access_token"],
+    &["cookie_array", "*", "token", "refresh_token"],
+    &["wasted_cookie", "*", "cookie"],
+    &["gemini_keys", "*", "key"],
+    &["invalid_keys", "*", "key"],
+    &["vertex", "credential", "private_key"],
+    &["vertex", "credentials", "*", "private_key"],
+    &["bedrock", "secret_access_key"],
+    &["bedrock", "session_token"],
+    &["openai_upstreams", "*", "keys", "*", "key"],
+    &["openai_upstreams", "*", "invalid_keys", "*", "key"],
+    &["backup", "object_store", "secret_access_key"],
+    &["persistence", "object_store", "secret_access_key"],
+    &["webhooks", "*", "secret"],
+    &["notify", "discord", "bot_token"],
+    &["notify", "telegram", "bot_token"],
+    &["tenants", "*", "cookie_array", "*", "cookie"],
+    &["tenants", "*", "cookie_array", "*", "token", "access_token"],
+    &[
+        "tenants",
+        "*",
+        "cookie_array",
+        "*",
+        "token",
+        "refresh_token",
+    ],
+    &["tenants", "*", "wasted_cookie", "*", "cookie"],
+    &["tenants", "*", "gemini_keys", "*", "key"],
+    &["tenants", "*", "invalid_keys", "*", "key"],
+];
+/// Passwords and the user keys routing rules match, kept in plaintext at rest but hidden when
+/// the config is shown
+const PASSWORD_PATHS: [&[&str]; 5] = [
+    &["password"],
+    &["admin_password"],
+    &["viewer_password"],
+    &["tenants", "*", "passwords", "*"],
+    &["routing_rules", "*", "match", "key"],
+];
+/// Shown in place of a redacted secret
+const REDACTED: &str = "********";
+
+struct SecretKeys {
+    aead: LessSafeKey,
+    nonce: hmac::Key,
+}
+
+impl SecretKeys {
+    fn derive(master: &str) -> Self {
+        let derive = |label: &str| {
+            let mut ctx = digest::Context::new(&digest::SHA256);
+            ctx.update(label.as_bytes());
+            ctx.update(master.as_bytes());
+            ctx.finish()
+        };
+        let key = UnboundKey::new(&AES_256_GCM_SIV, derive("clewdr-aead").as_ref())
+            .expect("SHA-256 digest is a valid AES-256 key");
+        Self {
+            aead: LessSafeKey::new(key),
+            nonce: hmac::Key::new(hmac::HMAC_SHA256, derive("clewdr-nonce").as_ref()),
+        }
+    }
+
+    /// Encrypts deterministically, so an encrypted cookie or key can still identify a DB row
+    fn encrypt(&self, plain: &str) -> String {
+        let tag = hmac::sign(&self.nonce, plain.as_bytes());
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&tag.as_ref()[..NONCE_LEN]);
+        let mut data = plain.as_bytes().to_vec();
+        self.aead
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+            .expect("AES-GCM-SIV accepts any plaintext length used here");
+        let mut out = nonce.to_vec();
+        out.extend(data);
+        format!("{SECRET_PREFIX}{}", BASE64_URL_SAFE_NO_PAD.encode(out))
+    }
+
+    fn decrypt(&self, sealed: &str) -> Option<String> {
+        let data = BASE64_URL_SAFE_NO_PAD.decode(sealed).ok()?;
+        if data.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        let mut buf = ciphertext.to_vec();
+        let plain = self
+            .aead
+            .open_in_place(nonce, Aad::empty(), &mut buf)
+            .ok()?;
+        String::from_utf8(plain.to_vec()).ok()
+    }
+}
+
+static KEYS: LazyLock<Option<SecretKeys>> = LazyLock::new(|| {
+    let master = std::env::var(MASTER_KEY_ENV).ok()?;
+    let master = master.trim();
+    (!master.is_empty()).then(|| SecretKeys::derive(master))
+});
+
+fn secret_error(message: &str) -> ClewdrError {
+    ClewdrError::Whatever {
+        message: message.to_string(),
+        source: None,
+    }
+}
+
+/// Whether secrets are encrypted at rest, that is `CLEWDR_MASTER_KEY` is set
+pub fn secrets_encrypted() -> bool {
+    KEYS.is_some()
+}
+
+/// Encrypts a secret for storage, returned as is without a master key
+pub fn encrypt_secret(plain: &str) -> String {
+    match KEYS.as_ref() {
+        Some(keys) if !plain.starts_with(SECRET_PREFIX) => keys.encrypt(plain),
+        _ => plain.to_string(),
+    }
+}
+
+/// Decrypts a stored secret, plaintext written before encryption was enabled passes through
+pub fn decrypt_secret(stored: &str) -> Result<String, ClewdrError> {
+    let Some(sealed) = stored.strip_prefix(SECRET_PREFIX) else {
+        return Ok(stored.to_string());
+    };
+    let keys = KEYS.as_ref().ok_or_else(|| {
+        secret_error("Stored secrets are encrypted but CLEWDR_MASTER_KEY is not set")
+    })?;
+    keys.decrypt(sealed)
+        .ok_or_else(|| secret_error("Failed to decrypt a stored secret, wrong CLEWDR_MASTER_KEY?"))
+}
+
+/// Applies `f` to every string at `path` below `value`
+fn visit(
+    value: &mut Value,
+    path: &[&str],
+    f: &mut impl FnMut(&mut String) -> Result<(), ClewdrError>,
+) -> Result<(), ClewdrError> {
+    let Some((first, rest)) = path.split_first() else {
+        return match value {
+            Value::String(s) => f(s),
+            _ => Ok(()),
+        };
+    };
+    match (*first, value) {
+        ("*", Value::Array(items)) => items.iter_mut().try_for_each(|v| visit(v, rest, f)),
+        (key, Value::Table(table)) => match table.get_mut(key) {
+            Some(v) => visit(v, rest, f),
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+fn visit_secrets(
+    table: &mut toml::Table,
+    mut f: impl FnMut(&mut String) -> Result<(), ClewdrError>,
+) -> Result<(), ClewdrError> {
+    let mut root = Value::Table(std::mem::take(table));
+    let res = SECRET_PATHS
+        .iter()
+        .try_for_each(|path| visit(&mut root, path, &mut f));
+    if let Value::Table(t) = root {
+        *table = t;
+    }
+    res
+}
+
+/// Replaces the password of every URL below `value`, such as a proxy or database URL
+fn redact_urls(value: &mut Value) {
+    match value {
+        Value::String(s) => {
+            if let Ok(mut url) = Url::parse(s)
+                && url.password().is_some()
+                && url.set_password(Some(REDACTED)).is_ok()
+            {
+                *s = url.to_string();
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_urls),
+        Value::Table(table) => table.values_mut().for_each(redact_urls),
+        _ => {}
+    }
+}
+
+/// Hides the secrets, passwords and URL credentials of a config in TOML form, for display
+pub fn redact_secrets(table: &mut toml::Table) {
+    let mut redact = |s: &mut String| {
+        if !s.is_empty() {
+            *s = REDACTED.to_string();
+        }
+        Ok(())
+    };
+    let _ = visit_secrets(table, &mut redact);
+    let mut root = Value::Table(std::mem::take(table));
+    for path in PASSWORD_PATHS {
+        let _ = visit(&mut root, path, &mut redact);
+    }
+    redact_urls(&mut root);
+    if let Value::Table(t) = root {
+        *table = t;
+    }
+}
+
+/// Writes the config as TOML, with cookies, tokens and keys encrypted when a master key is set
+pub fn seal_config(config: &ClewdrConfig) -> Result<String, ClewdrError> {
+    let mut table = toml::Table::try_from(config)?;
+    if secrets_encrypted() {
+        visit_secrets(&mut table, |s| {
+            *s = encrypt_secret(s);
+            Ok(())
+        })?;
+    }
+    Ok(toml::to_string_pretty(&table)?)
+}
+
+/// Decrypts the secrets in the TOML text of a config written by `seal_config`
+pub fn open_config_text(text: &str) -> Result<String, ClewdrError> {
+    if !text.contains(SECRET_PREFIX) {
+        return Ok(text.to_string());
+    }
+    let mut table = toml::from_str::<toml::Table>(text)?;
+    visit_secrets(&mut table, |s| {
+        *s = decrypt_secret(s)?;
+        Ok(())
+    })?;
+    Ok(toml::to_string_pretty(&table)?)
+}
+
+/// Parses a config written by `seal_config`
+pub fn open_config(text: &str) -> Result<ClewdrConfig, ClewdrError> {
+    Ok(toml::from_str(&open_config_text(text)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_round_trip() {
+        let keys = SecretKeys::derive("correct horse battery staple");
+        let sealed = keys.encrypt("sk-ant-sid01-abc");
+        assert!(sealed.starts_with(SECRET_PREFIX));
+        assert_eq!(sealed, keys.encrypt("sk-ant-sid01-abc"));
+        assert_ne!(sealed, keys.encrypt("sk-ant-sid01-abd"));
+        let body = sealed.strip_prefix(SECRET_PREFIX).unwrap();
+        assert_eq!(keys.decrypt(body).as_deref(), Some("sk-ant-sid01-abc"));
+        assert_eq!(SecretKeys::derive("wrong").decrypt(body), None);
+
+        let mut table = toml::from_str::<toml::Table>(
+            "port = 8484\n[[gemini_keys]]\nkey = \"AIza\"\n[[gemini_keys]]\nkey = \"AIzb\"\n",
+        )
+        .unwrap();
+        visit_secrets(&mut table, |s| {
+            *s = keys.encrypt(s);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(table["port"].as_integer(), Some(8484));
+        assert_eq!(
+            table["gemini_keys"][1]["key"].as_str(),
+            Some(keys.encrypt("AIzb").as_str())
+        );
+    }
+}
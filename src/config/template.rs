@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+/// Named replacement for `custom_system` and `custom_prompt`
+///
+/// Both texts may contain the placeholders `{date}`, `{model}` and `{user_name}`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct PromptTemplate {
+    /// Replaces `custom_system`, the system prelude of Claude Code requests
+    #[serde(default)]
+    pub system: Option<String>,
+    /// Replaces `custom_prompt`, the prompt sent along the Claude Web attachment
+    #[serde(default)]
+    pub prompt: Option<String>,
+}
+
+/// Values substituted into template placeholders
+#[derive(Debug, Clone)]
+pub struct TemplateVars<'a> {
+    pub date: String,
+    pub model: &'a str,
+    pub user_name: &'a str,
+}
+
+impl<'a> TemplateVars<'a> {
+    /// Placeholder values for a request, the date is the current UTC date
+    pub fn new(model: &'a str, user_name: &'a str) -> Self {
+        Self {
+            date: chrono::Utc::now().format("%Y-%m-%d").to_string(),
+            model,
+            user_name,
+        }
+    }
+
+    /// Replaces the placeholders in `text`, unknown placeholders are kept as is
+    pub fn render(&self, text: &str) -> String {
+        text.replace("{date}", &self.date)
+            .replace("{model}", self.model)
+            .replace("{user_name}", self.user_name)
+    }
+}
+
+/// Splits a `model@template` name into the model and the template name
+pub fn split_template_suffix(model: &str) -> (&str, Option<&str>) {
+    match model.rsplit_once('@') {
+        Some((model, name)) if !model.is_empty() && !name.is_empty() => (model, Some(name)),
+        _ => (model, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_and_split() {
+        let vars = TemplateVars {
+            date: "2025-01-01".to_string(),
+            model: "claude-sonnet-4",
+            user_name: "Alice",
+        };
+        assert_eq!(
+            vars.render("{user_name} talks to {model} on {date}, {unknown}"),
+            "Alice talks to claude-sonnet-4 on 2025-01-01, {unknown}"
+        );
+        assert_eq!(
+            split_template_suffix("claude-sonnet-4@roleplay"),
+            ("claude-sonnet-4", Some("roleplay"))
+        );
+        assert_eq!(
+            split_template_suffix("claude-sonnet-4"),
+            ("claude-sonnet-4", None)
+        );
+        assert_eq!(split_template_suffix("claude@"), ("claude@", None));
+    }
+}
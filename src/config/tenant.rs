@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{CookieStatus, KeyStatus, UselessCookie};
+
+/// A tenant with its own API passwords, cookie and key pools, and usage
+///
+/// Requests reach a tenant through the `/t/{name}` path prefix, e.g. `/t/{name}/v1/messages`,
+/// or by using one of its passwords on the regular paths. The pools are kept here in file
+/// mode, other persistence modes store them in rows or objects of the tenant and keep only
+/// the dropped keys here.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TenantConfig {
+    /// Name in the path prefix, letters, digits, `-` and `_`
+    pub name: String,
+    /// API passwords of the tenant, they are not accepted outside of it
+    #[serde(default)]
+    pub passwords: Vec<String>,
+    #[serde(default)]
+    pub cookie_array: HashSet<CookieStatus>,
+    #[serde(default)]
+    pub wasted_cookie: HashSet<UselessCookie>,
+    #[serde(default)]
+    pub gemini_keys: HashSet<KeyStatus>,
+    #[serde(default)]
+    pub invalid_keys: HashSet<KeyStatus>,
+}
+
+impl TenantConfig {
+    /// Whether `key` is one of the tenant's passwords
+    pub fn accepts(&self, key: &str) -> bool {
+        !key.is_empty() && self.passwords.iter().any(|p| p == key)
+    }
+
+    /// Whether the name can be used as a path segment
+    pub fn has_valid_name(&self) -> bool {
+        !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    /// Takes the pools of the tenant with the same name in `old`, the config page does not
+    /// manage them
+    pub fn keep_pools(&mut self, old: &[TenantConfig]) {
+        let Some(old) = old.iter().find(|t| t.name == self.name) else {
+            return;
+        };
+        self.cookie_array = old.cookie_array.to_owned();
+        self.wasted_cookie = old.wasted_cookie.to_owned();
+        self.gemini_keys = old.gemini_keys.to_owned();
+        self.invalid_keys = old.invalid_keys.to_owned();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tenant_passwords_and_names() {
+        let tenant: TenantConfig =
+            serde_json::from_str(r#"{ "name": "team-a", "passwords": ["pw-a"] }"#).unwrap();
+        assert!(tenant.has_valid_name());
+        assert!(tenant.accepts("pw-a"));
+        assert!(!tenant.accepts("pw-b"));
+        assert!(!tenant.accepts(""));
+        let tenant = TenantConfig {
+            name: "team/a".to_string(),
+            ..Default::default()
+        };
+        assert!(!tenant.has_valid_name());
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::ProxyTarget;
+
+/// Phase of an upstream request that can time out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display, strum::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum TimeoutPhase {
+    /// Establishing the connection, TLS included
+    Connect,
+    /// Waiting for the response headers
+    FirstByte,
+    /// The whole request, body included
+    Total,
+}
+
+/// Timeouts of upstream requests in seconds, 0 disables a phase and unset ones fall back
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Timeouts {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_byte: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+}
+
+impl Timeouts {
+    /// Fills the unset phases from `base`
+    pub fn or(self, base: Timeouts) -> Timeouts {
+        Timeouts {
+            connect: self.connect.or(base.connect),
+            first_byte: self.first_byte.or(base.first_byte),
+            total: self.total.or(base.total),
+        }
+    }
+
+    /// Duration of a phase, `None` if it is unset or disabled
+    pub fn get(&self, phase: TimeoutPhase) -> Option<Duration> {
+        match phase {
+            TimeoutPhase::Connect => self.connect,
+            TimeoutPhase::FirstByte => self.first_byte,
+            TimeoutPhase::Total => self.total,
+        }
+        .filter(|s| *s > 0)
+        .map(Duration::from_secs)
+    }
+}
+
+/// Timeouts of the upstream requests, can hot reload
+///
+/// The timeouts of an upstream fall back to `default` phase by phase.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TimeoutConfig {
+    #[serde(default)]
+    pub default: Timeouts,
+    #[serde(default)]
+    pub claude: Timeouts,
+    #[serde(default)]
+    pub gemini: Timeouts,
+    #[serde(default)]
+    pub vertex: Timeouts,
+}
+
+impl TimeoutConfig {
+    /// Timeouts of requests to the given upstream
+    pub fn for_target(&self, target: ProxyTarget) -> Timeouts {
+        match target {
+            ProxyTarget::Claude => self.claude,
+            ProxyTarget::Gemini => self.gemini,
+            ProxyTarget::Vertex => self.vertex,
+            ProxyTarget::Bedrock | ProxyTarget::OpenAiUpstream => Timeouts::default(),
+        }
+        .or(self.default)
+    }
+
+    pub fn is_set(&self) -> bool {
+        [self.default, self.claude, self.gemini, self.vertex]
+            .iter()
+            .any(|t| *t != Timeouts::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upstream_timeouts_fall_back_to_default() {
+        let config: TimeoutConfig = toml::from_str(
+            r#"
+            default = { connect = 10, total = 600 }
+            gemini = { first_byte = 30, total = 0 }
+            "#,
+        )
+        .unwrap();
+        let gemini = config.for_target(ProxyTarget::Gemini);
+        assert_eq!(
+            gemini.get(TimeoutPhase::Connect),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            gemini.get(TimeoutPhase::FirstByte),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(gemini.get(TimeoutPhase::Total), None);
+        let claude = config.for_target(ProxyTarget::Claude);
+        assert_eq!(claude.get(TimeoutPhase::FirstByte), None);
+        assert_eq!(
+            claude.get(TimeoutPhase::Total),
+            Some(Duration::from_secs(600))
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// Pool and service events webhooks can be notified of
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// A cookie was found banned or its organization disabled
+    CookieBanned,
+    /// A cookie hit its usage limit and waits for the reset
+    CookieRateLimited,
+    /// No cookie or key could serve a request
+    PoolEmpty,
+    /// Writing to the database failed
+    DbUnhealthy,
+    /// A newer version was released
+    UpdateAvailable,
+    /// Installing a newer version failed
+    UpdateFailed,
+    /// Sent by `POST /api/webhooks/test`
+    Test,
+}
+
+/// An endpoint notified of events with a JSON POST, e.g. a Slack or Discord incoming webhook
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Events sent to the endpoint, every event when empty
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    /// Signs the body with HMAC-SHA256 in the `x-clewdr-signature` header when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+impl WebhookEvent {
+    /// Events also sent as Discord and Telegram alerts
+    pub fn is_critical(self) -> bool {
+        matches!(
+            self,
+            Self::CookieBanned | Self::PoolEmpty | Self::UpdateFailed
+        )
+    }
+}
+
+impl WebhookConfig {
+    /// Whether the endpoint wants `event`, test events go to every endpoint
+    pub fn accepts(&self, event: WebhookEvent) -> bool {
+        event == WebhookEvent::Test || self.events.is_empty() || self.events.contains(&event)
+    }
+}
@@ -14,9 +14,15 @@ use snafu::Location;
 use strum::IntoStaticStr;
 use tokio::sync::oneshot;
 use tracing::{debug, error};
-use wreq::{Response, StatusCode, header::InvalidHeaderValue};
+use wreq::{
+    Response, StatusCode,
+    header::{InvalidHeaderValue, RETRY_AFTER},
+};
 
-use crate::{config::Reason, types::claude::Message};
+use crate::{
+    config::{Capability, Reason, TimeoutPhase},
+    types::claude::Message,
+};
 
 #[derive(Debug, IntoStaticStr, snafu::Snafu)]
 #[snafu(visibility(pub(crate)))]
@@ -89,8 +95,19 @@ pub enum ClewdrError {
     InvalidHeaderValue { source: InvalidHeaderValue },
     #[snafu(display("Bad request: {}", msg))]
     BadRequest { msg: &'static str },
+    #[snafu(display("Request body exceeds the limit of {} MiB", limit))]
+    PayloadTooLarge { limit: usize },
     #[snafu(display("Retries exceeded"))]
     TooManyRetries,
+    #[snafu(display("Upstream timed out, phase: {}", phase))]
+    UpstreamTimeout { phase: TimeoutPhase },
+    #[snafu(display("Organization request rate exceeded, retry after {}s", retry_after))]
+    OrgThrottled { retry_after: u64 },
+    #[snafu(display("Provider {} is failing, retry after {}s", provider, retry_after))]
+    CircuitOpen {
+        provider: &'static str,
+        retry_after: u64,
+    },
     #[snafu(display("EventSource error: {}", source))]
     #[snafu(context(false))]
     EventSourceAxumError {
@@ -118,6 +135,8 @@ pub enum ClewdrError {
     NoCookieAvailable,
     #[snafu(display("No key available"))]
     NoKeyAvailable,
+    #[snafu(display("No cookie in the pool has the {:?} capability", capability))]
+    NoCapableCookie { capability: Capability },
     #[snafu(display("Invalid Cookie: {}", reason))]
     #[snafu(context(false))]
     InvalidCookie {
@@ -150,6 +169,9 @@ pub enum ClewdrError {
     },
     #[snafu(display("Http error: code: {}, body: {}", code.to_string().red(), serde_json::to_string_pretty(&inner).unwrap_or_default()))]
     GeminiHttpError { code: StatusCode, inner: Value },
+    /// Error of an OpenAI compatible upstream, its body is passed on as it is
+    #[snafu(display("Http error: code: {}, body: {}", code.to_string().red(), serde_json::to_string_pretty(&inner).unwrap_or_default()))]
+    UpstreamHttpError { code: StatusCode, inner: Value },
     #[snafu(display("Unexpected None: {}", msg))]
     UnexpectedNone { msg: &'static str },
     #[snafu(display("IO error: {}", source))]
@@ -165,6 +187,23 @@ pub enum ClewdrError {
     TimestampError { timestamp: i64 },
     #[snafu(display("Key/Password Invalid"))]
     InvalidAuth,
+    #[snafu(display("Access denied for {}", ip))]
+    AccessDenied { ip: String },
+    #[snafu(display("Response blocked by rule {}", rule))]
+    ResponseBlocked { rule: String },
+    #[snafu(display(
+        "Request has about {} input tokens, over the limit of {} for {}",
+        tokens,
+        limit,
+        model
+    ))]
+    InputTooLong {
+        model: String,
+        tokens: u32,
+        limit: u32,
+    },
+    #[snafu(display("Request rejected by rule {}: {}", rule, reason))]
+    RequestRejected { rule: String, reason: String },
     #[snafu(whatever, display("{}: {}", message, source.as_ref().map_or_else(|| "Unknown error".into(), |e| e.to_string())))]
     Whatever {
         message: String,
@@ -173,8 +212,92 @@ pub enum ClewdrError {
     },
 }
 
+/// Header with the unix timestamp at which a rate limit resets
+pub const RATELIMIT_RESET_HEADER: &str = "x-ratelimit-reset";
+/// Header naming the phase of a timed out upstream request, `connect`, `first_byte` or `total`
+pub const TIMEOUT_PHASE_HEADER: &str = "x-clewdr-timeout-phase";
+
+/// Seconds Gemini asks to wait before retrying, from the `RetryInfo` detail of its error
+fn gemini_retry_delay(body: &Value) -> Option<i64> {
+    let body = match body {
+        Value::Array(a) => a.first()?,
+        b => b,
+    };
+    body["error"]["details"].as_array()?.iter().find_map(|d| {
+        let secs = d["retryDelay"]
+            .as_str()?
+            .strip_suffix('s')?
+            .parse::<f64>()
+            .ok()?;
+        Some(secs.ceil() as i64)
+    })
+}
+
+impl ClewdrError {
+    /// Unix timestamp at which retrying the request can succeed, when it is known
+    ///
+    /// For `NoCookieAvailable` this is the earliest reset of the exhausted cookies.
+    fn retry_at(&self) -> Option<i64> {
+        let now = Utc::now().timestamp();
+        match self {
+            ClewdrError::OrgThrottled { retry_after }
+            | ClewdrError::CircuitOpen { retry_after, .. } => Some(now + *retry_after as i64),
+            ClewdrError::InvalidCookie {
+                reason: Reason::TooManyRequest(ts),
+            } => Some(*ts),
+            ClewdrError::NoCookieAvailable => crate::services::quota::pool_reset(),
+            ClewdrError::GeminiHttpError { code, inner }
+                if *code == StatusCode::TOO_MANY_REQUESTS =>
+            {
+                gemini_retry_delay(inner).map(|d| now + d)
+            }
+            _ => None,
+        }
+    }
+
+    /// Phase of the upstream request that timed out, for timeout errors
+    fn timeout_phase(&self) -> Option<TimeoutPhase> {
+        match self {
+            ClewdrError::UpstreamTimeout { phase } => Some(*phase),
+            ClewdrError::WreqError { source, .. } if source.is_timeout() => {
+                Some(if source.is_connect() {
+                    TimeoutPhase::Connect
+                } else {
+                    TimeoutPhase::Total
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
 impl IntoResponse for ClewdrError {
+    /// Rate limited errors carry `retry-after` in seconds and `x-ratelimit-reset` as a unix
+    /// timestamp, so clients can schedule their retries. Upstream timeouts carry the phase
+    /// that timed out in `x-clewdr-timeout-phase`.
     fn into_response(self) -> axum::response::Response {
+        if let Some(phase) = self.timeout_phase() {
+            let mut resp = self.error_response();
+            resp.headers_mut().insert(
+                TIMEOUT_PHASE_HEADER,
+                http::HeaderValue::from_static(phase.into()),
+            );
+            return resp;
+        }
+        let Some(retry_at) = self.retry_at() else {
+            return self.error_response();
+        };
+        let mut resp = self.error_response();
+        let retry_after = (retry_at - Utc::now().timestamp()).max(1);
+        let headers = resp.headers_mut();
+        headers.insert(RETRY_AFTER, retry_after.into());
+        headers.insert(RATELIMIT_RESET_HEADER, retry_at.into());
+        resp
+    }
+}
+
+impl ClewdrError {
+    fn error_response(self) -> axum::response::Response {
         let (status, msg) = match self {
             ClewdrError::UrlError {
                 loc,
@@ -200,7 +323,8 @@ impl IntoResponse for ClewdrError {
             ClewdrError::ClaudeHttpError { code, inner } => {
                 return (code, Json(ClaudeError { error: inner })).into_response();
             }
-            ClewdrError::GeminiHttpError { code, inner } => {
+            ClewdrError::GeminiHttpError { code, inner }
+            | ClewdrError::UpstreamHttpError { code, inner } => {
                 return (code, Json(inner)).into_response();
             }
             ClewdrError::TestMessage => {
@@ -216,10 +340,40 @@ impl IntoResponse for ClewdrError {
                 (source.status(), json!(source.body_text()))
             }
             ClewdrError::TooManyRetries => (StatusCode::GATEWAY_TIMEOUT, json!(self.to_string())),
+            ClewdrError::UpstreamTimeout { .. } => {
+                (StatusCode::GATEWAY_TIMEOUT, json!(self.to_string()))
+            }
+            ClewdrError::WreqError { ref source, .. } if source.is_timeout() => {
+                (StatusCode::GATEWAY_TIMEOUT, json!(self.to_string()))
+            }
+            ClewdrError::OrgThrottled { .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, json!(self.to_string()))
+            }
+            ClewdrError::CircuitOpen { .. } => {
+                (StatusCode::SERVICE_UNAVAILABLE, json!(self.to_string()))
+            }
+            ClewdrError::InvalidCookie {
+                reason: Reason::TooManyRequest(_),
+            } => (StatusCode::TOO_MANY_REQUESTS, json!(self.to_string())),
             ClewdrError::InvalidCookie { .. } => (StatusCode::BAD_REQUEST, json!(self.to_string())),
+            ClewdrError::NoCookieAvailable | ClewdrError::NoKeyAvailable => {
+                (StatusCode::SERVICE_UNAVAILABLE, json!(self.to_string()))
+            }
+            ClewdrError::NoCapableCookie { .. } => {
+                (StatusCode::BAD_REQUEST, json!(self.to_string()))
+            }
             ClewdrError::PathNotFound { .. } => (StatusCode::NOT_FOUND, json!(self.to_string())),
             ClewdrError::InvalidAuth => (StatusCode::UNAUTHORIZED, json!(self.to_string())),
+            ClewdrError::AccessDenied { .. } => (StatusCode::FORBIDDEN, json!(self.to_string())),
+            ClewdrError::RequestRejected { .. } => (StatusCode::FORBIDDEN, json!(self.to_string())),
+            ClewdrError::ResponseBlocked { .. } => {
+                (StatusCode::BAD_GATEWAY, json!(self.to_string()))
+            }
             ClewdrError::BadRequest { .. } => (StatusCode::BAD_REQUEST, json!(self.to_string())),
+            ClewdrError::InputTooLong { .. } => (StatusCode::BAD_REQUEST, json!(self.to_string())),
+            ClewdrError::PayloadTooLarge { .. } => {
+                (StatusCode::PAYLOAD_TOO_LARGE, json!(self.to_string()))
+            }
             ClewdrError::InvalidHeaderValue { .. } => {
                 (StatusCode::BAD_REQUEST, json!(self.to_string()))
             }
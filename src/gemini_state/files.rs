@@ -0,0 +1,179 @@
+use std::{sync::LazyLock, time::Duration};
+
+use axum::{body::Body, http::HeaderMap, response::Response};
+use bytes::Bytes;
+use colored::Colorize;
+use http::header::CONTENT_TYPE;
+use moka::sync::Cache;
+use serde::Serialize;
+use serde_json::Value;
+use snafu::ResultExt;
+use tracing::info;
+use wreq::Url;
+
+use crate::{
+    config::KeyStatus,
+    error::{CheckGeminiErr, ClewdrError, UrlSnafu, WreqSnafu},
+    gemini_state::GeminiState,
+    services::endpoints::{self, Upstream},
+};
+
+/// Largest chunk of a resumable upload, the official SDKs send 8 MiB chunks
+pub const MAX_UPLOAD_CHUNK: usize = 64 * 1024 * 1024;
+/// Header of the resumable upload protocol carrying the URL the file is sent to
+const UPLOAD_URL_HEADER: &str = "x-goog-upload-url";
+/// Prefix of the resumable upload protocol headers, passed through both ways
+const UPLOAD_HEADER_PREFIX: &str = "x-goog-upload-";
+
+/// Key each uploaded file belongs to, a file can only be used with the key that uploaded it
+///
+/// Gemini deletes uploaded files after 48 hours. The bindings are only kept in memory, after a
+/// restart a file is used with whichever key the pool hands out and has to be uploaded again.
+static FILE_KEYS: LazyLock<Cache<String, KeyStatus>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(10_000)
+        .time_to_live(Duration::from_secs(48 * 60 * 60))
+        .build()
+});
+
+/// Upstream upload URL and key of each upload in progress, by upload id
+static UPLOADS: LazyLock<Cache<String, (String, KeyStatus)>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(1000)
+        .time_to_live(Duration::from_secs(24 * 60 * 60))
+        .build()
+});
+
+/// A request of the Gemini Files API resumable upload protocol
+pub struct GeminiUpload {
+    /// Upload session the request belongs to, `None` for the request starting one
+    pub upload_id: Option<String>,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    /// Base of the upload URL handed back to the client, e.g. `http://127.0.0.1:8484/v1`
+    pub base: String,
+}
+
+/// Key that uploaded a file, by its name (`files/{id}`)
+pub fn file_owner(name: &str) -> Option<KeyStatus> {
+    FILE_KEYS.get(name)
+}
+
+/// Key that uploaded the files a request refers to through `fileData.fileUri`
+pub fn request_file_owner(body: &impl Serialize) -> Option<KeyStatus> {
+    fn find(value: &Value) -> Option<KeyStatus> {
+        match value {
+            Value::Object(map) => map.iter().find_map(|(k, v)| match (k.as_str(), v) {
+                ("fileUri" | "file_uri", Value::String(uri)) => {
+                    let (_, id) = uri.rsplit_once("files/")?;
+                    file_owner(&format!("files/{id}"))
+                }
+                _ => find(v),
+            }),
+            Value::Array(items) => items.iter().find_map(find),
+            _ => None,
+        }
+    }
+    find(&serde_json::to_value(body).ok()?)
+}
+
+/// Upload protocol headers of `headers`, with the content type
+fn upload_headers(headers: &HeaderMap) -> HeaderMap {
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            name.as_str().starts_with(UPLOAD_HEADER_PREFIX) || **name == CONTENT_TYPE
+        })
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect()
+}
+
+impl GeminiState {
+    /// Forwards a request of a resumable upload to the Gemini Files API
+    ///
+    /// The request starting an upload is sent with a pooled key, and the upload URL Gemini
+    /// answers with is replaced by one pointing back here, so the key never reaches the
+    /// client. Later chunks go to the same upload session with the same key. Once the file
+    /// is finalized it is remembered, so requests referring to it use that key too.
+    pub async fn upload_file(&mut self, upload: GeminiUpload) -> Result<Response, ClewdrError> {
+        let (url, key) = match upload.upload_id.as_deref() {
+            Some(id) => UPLOADS.get(id).ok_or(ClewdrError::BadRequest {
+                msg: "Unknown or expired upload session",
+            })?,
+            None => {
+                self.request_key().await?;
+                let Some(key) = self.key.to_owned() else {
+                    return Err(ClewdrError::UnexpectedNone {
+                        msg: "Key is None, did you request a key?",
+                    });
+                };
+                let url = format!(
+                    "{}upload/v1beta/files?key={}",
+                    endpoints::pick(Upstream::Gemini),
+                    key.key.inner
+                );
+                (url, key)
+            }
+        };
+        if upload.upload_id.is_some() {
+            self.key = Some(key.to_owned());
+            self.rebuild_client()?;
+        }
+        info!("[FILE] {}", key.key.ellipse().green());
+        let res = self
+            .client
+            .post(url)
+            .headers(upload_headers(&upload.headers))
+            .body(upload.body)
+            .send()
+            .await
+            .context(WreqSnafu {
+                msg: "Failed to send upload request to Gemini API",
+            })?
+            .check_gemini()
+            .await?;
+        let status = res.status();
+        let mut headers = upload_headers(res.headers());
+        if let Some(upstream) = headers
+            .get(UPLOAD_URL_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| Url::parse(v).ok())
+        {
+            let Some((_, id)) = upstream.query_pairs().find(|(k, _)| k == "upload_id") else {
+                return Err(ClewdrError::UnexpectedNone {
+                    msg: "Upload URL without an upload id",
+                });
+            };
+            let base = format!("{}/upload/v1beta/files", upload.base);
+            let local = Url::parse_with_params(
+                &base,
+                [
+                    ("upload_id", id.as_ref()),
+                    ("upload_protocol", "resumable"),
+                    ("key", self.query.key.as_str()),
+                ],
+            )
+            .context(UrlSnafu {
+                url: base.to_owned(),
+            })?;
+            UPLOADS.insert(id.into_owned(), (upstream.to_string(), key.to_owned()));
+            headers.insert(UPLOAD_URL_HEADER, local.as_str().parse()?);
+        }
+        let bytes = res.bytes().await.context(WreqSnafu {
+            msg: "Failed to read Gemini upload response",
+        })?;
+        let name = serde_json::from_slice::<Value>(&bytes)
+            .ok()
+            .and_then(|v| v["file"]["name"].as_str().map(ToOwned::to_owned));
+        if let Some(name) = name {
+            info!("[FILE] {} uploaded", name);
+            FILE_KEYS.insert(name, key);
+            if let Some(id) = upload.upload_id {
+                UPLOADS.invalidate(&id);
+            }
+        }
+        let mut resp = Response::builder().status(status).body(Body::from(bytes))?;
+        resp.headers_mut().extend(headers);
+        Ok(resp)
+    }
+}
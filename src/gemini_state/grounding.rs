@@ -0,0 +1,69 @@
+use axum::{
+    Json,
+    body::to_bytes,
+    response::{IntoResponse, Response, Sse, sse::Event},
+};
+use eventsource_stream::Eventsource;
+use futures::{TryStreamExt, future};
+use serde_json::Value;
+
+use crate::{
+    error::ClewdrError,
+    gemini_state::{GeminiApiFormat, GeminiState, files},
+    types::{
+        gemini::grounding::{OaiStreamMapper, oai_response},
+        oai::CreateMessageParams,
+    },
+};
+
+impl GeminiState {
+    /// Sends an OpenAI request using grounding tools through the native API
+    ///
+    /// The answer is mapped back to the OpenAI format, with the grounding sources as
+    /// `url_citation` annotations.
+    pub async fn try_grounded(
+        &mut self,
+        body: CreateMessageParams,
+    ) -> Result<Response, ClewdrError> {
+        let native = body.to_gemini();
+        if !self.vertex {
+            // uploaded files only work with the key that uploaded them
+            self.file_key = files::request_file_owner(&native);
+        }
+        self.api_format = GeminiApiFormat::Gemini;
+        self.model = self.model.trim_start_matches("google/").to_string();
+        let method = if self.stream {
+            self.query.alt = Some("sse".to_string());
+            "streamGenerateContent"
+        } else {
+            "generateContent"
+        };
+        self.path = format!("models/{}:{method}", self.model);
+        let model = self.model.to_owned();
+        let resp = self.try_chat(native).await?;
+        if !self.stream {
+            let bytes = to_bytes(resp.into_body(), usize::MAX).await.map_err(|_| {
+                ClewdrError::UnexpectedNone {
+                    msg: "Failed to read Gemini response",
+                }
+            })?;
+            let res = serde_json::from_slice::<Value>(&bytes)?;
+            return Ok(Json(oai_response(&res, &model)).into_response());
+        }
+        let mut mapper = OaiStreamMapper::default();
+        let stream = resp
+            .into_body()
+            .into_data_stream()
+            .eventsource()
+            .map_ok(move |event| {
+                let chunk = serde_json::from_str::<Value>(&event.data).ok()?;
+                mapper
+                    .map(&chunk)
+                    .and_then(|data| Event::default().json_data(data).ok())
+            })
+            .try_filter_map(|e| future::ready(Ok(e)));
+        Ok(Sse::new(stream)
+            .keep_alive(Default::default())
+            .into_response())
+    }
+}
@@ -1,27 +1,49 @@
 use std::sync::LazyLock;
 
-use axum::response::Response;
+use axum::{
+    Json,
+    response::{IntoResponse, Response},
+};
 use colored::Colorize;
 use http::header::CONTENT_TYPE;
 use hyper_util::client::legacy::connect::HttpConnector;
 use serde::Serialize;
-use serde_json::Value;
+use serde_json::{Value, json};
 use snafu::ResultExt;
 use strum::Display;
 use tokio::spawn;
 use tracing::{error, info};
-use wreq::{Client, ClientBuilder, header::AUTHORIZATION};
+use usage::parse_usage;
+use wreq::{Client, header::AUTHORIZATION};
 use yup_oauth2::{CustomHyperClientBuilder, ServiceAccountAuthenticator, ServiceAccountKey};
 
 use crate::{
-    config::{CLEWDR_CONFIG, GEMINI_ENDPOINT, KeyStatus},
+    config::{CLEWDR_CONFIG, GeminiKey, KeyStatus, ProxyTarget, Timeouts},
     error::{CheckGeminiErr, ClewdrError, InvalidUriSnafu, WreqSnafu},
-    middleware::gemini::*,
-    services::key_actor::KeyActorHandle,
-    types::gemini::response::{FinishReason, GeminiResponse},
+    middleware::{
+        gemini::*,
+        proxy::current_proxy,
+        timeout::{current_timeouts, first_byte},
+    },
+    services::breaker::Provider,
+    services::capture::CaptureExt,
+    services::client_pool::{self, ClientKind},
+    services::endpoints::{self, Upstream},
+    services::key_actor::{KeyActorHandle, KeyUsage},
+    services::recent_errors,
+    services::retry::{Failure, RetryPolicy},
+    types::gemini::{
+        image::ImageGenerationRequest,
+        response::{FinishReason, GeminiResponse},
+        tokens::estimate_tokens,
+    },
     utils::forward_response,
 };
 
+pub mod files;
+mod grounding;
+pub(crate) mod usage;
+
 #[derive(Clone, Display, PartialEq, Eq)]
 pub enum GeminiApiFormat {
     Gemini,
@@ -30,15 +52,29 @@ pub enum GeminiApiFormat {
 
 static DUMMY_CLIENT: LazyLock<Client> = LazyLock::new(Client::new);
 
+/// Answers `countTokens` with the local estimate, like Gemini's own response
+fn local_count_tokens_response(body: &Value) -> Response {
+    Json(json!({ "totalTokens": estimate_tokens(body) })).into_response()
+}
+
 // TODO: replace yup-oauth2 with oauth2 crate
-async fn get_token(sa_key: ServiceAccountKey) -> Result<String, ClewdrError> {
+pub(crate) async fn get_token(sa_key: ServiceAccountKey) -> Result<String, ClewdrError> {
     const SCOPES: [&str; 1] = ["https://www.googleapis.com/auth/cloud-platform"];
-    let token = if let Some(proxy) = CLEWDR_CONFIG.load().proxy.to_owned() {
-        let proxy = proxy
-            .trim_start_matches("http://")
-            .trim_start_matches("https://")
-            .trim_start_matches("socks5://");
-        let proxy = format!("http://{proxy}");
+    let config = CLEWDR_CONFIG.load();
+    let proxy = config.vertex_proxy.to_owned().or(config.proxy.to_owned());
+    drop(config);
+    let token = if let Some(proxy) = proxy {
+        let (scheme, rest) = proxy.split_once("://").unwrap_or(("http", &proxy));
+        if !matches!(scheme, "http" | "https") {
+            return Err(ClewdrError::Whatever {
+                message: format!(
+                    "Vertex AI token requests only go through HTTP proxies, not {scheme}, set vertex_proxy to an HTTP proxy"
+                ),
+                source: None,
+            });
+        }
+        // the connector tunnels HTTPS through the proxy with CONNECT either way
+        let proxy = format!("http://{rest}");
         let proxy_uri = proxy.parse().context(InvalidUriSnafu {
             uri: proxy.to_owned(),
         })?;
@@ -64,18 +100,90 @@ async fn get_token(sa_key: ServiceAccountKey) -> Result<String, ClewdrError> {
     Ok(token.into())
 }
 
+/// Checks a key with a one-item `models.list` call, the cheapest authenticated call
+///
+/// # Returns
+/// * `Err(ClewdrError::GeminiHttpError)` - Gemini refused the key
+pub async fn check_key(key: &GeminiKey) -> Result<(), ClewdrError> {
+    let client = client_pool::client(
+        ClientKind::Gemini,
+        current_proxy(ProxyTarget::Gemini),
+        &current_timeouts(ProxyTarget::Gemini),
+    )?;
+    client
+        .get(format!(
+            "{}v1beta/models",
+            endpoints::pick(Upstream::Gemini)
+        ))
+        .query(&[("pageSize", "1"), ("key", key.inner.as_str())])
+        .send()
+        .await
+        .context(WreqSnafu {
+            msg: "Failed to send key check to Gemini API",
+        })?
+        .check_gemini()
+        .await?;
+    Ok(())
+}
+
+/// Models the key can generate content with, from the `models.list` call
+///
+/// # Returns
+/// * `Vec<String>` - Model names without the `models/` prefix
+pub async fn list_models(key: &GeminiKey) -> Result<Vec<String>, ClewdrError> {
+    let client = client_pool::client(
+        ClientKind::Gemini,
+        current_proxy(ProxyTarget::Gemini),
+        &current_timeouts(ProxyTarget::Gemini),
+    )?;
+    let res = client
+        .get(format!(
+            "{}v1beta/models",
+            endpoints::pick(Upstream::Gemini)
+        ))
+        .query(&[("pageSize", "1000"), ("key", key.inner.as_str())])
+        .send()
+        .await
+        .context(WreqSnafu {
+            msg: "Failed to list Gemini models",
+        })?
+        .check_gemini()
+        .await?
+        .json::<Value>()
+        .await
+        .context(WreqSnafu {
+            msg: "Failed to parse Gemini model list",
+        })?;
+    Ok(res["models"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|m| {
+            m["supportedGenerationMethods"]
+                .as_array()
+                .is_some_and(|methods| methods.iter().any(|x| x == "generateContent"))
+        })
+        .filter_map(|m| m["name"].as_str())
+        .map(|name| name.trim_start_matches("models/").to_string())
+        .collect())
+}
+
 #[derive(Clone)]
 pub struct GeminiState {
     pub model: String,
     pub vertex: bool,
     pub path: String,
     pub key: Option<KeyStatus>,
+    /// Key that uploaded the files the request refers to, used instead of a pooled key
+    pub file_key: Option<KeyStatus>,
     pub stream: bool,
     pub query: GeminiArgs,
     pub key_handle: KeyActorHandle,
     pub api_format: GeminiApiFormat,
     pub client: Client,
     pub vertex_credential: Option<ServiceAccountKey>,
+    /// Timeouts of the upstream requests, resolved from the context
+    pub timeouts: Timeouts,
 }
 
 impl GeminiState {
@@ -88,10 +196,12 @@ impl GeminiState {
             query: GeminiArgs::default(),
             stream: false,
             key: None,
+            file_key: None,
             key_handle: tx,
             api_format: GeminiApiFormat::Gemini,
             client: DUMMY_CLIENT.to_owned(),
             vertex_credential: None,
+            timeouts: Timeouts::default(),
         }
     }
 
@@ -103,19 +213,55 @@ impl GeminiState {
         Ok(())
     }
 
+    /// Reports token usage or a 429 of the current key to the key actor
+    pub async fn record_usage(&self, usage: KeyUsage) {
+        if let Some(key) = self.key.to_owned()
+            && let Err(e) = self.key_handle.record_usage(key, usage).await
+        {
+            error!("Failed to record key usage: {}", e);
+        }
+    }
+
     pub async fn request_key(&mut self) -> Result<(), ClewdrError> {
-        let key = self.key_handle.request().await?;
+        let key = match self.file_key.to_owned() {
+            Some(key) => key,
+            None => self.key_handle.request().await?,
+        };
+        let provider = if self.vertex { "vertex" } else { "gemini" };
+        tracing::Span::current().record("provider", provider);
         self.key = Some(key.to_owned());
-        let mut client = ClientBuilder::new();
-        if let Some(proxy) = CLEWDR_CONFIG.load().wreq_proxy.to_owned() {
-            client = client.proxy(proxy);
-        }
-        self.client = client.build().context(WreqSnafu {
-            msg: "Failed to build Gemini client",
-        })?;
+        self.rebuild_client()
+    }
+
+    /// Picks the pooled client of the latest proxy configuration
+    fn rebuild_client(&mut self) -> Result<(), ClewdrError> {
+        let target = if self.vertex {
+            ProxyTarget::Vertex
+        } else {
+            ProxyTarget::Gemini
+        };
+        self.client =
+            client_pool::client(ClientKind::Gemini, current_proxy(target), &self.timeouts)?;
         Ok(())
     }
 
+    /// Picks the Vertex credential assigned to this state, or the first configured one
+    fn vertex_credential(&self) -> Result<ServiceAccountKey, ClewdrError> {
+        self.vertex_credential
+            .to_owned()
+            .or_else(|| {
+                CLEWDR_CONFIG
+                    .load()
+                    .vertex
+                    .credential_list()
+                    .into_iter()
+                    .next()
+            })
+            .ok_or(ClewdrError::BadRequest {
+                msg: "Vertex credential not found",
+            })
+    }
+
     pub fn update_from_ctx(&mut self, ctx: &GeminiContext) {
         self.path = ctx.path.to_owned();
         self.stream = ctx.stream.to_owned();
@@ -123,19 +269,18 @@ impl GeminiState {
         self.model = ctx.model.to_owned();
         self.vertex = ctx.vertex.to_owned();
         self.api_format = ctx.api_format.to_owned();
+        self.timeouts = current_timeouts(if self.vertex {
+            ProxyTarget::Vertex
+        } else {
+            ProxyTarget::Gemini
+        });
     }
 
     async fn vertex_response(
         &mut self,
         p: impl Sized + Serialize,
     ) -> Result<wreq::Response, ClewdrError> {
-        let mut client = ClientBuilder::new();
-        if let Some(proxy) = CLEWDR_CONFIG.load().wreq_proxy.to_owned() {
-            client = client.proxy(proxy);
-        }
-        self.client = client.build().context(WreqSnafu {
-            msg: "Failed to build Gemini client",
-        })?;
+        self.rebuild_client()?;
         let method = if self.stream {
             "streamGenerateContent"
         } else {
@@ -143,57 +288,50 @@ impl GeminiState {
         };
 
         // Get an access token
-        let Some(cred) = self.vertex_credential.to_owned().or_else(|| {
-            CLEWDR_CONFIG
-                .load()
-                .vertex
-                .credential_list()
-                .into_iter()
-                .next()
-        }) else {
-            return Err(ClewdrError::BadRequest {
-                msg: "Vertex credential not found",
-            });
-        };
-
+        let cred = self.vertex_credential()?;
         let access_token = get_token(cred.to_owned()).await?;
         let bearer = format!("Bearer {access_token}");
-        let res = match self.api_format {
-            GeminiApiFormat::Gemini => {
-                let endpoint = format!(
-                    "https://aiplatform.googleapis.com/v1/projects/{}/locations/global/publishers/google/models/{}:{method}",
-                    cred.project_id.unwrap_or_default(),
-                    self.model
-                );
-                let query_vec = self.query.to_vec();
-                self
-                    .client
-                    .post(endpoint)
-                    .query(&query_vec)
-                    .header(AUTHORIZATION, bearer)
-                    .json(&p)
-                    .send()
-                    .await
-                    .context(WreqSnafu {
-                        msg: "Failed to send request to Gemini Vertex API",
-                    })?
-            }
-            GeminiApiFormat::OpenAI => {
-                self.client
-                    .post(format!(
-                        "https://aiplatform.googleapis.com/v1beta1/projects/{}/locations/global/endpoints/openapi/chat/completions",
+        let send = async {
+            match self.api_format {
+                GeminiApiFormat::Gemini => {
+                    let endpoint = format!(
+                        "https://aiplatform.googleapis.com/v1/projects/{}/locations/global/publishers/google/models/{}:{method}",
                         cred.project_id.unwrap_or_default(),
-                    ))
-                    .header(AUTHORIZATION, bearer)
-                    .json(&p)
-                    .send()
-                    .await
-                    .context(WreqSnafu {
-                        msg: "Failed to send request to Gemini Vertex OpenAI API",
-                    })?
+                        self.model
+                    );
+                    let query_vec = self.query.to_vec();
+                    self
+                        .client
+                        .post(endpoint)
+                        .query(&query_vec)
+                        .header(AUTHORIZATION, bearer)
+                        .json(&p)
+                        .send_captured()
+                        .await
+                        .context(WreqSnafu {
+                            msg: "Failed to send request to Gemini Vertex API",
+                        })
+                }
+                GeminiApiFormat::OpenAI => {
+                    self.client
+                        .post(format!(
+                            "https://aiplatform.googleapis.com/v1beta1/projects/{}/locations/global/endpoints/openapi/chat/completions",
+                            cred.project_id.unwrap_or_default(),
+                        ))
+                        .header(AUTHORIZATION, bearer)
+                        .json(&p)
+                        .send_captured()
+                        .await
+                        .context(WreqSnafu {
+                            msg: "Failed to send request to Gemini Vertex OpenAI API",
+                        })
+                }
             }
         };
-        let res = res.check_gemini().await?;
+        let res = first_byte(&self.timeouts, send)
+            .await?
+            .check_gemini()
+            .await?;
         Ok(res)
     }
 
@@ -213,86 +351,237 @@ impl GeminiState {
         };
         info!("[KEY] {}", key.key.ellipse().green());
         let key = key.key.to_string();
-        let res = match self.api_format {
-            GeminiApiFormat::Gemini => {
-                let mut query_vec = self.query.to_vec();
-                query_vec.push(("key", key.as_str()));
-                self.client
-                    .post(format!("{}v1beta/{}", GEMINI_ENDPOINT, self.path))
-                    .query(&query_vec)
+        let endpoint = endpoints::pick(Upstream::Gemini);
+        let send = async {
+            match self.api_format {
+                GeminiApiFormat::Gemini => {
+                    let mut query_vec = self.query.to_vec();
+                    query_vec.push(("key", key.as_str()));
+                    self.client
+                        .post(format!("{}v1beta/{}", endpoint, self.path))
+                        .query(&query_vec)
+                        .json(&p)
+                        .send_captured()
+                        .await
+                        .context(WreqSnafu {
+                            msg: "Failed to send request to Gemini API",
+                        })
+                }
+                GeminiApiFormat::OpenAI => self
+                    .client
+                    .post(format!("{endpoint}v1beta/openai/chat/completions",))
+                    .header(AUTHORIZATION, format!("Bearer {key}"))
                     .json(&p)
-                    .send()
+                    .send_captured()
                     .await
                     .context(WreqSnafu {
-                        msg: "Failed to send request to Gemini API",
-                    })?
+                        msg: "Failed to send request to Gemini OpenAI API",
+                    }),
             }
-            GeminiApiFormat::OpenAI => self
-                .client
-                .post(format!("{GEMINI_ENDPOINT}v1beta/openai/chat/completions",))
-                .header(AUTHORIZATION, format!("Bearer {key}"))
-                .json(&p)
-                .send()
-                .await
-                .context(WreqSnafu {
-                    msg: "Failed to send request to Gemini OpenAI API",
-                })?,
         };
-        let res = res.check_gemini().await?;
-        Ok(res)
+        let res = match first_byte(&self.timeouts, send).await {
+            Ok(res) => res.check_gemini().await,
+            Err(e) => Err(e),
+        };
+        endpoints::report(Upstream::Gemini, &endpoint, &res);
+        res
     }
 
-    pub async fn try_chat(&mut self, p: impl Serialize + Clone) -> Result<Response, ClewdrError> {
-        let mut err = None;
-        for i in 0..CLEWDR_CONFIG.load().max_retries + 1 {
-            if i > 0 {
-                info!("[RETRY] attempt: {}", i.to_string().green());
+    /// Forwards an auxiliary call (`countTokens`, `models` list/get) with a pooled key
+    ///
+    /// These calls are cheap and idempotent, so they are sent once outside the retry loop.
+    /// A body means POST, no body means GET. `countTokens` is estimated locally when
+    /// `offline_count_tokens` is set, no key is available or the key is rate limited.
+    pub async fn send_auxiliary(&mut self, body: Option<Value>) -> Result<Response, ClewdrError> {
+        let count_body = body
+            .as_ref()
+            .filter(|_| self.path.ends_with(":countTokens"))
+            .cloned();
+        let Some(count_body) = count_body else {
+            return self.forward_auxiliary(body).await;
+        };
+        if CLEWDR_CONFIG.load().offline_count_tokens {
+            return Ok(local_count_tokens_response(&count_body));
+        }
+        match self.forward_auxiliary(body).await {
+            Err(ClewdrError::NoKeyAvailable) => Ok(local_count_tokens_response(&count_body)),
+            Err(ClewdrError::GeminiHttpError { code, .. }) if code.as_u16() == 429 => {
+                Ok(local_count_tokens_response(&count_body))
             }
-            let mut state = self.to_owned();
-            let p = p.to_owned();
+            res => res,
+        }
+    }
 
-            match state.send_chat(p).await {
-                Ok(resp) => match state.check_empty_choices(resp).await {
-                    Ok(resp) => return Ok(resp),
-                    Err(e) => {
+    async fn forward_auxiliary(&mut self, body: Option<Value>) -> Result<Response, ClewdrError> {
+        if self.vertex {
+            return self.vertex_auxiliary(body).await;
+        }
+        self.request_key().await?;
+        let Some(key) = self.key.to_owned() else {
+            return Err(ClewdrError::UnexpectedNone {
+                msg: "Key is None, did you request a key?",
+            });
+        };
+        info!("[KEY] {}", key.key.ellipse().green());
+        let mut query_vec = self.query.to_vec();
+        query_vec.push(("key", key.key.inner.as_str()));
+        let url = format!("{}v1beta/{}", endpoints::pick(Upstream::Gemini), self.path);
+        let req = match body {
+            Some(body) => self.client.post(url).json(&body),
+            None => self.client.get(url),
+        };
+        let res = req
+            .query(&query_vec)
+            .send_captured()
+            .await
+            .context(WreqSnafu {
+                msg: "Failed to send auxiliary request to Gemini API",
+            })?
+            .check_gemini()
+            .await?;
+        forward_response(res)
+    }
+
+    /// Generates images for the OpenAI images API with a pooled key
+    pub async fn send_image(
+        &mut self,
+        req: ImageGenerationRequest,
+    ) -> Result<Response, ClewdrError> {
+        self.request_key().await?;
+        let Some(key) = self.key.to_owned() else {
+            return Err(ClewdrError::UnexpectedNone {
+                msg: "Key is None, did you request a key?",
+            });
+        };
+        info!("[KEY] {}", key.key.ellipse().green());
+        let res = self
+            .client
+            .post(format!(
+                "{}v1beta/models/{}:{}",
+                endpoints::pick(Upstream::Gemini),
+                req.model(),
+                req.method()
+            ))
+            .query(&[("key", key.key.inner.as_str())])
+            .json(&req.to_gemini())
+            .send_captured()
+            .await
+            .context(WreqSnafu {
+                msg: "Failed to send image request to Gemini API",
+            })?
+            .check_gemini()
+            .await?;
+        let res = res.json::<Value>().await.context(WreqSnafu {
+            msg: "Failed to parse Gemini image response",
+        })?;
+        self.record_usage(parse_usage(&res).unwrap_or_default())
+            .await;
+        let images = req.convert_response(&res);
+        if images.data.is_empty() {
+            return Err(ClewdrError::EmptyChoices);
+        }
+        Ok(Json(images).into_response())
+    }
+
+    async fn vertex_auxiliary(&mut self, body: Option<Value>) -> Result<Response, ClewdrError> {
+        let Some(body) = body else {
+            return Err(ClewdrError::BadRequest {
+                msg: "Listing models is not supported on Vertex",
+            });
+        };
+        self.rebuild_client()?;
+        let cred = self.vertex_credential()?;
+        let access_token = get_token(cred.to_owned()).await?;
+        let endpoint = format!(
+            "https://aiplatform.googleapis.com/v1/projects/{}/locations/global/publishers/google/models/{}:countTokens",
+            cred.project_id.unwrap_or_default(),
+            self.model
+        );
+        let res = self
+            .client
+            .post(endpoint)
+            .header(AUTHORIZATION, format!("Bearer {access_token}"))
+            .json(&body)
+            .send_captured()
+            .await
+            .context(WreqSnafu {
+                msg: "Failed to send auxiliary request to Gemini Vertex API",
+            })?
+            .check_gemini()
+            .await?;
+        forward_response(res)
+    }
+
+    pub async fn try_chat(&mut self, p: impl Serialize + Clone) -> Result<Response, ClewdrError> {
+        let p = &p;
+        RetryPolicy::from_config()
+            .surface_last_error()
+            .executor(Self::classify, Self::report_failure)
+            .run(self, |mut state| async move {
+                let res = match state.send_chat(p.to_owned()).await {
+                    Ok(resp) => state.check_empty_choices(resp).await.inspect_err(|e| {
                         error!("Failed to check empty choices: {}", e);
-                        err = Some(e);
-                        continue;
-                    }
-                },
-                Err(e) => {
-                    if let Some(key) = state.key.to_owned() {
-                        error!("[{}] {}", key.key.ellipse().green(), e);
-                    } else {
-                        error!("{}", e);
-                    }
-                    match e {
-                        ClewdrError::GeminiHttpError { code, .. } => {
-                            if code == 403 {
-                                spawn(async move {
-                                    state.report_403().await.unwrap_or_else(|e| {
-                                        error!("Failed to report 403: {}", e);
-                                    });
-                                });
-                            }
-                            err = Some(e);
-                            continue;
+                    }),
+                    Err(e) => {
+                        if let Some(key) = state.key.to_owned() {
+                            error!("[{}] {}", key.key.ellipse().green(), e);
+                        } else {
+                            error!("{}", e);
                         }
-                        e => return Err(e),
+                        let provider = if state.vertex {
+                            Provider::Vertex
+                        } else {
+                            Provider::GeminiAiStudio
+                        };
+                        let key = state.key.as_ref().map(|k| k.key.ellipse());
+                        recent_errors::record(provider, key, &e);
+                        Err(e)
                     }
-                }
-            }
+                };
+                (state, res)
+            })
+            .await
+    }
+
+    /// Upstream errors, including server errors, transport failures, unreadable and empty
+    /// answers are retried with another key
+    fn classify(e: &ClewdrError) -> Failure {
+        match e {
+            ClewdrError::GeminiHttpError { .. }
+            | ClewdrError::WreqError { .. }
+            | ClewdrError::JsonError { .. }
+            | ClewdrError::EmptyChoices => Failure::Retry,
+            _ => Failure::Abort,
         }
-        error!("Max retries exceeded");
-        if let Some(e) = err {
-            return Err(e);
+    }
+
+    /// Reports a rate limited or forbidden key to the key actor
+    fn report_failure(self, e: &ClewdrError) -> impl Future<Output = ()> + use<> {
+        let code = match e {
+            ClewdrError::GeminiHttpError { code, .. } => code.as_u16(),
+            _ => 0,
+        };
+        async move {
+            if code == 429 {
+                self.record_usage(KeyUsage {
+                    rate_limited: true,
+                    ..Default::default()
+                })
+                .await;
+            }
+            if code == 403 {
+                spawn(async move {
+                    self.report_403().await.unwrap_or_else(|e| {
+                        error!("Failed to report 403: {}", e);
+                    });
+                });
+            }
         }
-        Err(ClewdrError::TooManyRetries)
     }
 
     async fn check_empty_choices(&self, resp: wreq::Response) -> Result<Response, ClewdrError> {
         if self.stream {
-            return forward_response(resp);
+            return self.forward_stream_with_usage(resp);
         }
         let bytes = resp.bytes().await.context(WreqSnafu {
             msg: "Failed to get bytes from Gemini response",
@@ -301,6 +590,9 @@ impl GeminiState {
         match self.api_format {
             GeminiApiFormat::Gemini => {
                 let res = serde_json::from_slice::<GeminiResponse>(&bytes)?;
+                let usage = json!({ "usageMetadata": res.usageMetadata });
+                self.record_usage(parse_usage(&usage).unwrap_or_default())
+                    .await;
                 if res.candidates.is_empty() {
                     return Err(ClewdrError::EmptyChoices);
                 }
@@ -310,6 +602,8 @@ impl GeminiState {
             }
             GeminiApiFormat::OpenAI => {
                 let res = serde_json::from_slice::<Value>(&bytes)?;
+                self.record_usage(parse_usage(&res).unwrap_or_default())
+                    .await;
                 if res["choices"].as_array().is_some_and(|v| v.is_empty()) {
                     return Err(ClewdrError::EmptyChoices);
                 }
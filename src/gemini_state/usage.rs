@@ -0,0 +1,137 @@
+use axum::{body::Body, response::Response};
+use futures::TryStreamExt;
+use serde_json::Value;
+
+use tracing::error;
+
+use crate::{
+    config::KeyStatus,
+    error::ClewdrError,
+    gemini_state::GeminiState,
+    services::key_actor::{KeyActorHandle, KeyUsage},
+    utils::forward_response,
+};
+
+/// Extracts token usage from a Gemini or OpenAI format response or stream chunk
+///
+/// Gemini reports `usageMetadata`, the OpenAI compatible endpoint reports `usage`.
+/// Thinking tokens are billed as output.
+pub fn parse_usage(value: &Value) -> Option<KeyUsage> {
+    let (input, output, total) = if let Some(u) = value.get("usageMetadata") {
+        (
+            u["promptTokenCount"].as_u64(),
+            u["candidatesTokenCount"]
+                .as_u64()
+                .map(|c| c + u["thoughtsTokenCount"].as_u64().unwrap_or_default()),
+            u["totalTokenCount"].as_u64(),
+        )
+    } else if let Some(u) = value.get("usage").filter(|u| u.is_object()) {
+        (
+            u["prompt_tokens"].as_u64(),
+            u["completion_tokens"].as_u64(),
+            u["total_tokens"].as_u64(),
+        )
+    } else {
+        return None;
+    };
+    let input_tokens = input.unwrap_or_default();
+    let output_tokens = output.unwrap_or_default();
+    Some(KeyUsage {
+        tokens: total.unwrap_or(input_tokens + output_tokens),
+        input_tokens,
+        output_tokens,
+        rate_limited: false,
+    })
+}
+
+/// Watches the SSE lines of a stream for usage, recorded to the key once the stream is dropped
+struct UsageTap {
+    key_handle: KeyActorHandle,
+    key: Option<KeyStatus>,
+    line: Vec<u8>,
+    usage: Option<KeyUsage>,
+}
+
+impl UsageTap {
+    fn feed(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if b != b'\n' {
+                self.line.push(b);
+                continue;
+            }
+            let line = std::mem::take(&mut self.line);
+            let Some(data) = line.strip_prefix(b"data:") else {
+                continue;
+            };
+            // usage is cumulative, the last chunk carrying it holds the totals
+            if let Some(usage) = serde_json::from_slice::<Value>(data.trim_ascii())
+                .ok()
+                .as_ref()
+                .and_then(parse_usage)
+            {
+                self.usage = Some(usage);
+            }
+        }
+    }
+}
+
+impl Drop for UsageTap {
+    fn drop(&mut self) {
+        let (Some(usage), Some(key)) = (self.usage.take(), self.key.take()) else {
+            return;
+        };
+        let key_handle = self.key_handle.to_owned();
+        tokio::spawn(async move {
+            if let Err(e) = key_handle.record_usage(key, usage).await {
+                error!("Failed to record key usage: {}", e);
+            }
+        });
+    }
+}
+
+/// Forwards a streaming response unchanged while accounting its token usage to `key`
+pub(crate) fn forward_stream_with_usage(
+    resp: wreq::Response,
+    key_handle: KeyActorHandle,
+    key: Option<KeyStatus>,
+) -> Result<Response, ClewdrError> {
+    let mut tap = UsageTap {
+        key_handle,
+        key,
+        line: Vec::new(),
+        usage: None,
+    };
+    let res = forward_response(resp)?;
+    Ok(res.map(|body| Body::from_stream(body.into_data_stream().inspect_ok(move |b| tap.feed(b)))))
+}
+
+impl GeminiState {
+    /// Forwards a streaming response unchanged while accounting its token usage to the key
+    pub(super) fn forward_stream_with_usage(
+        &self,
+        resp: wreq::Response,
+    ) -> Result<Response, ClewdrError> {
+        forward_stream_with_usage(resp, self.key_handle.to_owned(), self.key.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn usage_from_both_formats() {
+        let gemini = json!({ "usageMetadata": {
+            "promptTokenCount": 10, "candidatesTokenCount": 5,
+            "thoughtsTokenCount": 3, "totalTokenCount": 18
+        }});
+        let u = parse_usage(&gemini).unwrap();
+        assert_eq!((u.input_tokens, u.output_tokens, u.tokens), (10, 8, 18));
+        let oai = json!({ "choices": [], "usage": { "prompt_tokens": 7, "completion_tokens": 2 }});
+        let u = parse_usage(&oai).unwrap();
+        assert_eq!((u.input_tokens, u.output_tokens, u.tokens), (7, 2, 9));
+        assert!(parse_usage(&json!({ "choices": [], "usage": null })).is_none());
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    convert::Infallible,
+    task::{Context, Poll},
+};
+
+use tonic::{Status, body::BoxBody, server::NamedService};
+use tower::Service;
+use tracing::info;
+
+use super::{
+    ResponseFuture, bearer,
+    proto::{
+        Cookie, CookieList, CookieRequest, Empty, InvalidCookie, Key, KeyList, KeyRequest,
+        SetDisabledRequest, StatusResponse,
+    },
+    unary,
+};
+use crate::{
+    api::ensure_db_writable,
+    config::{CLEWDR_CONFIG, CookieStatus, GeminiKey, KeyStatus},
+    error::ClewdrError,
+    services::{cookie_actor::CookieActorHandle, key_actor::KeyActorHandle},
+};
+
+/// AdminService, manages the cookies and keys of the running actors
+#[derive(Clone)]
+pub struct AdminService {
+    cookie_handle: CookieActorHandle,
+    key_handle: KeyActorHandle,
+}
+
+impl AdminService {
+    pub fn new(cookie_handle: CookieActorHandle, key_handle: KeyActorHandle) -> Self {
+        Self {
+            cookie_handle,
+            key_handle,
+        }
+    }
+
+    async fn list_cookies(self, _: Empty) -> Result<CookieList, Status> {
+        let status = self.cookie_handle.get_status().await.map_err(internal)?;
+        let cookie = |c: CookieStatus| Cookie {
+            cookie: c.cookie.to_string(),
+            disabled: c.disabled,
+            reset_time: c.reset_time,
+        };
+        Ok(CookieList {
+            valid: status.valid.into_iter().map(cookie).collect(),
+            exhausted: status.exhausted.into_iter().map(cookie).collect(),
+            invalid: status
+                .invalid
+                .into_iter()
+                .map(|u| InvalidCookie {
+                    cookie: u.cookie.to_string(),
+                    reason: u.reason.to_string(),
+                })
+                .collect(),
+        })
+    }
+
+    async fn add_cookie(self, req: CookieRequest) -> Result<Empty, Status> {
+        let cookie = parse_cookie(&req.cookie)?;
+        writable().await?;
+        info!("Cookie accepted: {}", cookie.cookie);
+        self.cookie_handle.submit(cookie).await.map_err(internal)?;
+        Ok(Empty {})
+    }
+
+    async fn delete_cookie(self, req: CookieRequest) -> Result<Empty, Status> {
+        let cookie = parse_cookie(&req.cookie)?;
+        writable().await?;
+        self.cookie_handle
+            .delete_cookie(cookie.to_owned())
+            .await
+            .map_err(internal)?;
+        info!("Cookie deleted successfully: {}", cookie.cookie);
+        Ok(Empty {})
+    }
+
+    async fn set_cookie_disabled(self, req: SetDisabledRequest) -> Result<Empty, Status> {
+        let cookie = parse_cookie(&req.value)?;
+        writable().await?;
+        self.cookie_handle
+            .set_disabled(cookie, req.disabled)
+            .await
+            .map_err(internal)?;
+        Ok(Empty {})
+    }
+
+    async fn list_keys(self, _: Empty) -> Result<KeyList, Status> {
+        let status = self.key_handle.get_status().await.map_err(internal)?;
+        let key = |k: KeyStatus| Key {
+            key: k.key.to_string(),
+            disabled: k.disabled,
+            count_requests: k.count_requests,
+            count_tokens: k.count_tokens,
+            quarantined_until: k.quarantined_until,
+        };
+        Ok(KeyList {
+            valid: status.valid.into_iter().map(key).collect(),
+            invalid: status.invalid.into_iter().map(key).collect(),
+        })
+    }
+
+    async fn add_key(self, req: KeyRequest) -> Result<Empty, Status> {
+        let key = parse_key(&req.key)?;
+        writable().await?;
+        info!("Key accepted: {}", key.key);
+        self.key_handle.submit(key).await.map_err(internal)?;
+        Ok(Empty {})
+    }
+
+    async fn delete_key(self, req: KeyRequest) -> Result<Empty, Status> {
+        let key = parse_key(&req.key)?;
+        writable().await?;
+        self.key_handle
+            .delete_key(key.to_owned())
+            .await
+            .map_err(internal)?;
+        info!("Key deleted successfully: {}", key.key);
+        Ok(Empty {})
+    }
+
+    async fn set_key_disabled(self, req: SetDisabledRequest) -> Result<Empty, Status> {
+        let key = parse_key(&req.value)?;
+        writable().await?;
+        self.key_handle
+            .set_disabled(key, req.disabled)
+            .await
+            .map_err(internal)?;
+        Ok(Empty {})
+    }
+
+    async fn status(self, _: Empty) -> Result<StatusResponse, Status> {
+        let cookies = self.cookie_handle.get_status().await.map_err(internal)?;
+        let keys = self.key_handle.get_status().await.map_err(internal)?;
+        Ok(StatusResponse {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            valid_cookies: cookies.valid.len() as u32,
+            exhausted_cookies: cookies.exhausted.len() as u32,
+            invalid_cookies: cookies.invalid.len() as u32,
+            valid_keys: keys.valid.len() as u32,
+        })
+    }
+}
+
+fn parse_cookie(cookie: &str) -> Result<CookieStatus, Status> {
+    CookieStatus::new(cookie, None).map_err(|_| Status::invalid_argument("Invalid cookie"))
+}
+
+fn parse_key(key: &str) -> Result<KeyStatus, Status> {
+    let key = GeminiKey::from(key);
+    if !key.validate() {
+        return Err(Status::invalid_argument("Invalid key"));
+    }
+    Ok(key.into())
+}
+
+/// Refuses changes while database storage is unhealthy, like the HTTP admin API
+async fn writable() -> Result<(), Status> {
+    ensure_db_writable()
+        .await
+        .map_err(|_| Status::unavailable("Database storage is unavailable"))
+}
+
+fn internal(e: ClewdrError) -> Status {
+    match e {
+        ClewdrError::UnexpectedNone { msg } => Status::not_found(msg),
+        e => Status::internal(e.to_string()),
+    }
+}
+
+impl Service<http::Request<BoxBody>> for AdminService {
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<BoxBody>) -> Self::Future {
+        if !bearer(&req).is_some_and(|t| CLEWDR_CONFIG.load().admin_auth(&t)) {
+            return Box::pin(async {
+                Ok(Status::unauthenticated("Invalid admin password").into_http())
+            });
+        }
+        let this = self.to_owned();
+        Box::pin(async move {
+            Ok(match req.uri().path() {
+                "/clewdr.AdminService/ListCookies" => unary(req, |m| this.list_cookies(m)).await,
+                "/clewdr.AdminService/AddCookie" => unary(req, |m| this.add_cookie(m)).await,
+                "/clewdr.AdminService/DeleteCookie" => unary(req, |m| this.delete_cookie(m)).await,
+                "/clewdr.AdminService/SetCookieDisabled" => {
+                    unary(req, |m| this.set_cookie_disabled(m)).await
+                }
+                "/clewdr.AdminService/ListKeys" => unary(req, |m| this.list_keys(m)).await,
+                "/clewdr.AdminService/AddKey" => unary(req, |m| this.add_key(m)).await,
+                "/clewdr.AdminService/DeleteKey" => unary(req, |m| this.delete_key(m)).await,
+                "/clewdr.AdminService/SetKeyDisabled" => {
+                    unary(req, |m| this.set_key_disabled(m)).await
+                }
+                "/clewdr.AdminService/Status" => unary(req, |m| this.status(m)).await,
+                _ => Status::unimplemented("Unknown method").into_http(),
+            })
+        })
+    }
+}
+
+impl NamedService for AdminService {
+    const NAME: &'static str = "clewdr.AdminService";
+}
@@ -0,0 +1,194 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    task::{Context, Poll},
+};
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    extract::{ConnectInfo, Request},
+    http::{
+        StatusCode,
+        header::{CONTENT_TYPE, HeaderValue},
+    },
+    response::Response,
+};
+use eventsource_stream::Eventsource;
+use futures::{Stream, StreamExt};
+use serde_json::Value;
+use tonic::{Code, Status, body::BoxBody, server::NamedService};
+use tower::{Service, ServiceExt};
+
+use super::{
+    ResponseFuture, bearer,
+    proto::{GenerateEvent, GenerateRequest, GenerateResponse, Provider},
+    remote_addr, server_streaming, unary,
+};
+use crate::config::CLEWDR_CONFIG;
+
+/// ChatService, runs Claude Messages API requests through the HTTP endpoints of the providers
+///
+/// Requests pass the same preprocessing, response rules and access control as HTTP clients.
+#[derive(Clone)]
+pub struct ChatService {
+    router: Router,
+}
+
+impl ChatService {
+    pub fn new(router: Router) -> Self {
+        Self { router }
+    }
+
+    /// Builds the HTTP request for a chat call
+    fn request(
+        key: &str,
+        peer: Option<SocketAddr>,
+        msg: GenerateRequest,
+        stream: bool,
+    ) -> Result<Request, Status> {
+        let path = match Provider::try_from(msg.provider) {
+            Ok(Provider::ClaudeWeb) => "/v1/messages",
+            Ok(Provider::ClaudeCode) => "/code/v1/messages",
+            Err(_) => return Err(Status::invalid_argument("Unknown provider")),
+        };
+        let Ok(Value::Object(mut body)) = serde_json::from_str::<Value>(&msg.body) else {
+            return Err(Status::invalid_argument("body must be a JSON object"));
+        };
+        body.insert("stream".to_string(), Value::Bool(stream));
+        let mut req = Request::post(path)
+            .header("x-api-key", key)
+            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+            .body(Body::from(Value::Object(body).to_string()))
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        if let Some(peer) = peer {
+            req.extensions_mut().insert(ConnectInfo(peer));
+        }
+        Ok(req)
+    }
+
+    async fn generate(self, req: Request) -> Result<GenerateResponse, Status> {
+        let resp = self.dispatch(req).await?;
+        let body = to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(GenerateResponse {
+            body: String::from_utf8_lossy(&body).into_owned(),
+        })
+    }
+
+    async fn stream_generate(
+        self,
+        req: Request,
+    ) -> Result<impl Stream<Item = Result<GenerateEvent, Status>> + Send + 'static, Status> {
+        let resp = self.dispatch(req).await?;
+        let is_stream = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("text/event-stream"));
+        if !is_stream {
+            return Err(Status::internal("Upstream did not return an event stream"));
+        }
+        Ok(resp
+            .into_body()
+            .into_data_stream()
+            .eventsource()
+            .map(|event| match event {
+                Ok(event) => Ok(GenerateEvent {
+                    event: event.event,
+                    data: event.data,
+                }),
+                Err(e) => Err(Status::internal(e.to_string())),
+            }))
+    }
+
+    /// Runs the request on the router, failed requests become a status carrying the error body
+    async fn dispatch(&self, req: Request) -> Result<Response, Status> {
+        let resp = self
+            .router
+            .to_owned()
+            .oneshot(req)
+            .await
+            .unwrap_or_else(|e| match e {});
+        if resp.status().is_success() {
+            return Ok(resp);
+        }
+        let code = status_code(resp.status());
+        let body = to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap_or_default();
+        Err(Status::new(code, String::from_utf8_lossy(&body)))
+    }
+}
+
+/// gRPC code for an HTTP error status
+fn status_code(status: StatusCode) -> Code {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::PAYLOAD_TOO_LARGE => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+        StatusCode::NOT_IMPLEMENTED => Code::Unimplemented,
+        s if s.is_server_error() => Code::Unavailable,
+        _ => Code::Unknown,
+    }
+}
+
+impl Service<http::Request<BoxBody>> for ChatService {
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<BoxBody>) -> Self::Future {
+        let Some(key) = bearer(&req).filter(|t| CLEWDR_CONFIG.load().user_auth(t)) else {
+            return Box::pin(async { Ok(Status::unauthenticated("Invalid API key").into_http()) });
+        };
+        let peer = remote_addr(&req);
+        let this = self.to_owned();
+        Box::pin(async move {
+            Ok(match req.uri().path() {
+                "/clewdr.ChatService/Generate" => {
+                    unary(req, move |msg| async move {
+                        this.generate(Self::request(&key, peer, msg, false)?).await
+                    })
+                    .await
+                }
+                "/clewdr.ChatService/StreamGenerate" => {
+                    server_streaming(req, move |msg| async move {
+                        this.stream_generate(Self::request(&key, peer, msg, true)?)
+                            .await
+                    })
+                    .await
+                }
+                _ => Status::unimplemented("Unknown method").into_http(),
+            })
+        })
+    }
+}
+
+impl NamedService for ChatService {
+    const NAME: &'static str = "clewdr.ChatService";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_errors_map_to_grpc_codes() {
+        assert_eq!(status_code(StatusCode::BAD_REQUEST), Code::InvalidArgument);
+        assert_eq!(
+            status_code(StatusCode::TOO_MANY_REQUESTS),
+            Code::ResourceExhausted
+        );
+        assert_eq!(status_code(StatusCode::BAD_GATEWAY), Code::Unavailable);
+        assert_eq!(status_code(StatusCode::CONFLICT), Code::Unknown);
+    }
+}
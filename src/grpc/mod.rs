@@ -0,0 +1,124 @@
+//! gRPC surface for typed clients, see `proto/clewdr.proto`
+//!
+//! Both services share the actor handles and the HTTP router of the main server,
+//! so cookies, keys and request handling are the same whichever transport is used.
+// every handler answers with `tonic::Status`, boxing it would only add noise
+#![allow(clippy::result_large_err)]
+mod admin;
+mod chat;
+pub mod proto;
+
+use std::{convert::Infallible, future::Future, net::SocketAddr, pin::Pin};
+
+use axum::Router;
+use colored::Colorize;
+use futures::Stream;
+use tonic::{
+    Status,
+    body::BoxBody,
+    codec::ProstCodec,
+    server::Grpc,
+    transport::{Server, server::TcpConnectInfo},
+};
+use tracing::{error, info};
+
+pub use admin::AdminService;
+pub use chat::ChatService;
+
+use crate::{
+    config::CLEWDR_CONFIG,
+    services::{cookie_actor::CookieActorHandle, key_actor::KeyActorHandle},
+};
+
+type ResponseFuture =
+    Pin<Box<dyn Future<Output = Result<http::Response<BoxBody>, Infallible>> + Send>>;
+
+/// Serves ChatService and AdminService on `grpc_listen`, `None` when it is unset
+///
+/// # Arguments
+/// * `router` - HTTP router chat requests are dispatched to
+/// * `cookie_handle` - Cookie actor of the HTTP server
+/// * `key_handle` - Key actor of the HTTP server
+pub fn spawn(
+    router: Router,
+    cookie_handle: CookieActorHandle,
+    key_handle: KeyActorHandle,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let addr = CLEWDR_CONFIG.load().grpc_listen?;
+    Some(tokio::spawn(async move {
+        info!("gRPC listening on {}", addr.to_string().green());
+        let result = Server::builder()
+            .add_service(ChatService::new(router))
+            .add_service(AdminService::new(cookie_handle, key_handle))
+            .serve_with_shutdown(addr, async {
+                _ = tokio::signal::ctrl_c().await;
+            })
+            .await;
+        if let Err(e) = result {
+            error!("gRPC server failed: {}", e);
+        }
+    }))
+}
+
+/// Bearer token of the `authorization` metadata
+fn bearer<B>(req: &http::Request<B>) -> Option<String> {
+    req.headers()
+        .get(http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|t| t.to_string())
+}
+
+/// Address of the connected client
+fn remote_addr<B>(req: &http::Request<B>) -> Option<SocketAddr> {
+    req.extensions()
+        .get::<TcpConnectInfo>()
+        .and_then(|info| info.remote_addr())
+}
+
+/// Decodes a unary call, runs `handler` on its message and encodes the reply
+async fn unary<M, R, F, Fut>(req: http::Request<BoxBody>, handler: F) -> http::Response<BoxBody>
+where
+    M: prost::Message + Default + Send + 'static,
+    R: prost::Message + Send + 'static,
+    F: FnOnce(M) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<R, Status>> + Send,
+{
+    let mut handler = Some(handler);
+    let service = tower::service_fn(move |req: tonic::Request<M>| {
+        let handler = handler.take();
+        async move {
+            let handler = handler.ok_or_else(|| Status::internal("Call handled twice"))?;
+            handler(req.into_inner()).await.map(tonic::Response::new)
+        }
+    });
+    Grpc::new(ProstCodec::<R, M>::default())
+        .unary(service, req)
+        .await
+}
+
+/// Decodes a server streaming call, runs `handler` on its message and encodes every item of the reply
+async fn server_streaming<M, R, S, F, Fut>(
+    req: http::Request<BoxBody>,
+    handler: F,
+) -> http::Response<BoxBody>
+where
+    M: prost::Message + Default + Send + 'static,
+    R: prost::Message + Send + 'static,
+    S: Stream<Item = Result<R, Status>> + Send + 'static,
+    F: FnOnce(M) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<S, Status>> + Send,
+{
+    let mut handler = Some(handler);
+    let service = tower::service_fn(move |req: tonic::Request<M>| {
+        let handler = handler.take();
+        async move {
+            let handler = handler.ok_or_else(|| Status::internal("Call handled twice"))?;
+            handler(req.into_inner()).await.map(tonic::Response::new)
+        }
+    });
+    Grpc::new(ProstCodec::<R, M>::default())
+        .server_streaming(service, req)
+        .await
+}
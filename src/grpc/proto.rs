@@ -0,0 +1,120 @@
+//! Messages of `proto/clewdr.proto`, kept by hand so building needs no `protoc`
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Provider {
+    ClaudeWeb = 0,
+    ClaudeCode = 1,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GenerateRequest {
+    #[prost(enumeration = "Provider", tag = "1")]
+    pub provider: i32,
+    /// Claude Messages API request body, JSON encoded
+    #[prost(string, tag = "2")]
+    pub body: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GenerateResponse {
+    /// Claude Messages API response body, JSON encoded
+    #[prost(string, tag = "1")]
+    pub body: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GenerateEvent {
+    #[prost(string, tag = "1")]
+    pub event: String,
+    #[prost(string, tag = "2")]
+    pub data: String,
+}
+
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct Empty {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Cookie {
+    #[prost(string, tag = "1")]
+    pub cookie: String,
+    #[prost(bool, tag = "2")]
+    pub disabled: bool,
+    #[prost(int64, optional, tag = "3")]
+    pub reset_time: Option<i64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct InvalidCookie {
+    #[prost(string, tag = "1")]
+    pub cookie: String,
+    #[prost(string, tag = "2")]
+    pub reason: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CookieList {
+    #[prost(message, repeated, tag = "1")]
+    pub valid: Vec<Cookie>,
+    #[prost(message, repeated, tag = "2")]
+    pub exhausted: Vec<Cookie>,
+    #[prost(message, repeated, tag = "3")]
+    pub invalid: Vec<InvalidCookie>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CookieRequest {
+    #[prost(string, tag = "1")]
+    pub cookie: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Key {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(bool, tag = "2")]
+    pub disabled: bool,
+    #[prost(uint64, tag = "3")]
+    pub count_requests: u64,
+    #[prost(uint64, tag = "4")]
+    pub count_tokens: u64,
+    #[prost(int64, optional, tag = "5")]
+    pub quarantined_until: Option<i64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct KeyList {
+    #[prost(message, repeated, tag = "1")]
+    pub valid: Vec<Key>,
+    #[prost(message, repeated, tag = "2")]
+    pub invalid: Vec<Key>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct KeyRequest {
+    #[prost(string, tag = "1")]
+    pub key: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SetDisabledRequest {
+    /// Cookie or key, depending on the method
+    #[prost(string, tag = "1")]
+    pub value: String,
+    #[prost(bool, tag = "2")]
+    pub disabled: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StatusResponse {
+    #[prost(string, tag = "1")]
+    pub version: String,
+    #[prost(uint32, tag = "2")]
+    pub valid_cookies: u32,
+    #[prost(uint32, tag = "3")]
+    pub exhausted_cookies: u32,
+    #[prost(uint32, tag = "4")]
+    pub invalid_cookies: u32,
+    #[prost(uint32, tag = "5")]
+    pub valid_keys: u32,
+}
@@ -8,13 +8,19 @@ use crate::config::CLEWDR_CONFIG;
 pub mod api;
 pub mod claude_code_state;
 pub mod claude_web_state;
+pub mod cli;
 pub mod config;
 pub mod error;
 pub mod gemini_state;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod middleware;
 pub mod persistence;
 pub mod providers;
+#[cfg(feature = "test-fixtures")]
+pub mod replay;
 pub mod router;
+pub mod server;
 pub mod services;
 pub mod types;
 pub mod utils;
@@ -77,6 +83,10 @@ pub struct Args {
     #[arg(short, long)]
     /// Force update of the application
     pub update: bool,
+    #[cfg(feature = "portable")]
+    #[arg(long)]
+    /// Restore the binary replaced by the last update
+    pub rollback: bool,
     #[arg(short, long)]
     /// load cookie from file
     pub file: Option<PathBuf>,
@@ -86,4 +96,6 @@ pub struct Args {
     #[arg(short, long)]
     /// Alternative log directory
     pub log_dir: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<cli::Command>,
 }
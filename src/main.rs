@@ -1,7 +1,9 @@
+use clap::Parser;
 use clewdr::{
-    self, FIG, IS_DEBUG,
-    config::{CLEWDR_CONFIG, CONFIG_PATH, LOG_DIR},
+    self, Args, FIG, IS_DEBUG,
+    config::{CLEWDR_CONFIG, CONFIG_PATH, LOG_DIR, LogFormat},
     error::ClewdrError,
+    services::{log_broadcast::LogBroadcastLayer, log_level},
     version_info_colored,
 };
 use colored::Colorize;
@@ -10,7 +12,7 @@ use mimalloc::MiMalloc;
 use tracing::Subscriber;
 use tracing_subscriber::{
     Layer, Registry,
-    fmt::{self, time::ChronoLocal},
+    fmt::{self, MakeWriter, time::ChronoLocal},
     layer::SubscriberExt,
     registry::LookupSpan,
 };
@@ -42,6 +44,25 @@ where
     tracing::subscriber::set_global_default(subscriber).expect("unable to set global subscriber");
 }
 
+/// Formatting layer writing to `writer` in the configured log format
+fn fmt_layer<S, W>(writer: W, timer: ChronoLocal) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + 'static,
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let layer = fmt::Layer::default().with_writer(writer).with_timer(timer);
+    match CLEWDR_CONFIG.load().log_format {
+        LogFormat::Text => layer.boxed(),
+        // request_id, provider and cookie come from the request span
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    }
+}
+
 /// Application entry point
 /// Sets up logging, checks for updates, initializes the application state,
 /// creates the router, and starts the server
@@ -70,28 +91,17 @@ async fn main() -> Result<(), ClewdrError> {
     } else {
         tracing_subscriber::filter::LevelFilter::INFO
     };
-    let env_filter = tracing_subscriber::EnvFilter::builder()
-        .with_default_directive(filter.into())
-        .from_env_lossy();
-    let subscriber = Registry::default().with(
-        fmt::Layer::default()
-            .with_writer(std::io::stdout)
-            .with_timer(timer.to_owned())
-            .with_filter(env_filter),
-    );
+    // outputs share the directives, which can be changed at runtime
+    let env_filter = log_level::reloadable(filter);
+    let subscriber = Registry::default()
+        .with(fmt_layer(std::io::stdout, timer.to_owned()).with_filter(env_filter))
+        .with(LogBroadcastLayer.with_filter(filter));
     let _guard = if !CLEWDR_CONFIG.load().no_fs && CLEWDR_CONFIG.load().log_to_file {
         std::fs::create_dir_all(LOG_DIR.as_path()).expect("Failed to create log directory");
         let file_appender = tracing_appender::rolling::daily(LOG_DIR.as_path(), "clewdr.log");
         let (file_writer, guard) = tracing_appender::non_blocking(file_appender);
-        let filter = tracing_subscriber::EnvFilter::builder()
-            .with_default_directive(filter.into())
-            .from_env_lossy();
-        let subscriber = subscriber.with(
-            fmt::Layer::default()
-                .with_writer(file_writer)
-                .with_timer(timer)
-                .with_filter(filter),
-        );
+        let filter = log_level::reloadable(filter);
+        let subscriber = subscriber.with(fmt_layer(file_writer, timer).with_filter(filter));
         setup_subscriber(subscriber);
         Some(guard)
     } else {
@@ -101,6 +111,11 @@ async fn main() -> Result<(), ClewdrError> {
 
     println!("{}\n{}", FIG, version_info_colored());
 
+    #[cfg(feature = "portable")]
+    if Args::try_parse().is_ok_and(|a| a.rollback) {
+        return clewdr::services::update::ClewdrUpdater::rollback();
+    }
+
     #[cfg(feature = "portable")]
     {
         use tracing::warn;
@@ -110,6 +125,10 @@ async fn main() -> Result<(), ClewdrError> {
         }
     }
 
+    if let Some(command) = Args::try_parse().ok().and_then(|a| a.command) {
+        return clewdr::cli::run(command).await;
+    }
+
     if let Err(e) = clewdr::persistence::storage().spawn_bootstrap().await {
         use tracing::warn;
         warn!("DB bootstrap skipped or failed: {}", e);
@@ -120,19 +139,12 @@ async fn main() -> Result<(), ClewdrError> {
     println!("{}", *CLEWDR_CONFIG);
 
     // build axum router
-    // create a TCP listener
-    let addr = CLEWDR_CONFIG.load().address();
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    let router = clewdr::router::RouterBuilder::new()
+    let builder = clewdr::router::RouterBuilder::new()
         .await
-        .with_default_setup()
-        .build();
-    // serve the application
-    Ok(axum::serve(listener, router)
-        .with_graceful_shutdown(async {
-            tokio::signal::ctrl_c()
-                .await
-                .expect("Failed to install Ctrl-C handler");
-        })
-        .await?)
+        .with_default_setup();
+    #[cfg(feature = "grpc")]
+    let _grpc = builder.spawn_grpc();
+    let router = builder.build();
+    // serve the application, over TLS if configured
+    clewdr::server::serve(router).await
 }
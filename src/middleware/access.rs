@@ -0,0 +1,133 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use tracing::warn;
+
+use crate::{
+    config::{AccessControlConfig, CLEWDR_CONFIG},
+    error::ClewdrError,
+};
+
+/// Resolves the address of the client
+///
+/// Every proxy appends the address it received the request from to `x-forwarded-for`, and
+/// clients can put anything in front. The rightmost hop that is not a trusted proxy is the
+/// client, hops to its left are never looked at.
+///
+/// # Arguments
+/// * `headers` - Request headers
+/// * `peer` - Address of the connected socket, `None` for Unix domain sockets
+/// * `acl` - Whether and from which proxies `x-forwarded-for` and `x-real-ip` are trusted
+///
+/// # Returns
+/// * `Option<IpAddr>` - The client address, if known
+fn client_ip(
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+    acl: &AccessControlConfig,
+) -> Option<IpAddr> {
+    if !acl.trust_proxy_headers
+        || (!acl.trusted_proxies.is_empty() && peer.is_some_and(|p| !acl.is_trusted_proxy(p)))
+    {
+        return peer;
+    }
+    let hops = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|hop| hop.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+    if hops.is_empty() {
+        return headers
+            .get("x-real-ip")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok())
+            .or(peer);
+    }
+    let mut client = None;
+    for hop in hops.into_iter().rev() {
+        // a malformed hop was not written by a trusted proxy
+        let Some(ip) = hop else {
+            break;
+        };
+        client = Some(ip);
+        if !acl.is_trusted_proxy(ip) {
+            break;
+        }
+    }
+    client.or(peer)
+}
+
+/// Middleware enforcing the `access_control` allowlist and denylist
+///
+/// Connections without a peer address, such as Unix domain sockets, are local and always allowed.
+pub async fn access_control(req: Request, next: Next) -> Result<Response, ClewdrError> {
+    let config = CLEWDR_CONFIG.load();
+    let acl = &config.access_control;
+    if !acl.is_active() {
+        drop(config);
+        return Ok(next.run(req).await);
+    }
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    if let Some(ip) = client_ip(req.headers(), peer, acl)
+        && !acl.is_allowed(ip)
+    {
+        warn!("Rejected request from {} to {}", ip, req.uri().path());
+        return Err(ClewdrError::AccessDenied { ip: ip.to_string() });
+    }
+    drop(config);
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarded_client_ip() {
+        let peer = Some("127.0.0.1".parse().unwrap());
+        let untrusted = AccessControlConfig::default();
+        let mut acl = AccessControlConfig {
+            trust_proxy_headers: true,
+            ..Default::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            "1.1.1.1, 203.0.113.7, 10.0.0.1".parse().unwrap(),
+        );
+        assert_eq!(client_ip(&headers, peer, &untrusted), peer);
+        // only the connected proxy is trusted, it appended the client
+        assert_eq!(
+            client_ip(&headers, peer, &acl),
+            Some("10.0.0.1".parse().unwrap())
+        );
+        acl.trusted_proxies = vec![
+            "127.0.0.1/32".parse().unwrap(),
+            "10.0.0.0/8".parse().unwrap(),
+        ];
+        assert_eq!(
+            client_ip(&headers, peer, &acl),
+            Some("203.0.113.7".parse().unwrap())
+        );
+        // a peer that is not a trusted proxy cannot pick its address
+        let stranger = Some("192.0.2.9".parse().unwrap());
+        assert_eq!(client_ip(&headers, stranger, &acl), stranger);
+        headers.remove("x-forwarded-for");
+        headers.insert("x-real-ip", "198.51.100.2".parse().unwrap());
+        assert_eq!(
+            client_ip(&headers, peer, &acl),
+            Some("198.51.100.2".parse().unwrap())
+        );
+        assert_eq!(client_ip(&HeaderMap::new(), None, &acl), None);
+    }
+}
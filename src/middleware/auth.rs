@@ -2,9 +2,15 @@ use axum::extract::FromRequestParts;
 use axum_auth::AuthBearer;
 use tracing::warn;
 
-use super::gemini::GeminiArgs;
+use super::{gemini::GeminiArgs, tenant::Tenant};
 use crate::{config::CLEWDR_CONFIG, error::ClewdrError};
 
+/// Whether `key` may use the API, as a password of the tenant the request belongs to if any
+fn api_auth(parts: &axum::http::request::Parts, key: &str) -> bool {
+    let tenant = parts.extensions.get::<Tenant>().map(|t| t.0.as_str());
+    CLEWDR_CONFIG.load().api_auth(tenant, key)
+}
+
 /// Extractor for the X-API-Key header used in Claude API compatibility
 ///
 /// This struct extracts the API key from the "x-api-key" header and makes it
@@ -40,7 +46,7 @@ where
         _: &S,
     ) -> Result<Self, Self::Rejection> {
         let query = GeminiArgs::from_request_parts(parts, &()).await?;
-        if !CLEWDR_CONFIG.load().user_auth(&query.key) {
+        if !api_auth(parts, &query.key) {
             warn!("Invalid query key: {}", query.key);
             return Err(ClewdrError::InvalidAuth);
         }
@@ -48,24 +54,32 @@ where
     }
 }
 
+/// Reads the Bearer token of an admin API request
+async fn admin_token(parts: &mut axum::http::request::Parts) -> Result<String, ClewdrError> {
+    let AuthBearer(key) = AuthBearer::from_request_parts(parts, &())
+        .await
+        .map_err(|_| ClewdrError::InvalidAuth)?;
+    Ok(key)
+}
+
 /// Middleware guard that ensures requests have valid admin authentication
 ///
-/// This extractor checks for a valid admin authorization token in the Bearer Auth header.
-/// It can be used on routes that should only be accessible to administrators.
+/// This extractor checks for the admin password in the Bearer Auth header.
+/// It guards every admin route that changes state or exposes secrets.
 ///
 /// # Example
 ///
 /// ```ignore
 /// async fn admin_only_handler(
-///     _: RequireAdminAuth,
+///     _: RequireAdminWrite,
 ///     // other extractors...
 /// ) -> impl IntoResponse {
 ///     // This handler only executes if admin authentication succeeds
 ///     // ...
 /// }
 /// ```ignore
-pub struct RequireAdminAuth;
-impl<S> FromRequestParts<S> for RequireAdminAuth
+pub struct RequireAdminWrite;
+impl<S> FromRequestParts<S> for RequireAdminWrite
 where
     S: Sync,
 {
@@ -74,9 +88,7 @@ where
         parts: &mut axum::http::request::Parts,
         _: &S,
     ) -> Result<Self, Self::Rejection> {
-        let AuthBearer(key) = AuthBearer::from_request_parts(parts, &())
-            .await
-            .map_err(|_| ClewdrError::InvalidAuth)?;
+        let key = admin_token(parts).await?;
         if !CLEWDR_CONFIG.load().admin_auth(&key) {
             warn!("Invalid admin key");
             return Err(ClewdrError::InvalidAuth);
@@ -85,6 +97,29 @@ where
     }
 }
 
+/// Middleware guard for read-only admin routes
+///
+/// Accepts the admin password as well as the viewer password, so status pages
+/// can be shared without granting control over cookies, keys or the config.
+pub struct RequireAdminRead;
+impl<S> FromRequestParts<S> for RequireAdminRead
+where
+    S: Sync,
+{
+    type Rejection = ClewdrError;
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let key = admin_token(parts).await?;
+        if !CLEWDR_CONFIG.load().viewer_auth(&key) {
+            warn!("Invalid viewer key");
+            return Err(ClewdrError::InvalidAuth);
+        }
+        Ok(Self)
+    }
+}
+
 /// Middleware guard that ensures requests have valid OpenAI API authentication
 ///
 /// This extractor validates the Bearer token against the configured OpenAI API keys.
@@ -114,7 +149,7 @@ where
         let AuthBearer(key) = AuthBearer::from_request_parts(parts, &())
             .await
             .map_err(|_| ClewdrError::InvalidAuth)?;
-        if !CLEWDR_CONFIG.load().user_auth(&key) {
+        if !api_auth(parts, &key) {
             warn!("Invalid Bearer key: {}", key);
             return Err(ClewdrError::InvalidAuth);
         }
@@ -137,7 +172,7 @@ where
         _: &S,
     ) -> Result<Self, Self::Rejection> {
         let XApiKey(key) = XApiKey::from_request_parts(parts, &()).await?;
-        if !CLEWDR_CONFIG.load().user_auth(&key) {
+        if !api_auth(parts, &key) {
             warn!("Invalid x-api-key: {}", key);
             return Err(ClewdrError::InvalidAuth);
         }
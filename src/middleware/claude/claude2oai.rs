@@ -1,9 +1,16 @@
+use std::collections::HashMap;
+
 use axum::response::sse::Event;
-use futures::{Stream, TryStreamExt};
+use futures::{Stream, TryStreamExt, future};
 use serde::Serialize;
-use serde_json::Value;
+use serde_json::{Value, json};
 
-use crate::types::claude::{ContentBlockDelta, CreateMessageResponse, StreamEvent};
+use crate::types::{
+    claude::{
+        Citation, ContentBlock, ContentBlockDelta, CreateMessageResponse, StopReason, StreamEvent,
+    },
+    oai::{STRUCTURED_OUTPUT_TOOL, url_citation},
+};
 
 /// Represents the data structure for streaming events in OpenAI API format
 /// Contains a choices array with deltas of content
@@ -17,12 +24,17 @@ impl StreamEventData {
     ///
     /// # Arguments
     /// * `content` - The event content to include
+    /// * `choice` - Index of the choice the content belongs to
     ///
     /// # Returns
     /// A new StreamEventData instance with the content wrapped in choices array
-    fn new(content: EventContent) -> Self {
+    fn new(content: EventContent, choice: usize) -> Self {
         Self {
-            choices: vec![StreamEventDelta { delta: content }],
+            choices: vec![StreamEventDelta {
+                index: choice,
+                delta: content,
+                finish_reason: None,
+            }],
         }
     }
 }
@@ -31,7 +43,10 @@ impl StreamEventData {
 /// Contains the content change for the current chunk
 #[derive(Debug, Serialize)]
 struct StreamEventDelta {
+    index: usize,
     delta: EventContent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finish_reason: Option<&'static str>,
 }
 
 /// Content of an event, either regular content or reasoning (thinking mode)
@@ -41,29 +56,160 @@ struct StreamEventDelta {
 pub enum EventContent {
     Content { content: String },
     Reasoning { reasoning_content: String },
+    ToolCalls { tool_calls: Vec<ToolCallDelta> },
+    Annotations { annotations: Vec<Value> },
+    Empty {},
+}
+
+/// Incremental function call in OpenAI streaming format
+///
+/// The first chunk of a call carries its id and name, later chunks only argument fragments.
+#[derive(Debug, Serialize)]
+pub struct ToolCallDelta {
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    type_: Option<&'static str>,
+    function: FunctionDelta,
+}
+
+#[derive(Debug, Serialize)]
+struct FunctionDelta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    arguments: String,
 }
 
 /// Creates an SSE event with the given content in OpenAI format
 ///
 /// # Arguments
 /// * `content` - The event content to include
+/// * `choice` - Index of the choice the content belongs to
 ///
 /// # Returns
 /// A formatted SSE Event ready to be sent to the client
-pub fn build_event(content: EventContent) -> Event {
+pub fn build_event(content: EventContent, choice: usize) -> Event {
     let event = Event::default();
-    let data = StreamEventData::new(content);
+    let data = StreamEventData::new(content, choice);
     event.json_data(data).unwrap()
 }
 
+/// Creates the final SSE event carrying the finish reason
+fn build_finish_event(reason: &'static str, choice: usize) -> Event {
+    let data = StreamEventData {
+        choices: vec![StreamEventDelta {
+            index: choice,
+            delta: EventContent::Empty {},
+            finish_reason: Some(reason),
+        }],
+    };
+    Event::default().json_data(data).unwrap()
+}
+
+/// Maps a Claude stop reason to an OpenAI finish reason
+fn finish_reason(reason: Option<&StopReason>) -> &'static str {
+    match reason {
+        Some(StopReason::EndTurn) => "stop",
+        Some(StopReason::MaxTokens) => "length",
+        Some(StopReason::StopSequence) => "stop",
+        Some(StopReason::ToolUse) => "tool_calls",
+        Some(StopReason::Refusal) => "content_filter",
+        None => "stop",
+    }
+}
+
+/// Collects the pages returned by a Claude.ai web search tool result as annotations
+///
+/// Results are `knowledge` items with a title and URL, other tool results yield nothing.
+fn search_sources(content: &Value) -> Vec<Value> {
+    content
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|item| item["type"] == "knowledge")
+        .filter_map(|item| {
+            let url = item["url"].as_str().filter(|u| !u.is_empty())?;
+            let title = item["title"].as_str().unwrap_or_default();
+            Some(url_citation(url, title, None))
+        })
+        .collect()
+}
+
+/// Holds back `input_json_delta` fragments until the arguments streamed so far end at a
+/// JSON boundary, outside any string and right after a complete value (`,`, `}` or `]`)
+#[derive(Default)]
+struct JsonCoalescer {
+    pending: String,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonCoalescer {
+    /// Adds a fragment, returns the arguments ready to be sent
+    fn push(&mut self, fragment: &str) -> Option<String> {
+        let mut boundary = None;
+        for (i, c) in fragment.char_indices() {
+            if self.in_string {
+                match c {
+                    _ if self.escaped => self.escaped = false,
+                    '\\' => self.escaped = true,
+                    '"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => self.in_string = true,
+                ',' | '}' | ']' => boundary = Some(self.pending.len() + i + 1),
+                _ => {}
+            }
+        }
+        self.pending.push_str(fragment);
+        let rest = self.pending.split_off(boundary?);
+        Some(std::mem::replace(&mut self.pending, rest))
+    }
+
+    /// Arguments still held back, sent when the tool use block ends
+    fn flush(&mut self) -> Option<String> {
+        (!self.pending.is_empty()).then(|| std::mem::take(&mut self.pending))
+    }
+}
+
+/// Event carrying a fragment of the arguments of a tool call
+fn arguments_event(tool_index: usize, arguments: String, choice: usize) -> Event {
+    build_event(
+        EventContent::ToolCalls {
+            tool_calls: vec![ToolCallDelta {
+                index: tool_index,
+                id: None,
+                type_: None,
+                function: FunctionDelta {
+                    name: None,
+                    arguments,
+                },
+            }],
+        },
+        choice,
+    )
+}
+
 /// Transforms a Claude.ai event stream into an OpenAI-compatible event stream
 ///
 /// Extracts content from Claude events and reformats them to match OpenAI's streaming format.
 /// This function processes each event in the stream, identifying the delta content type
-/// (text or thinking), and converting it to the appropriate OpenAI-compatible event format.
+/// (text, thinking or tool input), and converting it to the appropriate OpenAI-compatible event format.
+/// Tool use blocks are renumbered so OpenAI tool call indexes start at 0, the
+/// structured output tool is streamed as plain content.
+/// Web search results and citations become `url_citation` annotations, citations are
+/// positioned by the characters of content streamed before them.
+/// With `coalesce`, tool call arguments are only split at JSON boundaries, for clients
+/// that parse every fragment.
 ///
 /// # Arguments
 /// * `s` - The input stream of Claude.ai events
+/// * `choice` - Index of the OpenAI choice the stream is emitted as
+/// * `coalesce` - Whether to coalesce tool call argument fragments
 ///
 /// # Returns
 /// A stream of OpenAI-compatible SSE events
@@ -71,29 +217,127 @@ pub fn build_event(content: EventContent) -> Event {
 /// # Type Parameters
 /// * `I` - The input stream type
 /// * `E` - The error type for the stream
-pub fn transform_stream<I, E>(s: I) -> impl Stream<Item = Result<Event, E>>
+pub fn transform_stream<I, E>(
+    s: I,
+    choice: usize,
+    coalesce: bool,
+) -> impl Stream<Item = Result<Event, E>>
 where
     I: Stream<Item = Result<eventsource_stream::Event, E>>,
 {
-    s.try_filter_map(async |eventsource_stream::Event { data, .. }| {
-        let Ok(parsed) = serde_json::from_str::<StreamEvent>(&data) else {
-            return Ok(None);
-        };
-        let StreamEvent::ContentBlockDelta { delta, .. } = parsed else {
-            return Ok(None);
-        };
-        match delta {
-            ContentBlockDelta::TextDelta { text } => {
-                Ok(Some(build_event(EventContent::Content { content: text })))
+    // Claude block index -> OpenAI tool call index
+    let mut tool_indexes = HashMap::new();
+    // Claude block index -> arguments held back
+    let mut coalescers = HashMap::<usize, JsonCoalescer>::new();
+    let mut structured = None;
+    // characters of content streamed so far, and citations waiting for their end
+    let mut content_len = 0;
+    let mut citations = HashMap::<String, (Citation, usize)>::new();
+    s.map_ok(move |eventsource_stream::Event { data, .. }| {
+        let parsed = serde_json::from_str::<StreamEvent>(&data).ok()?;
+        match parsed {
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block: ContentBlock::ToolUse { name, .. },
+            } if name == STRUCTURED_OUTPUT_TOOL => {
+                structured = Some(index);
+                None
+            }
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block: ContentBlock::ToolUse { id, name, .. },
+            } => {
+                let tool_index = tool_indexes.len();
+                tool_indexes.insert(index, tool_index);
+                Some(build_event(
+                    EventContent::ToolCalls {
+                        tool_calls: vec![ToolCallDelta {
+                            index: tool_index,
+                            id: Some(id),
+                            type_: Some("function"),
+                            function: FunctionDelta {
+                                name: Some(name),
+                                arguments: String::new(),
+                            },
+                        }],
+                    },
+                    choice,
+                ))
+            }
+            StreamEvent::ContentBlockStart {
+                content_block: ContentBlock::ToolResult { content, .. },
+                ..
+            } => {
+                let annotations = search_sources(&content);
+                (!annotations.is_empty())
+                    .then(|| build_event(EventContent::Annotations { annotations }, choice))
+            }
+            StreamEvent::ContentBlockDelta { index, delta } => match delta {
+                ContentBlockDelta::TextDelta { text } => {
+                    content_len += text.chars().count();
+                    Some(build_event(EventContent::Content { content: text }, choice))
+                }
+                ContentBlockDelta::CitationStartDelta { citation } => {
+                    citations.insert(citation.uuid.to_owned(), (citation, content_len));
+                    None
+                }
+                ContentBlockDelta::CitationEndDelta { citation_uuid } => {
+                    let (citation, start) = citations.remove(&citation_uuid)?;
+                    let annotation =
+                        url_citation(&citation.url, &citation.title, Some((start, content_len)));
+                    Some(build_event(
+                        EventContent::Annotations {
+                            annotations: vec![annotation],
+                        },
+                        choice,
+                    ))
+                }
+                ContentBlockDelta::ThinkingDelta { thinking } => Some(build_event(
+                    EventContent::Reasoning {
+                        reasoning_content: thinking,
+                    },
+                    choice,
+                )),
+                ContentBlockDelta::InputJsonDelta { partial_json } if structured == Some(index) => {
+                    Some(build_event(
+                        EventContent::Content {
+                            content: partial_json,
+                        },
+                        choice,
+                    ))
+                }
+                ContentBlockDelta::InputJsonDelta { partial_json } => {
+                    let tool_index = *tool_indexes.get(&index)?;
+                    let arguments = if coalesce {
+                        coalescers.entry(index).or_default().push(&partial_json)?
+                    } else {
+                        partial_json
+                    };
+                    Some(arguments_event(tool_index, arguments, choice))
+                }
+                _ => None,
+            },
+            StreamEvent::ContentBlockStop { index } => {
+                let arguments = coalescers.remove(&index)?.flush()?;
+                Some(arguments_event(
+                    *tool_indexes.get(&index)?,
+                    arguments,
+                    choice,
+                ))
             }
-            ContentBlockDelta::ThinkingDelta { thinking } => {
-                Ok(Some(build_event(EventContent::Reasoning {
-                    reasoning_content: thinking,
-                })))
+            StreamEvent::MessageDelta { delta, .. } => {
+                let reason = match delta.stop_reason? {
+                    StopReason::ToolUse if tool_indexes.is_empty() && structured.is_some() => {
+                        "stop"
+                    }
+                    reason => finish_reason(Some(&reason)),
+                };
+                Some(build_finish_event(reason, choice))
             }
-            _ => Ok(None),
+            _ => None,
         }
     })
+    .try_filter_map(|e| future::ready(Ok(e)))
 }
 
 pub fn transforms_json(input: CreateMessageResponse) -> Value {
@@ -101,13 +345,41 @@ pub fn transforms_json(input: CreateMessageResponse) -> Value {
         .content
         .iter()
         .filter_map(|block| match block {
-            crate::types::claude::ContentBlock::Text { text } => Some(text.clone()),
+            ContentBlock::Text { text } => Some(text.clone()),
+            ContentBlock::ToolUse { name, input, .. } if name == STRUCTURED_OUTPUT_TOOL => {
+                Some(input.to_string())
+            }
             _ => None,
         })
         .collect::<String>();
+    let tool_calls = input
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::ToolUse { name, .. } if name == STRUCTURED_OUTPUT_TOOL => None,
+            ContentBlock::ToolUse { id, name, input } => Some(json!({
+                "id": id,
+                "type": "function",
+                "function": {
+                    "name": name,
+                    "arguments": input.to_string(),
+                },
+            })),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let annotations = input
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::ToolResult { content, .. } => Some(search_sources(content)),
+            _ => None,
+        })
+        .flatten()
+        .collect::<Vec<_>>();
 
     let usage = input.usage.as_ref().map(|u| {
-        serde_json::json!({
+        json!({
             "prompt_tokens": u.input_tokens,
             "completion_tokens": u.output_tokens,
             "total_tokens": u.input_tokens + u.output_tokens
@@ -115,15 +387,25 @@ pub fn transforms_json(input: CreateMessageResponse) -> Value {
     });
 
     let finish_reason = match input.stop_reason {
-        Some(crate::types::claude::StopReason::EndTurn) => "stop",
-        Some(crate::types::claude::StopReason::MaxTokens) => "length",
-        Some(crate::types::claude::StopReason::StopSequence) => "stop",
-        Some(crate::types::claude::StopReason::ToolUse) => "tool_calls",
-        Some(crate::types::claude::StopReason::Refusal) => "content_filter",
-        None => "stop",
+        Some(StopReason::ToolUse) if tool_calls.is_empty() => "stop",
+        ref reason => finish_reason(reason.as_ref()),
     };
 
-    serde_json::json!({
+    let mut message = json!({
+        "role": "assistant",
+        "content": content
+    });
+    if !tool_calls.is_empty() {
+        if content.is_empty() {
+            message["content"] = Value::Null;
+        }
+        message["tool_calls"] = json!(tool_calls);
+    }
+    if !annotations.is_empty() {
+        message["annotations"] = json!(annotations);
+    }
+
+    json!({
         "id": input.id,
         "object": "chat.completion",
         "created": std::time::SystemTime::now()
@@ -133,12 +415,103 @@ pub fn transforms_json(input: CreateMessageResponse) -> Value {
         "model": input.model,
         "choices": [{
             "index": 0,
-            "message": {
-                "role": "assistant",
-                "content": content
-            },
+            "message": message,
             "finish_reason": finish_reason
         }],
         "usage": usage
     })
 }
+
+/// Merges the OpenAI responses of all candidates of a request into one response
+///
+/// The first response supplies the id and model, each response supplies its first choice.
+/// Prompt tokens are counted once like OpenAI does, completion tokens are summed.
+pub fn merge_choices(responses: Vec<Value>) -> Value {
+    let mut merged = Value::Null;
+    let mut choices = vec![];
+    let mut completion_tokens = 0;
+    for (i, mut response) in responses.into_iter().enumerate() {
+        if let Some(mut choice) = response["choices"].get_mut(0).map(Value::take) {
+            choice["index"] = json!(i);
+            choices.push(choice);
+        }
+        completion_tokens += response["usage"]["completion_tokens"]
+            .as_u64()
+            .unwrap_or_default();
+        if merged.is_null() {
+            merged = response;
+        }
+    }
+    merged["choices"] = json!(choices);
+    if let Some(prompt_tokens) = merged["usage"]["prompt_tokens"].as_u64() {
+        merged["usage"]["completion_tokens"] = json!(completion_tokens);
+        merged["usage"]["total_tokens"] = json!(prompt_tokens + completion_tokens);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_candidates_into_choices() {
+        let response = |content: &str, completion_tokens: u64| {
+            json!({
+                "id": format!("msg_{content}"),
+                "object": "chat.completion",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": "stop"
+                }],
+                "usage": {
+                    "prompt_tokens": 10,
+                    "completion_tokens": completion_tokens,
+                    "total_tokens": 10 + completion_tokens
+                }
+            })
+        };
+        let merged = merge_choices(vec![response("a", 3), response("b", 5)]);
+        assert_eq!(merged["id"], "msg_a");
+        assert_eq!(merged["choices"][1]["index"], 1);
+        assert_eq!(merged["choices"][1]["message"]["content"], "b");
+        assert_eq!(merged["usage"]["completion_tokens"], 8);
+        assert_eq!(merged["usage"]["total_tokens"], 18);
+    }
+
+    #[test]
+    fn web_search_results_become_annotations() {
+        let content = json!([
+            { "type": "knowledge", "title": "Rust", "url": "https://www.rust-lang.org" },
+            { "type": "knowledge", "title": "No url", "url": "" },
+            { "type": "text", "text": "ignored" }
+        ]);
+        let sources = search_sources(&content);
+        assert_eq!(sources.len(), 1);
+        assert_eq!(sources[0]["type"], "url_citation");
+        assert_eq!(
+            sources[0]["url_citation"]["url"],
+            "https://www.rust-lang.org"
+        );
+        assert!(sources[0]["url_citation"].get("start_index").is_none());
+
+        let cited = url_citation("https://a.example", "A", Some((3, 9)));
+        assert_eq!(cited["url_citation"]["start_index"], 3);
+        assert_eq!(cited["url_citation"]["end_index"], 9);
+    }
+
+    #[test]
+    fn tool_arguments_split_at_json_boundaries() {
+        let mut coalescer = JsonCoalescer::default();
+        assert_eq!(coalescer.push(r#"{"cmd": "ls -a, "#), None);
+        assert_eq!(coalescer.push(r#"-l\""#), None);
+        assert_eq!(
+            coalescer.push(r#"", "n": 1, "x"#).as_deref(),
+            Some(r#"{"cmd": "ls -a, -l\"", "n": 1,"#)
+        );
+        assert_eq!(coalescer.push(r#"": [1"#), None);
+        assert_eq!(coalescer.push("]}").as_deref(), Some(r#" "x": [1]}"#));
+        assert_eq!(coalescer.flush(), None);
+    }
+}
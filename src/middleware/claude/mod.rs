@@ -9,7 +9,7 @@ pub use response::*;
 pub use stop_sequences::*;
 use strum::Display;
 
-use crate::types::claude::Usage;
+use crate::{config::Priority, types::claude::Usage};
 
 /// Represents the format of the API response
 ///
@@ -67,6 +67,66 @@ impl ClaudeContext {
         }
     }
 
+    pub fn conversation_id(&self) -> Option<String> {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.conversation_id.to_owned(),
+            ClaudeContext::Code(_) => None,
+        }
+    }
+
+    pub fn requested_org(&self) -> Option<String> {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.org.to_owned(),
+            ClaudeContext::Code(_) => None,
+        }
+    }
+
+    pub fn cookie_tags(&self) -> Vec<String> {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.cookie_tags.to_owned(),
+            ClaudeContext::Code(ctx) => ctx.cookie_tags.to_owned(),
+        }
+    }
+
+    pub fn priority(&self) -> Priority {
+        match self {
+            ClaudeContext::Web(ctx) => ctx.priority,
+            ClaudeContext::Code(ctx) => ctx.priority,
+        }
+    }
+
+    /// `anthropic-version` for the Claude Code upstream
+    pub fn anthropic_version(&self) -> Option<String> {
+        match self {
+            ClaudeContext::Web(_) => None,
+            ClaudeContext::Code(ctx) => Some(ctx.anthropic_version.to_owned()),
+        }
+    }
+
+    /// Allowed client `anthropic-beta` flags for the Claude Code upstream
+    pub fn anthropic_betas(&self) -> Vec<String> {
+        match self {
+            ClaudeContext::Web(_) => Vec::new(),
+            ClaudeContext::Code(ctx) => ctx.anthropic_betas.to_owned(),
+        }
+    }
+
+    pub fn custom_prompt(&self) -> Option<String> {
+        match self {
+            ClaudeContext::Web(ctx) => Some(ctx.custom_prompt.to_owned()),
+            ClaudeContext::Code(_) => None,
+        }
+    }
+
+    /// Context of one candidate of a request with `n > 1`, candidates never continue a conversation
+    pub fn candidate(&self) -> Self {
+        let mut cx = self.to_owned();
+        if let ClaudeContext::Web(ctx) = &mut cx {
+            ctx.conversation_id = None;
+        }
+        cx
+    }
+
     pub fn usage(&self) -> &Usage {
         match self {
             ClaudeContext::Web(ctx) => &ctx.usage,
@@ -8,18 +8,30 @@ use std::{
 use axum::{
     Json,
     extract::{FromRequest, Request},
+    http::HeaderMap,
 };
 use serde_json::{Value, json};
+use tracing::debug;
 
 use crate::{
-    config::CLEWDR_CONFIG,
+    claude_web_state::{
+        bootstrap::{ORG_FIELD, ORG_HEADER},
+        conversation::{CONVERSATION_FIELD, CONVERSATION_HEADER, scoped_id},
+    },
+    config::{CLEWDR_CONFIG, Priority, TemplateVars, parse_tags, split_template_suffix},
     error::ClewdrError,
-    middleware::claude::{ClaudeApiFormat, ClaudeContext},
+    middleware::{
+        claude::{ClaudeApiFormat, ClaudeContext},
+        limits::{clamp_max_tokens, model_limit, validate_request},
+        routing::user_key,
+        tenant::Tenant,
+    },
     types::{
         claude::{
             ContentBlock, CreateMessageParams, Message, MessageContent, Role, Thinking, Usage,
+            default_max_tokens,
         },
-        oai::CreateMessageParams as OaiCreateMessageParams,
+        oai::{CreateMessageParams as OaiCreateMessageParams, STRUCTURED_OUTPUT_TOOL},
     },
 };
 
@@ -54,6 +66,16 @@ pub struct ClaudeWebContext {
     pub(super) stop_sequences: Vec<String>,
     /// User information about input and output tokens
     pub(super) usage: Usage,
+    /// Client supplied id of a Claude.ai conversation to continue
+    pub(super) conversation_id: Option<String>,
+    /// Organization asked for by UUID or name, for accounts with several workspaces
+    pub(super) org: Option<String>,
+    /// Tags the dispatched cookie must carry
+    pub(super) cookie_tags: Vec<String>,
+    /// Priority of the request when it has to wait for a cookie
+    pub(super) priority: Priority,
+    /// Prompt sent along the attachment, `custom_prompt` or the selected template
+    pub(super) custom_prompt: String,
 }
 
 /// Predefined test message in Claude format for connection testing
@@ -73,7 +95,127 @@ static TEST_MESSAGE_CLAUDE: LazyLock<Message> = LazyLock::new(|| {
 /// Predefined test message in OpenAI format for connection testing
 static TEST_MESSAGE_OAI: LazyLock<Message> = LazyLock::new(|| Message::new_text(Role::User, "Hi"));
 
-struct NormalizeRequest(CreateMessageParams, ClaudeApiFormat);
+/// Header selecting a prompt template by name
+pub const TEMPLATE_HEADER: &str = "x-clewdr-template";
+
+/// Header restricting dispatch to cookies carrying every listed tag, comma separated
+pub const COOKIE_TAGS_HEADER: &str = "x-clewdr-cookie-tags";
+
+/// The configured `cookie_tags` plus the tags of the `x-clewdr-cookie-tags` header
+///
+/// The header can only narrow the cookies a request may use, never drop a configured tag.
+fn cookie_tags(headers: &HeaderMap) -> Vec<String> {
+    let config = CLEWDR_CONFIG.load();
+    let header = headers
+        .get(COOKIE_TAGS_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    parse_tags(
+        config
+            .cookie_tags
+            .iter()
+            .map(|t| t.as_str())
+            .chain(header.split(',')),
+    )
+}
+
+/// Header setting the priority class of a request: `high`, `normal` or `batch`
+pub const PRIORITY_HEADER: &str = "x-clewdr-priority";
+
+/// Priority of the `x-clewdr-priority` header, or the configured `priority` without it
+fn priority(headers: &HeaderMap) -> Result<Priority, ClewdrError> {
+    let Some(value) = headers.get(PRIORITY_HEADER) else {
+        return Ok(CLEWDR_CONFIG.load().priority);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(Priority::parse)
+        .ok_or(ClewdrError::BadRequest {
+            msg: "Unknown priority, expected high, normal or batch",
+        })
+}
+
+/// `anthropic-version` of the client, or the configured `anthropic_version` without it
+fn anthropic_version(headers: &HeaderMap) -> String {
+    headers
+        .get("anthropic-version")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map_or_else(
+            || CLEWDR_CONFIG.load().anthropic_version.to_owned(),
+            Into::into,
+        )
+}
+
+/// `anthropic-beta` flags of the client that the `anthropic_betas` allowlist lets through
+///
+/// Flags may be comma separated or spread over several headers, others are dropped.
+pub(crate) fn anthropic_betas(headers: &HeaderMap) -> Vec<String> {
+    let config = CLEWDR_CONFIG.load();
+    headers
+        .get_all("anthropic-beta")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|b| config.anthropic_betas.iter().any(|a| a == b))
+        .map(String::from)
+        .collect()
+}
+
+/// `custom_system` and `custom_prompt` of a request, after template selection and rendering
+struct Prompts {
+    system: Option<String>,
+    prompt: String,
+}
+
+struct NormalizeRequest(CreateMessageParams, ClaudeApiFormat, Prompts);
+
+/// Picks the prompt template from the `x-clewdr-template` header or a `model@template` suffix
+/// and renders it, falling back to the global `custom_system` and `custom_prompt`
+///
+/// A model suffix that names no template is left untouched, an unknown header is an error.
+fn select_prompts(
+    body: &mut CreateMessageParams,
+    header: Option<String>,
+) -> Result<Prompts, ClewdrError> {
+    let config = CLEWDR_CONFIG.load();
+    let (model, suffix) = split_template_suffix(&body.model);
+    let (model, suffix) = (model.to_string(), suffix.map(|s| s.to_string()));
+    let suffix = suffix.filter(|n| config.prompt_templates.contains_key(n));
+    if suffix.is_some() {
+        body.model = model;
+    }
+    let template =
+        match header.or(suffix) {
+            Some(name) => Some(config.prompt_templates.get(&name).ok_or(
+                ClewdrError::BadRequest {
+                    msg: "Unknown prompt template",
+                },
+            )?),
+            None => None,
+        };
+    let user_name = body
+        .metadata
+        .as_ref()
+        .and_then(|m| m.fields.get("user_name").or(m.fields.get("user_id")))
+        .map(|u| u.as_str())
+        .unwrap_or("User");
+    let vars = TemplateVars::new(&body.model, user_name);
+    let system = template
+        .and_then(|t| t.system.as_ref())
+        .or(config.custom_system.as_ref())
+        .map(|s| vars.render(s));
+    let prompt = template
+        .and_then(|t| t.prompt.as_ref())
+        .unwrap_or(&config.custom_prompt);
+    Ok(Prompts {
+        system,
+        prompt: vars.render(prompt),
+    })
+}
 
 fn sanitize_messages(msgs: Vec<Message>) -> Vec<Message> {
     msgs.into_iter()
@@ -115,6 +257,55 @@ fn sanitize_messages(msgs: Vec<Message>) -> Vec<Message> {
         .collect()
 }
 
+/// Rejects client tools named like the tool structured output is carried by
+fn check_tool_names<'a>(mut names: impl Iterator<Item = &'a String>) -> Result<(), ClewdrError> {
+    if names.any(|n| n == STRUCTURED_OUTPUT_TOOL) {
+        return Err(ClewdrError::BadRequest {
+            msg: "Tool name is reserved for structured output",
+        });
+    }
+    Ok(())
+}
+
+/// Smallest thinking budget Claude accepts
+const MIN_THINKING_BUDGET: u64 = 1024;
+
+/// Thinking that fits below a lowered `max_tokens`
+///
+/// A budget at or above `max_tokens` is halved, but not below the smallest budget Claude
+/// accepts. Thinking is dropped when even that budget does not fit.
+fn fit_thinking(thinking: Option<Thinking>, max_tokens: u32) -> Option<Thinking> {
+    let mut thinking = thinking?;
+    let max_tokens = u64::from(max_tokens);
+    if thinking.budget_tokens < max_tokens {
+        return Some(thinking);
+    }
+    if max_tokens <= MIN_THINKING_BUDGET {
+        return None;
+    }
+    thinking.budget_tokens = (max_tokens / 2).max(MIN_THINKING_BUDGET);
+    Some(thinking)
+}
+
+/// Splits a `-thinking` or `-thinking-<budget>` suffix off a model name
+///
+/// A trailing `-1M` stays on the returned model.
+///
+/// # Returns
+/// * `Option<(String, Option<u64>)>` - Model without the suffix and the explicit budget, `None` without the suffix
+fn split_thinking_suffix(model: &str) -> Option<(String, Option<u64>)> {
+    let (model, one_m) = match model.strip_suffix("-1M") {
+        Some(model) => (model, "-1M"),
+        None => (model, ""),
+    };
+    if let Some(base) = model.strip_suffix("-thinking") {
+        return Some((format!("{base}{one_m}"), None));
+    }
+    let (base, budget) = model.rsplit_once("-thinking-")?;
+    let budget = budget.parse().ok()?;
+    Some((format!("{base}{one_m}"), Some(budget)))
+}
+
 impl<S> FromRequest<S> for NormalizeRequest
 where
     S: Send + Sync,
@@ -123,6 +314,12 @@ where
 
     async fn from_request(req: Request, _: &S) -> Result<Self, Self::Rejection> {
         let uri = req.uri().to_string();
+        let template = req
+            .headers()
+            .get(TEMPLATE_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
         let format = if uri.contains("chat/completions") {
             ClaudeApiFormat::OpenAI
         } else {
@@ -131,17 +328,25 @@ where
         let Json(mut body) = match format {
             ClaudeApiFormat::OpenAI => {
                 let Json(json) = Json::<OaiCreateMessageParams>::from_request(req, &()).await?;
+                check_tool_names(json.tools.iter().flatten().map(|t| &t.function.name))?;
                 Json(json.into())
             }
-            ClaudeApiFormat::Claude => Json::<CreateMessageParams>::from_request(req, &()).await?,
+            ClaudeApiFormat::Claude => {
+                let json = Json::<CreateMessageParams>::from_request(req, &()).await?;
+                check_tool_names(json.tools.iter().flatten().map(|t| &t.name))?;
+                json
+            }
         };
+        validate_request(&body)?;
         // Sanitize messages: trim whitespace and drop whitespace-only assistant turns
         body.messages = sanitize_messages(body.messages);
-        if body.model.ends_with("-thinking") {
-            body.model = body.model.trim_end_matches("-thinking").to_string();
-            body.thinking.get_or_insert(Thinking::new(4096));
+        let prompts = select_prompts(&mut body, template)?;
+        if let Some((model, budget)) = split_thinking_suffix(&body.model) {
+            body.model = model;
+            let budget = budget.unwrap_or_else(|| CLEWDR_CONFIG.load().thinking_budget);
+            body.thinking.get_or_insert(Thinking::new(budget));
         }
-        Ok(Self(body, format))
+        Ok(Self(body, format, prompts))
     }
 }
 
@@ -152,7 +357,29 @@ where
     type Rejection = ClewdrError;
 
     async fn from_request(req: Request, _: &S) -> Result<Self, Self::Rejection> {
-        let NormalizeRequest(body, format) = NormalizeRequest::from_request(req, &()).await?;
+        let (parts, body) = req.into_parts();
+        let caller = (
+            parts
+                .extensions
+                .get::<Tenant>()
+                .map(|Tenant(t)| t.to_owned()),
+            user_key(&parts),
+        );
+        let req = Request::from_parts(parts, body);
+        let header_conversation_id = req
+            .headers()
+            .get(CONVERSATION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string());
+        let header_org = req
+            .headers()
+            .get(ORG_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string());
+        let cookie_tags = cookie_tags(req.headers());
+        let priority = priority(req.headers())?;
+        let NormalizeRequest(mut body, format, prompts) =
+            NormalizeRequest::from_request(req, &()).await?;
 
         // Check for test messages and respond appropriately
         if !body.stream.unwrap_or_default()
@@ -167,6 +394,27 @@ where
         let stream = body.stream.unwrap_or_default();
 
         let input_tokens = body.count_tokens();
+        let limit = model_limit(&body.model, || input_tokens)?;
+        if let Some(max_tokens) = clamp_max_tokens(&limit, Some(body.max_tokens)) {
+            body.max_tokens = max_tokens;
+        }
+        let conversation_id = header_conversation_id
+            .or_else(|| {
+                body.metadata
+                    .as_ref()
+                    .and_then(|m| m.fields.get(CONVERSATION_FIELD))
+                    .map(|v| v.trim().to_string())
+            })
+            .filter(|id| !id.is_empty())
+            .map(|id| scoped_id(caller.0.as_deref(), caller.1.as_deref(), &id));
+        let org = header_org
+            .or_else(|| {
+                body.metadata
+                    .as_ref()
+                    .and_then(|m| m.fields.get(ORG_FIELD))
+                    .map(|v| v.trim().to_string())
+            })
+            .filter(|org| !org.is_empty());
         let info = ClaudeWebContext {
             stream,
             api_format: format,
@@ -174,7 +422,13 @@ where
             usage: Usage {
                 input_tokens,
                 output_tokens: 0, // Placeholder for output token count
+                ..Default::default()
             },
+            conversation_id,
+            org,
+            cookie_tags,
+            priority,
+            custom_prompt: prompts.prompt,
         };
 
         Ok(Self(body, ClaudeContext::Web(info)))
@@ -189,6 +443,14 @@ pub struct ClaudeCodeContext {
     pub(super) api_format: ClaudeApiFormat,
     /// The hash of the system messages for caching purposes
     pub(super) system_prompt_hash: Option<u64>,
+    /// Tags the dispatched cookie must carry
+    pub(super) cookie_tags: Vec<String>,
+    /// Priority of the request when it has to wait for a cookie
+    pub(super) priority: Priority,
+    /// `anthropic-version` sent upstream
+    pub(super) anthropic_version: String,
+    /// Client `anthropic-beta` flags sent upstream next to the OAuth one
+    pub(super) anthropic_betas: Vec<String>,
     // Usage information for the request
     pub(super) usage: Usage,
 }
@@ -202,8 +464,25 @@ where
     type Rejection = ClewdrError;
 
     async fn from_request(req: Request, _: &S) -> Result<Self, Self::Rejection> {
-        let NormalizeRequest(mut body, format) = NormalizeRequest::from_request(req, &()).await?;
-        // Handle thinking mode by modifying the model name
+        let cookie_tags = cookie_tags(req.headers());
+        let priority = priority(req.headers())?;
+        let anthropic_version = anthropic_version(req.headers());
+        let anthropic_betas = anthropic_betas(req.headers());
+        let NormalizeRequest(mut body, format, prompts) =
+            NormalizeRequest::from_request(req, &()).await?;
+        // Extended thinking needs room past the budget and rejects custom sampling
+        if let Some(ref thinking) = body.thinking {
+            if u64::from(body.max_tokens) <= thinking.budget_tokens {
+                body.max_tokens = thinking
+                    .budget_tokens
+                    .saturating_add(u64::from(default_max_tokens()))
+                    .try_into()
+                    .unwrap_or(u32::MAX);
+            }
+            body.temperature = None;
+            body.top_k = None;
+            body.top_p = None;
+        }
         if (body.model.contains("opus-4-1") || body.model.contains("sonnet-4-5"))
             && body.temperature.is_some()
         {
@@ -226,9 +505,8 @@ where
         const PRELUDE_TEXT: &str = "You are Claude Code, Anthropic's official CLI for Claude.";
         let prelude_blk = || -> ContentBlock {
             ContentBlock::Text {
-                text: CLEWDR_CONFIG
-                    .load()
-                    .custom_system
+                text: prompts
+                    .system
                     .clone()
                     .unwrap_or_else(|| PRELUDE_TEXT.to_string()),
             }
@@ -253,6 +531,12 @@ where
             }
         }
 
+        let config = CLEWDR_CONFIG.load();
+        if config.prompt_caching && body.cache_system_prompt(config.prompt_cache_min_tokens) {
+            debug!("Added cache_control marker to system prompt");
+        }
+        drop(config);
+
         let cache_systems = body
             .system
             .as_ref()
@@ -269,17 +553,65 @@ where
         });
 
         let input_tokens = body.count_tokens();
+        let limit = model_limit(&body.model, || input_tokens)?;
+        if let Some(max_tokens) = clamp_max_tokens(&limit, Some(body.max_tokens)) {
+            body.max_tokens = max_tokens;
+            body.thinking = fit_thinking(body.thinking.take(), max_tokens);
+        }
 
         let info = ClaudeCodeContext {
             stream,
             api_format: format,
             system_prompt_hash,
+            cookie_tags,
+            priority,
+            anthropic_version,
+            anthropic_betas,
             usage: Usage {
                 input_tokens,
                 output_tokens: 0, // Placeholder for output token count
+                ..Default::default()
             },
         };
 
         Ok(Self(body, ClaudeContext::Code(info)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thinking_suffix() {
+        assert_eq!(
+            split_thinking_suffix("claude-opus-4-1-20250805-thinking"),
+            Some(("claude-opus-4-1-20250805".to_string(), None))
+        );
+        assert_eq!(
+            split_thinking_suffix("claude-sonnet-4-20250514-thinking-16000-1M"),
+            Some(("claude-sonnet-4-20250514-1M".to_string(), Some(16000)))
+        );
+        assert_eq!(split_thinking_suffix("claude-sonnet-4-20250514"), None);
+        assert_eq!(split_thinking_suffix("claude-thinking-max"), None);
+    }
+
+    #[test]
+    fn thinking_fits_lowered_max_tokens() {
+        let budget = |t: Option<Thinking>| t.map(|t| t.budget_tokens);
+        assert_eq!(
+            budget(fit_thinking(Some(Thinking::new(2000)), 8000)),
+            Some(2000)
+        );
+        assert_eq!(
+            budget(fit_thinking(Some(Thinking::new(16000)), 8000)),
+            Some(4000)
+        );
+        assert_eq!(
+            budget(fit_thinking(Some(Thinking::new(4000)), 1500)),
+            Some(1024)
+        );
+        assert_eq!(budget(fit_thinking(Some(Thinking::new(4000)), 1024)), None);
+        assert_eq!(budget(fit_thinking(None, 8000)), None);
+    }
+}
@@ -4,13 +4,14 @@ use axum::{
     response::{IntoResponse, Response, Sse},
 };
 use eventsource_stream::Eventsource;
-use futures::TryStreamExt;
+use futures::{TryStreamExt, stream};
 use http::header::CONTENT_TYPE;
 use tracing::warn;
 
 use super::{ClaudeApiFormat, transform_stream};
 use crate::{
-    middleware::claude::{ClaudeContext, transforms_json},
+    config::CLEWDR_CONFIG,
+    middleware::claude::{ClaudeContext, apply_stop_sequences, merge_choices, transforms_json},
     types::claude::{CreateMessageResponse, StreamEvent},
 };
 
@@ -64,13 +65,57 @@ pub async fn to_oai(resp: Response) -> impl IntoResponse {
             Err(resp) => return resp,
         }
     }
+    let coalesce = cx.is_code() && CLEWDR_CONFIG.load().coalesce_tool_json;
     let stream = resp.into_body().into_data_stream().eventsource();
-    let stream = transform_stream(stream);
+    let stream = transform_stream(stream, 0, coalesce);
     Sse::new(stream)
         .keep_alive(Default::default())
         .into_response()
 }
 
+/// Merges the responses of the candidates of an OpenAI request with `n > 1`
+///
+/// Each candidate passes the same overload and stop sequence checks as a single response.
+/// Non-stream responses become one choice per candidate, streams are interleaved as
+/// `choices[i]` deltas in arrival order. The first failed candidate is returned as is.
+///
+/// # Arguments
+/// * `candidates` - Context and upstream response of each candidate, in choice order
+///
+/// # Returns
+/// The merged response in OpenAI format
+pub async fn merge_candidates(candidates: Vec<(ClaudeContext, Response)>) -> Response {
+    let is_stream = candidates.first().is_some_and(|(cx, _)| cx.is_stream());
+    let coalesce = candidates.first().is_some_and(|(cx, _)| cx.is_code())
+        && CLEWDR_CONFIG.load().coalesce_tool_json;
+    let mut responses = vec![];
+    for (cx, mut resp) in candidates {
+        resp.extensions_mut().insert(cx);
+        let resp = check_overloaded(resp).await;
+        if !resp.status().is_success() || resp.extensions().get::<ClaudeContext>().is_none() {
+            return resp;
+        }
+        responses.push(apply_stop_sequences(resp).await);
+    }
+    if !is_stream {
+        let mut choices = vec![];
+        for resp in responses {
+            match parse_response::<CreateMessageResponse>(resp).await {
+                Ok(response) => choices.push(transforms_json(response)),
+                Err(resp) => return resp,
+            }
+        }
+        return Json(merge_choices(choices)).into_response();
+    }
+    let streams = responses.into_iter().enumerate().map(|(i, resp)| {
+        let stream = resp.into_body().into_data_stream().eventsource();
+        Box::pin(transform_stream(stream, i, coalesce))
+    });
+    Sse::new(stream::select_all(streams))
+        .keep_alive(Default::default())
+        .into_response()
+}
+
 pub async fn add_usage_info(resp: Response) -> impl IntoResponse {
     let Some(cx) = resp.extensions().get::<ClaudeContext>() else {
         return resp;
@@ -2,4 +2,4 @@ mod path;
 mod request;
 
 pub use path::GeminiArgs;
-pub use request::{GeminiContext, GeminiOaiPreprocess, GeminiPreprocess};
+pub use request::{GeminiAuxPreprocess, GeminiContext, GeminiOaiPreprocess, GeminiPreprocess};
@@ -5,14 +5,21 @@ use struct_iterable::Iterable;
 use crate::error::ClewdrError;
 
 #[derive(Debug, Clone, Deserialize, Iterable, Default)]
+#[allow(non_snake_case)]
 pub struct GeminiArgs {
     pub key: String,
     pub alt: Option<String>,
+    /// Paging of `models` list calls
+    pub pageSize: Option<String>,
+    pub pageToken: Option<String>,
 }
 
 #[derive(Deserialize)]
+#[allow(non_snake_case)]
 struct GeminiQueryAlt {
     pub alt: Option<String>,
+    pub pageSize: Option<String>,
+    pub pageToken: Option<String>,
 }
 
 impl<S> FromRequestParts<S> for GeminiArgs
@@ -38,6 +45,8 @@ where
                 Ok(Self {
                     key: key.to_string(),
                     alt: q.alt,
+                    pageSize: q.pageSize,
+                    pageToken: q.pageToken,
                 })
             }
         }
@@ -1,14 +1,20 @@
 use axum::{
     Json, RequestExt,
     extract::{FromRequest, Path, Request},
+    http::Method,
 };
+use serde_json::{Value, json};
 
 use super::GeminiArgs;
 use crate::{
-    config::CLEWDR_CONFIG,
+    config::{CLEWDR_CONFIG, SAFETY_HEADER},
     error::ClewdrError,
     gemini_state::GeminiApiFormat,
-    types::{gemini::request::GeminiRequestBody, oai::CreateMessageParams},
+    middleware::limits::{clamp_max_tokens, model_limit},
+    types::{
+        gemini::{grounding::take_grounding, request::GeminiRequestBody, tokens::estimate_tokens},
+        oai::CreateMessageParams,
+    },
 };
 
 #[derive(Clone)]
@@ -21,6 +27,14 @@ pub struct GeminiContext {
     pub api_format: GeminiApiFormat,
 }
 
+/// Value of the safety override header of a request
+fn safety_header(req: &Request) -> Option<String> {
+    req.headers()
+        .get(SAFETY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
 pub struct GeminiPreprocess(pub GeminiRequestBody, pub GeminiContext);
 
 impl<S> FromRequest<S> for GeminiPreprocess
@@ -47,6 +61,7 @@ where
             });
         };
         let query = req.extract_parts::<GeminiArgs>().await?;
+        let safety = safety_header(&req);
         let ctx = GeminiContext {
             vertex,
             model,
@@ -56,7 +71,30 @@ where
             api_format: GeminiApiFormat::Gemini,
         };
         let Json(mut body) = Json::<GeminiRequestBody>::from_request(req, &()).await?;
-        body.safety_off();
+        let limit = model_limit(&ctx.model, || {
+            estimate_tokens(&serde_json::to_value(&body).unwrap_or_default())
+        })?;
+        if limit.max_tokens.is_some()
+            && body.generation_config.as_ref().is_none_or(Value::is_object)
+        {
+            let config = body.generation_config.get_or_insert_with(|| json!({}));
+            let field = if config.get("max_output_tokens").is_some() {
+                "max_output_tokens"
+            } else {
+                "maxOutputTokens"
+            };
+            let requested = config[field]
+                .as_u64()
+                .map(|t| t.try_into().unwrap_or(u32::MAX));
+            if let Some(max) = clamp_max_tokens(&limit, requested) {
+                config[field] = max.into();
+            }
+        }
+        body.hoist_system();
+        CLEWDR_CONFIG
+            .load()
+            .gemini_safety
+            .apply(&mut body.safety_settings, safety.as_deref());
         Ok(GeminiPreprocess(body, ctx))
     }
 }
@@ -76,11 +114,28 @@ where
                 msg: "Vertex is not configured",
             });
         }
-        let Json(mut body) = Json::<CreateMessageParams>::from_request(req, &()).await?;
+        let safety = safety_header(&req);
+        let Json(mut raw) = Json::<Value>::from_request(req, &()).await?;
+        // grounding tools are not OpenAI tools, the request goes to the native API with them
+        let grounding = take_grounding(&mut raw);
+        let Json(mut body) = Json::<CreateMessageParams>::from_bytes(&serde_json::to_vec(&raw)?)?;
+        body.grounding = grounding;
         let model = body.model.to_owned();
+        let limit = model_limit(&model, || body.count_tokens())?;
+        if let Some(max) = clamp_max_tokens(&limit, body.max_tokens.or(body.max_completion_tokens))
+        {
+            if body.max_completion_tokens.is_some() {
+                body.max_completion_tokens = Some(max);
+            }
+            if body.max_tokens.is_some() || body.max_completion_tokens.is_none() {
+                body.max_tokens = Some(max);
+            }
+        }
+        body.preprocess_response_format();
         if vertex {
             body.preprocess_vertex();
         }
+        body.apply_safety_settings(&CLEWDR_CONFIG.load().gemini_safety, safety.as_deref());
         let stream = body.stream.unwrap_or_default();
         let ctx = GeminiContext {
             vertex,
@@ -93,3 +148,44 @@ where
         Ok(GeminiOaiPreprocess(body, ctx))
     }
 }
+
+/// Auxiliary Gemini calls (`countTokens`, `models` list/get), forwarded without transformation
+pub struct GeminiAuxPreprocess(pub Option<Value>, pub GeminiContext);
+
+impl<S> FromRequest<S> for GeminiAuxPreprocess
+where
+    S: Send + Sync,
+{
+    type Rejection = ClewdrError;
+
+    async fn from_request(mut req: Request, _: &S) -> Result<Self, Self::Rejection> {
+        let Path(path) = req.extract_parts::<Path<String>>().await?;
+        let vertex = req.uri().to_string().contains("vertex");
+        if vertex && !CLEWDR_CONFIG.load().vertex.validate() {
+            return Err(ClewdrError::BadRequest {
+                msg: "Vertex is not configured",
+            });
+        }
+        // empty for `models` list calls
+        let model = path
+            .strip_prefix("models/")
+            .map(|s| s.split_once(':').map(|s| s.0).unwrap_or(s).to_string())
+            .unwrap_or_default();
+        let query = req.extract_parts::<GeminiArgs>().await?;
+        let ctx = GeminiContext {
+            vertex,
+            model,
+            stream: false,
+            path,
+            query,
+            api_format: GeminiApiFormat::Gemini,
+        };
+        let body = if req.method() == Method::POST {
+            let Json(body) = Json::<Value>::from_request(req, &()).await?;
+            Some(body)
+        } else {
+            None
+        };
+        Ok(GeminiAuxPreprocess(body, ctx))
+    }
+}
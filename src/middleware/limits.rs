@@ -0,0 +1,182 @@
+use axum::{
+    body::{Body, to_bytes},
+    extract::Request,
+    http::header::CONTENT_LENGTH,
+    middleware::Next,
+    response::Response,
+};
+
+use tracing::info;
+
+use crate::{
+    config::{CLEWDR_CONFIG, ModelLimit},
+    error::ClewdrError,
+    types::claude::{ContentBlock, CreateMessageParams, MessageContent},
+};
+
+pub(crate) const MIB: usize = 1024 * 1024;
+
+/// Middleware that rejects request bodies larger than `max_body_size` with 413
+///
+/// The body is buffered here, so the limit holds even without a `Content-Length` header.
+/// Routes using it should disable axum's default body limit.
+pub async fn limit_body_size(req: Request, next: Next) -> Result<Response, ClewdrError> {
+    let limit_mib = CLEWDR_CONFIG.load().max_body_size;
+    if limit_mib == 0 {
+        return Ok(next.run(req).await);
+    }
+    let limit = limit_mib.saturating_mul(MIB);
+    let too_large = ClewdrError::PayloadTooLarge { limit: limit_mib };
+    let declared = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared.is_some_and(|len| len > limit) {
+        return Err(too_large);
+    }
+    let (parts, body) = req.into_parts();
+    let Ok(bytes) = to_bytes(body, limit).await else {
+        return Err(too_large);
+    };
+    Ok(next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await)
+}
+
+/// Decoded size of an inline image in bytes, `None` for blocks without inline image data
+fn image_size(block: &ContentBlock) -> Option<usize> {
+    let data = match block {
+        ContentBlock::Image { source } => source.data.as_str(),
+        ContentBlock::ImageUrl { image_url } => image_url
+            .url
+            .strip_prefix("data:")?
+            .split_once(',')
+            .map(|(_, data)| data)?,
+        _ => return None,
+    };
+    Some(data.trim_end_matches('=').len() * 3 / 4)
+}
+
+/// Checks a request against `max_messages`, `max_stop_sequences` and `max_image_size`
+///
+/// Runs before a cookie is requested, so oversized requests never reach Claude.
+pub fn validate_request(body: &CreateMessageParams) -> Result<(), ClewdrError> {
+    let config = CLEWDR_CONFIG.load();
+    check_limits(
+        body,
+        config.max_messages,
+        config.max_stop_sequences,
+        config.max_image_size.saturating_mul(MIB),
+    )
+}
+
+/// Checks a request against the `model_limits` of its model
+///
+/// # Arguments
+/// * `model` - Requested model
+/// * `input_tokens` - Estimates the prompt tokens, only called when the model has an input limit
+///
+/// # Returns
+/// * `ModelLimit` - Limits of the model, for `clamp_max_tokens`
+pub fn model_limit(
+    model: &str,
+    input_tokens: impl FnOnce() -> u32,
+) -> Result<ModelLimit, ClewdrError> {
+    let limit = CLEWDR_CONFIG.load().model_limits.for_model(model);
+    if let Some(max) = limit.max_input_tokens {
+        let tokens = input_tokens();
+        if tokens > max {
+            return Err(ClewdrError::InputTooLong {
+                model: model.to_string(),
+                tokens,
+                limit: max,
+            });
+        }
+    }
+    Ok(limit)
+}
+
+/// Output tokens to ask for, the requested `max_tokens` lowered to the model's `max_tokens`
+///
+/// A request without `max_tokens` gets the model's limit.
+pub fn clamp_max_tokens(limit: &ModelLimit, requested: Option<u32>) -> Option<u32> {
+    match (requested, limit.max_tokens) {
+        (Some(requested), Some(max)) if requested > max => {
+            info!("Lowering max_tokens from {} to {}", requested, max);
+            Some(max)
+        }
+        (requested, max) => requested.or(max),
+    }
+}
+
+fn check_limits(
+    body: &CreateMessageParams,
+    max_messages: usize,
+    max_stop_sequences: usize,
+    max_image_bytes: usize,
+) -> Result<(), ClewdrError> {
+    if max_messages > 0 && body.messages.len() > max_messages {
+        return Err(ClewdrError::BadRequest {
+            msg: "Too many messages in request",
+        });
+    }
+    if max_stop_sequences > 0
+        && body
+            .stop_sequences
+            .as_ref()
+            .is_some_and(|s| s.len() > max_stop_sequences)
+    {
+        return Err(ClewdrError::BadRequest {
+            msg: "Too many stop sequences in request",
+        });
+    }
+    if max_image_bytes == 0 {
+        return Ok(());
+    }
+    let oversized = body
+        .messages
+        .iter()
+        .filter_map(|m| match m.content {
+            MessageContent::Blocks { ref content } => Some(content),
+            MessageContent::Text { .. } => None,
+        })
+        .flatten()
+        .filter_map(image_size)
+        .any(|size| size > max_image_bytes);
+    if oversized {
+        return Err(ClewdrError::BadRequest {
+            msg: "Image exceeds the maximum size",
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::claude::{ImageUrl, Message, Role};
+
+    #[test]
+    fn rejects_over_limits() {
+        let image = ContentBlock::ImageUrl {
+            image_url: ImageUrl {
+                url: format!("data:image/png;base64,{}", "A".repeat(4000)),
+            },
+        };
+        assert_eq!(image_size(&image), Some(3000));
+        let body = CreateMessageParams {
+            messages: vec![
+                Message::new_text(Role::User, "Hi"),
+                Message::new_blocks(Role::User, vec![image]),
+            ],
+            stop_sequences: Some(vec!["a".into(), "b".into()]),
+            ..Default::default()
+        };
+        assert!(check_limits(&body, 2, 2, 3000).is_ok());
+        assert!(check_limits(&body, 0, 0, 0).is_ok());
+        assert!(check_limits(&body, 1, 0, 0).is_err());
+        assert!(check_limits(&body, 0, 1, 0).is_err());
+        assert!(check_limits(&body, 0, 0, 2999).is_err());
+    }
+}
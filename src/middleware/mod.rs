@@ -5,9 +5,29 @@
 ///
 /// - Authentication: Verify API keys for different authentication methods (admin, OpenAI, Claude)
 /// - Request preprocessing: Normalize requests from different API formats
+/// - Limits: Reject oversized or malformed payloads before any cookie or key is used
+/// - Parameters: Strip OpenAI parameters the upstream cannot honour
+/// - Scrubbing: Fix or reject malformed client metadata fields of OpenAI requests
+/// - Routing: Send requests to another provider, model or proxy, or reject them, by configured rules
+/// - Rules: Rewrite or block generated text according to configured patterns
+/// - Tenants: Serve the requests of a tenant from its own pools
+/// - Timeouts: Bound the phases of upstream requests, per upstream or per request
 /// - Response transformation: Convert between different response formats and handle streaming
+/// - OpenAI errors: Give errors of the OpenAI compatible routes the OpenAI error shape
+pub mod access;
 mod auth;
 pub mod claude;
 pub mod gemini;
+pub mod limits;
+pub mod oai_error;
+pub mod params;
+pub mod proxy;
+pub mod routing;
+pub mod rules;
+pub mod scrub;
+pub mod tenant;
+pub mod timeout;
 
-pub use auth::{RequireAdminAuth, RequireBearerAuth, RequireQueryKeyAuth, RequireXApiKeyAuth};
+pub use auth::{
+    RequireAdminRead, RequireAdminWrite, RequireBearerAuth, RequireQueryKeyAuth, RequireXApiKeyAuth,
+};
@@ -0,0 +1,132 @@
+use axum::{
+    Json,
+    body::{Body, to_bytes},
+    response::{IntoResponse, Response},
+};
+use http::{StatusCode, header::CONTENT_TYPE};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::warn;
+
+/// Error body of the OpenAI API
+#[derive(Debug, Serialize)]
+pub struct OaiError {
+    pub error: OaiErrorBody,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OaiErrorBody {
+    pub message: String,
+    pub r#type: String,
+    pub param: Option<String>,
+    pub code: Option<String>,
+}
+
+/// OpenAI error type of a status code
+fn oai_type(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::UNAUTHORIZED => "authentication_error",
+        StatusCode::FORBIDDEN => "permission_error",
+        StatusCode::NOT_FOUND => "not_found_error",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+        s if s.is_server_error() => "server_error",
+        _ => "invalid_request_error",
+    }
+}
+
+impl OaiErrorBody {
+    /// Converts an Anthropic or Gemini style error object
+    ///
+    /// The type follows the status, as OpenAI clients expect, the original type or status
+    /// is kept as `code`.
+    fn from_error(status: StatusCode, error: &Value) -> Self {
+        let message = match &error["message"] {
+            Value::String(m) => m.to_owned(),
+            Value::Null => status.to_string(),
+            m => m.to_string(),
+        };
+        let code = ["type", "status", "code"]
+            .iter()
+            .find_map(|k| match &error[k] {
+                Value::String(c) => Some(c.to_owned()),
+                Value::Number(c) => Some(c.to_string()),
+                _ => None,
+            });
+        Self {
+            message,
+            r#type: oai_type(status).to_string(),
+            param: None,
+            code,
+        }
+    }
+}
+
+/// Rewrites error responses into the OpenAI error shape
+///
+/// ClewdR errors and upstream Claude errors carry `{"error": {"message", "type", "code"}}`
+/// in Anthropic style, Gemini errors may come wrapped in an array. OpenAI SDKs expect
+/// `{"error": {"message", "type", "param", "code"}}` with a string message and code, so this
+/// is applied to the OpenAI compatible routes. Successful and non-JSON responses are
+/// passed through untouched.
+pub async fn to_oai_error(resp: Response) -> Response {
+    let status = resp.status();
+    let is_json = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !(status.is_client_error() || status.is_server_error()) || !is_json {
+        return resp;
+    }
+    let (mut parts, body) = resp.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to read error body: {}", e);
+            return (
+                status,
+                Json(OaiError {
+                    error: OaiErrorBody::from_error(status, &Value::Null),
+                }),
+            )
+                .into_response();
+        }
+    };
+    let parsed = serde_json::from_slice::<Value>(&bytes).unwrap_or_default();
+    let parsed = match parsed {
+        Value::Array(mut a) if !a.is_empty() => a.swap_remove(0),
+        v => v,
+    };
+    let Some(error) = parsed.get("error").filter(|e| e.is_object()) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    let body = OaiError {
+        error: OaiErrorBody::from_error(status, error),
+    };
+    parts.headers.remove(http::header::CONTENT_LENGTH);
+    let mut resp = Json(body).into_response();
+    resp.headers_mut().extend(parts.headers);
+    *resp.status_mut() = status;
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn claude_error_becomes_openai_error() {
+        let resp = crate::error::ClewdrError::BadRequest {
+            msg: "Invalid request",
+        }
+        .into_response();
+        let resp = to_oai_error(resp).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["error"]["message"].is_string());
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+        assert!(body["error"]["code"].is_string());
+        assert!(body["error"]["param"].is_null());
+    }
+}
@@ -0,0 +1,122 @@
+use axum::{
+    body::{Body, to_bytes},
+    extract::Request,
+    http::HeaderValue,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value};
+use tracing::debug;
+
+use crate::{
+    config::{CLEWDR_CONFIG, ParamPolicy},
+    error::ClewdrError,
+};
+
+/// Response header listing the OpenAI parameters removed from the request
+pub const DROPPED_PARAMS_HEADER: &str = "x-clewdr-dropped-params";
+
+/// OpenAI parameters no upstream can honour
+const UNSUPPORTED_PARAMS: [&str; 5] = [
+    "logprobs",
+    "top_logprobs",
+    "presence_penalty",
+    "frequency_penalty",
+    "logit_bias",
+];
+
+/// Whether a parameter value asks for nothing, like `false`, `0` or `{}`
+fn is_neutral(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Bool(b) => !b,
+        Value::Number(n) => n.as_f64() == Some(0.0),
+        Value::Object(o) => o.is_empty(),
+        _ => false,
+    }
+}
+
+/// Removes the parameters no upstream can honour from a request body
+///
+/// # Returns
+/// * `Vec<&'static str>` - Removed parameters that asked for something, neutral ones are removed silently
+fn strip_unsupported(body: &mut Map<String, Value>) -> Vec<&'static str> {
+    let mut dropped = vec![];
+    for name in UNSUPPORTED_PARAMS {
+        if let Some(value) = body.remove(name)
+            && !is_neutral(&value)
+        {
+            dropped.push(name);
+        }
+    }
+    dropped
+}
+
+/// Middleware that strips OpenAI parameters the upstream cannot honour
+///
+/// With `oai_param_policy = "reject"` such requests are answered with 400 instead.
+/// Either way the offending parameters are listed in `x-clewdr-dropped-params`.
+/// Bodies that are not JSON objects are passed through untouched.
+pub async fn sanitize_oai_params(req: Request, next: Next) -> Result<Response, ClewdrError> {
+    let (parts, body) = req.into_parts();
+    // the body limit middleware already bounded the body
+    let bytes = to_bytes(body, usize::MAX)
+        .await
+        .map_err(|_| ClewdrError::BadRequest {
+            msg: "Failed to read request body",
+        })?;
+    let Ok(Value::Object(mut json)) = serde_json::from_slice::<Value>(&bytes) else {
+        return Ok(next
+            .run(Request::from_parts(parts, Body::from(bytes)))
+            .await);
+    };
+    let dropped = strip_unsupported(&mut json);
+    let header = (!dropped.is_empty())
+        .then(|| dropped.join(","))
+        .and_then(|v| HeaderValue::from_str(&v).ok());
+    if let Some(ref header) = header {
+        if CLEWDR_CONFIG.load().oai_param_policy == ParamPolicy::Reject {
+            let mut res = ClewdrError::BadRequest {
+                msg: "Unsupported OpenAI parameters in request",
+            }
+            .into_response();
+            res.headers_mut()
+                .insert(DROPPED_PARAMS_HEADER, header.to_owned());
+            return Ok(res);
+        }
+        debug!("Dropped unsupported OpenAI parameters: {:?}", dropped);
+    }
+    let body = Body::from(serde_json::to_vec(&json)?);
+    let mut res = next.run(Request::from_parts(parts, body)).await;
+    if let Some(header) = header {
+        res.headers_mut().insert(DROPPED_PARAMS_HEADER, header);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn strips_unsupported_params() {
+        let Value::Object(mut body) = json!({
+            "model": "claude-sonnet-4",
+            "logprobs": true,
+            "presence_penalty": 0,
+            "frequency_penalty": 0.5,
+            "logit_bias": {},
+            "n": 2,
+        }) else {
+            unreachable!()
+        };
+        assert_eq!(
+            strip_unsupported(&mut body),
+            ["logprobs", "frequency_penalty"]
+        );
+        assert_eq!(body.len(), 2);
+        assert_eq!(body.get("n"), Some(&json!(2)));
+    }
+}
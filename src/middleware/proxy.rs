@@ -0,0 +1,62 @@
+use crate::{
+    config::{CLEWDR_CONFIG, ProxyTarget, proxy_from_str},
+    error::ClewdrError,
+};
+use axum::{extract::Request, middleware::Next, response::Response};
+use tracing::{info, warn};
+
+/// Header carrying a proxy URL for the upstream calls of a single request
+pub const PROXY_HEADER: &str = "x-clewdr-proxy";
+
+tokio::task_local! {
+    static PROXY_OVERRIDE: String;
+}
+
+/// Middleware that routes the upstream calls of a request through the proxy in `x-clewdr-proxy`
+///
+/// The header is only honoured when `allow_proxy_override` is enabled by the admin,
+/// otherwise it is ignored and the global proxy is used.
+pub async fn proxy_override(req: Request, next: Next) -> Result<Response, ClewdrError> {
+    let Some(value) = req.headers().get(PROXY_HEADER) else {
+        return Ok(next.run(req).await);
+    };
+    if !CLEWDR_CONFIG.load().allow_proxy_override {
+        warn!(
+            "Ignoring {} header, proxy override is disabled",
+            PROXY_HEADER
+        );
+        return Ok(next.run(req).await);
+    }
+    let Some(proxy) = value
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|v| proxy_from_str(v).is_ok())
+    else {
+        return Err(ClewdrError::BadRequest {
+            msg: "Invalid x-clewdr-proxy header",
+        });
+    };
+    info!("Using proxy from {} header", PROXY_HEADER);
+    Ok(with_proxy(proxy.to_string(), next.run(req)).await)
+}
+
+/// Runs `fut` with its upstream calls going through the proxy at `proxy`
+pub async fn with_proxy<F: Future>(proxy: String, fut: F) -> F::Output {
+    PROXY_OVERRIDE.scope(proxy, fut).await
+}
+
+/// Proxy for upstream calls made while serving the current request
+///
+/// # Arguments
+/// * `target` - Upstream the calls go to
+///
+/// # Returns
+/// * `Option<String>` - URL of the per-request override if set, otherwise of the configured
+///   proxy of the upstream
+pub fn current_proxy(target: ProxyTarget) -> Option<String> {
+    PROXY_OVERRIDE
+        .try_with(|p| p.to_owned())
+        .ok()
+        .or_else(|| CLEWDR_CONFIG.load().upstream_proxy(target))
+}
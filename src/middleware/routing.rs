@@ -0,0 +1,242 @@
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::Request,
+    http::{HeaderValue, header::CONTENT_LENGTH, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::Value;
+use snafu::ResultExt;
+use tracing::{debug, info, warn};
+
+use super::{limits::MIB, proxy::with_proxy, timeout::with_timeouts};
+use crate::{
+    config::{
+        BEDROCK_MODEL_SUFFIX, CLEWDR_CONFIG, RouteProvider, RouteRequest, RoutingRule, match_route,
+        proxy_from_str, upstream_path,
+    },
+    error::{ClewdrError, InvalidUriSnafu},
+};
+
+/// Prefixes of the API paths routing rules apply to, the admin API and the frontend are never routed
+pub(super) const ROUTED_PREFIXES: [&str; 6] = [
+    "/v1/",
+    "/code/v1/",
+    "/gemini/",
+    "/vertex/",
+    "/bedrock/",
+    "/openai/",
+];
+
+/// User key of a request, from `x-api-key`, a bearer token or the `key` query parameter
+pub(crate) fn user_key(parts: &Parts) -> Option<String> {
+    let header = |name: &str| parts.headers.get(name).and_then(|v| v.to_str().ok());
+    header("x-api-key")
+        .or_else(|| header("authorization").and_then(|v| v.strip_prefix("Bearer ")))
+        .or_else(|| {
+            parts
+                .uri
+                .query()?
+                .split('&')
+                .find_map(|p| p.strip_prefix("key="))
+        })
+        .map(str::to_string)
+}
+
+/// Model named in the path of a native Gemini request, e.g. `/v1/v1beta/models/{model}:generateContent`
+fn path_model(path: &str) -> Option<&str> {
+    let (_, rest) = path.split_once("/models/")?;
+    rest.split(':').next()
+}
+
+/// Replaces the model of a request, in the JSON body or, when the body names none, in the path
+fn set_model(
+    model: &str,
+    json: &mut Option<Value>,
+    bytes: &mut Bytes,
+    parts: &mut Parts,
+    target: &mut String,
+) -> Result<(), ClewdrError> {
+    match json.as_mut().filter(|b| b.get("model").is_some()) {
+        Some(body) => {
+            body["model"] = model.into();
+            *bytes = serde_json::to_vec(body)?.into();
+            parts
+                .headers
+                .insert(CONTENT_LENGTH, HeaderValue::from(bytes.len()));
+        }
+        None => {
+            if let Some(old) = path_model(target) {
+                *target =
+                    target.replacen(&format!("/models/{old}"), &format!("/models/{model}"), 1);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Middleware resolving model aliases, then applying the first matching routing rule to an
+/// API request
+///
+/// Runs before the router, so a rule picking another provider rewrites the path and the
+/// request is served by that provider's handler. JSON bodies are buffered to match body
+/// fields and to replace the model. Rules see the model an alias resolves to. Messages
+/// requests for a `-bedrock` model go to Bedrock unless a rule picks another provider, other
+/// APIs refuse them with a 400 unless a rule picks a provider or another model, chat
+/// completions for a `{upstream}/{model}` model, also when a rule sets it, go to that
+/// OpenAI compatible upstream.
+pub async fn apply_routing_rules(req: Request, next: Next) -> Result<Response, ClewdrError> {
+    let (rules, aliases, limit_mib, bedrock, upstreams) = {
+        let config = CLEWDR_CONFIG.load();
+        (
+            config.routing_rules.to_owned(),
+            config.model_aliases.to_owned(),
+            config.max_body_size,
+            config.bedrock.is_enabled(),
+            !config.openai_upstreams.is_empty(),
+        )
+    };
+    let path = req.uri().path().to_string();
+    if (rules.is_empty() && aliases.is_empty() && !bedrock && !upstreams)
+        || !ROUTED_PREFIXES.iter().any(|p| path.starts_with(p))
+    {
+        return Ok(next.run(req).await);
+    }
+    let (mut parts, body) = req.into_parts();
+    let limit = match limit_mib {
+        0 => usize::MAX,
+        mib => mib.saturating_mul(MIB),
+    };
+    let Ok(mut bytes) = to_bytes(body, limit).await else {
+        return Err(ClewdrError::PayloadTooLarge { limit: limit_mib });
+    };
+    let mut json = serde_json::from_slice::<Value>(&bytes).ok();
+    let mut target = path.to_owned();
+
+    let requested = json
+        .as_ref()
+        .and_then(|b| b["model"].as_str())
+        .or_else(|| path_model(&path))
+        .map(str::to_string);
+    if let Some((alias, model)) = requested.as_deref().and_then(|m| aliases.get_key_value(m)) {
+        debug!("Model alias {} resolved to {}", alias, model);
+        set_model(model, &mut json, &mut bytes, &mut parts, &mut target)?;
+    }
+    let bedrock_model = |json: &Option<Value>| {
+        bedrock
+            && json
+                .as_ref()
+                .and_then(|b| b["model"].as_str())
+                .is_some_and(|m| m.ends_with(BEDROCK_MODEL_SUFFIX))
+    };
+    // Bedrock only serves the Messages API, other APIs are refused unless a rule reroutes them
+    let mut bedrock_unserved = false;
+    if bedrock_model(&json) {
+        match RouteProvider::Bedrock.path_for(&target) {
+            Some(p) => target = p,
+            None => bedrock_unserved = true,
+        }
+    }
+
+    let key = user_key(&parts);
+    let route = RouteRequest {
+        path: &path,
+        model: json
+            .as_ref()
+            .and_then(|b| b["model"].as_str())
+            .or_else(|| path_model(&target)),
+        key: key.as_deref(),
+        headers: parts
+            .headers
+            .iter()
+            .filter_map(|(k, v)| Some((k.as_str(), v.to_str().ok()?)))
+            .collect(),
+        body: json.as_ref(),
+    };
+    let matched = match_route(&rules, &route);
+    let mut proxy = None;
+    let mut timeouts = None;
+    let mut picked = false;
+    if let Some((index, rule)) = matched {
+        let RoutingRule {
+            name,
+            provider,
+            model,
+            proxy: rule_proxy,
+            timeouts: rule_timeouts,
+            reject,
+            ..
+        } = rule.to_owned();
+        let name = name.unwrap_or_else(|| format!("#{index}"));
+
+        if let Some(reason) = reject {
+            warn!("Request to {} rejected by routing rule {}", path, name);
+            return Err(ClewdrError::RequestRejected { rule: name, reason });
+        }
+        if let Some(provider) = provider {
+            picked = true;
+            let Some(p) = provider.path_for(&target) else {
+                warn!(
+                    "Routing rule {} picks {:?}, which does not serve {}",
+                    name, provider, path
+                );
+                return Err(ClewdrError::BadRequest {
+                    msg: "Routing rule picks a provider that does not serve this API",
+                });
+            };
+            target = p;
+        }
+        if let Some(model) = model {
+            set_model(&model, &mut json, &mut bytes, &mut parts, &mut target)?;
+        }
+        proxy = match rule_proxy.map(|p| proxy_from_str(&p).map(|_| p)) {
+            Some(Ok(proxy)) => Some(proxy),
+            Some(Err(e)) => {
+                warn!("Routing rule {} has an invalid proxy: {}", name, e);
+                None
+            }
+            None => None,
+        };
+        timeouts = rule_timeouts;
+        info!("Routing rule {} applied to {}", name, path);
+    }
+    if bedrock_unserved && !picked && bedrock_model(&json) {
+        return Err(ClewdrError::BadRequest {
+            msg: "Bedrock models are only served on the Anthropic Messages API",
+        });
+    }
+    if upstreams
+        && !picked
+        && let Some(name) = json
+            .as_ref()
+            .and_then(|b| b["model"].as_str())
+            .and_then(|m| {
+                CLEWDR_CONFIG
+                    .load()
+                    .upstream_of_model(m)
+                    .map(|u| u.name.to_owned())
+            })
+        && let Some(p) = upstream_path(&name, &target)
+    {
+        target = p;
+    }
+    if target != path {
+        let uri = match parts.uri.query() {
+            Some(query) => format!("{target}?{query}"),
+            None => target,
+        };
+        parts.uri = uri.parse().context(InvalidUriSnafu { uri })?;
+    }
+
+    let req = Request::from_parts(parts, Body::from(bytes));
+    let run = async move {
+        match proxy {
+            Some(proxy) => with_proxy(proxy, next.run(req)).await,
+            None => next.run(req).await,
+        }
+    };
+    match timeouts {
+        Some(timeouts) => Ok(with_timeouts(timeouts, run).await),
+        None => Ok(run.await),
+    }
+}
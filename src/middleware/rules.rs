@@ -0,0 +1,187 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+use async_stream::stream;
+use axum::{
+    body::{Body, to_bytes},
+    response::{IntoResponse, Response, Sse, sse::Event},
+};
+use eventsource_stream::Eventsource;
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use serde_json::{Value, json};
+use tracing::warn;
+
+use crate::{
+    config::{CLEWDR_CONFIG, ResponseRule, RuleOutcome, run_rules},
+    error::ClewdrError,
+};
+
+/// Matches per rule name since start
+static HITS: LazyLock<Mutex<HashMap<String, u64>>> = LazyLock::new(Default::default);
+
+/// Number of responses or stream events each rule matched since start
+pub fn rule_hits() -> HashMap<String, u64> {
+    HITS.lock().unwrap_or_else(|e| e.into_inner()).to_owned()
+}
+
+/// Runs the rules over one piece of generated text, counting the hits
+///
+/// # Returns
+/// * `Err(name)` - A deny rule matched
+fn rewrite_text<'a>(rules: &'a [ResponseRule], text: &mut String) -> Result<(), &'a str> {
+    let outcome = run_rules(rules, text, |i| {
+        let mut hits = HITS.lock().unwrap_or_else(|e| e.into_inner());
+        *hits.entry(rules[i].name().to_string()).or_default() += 1;
+    });
+    match outcome {
+        RuleOutcome::Unchanged => {}
+        RuleOutcome::Rewritten(new) => *text = new,
+        RuleOutcome::Denied(name) => return Err(name),
+    }
+    Ok(())
+}
+
+/// Runs the rules over the generated text of a JSON payload
+///
+/// Generated text is every string under a `text` or `content` key, which covers
+/// Claude content blocks and deltas, Gemini parts and OpenAI messages and deltas.
+///
+/// # Returns
+/// * `Err(name)` - A deny rule matched
+fn rewrite_value<'a>(rules: &'a [ResponseRule], value: &mut Value) -> Result<(), &'a str> {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                match v {
+                    Value::String(text) if key == "text" || key == "content" => {
+                        rewrite_text(rules, text)?
+                    }
+                    v => rewrite_value(rules, v)?,
+                }
+            }
+        }
+        Value::Array(items) => {
+            for v in items {
+                rewrite_value(rules, v)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Runs the rules over the generated text of a stream event
+///
+/// Claude events, the ones with a `type`, only carry generated text in `text_delta`
+/// deltas, every other Claude event is left alone. OpenAI and Gemini chunks go
+/// through [`rewrite_value`].
+///
+/// # Returns
+/// * `Ok(true)` - The event may have changed and has to be serialized again
+/// * `Err(name)` - A deny rule matched
+fn rewrite_event<'a>(rules: &'a [ResponseRule], value: &mut Value) -> Result<bool, &'a str> {
+    if value.get("type").is_none() {
+        return rewrite_value(rules, value).map(|_| true);
+    }
+    if value["type"] != "content_block_delta" || value["delta"]["type"] != "text_delta" {
+        return Ok(false);
+    }
+    match value["delta"].get_mut("text") {
+        Some(Value::String(text)) => rewrite_text(rules, text).map(|_| true),
+        _ => Ok(false),
+    }
+}
+
+/// Applies the configured `response_rules` to successful responses
+///
+/// JSON bodies are buffered and rewritten as a whole. Event streams are rewritten
+/// event by event, keeping the response headers, so a pattern split across two
+/// deltas is not matched. When a deny
+/// rule matches, a JSON response becomes an error and a stream ends with an error event.
+pub async fn apply_response_rules(resp: Response) -> Response {
+    let rules = CLEWDR_CONFIG.load().response_rules.to_owned();
+    if rules.is_empty() || !resp.status().is_success() {
+        return resp;
+    }
+    let content_type = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if content_type.contains("text/event-stream") {
+        return rewrite_stream(rules, resp);
+    }
+    if !content_type.contains("application/json") {
+        return resp;
+    }
+    let (mut parts, body) = resp.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to read response body: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let Ok(mut value) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    if let Err(rule) = rewrite_value(&rules, &mut value) {
+        return ClewdrError::ResponseBlocked {
+            rule: rule.to_string(),
+        }
+        .into_response();
+    }
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(value.to_string()))
+}
+
+fn rewrite_stream(rules: Vec<ResponseRule>, resp: Response) -> Response {
+    let (mut parts, body) = resp.into_parts();
+    let events = body.into_data_stream().eventsource();
+    let stream = stream! {
+        for await event in events {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            };
+            let out = Event::default().event(&event.event).id(&event.id);
+            let out = match event.retry {
+                Some(retry) => out.retry(retry),
+                None => out,
+            };
+            let Ok(mut value) = serde_json::from_str::<Value>(&event.data) else {
+                yield Ok(out.data(event.data));
+                continue;
+            };
+            let changed = match rewrite_event(&rules, &mut value) {
+                Ok(changed) => changed,
+                Err(rule) => {
+                    let error = json!({
+                        "type": "error",
+                        "error": {
+                            "type": "ResponseBlocked",
+                            "message": format!("Response blocked by rule {rule}"),
+                        },
+                    });
+                    yield Ok(Event::default().event("error").data(error.to_string()));
+                    return;
+                }
+            };
+            if changed {
+                yield Ok(out.data(value.to_string()));
+            } else {
+                yield Ok(out.data(event.data));
+            }
+        }
+    };
+    let sse = Sse::new(stream)
+        .keep_alive(Default::default())
+        .into_response();
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, sse.into_body())
+}
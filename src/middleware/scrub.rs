@@ -0,0 +1,197 @@
+use axum::{
+    body::{Body, to_bytes},
+    extract::Request,
+    http::HeaderValue,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value, json};
+use tracing::debug;
+
+use crate::{
+    config::{CLEWDR_CONFIG, ScrubConfig, ScrubPolicy},
+    error::ClewdrError,
+};
+
+/// Response header listing the checks that found suspicious fields in the request
+pub const SCRUBBED_HEADER: &str = "x-clewdr-scrubbed";
+/// Text replacing a content part whose data URL is too large
+const OMITTED_NOTE: &str = "[attachment omitted: too large]";
+
+/// Whether a content part holds a data URL longer than `limit` bytes
+fn has_large_data_url(value: &Value, limit: usize) -> bool {
+    match value {
+        Value::String(s) => s.len() > limit && s.starts_with("data:"),
+        Value::Array(items) => items.iter().any(|v| has_large_data_url(v, limit)),
+        Value::Object(map) => map.values().any(|v| has_large_data_url(v, limit)),
+        _ => false,
+    }
+}
+
+/// Scrubs the messages of a request body
+///
+/// Fields are only changed for checks with policy `fix`.
+///
+/// # Returns
+/// * `Vec<(&'static str, ScrubPolicy)>` - Checks that found something, each listed once
+fn scrub(body: &mut Map<String, Value>, config: &ScrubConfig) -> Vec<(&'static str, ScrubPolicy)> {
+    let Some(Value::Array(messages)) = body.get_mut("messages") else {
+        return vec![];
+    };
+    let mut found = vec![];
+    let mut report = |check: &'static str, policy: ScrubPolicy| {
+        if !found.iter().any(|(c, _)| *c == check) {
+            found.push((check, policy));
+        }
+    };
+    let limit = config.max_data_url_size.saturating_mul(1024);
+    let mut started = false;
+    let mut nested = 0;
+    for message in messages.iter_mut().filter_map(Value::as_object_mut) {
+        if config.names != ScrubPolicy::Off
+            && let Some(Value::String(name)) = message.get("name")
+            && name.chars().any(char::is_control)
+        {
+            report("name", config.names);
+            if config.names == ScrubPolicy::Fix {
+                let clean = name.chars().filter(|c| !c.is_control()).collect::<String>();
+                let clean = clean.trim();
+                if clean.is_empty() {
+                    message.remove("name");
+                } else {
+                    message.insert("name".to_string(), clean.into());
+                }
+            }
+        }
+        if config.data_urls != ScrubPolicy::Off
+            && let Some(Value::Array(parts)) = message.get_mut("content")
+        {
+            for part in parts.iter_mut() {
+                if has_large_data_url(part, limit) {
+                    report("data_url", config.data_urls);
+                    if config.data_urls == ScrubPolicy::Fix {
+                        *part = json!({ "type": "text", "text": OMITTED_NOTE });
+                    }
+                }
+            }
+        }
+        let system = matches!(
+            message.get("role").and_then(Value::as_str),
+            Some("system" | "developer")
+        );
+        if !system {
+            started = true;
+        } else if started && config.nested_system != ScrubPolicy::Off {
+            nested += 1;
+            if nested > config.max_nested_system {
+                report("nested_system", config.nested_system);
+                if config.nested_system == ScrubPolicy::Fix {
+                    message.insert("role".to_string(), "user".into());
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Middleware that scrubs suspicious client metadata fields of OpenAI requests
+///
+/// Checks message `name` fields for control characters, content parts for data URLs above
+/// `scrub.max_data_url_size` and the conversation for more than `scrub.max_nested_system`
+/// system messages after its start. Each check follows its own policy of `scrub`, the
+/// checks that found something are listed in `x-clewdr-scrubbed`.
+/// Bodies that are not JSON objects are passed through untouched.
+pub async fn scrub_oai_request(req: Request, next: Next) -> Result<Response, ClewdrError> {
+    let config = CLEWDR_CONFIG.load().scrub.to_owned();
+    if !config.is_enabled() {
+        return Ok(next.run(req).await);
+    }
+    let (parts, body) = req.into_parts();
+    // the body limit middleware already bounded the body
+    let bytes = to_bytes(body, usize::MAX)
+        .await
+        .map_err(|_| ClewdrError::BadRequest {
+            msg: "Failed to read request body",
+        })?;
+    let Ok(Value::Object(mut json)) = serde_json::from_slice::<Value>(&bytes) else {
+        return Ok(next
+            .run(Request::from_parts(parts, Body::from(bytes)))
+            .await);
+    };
+    let found = scrub(&mut json, &config);
+    if found.is_empty() {
+        return Ok(next
+            .run(Request::from_parts(parts, Body::from(bytes)))
+            .await);
+    }
+    let header = HeaderValue::from_str(
+        &found
+            .iter()
+            .map(|(check, _)| *check)
+            .collect::<Vec<_>>()
+            .join(","),
+    )
+    .ok();
+    if found.iter().any(|(_, p)| *p == ScrubPolicy::Reject) {
+        let mut res = ClewdrError::BadRequest {
+            msg: "Malformed client metadata in request",
+        }
+        .into_response();
+        if let Some(header) = header {
+            res.headers_mut().insert(SCRUBBED_HEADER, header);
+        }
+        return Ok(res);
+    }
+    debug!("Scrubbed OpenAI request fields: {:?}", found);
+    let body = Body::from(serde_json::to_vec(&json)?);
+    let mut res = next.run(Request::from_parts(parts, body)).await;
+    if let Some(header) = header {
+        res.headers_mut().insert(SCRUBBED_HEADER, header);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrubs_suspicious_fields() {
+        let config = ScrubConfig {
+            names: ScrubPolicy::Fix,
+            data_urls: ScrubPolicy::Fix,
+            max_data_url_size: 1,
+            nested_system: ScrubPolicy::Reject,
+            max_nested_system: 1,
+        };
+        let large = format!("data:image/png;base64,{}", "A".repeat(2048));
+        let Value::Object(mut body) = json!({
+            "messages": [
+                { "role": "system", "content": "preset" },
+                { "role": "user", "name": "Alice\u{0}\n", "content": [
+                    { "type": "text", "text": "look" },
+                    { "type": "image_url", "image_url": { "url": large } },
+                ] },
+                { "role": "system", "content": "a" },
+                { "role": "assistant", "name": "\u{7}", "content": "hi" },
+                { "role": "system", "content": "b" },
+            ]
+        }) else {
+            unreachable!()
+        };
+        assert_eq!(
+            scrub(&mut body, &config),
+            [
+                ("name", ScrubPolicy::Fix),
+                ("data_url", ScrubPolicy::Fix),
+                ("nested_system", ScrubPolicy::Reject),
+            ]
+        );
+        let messages = &body["messages"];
+        assert_eq!(messages[1]["name"], "Alice");
+        assert_eq!(messages[1]["content"][0]["text"], "look");
+        assert_eq!(messages[1]["content"][1]["text"], OMITTED_NOTE);
+        assert!(messages[3].get("name").is_none());
+        assert_eq!(messages[4]["role"], "system");
+    }
+}
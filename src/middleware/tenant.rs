@@ -0,0 +1,76 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    Router,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use snafu::ResultExt;
+use tower::ServiceExt;
+use tracing::debug;
+
+use super::routing::{ROUTED_PREFIXES, user_key};
+use crate::{
+    config::CLEWDR_CONFIG,
+    error::{ClewdrError, InvalidUriSnafu},
+};
+
+/// Path prefix of the routes of a tenant, `/t/{tenant}/v1/...`
+const TENANT_PREFIX: &str = "/t/";
+
+/// Tenant a request is served for, set by `dispatch_tenant`
+#[derive(Debug, Clone)]
+pub struct Tenant(pub String);
+
+/// Routes of every tenant, by name
+pub type TenantRouters = Arc<HashMap<String, Router>>;
+
+/// Middleware sending the requests of a tenant to the tenant's own routes
+///
+/// A request belongs to a tenant when its path starts with `/t/{tenant}`, which is stripped,
+/// or when an API request uses one of the tenant's passwords. Other requests go on to the
+/// shared routes.
+pub async fn dispatch_tenant(
+    State(routers): State<TenantRouters>,
+    req: Request,
+    next: Next,
+) -> Result<Response, ClewdrError> {
+    if routers.is_empty() {
+        return Ok(next.run(req).await);
+    }
+    let (mut parts, body) = req.into_parts();
+    let path = parts.uri.path().to_string();
+    let name = if let Some(scoped) = path.strip_prefix(TENANT_PREFIX) {
+        let (name, rest) = scoped.split_once('/').unwrap_or((scoped, ""));
+        let uri = match parts.uri.query() {
+            Some(query) => format!("/{rest}?{query}"),
+            None => format!("/{rest}"),
+        };
+        parts.uri = uri.parse().context(InvalidUriSnafu { uri })?;
+        name.to_string()
+    } else if ROUTED_PREFIXES.iter().any(|p| path.starts_with(p))
+        && let Some(name) = user_key(&parts).and_then(|k| {
+            CLEWDR_CONFIG
+                .load()
+                .tenant_of_key(&k)
+                .map(|t| t.name.to_owned())
+        })
+    {
+        name
+    } else {
+        return Ok(next.run(Request::from_parts(parts, body)).await);
+    };
+    let Some(router) = routers.get(&name) else {
+        return Err(ClewdrError::PathNotFound {
+            msg: format!("Unknown tenant {name}"),
+        });
+    };
+    debug!("Request to {} served for tenant {}", path, name);
+    parts.extensions.insert(Tenant(name));
+    Ok(router
+        .to_owned()
+        .oneshot(Request::from_parts(parts, body))
+        .await
+        .unwrap_or_else(|e| match e {}))
+}
@@ -0,0 +1,59 @@
+use wreq::ClientBuilder;
+
+use crate::{
+    config::{CLEWDR_CONFIG, ProxyTarget, TimeoutPhase, Timeouts},
+    error::ClewdrError,
+};
+
+tokio::task_local! {
+    static TIMEOUT_OVERRIDE: Timeouts;
+}
+
+/// Runs `fut` with the timeouts of its upstream calls taken from `timeouts` first
+pub async fn with_timeouts<F: Future>(timeouts: Timeouts, fut: F) -> F::Output {
+    TIMEOUT_OVERRIDE.scope(timeouts, fut).await
+}
+
+/// Timeouts of upstream calls made while serving the current request
+///
+/// # Arguments
+/// * `target` - Upstream the calls go to
+///
+/// # Returns
+/// * `Timeouts` - The per-request override if set, each unset phase from the configured timeouts
+///   of the upstream
+pub fn current_timeouts(target: ProxyTarget) -> Timeouts {
+    let configured = CLEWDR_CONFIG.load().timeouts.for_target(target);
+    TIMEOUT_OVERRIDE
+        .try_with(|t| t.or(configured))
+        .unwrap_or(configured)
+}
+
+/// Waits for the response headers of an upstream call for at most the first byte timeout
+///
+/// # Returns
+/// * `Err(ClewdrError::UpstreamTimeout)` - The upstream did not answer in time
+pub async fn first_byte<T>(
+    timeouts: &Timeouts,
+    fut: impl Future<Output = Result<T, ClewdrError>>,
+) -> Result<T, ClewdrError> {
+    let Some(limit) = timeouts.get(TimeoutPhase::FirstByte) else {
+        return fut.await;
+    };
+    tokio::time::timeout(limit, fut)
+        .await
+        .unwrap_or(Err(ClewdrError::UpstreamTimeout {
+            phase: TimeoutPhase::FirstByte,
+        }))
+}
+
+/// Sets the connect and total timeouts on a client
+pub fn apply_timeouts(mut builder: ClientBuilder, timeouts: &Timeouts) -> ClientBuilder {
+    if let Some(connect) = timeouts.get(TimeoutPhase::Connect) {
+        builder = builder.connect_timeout(connect);
+    }
+    if let Some(total) = timeouts.get(TimeoutPhase::Total) {
+        builder = builder.timeout(total);
+    }
+    builder
+}
@@ -1,38 +1,61 @@
-use sea_orm::{ConnectionTrait, Database, DatabaseConnection, Schema};
+use sea_orm::{Database, DatabaseConnection, DbErr};
+use sea_orm_migration::MigratorTrait;
 use tokio::sync::OnceCell;
 
-use super::entities::{
-    ColumnCookie, ColumnKeyRow, EntityConfig, EntityCookie, EntityKeyRow, EntityWasted,
-};
+use super::migration::Migrator;
 use crate::error::ClewdrError;
 
 static CONN: OnceCell<DatabaseConnection> = OnceCell::const_new();
 
-pub async fn ensure_conn() -> Result<DatabaseConnection, ClewdrError> {
+/// Opens a new connection to the configured database
+async fn connect() -> Result<DatabaseConnection, ClewdrError> {
     if !crate::config::CLEWDR_CONFIG.load().is_db_mode() {
         return Err(ClewdrError::Whatever {
             message: "DB mode not enabled".into(),
             source: None,
         });
     }
+    let cfg = crate::config::CLEWDR_CONFIG.load();
+    let url = cfg.database_url().ok_or(ClewdrError::UnexpectedNone {
+        msg: "Database URL not provided",
+    })?;
+    if url.starts_with("sqlite://")
+        && !cfg.no_fs
+        && let Some(parent) = std::path::Path::new(&url["sqlite://".len()..]).parent()
+    {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    Database::connect(&url)
+        .await
+        .map_err(|e| ClewdrError::Whatever {
+            message: "db_connect".into(),
+            source: Some(Box::new(e)),
+        })
+}
+
+/// Connects to the database of a candidate config and pings it, the shared connection is untouched
+pub async fn probe(config: &crate::config::ClewdrConfig) -> Result<(), ClewdrError> {
+    let url = config.database_url().ok_or(ClewdrError::UnexpectedNone {
+        msg: "Database URL not provided",
+    })?;
+    let db = Database::connect(&url)
+        .await
+        .map_err(|e| ClewdrError::Whatever {
+            message: "db_connect".into(),
+            source: Some(Box::new(e)),
+        })?;
+    let res = db.ping().await.map_err(|e| ClewdrError::Whatever {
+        message: "db_ping".into(),
+        source: Some(Box::new(e)),
+    });
+    let _ = db.close().await;
+    res
+}
+
+pub async fn ensure_conn() -> Result<DatabaseConnection, ClewdrError> {
     let db = CONN
         .get_or_try_init(|| async {
-            let cfg = crate::config::CLEWDR_CONFIG.load();
-            let url = cfg.database_url().ok_or(ClewdrError::UnexpectedNone {
-                msg: "Database URL not provided",
-            })?;
-            if url.starts_with("sqlite://")
-                && !cfg.no_fs
-                && let Some(parent) = std::path::Path::new(&url["sqlite://".len()..]).parent()
-            {
-                let _ = std::fs::create_dir_all(parent);
-            }
-            let db = Database::connect(&url)
-                .await
-                .map_err(|e| ClewdrError::Whatever {
-                    message: "db_connect".into(),
-                    source: Some(Box::new(e)),
-                })?;
+            let db = connect().await?;
             migrate(&db).await?;
             Ok::<_, ClewdrError>(db)
         })
@@ -40,84 +63,46 @@ pub async fn ensure_conn() -> Result<DatabaseConnection, ClewdrError> {
     Ok(db.clone())
 }
 
+/// Brings the schema up to date, or refuses to start on an outdated one when
+/// `persistence.auto_migrate` is off
 async fn migrate(db: &DatabaseConnection) -> Result<(), ClewdrError> {
-    let backend = db.get_database_backend();
-    let schema = Schema::new(backend);
-    let stmt: sea_orm::sea_query::TableCreateStatement =
-        schema.create_table_from_entity(EntityConfig);
-    db.execute(backend.build(&stmt)).await.ok();
-    let stmt = schema.create_table_from_entity(EntityCookie);
-    db.execute(backend.build(&stmt)).await.ok();
-    let stmt = schema.create_table_from_entity(EntityWasted);
-    db.execute(backend.build(&stmt)).await.ok();
-    let stmt = schema.create_table_from_entity(EntityKeyRow);
-    db.execute(backend.build(&stmt)).await.ok();
-    // indexes
-    use sea_orm::sea_query::{ColumnDef, Index, TableAlterStatement};
-    // cookies(token_org_uuid)
-    let idx = Index::create()
-        .name("idx_cookies_org_uuid")
-        .table(EntityCookie)
-        .col(ColumnCookie::TokenOrgUuid)
-        .to_owned();
-    db.execute(backend.build(&idx)).await.ok();
-    // cookies(reset_time)
-    let idx = Index::create()
-        .name("idx_cookies_reset")
-        .table(EntityCookie)
-        .col(ColumnCookie::ResetTime)
-        .to_owned();
-    db.execute(backend.build(&idx)).await.ok();
-    // keys(count_403)
-    let idx = Index::create()
-        .name("idx_keys_count")
-        .table(EntityKeyRow)
-        .col(ColumnKeyRow::Count403)
-        .to_owned();
-    db.execute(backend.build(&idx)).await.ok();
-
-    // Ensure supports_claude_1m column exists on cookies table
-    let alter = TableAlterStatement::new()
-        .table(EntityCookie)
-        .add_column(
-            ColumnDef::new(ColumnCookie::SupportsClaude1m)
-                .boolean()
-                .null(),
-        )
-        .add_column(
-            ColumnDef::new(ColumnCookie::CountTokensAllowed)
-                .boolean()
-                .null(),
-        )
-        .add_column(
-            ColumnDef::new(ColumnCookie::TotalInputTokens)
-                .big_integer()
-                .null(),
-        )
-        .add_column(
-            ColumnDef::new(ColumnCookie::TotalOutputTokens)
-                .big_integer()
-                .null(),
-        )
-        .add_column(
-            ColumnDef::new(ColumnCookie::WindowInputTokens)
-                .big_integer()
-                .null(),
-        )
-        .add_column(
-            ColumnDef::new(ColumnCookie::WindowOutputTokens)
-                .big_integer()
-                .null(),
-        )
-        .add_column(ColumnDef::new(ColumnCookie::LifetimeUsage).string().null())
-        .add_column(ColumnDef::new(ColumnCookie::SessionUsage).string().null())
-        .add_column(ColumnDef::new(ColumnCookie::WeeklyUsage).string().null())
-        .add_column(
-            ColumnDef::new(ColumnCookie::WeeklyOpusUsage)
-                .string()
-                .null(),
-        )
-        .to_owned();
-    db.execute(backend.build(&alter)).await.ok();
+    if crate::config::CLEWDR_CONFIG.load().persistence.auto_migrate {
+        return Migrator::up(db, None).await.map_err(migration_error);
+    }
+    let pending = Migrator::get_pending_migrations(db)
+        .await
+        .map_err(migration_error)?;
+    if !pending.is_empty() {
+        return Err(ClewdrError::Whatever {
+            message: format!(
+                "{} pending database migrations, run `clewdr migrate`",
+                pending.len()
+            ),
+            source: None,
+        });
+    }
     Ok(())
 }
+
+/// Applies pending migrations, whatever `persistence.auto_migrate` says
+///
+/// # Returns
+/// * `Result<Vec<String>, ClewdrError>` - Names of the applied migrations
+pub async fn run_migrations() -> Result<Vec<String>, ClewdrError> {
+    let db = connect().await?;
+    let pending = Migrator::get_pending_migrations(&db)
+        .await
+        .map_err(migration_error)?
+        .iter()
+        .map(|m| m.name().to_string())
+        .collect();
+    Migrator::up(&db, None).await.map_err(migration_error)?;
+    Ok(pending)
+}
+
+fn migration_error(e: DbErr) -> ClewdrError {
+    ClewdrError::Whatever {
+        message: "db_migrate".into(),
+        source: Some(Box::new(e)),
+    }
+}
@@ -61,6 +61,34 @@ pub mod entity_cookie {
         pub weekly_opus_usage: Option<String>,
         #[sea_orm(nullable)]
         pub lifetime_usage: Option<String>,
+        #[sea_orm(nullable)]
+        pub disabled: Option<bool>,
+        /// JSON list of the chat capable organizations
+        #[sea_orm(nullable)]
+        pub organizations: Option<String>,
+        #[sea_orm(nullable)]
+        pub pinned_org: Option<String>,
+        /// Lifetime prompt cache tokens, copied from `lifetime_usage` so SQL can read them
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub cache_read_input_tokens: Option<i64>,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub cache_creation_input_tokens: Option<i64>,
+        /// JSON list of the operator tags
+        #[sea_orm(nullable)]
+        pub tags: Option<String>,
+        /// JSON list of the latest health events
+        #[sea_orm(column_type = "Text", nullable)]
+        pub health_events: Option<String>,
+        /// JSON of the flags found and dismissed on the last clearing
+        #[sea_orm(column_type = "Text", nullable)]
+        pub cleared_flags: Option<String>,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub count_403: Option<i64>,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub count_429: Option<i64>,
+        /// Tenant owning the cookie, `None` for the shared pool
+        #[sea_orm(nullable)]
+        pub tenant: Option<String>,
     }
     #[derive(Copy, Clone, Debug, EnumIter)]
     pub enum Relation {}
@@ -80,6 +108,9 @@ pub mod entity_wasted {
         #[sea_orm(primary_key, auto_increment = false)]
         pub cookie: String,
         pub reason: String,
+        /// Tenant owning the cookie, `None` for the shared pool
+        #[sea_orm(nullable)]
+        pub tenant: Option<String>,
     }
     #[derive(Copy, Clone, Debug, EnumIter)]
     pub enum Relation {}
@@ -99,6 +130,133 @@ pub mod entity_key {
         #[sea_orm(primary_key, auto_increment = false)]
         pub key: String,
         pub count_403: i64,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub count_requests: Option<i64>,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub count_tokens: Option<i64>,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub count_429: Option<i64>,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub quota_day: Option<i64>,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub input_tokens: Option<i64>,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub output_tokens: Option<i64>,
+        #[sea_orm(nullable)]
+        pub disabled: Option<bool>,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub total_requests: Option<i64>,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub total_429: Option<i64>,
+        /// Tenant owning the key, `None` for the shared pool
+        #[sea_orm(nullable)]
+        pub tenant: Option<String>,
+        /// Dropped from the pool, kept until it is submitted again or deleted
+        #[sea_orm(nullable)]
+        pub invalid: Option<bool>,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub consecutive_failures: Option<i64>,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub quarantined_until: Option<i64>,
+        #[sea_orm(column_type = "BigInteger", nullable)]
+        pub failed_probes: Option<i64>,
+    }
+    #[derive(Copy, Clone, Debug, EnumIter)]
+    pub enum Relation {}
+    impl RelationTrait for Relation {
+        fn def(&self) -> RelationDef {
+            panic!()
+        }
+    }
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+pub mod entity_lease {
+    use super::*;
+    #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+    #[sea_orm(table_name = "cookie_leases")]
+    pub struct Model {
+        #[sea_orm(primary_key, auto_increment = false)]
+        pub cookie: String,
+        pub holder: String,
+        #[sea_orm(column_type = "BigInteger")]
+        pub expires_at: i64,
+    }
+    #[derive(Copy, Clone, Debug, EnumIter)]
+    pub enum Relation {}
+    impl RelationTrait for Relation {
+        fn def(&self) -> RelationDef {
+            panic!()
+        }
+    }
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+pub mod entity_transcript {
+    use super::*;
+    #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+    #[sea_orm(table_name = "transcripts")]
+    pub struct Model {
+        #[sea_orm(primary_key)]
+        pub id: i64,
+        #[sea_orm(column_type = "BigInteger")]
+        pub created_at: i64,
+        pub provider: String,
+        pub model: String,
+        #[sea_orm(column_type = "Text")]
+        pub prompt: String,
+        #[sea_orm(column_type = "Text")]
+        pub completion: String,
+        #[sea_orm(column_type = "BigInteger")]
+        pub input_tokens: i64,
+        #[sea_orm(column_type = "BigInteger")]
+        pub output_tokens: i64,
+    }
+    #[derive(Copy, Clone, Debug, EnumIter)]
+    pub enum Relation {}
+    impl RelationTrait for Relation {
+        fn def(&self) -> RelationDef {
+            panic!()
+        }
+    }
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+pub mod entity_affinity {
+    use super::*;
+    #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+    #[sea_orm(table_name = "cookie_affinity")]
+    pub struct Model {
+        /// Prompt hash, the bits of the `u64` stored as `i64`
+        #[sea_orm(primary_key, auto_increment = false)]
+        pub hash: i64,
+        pub cookie: String,
+        #[sea_orm(column_type = "BigInteger")]
+        pub expires_at: i64,
+    }
+    #[derive(Copy, Clone, Debug, EnumIter)]
+    pub enum Relation {}
+    impl RelationTrait for Relation {
+        fn def(&self) -> RelationDef {
+            panic!()
+        }
+    }
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+pub mod entity_batch_job {
+    use super::*;
+    #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+    #[sea_orm(table_name = "batch_jobs")]
+    pub struct Model {
+        #[sea_orm(primary_key, auto_increment = false)]
+        pub id: String,
+        #[sea_orm(column_type = "BigInteger")]
+        pub created_at: i64,
+        pub status: String,
+        /// The whole job as JSON, with the outcome of each request
+        #[sea_orm(column_type = "Text")]
+        pub job: String,
     }
     #[derive(Copy, Clone, Debug, EnumIter)]
     pub enum Relation {}
@@ -111,6 +269,12 @@ pub mod entity_key {
 }
 
 // Convenient aliases to match previous names used in code
+pub use entity_affinity::{
+    ActiveModel as ActiveModelAffinity, Column as ColumnAffinity, Entity as EntityAffinity,
+};
+pub use entity_batch_job::{
+    ActiveModel as ActiveModelBatchJob, Column as ColumnBatchJob, Entity as EntityBatchJob,
+};
 pub use entity_config::{
     ActiveModel as ActiveModelConfig, Column as ColumnConfig, Entity as EntityConfig,
 };
@@ -120,6 +284,12 @@ pub use entity_cookie::{
 pub use entity_key::{
     ActiveModel as ActiveModelKeyRow, Column as ColumnKeyRow, Entity as EntityKeyRow,
 };
+pub use entity_lease::{
+    ActiveModel as ActiveModelLease, Column as ColumnLease, Entity as EntityLease,
+};
+pub use entity_transcript::{
+    ActiveModel as ActiveModelTranscript, Column as ColumnTranscript, Entity as EntityTranscript,
+};
 pub use entity_wasted::{
     ActiveModel as ActiveModelWasted, Column as ColumnWasted, Entity as EntityWasted,
 };
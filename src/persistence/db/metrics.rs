@@ -3,6 +3,10 @@ use std::sync::{
     atomic::{AtomicI64, AtomicU64, Ordering},
 };
 
+use serde_json::json;
+
+use crate::{config::WebhookEvent, services::webhook};
+
 // metrics
 pub static LAST_WRITE_TS: LazyLock<AtomicI64> = LazyLock::new(|| AtomicI64::new(0));
 pub static WRITE_ERROR_COUNT: LazyLock<AtomicU64> = LazyLock::new(|| AtomicU64::new(0));
@@ -26,4 +30,10 @@ pub fn record_error_msg(e: &dyn std::error::Error) {
     if let Ok(mut g) = LAST_ERROR.lock() {
         *g = Some(e.to_string());
     }
+    webhook::emit(
+        WebhookEvent::DbUnhealthy,
+        "db",
+        format!("Database write failed: {e}"),
+        json!({ "error": e.to_string() }),
+    );
 }
@@ -0,0 +1,234 @@
+use sea_orm::{EntityName, EntityTrait};
+use sea_orm_migration::prelude::*;
+
+use crate::persistence::db::entities::{
+    ColumnConfig, ColumnCookie, ColumnKeyRow, ColumnLease, ColumnTranscript, ColumnWasted,
+    EntityConfig, EntityCookie, EntityKeyRow, EntityLease, EntityTranscript, EntityWasted,
+};
+
+/// The tables as first released, later columns are added by the following migrations
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+impl Migration {
+    async fn create_table(
+        manager: &SchemaManager<'_>,
+        table: TableCreateStatement,
+    ) -> Result<(), DbErr> {
+        manager.create_table(table.if_not_exists().to_owned()).await
+    }
+
+    async fn create_index<E, C>(
+        manager: &SchemaManager<'_>,
+        name: &str,
+        entity: E,
+        column: C,
+    ) -> Result<(), DbErr>
+    where
+        E: EntityTrait,
+        C: IntoIden,
+    {
+        if manager.has_index(entity.table_name(), name).await? {
+            return Ok(());
+        }
+        manager
+            .create_index(
+                Index::create()
+                    .name(name)
+                    .table(entity)
+                    .col(column)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        Self::create_table(
+            manager,
+            Table::create()
+                .table(EntityConfig)
+                .col(
+                    ColumnDef::new(ColumnConfig::K)
+                        .string()
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(ColumnDef::new(ColumnConfig::Data).string().not_null())
+                .col(ColumnDef::new(ColumnConfig::UpdatedAt).big_integer().null())
+                .to_owned(),
+        )
+        .await?;
+        Self::create_table(
+            manager,
+            Table::create()
+                .table(EntityCookie)
+                .col(
+                    ColumnDef::new(ColumnCookie::Cookie)
+                        .string()
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(ColumnDef::new(ColumnCookie::ResetTime).big_integer().null())
+                .col(ColumnDef::new(ColumnCookie::TokenAccess).string().null())
+                .col(ColumnDef::new(ColumnCookie::TokenRefresh).string().null())
+                .col(
+                    ColumnDef::new(ColumnCookie::TokenExpiresAt)
+                        .big_integer()
+                        .null(),
+                )
+                .col(
+                    ColumnDef::new(ColumnCookie::TokenExpiresIn)
+                        .big_integer()
+                        .null(),
+                )
+                .col(ColumnDef::new(ColumnCookie::TokenOrgUuid).string().null())
+                .to_owned(),
+        )
+        .await?;
+        Self::create_table(
+            manager,
+            Table::create()
+                .table(EntityWasted)
+                .col(
+                    ColumnDef::new(ColumnWasted::Cookie)
+                        .string()
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(ColumnDef::new(ColumnWasted::Reason).string().not_null())
+                .to_owned(),
+        )
+        .await?;
+        Self::create_table(
+            manager,
+            Table::create()
+                .table(EntityKeyRow)
+                .col(
+                    ColumnDef::new(ColumnKeyRow::Key)
+                        .string()
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(ColumnKeyRow::Count403)
+                        .big_integer()
+                        .not_null(),
+                )
+                .to_owned(),
+        )
+        .await?;
+        Self::create_table(
+            manager,
+            Table::create()
+                .table(EntityLease)
+                .col(
+                    ColumnDef::new(ColumnLease::Cookie)
+                        .string()
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(ColumnDef::new(ColumnLease::Holder).string().not_null())
+                .col(
+                    ColumnDef::new(ColumnLease::ExpiresAt)
+                        .big_integer()
+                        .not_null(),
+                )
+                .to_owned(),
+        )
+        .await?;
+        Self::create_table(
+            manager,
+            Table::create()
+                .table(EntityTranscript)
+                .col(
+                    ColumnDef::new(ColumnTranscript::Id)
+                        .big_integer()
+                        .not_null()
+                        .auto_increment()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(ColumnTranscript::CreatedAt)
+                        .big_integer()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(ColumnTranscript::Provider)
+                        .string()
+                        .not_null(),
+                )
+                .col(ColumnDef::new(ColumnTranscript::Model).string().not_null())
+                .col(ColumnDef::new(ColumnTranscript::Prompt).text().not_null())
+                .col(
+                    ColumnDef::new(ColumnTranscript::Completion)
+                        .text()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(ColumnTranscript::InputTokens)
+                        .big_integer()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(ColumnTranscript::OutputTokens)
+                        .big_integer()
+                        .not_null(),
+                )
+                .to_owned(),
+        )
+        .await?;
+
+        Self::create_index(
+            manager,
+            "idx_cookies_org_uuid",
+            EntityCookie,
+            ColumnCookie::TokenOrgUuid,
+        )
+        .await?;
+        Self::create_index(
+            manager,
+            "idx_cookies_reset",
+            EntityCookie,
+            ColumnCookie::ResetTime,
+        )
+        .await?;
+        Self::create_index(
+            manager,
+            "idx_transcripts_created",
+            EntityTranscript,
+            ColumnTranscript::CreatedAt,
+        )
+        .await?;
+        Self::create_index(
+            manager,
+            "idx_keys_count",
+            EntityKeyRow,
+            ColumnKeyRow::Count403,
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in [
+            EntityTranscript.table_name(),
+            EntityLease.table_name(),
+            EntityKeyRow.table_name(),
+            EntityWasted.table_name(),
+            EntityCookie.table_name(),
+            EntityConfig.table_name(),
+        ] {
+            manager
+                .drop_table(
+                    Table::drop()
+                        .table(Alias::new(table))
+                        .if_exists()
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+
+use super::{add_column, drop_column};
+use crate::persistence::db::entities::{ColumnCookie, EntityCookie};
+
+/// Claude capability flags and usage buckets of cookies
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for def in [
+            ColumnDef::new(ColumnCookie::SupportsClaude1m)
+                .boolean()
+                .to_owned(),
+            ColumnDef::new(ColumnCookie::CountTokensAllowed)
+                .boolean()
+                .to_owned(),
+            ColumnDef::new(ColumnCookie::TotalInputTokens)
+                .big_integer()
+                .to_owned(),
+            ColumnDef::new(ColumnCookie::TotalOutputTokens)
+                .big_integer()
+                .to_owned(),
+            ColumnDef::new(ColumnCookie::WindowInputTokens)
+                .big_integer()
+                .to_owned(),
+            ColumnDef::new(ColumnCookie::WindowOutputTokens)
+                .big_integer()
+                .to_owned(),
+            ColumnDef::new(ColumnCookie::LifetimeUsage)
+                .string()
+                .to_owned(),
+            ColumnDef::new(ColumnCookie::SessionUsage)
+                .string()
+                .to_owned(),
+            ColumnDef::new(ColumnCookie::WeeklyUsage)
+                .string()
+                .to_owned(),
+            ColumnDef::new(ColumnCookie::WeeklyOpusUsage)
+                .string()
+                .to_owned(),
+        ] {
+            add_column(manager, EntityCookie, def).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            ColumnCookie::SupportsClaude1m,
+            ColumnCookie::CountTokensAllowed,
+            ColumnCookie::TotalInputTokens,
+            ColumnCookie::TotalOutputTokens,
+            ColumnCookie::WindowInputTokens,
+            ColumnCookie::WindowOutputTokens,
+            ColumnCookie::LifetimeUsage,
+            ColumnCookie::SessionUsage,
+            ColumnCookie::WeeklyUsage,
+            ColumnCookie::WeeklyOpusUsage,
+        ] {
+            drop_column(manager, EntityCookie, column).await?;
+        }
+        Ok(())
+    }
+}
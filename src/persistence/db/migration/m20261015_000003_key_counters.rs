@@ -0,0 +1,40 @@
+use sea_orm_migration::prelude::*;
+
+use super::{add_column, drop_column};
+use crate::persistence::db::entities::{ColumnKeyRow, EntityKeyRow};
+
+/// Daily quota and lifetime token counters of keys
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            ColumnKeyRow::CountRequests,
+            ColumnKeyRow::CountTokens,
+            ColumnKeyRow::Count429,
+            ColumnKeyRow::QuotaDay,
+            ColumnKeyRow::InputTokens,
+            ColumnKeyRow::OutputTokens,
+        ] {
+            let def = ColumnDef::new(column).big_integer().to_owned();
+            add_column(manager, EntityKeyRow, def).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            ColumnKeyRow::CountRequests,
+            ColumnKeyRow::CountTokens,
+            ColumnKeyRow::Count429,
+            ColumnKeyRow::QuotaDay,
+            ColumnKeyRow::InputTokens,
+            ColumnKeyRow::OutputTokens,
+        ] {
+            drop_column(manager, EntityKeyRow, column).await?;
+        }
+        Ok(())
+    }
+}
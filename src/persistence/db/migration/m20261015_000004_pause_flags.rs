@@ -0,0 +1,23 @@
+use sea_orm_migration::prelude::*;
+
+use super::{add_column, drop_column};
+use crate::persistence::db::entities::{ColumnCookie, ColumnKeyRow, EntityCookie, EntityKeyRow};
+
+/// Pause flags of cookies and keys
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let def = ColumnDef::new(ColumnCookie::Disabled).boolean().to_owned();
+        add_column(manager, EntityCookie, def).await?;
+        let def = ColumnDef::new(ColumnKeyRow::Disabled).boolean().to_owned();
+        add_column(manager, EntityKeyRow, def).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        drop_column(manager, EntityCookie, ColumnCookie::Disabled).await?;
+        drop_column(manager, EntityKeyRow, ColumnKeyRow::Disabled).await
+    }
+}
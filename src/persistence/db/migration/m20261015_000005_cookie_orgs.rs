@@ -0,0 +1,28 @@
+use sea_orm_migration::prelude::*;
+
+use super::{add_column, drop_column};
+use crate::persistence::db::entities::{ColumnCookie, EntityCookie};
+
+/// Discovered and pinned organizations of cookies
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for def in [
+            ColumnDef::new(ColumnCookie::Organizations)
+                .string()
+                .to_owned(),
+            ColumnDef::new(ColumnCookie::PinnedOrg).string().to_owned(),
+        ] {
+            add_column(manager, EntityCookie, def).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        drop_column(manager, EntityCookie, ColumnCookie::Organizations).await?;
+        drop_column(manager, EntityCookie, ColumnCookie::PinnedOrg).await
+    }
+}
@@ -0,0 +1,55 @@
+use sea_orm::EntityName;
+use sea_orm_migration::prelude::*;
+
+use crate::persistence::db::entities::{ColumnAffinity, EntityAffinity};
+
+/// Prompt hash to cookie pins of Claude Code requests
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(EntityAffinity)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ColumnAffinity::Hash)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ColumnAffinity::Cookie).string().not_null())
+                    .col(
+                        ColumnDef::new(ColumnAffinity::ExpiresAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        if manager
+            .has_index(EntityAffinity.table_name(), "idx_affinity_expires")
+            .await?
+        {
+            return Ok(());
+        }
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_affinity_expires")
+                    .table(EntityAffinity)
+                    .col(ColumnAffinity::ExpiresAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(EntityAffinity).if_exists().to_owned())
+            .await
+    }
+}
@@ -0,0 +1,35 @@
+use sea_orm_migration::prelude::*;
+
+use super::{add_column, drop_column};
+use crate::persistence::db::entities::{ColumnCookie, EntityCookie};
+
+/// Lifetime prompt cache tokens of cookies
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for def in [
+            ColumnDef::new(ColumnCookie::CacheReadInputTokens)
+                .big_integer()
+                .to_owned(),
+            ColumnDef::new(ColumnCookie::CacheCreationInputTokens)
+                .big_integer()
+                .to_owned(),
+        ] {
+            add_column(manager, EntityCookie, def).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        drop_column(manager, EntityCookie, ColumnCookie::CacheReadInputTokens).await?;
+        drop_column(
+            manager,
+            EntityCookie,
+            ColumnCookie::CacheCreationInputTokens,
+        )
+        .await
+    }
+}
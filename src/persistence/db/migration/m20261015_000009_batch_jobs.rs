@@ -0,0 +1,40 @@
+use sea_orm_migration::prelude::*;
+
+use crate::persistence::db::entities::{ColumnBatchJob, EntityBatchJob};
+
+/// Jobs of the batch completion API
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(EntityBatchJob)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ColumnBatchJob::Id)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ColumnBatchJob::CreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ColumnBatchJob::Status).string().not_null())
+                    .col(ColumnDef::new(ColumnBatchJob::Job).text().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(EntityBatchJob).if_exists().to_owned())
+            .await
+    }
+}
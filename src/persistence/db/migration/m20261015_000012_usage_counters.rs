@@ -0,0 +1,33 @@
+use sea_orm_migration::prelude::*;
+
+use super::{add_column, drop_column};
+use crate::persistence::db::entities::{ColumnCookie, ColumnKeyRow, EntityCookie, EntityKeyRow};
+
+/// Lifetime request and rejection counters of keys and cookies, for usage exports
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [ColumnKeyRow::TotalRequests, ColumnKeyRow::Total429] {
+            let def = ColumnDef::new(column).big_integer().to_owned();
+            add_column(manager, EntityKeyRow, def).await?;
+        }
+        for column in [ColumnCookie::Count403, ColumnCookie::Count429] {
+            let def = ColumnDef::new(column).big_integer().to_owned();
+            add_column(manager, EntityCookie, def).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [ColumnKeyRow::TotalRequests, ColumnKeyRow::Total429] {
+            drop_column(manager, EntityKeyRow, column).await?;
+        }
+        for column in [ColumnCookie::Count403, ColumnCookie::Count429] {
+            drop_column(manager, EntityCookie, column).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+use sea_orm_migration::prelude::*;
+
+use super::{add_column, drop_column};
+use crate::persistence::db::entities::{
+    ColumnCookie, ColumnKeyRow, ColumnWasted, EntityCookie, EntityKeyRow, EntityWasted,
+};
+
+/// Owner of cookie and key rows, so the pools of tenants get rows of their own
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        add_column(
+            manager,
+            EntityCookie,
+            ColumnDef::new(ColumnCookie::Tenant).string().to_owned(),
+        )
+        .await?;
+        add_column(
+            manager,
+            EntityWasted,
+            ColumnDef::new(ColumnWasted::Tenant).string().to_owned(),
+        )
+        .await?;
+        add_column(
+            manager,
+            EntityKeyRow,
+            ColumnDef::new(ColumnKeyRow::Tenant).string().to_owned(),
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        drop_column(manager, EntityCookie, ColumnCookie::Tenant).await?;
+        drop_column(manager, EntityWasted, ColumnWasted::Tenant).await?;
+        drop_column(manager, EntityKeyRow, ColumnKeyRow::Tenant).await
+    }
+}
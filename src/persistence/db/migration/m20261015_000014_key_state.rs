@@ -0,0 +1,40 @@
+use sea_orm_migration::prelude::*;
+
+use super::{add_column, drop_column};
+use crate::persistence::db::entities::{ColumnKeyRow, EntityKeyRow};
+
+/// Dropped keys and the quarantine state of keys, so they survive a restart
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        add_column(
+            manager,
+            EntityKeyRow,
+            ColumnDef::new(ColumnKeyRow::Invalid).boolean().to_owned(),
+        )
+        .await?;
+        for column in [
+            ColumnKeyRow::ConsecutiveFailures,
+            ColumnKeyRow::QuarantinedUntil,
+            ColumnKeyRow::FailedProbes,
+        ] {
+            add_column(
+                manager,
+                EntityKeyRow,
+                ColumnDef::new(column).big_integer().to_owned(),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        drop_column(manager, EntityKeyRow, ColumnKeyRow::Invalid).await?;
+        drop_column(manager, EntityKeyRow, ColumnKeyRow::ConsecutiveFailures).await?;
+        drop_column(manager, EntityKeyRow, ColumnKeyRow::QuarantinedUntil).await?;
+        drop_column(manager, EntityKeyRow, ColumnKeyRow::FailedProbes).await
+    }
+}
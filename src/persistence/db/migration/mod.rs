@@ -0,0 +1,85 @@
+//! Versioned schema migrations, applied in order and recorded in `seaql_migrations`
+//!
+//! Databases created by versions without migrations already have some of the tables
+//! and columns, so every step checks the schema before changing it.
+use sea_orm::{ColumnTrait, EntityName, EntityTrait};
+use sea_orm_migration::prelude::*;
+
+mod m20261015_000001_create_tables;
+mod m20261015_000002_cookie_usage;
+mod m20261015_000003_key_counters;
+mod m20261015_000004_pause_flags;
+mod m20261015_000005_cookie_orgs;
+mod m20261015_000006_cookie_affinity;
+mod m20261015_000007_cookie_cache_usage;
+mod m20261015_000008_cookie_tags;
+mod m20261015_000009_batch_jobs;
+mod m20261015_000010_cookie_health;
+mod m20261015_000011_cookie_cleared_flags;
+mod m20261015_000012_usage_counters;
+mod m20261015_000013_tenant_pools;
+mod m20261015_000014_key_state;
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20261015_000001_create_tables::Migration),
+            Box::new(m20261015_000002_cookie_usage::Migration),
+            Box::new(m20261015_000003_key_counters::Migration),
+            Box::new(m20261015_000004_pause_flags::Migration),
+            Box::new(m20261015_000005_cookie_orgs::Migration),
+            Box::new(m20261015_000006_cookie_affinity::Migration),
+            Box::new(m20261015_000007_cookie_cache_usage::Migration),
+            Box::new(m20261015_000008_cookie_tags::Migration),
+            Box::new(m20261015_000009_batch_jobs::Migration),
+            Box::new(m20261015_000010_cookie_health::Migration),
+            Box::new(m20261015_000011_cookie_cleared_flags::Migration),
+            Box::new(m20261015_000012_usage_counters::Migration),
+            Box::new(m20261015_000013_tenant_pools::Migration),
+            Box::new(m20261015_000014_key_state::Migration),
+        ]
+    }
+}
+
+/// Adds a nullable column unless the table already has it
+async fn add_column<E>(
+    manager: &SchemaManager<'_>,
+    entity: E,
+    mut def: ColumnDef,
+) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+{
+    let column = def.get_column_name();
+    if manager.has_column(entity.table_name(), &column).await? {
+        return Ok(());
+    }
+    manager
+        .alter_table(
+            Table::alter()
+                .table(entity)
+                .add_column(def.null())
+                .to_owned(),
+        )
+        .await
+}
+
+/// Drops a column if the table has it
+async fn drop_column<E, C>(manager: &SchemaManager<'_>, entity: E, column: C) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+    C: ColumnTrait,
+{
+    if !manager
+        .has_column(entity.table_name(), column.as_str())
+        .await?
+    {
+        return Ok(());
+    }
+    manager
+        .alter_table(Table::alter().table(entity).drop_column(column).to_owned())
+        .await
+}
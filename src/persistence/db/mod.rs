@@ -1,104 +1,129 @@
 pub mod conn;
 pub mod entities;
 pub mod metrics;
+mod migration;
 pub mod repo;
 
+use async_trait::async_trait;
+
 use crate::{
     config::{ClewdrConfig, CookieStatus, KeyStatus, UselessCookie},
     error::ClewdrError,
-    persistence::StorageLayer,
+    persistence::{Affinity, BatchJob, StorageBatch, StorageLayer, Transcript},
 };
 
-pub struct DbLayer;
+pub struct DbLayer {
+    /// Tenant whose pools the cookie and key methods read and write, `None` for the shared pools
+    pub tenant: Option<String>,
+}
 
+#[async_trait]
 impl StorageLayer for DbLayer {
     fn is_enabled(&self) -> bool {
         true
     }
-    fn spawn_bootstrap(
-        &self,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        Box::pin(async { repo::bootstrap_from_db_if_enabled().await })
+    async fn spawn_bootstrap(&self) -> Result<(), ClewdrError> {
+        repo::bootstrap_from_db_if_enabled().await
     }
-    fn persist_config(
-        &self,
-        cfg: &ClewdrConfig,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        let c = cfg.clone();
-        Box::pin(async move { repo::persist_config(&c).await })
+    async fn persist_config(&self, cfg: &ClewdrConfig) -> Result<(), ClewdrError> {
+        repo::persist_config(cfg).await
     }
-    fn persist_cookies(
+    async fn config_updated_at(&self) -> Result<Option<i64>, ClewdrError> {
+        repo::config_updated_at().await
+    }
+    async fn persist_cookies(
         &self,
         valid: &[CookieStatus],
         exhausted: &[CookieStatus],
         invalid: &[UselessCookie],
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        let v = valid.to_vec();
-        let e = exhausted.to_vec();
-        let i = invalid.to_vec();
-        Box::pin(async move { repo::persist_cookies(&v, &e, &i).await })
+    ) -> Result<(), ClewdrError> {
+        repo::persist_cookies(valid, exhausted, invalid, self.tenant.as_deref()).await
     }
-    fn persist_keys(
-        &self,
-        keys: &[KeyStatus],
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        let k = keys.to_vec();
-        Box::pin(async move { repo::persist_keys(&k).await })
+    async fn persist_batch(&self, batch: &StorageBatch) -> Result<(), ClewdrError> {
+        repo::persist_batch(batch, self.tenant.as_deref()).await
     }
-    fn persist_cookie_upsert(
-        &self,
-        c: &CookieStatus,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        let cc = c.clone();
-        Box::pin(async move { repo::persist_cookie_upsert(&cc).await })
+    async fn persist_keys(&self, keys: &[KeyStatus]) -> Result<(), ClewdrError> {
+        repo::persist_keys(keys, self.tenant.as_deref()).await
     }
-    fn delete_cookie_row(
-        &self,
-        c: &CookieStatus,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        let cc = c.clone();
-        Box::pin(async move { repo::delete_cookie_row(&cc).await })
+    async fn persist_cookie_upsert(&self, c: &CookieStatus) -> Result<(), ClewdrError> {
+        repo::persist_cookie_upsert(c, self.tenant.as_deref()).await
     }
-    fn persist_wasted_upsert(
-        &self,
-        u: &UselessCookie,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        let uu = u.clone();
-        Box::pin(async move { repo::persist_wasted_upsert(&uu).await })
+    async fn delete_cookie_row(&self, c: &CookieStatus) -> Result<(), ClewdrError> {
+        repo::delete_cookie_row(c).await
     }
-    fn persist_key_upsert(
-        &self,
-        k: &KeyStatus,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        let kk = k.clone();
-        Box::pin(async move { repo::persist_key_upsert(&kk).await })
+    async fn persist_wasted_upsert(&self, u: &UselessCookie) -> Result<(), ClewdrError> {
+        repo::persist_wasted_upsert(u, self.tenant.as_deref()).await
     }
-    fn delete_key_row(
-        &self,
-        k: &KeyStatus,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        let kk = k.clone();
-        Box::pin(async move { repo::delete_key_row(&kk).await })
+    async fn persist_key_upsert(&self, k: &KeyStatus) -> Result<(), ClewdrError> {
+        repo::persist_key_upsert(k, self.tenant.as_deref()).await
+    }
+    async fn delete_key_row(&self, k: &KeyStatus) -> Result<(), ClewdrError> {
+        repo::delete_key_row(k).await
+    }
+    async fn import_from_file(&self) -> Result<serde_json::Value, ClewdrError> {
+        repo::import_config_from_file().await
+    }
+    async fn export_current_config(&self) -> Result<serde_json::Value, ClewdrError> {
+        repo::export_current_config().await
     }
-    fn import_from_file(
+    async fn snapshot_config(&self) -> Result<ClewdrConfig, ClewdrError> {
+        repo::snapshot_config().await
+    }
+    async fn status(&self) -> Result<serde_json::Value, ClewdrError> {
+        repo::status_json().await
+    }
+    async fn acquire_lease(
         &self,
-    ) -> std::pin::Pin<
-        Box<dyn std::future::Future<Output = Result<serde_json::Value, ClewdrError>> + Send>,
-    > {
-        Box::pin(async move { repo::import_config_from_file().await })
+        cookie: &str,
+        holder: &str,
+        ttl_secs: u64,
+    ) -> Result<bool, ClewdrError> {
+        repo::acquire_lease(cookie, holder, ttl_secs).await
+    }
+    async fn release_lease(&self, cookie: &str, holder: &str) -> Result<(), ClewdrError> {
+        repo::release_lease(cookie, holder).await
+    }
+    async fn persist_affinity(&self, a: &Affinity) -> Result<(), ClewdrError> {
+        repo::upsert_affinity(a).await
+    }
+    async fn load_affinity(&self, now: i64) -> Result<Vec<Affinity>, ClewdrError> {
+        repo::load_affinity(now).await
+    }
+    async fn prune_affinity(&self, now: i64) -> Result<u64, ClewdrError> {
+        repo::prune_affinity(now).await
+    }
+    async fn persist_transcript(&self, t: &Transcript) -> Result<(), ClewdrError> {
+        repo::insert_transcript(t).await
     }
-    fn export_current_config(
+    async fn list_transcripts(
         &self,
-    ) -> std::pin::Pin<
-        Box<dyn std::future::Future<Output = Result<serde_json::Value, ClewdrError>> + Send>,
-    > {
-        Box::pin(async move { repo::export_current_config().await })
+        offset: u64,
+        limit: u64,
+        search: Option<&str>,
+    ) -> Result<(Vec<Transcript>, u64), ClewdrError> {
+        repo::list_transcripts(offset, limit, search).await
+    }
+    async fn prune_transcripts(&self, before: i64) -> Result<u64, ClewdrError> {
+        repo::prune_transcripts(before).await
+    }
+    async fn persist_batch_job(&self, job: &BatchJob) -> Result<(), ClewdrError> {
+        repo::upsert_batch_job(job).await
+    }
+    async fn load_batch_job(&self, id: &str) -> Result<Option<BatchJob>, ClewdrError> {
+        repo::load_batch_job(id).await
+    }
+    async fn load_unfinished_batch_jobs(&self) -> Result<Vec<BatchJob>, ClewdrError> {
+        repo::load_unfinished_batch_jobs().await
+    }
+    async fn load_keys(&self) -> Result<Vec<KeyStatus>, ClewdrError> {
+        repo::load_all_keys(self.tenant.as_deref(), false).await
+    }
+    async fn load_invalid_keys(&self) -> Result<Vec<KeyStatus>, ClewdrError> {
+        repo::load_all_keys(self.tenant.as_deref(), true).await
     }
-    fn status(
+    async fn load_cookies(
         &self,
-    ) -> std::pin::Pin<
-        Box<dyn std::future::Future<Output = Result<serde_json::Value, ClewdrError>> + Send>,
-    > {
-        Box::pin(async move { repo::status_json().await })
+    ) -> Result<(Vec<CookieStatus>, Vec<CookieStatus>, Vec<UselessCookie>), ClewdrError> {
+        repo::load_all_cookies(self.tenant.as_deref()).await
     }
 }
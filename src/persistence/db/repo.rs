@@ -1,11 +1,20 @@
-use sea_orm::{ActiveValue::Set, entity::prelude::*};
+use sea_orm::{
+    ActiveValue::{NotSet, Set},
+    Condition, DatabaseTransaction, QueryOrder, QuerySelect, TransactionTrait,
+    entity::prelude::*,
+    sea_query::SimpleExpr,
+};
 use serde_json::json;
 use tracing::error;
 
 use super::{conn::ensure_conn, entities::*, metrics::*};
 use crate::{
-    config::{ClewdrConfig, CookieStatus, KeyStatus, UsageBreakdown, UselessCookie},
+    config::{
+        ClewdrConfig, CookieStatus, KeyStatus, UsageBreakdown, UselessCookie, decrypt_secret,
+        encrypt_secret, open_config, seal_config,
+    },
     error::ClewdrError,
+    persistence::{Affinity, BatchJob, BatchStatus, StorageBatch, Transcript},
 };
 
 fn clamp_u64_to_i64(value: u64) -> i64 {
@@ -16,15 +25,149 @@ fn clamp_u64_to_i64(value: u64) -> i64 {
     }
 }
 
+/// Rows of a tenant's pool, or of the shared pool for `None`
+fn owned_by(column: impl ColumnTrait, tenant: Option<&str>) -> SimpleExpr {
+    match tenant {
+        Some(name) => column.eq(name),
+        None => column.is_null(),
+    }
+}
+
+/// Rows of dropped keys for `invalid`, of valid keys otherwise
+fn key_state(invalid: bool) -> Condition {
+    match invalid {
+        true => Condition::all().add(ColumnKeyRow::Invalid.eq(true)),
+        false => Condition::any()
+            .add(ColumnKeyRow::Invalid.is_null())
+            .add(ColumnKeyRow::Invalid.eq(false)),
+    }
+}
+
+fn key_active_model(k: &KeyStatus, tenant: Option<&str>, invalid: bool) -> ActiveModelKeyRow {
+    ActiveModelKeyRow {
+        key: Set(encrypt_secret(&k.key)),
+        count_403: Set(k.count_403 as i64),
+        count_requests: Set(Some(clamp_u64_to_i64(k.count_requests))),
+        count_tokens: Set(Some(clamp_u64_to_i64(k.count_tokens))),
+        count_429: Set(Some(k.count_429 as i64)),
+        quota_day: Set(Some(k.quota_day)),
+        input_tokens: Set(Some(clamp_u64_to_i64(k.input_tokens))),
+        output_tokens: Set(Some(clamp_u64_to_i64(k.output_tokens))),
+        disabled: Set(Some(k.disabled)),
+        total_requests: Set(Some(clamp_u64_to_i64(k.total_requests))),
+        total_429: Set(Some(k.total_429 as i64)),
+        tenant: Set(tenant.map(str::to_string)),
+        invalid: Set(Some(invalid)),
+        consecutive_failures: Set(Some(k.consecutive_failures as i64)),
+        quarantined_until: Set(k.quarantined_until),
+        failed_probes: Set(Some(k.failed_probes as i64)),
+    }
+}
+
+fn key_from_row(r: entity_key::Model) -> Result<KeyStatus, ClewdrError> {
+    Ok(KeyStatus {
+        key: decrypt_secret(&r.key)?.into(),
+        count_403: r.count_403 as u32,
+        count_requests: r.count_requests.unwrap_or_default().max(0) as u64,
+        count_tokens: r.count_tokens.unwrap_or_default().max(0) as u64,
+        count_429: r.count_429.unwrap_or_default().max(0) as u32,
+        quota_day: r.quota_day.unwrap_or_default(),
+        input_tokens: r.input_tokens.unwrap_or_default().max(0) as u64,
+        output_tokens: r.output_tokens.unwrap_or_default().max(0) as u64,
+        total_requests: r.total_requests.unwrap_or_default().max(0) as u64,
+        total_429: r.total_429.unwrap_or_default().max(0) as u32,
+        disabled: r.disabled.unwrap_or_default(),
+        consecutive_failures: r.consecutive_failures.unwrap_or_default().max(0) as u32,
+        quarantined_until: r.quarantined_until,
+        failed_probes: r.failed_probes.unwrap_or_default().max(0) as u32,
+    })
+}
+
+/// Cookie of a `cookies` row, with its token, flags and usage
+fn cookie_from_row(r: entity_cookie::Model) -> Result<CookieStatus, ClewdrError> {
+    let mut c = CookieStatus::new(&decrypt_secret(&r.cookie)?, r.reset_time).unwrap_or_default();
+    if let Some(acc) = r.token_access {
+        let expires_at = r
+            .token_expires_at
+            .and_then(|s| chrono::DateTime::from_timestamp(s, 0))
+            .unwrap_or_else(chrono::Utc::now);
+        let expires_in =
+            std::time::Duration::from_secs(r.token_expires_in.unwrap_or_default() as u64);
+        c.token = Some(crate::config::TokenInfo {
+            access_token: decrypt_secret(&acc)?,
+            refresh_token: decrypt_secret(&r.token_refresh.unwrap_or_default())?,
+            organization: crate::config::Organization {
+                uuid: r.token_org_uuid.unwrap_or_default(),
+            },
+            expires_at,
+            expires_in,
+        });
+    }
+    c.supports_claude_1m = r.supports_claude_1m;
+    c.count_tokens_allowed = r.count_tokens_allowed;
+    c.disabled = r.disabled.unwrap_or_default();
+    c.organizations = r
+        .organizations
+        .as_deref()
+        .and_then(|s| serde_json::from_str(s).ok())
+        .unwrap_or_default();
+    c.pinned_org = r.pinned_org;
+    c.tags = r
+        .tags
+        .as_deref()
+        .and_then(|s| serde_json::from_str(s).ok())
+        .unwrap_or_default();
+    c.health = r
+        .health_events
+        .as_deref()
+        .and_then(|s| serde_json::from_str(s).ok())
+        .unwrap_or_default();
+    c.cleared_flags = r
+        .cleared_flags
+        .as_deref()
+        .and_then(|s| serde_json::from_str(s).ok());
+    c.count_403 = r.count_403.unwrap_or_default().max(0) as u32;
+    c.count_429 = r.count_429.unwrap_or_default().max(0) as u32;
+    if let Some(s) = r.session_usage.as_ref() {
+        if let Ok(v) = serde_json::from_str::<UsageBreakdown>(s) {
+            c.session_usage = v;
+        }
+    }
+    if let Some(s) = r.weekly_usage.as_ref() {
+        if let Ok(v) = serde_json::from_str::<UsageBreakdown>(s) {
+            c.weekly_usage = v;
+        }
+    }
+    if let Some(s) = r.weekly_opus_usage.as_ref() {
+        if let Ok(v) = serde_json::from_str::<UsageBreakdown>(s) {
+            c.weekly_opus_usage = v;
+        }
+    }
+    if let Some(s) = r.lifetime_usage.as_ref() {
+        if let Ok(v) = serde_json::from_str::<UsageBreakdown>(s) {
+            c.lifetime_usage = v;
+        }
+    }
+    // the cache columns are kept for SQL queries, edits to them win over the JSON copy
+    if let Some(read) = r.cache_read_input_tokens {
+        c.lifetime_usage.cache_read_input_tokens = read.max(0) as u64;
+    }
+    if let Some(creation) = r.cache_creation_input_tokens {
+        c.lifetime_usage.cache_creation_input_tokens = creation.max(0) as u64;
+    }
+    Ok(c)
+}
+
 pub async fn bootstrap_from_db_if_enabled() -> Result<(), ClewdrError> {
     if !crate::config::CLEWDR_CONFIG.load().is_db_mode() {
         return Ok(());
     }
     let db = ensure_conn().await?;
     if let Ok(Some(row)) = EntityConfig::find_by_id("main").one(&db).await {
-        match toml::from_str::<ClewdrConfig>(&row.data) {
+        match open_config(&row.data) {
             Ok(mut cfg) => {
                 cfg = cfg.validate();
+                crate::config::record_stored(&cfg);
                 crate::config::CLEWDR_CONFIG.store(std::sync::Arc::new(cfg));
             }
             Err(e) => {
@@ -35,12 +178,25 @@ pub async fn bootstrap_from_db_if_enabled() -> Result<(), ClewdrError> {
     Ok(())
 }
 
+/// When the stored config was last written, `None` if there is none
+pub async fn config_updated_at() -> Result<Option<i64>, ClewdrError> {
+    let db = ensure_conn().await?;
+    let row = EntityConfig::find_by_id("main")
+        .one(&db)
+        .await
+        .map_err(|e| ClewdrError::Whatever {
+            message: "load_config".into(),
+            source: Some(Box::new(e)),
+        })?;
+    Ok(row.and_then(|r| r.updated_at))
+}
+
 pub async fn persist_config(config: &ClewdrConfig) -> Result<(), ClewdrError> {
     if !crate::config::CLEWDR_CONFIG.load().is_db_mode() {
         return Ok(());
     }
     let db = ensure_conn().await?;
-    let data = toml::to_string_pretty(config)?;
+    let data = seal_config(config)?;
     use sea_orm::sea_query::OnConflict;
     let am = ActiveModelConfig {
         k: Set("main".to_string()),
@@ -73,16 +229,11 @@ pub async fn persist_config(config: &ClewdrConfig) -> Result<(), ClewdrError> {
     Ok(())
 }
 
-pub async fn persist_cookie_upsert(c: &CookieStatus) -> Result<(), ClewdrError> {
-    if !crate::config::CLEWDR_CONFIG.load().is_db_mode() {
-        return Ok(());
-    }
-    let db = ensure_conn().await?;
-    use sea_orm::sea_query::OnConflict;
+fn cookie_active_model(c: &CookieStatus, tenant: Option<&str>) -> ActiveModelCookie {
     let (acc, rtk, exp_at, exp_in, org) = if let Some(t) = &c.token {
         (
-            Some(t.access_token.clone()),
-            Some(t.refresh_token.clone()),
+            Some(encrypt_secret(&t.access_token)),
+            Some(encrypt_secret(&t.refresh_token)),
             Some(t.expires_at.timestamp()),
             Some(t.expires_in.as_secs() as i64),
             Some(t.organization.uuid.clone()),
@@ -90,8 +241,8 @@ pub async fn persist_cookie_upsert(c: &CookieStatus) -> Result<(), ClewdrError>
     } else {
         (None, None, None, None, None)
     };
-    let am = ActiveModelCookie {
-        cookie: Set(c.cookie.to_string()),
+    ActiveModelCookie {
+        cookie: Set(encrypt_secret(&c.cookie.to_string())),
         reset_time: Set(c.reset_time),
         token_access: Set(acc),
         token_refresh: Set(rtk),
@@ -116,9 +267,36 @@ pub async fn persist_cookie_upsert(c: &CookieStatus) -> Result<(), ClewdrError>
         lifetime_usage: Set(Some(
             serde_json::to_string(&c.lifetime_usage).unwrap_or_else(|_| "{}".to_string()),
         )),
-    };
-    let start = std::time::Instant::now();
-    let res = EntityCookie::insert(am)
+        disabled: Set(Some(c.disabled)),
+        organizations: Set(Some(
+            serde_json::to_string(&c.organizations).unwrap_or_else(|_| "[]".to_string()),
+        )),
+        pinned_org: Set(c.pinned_org.to_owned()),
+        cache_read_input_tokens: Set(Some(c.lifetime_usage.cache_read_input_tokens as i64)),
+        cache_creation_input_tokens: Set(Some(c.lifetime_usage.cache_creation_input_tokens as i64)),
+        tags: Set(Some(
+            serde_json::to_string(&c.tags).unwrap_or_else(|_| "[]".to_string()),
+        )),
+        health_events: Set(Some(
+            serde_json::to_string(&c.health).unwrap_or_else(|_| "[]".to_string()),
+        )),
+        cleared_flags: Set(c
+            .cleared_flags
+            .as_ref()
+            .and_then(|f| serde_json::to_string(f).ok())),
+        count_403: Set(Some(c.count_403 as i64)),
+        count_429: Set(Some(c.count_429 as i64)),
+        tenant: Set(tenant.map(str::to_string)),
+    }
+}
+
+async fn upsert_cookie_on(
+    db: &impl ConnectionTrait,
+    c: &CookieStatus,
+    tenant: Option<&str>,
+) -> Result<(), DbErr> {
+    use sea_orm::sea_query::OnConflict;
+    EntityCookie::insert(cookie_active_model(c, tenant))
         .on_conflict(
             OnConflict::column(ColumnCookie::Cookie)
                 .update_columns([
@@ -134,11 +312,91 @@ pub async fn persist_cookie_upsert(c: &CookieStatus) -> Result<(), ClewdrError>
                     ColumnCookie::WeeklyUsage,
                     ColumnCookie::WeeklyOpusUsage,
                     ColumnCookie::LifetimeUsage,
+                    ColumnCookie::Disabled,
+                    ColumnCookie::Organizations,
+                    ColumnCookie::PinnedOrg,
+                    ColumnCookie::CacheReadInputTokens,
+                    ColumnCookie::CacheCreationInputTokens,
+                    ColumnCookie::Tags,
+                    ColumnCookie::HealthEvents,
+                    ColumnCookie::ClearedFlags,
+                    ColumnCookie::Count403,
+                    ColumnCookie::Count429,
+                    ColumnCookie::Tenant,
                 ])
                 .to_owned(),
         )
-        .exec(&db)
-        .await;
+        .exec(db)
+        .await
+        .map(|_| ())
+}
+
+async fn upsert_key_on(
+    db: &impl ConnectionTrait,
+    k: &KeyStatus,
+    tenant: Option<&str>,
+    invalid: bool,
+) -> Result<(), DbErr> {
+    use sea_orm::sea_query::OnConflict;
+    EntityKeyRow::insert(key_active_model(k, tenant, invalid))
+        .on_conflict(
+            OnConflict::column(ColumnKeyRow::Key)
+                .update_columns([
+                    ColumnKeyRow::Count403,
+                    ColumnKeyRow::CountRequests,
+                    ColumnKeyRow::CountTokens,
+                    ColumnKeyRow::Count429,
+                    ColumnKeyRow::QuotaDay,
+                    ColumnKeyRow::InputTokens,
+                    ColumnKeyRow::OutputTokens,
+                    ColumnKeyRow::Disabled,
+                    ColumnKeyRow::TotalRequests,
+                    ColumnKeyRow::Total429,
+                    ColumnKeyRow::Tenant,
+                    ColumnKeyRow::Invalid,
+                    ColumnKeyRow::ConsecutiveFailures,
+                    ColumnKeyRow::QuarantinedUntil,
+                    ColumnKeyRow::FailedProbes,
+                ])
+                .to_owned(),
+        )
+        .exec(db)
+        .await
+        .map(|_| ())
+}
+
+async fn upsert_wasted_on(
+    db: &impl ConnectionTrait,
+    u: &UselessCookie,
+    tenant: Option<&str>,
+) -> Result<(), DbErr> {
+    use sea_orm::sea_query::OnConflict;
+    let am = ActiveModelWasted {
+        cookie: Set(encrypt_secret(&u.cookie.to_string())),
+        reason: Set(serde_json::to_string(&u.reason).unwrap_or_else(|_| "\"Unknown\"".to_string())),
+        tenant: Set(tenant.map(str::to_string)),
+    };
+    EntityWasted::insert(am)
+        .on_conflict(
+            OnConflict::column(ColumnWasted::Cookie)
+                .update_columns([ColumnWasted::Reason, ColumnWasted::Tenant])
+                .to_owned(),
+        )
+        .exec(db)
+        .await
+        .map(|_| ())
+}
+
+pub async fn persist_cookie_upsert(
+    c: &CookieStatus,
+    tenant: Option<&str>,
+) -> Result<(), ClewdrError> {
+    if !crate::config::CLEWDR_CONFIG.load().is_db_mode() {
+        return Ok(());
+    }
+    let db = ensure_conn().await?;
+    let start = std::time::Instant::now();
+    let res = upsert_cookie_on(&db, c, tenant).await;
     match res {
         Ok(_) => {
             record_duration(start);
@@ -162,7 +420,7 @@ pub async fn delete_cookie_row(c: &CookieStatus) -> Result<(), ClewdrError> {
     }
     let db = ensure_conn().await?;
     let start = std::time::Instant::now();
-    let res = EntityCookie::delete_by_id(c.cookie.to_string())
+    let res = EntityCookie::delete_by_id(encrypt_secret(&c.cookie.to_string()))
         .exec(&db)
         .await;
     match res {
@@ -179,32 +437,23 @@ pub async fn delete_cookie_row(c: &CookieStatus) -> Result<(), ClewdrError> {
             });
         }
     }
-    EntityWasted::delete_by_id(c.cookie.to_string())
+    EntityWasted::delete_by_id(encrypt_secret(&c.cookie.to_string()))
         .exec(&db)
         .await
         .ok();
     Ok(())
 }
 
-pub async fn persist_wasted_upsert(u: &UselessCookie) -> Result<(), ClewdrError> {
+pub async fn persist_wasted_upsert(
+    u: &UselessCookie,
+    tenant: Option<&str>,
+) -> Result<(), ClewdrError> {
     if !crate::config::CLEWDR_CONFIG.load().is_db_mode() {
         return Ok(());
     }
     let db = ensure_conn().await?;
-    use sea_orm::sea_query::OnConflict;
-    let am = ActiveModelWasted {
-        cookie: Set(u.cookie.to_string()),
-        reason: Set(serde_json::to_string(&u.reason).unwrap_or_else(|_| "\"Unknown\"".to_string())),
-    };
     let start = std::time::Instant::now();
-    let res = EntityWasted::insert(am)
-        .on_conflict(
-            OnConflict::column(ColumnWasted::Cookie)
-                .update_columns([ColumnWasted::Reason])
-                .to_owned(),
-        )
-        .exec(&db)
-        .await;
+    let res = upsert_wasted_on(&db, u, tenant).await;
     match res {
         Ok(_) => {
             record_duration(start);
@@ -222,19 +471,21 @@ pub async fn persist_wasted_upsert(u: &UselessCookie) -> Result<(), ClewdrError>
     Ok(())
 }
 
-pub async fn persist_keys(keys: &[KeyStatus]) -> Result<(), ClewdrError> {
+pub async fn persist_keys(keys: &[KeyStatus], tenant: Option<&str>) -> Result<(), ClewdrError> {
     if !crate::config::CLEWDR_CONFIG.load().is_db_mode() {
         return Ok(());
     }
     let db = ensure_conn().await?;
-    EntityKeyRow::delete_many().exec(&db).await.ok(); // bulk reset (non-critical errors ignored)
+    // bulk reset of the valid keys (non-critical errors ignored)
+    EntityKeyRow::delete_many()
+        .filter(owned_by(ColumnKeyRow::Tenant, tenant))
+        .filter(key_state(false))
+        .exec(&db)
+        .await
+        .ok();
     for k in keys {
-        let am = ActiveModelKeyRow {
-            key: Set(k.key.to_string()),
-            count_403: Set(k.count_403 as i64),
-        };
         let start = std::time::Instant::now();
-        match EntityKeyRow::insert(am).exec(&db).await {
+        match upsert_key_on(&db, k, tenant, false).await {
             Ok(_) => {
                 record_duration(start);
                 mark_write_ok();
@@ -249,25 +500,13 @@ pub async fn persist_keys(keys: &[KeyStatus]) -> Result<(), ClewdrError> {
     Ok(())
 }
 
-pub async fn persist_key_upsert(k: &KeyStatus) -> Result<(), ClewdrError> {
+pub async fn persist_key_upsert(k: &KeyStatus, tenant: Option<&str>) -> Result<(), ClewdrError> {
     if !crate::config::CLEWDR_CONFIG.load().is_db_mode() {
         return Ok(());
     }
     let db = ensure_conn().await?;
-    use sea_orm::sea_query::OnConflict;
-    let am = ActiveModelKeyRow {
-        key: Set(k.key.to_string()),
-        count_403: Set(k.count_403 as i64),
-    };
     let start = std::time::Instant::now();
-    let res = EntityKeyRow::insert(am)
-        .on_conflict(
-            OnConflict::column(ColumnKeyRow::Key)
-                .update_columns([ColumnKeyRow::Count403])
-                .to_owned(),
-        )
-        .exec(&db)
-        .await;
+    let res = upsert_key_on(&db, k, tenant, false).await;
     match res {
         Ok(_) => {
             record_duration(start);
@@ -291,7 +530,7 @@ pub async fn delete_key_row(k: &KeyStatus) -> Result<(), ClewdrError> {
     }
     let db = ensure_conn().await?;
     let start = std::time::Instant::now();
-    let res = EntityKeyRow::delete_by_id(k.key.to_string())
+    let res = EntityKeyRow::delete_by_id(encrypt_secret(&k.key))
         .exec(&db)
         .await;
     match res {
@@ -313,7 +552,7 @@ pub async fn delete_key_row(k: &KeyStatus) -> Result<(), ClewdrError> {
 
 pub async fn import_config_from_file() -> Result<serde_json::Value, ClewdrError> {
     let text = tokio::fs::read_to_string(crate::config::CONFIG_PATH.as_path()).await?;
-    let cfg: ClewdrConfig = toml::from_str(&text)?;
+    let cfg = open_config(&text)?;
     persist_config(&cfg).await?;
     let mut valid = vec![];
     let mut exhausted = vec![];
@@ -325,144 +564,431 @@ pub async fn import_config_from_file() -> Result<serde_json::Value, ClewdrError>
         }
     }
     let invalid: Vec<UselessCookie> = cfg.wasted_cookie.iter().cloned().collect();
-    persist_cookies(&valid, &exhausted, &invalid).await?;
+    persist_cookies(&valid, &exhausted, &invalid, None).await?;
     let keys: Vec<KeyStatus> = cfg.gemini_keys.iter().cloned().collect();
-    persist_keys(&keys).await?;
+    persist_keys(&keys, None).await?;
     Ok(json!({"status":"ok"}))
 }
 
 pub async fn export_current_config() -> Result<serde_json::Value, ClewdrError> {
-    // Reconstruct latest runtime config from DB rows
+    let cfg = snapshot_config().await?;
+    if crate::config::CLEWDR_CONFIG.load().no_fs {
+        return Err(ClewdrError::Whatever {
+            message: "File export disabled when no_fs is enabled".into(),
+            source: None,
+        });
+    }
+    let toml = toml::to_string_pretty(&cfg)?;
+    Ok(json!({"toml": toml}))
+}
+
+/// Reconstructs the latest runtime config, with its cookies and keys, from DB rows
+pub async fn snapshot_config() -> Result<ClewdrConfig, ClewdrError> {
     let db = ensure_conn().await?;
     // base config from DB row or current
     let mut cfg = if let Ok(Some(row)) = EntityConfig::find_by_id("main").one(&db).await {
-        toml::from_str::<ClewdrConfig>(&row.data)
+        open_config(&row.data)
             .unwrap_or_else(|_| crate::config::CLEWDR_CONFIG.load().as_ref().clone())
     } else {
         crate::config::CLEWDR_CONFIG.load().as_ref().clone()
     };
     // cookies
-    let cookie_rows = EntityCookie::find().all(&db).await.unwrap_or_default();
+    let cookie_rows = EntityCookie::find()
+        .filter(ColumnCookie::Tenant.is_null())
+        .all(&db)
+        .await
+        .unwrap_or_default();
     cfg.cookie_array.clear();
     for r in cookie_rows {
-        let mut c = CookieStatus::new(&r.cookie, r.reset_time).unwrap_or_default();
-        if let Some(acc) = r.token_access {
-            let expires_at = r
-                .token_expires_at
-                .and_then(|s| chrono::DateTime::from_timestamp(s, 0))
-                .unwrap_or_else(chrono::Utc::now);
-            let expires_in =
-                std::time::Duration::from_secs(r.token_expires_in.unwrap_or_default() as u64);
-            c.token = Some(crate::config::TokenInfo {
-                access_token: acc,
-                refresh_token: r.token_refresh.unwrap_or_default(),
-                organization: crate::config::Organization {
-                    uuid: r.token_org_uuid.unwrap_or_default(),
-                },
-                expires_at,
-                expires_in,
-            });
-        }
-        c.supports_claude_1m = r.supports_claude_1m;
-        c.count_tokens_allowed = r.count_tokens_allowed;
-        if let Some(s) = r.session_usage.as_ref() {
-            if let Ok(v) = serde_json::from_str::<UsageBreakdown>(s) {
-                c.session_usage = v;
-            }
-        }
-        if let Some(s) = r.weekly_usage.as_ref() {
-            if let Ok(v) = serde_json::from_str::<UsageBreakdown>(s) {
-                c.weekly_usage = v;
-            }
-        }
-        if let Some(s) = r.weekly_opus_usage.as_ref() {
-            if let Ok(v) = serde_json::from_str::<UsageBreakdown>(s) {
-                c.weekly_opus_usage = v;
-            }
-        }
-        if let Some(s) = r.lifetime_usage.as_ref() {
-            if let Ok(v) = serde_json::from_str::<UsageBreakdown>(s) {
-                c.lifetime_usage = v;
-            }
-        }
-        cfg.cookie_array.insert(c);
+        cfg.cookie_array.insert(cookie_from_row(r)?);
     }
     // wasted
-    let wasted_rows = EntityWasted::find().all(&db).await.unwrap_or_default();
+    let wasted_rows = EntityWasted::find()
+        .filter(ColumnWasted::Tenant.is_null())
+        .all(&db)
+        .await
+        .unwrap_or_default();
     cfg.wasted_cookie.clear();
     for r in wasted_rows {
         if let Ok(reason) = serde_json::from_str(&r.reason)
-            && let Ok(cc) = <crate::config::ClewdrCookie as std::str::FromStr>::from_str(&r.cookie)
+            && let Ok(cc) = <crate::config::ClewdrCookie as std::str::FromStr>::from_str(
+                &decrypt_secret(&r.cookie)?,
+            )
         {
             cfg.wasted_cookie.insert(UselessCookie::new(cc, reason));
         }
     }
     // keys
-    let key_rows = EntityKeyRow::find().all(&db).await.unwrap_or_default();
+    let key_rows = EntityKeyRow::find()
+        .filter(ColumnKeyRow::Tenant.is_null())
+        .all(&db)
+        .await
+        .unwrap_or_default();
     cfg.gemini_keys.clear();
     for r in key_rows {
-        cfg.gemini_keys.insert(KeyStatus {
-            key: r.key.into(),
-            count_403: r.count_403 as u32,
-        });
-    }
-
-    if crate::config::CLEWDR_CONFIG.load().no_fs {
-        return Err(ClewdrError::Whatever {
-            message: "File export disabled when no_fs is enabled".into(),
-            source: None,
-        });
+        if r.invalid == Some(true) {
+            cfg.invalid_keys.insert(key_from_row(r)?);
+        } else {
+            cfg.gemini_keys.insert(key_from_row(r)?);
+        }
     }
-    let toml = toml::to_string_pretty(&cfg)?;
-    Ok(json!({"toml": toml}))
+    crate::persistence::fill_tenant_pools(&mut cfg).await?;
+    Ok(cfg)
 }
 
 pub async fn persist_cookies(
     valid: &[CookieStatus],
     exhausted: &[CookieStatus],
     invalid: &[UselessCookie],
+    tenant: Option<&str>,
 ) -> Result<(), ClewdrError> {
-    if !crate::config::CLEWDR_CONFIG.load().is_db_mode() {
+    persist_batch(&StorageBatch::snapshot(valid, exhausted, invalid), tenant).await
+}
+
+async fn apply_batch(
+    txn: &DatabaseTransaction,
+    batch: &StorageBatch,
+    tenant: Option<&str>,
+) -> Result<(), DbErr> {
+    if batch.replace {
+        EntityCookie::delete_many()
+            .filter(owned_by(ColumnCookie::Tenant, tenant))
+            .exec(txn)
+            .await?;
+        EntityWasted::delete_many()
+            .filter(owned_by(ColumnWasted::Tenant, tenant))
+            .exec(txn)
+            .await?;
+    }
+    for c in &batch.cookies {
+        upsert_cookie_on(txn, c, tenant).await?;
+    }
+    for u in &batch.wasted {
+        upsert_wasted_on(txn, u, tenant).await?;
+        EntityCookie::delete_by_id(encrypt_secret(&u.cookie.to_string()))
+            .exec(txn)
+            .await?;
+    }
+    for c in &batch.deleted {
+        EntityCookie::delete_by_id(encrypt_secret(&c.cookie.to_string()))
+            .exec(txn)
+            .await?;
+        EntityWasted::delete_by_id(encrypt_secret(&c.cookie.to_string()))
+            .exec(txn)
+            .await?;
+    }
+    if batch.replace_keys {
+        EntityKeyRow::delete_many()
+            .filter(owned_by(ColumnKeyRow::Tenant, tenant))
+            .exec(txn)
+            .await?;
+    }
+    for k in &batch.keys {
+        upsert_key_on(txn, k, tenant, false).await?;
+    }
+    for k in &batch.invalid_keys {
+        upsert_key_on(txn, k, tenant, true).await?;
+    }
+    Ok(())
+}
+
+pub async fn persist_batch(batch: &StorageBatch, tenant: Option<&str>) -> Result<(), ClewdrError> {
+    if !crate::config::CLEWDR_CONFIG.load().is_db_mode() || batch.is_empty() {
         return Ok(());
     }
     let db = ensure_conn().await?;
-    EntityCookie::delete_many().exec(&db).await.ok();
-    EntityWasted::delete_many().exec(&db).await.ok();
-    for c in valid.iter().chain(exhausted.iter()) {
-        let _ = persist_cookie_upsert(c).await;
+    let start = std::time::Instant::now();
+    let res = match db.begin().await {
+        Ok(txn) => match apply_batch(&txn, batch, tenant).await {
+            Ok(()) => txn.commit().await,
+            // dropping the transaction rolls it back
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    match res {
+        Ok(_) => {
+            record_duration(start);
+            mark_write_ok();
+        }
+        Err(e) => {
+            record_error_msg(&e);
+            mark_write_err();
+            return Err(ClewdrError::Whatever {
+                message: "persist_batch".into(),
+                source: Some(Box::new(e)),
+            });
+        }
     }
-    for u in invalid {
-        let _ = persist_wasted_upsert(u).await;
+    Ok(())
+}
+
+/// Takes or renews the lease on a cookie, fails while another holder's lease is unexpired
+pub async fn acquire_lease(cookie: &str, holder: &str, ttl_secs: u64) -> Result<bool, ClewdrError> {
+    let db = ensure_conn().await?;
+    let now = chrono::Utc::now().timestamp();
+    let expires_at = now.saturating_add(clamp_u64_to_i64(ttl_secs));
+    let am = ActiveModelLease {
+        cookie: Set(encrypt_secret(cookie)),
+        holder: Set(holder.to_string()),
+        expires_at: Set(expires_at),
+    };
+    // a conflicting insert means someone holds or held the lease
+    if EntityLease::insert(am).exec(&db).await.is_ok() {
+        return Ok(true);
     }
+    let res = EntityLease::update_many()
+        .col_expr(ColumnLease::Holder, Expr::value(holder))
+        .col_expr(ColumnLease::ExpiresAt, Expr::value(expires_at))
+        .filter(ColumnLease::Cookie.eq(encrypt_secret(cookie)))
+        .filter(
+            ColumnLease::Holder
+                .eq(holder)
+                .or(ColumnLease::ExpiresAt.lt(now)),
+        )
+        .exec(&db)
+        .await
+        .map_err(|e| ClewdrError::Whatever {
+            message: "acquire_lease".into(),
+            source: Some(Box::new(e)),
+        })?;
+    Ok(res.rows_affected > 0)
+}
+
+/// Drops a lease, only if `holder` still holds it
+pub async fn release_lease(cookie: &str, holder: &str) -> Result<(), ClewdrError> {
+    let db = ensure_conn().await?;
+    EntityLease::delete_many()
+        .filter(ColumnLease::Cookie.eq(encrypt_secret(cookie)))
+        .filter(ColumnLease::Holder.eq(holder))
+        .exec(&db)
+        .await
+        .map_err(|e| ClewdrError::Whatever {
+            message: "release_lease".into(),
+            source: Some(Box::new(e)),
+        })?;
     Ok(())
 }
 
-// Read helpers used by background sync
-pub async fn load_all_keys() -> Result<Vec<KeyStatus>, ClewdrError> {
+/// Stores or renews the cookie pinned to a prompt hash
+pub async fn upsert_affinity(a: &Affinity) -> Result<(), ClewdrError> {
     let db = ensure_conn().await?;
-    let rows = EntityKeyRow::find()
+    use sea_orm::sea_query::OnConflict;
+    let am = ActiveModelAffinity {
+        hash: Set(a.hash as i64),
+        cookie: Set(encrypt_secret(&a.cookie)),
+        expires_at: Set(a.expires_at),
+    };
+    EntityAffinity::insert(am)
+        .on_conflict(
+            OnConflict::column(ColumnAffinity::Hash)
+                .update_columns([ColumnAffinity::Cookie, ColumnAffinity::ExpiresAt])
+                .to_owned(),
+        )
+        .exec(&db)
+        .await
+        .map_err(|e| ClewdrError::Whatever {
+            message: "upsert_affinity".into(),
+            source: Some(Box::new(e)),
+        })?;
+    Ok(())
+}
+
+/// Deletes the pins expired by `now` and returns the others
+pub async fn load_affinity(now: i64) -> Result<Vec<Affinity>, ClewdrError> {
+    prune_affinity(now).await?;
+    let db = ensure_conn().await?;
+    let rows = EntityAffinity::find()
         .all(&db)
         .await
         .map_err(|e| ClewdrError::Whatever {
-            message: "load_keys".into(),
+            message: "load_affinity".into(),
+            source: Some(Box::new(e)),
+        })?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(Affinity {
+                hash: r.hash as u64,
+                cookie: decrypt_secret(&r.cookie)?,
+                expires_at: r.expires_at,
+            })
+        })
+        .collect()
+}
+
+pub async fn prune_affinity(now: i64) -> Result<u64, ClewdrError> {
+    let db = ensure_conn().await?;
+    let res = EntityAffinity::delete_many()
+        .filter(ColumnAffinity::ExpiresAt.lte(now))
+        .exec(&db)
+        .await
+        .map_err(|e| ClewdrError::Whatever {
+            message: "prune_affinity".into(),
+            source: Some(Box::new(e)),
+        })?;
+    Ok(res.rows_affected)
+}
+
+pub async fn upsert_batch_job(job: &BatchJob) -> Result<(), ClewdrError> {
+    let db = ensure_conn().await?;
+    use sea_orm::sea_query::OnConflict;
+    let am = ActiveModelBatchJob {
+        id: Set(job.id.to_owned()),
+        created_at: Set(job.created_at),
+        status: Set(json!(job.status).as_str().unwrap_or_default().to_string()),
+        job: Set(serde_json::to_string(job).unwrap_or_else(|_| "{}".to_string())),
+    };
+    EntityBatchJob::insert(am)
+        .on_conflict(
+            OnConflict::column(ColumnBatchJob::Id)
+                .update_columns([ColumnBatchJob::Status, ColumnBatchJob::Job])
+                .to_owned(),
+        )
+        .exec(&db)
+        .await
+        .map_err(|e| ClewdrError::Whatever {
+            message: "upsert_batch_job".into(),
+            source: Some(Box::new(e)),
+        })?;
+    Ok(())
+}
+
+pub async fn load_batch_job(id: &str) -> Result<Option<BatchJob>, ClewdrError> {
+    let db = ensure_conn().await?;
+    let row = EntityBatchJob::find_by_id(id.to_string())
+        .one(&db)
+        .await
+        .map_err(|e| ClewdrError::Whatever {
+            message: "load_batch_job".into(),
+            source: Some(Box::new(e)),
+        })?;
+    Ok(row.and_then(|r| serde_json::from_str(&r.job).ok()))
+}
+
+pub async fn load_unfinished_batch_jobs() -> Result<Vec<BatchJob>, ClewdrError> {
+    let db = ensure_conn().await?;
+    let status = json!(BatchStatus::InProgress);
+    let rows = EntityBatchJob::find()
+        .filter(ColumnBatchJob::Status.eq(status.as_str().unwrap_or_default()))
+        .all(&db)
+        .await
+        .map_err(|e| ClewdrError::Whatever {
+            message: "load_unfinished_batch_jobs".into(),
             source: Some(Box::new(e)),
         })?;
     Ok(rows
         .into_iter()
-        .map(|r| KeyStatus {
-            key: r.key.into(),
-            count_403: r.count_403 as u32,
-        })
+        .filter_map(|r| serde_json::from_str(&r.job).ok())
         .collect())
 }
 
-pub async fn load_all_cookies()
--> Result<(Vec<CookieStatus>, Vec<CookieStatus>, Vec<UselessCookie>), ClewdrError> {
+pub async fn insert_transcript(t: &Transcript) -> Result<(), ClewdrError> {
+    let db = ensure_conn().await?;
+    let am = ActiveModelTranscript {
+        id: NotSet,
+        created_at: Set(t.created_at),
+        provider: Set(t.provider.to_owned()),
+        model: Set(t.model.to_owned()),
+        prompt: Set(t.prompt.to_owned()),
+        completion: Set(t.completion.to_owned()),
+        input_tokens: Set(clamp_u64_to_i64(t.input_tokens)),
+        output_tokens: Set(clamp_u64_to_i64(t.output_tokens)),
+    };
+    EntityTranscript::insert(am)
+        .exec(&db)
+        .await
+        .map_err(|e| ClewdrError::Whatever {
+            message: "insert_transcript".into(),
+            source: Some(Box::new(e)),
+        })?;
+    Ok(())
+}
+
+/// Page of transcripts, newest first, and the number of all matching transcripts
+pub async fn list_transcripts(
+    offset: u64,
+    limit: u64,
+    search: Option<&str>,
+) -> Result<(Vec<Transcript>, u64), ClewdrError> {
+    let db = ensure_conn().await?;
+    let mut query = EntityTranscript::find();
+    if let Some(search) = search {
+        query = query.filter(
+            Condition::any()
+                .add(ColumnTranscript::Model.contains(search))
+                .add(ColumnTranscript::Prompt.contains(search))
+                .add(ColumnTranscript::Completion.contains(search)),
+        );
+    }
+    let map_err = |e| ClewdrError::Whatever {
+        message: "list_transcripts".into(),
+        source: Some(Box::new(e)),
+    };
+    let total = query.to_owned().count(&db).await.map_err(map_err)?;
+    let rows = query
+        .order_by_desc(ColumnTranscript::Id)
+        .offset(offset)
+        .limit(limit)
+        .all(&db)
+        .await
+        .map_err(map_err)?;
+    let transcripts = rows
+        .into_iter()
+        .map(|r| Transcript {
+            id: r.id,
+            created_at: r.created_at,
+            provider: r.provider,
+            model: r.model,
+            prompt: r.prompt,
+            completion: r.completion,
+            input_tokens: r.input_tokens.max(0) as u64,
+            output_tokens: r.output_tokens.max(0) as u64,
+        })
+        .collect();
+    Ok((transcripts, total))
+}
+
+pub async fn prune_transcripts(before: i64) -> Result<u64, ClewdrError> {
+    let db = ensure_conn().await?;
+    let res = EntityTranscript::delete_many()
+        .filter(ColumnTranscript::CreatedAt.lt(before))
+        .exec(&db)
+        .await
+        .map_err(|e| ClewdrError::Whatever {
+            message: "prune_transcripts".into(),
+            source: Some(Box::new(e)),
+        })?;
+    Ok(res.rows_affected)
+}
+
+// Read helpers used by background sync
+/// Stored valid keys, or dropped ones for `invalid`
+pub async fn load_all_keys(
+    tenant: Option<&str>,
+    invalid: bool,
+) -> Result<Vec<KeyStatus>, ClewdrError> {
+    let db = ensure_conn().await?;
+    let rows = EntityKeyRow::find()
+        .filter(owned_by(ColumnKeyRow::Tenant, tenant))
+        .filter(key_state(invalid))
+        .all(&db)
+        .await
+        .map_err(|e| ClewdrError::Whatever {
+            message: "load_keys".into(),
+            source: Some(Box::new(e)),
+        })?;
+    rows.into_iter().map(key_from_row).collect()
+}
+
+pub async fn load_all_cookies(
+    tenant: Option<&str>,
+) -> Result<(Vec<CookieStatus>, Vec<CookieStatus>, Vec<UselessCookie>), ClewdrError> {
     let db = ensure_conn().await?;
     let mut valid = Vec::new();
     let mut exhausted = Vec::new();
     let mut invalid = Vec::new();
     let rows = EntityCookie::find()
+        .filter(owned_by(ColumnCookie::Tenant, tenant))
         .all(&db)
         .await
         .map_err(|e| ClewdrError::Whatever {
@@ -470,47 +996,7 @@ pub async fn load_all_cookies()
             source: Some(Box::new(e)),
         })?;
     for r in rows {
-        let mut c = CookieStatus::new(&r.cookie, r.reset_time).unwrap_or_default();
-        if let Some(acc) = r.token_access {
-            let expires_at = r
-                .token_expires_at
-                .and_then(|s| chrono::DateTime::from_timestamp(s, 0))
-                .unwrap_or_else(chrono::Utc::now);
-            let expires_in =
-                std::time::Duration::from_secs(r.token_expires_in.unwrap_or_default() as u64);
-            c.token = Some(crate::config::TokenInfo {
-                access_token: acc,
-                refresh_token: r.token_refresh.unwrap_or_default(),
-                organization: crate::config::Organization {
-                    uuid: r.token_org_uuid.unwrap_or_default(),
-                },
-                expires_at,
-                expires_in,
-            });
-        }
-        c.supports_claude_1m = r.supports_claude_1m;
-        c.count_tokens_allowed = r.count_tokens_allowed;
-        // Legacy totals/windows removed; ignore DB columns if present
-        if let Some(s) = r.session_usage.as_ref() {
-            if let Ok(v) = serde_json::from_str::<UsageBreakdown>(s) {
-                c.session_usage = v;
-            }
-        }
-        if let Some(s) = r.weekly_usage.as_ref() {
-            if let Ok(v) = serde_json::from_str::<UsageBreakdown>(s) {
-                c.weekly_usage = v;
-            }
-        }
-        if let Some(s) = r.weekly_opus_usage.as_ref() {
-            if let Ok(v) = serde_json::from_str::<UsageBreakdown>(s) {
-                c.weekly_opus_usage = v;
-            }
-        }
-        if let Some(s) = r.lifetime_usage.as_ref() {
-            if let Ok(v) = serde_json::from_str::<UsageBreakdown>(s) {
-                c.lifetime_usage = v;
-            }
-        }
+        let c = cookie_from_row(r)?;
         if c.reset_time.is_some() {
             exhausted.push(c);
         } else {
@@ -518,6 +1004,7 @@ pub async fn load_all_cookies()
         }
     }
     let wasted = EntityWasted::find()
+        .filter(owned_by(ColumnWasted::Tenant, tenant))
         .all(&db)
         .await
         .map_err(|e| ClewdrError::Whatever {
@@ -526,7 +1013,9 @@ pub async fn load_all_cookies()
         })?;
     for r in wasted {
         if let Ok(reason) = serde_json::from_str(&r.reason)
-            && let Ok(cc) = <crate::config::ClewdrCookie as std::str::FromStr>::from_str(&r.cookie)
+            && let Ok(cc) = <crate::config::ClewdrCookie as std::str::FromStr>::from_str(
+                &decrypt_secret(&r.cookie)?,
+            )
         {
             invalid.push(UselessCookie::new(cc, reason));
         }
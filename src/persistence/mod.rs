@@ -1,198 +1,514 @@
-use std::sync::LazyLock;
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
 
-use serde_json::json;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::broadcast;
 
 use crate::{
-    config::{ClewdrConfig, CookieStatus, KeyStatus, UselessCookie},
+    config::{
+        CLEWDR_CONFIG, ClewdrConfig, CookieStatus, KeyStatus, PersistenceMode, UselessCookie,
+    },
     error::ClewdrError,
 };
 
+/// Cookie and key writes applied together, in a single transaction where the backend
+/// supports it
+#[derive(Debug, Clone, Default)]
+pub struct StorageBatch {
+    /// Drop every stored cookie and wasted cookie first, making the batch a full pool snapshot
+    pub replace: bool,
+    /// Cookies to insert or update
+    pub cookies: Vec<CookieStatus>,
+    /// Cookies to store as wasted, their pool rows are removed
+    pub wasted: Vec<UselessCookie>,
+    /// Cookies to remove from both the pool and the wasted list
+    pub deleted: Vec<CookieStatus>,
+    /// Drop every stored key, valid or dropped, first
+    pub replace_keys: bool,
+    /// Keys to insert or update as valid
+    pub keys: Vec<KeyStatus>,
+    /// Keys to store as dropped, they leave the valid pool
+    pub invalid_keys: Vec<KeyStatus>,
+}
+
+impl StorageBatch {
+    /// A batch replacing all stored cookies with the given pool
+    pub fn snapshot(
+        valid: &[CookieStatus],
+        exhausted: &[CookieStatus],
+        invalid: &[UselessCookie],
+    ) -> Self {
+        Self {
+            replace: true,
+            cookies: valid.iter().chain(exhausted).cloned().collect(),
+            wasted: invalid.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// A batch writing the given pool over the stored one, leaving other stored cookies alone
+    pub fn upsert(
+        valid: &[CookieStatus],
+        exhausted: &[CookieStatus],
+        invalid: &[UselessCookie],
+    ) -> Self {
+        Self {
+            replace: false,
+            ..Self::snapshot(valid, exhausted, invalid)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.has_cookies() && !self.has_keys()
+    }
+
+    /// Whether the batch writes any cookie
+    pub fn has_cookies(&self) -> bool {
+        self.replace
+            || !self.cookies.is_empty()
+            || !self.wasted.is_empty()
+            || !self.deleted.is_empty()
+    }
+
+    /// Whether the batch writes any key
+    pub fn has_keys(&self) -> bool {
+        self.replace_keys || !self.keys.is_empty() || !self.invalid_keys.is_empty()
+    }
+}
+
+/// Prompt and completion of one upstream request, kept for debugging
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Transcript {
+    /// Row id, assigned when stored
+    pub id: i64,
+    /// Unix timestamp of the request
+    pub created_at: i64,
+    pub provider: String,
+    pub model: String,
+    /// System prompt and messages as JSON, secrets redacted
+    pub prompt: String,
+    /// Generated text, secrets redacted
+    pub completion: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// State of a batch job or of one of its requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    Pending,
+    InProgress,
+    Completed,
+    Failed,
+}
+
+/// Outcome of one request of a batch job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItem {
+    pub status: BatchStatus,
+    /// HTTP status the request was answered with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_code: Option<u16>,
+    /// Response body, a string when it is not JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+}
+
+/// Chat completion requests run in the background, submitted through `POST /v1/batch`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchJob {
+    pub id: String,
+    /// Hash of the tenant and API key that submitted the job, only they can read it
+    #[serde(default)]
+    pub owner: String,
+    /// Unix timestamp of the submission
+    pub created_at: i64,
+    /// Unix timestamp the last request finished at
+    #[serde(default)]
+    pub completed_at: Option<i64>,
+    /// Unix timestamp the running instance last stored the progress at
+    #[serde(default)]
+    pub updated_at: Option<i64>,
+    pub status: BatchStatus,
+    /// Outcome of each request, in submission order
+    pub items: Vec<BatchItem>,
+}
+
+/// Cookie a Claude Code prompt hash sticks to, so prompt caching survives restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Affinity {
+    pub hash: u64,
+    /// Cookie as `ClewdrCookie` displays it
+    pub cookie: String,
+    /// Unix timestamp after which the pin is dropped
+    pub expires_at: i64,
+}
+
+/// Change reported by another instance sharing the same storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageEvent {
+    Config,
+    Cookies,
+    Keys,
+}
+
 /// Storage abstraction for Clewdr persistent state.
 /// Implementations may back onto a database or the filesystem.
+#[async_trait]
 pub trait StorageLayer: Send + Sync + 'static {
     fn is_enabled(&self) -> bool;
-    fn spawn_bootstrap(
-        &self,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>>;
-    fn persist_config(
-        &self,
-        cfg: &ClewdrConfig,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>>;
-    fn persist_cookies(
+    async fn spawn_bootstrap(&self) -> Result<(), ClewdrError>;
+    async fn persist_config(&self, cfg: &ClewdrConfig) -> Result<(), ClewdrError>;
+    /// Unix timestamp of the last write of the stored config, `None` if unknown
+    async fn config_updated_at(&self) -> Result<Option<i64>, ClewdrError> {
+        Ok(None)
+    }
+    async fn persist_cookies(
         &self,
         valid: &[CookieStatus],
         exhausted: &[CookieStatus],
         invalid: &[UselessCookie],
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>>;
-    fn persist_keys(
-        &self,
-        keys: &[KeyStatus],
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>>;
-    fn persist_cookie_upsert(
-        &self,
-        c: &CookieStatus,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>>;
-    fn delete_cookie_row(
-        &self,
-        c: &CookieStatus,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>>;
-    fn persist_wasted_upsert(
-        &self,
-        u: &UselessCookie,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>>;
-    fn persist_key_upsert(
-        &self,
-        k: &KeyStatus,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>>;
-    fn delete_key_row(
-        &self,
-        k: &KeyStatus,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>>;
-    fn import_from_file(
+    ) -> Result<(), ClewdrError>;
+    /// Applies all writes of the batch atomically, nothing is written if any of them fails
+    async fn persist_batch(&self, batch: &StorageBatch) -> Result<(), ClewdrError>;
+    async fn persist_keys(&self, keys: &[KeyStatus]) -> Result<(), ClewdrError>;
+    async fn persist_cookie_upsert(&self, c: &CookieStatus) -> Result<(), ClewdrError>;
+    async fn delete_cookie_row(&self, c: &CookieStatus) -> Result<(), ClewdrError>;
+    async fn persist_wasted_upsert(&self, u: &UselessCookie) -> Result<(), ClewdrError>;
+    async fn persist_key_upsert(&self, k: &KeyStatus) -> Result<(), ClewdrError>;
+    async fn delete_key_row(&self, k: &KeyStatus) -> Result<(), ClewdrError>;
+    async fn import_from_file(&self) -> Result<serde_json::Value, ClewdrError>;
+    async fn export_current_config(&self) -> Result<serde_json::Value, ClewdrError>;
+    /// Current config with the stored cookies and keys, what a backup holds
+    async fn snapshot_config(&self) -> Result<ClewdrConfig, ClewdrError> {
+        Ok(CLEWDR_CONFIG.load().as_ref().clone())
+    }
+    async fn status(&self) -> Result<serde_json::Value, ClewdrError>;
+    /// Stored valid keys
+    async fn load_keys(&self) -> Result<Vec<KeyStatus>, ClewdrError>;
+    /// Stored dropped keys, backends without key storage keep them in the config
+    async fn load_invalid_keys(&self) -> Result<Vec<KeyStatus>, ClewdrError> {
+        Ok(vec![])
+    }
+    /// Stored cookies split into valid, exhausted and invalid
+    async fn load_cookies(
         &self,
-    ) -> std::pin::Pin<
-        Box<dyn std::future::Future<Output = Result<serde_json::Value, ClewdrError>> + Send>,
-    >;
-    fn export_current_config(
+    ) -> Result<(Vec<CookieStatus>, Vec<CookieStatus>, Vec<UselessCookie>), ClewdrError>;
+    /// Rotates the cookie pool shared with other instances and returns the next cookie
+    ///
+    /// `None` means the pool is not shared and the caller rotates its own.
+    async fn next_cookie(&self) -> Result<Option<String>, ClewdrError> {
+        Ok(None)
+    }
+    /// Takes or renews the cluster lease on a cookie for `holder`
+    ///
+    /// # Returns
+    /// * `Ok(false)` - Another instance holds an unexpired lease on the cookie
+    async fn acquire_lease(
         &self,
-    ) -> std::pin::Pin<
-        Box<dyn std::future::Future<Output = Result<serde_json::Value, ClewdrError>> + Send>,
-    >;
-    fn status(
+        _cookie: &str,
+        _holder: &str,
+        _ttl_secs: u64,
+    ) -> Result<bool, ClewdrError> {
+        Ok(true)
+    }
+    /// Gives up the cluster lease on a cookie, if `holder` still holds it
+    async fn release_lease(&self, _cookie: &str, _holder: &str) -> Result<(), ClewdrError> {
+        Ok(())
+    }
+    /// Stores or renews a prompt hash pin, only the database and Redis keep it
+    ///
+    /// S3 drops pins, as every new conversation would rewrite a whole object
+    async fn persist_affinity(&self, _a: &Affinity) -> Result<(), ClewdrError> {
+        Ok(())
+    }
+    /// Prompt hash pins still valid at `now` (unix timestamp), expired ones are deleted
+    async fn load_affinity(&self, _now: i64) -> Result<Vec<Affinity>, ClewdrError> {
+        Ok(vec![])
+    }
+    /// Deletes the prompt hash pins expired by `now` (unix timestamp), returns how many
+    async fn prune_affinity(&self, _now: i64) -> Result<u64, ClewdrError> {
+        Ok(0)
+    }
+    /// Stores a transcript, backends without a database drop it
+    async fn persist_transcript(&self, _t: &Transcript) -> Result<(), ClewdrError> {
+        Ok(())
+    }
+    /// Stored transcripts, newest first, and the number of all matching ones
+    ///
+    /// # Arguments
+    /// * `search` - Substring of the model, prompt or completion to match
+    async fn list_transcripts(
         &self,
-    ) -> std::pin::Pin<
-        Box<dyn std::future::Future<Output = Result<serde_json::Value, ClewdrError>> + Send>,
-    >;
+        _offset: u64,
+        _limit: u64,
+        _search: Option<&str>,
+    ) -> Result<(Vec<Transcript>, u64), ClewdrError> {
+        Ok((vec![], 0))
+    }
+    /// Deletes transcripts created before `before` (unix timestamp), returns how many
+    async fn prune_transcripts(&self, _before: i64) -> Result<u64, ClewdrError> {
+        Ok(0)
+    }
+    /// Stores or updates a batch job, backends without a database drop it
+    async fn persist_batch_job(&self, _job: &BatchJob) -> Result<(), ClewdrError> {
+        Ok(())
+    }
+    /// A stored batch job by id
+    async fn load_batch_job(&self, _id: &str) -> Result<Option<BatchJob>, ClewdrError> {
+        Ok(None)
+    }
+    /// Stored batch jobs still in progress, on any instance
+    async fn load_unfinished_batch_jobs(&self) -> Result<Vec<BatchJob>, ClewdrError> {
+        Ok(vec![])
+    }
+    /// Changes made by other instances, `None` if the backend cannot report them
+    fn subscribe(&self) -> Option<broadcast::Receiver<StorageEvent>> {
+        None
+    }
 }
 
 struct FileLayer;
 
+#[async_trait]
 impl StorageLayer for FileLayer {
     fn is_enabled(&self) -> bool {
         false
     }
-    fn spawn_bootstrap(
-        &self,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        Box::pin(async { Ok(()) })
+    async fn spawn_bootstrap(&self) -> Result<(), ClewdrError> {
+        Ok(())
     }
-    fn persist_config(
-        &self,
-        _cfg: &ClewdrConfig,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        Box::pin(async { Ok(()) })
+    async fn persist_config(&self, _cfg: &ClewdrConfig) -> Result<(), ClewdrError> {
+        Ok(())
     }
-    fn persist_cookies(
+    async fn persist_cookies(
         &self,
         _valid: &[CookieStatus],
         _exhausted: &[CookieStatus],
         _invalid: &[UselessCookie],
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        Box::pin(async { Ok(()) })
+    ) -> Result<(), ClewdrError> {
+        Ok(())
     }
-    fn persist_keys(
-        &self,
-        _keys: &[KeyStatus],
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        Box::pin(async { Ok(()) })
+    async fn persist_batch(&self, _batch: &StorageBatch) -> Result<(), ClewdrError> {
+        Ok(())
     }
-    fn persist_cookie_upsert(
-        &self,
-        _c: &CookieStatus,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        Box::pin(async { Ok(()) })
+    async fn persist_keys(&self, _keys: &[KeyStatus]) -> Result<(), ClewdrError> {
+        Ok(())
     }
-    fn delete_cookie_row(
-        &self,
-        _c: &CookieStatus,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        Box::pin(async { Ok(()) })
+    async fn persist_cookie_upsert(&self, _c: &CookieStatus) -> Result<(), ClewdrError> {
+        Ok(())
     }
-    fn persist_wasted_upsert(
-        &self,
-        _u: &UselessCookie,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        Box::pin(async { Ok(()) })
+    async fn delete_cookie_row(&self, _c: &CookieStatus) -> Result<(), ClewdrError> {
+        Ok(())
     }
-    fn persist_key_upsert(
-        &self,
-        _k: &KeyStatus,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        Box::pin(async { Ok(()) })
+    async fn persist_wasted_upsert(&self, _u: &UselessCookie) -> Result<(), ClewdrError> {
+        Ok(())
     }
-    fn delete_key_row(
-        &self,
-        _k: &KeyStatus,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ClewdrError>> + Send>> {
-        Box::pin(async { Ok(()) })
+    async fn persist_key_upsert(&self, _k: &KeyStatus) -> Result<(), ClewdrError> {
+        Ok(())
     }
-    fn import_from_file(
-        &self,
-    ) -> std::pin::Pin<
-        Box<dyn std::future::Future<Output = Result<serde_json::Value, ClewdrError>> + Send>,
-    > {
-        Box::pin(async {
-            Err(ClewdrError::PathNotFound {
-                msg: "DB feature not enabled".into(),
-            })
+    async fn delete_key_row(&self, _k: &KeyStatus) -> Result<(), ClewdrError> {
+        Ok(())
+    }
+    async fn import_from_file(&self) -> Result<serde_json::Value, ClewdrError> {
+        Err(ClewdrError::PathNotFound {
+            msg: "DB feature not enabled".into(),
         })
     }
-    fn export_current_config(
-        &self,
-    ) -> std::pin::Pin<
-        Box<dyn std::future::Future<Output = Result<serde_json::Value, ClewdrError>> + Send>,
-    > {
-        Box::pin(async {
-            Err(ClewdrError::PathNotFound {
-                msg: "DB feature not enabled".into(),
-            })
+    async fn export_current_config(&self) -> Result<serde_json::Value, ClewdrError> {
+        Err(ClewdrError::PathNotFound {
+            msg: "DB feature not enabled".into(),
         })
     }
-    fn status(
+    async fn status(&self) -> Result<serde_json::Value, ClewdrError> {
+        // In file mode, there is no external DB to check. Treat as healthy.
+        Ok(json!({
+            "enabled": false,
+            "mode": "file",
+            "healthy": true,
+            "details": { "driver": "file" }
+        }))
+    }
+    async fn load_keys(&self) -> Result<Vec<KeyStatus>, ClewdrError> {
+        Ok(vec![])
+    }
+    async fn load_cookies(
         &self,
-    ) -> std::pin::Pin<
-        Box<dyn std::future::Future<Output = Result<serde_json::Value, ClewdrError>> + Send>,
-    > {
-        Box::pin(async {
-            // In file mode, there is no external DB to check. Treat as healthy.
-            Ok(json!({
-                "enabled": false,
-                "mode": "file",
-                "healthy": true,
-                "details": { "driver": "file" }
-            }))
-        })
+    ) -> Result<(Vec<CookieStatus>, Vec<CookieStatus>, Vec<UselessCookie>), ClewdrError> {
+        Ok((vec![], vec![], vec![]))
     }
 }
 
 // Feature-gated DB module providing actual implementation
 #[cfg(feature = "db")]
 pub mod db;
+// Feature-gated Redis module, shares state between instances
+#[cfg(feature = "db-redis")]
+pub mod redis_store;
+// S3 compatible bucket, only needs the HTTP client so it is always built
+pub mod s3_store;
 
-static STORAGE: LazyLock<std::sync::Arc<dyn StorageLayer>> = LazyLock::new(|| {
+/// Storage of the configured mode, for the shared pools or the pools of `tenant`
+fn layer(tenant: Option<String>) -> Box<dyn StorageLayer> {
     #[cfg(feature = "db")]
     {
         if crate::config::CLEWDR_CONFIG.load().is_db_mode() {
-            return std::sync::Arc::new(db::DbLayer);
+            return Box::new(db::DbLayer { tenant });
+        }
+    }
+    #[cfg(feature = "db-redis")]
+    {
+        if crate::config::CLEWDR_CONFIG.load().is_redis_mode() {
+            return Box::new(redis_store::RedisLayer { tenant });
         }
     }
-    std::sync::Arc::new(FileLayer)
-});
+    if crate::config::CLEWDR_CONFIG.load().is_s3_mode() {
+        return Box::new(s3_store::S3Layer { tenant });
+    }
+    Box::new(FileLayer)
+}
+
+static STORAGE: LazyLock<Box<dyn StorageLayer>> = LazyLock::new(|| layer(None));
 
 pub fn storage() -> &'static dyn StorageLayer {
     &**STORAGE
 }
 
-// Public helpers for read-only snapshots used by background sync
-#[cfg(feature = "db")]
-pub use db::repo::{load_all_cookies, load_all_keys, persist_key_upsert};
+/// Storage of the cookie and key pools of a tenant, apart from the shared pools
+///
+/// Only the pool methods are scoped to the tenant, the config, leases, transcripts and
+/// batch jobs are shared. In file mode the pools reach the config file with the tenant's
+/// entry instead.
+pub fn tenant_storage(name: &str) -> &'static dyn StorageLayer {
+    static TENANTS: LazyLock<Mutex<HashMap<String, &'static dyn StorageLayer>>> =
+        LazyLock::new(Default::default);
+    let mut tenants = TENANTS.lock().unwrap_or_else(|e| e.into_inner());
+    *tenants
+        .entry(name.to_string())
+        // tenants are started once, so each name leaks a single layer
+        .or_insert_with(|| Box::leak(layer(Some(name.to_string()))))
+}
 
-#[cfg(not(feature = "db"))]
+/// Storage of pools kept in the config only, such as the key pools of upstreams
+///
+/// Nothing is written to the cookie and key tables, the pools reach the storage with the
+/// config itself.
+pub fn detached() -> &'static dyn StorageLayer {
+    static DETACHED: FileLayer = FileLayer;
+    &DETACHED
+}
+
+/// Moves the pools a tenant's config entry still holds into the tenant's storage
+///
+/// Tenant pools are kept in the config in file mode and were in every mode before, they
+/// are imported once and left out of the config afterwards. Dropped keys are moved by the
+/// key actor when it starts.
+pub async fn import_tenant_pools(name: &str) -> Result<(), ClewdrError> {
+    let storage = tenant_storage(name);
+    if !storage.is_enabled() {
+        return Ok(());
+    }
+    let Some((cookies, wasted, keys)) = CLEWDR_CONFIG.load().tenant(name).map(|t| {
+        (
+            t.cookie_array.iter().cloned().collect::<Vec<_>>(),
+            t.wasted_cookie.iter().cloned().collect::<Vec<_>>(),
+            t.gemini_keys.iter().cloned().collect::<Vec<_>>(),
+        )
+    }) else {
+        return Ok(());
+    };
+    if cookies.is_empty() && wasted.is_empty() && keys.is_empty() {
+        return Ok(());
+    }
+    storage
+        .persist_batch(&StorageBatch::upsert(&cookies, &[], &wasted))
+        .await?;
+    for k in &keys {
+        storage.persist_key_upsert(k).await?;
+    }
+    CLEWDR_CONFIG.rcu(|config| {
+        let mut config = ClewdrConfig::clone(config);
+        if let Some(tenant) = config.tenant_mut(name) {
+            tenant.cookie_array.clear();
+            tenant.wasted_cookie.clear();
+            tenant.gemini_keys.clear();
+        }
+        config
+    });
+    CLEWDR_CONFIG.load().save().await?;
+    tracing::info!(
+        "Moved {} cookies and {} keys of tenant {} to storage",
+        cookies.len() + wasted.len(),
+        keys.len(),
+        name
+    );
+    Ok(())
+}
+
+/// Fills the tenant entries of a snapshot with the pools in their storage
+pub async fn fill_tenant_pools(cfg: &mut ClewdrConfig) -> Result<(), ClewdrError> {
+    for tenant in cfg.tenants.iter_mut() {
+        let storage = tenant_storage(&tenant.name);
+        if !storage.is_enabled() {
+            continue;
+        }
+        let (valid, exhausted, invalid) = storage.load_cookies().await?;
+        tenant.cookie_array = valid.into_iter().chain(exhausted).collect();
+        tenant.wasted_cookie = invalid.into_iter().collect();
+        tenant.gemini_keys = storage.load_keys().await?.into_iter().collect();
+        tenant
+            .invalid_keys
+            .extend(storage.load_invalid_keys().await?);
+    }
+    Ok(())
+}
+
+// Public helpers for read-only snapshots used by background sync
 pub async fn load_all_keys() -> Result<Vec<KeyStatus>, ClewdrError> {
-    Ok(vec![])
+    storage().load_keys().await
 }
-#[cfg(not(feature = "db"))]
+
 pub async fn load_all_cookies()
 -> Result<(Vec<CookieStatus>, Vec<CookieStatus>, Vec<UselessCookie>), ClewdrError> {
-    Ok((vec![], vec![], vec![]))
+    storage().load_cookies().await
+}
+
+/// Checks that the storage of a candidate config is reachable, for dry-run config validation
+pub async fn probe(config: &ClewdrConfig) -> Result<(), ClewdrError> {
+    match config.persistence.mode {
+        PersistenceMode::File => Ok(()),
+        #[cfg(feature = "db")]
+        PersistenceMode::Sqlite | PersistenceMode::Postgres | PersistenceMode::Mysql => {
+            db::conn::probe(config).await
+        }
+        #[cfg(feature = "db-redis")]
+        PersistenceMode::Redis => redis_store::probe(config).await,
+        PersistenceMode::S3 => s3_store::probe(config).await,
+        #[allow(unreachable_patterns)]
+        _ => Err(ClewdrError::PathNotFound {
+            msg: "Persistence mode not supported by this build".into(),
+        }),
+    }
+}
+
+/// Applies pending schema migrations of the configured database, for `clewdr migrate`
+///
+/// # Returns
+/// * `Result<Vec<String>, ClewdrError>` - Names of the applied migrations
+pub async fn migrate() -> Result<Vec<String>, ClewdrError> {
+    #[cfg(feature = "db")]
+    {
+        db::conn::run_migrations().await
+    }
+    #[cfg(not(feature = "db"))]
+    {
+        Err(ClewdrError::PathNotFound {
+            msg: "DB feature not enabled".into(),
+        })
+    }
 }
@@ -0,0 +1,543 @@
+use std::{collections::HashMap, sync::OnceLock, time::Duration};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use redis::{AsyncCommands, Client, RedisError, aio::ConnectionManager};
+use serde_json::json;
+use tokio::sync::{OnceCell, broadcast};
+use tracing::{error, warn};
+
+use crate::{
+    config::{
+        CLEWDR_CONFIG, ClewdrConfig, CookieStatus, KeyStatus, UselessCookie, decrypt_secret,
+        encrypt_secret, open_config, record_stored, seal_config,
+    },
+    error::ClewdrError,
+    persistence::{Affinity, StorageBatch, StorageEvent, StorageLayer},
+};
+
+const CONFIG_KEY: &str = "clewdr:config";
+/// Hash of cookie string to JSON `CookieStatus`
+const COOKIES_KEY: &str = "clewdr:cookies";
+/// Hash of cookie string to JSON `UselessCookie`
+const WASTED_KEY: &str = "clewdr:wasted";
+/// Hash of key string to JSON `KeyStatus`
+const KEYS_KEY: &str = "clewdr:keys";
+/// Hash of key string to JSON `KeyStatus`, for dropped keys
+const INVALID_KEYS_KEY: &str = "clewdr:invalid_keys";
+/// List of dispatchable cookies, rotated by every instance
+const READY_KEY: &str = "clewdr:cookies:ready";
+/// Prefix of the per-cookie lease keys, holding the instance id with an expiry
+const LEASE_PREFIX: &str = "clewdr:lease:";
+/// Hash of prompt hash to JSON `Affinity`, shared by all tenants like the database table
+const AFFINITY_KEY: &str = "clewdr:affinity";
+/// Channel announcing which part of the state changed
+const EVENTS_CHANNEL: &str = "clewdr:events";
+/// Prefix of the pool keys of tenants, e.g. `clewdr:tenant:{name}:cookies`
+const TENANT_PREFIX: &str = "clewdr:tenant:";
+
+static CONN: OnceCell<ConnectionManager> = OnceCell::const_new();
+static EVENTS: OnceLock<broadcast::Sender<StorageEvent>> = OnceLock::new();
+
+fn redis_err(message: &'static str) -> impl FnOnce(RedisError) -> ClewdrError {
+    move |e| ClewdrError::Whatever {
+        message: message.into(),
+        source: Some(Box::new(e)),
+    }
+}
+
+fn client() -> Result<Client, ClewdrError> {
+    let url = CLEWDR_CONFIG
+        .load()
+        .database_url()
+        .ok_or(ClewdrError::UnexpectedNone {
+            msg: "Redis URL not provided",
+        })?;
+    Client::open(url).map_err(redis_err("redis_connect"))
+}
+
+/// Connects to the Redis server of a candidate config and pings it
+pub async fn probe(config: &ClewdrConfig) -> Result<(), ClewdrError> {
+    let url = config.database_url().ok_or(ClewdrError::UnexpectedNone {
+        msg: "Redis URL not provided",
+    })?;
+    let mut conn = Client::open(url)
+        .map_err(redis_err("redis_connect"))?
+        .get_multiplexed_async_connection()
+        .await
+        .map_err(redis_err("redis_connect"))?;
+    redis::cmd("PING")
+        .query_async::<String>(&mut conn)
+        .await
+        .map_err(redis_err("redis_ping"))?;
+    Ok(())
+}
+
+async fn conn() -> Result<ConnectionManager, ClewdrError> {
+    CONN.get_or_try_init(|| async {
+        client()?
+            .get_connection_manager()
+            .await
+            .map_err(redis_err("redis_connect"))
+    })
+    .await
+    .cloned()
+}
+
+fn event_name(event: StorageEvent) -> &'static str {
+    match event {
+        StorageEvent::Config => "config",
+        StorageEvent::Cookies => "cookies",
+        StorageEvent::Keys => "keys",
+    }
+}
+
+/// Forwards events published by any instance until the subscription drops
+async fn listen(tx: &broadcast::Sender<StorageEvent>) -> Result<(), ClewdrError> {
+    let mut pubsub = client()?
+        .get_async_pubsub()
+        .await
+        .map_err(redis_err("redis_subscribe"))?;
+    pubsub
+        .subscribe(EVENTS_CHANNEL)
+        .await
+        .map_err(redis_err("redis_subscribe"))?;
+    let mut messages = pubsub.on_message();
+    while let Some(msg) = messages.next().await {
+        let event = match msg.get_payload::<String>().as_deref() {
+            Ok("config") => StorageEvent::Config,
+            Ok("cookies") => StorageEvent::Cookies,
+            Ok("keys") => StorageEvent::Keys,
+            _ => continue,
+        };
+        let _ = tx.send(event);
+    }
+    Ok(())
+}
+
+/// Hash field and list item of a cookie or key, encrypted when secrets are
+fn entry_id(secret: &str) -> String {
+    encrypt_secret(secret)
+}
+
+/// Hash value of a cookie or key, the whole JSON is encrypted when secrets are
+fn encode<T: serde::Serialize>(value: &T) -> Result<String, ClewdrError> {
+    Ok(encrypt_secret(&serde_json::to_string(value)?))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(map: HashMap<String, String>) -> Vec<T> {
+    map.into_iter()
+        .filter_map(|(k, v)| {
+            let v = decrypt_secret(&v)
+                .inspect_err(|e| warn!("Skipping unreadable Redis entry: {}", e))
+                .ok()?;
+            serde_json::from_str(&v)
+                .inspect_err(|e| warn!("Skipping malformed Redis entry {}: {}", k, e))
+                .ok()
+        })
+        .collect()
+}
+
+/// Splits the stored pins into live and expired ones, deleting the expired
+async fn sweep_affinity(now: i64) -> Result<(Vec<Affinity>, u64), ClewdrError> {
+    let mut c = conn().await?;
+    let rows: HashMap<String, String> = c
+        .hgetall(AFFINITY_KEY)
+        .await
+        .map_err(redis_err("load_affinity"))?;
+    let (live, expired): (Vec<_>, Vec<_>) = decode::<Affinity>(rows)
+        .into_iter()
+        .partition(|a| a.expires_at > now);
+    if !expired.is_empty() {
+        let fields = expired
+            .iter()
+            .map(|a| a.hash.to_string())
+            .collect::<Vec<_>>();
+        c.hdel::<_, _, ()>(AFFINITY_KEY, fields)
+            .await
+            .map_err(redis_err("prune_affinity"))?;
+    }
+    Ok((live, expired.len() as u64))
+}
+
+async fn read_config() -> Result<Option<ClewdrConfig>, ClewdrError> {
+    let data: Option<String> = conn()
+        .await?
+        .get(CONFIG_KEY)
+        .await
+        .map_err(redis_err("load_config"))?;
+    data.map(|d| open_config(&d)).transpose()
+}
+
+pub struct RedisLayer {
+    /// Tenant whose pools the cookie and key methods read and write, `None` for the shared pools
+    pub tenant: Option<String>,
+}
+
+impl RedisLayer {
+    /// Name of a pool key, under the tenant's prefix for a tenant
+    fn scoped(&self, key: &str) -> String {
+        match &self.tenant {
+            Some(name) => format!(
+                "{TENANT_PREFIX}{name}:{}",
+                key.trim_start_matches("clewdr:")
+            ),
+            None => key.to_string(),
+        }
+    }
+
+    /// Announces a change of the shared pools, instances serve tenant pools from memory
+    fn publish(&self, pipe: &mut redis::Pipeline, event: StorageEvent) {
+        if self.tenant.is_none() {
+            pipe.publish(EVENTS_CHANNEL, event_name(event)).ignore();
+        }
+    }
+
+    async fn write_batch(&self, batch: &StorageBatch) -> Result<(), ClewdrError> {
+        let (cookies_key, wasted_key, ready_key) = (
+            self.scoped(COOKIES_KEY),
+            self.scoped(WASTED_KEY),
+            self.scoped(READY_KEY),
+        );
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        if batch.replace {
+            pipe.del(&cookies_key)
+                .ignore()
+                .del(&wasted_key)
+                .ignore()
+                .del(&ready_key)
+                .ignore();
+        }
+        for c in &batch.cookies {
+            let id = entry_id(&c.cookie.to_string());
+            pipe.hset(&cookies_key, &id, encode(c)?)
+                .ignore()
+                .lrem(&ready_key, 0, &id)
+                .ignore();
+            if c.reset_time.is_none() {
+                pipe.rpush(&ready_key, &id).ignore();
+            }
+        }
+        for u in &batch.wasted {
+            let id = entry_id(&u.cookie.to_string());
+            pipe.hset(&wasted_key, &id, encode(u)?)
+                .ignore()
+                .hdel(&cookies_key, &id)
+                .ignore()
+                .lrem(&ready_key, 0, &id)
+                .ignore();
+        }
+        for c in &batch.deleted {
+            let id = entry_id(&c.cookie.to_string());
+            pipe.hdel(&cookies_key, &id)
+                .ignore()
+                .hdel(&wasted_key, &id)
+                .ignore()
+                .lrem(&ready_key, 0, &id)
+                .ignore();
+        }
+        let (keys_key, invalid_key) = (self.scoped(KEYS_KEY), self.scoped(INVALID_KEYS_KEY));
+        if batch.replace_keys {
+            pipe.del(&keys_key).ignore().del(&invalid_key).ignore();
+        }
+        for k in &batch.keys {
+            let id = entry_id(&k.key);
+            pipe.hset(&keys_key, &id, encode(k)?)
+                .ignore()
+                .hdel(&invalid_key, &id)
+                .ignore();
+        }
+        for k in &batch.invalid_keys {
+            let id = entry_id(&k.key);
+            pipe.hset(&invalid_key, &id, encode(k)?)
+                .ignore()
+                .hdel(&keys_key, &id)
+                .ignore();
+        }
+        if batch.has_cookies() {
+            self.publish(&mut pipe, StorageEvent::Cookies);
+        }
+        if batch.has_keys() {
+            self.publish(&mut pipe, StorageEvent::Keys);
+        }
+        pipe.query_async::<()>(&mut conn().await?)
+            .await
+            .map_err(redis_err("persist_batch"))
+    }
+}
+
+#[async_trait]
+impl StorageLayer for RedisLayer {
+    fn is_enabled(&self) -> bool {
+        true
+    }
+    async fn spawn_bootstrap(&self) -> Result<(), ClewdrError> {
+        let Some(mut cfg) = read_config().await? else {
+            return Ok(());
+        };
+        // the hashes are newer than the cookie lists inside the stored config
+        let (valid, exhausted, invalid) = self.load_cookies().await?;
+        cfg.cookie_array = valid.into_iter().chain(exhausted).collect();
+        cfg.wasted_cookie = invalid.into_iter().collect();
+        cfg.gemini_keys = self.load_keys().await?.into_iter().collect();
+        let cfg = cfg.validate();
+        record_stored(&cfg);
+        CLEWDR_CONFIG.store(std::sync::Arc::new(cfg));
+        Ok(())
+    }
+    async fn persist_config(&self, cfg: &ClewdrConfig) -> Result<(), ClewdrError> {
+        let data = seal_config(cfg)?;
+        redis::pipe()
+            .atomic()
+            .set(CONFIG_KEY, data)
+            .ignore()
+            .publish(EVENTS_CHANNEL, event_name(StorageEvent::Config))
+            .ignore()
+            .query_async::<()>(&mut conn().await?)
+            .await
+            .map_err(redis_err("save_config"))
+    }
+    async fn persist_cookies(
+        &self,
+        valid: &[CookieStatus],
+        exhausted: &[CookieStatus],
+        invalid: &[UselessCookie],
+    ) -> Result<(), ClewdrError> {
+        self.write_batch(&StorageBatch::snapshot(valid, exhausted, invalid))
+            .await
+    }
+    async fn persist_batch(&self, batch: &StorageBatch) -> Result<(), ClewdrError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.write_batch(batch).await
+    }
+    async fn persist_keys(&self, keys: &[KeyStatus]) -> Result<(), ClewdrError> {
+        let keys_key = self.scoped(KEYS_KEY);
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(&keys_key).ignore();
+        for k in keys {
+            pipe.hset(&keys_key, entry_id(&k.key), encode(k)?)
+                .ignore()
+                .hdel(self.scoped(INVALID_KEYS_KEY), entry_id(&k.key))
+                .ignore();
+        }
+        self.publish(&mut pipe, StorageEvent::Keys);
+        pipe.query_async::<()>(&mut conn().await?)
+            .await
+            .map_err(redis_err("persist_keys"))
+    }
+    async fn persist_cookie_upsert(&self, c: &CookieStatus) -> Result<(), ClewdrError> {
+        self.write_batch(&StorageBatch {
+            cookies: vec![c.to_owned()],
+            ..Default::default()
+        })
+        .await
+    }
+    async fn delete_cookie_row(&self, c: &CookieStatus) -> Result<(), ClewdrError> {
+        self.write_batch(&StorageBatch {
+            deleted: vec![c.to_owned()],
+            ..Default::default()
+        })
+        .await
+    }
+    async fn persist_wasted_upsert(&self, u: &UselessCookie) -> Result<(), ClewdrError> {
+        self.write_batch(&StorageBatch {
+            wasted: vec![u.to_owned()],
+            ..Default::default()
+        })
+        .await
+    }
+    async fn persist_key_upsert(&self, k: &KeyStatus) -> Result<(), ClewdrError> {
+        self.write_batch(&StorageBatch {
+            keys: vec![k.to_owned()],
+            ..Default::default()
+        })
+        .await
+    }
+    async fn delete_key_row(&self, k: &KeyStatus) -> Result<(), ClewdrError> {
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .hdel(self.scoped(KEYS_KEY), entry_id(&k.key))
+            .ignore()
+            .hdel(self.scoped(INVALID_KEYS_KEY), entry_id(&k.key))
+            .ignore();
+        self.publish(&mut pipe, StorageEvent::Keys);
+        pipe.query_async::<()>(&mut conn().await?)
+            .await
+            .map_err(redis_err("delete_key"))
+    }
+    async fn import_from_file(&self) -> Result<serde_json::Value, ClewdrError> {
+        let text = tokio::fs::read_to_string(crate::config::CONFIG_PATH.as_path()).await?;
+        let cfg = open_config(&text)?;
+        self.persist_config(&cfg).await?;
+        let (exhausted, valid): (Vec<_>, Vec<_>) = cfg
+            .cookie_array
+            .iter()
+            .cloned()
+            .partition(|c| c.reset_time.is_some());
+        let invalid: Vec<UselessCookie> = cfg.wasted_cookie.iter().cloned().collect();
+        self.persist_cookies(&valid, &exhausted, &invalid).await?;
+        let keys: Vec<KeyStatus> = cfg.gemini_keys.iter().cloned().collect();
+        self.persist_keys(&keys).await?;
+        Ok(json!({"status":"ok"}))
+    }
+    async fn snapshot_config(&self) -> Result<ClewdrConfig, ClewdrError> {
+        let mut cfg = read_config()
+            .await?
+            .unwrap_or_else(|| CLEWDR_CONFIG.load().as_ref().clone());
+        let (valid, exhausted, invalid) = self.load_cookies().await?;
+        cfg.cookie_array = valid.into_iter().chain(exhausted).collect();
+        cfg.wasted_cookie = invalid.into_iter().collect();
+        cfg.gemini_keys = self.load_keys().await?.into_iter().collect();
+        cfg.invalid_keys.extend(self.load_invalid_keys().await?);
+        crate::persistence::fill_tenant_pools(&mut cfg).await?;
+        Ok(cfg)
+    }
+    async fn export_current_config(&self) -> Result<serde_json::Value, ClewdrError> {
+        let cfg = self.snapshot_config().await?;
+        if CLEWDR_CONFIG.load().no_fs {
+            return Err(ClewdrError::Whatever {
+                message: "File export disabled when no_fs is enabled".into(),
+                source: None,
+            });
+        }
+        let toml = toml::to_string_pretty(&cfg)?;
+        Ok(json!({"toml": toml}))
+    }
+    async fn status(&self) -> Result<serde_json::Value, ClewdrError> {
+        let start = std::time::Instant::now();
+        let res = match conn().await {
+            Ok(mut c) => redis::cmd("PING")
+                .query_async::<String>(&mut c)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        let latency_ms = res.is_ok().then(|| start.elapsed().as_millis());
+        Ok(json!({
+            "enabled": true,
+            "mode": "redis",
+            "healthy": res.is_ok(),
+            "latency_ms": latency_ms,
+            "error": res.err(),
+            "details": { "driver": "redis" }
+        }))
+    }
+    async fn load_keys(&self) -> Result<Vec<KeyStatus>, ClewdrError> {
+        let rows: HashMap<String, String> = conn()
+            .await?
+            .hgetall(self.scoped(KEYS_KEY))
+            .await
+            .map_err(redis_err("load_keys"))?;
+        Ok(decode(rows))
+    }
+    async fn load_invalid_keys(&self) -> Result<Vec<KeyStatus>, ClewdrError> {
+        let rows: HashMap<String, String> = conn()
+            .await?
+            .hgetall(self.scoped(INVALID_KEYS_KEY))
+            .await
+            .map_err(redis_err("load_invalid_keys"))?;
+        Ok(decode(rows))
+    }
+    async fn load_cookies(
+        &self,
+    ) -> Result<(Vec<CookieStatus>, Vec<CookieStatus>, Vec<UselessCookie>), ClewdrError> {
+        let mut c = conn().await?;
+        let rows: HashMap<String, String> = c
+            .hgetall(self.scoped(COOKIES_KEY))
+            .await
+            .map_err(redis_err("load_cookies"))?;
+        let wasted: HashMap<String, String> = c
+            .hgetall(self.scoped(WASTED_KEY))
+            .await
+            .map_err(redis_err("load_wasted"))?;
+        let (exhausted, valid): (Vec<_>, Vec<_>) = decode::<CookieStatus>(rows)
+            .into_iter()
+            .partition(|c| c.reset_time.is_some());
+        Ok((valid, exhausted, decode(wasted)))
+    }
+    async fn next_cookie(&self) -> Result<Option<String>, ClewdrError> {
+        // LMOVE on a single list pops the head and pushes it back as the tail atomically
+        let ready_key = self.scoped(READY_KEY);
+        let id: Option<String> = redis::cmd("LMOVE")
+            .arg(&ready_key)
+            .arg(&ready_key)
+            .arg("LEFT")
+            .arg("RIGHT")
+            .query_async(&mut conn().await?)
+            .await
+            .map_err(redis_err("next_cookie"))?;
+        id.map(|id| decrypt_secret(&id)).transpose()
+    }
+    async fn acquire_lease(
+        &self,
+        cookie: &str,
+        holder: &str,
+        ttl_secs: u64,
+    ) -> Result<bool, ClewdrError> {
+        // take a free lease, or renew our own
+        let script = redis::Script::new(
+            r"
+            local cur = redis.call('GET', KEYS[1])
+            if cur and cur ~= ARGV[1] then return 0 end
+            redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[2])
+            return 1
+            ",
+        );
+        let taken: i32 = script
+            .key(format!("{LEASE_PREFIX}{}", entry_id(cookie)))
+            .arg(holder)
+            .arg(ttl_secs.max(1))
+            .invoke_async(&mut conn().await?)
+            .await
+            .map_err(redis_err("acquire_lease"))?;
+        Ok(taken == 1)
+    }
+    async fn release_lease(&self, cookie: &str, holder: &str) -> Result<(), ClewdrError> {
+        let script = redis::Script::new(
+            r"
+            if redis.call('GET', KEYS[1]) == ARGV[1] then
+                return redis.call('DEL', KEYS[1])
+            end
+            return 0
+            ",
+        );
+        script
+            .key(format!("{LEASE_PREFIX}{}", entry_id(cookie)))
+            .arg(holder)
+            .invoke_async::<()>(&mut conn().await?)
+            .await
+            .map_err(redis_err("release_lease"))
+    }
+    async fn persist_affinity(&self, a: &Affinity) -> Result<(), ClewdrError> {
+        conn()
+            .await?
+            .hset::<_, _, _, ()>(AFFINITY_KEY, a.hash.to_string(), encode(a)?)
+            .await
+            .map_err(redis_err("persist_affinity"))
+    }
+    async fn load_affinity(&self, now: i64) -> Result<Vec<Affinity>, ClewdrError> {
+        Ok(sweep_affinity(now).await?.0)
+    }
+    async fn prune_affinity(&self, now: i64) -> Result<u64, ClewdrError> {
+        Ok(sweep_affinity(now).await?.1)
+    }
+    fn subscribe(&self) -> Option<broadcast::Receiver<StorageEvent>> {
+        let tx = EVENTS.get_or_init(|| {
+            let (tx, _) = broadcast::channel(16);
+            let listener = tx.clone();
+            tokio::spawn(async move {
+                loop {
+                    if let Err(e) = listen(&listener).await {
+                        error!("Redis subscription failed: {}", e);
+                    }
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            });
+            tx
+        });
+        Some(tx.subscribe())
+    }
+}
@@ -0,0 +1,426 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::json;
+use tokio::sync::OnceCell;
+use tracing::{debug, warn};
+
+use crate::{
+    config::{
+        CLEWDR_CONFIG, ClewdrConfig, CookieStatus, KeyStatus, UselessCookie, decrypt_secret,
+        encrypt_secret, open_config, record_stored, seal_config,
+    },
+    error::ClewdrError,
+    persistence::{StorageBatch, StorageLayer},
+    services::object_store::ObjectStore,
+};
+
+/// Sealed `clewdr.toml` text
+const CONFIG_OBJECT: &str = "config.json";
+/// Pool and wasted cookies
+const COOKIES_OBJECT: &str = "cookies.json";
+/// Gemini keys
+const KEYS_OBJECT: &str = "keys.json";
+/// Prefix of the pool objects of tenants, e.g. `tenants/{name}/cookies.json`
+const TENANTS_PREFIX: &str = "tenants/";
+/// Read-modify-write rounds before a write contended by other instances gives up
+const MAX_ATTEMPTS: usize = 5;
+
+static STORE: OnceCell<ObjectStore> = OnceCell::const_new();
+
+/// Body of every object, `version` counts its writes
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Versioned<T> {
+    version: u64,
+    /// Unix timestamp of the last write
+    updated_at: i64,
+    #[serde(flatten)]
+    data: T,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct ConfigData {
+    config: String,
+}
+
+/// Entries are JSON, encrypted when secrets are
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct CookiesData {
+    cookies: Vec<String>,
+    wasted: Vec<String>,
+}
+
+/// Entries are JSON, encrypted when secrets are
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct KeysData {
+    keys: Vec<String>,
+    /// Dropped keys
+    #[serde(default)]
+    invalid: Vec<String>,
+}
+
+fn unavailable() -> ClewdrError {
+    ClewdrError::UnexpectedNone {
+        msg: "persistence.object_store not provided",
+    }
+}
+
+async fn store() -> Result<&'static ObjectStore, ClewdrError> {
+    STORE
+        .get_or_try_init(|| async {
+            let config = CLEWDR_CONFIG.load();
+            ObjectStore::new(
+                config
+                    .persistence
+                    .object_store
+                    .as_ref()
+                    .ok_or_else(unavailable)?,
+            )
+        })
+        .await
+}
+
+/// Reads the bucket of a candidate config
+pub async fn probe(config: &ClewdrConfig) -> Result<(), ClewdrError> {
+    let store = config
+        .persistence
+        .object_store
+        .as_ref()
+        .ok_or_else(unavailable)?;
+    ObjectStore::new(store)?.get(CONFIG_OBJECT).await?;
+    Ok(())
+}
+
+fn encode<T: Serialize>(values: &[T]) -> Result<Vec<String>, ClewdrError> {
+    values
+        .iter()
+        .map(|v| Ok(encrypt_secret(&serde_json::to_string(v)?)))
+        .collect()
+}
+
+/// Entries that can be read, others are skipped with a warning
+fn decode_lossy<T: DeserializeOwned>(entries: &[String]) -> Vec<T> {
+    entries
+        .iter()
+        .filter_map(|v| {
+            let v = decrypt_secret(v)
+                .inspect_err(|e| warn!("Skipping unreadable S3 entry: {}", e))
+                .ok()?;
+            serde_json::from_str(&v)
+                .inspect_err(|e| warn!("Skipping malformed S3 entry: {}", e))
+                .ok()
+        })
+        .collect()
+}
+
+/// Every entry, failing on the first unreadable one so a rewrite cannot drop it
+fn decode<T: DeserializeOwned>(entries: &[String]) -> Result<Vec<T>, ClewdrError> {
+    entries
+        .iter()
+        .map(|v| Ok(serde_json::from_str(&decrypt_secret(v)?)?))
+        .collect()
+}
+
+/// An object with its ETag, an empty one without ETag when it does not exist yet
+async fn read<T>(name: &str) -> Result<(Versioned<T>, Option<String>), ClewdrError>
+where
+    T: DeserializeOwned + Default,
+{
+    match store().await?.get(name).await? {
+        Some((text, etag)) => Ok((serde_json::from_str(&text)?, Some(etag))),
+        None => Ok((Versioned::default(), None)),
+    }
+}
+
+/// Applies `f` to an object and writes it back if no other instance wrote it meanwhile,
+/// otherwise `f` is applied again to the fresh object
+async fn update<T>(
+    name: &str,
+    mut f: impl FnMut(&mut T) -> Result<(), ClewdrError>,
+) -> Result<(), ClewdrError>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    for _ in 0..MAX_ATTEMPTS {
+        let (mut object, etag) = read::<T>(name).await?;
+        f(&mut object.data)?;
+        object.version += 1;
+        object.updated_at = Utc::now().timestamp();
+        let body = serde_json::to_vec(&object)?;
+        if store().await?.put_if(name, body, etag.as_deref()).await? {
+            return Ok(());
+        }
+        debug!("{} changed by another instance, retrying", name);
+    }
+    Err(ClewdrError::Whatever {
+        message: format!("{name} kept changing, gave up writing it"),
+        source: None,
+    })
+}
+
+fn apply_batch(data: &mut CookiesData, batch: &StorageBatch) -> Result<(), ClewdrError> {
+    let (mut cookies, mut wasted): (Vec<CookieStatus>, Vec<UselessCookie>) = if batch.replace {
+        (vec![], vec![])
+    } else {
+        (decode(&data.cookies)?, decode(&data.wasted)?)
+    };
+    for c in &batch.cookies {
+        cookies.retain(|x| x != c);
+        cookies.push(c.to_owned());
+    }
+    for u in &batch.wasted {
+        cookies.retain(|x| x.cookie != u.cookie);
+        wasted.retain(|x| x != u);
+        wasted.push(u.to_owned());
+    }
+    for c in &batch.deleted {
+        cookies.retain(|x| x != c);
+        wasted.retain(|x| x.cookie != c.cookie);
+    }
+    data.cookies = encode(&cookies)?;
+    data.wasted = encode(&wasted)?;
+    Ok(())
+}
+
+async fn write_batch(name: &str, batch: &StorageBatch) -> Result<(), ClewdrError> {
+    update(name, |data| apply_batch(data, batch)).await
+}
+
+/// Applies the key writes of a batch to the valid and dropped keys
+fn apply_key_batch(keys: &mut Vec<KeyStatus>, invalid: &mut Vec<KeyStatus>, batch: &StorageBatch) {
+    if batch.replace_keys {
+        keys.clear();
+        invalid.clear();
+    }
+    for k in &batch.keys {
+        invalid.retain(|x| x != k);
+        keys.retain(|x| x != k);
+        keys.push(k.to_owned());
+    }
+    for k in &batch.invalid_keys {
+        keys.retain(|x| x != k);
+        invalid.retain(|x| x != k);
+        invalid.push(k.to_owned());
+    }
+}
+
+/// Applies `f` to the valid and dropped keys of an object
+async fn update_keys(
+    name: &str,
+    mut f: impl FnMut(&mut Vec<KeyStatus>, &mut Vec<KeyStatus>) + Send,
+) -> Result<(), ClewdrError> {
+    update(name, |data: &mut KeysData| {
+        let mut keys = decode(&data.keys)?;
+        let mut invalid = decode(&data.invalid)?;
+        f(&mut keys, &mut invalid);
+        data.keys = encode(&keys)?;
+        data.invalid = encode(&invalid)?;
+        Ok(())
+    })
+    .await
+}
+
+async fn read_config() -> Result<Option<ClewdrConfig>, ClewdrError> {
+    let (object, etag) = read::<ConfigData>(CONFIG_OBJECT).await?;
+    etag.map(|_| open_config(&object.data.config)).transpose()
+}
+
+/// Keeps the config, cookies and keys as JSON objects in an S3 compatible bucket
+///
+/// Every write reads the object and uploads it conditionally on its ETag, so instances
+/// sharing the bucket retry instead of overwriting each other's changes.
+pub struct S3Layer {
+    /// Tenant whose pools the cookie and key methods read and write, `None` for the shared pools
+    pub tenant: Option<String>,
+}
+
+impl S3Layer {
+    /// Name of a pool object, below the tenant's prefix for a tenant
+    fn object(&self, name: &str) -> String {
+        match &self.tenant {
+            Some(tenant) => format!("{TENANTS_PREFIX}{tenant}/{name}"),
+            None => name.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl StorageLayer for S3Layer {
+    fn is_enabled(&self) -> bool {
+        true
+    }
+    async fn spawn_bootstrap(&self) -> Result<(), ClewdrError> {
+        let Some(mut cfg) = read_config().await? else {
+            return Ok(());
+        };
+        // the cookie and key objects are newer than the lists inside the stored config
+        let (valid, exhausted, invalid) = self.load_cookies().await?;
+        cfg.cookie_array = valid.into_iter().chain(exhausted).collect();
+        cfg.wasted_cookie = invalid.into_iter().collect();
+        cfg.gemini_keys = self.load_keys().await?.into_iter().collect();
+        let cfg = cfg.validate();
+        record_stored(&cfg);
+        CLEWDR_CONFIG.store(std::sync::Arc::new(cfg));
+        Ok(())
+    }
+    async fn persist_config(&self, cfg: &ClewdrConfig) -> Result<(), ClewdrError> {
+        let config = seal_config(cfg)?;
+        update(CONFIG_OBJECT, |data: &mut ConfigData| {
+            data.config = config.to_owned();
+            Ok(())
+        })
+        .await
+    }
+    async fn config_updated_at(&self) -> Result<Option<i64>, ClewdrError> {
+        let (object, etag) = read::<ConfigData>(CONFIG_OBJECT).await?;
+        Ok(etag.map(|_| object.updated_at))
+    }
+    async fn persist_cookies(
+        &self,
+        valid: &[CookieStatus],
+        exhausted: &[CookieStatus],
+        invalid: &[UselessCookie],
+    ) -> Result<(), ClewdrError> {
+        write_batch(
+            &self.object(COOKIES_OBJECT),
+            &StorageBatch::snapshot(valid, exhausted, invalid),
+        )
+        .await
+    }
+    /// The keys and the cookies are separate objects, a batch writing both is not atomic
+    async fn persist_batch(&self, batch: &StorageBatch) -> Result<(), ClewdrError> {
+        if batch.has_keys() {
+            update_keys(&self.object(KEYS_OBJECT), |keys, invalid| {
+                apply_key_batch(keys, invalid, batch)
+            })
+            .await?;
+        }
+        if batch.has_cookies() {
+            write_batch(&self.object(COOKIES_OBJECT), batch).await?;
+        }
+        Ok(())
+    }
+    async fn persist_keys(&self, keys: &[KeyStatus]) -> Result<(), ClewdrError> {
+        update_keys(&self.object(KEYS_OBJECT), |stored, invalid| {
+            invalid.retain(|x| !keys.contains(x));
+            *stored = keys.to_vec();
+        })
+        .await
+    }
+    async fn persist_cookie_upsert(&self, c: &CookieStatus) -> Result<(), ClewdrError> {
+        write_batch(
+            &self.object(COOKIES_OBJECT),
+            &StorageBatch {
+                cookies: vec![c.to_owned()],
+                ..Default::default()
+            },
+        )
+        .await
+    }
+    async fn delete_cookie_row(&self, c: &CookieStatus) -> Result<(), ClewdrError> {
+        write_batch(
+            &self.object(COOKIES_OBJECT),
+            &StorageBatch {
+                deleted: vec![c.to_owned()],
+                ..Default::default()
+            },
+        )
+        .await
+    }
+    async fn persist_wasted_upsert(&self, u: &UselessCookie) -> Result<(), ClewdrError> {
+        write_batch(
+            &self.object(COOKIES_OBJECT),
+            &StorageBatch {
+                wasted: vec![u.to_owned()],
+                ..Default::default()
+            },
+        )
+        .await
+    }
+    async fn persist_key_upsert(&self, k: &KeyStatus) -> Result<(), ClewdrError> {
+        self.persist_batch(&StorageBatch {
+            keys: vec![k.to_owned()],
+            ..Default::default()
+        })
+        .await
+    }
+    async fn delete_key_row(&self, k: &KeyStatus) -> Result<(), ClewdrError> {
+        update_keys(&self.object(KEYS_OBJECT), |keys, invalid| {
+            keys.retain(|x| x != k);
+            invalid.retain(|x| x != k);
+        })
+        .await
+    }
+    async fn import_from_file(&self) -> Result<serde_json::Value, ClewdrError> {
+        let text = tokio::fs::read_to_string(crate::config::CONFIG_PATH.as_path()).await?;
+        let cfg = open_config(&text)?;
+        self.persist_config(&cfg).await?;
+        let (exhausted, valid): (Vec<_>, Vec<_>) = cfg
+            .cookie_array
+            .iter()
+            .cloned()
+            .partition(|c| c.reset_time.is_some());
+        let invalid: Vec<UselessCookie> = cfg.wasted_cookie.iter().cloned().collect();
+        self.persist_cookies(&valid, &exhausted, &invalid).await?;
+        let keys: Vec<KeyStatus> = cfg.gemini_keys.iter().cloned().collect();
+        self.persist_keys(&keys).await?;
+        Ok(json!({"status":"ok"}))
+    }
+    async fn snapshot_config(&self) -> Result<ClewdrConfig, ClewdrError> {
+        let mut cfg = read_config()
+            .await?
+            .unwrap_or_else(|| CLEWDR_CONFIG.load().as_ref().clone());
+        let (valid, exhausted, invalid) = self.load_cookies().await?;
+        cfg.cookie_array = valid.into_iter().chain(exhausted).collect();
+        cfg.wasted_cookie = invalid.into_iter().collect();
+        cfg.gemini_keys = self.load_keys().await?.into_iter().collect();
+        cfg.invalid_keys.extend(self.load_invalid_keys().await?);
+        crate::persistence::fill_tenant_pools(&mut cfg).await?;
+        Ok(cfg)
+    }
+    async fn export_current_config(&self) -> Result<serde_json::Value, ClewdrError> {
+        let cfg = self.snapshot_config().await?;
+        if CLEWDR_CONFIG.load().no_fs {
+            return Err(ClewdrError::Whatever {
+                message: "File export disabled when no_fs is enabled".into(),
+                source: None,
+            });
+        }
+        let toml = toml::to_string_pretty(&cfg)?;
+        Ok(json!({"toml": toml}))
+    }
+    async fn status(&self) -> Result<serde_json::Value, ClewdrError> {
+        let start = std::time::Instant::now();
+        let res = match store().await {
+            Ok(s) => s.get(CONFIG_OBJECT).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        let latency_ms = res.is_ok().then(|| start.elapsed().as_millis());
+        Ok(json!({
+            "enabled": true,
+            "mode": "s3",
+            "healthy": res.is_ok(),
+            "latency_ms": latency_ms,
+            "error": res.err(),
+            "details": { "driver": "s3" }
+        }))
+    }
+    async fn load_keys(&self) -> Result<Vec<KeyStatus>, ClewdrError> {
+        let (object, _) = read::<KeysData>(&self.object(KEYS_OBJECT)).await?;
+        Ok(decode_lossy(&object.data.keys))
+    }
+    async fn load_invalid_keys(&self) -> Result<Vec<KeyStatus>, ClewdrError> {
+        let (object, _) = read::<KeysData>(&self.object(KEYS_OBJECT)).await?;
+        Ok(decode_lossy(&object.data.invalid))
+    }
+    async fn load_cookies(
+        &self,
+    ) -> Result<(Vec<CookieStatus>, Vec<CookieStatus>, Vec<UselessCookie>), ClewdrError> {
+        let (object, _) = read::<CookiesData>(&self.object(COOKIES_OBJECT)).await?;
+        let (exhausted, valid): (Vec<_>, Vec<_>) =
+            decode_lossy::<CookieStatus>(&object.data.cookies)
+                .into_iter()
+                .partition(|c| c.reset_time.is_some());
+        Ok((valid, exhausted, decode_lossy(&object.data.wasted)))
+    }
+}
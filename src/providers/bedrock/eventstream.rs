@@ -0,0 +1,186 @@
+use base64::{Engine, prelude::BASE64_STANDARD};
+use bytes::{Buf, Bytes, BytesMut};
+use serde_json::{Value, json};
+
+/// Length of the prelude: total length, headers length and prelude CRC
+const PRELUDE_LEN: usize = 12;
+/// Length of the CRC closing every message
+const CRC_LEN: usize = 4;
+
+/// Message of the `application/vnd.amazon.eventstream` encoding, with its string headers
+#[derive(Debug)]
+pub struct EventMessage {
+    pub headers: Vec<(String, String)>,
+    pub payload: Bytes,
+}
+
+impl EventMessage {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// SSE event of the Anthropic streaming API carried by the message
+    ///
+    /// Chunks hold a base64 encoded Anthropic event, exceptions become an `error` event.
+    ///
+    /// # Returns
+    /// * `None` - The message carries no event
+    pub fn to_sse(&self) -> Option<Bytes> {
+        let payload = serde_json::from_slice::<Value>(&self.payload).unwrap_or_default();
+        match self.header(":message-type") {
+            Some("event") if self.header(":event-type") == Some("chunk") => {
+                let data = BASE64_STANDARD.decode(payload["bytes"].as_str()?).ok()?;
+                let data = String::from_utf8(data).ok()?;
+                let event = serde_json::from_str::<Value>(&data).ok()?;
+                let name = event["type"].as_str().unwrap_or("message");
+                Some(Bytes::from(format!("event: {name}\ndata: {data}\n\n")))
+            }
+            Some("exception") => {
+                let error = json!({
+                    "type": "error",
+                    "error": {
+                        "type": self.header(":exception-type").unwrap_or("api_error"),
+                        "message": payload["message"].as_str().unwrap_or_default(),
+                    },
+                });
+                Some(Bytes::from(format!("event: error\ndata: {error}\n\n")))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Splits a byte stream into event stream messages
+///
+/// The CRCs are not checked, TLS already protects the messages.
+#[derive(Default)]
+pub struct EventStreamDecoder {
+    buf: BytesMut,
+}
+
+impl EventStreamDecoder {
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Next complete message
+    ///
+    /// # Returns
+    /// * `None` - More bytes are needed
+    /// * `Some(Err)` - The stream is malformed, nothing after it can be read
+    pub fn next_message(&mut self) -> Option<Result<EventMessage, &'static str>> {
+        if self.buf.len() < PRELUDE_LEN {
+            return None;
+        }
+        let total = u32::from_be_bytes(self.buf[0..4].try_into().ok()?) as usize;
+        let headers_len = u32::from_be_bytes(self.buf[4..8].try_into().ok()?) as usize;
+        if total < PRELUDE_LEN + headers_len + CRC_LEN {
+            return Some(Err("Malformed event stream message"));
+        }
+        if self.buf.len() < total {
+            return None;
+        }
+        let mut message = self.buf.split_to(total).freeze();
+        message.advance(PRELUDE_LEN);
+        let headers = message.split_to(headers_len);
+        message.truncate(message.len() - CRC_LEN);
+        Some(parse_headers(headers).map(|headers| EventMessage {
+            headers,
+            payload: message,
+        }))
+    }
+}
+
+/// Reads the headers of a message, keeping the string ones
+fn parse_headers(mut buf: Bytes) -> Result<Vec<(String, String)>, &'static str> {
+    const MALFORMED: &str = "Malformed event stream header";
+    let mut headers = vec![];
+    while buf.has_remaining() {
+        let name_len = buf.get_u8() as usize;
+        if buf.remaining() < name_len + 1 {
+            return Err(MALFORMED);
+        }
+        let name = String::from_utf8_lossy(&buf.split_to(name_len)).into_owned();
+        let value_len = match buf.get_u8() {
+            // true, false
+            0 | 1 => 0,
+            // byte, short, integer, long, timestamp, uuid
+            2 => 1,
+            3 => 2,
+            4 => 4,
+            5 | 8 => 8,
+            9 => 16,
+            // byte array, string
+            6 | 7 if buf.remaining() >= 2 => buf.get_u16() as usize,
+            _ => return Err(MALFORMED),
+        };
+        if buf.remaining() < value_len {
+            return Err(MALFORMED);
+        }
+        let value = buf.split_to(value_len);
+        if let Ok(value) = String::from_utf8(value.to_vec()) {
+            headers.push((name, value));
+        }
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(headers: &[(&str, &str)], payload: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![];
+        for (name, value) in headers {
+            encoded.push(name.len() as u8);
+            encoded.extend(name.as_bytes());
+            encoded.push(7);
+            encoded.extend((value.len() as u16).to_be_bytes());
+            encoded.extend(value.as_bytes());
+        }
+        let total = PRELUDE_LEN + encoded.len() + payload.len() + CRC_LEN;
+        let mut out = vec![];
+        out.extend((total as u32).to_be_bytes());
+        out.extend((encoded.len() as u32).to_be_bytes());
+        out.extend([0; 4]);
+        out.extend(encoded);
+        out.extend(payload);
+        out.extend([0; 4]);
+        out
+    }
+
+    #[test]
+    fn decodes_split_chunks() {
+        let event = r#"{"type":"message_stop"}"#;
+        let payload = json!({ "bytes": BASE64_STANDARD.encode(event) }).to_string();
+        let mut bytes = message(
+            &[(":message-type", "event"), (":event-type", "chunk")],
+            payload.as_bytes(),
+        );
+        bytes.extend(message(
+            &[
+                (":message-type", "exception"),
+                (":exception-type", "throttlingException"),
+            ],
+            br#"{"message":"Too many requests"}"#,
+        ));
+        let mut decoder = EventStreamDecoder::default();
+        let (head, tail) = bytes.split_at(20);
+        decoder.push(head);
+        assert!(decoder.next_message().is_none());
+        decoder.push(tail);
+        let chunk = decoder.next_message().unwrap().unwrap();
+        assert_eq!(
+            chunk.to_sse().unwrap(),
+            format!("event: message_stop\ndata: {event}\n\n")
+        );
+        let exception = decoder.next_message().unwrap().unwrap().to_sse().unwrap();
+        let exception = String::from_utf8(exception.to_vec()).unwrap();
+        assert!(exception.starts_with("event: error\n"));
+        assert!(exception.contains("throttlingException"));
+        assert!(decoder.next_message().is_none());
+    }
+}
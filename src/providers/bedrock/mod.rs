@@ -0,0 +1,228 @@
+mod eventstream;
+
+use std::time::Duration;
+
+use async_stream::stream;
+use axum::{body::Body, response::Response};
+use chrono::Utc;
+use colored::Colorize;
+use futures::StreamExt;
+use serde_json::json;
+use snafu::ResultExt;
+use tracing::{info, warn};
+use wreq::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
+
+use self::eventstream::EventStreamDecoder;
+use super::{
+    LLMProvider,
+    passthrough::{AnthropicInvocation, PassthroughBody, check_response},
+};
+use crate::{
+    config::{CLEWDR_CONFIG, ProxyTarget},
+    error::{ClewdrError, WreqSnafu},
+    middleware::{proxy::current_proxy, timeout::current_timeouts, timeout::first_byte},
+    services::{
+        breaker::{self, Provider},
+        capture::CaptureExt,
+        client_pool::{self, ClientKind},
+        recent_errors,
+        sigv4::{self, SigningScope},
+    },
+    utils::{backpressure, enabled, forward_response, sse_error},
+};
+
+/// `anthropic_version` Bedrock expects in the body instead of the header
+const BEDROCK_ANTHROPIC_VERSION: &str = "bedrock-2023-05-31";
+
+/// Claude models hosted by AWS Bedrock, called with the configured IAM credentials
+#[derive(Default)]
+pub struct BedrockProvider;
+
+impl BedrockProvider {
+    async fn send(&self, request: AnthropicInvocation) -> Result<Response, ClewdrError> {
+        let AnthropicInvocation { mut body, betas } = request;
+        let config = CLEWDR_CONFIG.load();
+        let creds = config
+            .bedrock
+            .credentials()
+            .ok_or(ClewdrError::BadRequest {
+                msg: "Bedrock credentials not configured",
+            })?;
+        let mut passthrough = PassthroughBody::parse(&mut body)?;
+        let stream = passthrough.stream;
+        let model_id = config.bedrock.model_id(&passthrough.model);
+        info!(
+            "[REQ] stream: {}, bedrock: {}, model: {}",
+            enabled(stream),
+            enabled(true),
+            model_id.green()
+        );
+        // the model and the streaming mode go in the URL, the API version and betas in the body
+        passthrough.fields.remove("model");
+        passthrough.fields.remove("stream");
+        passthrough.default_version(BEDROCK_ANTHROPIC_VERSION);
+        if !betas.is_empty() {
+            passthrough
+                .fields
+                .insert("anthropic_beta".to_string(), json!(betas));
+        }
+        let payload = serde_json::to_vec(&body)?;
+
+        let host = format!("bedrock-runtime.{}.amazonaws.com", creds.region);
+        let action = if stream {
+            "invoke-with-response-stream"
+        } else {
+            "invoke"
+        };
+        let path = format!("/model/{}/{action}", sigv4::uri_encode(&model_id, false));
+        let now = Utc::now();
+        let amz_date = sigv4::amz_date(now);
+        let payload_hash = sigv4::sha256_hex(&payload);
+        let mut headers = vec![
+            ("host", host.as_str()),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+        if let Some(token) = creds.session_token.as_deref() {
+            headers.push(("x-amz-security-token", token));
+        }
+        let authorization = sigv4::authorization(
+            &SigningScope {
+                access_key_id: &creds.access_key_id,
+                secret_access_key: &creds.secret_access_key,
+                region: &creds.region,
+                service: "bedrock",
+            },
+            "POST",
+            // services other than S3 expect the path encoded twice
+            &sigv4::uri_encode(&path, true),
+            "",
+            &headers,
+            &payload_hash,
+            now,
+        );
+
+        let timeouts = current_timeouts(ProxyTarget::Bedrock);
+        let client = client_pool::client(
+            ClientKind::Bedrock,
+            current_proxy(ProxyTarget::Bedrock),
+            &timeouts,
+        )?;
+        let mut req = client
+            .post(format!("https://{host}{path}"))
+            .header(AUTHORIZATION, authorization)
+            .header(CONTENT_TYPE, "application/json")
+            .header(
+                ACCEPT,
+                if stream {
+                    "application/vnd.amazon.eventstream"
+                } else {
+                    "application/json"
+                },
+            )
+            .header("x-amz-content-sha256", &payload_hash)
+            .header("x-amz-date", &amz_date)
+            .body(payload);
+        if let Some(token) = creds.session_token.as_deref() {
+            req = req.header("x-amz-security-token", token);
+        }
+        let res = first_byte(&timeouts, async {
+            req.send_captured().await.context(WreqSnafu {
+                msg: "Failed to send request to Bedrock API",
+            })
+        })
+        .await?;
+        let res = check_bedrock(res).await?;
+        if stream {
+            Ok(event_stream_to_sse(res)?)
+        } else {
+            forward_response(res)
+        }
+    }
+}
+
+/// Turns a failed Bedrock response into a Claude error, typed after `x-amzn-ErrorType`
+async fn check_bedrock(res: wreq::Response) -> Result<wreq::Response, ClewdrError> {
+    let error_type = res
+        .headers()
+        .get("x-amzn-ErrorType")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(':').next().unwrap_or(v).to_string());
+    check_response(res, |aws| {
+        let message = aws["message"].as_str().or(aws["Message"].as_str());
+        (message.map(str::to_string), error_type)
+    })
+    .await
+}
+
+/// Re-encodes a Bedrock event stream as the SSE stream of the Anthropic API
+///
+/// Like `forward_response`, a stalled or broken upstream ends the stream with an SSE `error`
+/// event and a client falling behind drops the upstream.
+fn event_stream_to_sse(res: wreq::Response) -> Result<Response, ClewdrError> {
+    let idle = match CLEWDR_CONFIG.load().stream_idle_timeout {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+    let mut upstream = res.bytes_stream();
+    let stream = stream! {
+        let mut decoder = EventStreamDecoder::default();
+        loop {
+            let next = match idle {
+                Some(idle) => match tokio::time::timeout(idle, upstream.next()).await {
+                    Ok(next) => next,
+                    Err(_) => Some(Err(format!(
+                        "Upstream sent nothing for {}s",
+                        idle.as_secs()
+                    ))),
+                },
+                None => upstream.next().await,
+            }
+            .map(|r| r.map_err(|e| format!("Upstream stream failed: {e}")));
+            match next {
+                Some(Ok(bytes)) => {
+                    decoder.push(&bytes);
+                    while let Some(message) = decoder.next_message() {
+                        match message {
+                            Ok(message) => {
+                                if let Some(event) = message.to_sse() {
+                                    yield Ok::<_, std::io::Error>(event);
+                                }
+                            }
+                            Err(message) => {
+                                warn!("{}", message);
+                                yield Ok(sse_error(message));
+                                return;
+                            }
+                        }
+                    }
+                }
+                Some(Err(message)) => {
+                    warn!("{}", message);
+                    yield Ok(sse_error(&message));
+                    break;
+                }
+                None => break,
+            }
+        }
+    };
+    Ok(http::Response::builder()
+        .header(CONTENT_TYPE, "text/event-stream")
+        .body(Body::from_stream(backpressure::relay(stream, async {})))?)
+}
+
+#[async_trait::async_trait]
+impl LLMProvider for BedrockProvider {
+    type Request = AnthropicInvocation;
+    type Output = Response;
+
+    async fn invoke(&self, request: Self::Request) -> Result<Self::Output, ClewdrError> {
+        breaker::check(Provider::Bedrock)?;
+        let res = self.send(request).await;
+        breaker::record(Provider::Bedrock, &res);
+        if let Err(ref e) = res {
+            recent_errors::record(Provider::Bedrock, None, e);
+        }
+        res
+    }
+}
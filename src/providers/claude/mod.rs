@@ -1,18 +1,31 @@
 use std::{sync::Arc, time::Instant};
 
-use axum::response::Response;
+use axum::{
+    Extension,
+    body::Body,
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
 use colored::Colorize;
-use tracing::info;
+use serde_json::{Value, json};
+use tracing::{info, warn};
 
 use super::LLMProvider;
 use crate::{
     claude_code_state::ClaudeCodeState,
-    claude_web_state::ClaudeWebState,
+    claude_web_state::{ClaudeWebState, files::FileObject},
+    config::CLEWDR_CONFIG,
     error::ClewdrError,
-    middleware::claude::{ClaudeApiFormat, ClaudeContext},
-    services::cookie_actor::CookieActorHandle,
-    types::claude::CreateMessageParams,
-    utils::{enabled, print_out_json},
+    middleware::claude::{ClaudeApiFormat, ClaudeContext, merge_candidates},
+    services::{
+        breaker::{self, Provider},
+        cookie_actor::CookieActorHandle,
+    },
+    types::{
+        claude::{ContentBlock, CreateMessageParams, CreateMessageResponse, Message, Role},
+        oai::STRUCTURED_OUTPUT_TOOL,
+    },
+    utils::{enabled, json_schema, print_out_json, transcript},
 };
 
 #[derive(Clone, Copy)]
@@ -86,6 +99,146 @@ impl ClaudeProviders {
     }
 }
 
+/// Invokes a message request, fanning OpenAI requests with `n > 1` out to `n` parallel invocations
+///
+/// Every candidate is a separate upstream request with its own cookie. Their responses are
+/// merged into one OpenAI response with a choice per candidate, see [`merge_candidates`].
+///
+/// # Returns
+/// * `Err(ClewdrError::BadRequest)` - `n` exceeds `max_candidates`
+pub async fn invoke_candidates<P>(
+    provider: &P,
+    mut invocation: ClaudeInvocation,
+) -> Result<Response, ClewdrError>
+where
+    P: LLMProvider<Request = ClaudeInvocation, Output = ClaudeProviderResponse>,
+{
+    // Claude has no `n`, it is never sent upstream
+    let n = invocation.params.n.take().unwrap_or(1);
+    if n <= 1 || invocation.context.api_format() != ClaudeApiFormat::OpenAI {
+        let ClaudeProviderResponse { context, response } = provider.invoke(invocation).await?;
+        return Ok((Extension(context), response).into_response());
+    }
+    if n > CLEWDR_CONFIG.load().max_candidates {
+        return Err(ClewdrError::BadRequest {
+            msg: "n exceeds the configured max_candidates",
+        });
+    }
+    info!("[CANDIDATES] n: {}", n.to_string().green());
+    let candidates = (0..n).map(|_| {
+        provider.invoke(ClaudeInvocation {
+            params: invocation.params.to_owned(),
+            context: invocation.context.candidate(),
+            operation: invocation.operation,
+        })
+    });
+    // wait for all candidates, so none is dropped while holding a cookie
+    let responses = futures::future::join_all(candidates)
+        .await
+        .into_iter()
+        .map(|r| r.map(|r| (r.context, r.response)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(merge_candidates(responses).await)
+}
+
+/// Schema of the structured output tool, present when an OpenAI client asked for structured output
+fn structured_schema(params: &CreateMessageParams) -> Option<Value> {
+    params
+        .tools
+        .as_ref()?
+        .iter()
+        .find(|t| t.name == STRUCTURED_OUTPUT_TOOL)
+        .map(|t| t.input_schema.to_owned())
+}
+
+/// Replaces the structured output tool with a system instruction, Claude web ignores tools
+fn inline_structured_instruction(params: &mut CreateMessageParams, schema: &Value) {
+    if let Some(tools) = params.tools.as_mut() {
+        tools.retain(|t| t.name != STRUCTURED_OUTPUT_TOOL);
+    }
+    params.tool_choice = None;
+    let instruction = json!({ "type": "text", "text": json_schema::instruction(schema) });
+    params.system = Some(match params.system.take() {
+        Some(Value::String(text)) => json!([{ "type": "text", "text": text }, instruction]),
+        Some(Value::Array(mut blocks)) => {
+            blocks.push(instruction);
+            Value::Array(blocks)
+        }
+        _ => json!([instruction]),
+    });
+}
+
+/// Validates the structured output of a non-stream response
+///
+/// # Returns
+/// * The response, rebuilt from the buffered body
+/// * A follow-up request asking the model to repair its output, when the output is invalid
+async fn check_structured(
+    response: Response,
+    schema: &Value,
+    params: &CreateMessageParams,
+) -> Result<(Response, Option<CreateMessageParams>), ClewdrError> {
+    if !response.status().is_success() {
+        return Ok((response, None));
+    }
+    let (parts, body) = response.into_parts();
+    let bytes =
+        axum::body::to_bytes(body, usize::MAX)
+            .await
+            .map_err(|_| ClewdrError::UnexpectedNone {
+                msg: "Failed to read structured output response",
+            })?;
+    let response = Response::from_parts(parts, Body::from(bytes.to_owned()));
+    let Ok(parsed) = serde_json::from_slice::<CreateMessageResponse>(&bytes) else {
+        return Ok((response, None));
+    };
+    let tool_use = parsed.content.iter().find_map(|b| match b {
+        ContentBlock::ToolUse { id, name, input } if name == STRUCTURED_OUTPUT_TOOL => {
+            Some((id.to_owned(), input.to_owned()))
+        }
+        _ => None,
+    });
+    let output = match tool_use {
+        Some((_, ref input)) => Some(input.to_owned()),
+        None => {
+            let text = parsed
+                .content
+                .iter()
+                .filter_map(|b| match b {
+                    ContentBlock::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<String>();
+            json_schema::extract_json(&text)
+        }
+    };
+    let error = match output.map(|v| json_schema::validate(&v, schema)) {
+        Some(Ok(())) => return Ok((response, None)),
+        Some(Err(e)) => e,
+        None => "the response is not valid JSON".to_string(),
+    };
+    warn!(
+        "[STRUCTURED] invalid output, asking for a repair: {}",
+        error
+    );
+    let prompt = json_schema::repair_prompt(&error);
+    let mut repair = params.to_owned();
+    repair
+        .messages
+        .push(Message::new_blocks(Role::Assistant, parsed.content));
+    repair.messages.push(match tool_use {
+        Some((id, _)) => Message::new_blocks(
+            Role::User,
+            vec![ContentBlock::ToolResult {
+                tool_use_id: id,
+                content: json!(prompt),
+            }],
+        ),
+        None => Message::new_text(Role::User, prompt),
+    });
+    Ok((response, Some(repair)))
+}
+
 #[derive(Clone)]
 pub struct ClaudeWebProvider {
     shared: Arc<ClaudeSharedState>,
@@ -95,21 +248,36 @@ impl ClaudeWebProvider {
     fn new(shared: Arc<ClaudeSharedState>) -> Self {
         Self { shared }
     }
-}
 
-#[async_trait::async_trait]
-impl LLMProvider for ClaudeWebProvider {
-    type Request = ClaudeInvocation;
-    type Output = ClaudeProviderResponse;
+    /// Uploads a document through the files API
+    pub async fn upload_file(
+        &self,
+        name: String,
+        bytes: Bytes,
+        purpose: String,
+    ) -> Result<FileObject, ClewdrError> {
+        let mut state = ClaudeWebState::new(self.shared.cookie_actor_handle.clone());
+        info!(
+            "[FILE] name: {}, size: {}",
+            name.green(),
+            bytes.len().to_string().green()
+        );
+        state.create_file(name, bytes, purpose).await
+    }
 
-    async fn invoke(&self, request: Self::Request) -> Result<Self::Output, ClewdrError> {
+    async fn send(&self, request: ClaudeInvocation) -> Result<ClaudeProviderResponse, ClewdrError> {
         let mut state = ClaudeWebState::new(self.shared.cookie_actor_handle.clone());
         let stream = request.context.is_stream();
         state.api_format = request.context.api_format();
         state.stream = stream;
         state.usage = request.context.usage().to_owned();
+        state.conversation_id = request.context.conversation_id();
+        state.requested_org = request.context.requested_org();
+        state.cookie_tags = request.context.cookie_tags();
+        state.priority = request.context.priority();
+        state.custom_prompt = request.context.custom_prompt();
         let ClaudeInvocation {
-            params,
+            mut params,
             context,
             operation,
         } = request;
@@ -130,9 +298,24 @@ impl LLMProvider for ClaudeWebProvider {
             enabled(params.thinking.is_some()),
             format_display
         );
+        let schema = structured_schema(&params);
+        if let Some(ref schema) = schema {
+            inline_structured_instruction(&mut params, schema);
+        }
         print_out_json(&params, "claude_web_client_req.json");
+        let record = transcript::begin("claude_web", &params, context.usage().input_tokens);
         let stopwatch = Instant::now();
-        let response = state.try_chat(params).await?;
+        let response = match schema.filter(|_| !stream) {
+            Some(schema) => {
+                let response = state.try_chat(params.to_owned()).await?;
+                match check_structured(response, &schema, &params).await? {
+                    (_, Some(repair)) => state.try_chat(repair).await?,
+                    (response, None) => response,
+                }
+            }
+            None => state.try_chat(params).await?,
+        };
+        let response = transcript::attach(record, stream, response);
         let elapsed = stopwatch.elapsed();
         info!(
             "[FIN] elapsed: {}s",
@@ -142,6 +325,19 @@ impl LLMProvider for ClaudeWebProvider {
     }
 }
 
+#[async_trait::async_trait]
+impl LLMProvider for ClaudeWebProvider {
+    type Request = ClaudeInvocation;
+    type Output = ClaudeProviderResponse;
+
+    async fn invoke(&self, request: Self::Request) -> Result<Self::Output, ClewdrError> {
+        breaker::check(Provider::ClaudeWeb)?;
+        let res = self.send(request).await;
+        breaker::record(Provider::ClaudeWeb, &res);
+        res
+    }
+}
+
 #[derive(Clone)]
 pub struct ClaudeCodeProvider {
     shared: Arc<ClaudeSharedState>,
@@ -151,18 +347,16 @@ impl ClaudeCodeProvider {
     fn new(shared: Arc<ClaudeSharedState>) -> Self {
         Self { shared }
     }
-}
-
-#[async_trait::async_trait]
-impl LLMProvider for ClaudeCodeProvider {
-    type Request = ClaudeInvocation;
-    type Output = ClaudeProviderResponse;
 
-    async fn invoke(&self, request: Self::Request) -> Result<Self::Output, ClewdrError> {
+    async fn send(&self, request: ClaudeInvocation) -> Result<ClaudeProviderResponse, ClewdrError> {
         let mut state = ClaudeCodeState::new(self.shared.cookie_actor_handle.clone());
         state.api_format = request.context.api_format();
         state.stream = request.context.is_stream();
         state.system_prompt_hash = request.context.system_prompt_hash();
+        state.cookie_tags = request.context.cookie_tags();
+        state.priority = request.context.priority();
+        state.anthropic_version = request.context.anthropic_version();
+        state.anthropic_betas = request.context.anthropic_betas();
         state.usage = request.context.usage().to_owned();
         let ClaudeInvocation {
             params,
@@ -183,8 +377,20 @@ impl LLMProvider for ClaudeCodeProvider {
                     format_display
                 );
                 print_out_json(&params, "claude_code_client_req.json");
+                let record =
+                    transcript::begin("claude_code", &params, context.usage().input_tokens);
                 let stopwatch = Instant::now();
-                let response = state.try_chat(params).await?;
+                let response = match structured_schema(&params).filter(|_| !state.stream) {
+                    Some(schema) => {
+                        let response = state.try_chat(params.to_owned()).await?;
+                        match check_structured(response, &schema, &params).await? {
+                            (_, Some(repair)) => state.try_chat(repair).await?,
+                            (response, None) => response,
+                        }
+                    }
+                    None => state.try_chat(params).await?,
+                };
+                let response = transcript::attach(record, state.stream, response);
                 let elapsed = stopwatch.elapsed();
                 info!(
                     "[FIN] elapsed: {}s",
@@ -211,6 +417,19 @@ impl LLMProvider for ClaudeCodeProvider {
     }
 }
 
+#[async_trait::async_trait]
+impl LLMProvider for ClaudeCodeProvider {
+    type Request = ClaudeInvocation;
+    type Output = ClaudeProviderResponse;
+
+    async fn invoke(&self, request: Self::Request) -> Result<Self::Output, ClewdrError> {
+        breaker::check(Provider::ClaudeCode)?;
+        let res = self.send(request).await;
+        breaker::record(Provider::ClaudeCode, &res);
+        res
+    }
+}
+
 pub fn build_providers(cookie_actor_handle: CookieActorHandle) -> ClaudeProviders {
     ClaudeProviders::new(cookie_actor_handle)
 }
@@ -14,6 +14,7 @@ use axum::{
 use bytes::Bytes;
 use colored::Colorize;
 use futures::{FutureExt, Stream, StreamExt, pin_mut};
+use serde_json::{Value, json};
 use snafu::{GenerateImplicitData, Location};
 use tokio::select;
 use tracing::info;
@@ -21,12 +22,21 @@ use yup_oauth2::ServiceAccountKey;
 
 use super::LLMProvider;
 use crate::{
-    config::CLEWDR_CONFIG,
+    config::{CLEWDR_CONFIG, TimeoutPhase},
     error::ClewdrError,
-    gemini_state::{GeminiApiFormat, GeminiState},
+    gemini_state::{
+        GeminiApiFormat, GeminiState,
+        files::{self, GeminiUpload},
+    },
     middleware::gemini::GeminiContext,
-    services::key_actor::KeyActorHandle,
-    types::{gemini::request::GeminiRequestBody, oai::CreateMessageParams},
+    services::{
+        breaker::{self, Provider},
+        key_actor::KeyActorHandle,
+    },
+    types::{
+        gemini::{image::ImageGenerationRequest, request::GeminiRequestBody},
+        oai::CreateMessageParams,
+    },
     utils::enabled,
 };
 
@@ -34,6 +44,10 @@ use crate::{
 pub enum GeminiPayload {
     Native(GeminiRequestBody),
     OpenAI(CreateMessageParams),
+    /// `countTokens` and `models` calls, forwarded verbatim
+    Auxiliary(Option<Value>),
+    /// OpenAI images API, mapped to Gemini image generation
+    Image(ImageGenerationRequest),
 }
 
 #[derive(Clone)]
@@ -79,14 +93,8 @@ impl GeminiAiStudioProvider {
         state.update_from_ctx(ctx);
         state
     }
-}
-
-#[async_trait::async_trait]
-impl LLMProvider for GeminiAiStudioProvider {
-    type Request = GeminiInvocation;
-    type Output = Response;
 
-    async fn invoke(&self, request: Self::Request) -> Result<Self::Output, ClewdrError> {
+    async fn send(&self, request: GeminiInvocation) -> Result<Response, ClewdrError> {
         if request.context.vertex {
             return Err(ClewdrError::BadRequest {
                 msg: "Vertex request routed to AI Studio provider",
@@ -96,8 +104,10 @@ impl LLMProvider for GeminiAiStudioProvider {
         let mut state = self.build_state(&request.context);
         match request.payload {
             GeminiPayload::Native(body) => {
+                // uploaded files only work with the key that uploaded them
+                state.file_key = files::request_file_owner(&body);
                 if !request.context.stream {
-                    let stream = keep_alive_stream(state, body);
+                    let stream = keep_alive_stream(state, body, Provider::GeminiAiStudio);
                     return Response::builder()
                         .header("Content-Type", "application/json")
                         .body(Body::from_stream(stream))
@@ -108,9 +118,48 @@ impl LLMProvider for GeminiAiStudioProvider {
                 }
                 state.try_chat(body).await
             }
+            GeminiPayload::OpenAI(body) if !body.grounding.is_empty() => {
+                state.try_grounded(body).await
+            }
             GeminiPayload::OpenAI(body) => state.try_chat(body).await,
+            GeminiPayload::Auxiliary(body) => {
+                state.file_key = files::file_owner(&request.context.path);
+                state.send_auxiliary(body).await
+            }
+            GeminiPayload::Image(body) => state.send_image(body).await,
         }
     }
+
+    /// Forwards a request of a Files API resumable upload
+    pub async fn upload(
+        &self,
+        ctx: &GeminiContext,
+        upload: GeminiUpload,
+    ) -> Result<Response, ClewdrError> {
+        breaker::check(Provider::GeminiAiStudio)?;
+        let mut state = self.build_state(ctx);
+        let res = state.upload_file(upload).await;
+        breaker::record(Provider::GeminiAiStudio, &res);
+        res
+    }
+}
+
+#[async_trait::async_trait]
+impl LLMProvider for GeminiAiStudioProvider {
+    type Request = GeminiInvocation;
+    type Output = Response;
+
+    async fn invoke(&self, request: Self::Request) -> Result<Self::Output, ClewdrError> {
+        breaker::check(Provider::GeminiAiStudio)?;
+        // non-stream native calls report from their keep-alive stream
+        let deferred =
+            matches!(request.payload, GeminiPayload::Native(_)) && !request.context.stream;
+        let res = self.send(request).await;
+        if !deferred {
+            breaker::record(Provider::GeminiAiStudio, &res);
+        }
+        res
+    }
 }
 
 pub struct GeminiVertexProvider {
@@ -137,14 +186,8 @@ impl GeminiVertexProvider {
         state.vertex_credential = Some(credential);
         Ok(state)
     }
-}
 
-#[async_trait::async_trait]
-impl LLMProvider for GeminiVertexProvider {
-    type Request = GeminiInvocation;
-    type Output = Response;
-
-    async fn invoke(&self, request: Self::Request) -> Result<Self::Output, ClewdrError> {
+    async fn send(&self, request: GeminiInvocation) -> Result<Response, ClewdrError> {
         if !request.context.vertex {
             return Err(ClewdrError::BadRequest {
                 msg: "AI Studio request routed to Vertex provider",
@@ -155,7 +198,7 @@ impl LLMProvider for GeminiVertexProvider {
         match request.payload {
             GeminiPayload::Native(body) => {
                 if !request.context.stream {
-                    let stream = keep_alive_stream(state, body);
+                    let stream = keep_alive_stream(state, body, Provider::Vertex);
                     return Response::builder()
                         .header("Content-Type", "application/json")
                         .body(Body::from_stream(stream))
@@ -166,18 +209,44 @@ impl LLMProvider for GeminiVertexProvider {
                 }
                 state.try_chat(body).await
             }
+            GeminiPayload::OpenAI(body) if !body.grounding.is_empty() => {
+                state.try_grounded(body).await
+            }
             GeminiPayload::OpenAI(body) => state.try_chat(body).await,
+            GeminiPayload::Auxiliary(body) => state.send_auxiliary(body).await,
+            GeminiPayload::Image(_) => Err(ClewdrError::BadRequest {
+                msg: "Image generation is not supported on Vertex",
+            }),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl LLMProvider for GeminiVertexProvider {
+    type Request = GeminiInvocation;
+    type Output = Response;
+
+    async fn invoke(&self, request: Self::Request) -> Result<Self::Output, ClewdrError> {
+        breaker::check(Provider::Vertex)?;
+        // non-stream native calls report from their keep-alive stream
+        let deferred =
+            matches!(request.payload, GeminiPayload::Native(_)) && !request.context.stream;
+        let res = self.send(request).await;
+        if !deferred {
+            breaker::record(Provider::Vertex, &res);
         }
+        res
     }
 }
 
+/// Round robin over the configured Vertex credentials
 #[derive(Default)]
-struct VertexCredentialPool {
+pub(crate) struct VertexCredentialPool {
     cursor: AtomicUsize,
 }
 
 impl VertexCredentialPool {
-    fn next(&self) -> Option<ServiceAccountKey> {
+    pub(crate) fn next(&self) -> Option<ServiceAccountKey> {
         let creds = CLEWDR_CONFIG.load().vertex.credential_list();
         if creds.is_empty() {
             return None;
@@ -202,21 +271,28 @@ fn log_request(ctx: &GeminiContext) {
     );
 }
 
+/// Longest non-stream native request in seconds when `timeouts.total` is unset
+const DEFAULT_KEEP_ALIVE_TIMEOUT: u64 = 360;
+
 fn keep_alive_stream<T>(
     mut state: GeminiState,
     body: T,
+    provider: Provider,
 ) -> impl Stream<Item = Result<Bytes, axum::Error>>
 where
     T: serde::Serialize + Clone + Send + 'static,
 {
     let mut interval = tokio::time::interval(Duration::from_secs(15));
-    let timeout = Duration::from_secs(360);
+    // unset totals keep the former limit, 0 lifts it
+    let timeout = match state.timeouts.total {
+        None => Some(Duration::from_secs(DEFAULT_KEEP_ALIVE_TIMEOUT)),
+        Some(_) => state.timeouts.get(TimeoutPhase::Total),
+    };
     stream! {
         let future = async move {
-            state
-                .try_chat(body.clone())
-                .await
-                .unwrap_or_else(|e| e.into_response())
+            let res = state.try_chat(body.clone()).await;
+            breaker::record(provider, &res);
+            res.unwrap_or_else(|e| e.into_response())
                 .into_body()
                 .into_data_stream()
         };
@@ -237,7 +313,19 @@ where
                     }
                 }
                 _ = interval.tick() => {
-                    if start.elapsed() > timeout {
+                    if timeout.is_some_and(|t| start.elapsed() > t) {
+                        // the status is already sent, so the error goes in the body
+                        let error = json!({
+                            "error": {
+                                "code": 504,
+                                "message": ClewdrError::UpstreamTimeout {
+                                    phase: TimeoutPhase::Total,
+                                }
+                                .to_string(),
+                                "status": "DEADLINE_EXCEEDED",
+                            }
+                        });
+                        yield Ok(Bytes::from(error.to_string()));
                         break;
                     }
                     yield Ok(Bytes::from("\n"));
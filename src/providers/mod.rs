@@ -2,8 +2,12 @@ use async_trait::async_trait;
 
 use crate::error::ClewdrError;
 
+pub mod bedrock;
 pub mod claude;
 pub mod gemini;
+pub mod openai_upstream;
+pub mod passthrough;
+pub mod vertex_claude;
 
 #[async_trait]
 pub trait LLMProvider: Send + Sync {
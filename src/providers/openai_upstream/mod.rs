@@ -0,0 +1,335 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::response::Response;
+use colored::Colorize;
+use http::header::CONTENT_TYPE;
+use serde_json::{Value, json};
+use snafu::ResultExt;
+use tokio::spawn;
+use tracing::{error, info};
+use wreq::{Client, header::AUTHORIZATION};
+
+use super::LLMProvider;
+use crate::{
+    config::{CLEWDR_CONFIG, KeyStatus, OpenAiUpstream, ProxyTarget, Timeouts},
+    error::{ClewdrError, WreqSnafu},
+    gemini_state::usage::{forward_stream_with_usage, parse_usage},
+    middleware::{proxy::current_proxy, timeout::current_timeouts, timeout::first_byte},
+    services::{
+        breaker::{self, Provider},
+        capture::CaptureExt,
+        client_pool::{self, ClientKind},
+        key_actor::{KeyActorHandle, KeyUsage},
+        key_prober, recent_errors,
+        retry::{Failure, RetryPolicy},
+    },
+    utils::{enabled, forward_response},
+};
+
+/// Key pools of the configured upstreams, by name
+///
+/// Upstreams are read from the config once at startup, so adding or removing one applies on
+/// restart. Keyless upstreams have no pool.
+#[derive(Clone, Default)]
+pub struct OpenAiUpstreamRegistry(Arc<HashMap<String, KeyActorHandle>>);
+
+impl OpenAiUpstreamRegistry {
+    /// Starts the key actor and prober of every configured upstream with keys
+    ///
+    /// An upstream whose actor fails to start is left out, its requests fail with no key.
+    pub async fn start() -> Self {
+        let names = CLEWDR_CONFIG
+            .load()
+            .openai_upstreams
+            .iter()
+            .filter(|u| !u.is_keyless())
+            .map(|u| u.name.to_owned())
+            .collect::<Vec<_>>();
+        let mut pools = HashMap::new();
+        for name in names {
+            let handle = match KeyActorHandle::start_upstream(&name).await {
+                Ok(handle) => handle,
+                Err(e) => {
+                    error!("Failed to start key pool of upstream {}: {}", name, e);
+                    continue;
+                }
+            };
+            let _key_probe = key_prober::spawn_upstream(handle.clone(), name.to_owned());
+            pools.insert(name, handle);
+        }
+        Self(Arc::new(pools))
+    }
+
+    pub fn get(&self, name: &str) -> Option<KeyActorHandle> {
+        self.0.get(name).cloned()
+    }
+}
+
+/// Request passed through to an OpenAI compatible upstream
+pub struct OpenAiUpstreamInvocation {
+    /// Name of the upstream
+    pub upstream: String,
+    /// Path below the API root, e.g. `chat/completions`
+    pub path: String,
+    pub body: Value,
+}
+
+/// OpenAI compatible upstreams, called with the keys of their pools
+#[derive(Clone, Default)]
+pub struct OpenAiUpstreamProvider {
+    pools: OpenAiUpstreamRegistry,
+}
+
+impl OpenAiUpstreamProvider {
+    pub fn new(pools: OpenAiUpstreamRegistry) -> Self {
+        Self { pools }
+    }
+
+    /// Key pool of `name`, `None` when the upstream is called without keys
+    fn pool(&self, upstream: &OpenAiUpstream) -> Result<Option<KeyActorHandle>, ClewdrError> {
+        if upstream.is_keyless() {
+            return Ok(None);
+        }
+        self.pools
+            .get(&upstream.name)
+            .map(Some)
+            .ok_or(ClewdrError::NoKeyAvailable)
+    }
+
+    async fn send(&self, request: OpenAiUpstreamInvocation) -> Result<Response, ClewdrError> {
+        let OpenAiUpstreamInvocation {
+            upstream,
+            path,
+            mut body,
+        } = request;
+        let Some(upstream) = CLEWDR_CONFIG.load().openai_upstream(&upstream).cloned() else {
+            return Err(ClewdrError::PathNotFound {
+                msg: format!("Unknown upstream: {upstream}"),
+            });
+        };
+        let Some(fields) = body.as_object_mut() else {
+            return Err(ClewdrError::BadRequest {
+                msg: "Request body must be a JSON object",
+            });
+        };
+        let stream = fields.get("stream").and_then(Value::as_bool) == Some(true);
+        if let Some(model) = fields.get("model").and_then(Value::as_str) {
+            let model = upstream.upstream_model(model).to_string();
+            info!(
+                "[REQ] stream: {}, upstream: {}, model: {}",
+                enabled(stream),
+                upstream.name.green(),
+                model.green()
+            );
+            fields.insert("model".to_string(), json!(model));
+        }
+        // streams only report usage when asked to
+        if stream && !fields.contains_key("stream_options") {
+            fields.insert(
+                "stream_options".to_string(),
+                json!({ "include_usage": true }),
+            );
+        }
+        let timeouts = current_timeouts(ProxyTarget::OpenAiUpstream);
+        let state = UpstreamState {
+            key_handle: self.pool(&upstream)?,
+            client: client_pool::client(
+                ClientKind::OpenAiUpstream,
+                current_proxy(ProxyTarget::OpenAiUpstream),
+                &timeouts,
+            )?,
+            upstream,
+            key: None,
+            stream,
+            timeouts,
+        };
+        let (path, body) = (&path, &body);
+        RetryPolicy::from_config()
+            .surface_last_error()
+            .executor(UpstreamState::classify, UpstreamState::report_failure)
+            .run(&state, |mut state| async move {
+                let res = state.try_send(path, body).await.inspect_err(|e| {
+                    let key = state.key.as_ref().map(|k| k.key.ellipse());
+                    match &key {
+                        Some(key) => error!("[{}] {}", key.green(), e),
+                        None => error!("{}", e),
+                    }
+                    recent_errors::record(Provider::OpenAiUpstream, key, e);
+                });
+                (state, res)
+            })
+            .await
+    }
+}
+
+/// State of one attempt, with the key it uses
+#[derive(Clone)]
+struct UpstreamState {
+    upstream: OpenAiUpstream,
+    key_handle: Option<KeyActorHandle>,
+    key: Option<KeyStatus>,
+    client: Client,
+    stream: bool,
+    timeouts: Timeouts,
+}
+
+impl UpstreamState {
+    async fn try_send(&mut self, path: &str, body: &Value) -> Result<Response, ClewdrError> {
+        if let Some(handle) = &self.key_handle {
+            let key = handle.request().await?;
+            info!("[KEY] {}", key.key.ellipse().green());
+            self.key = Some(key);
+        }
+        let mut req = self.client.post(self.upstream.url(path)).json(body);
+        if let Some(version) = &self.upstream.api_version {
+            req = req.query(&[("api-version", version)]);
+        }
+        if let Some(key) = &self.key {
+            req = if self.upstream.azure {
+                req.header("api-key", key.key.inner.as_str())
+            } else {
+                req.header(AUTHORIZATION, format!("Bearer {}", key.key))
+            };
+        }
+        let res = first_byte(&self.timeouts, async {
+            req.send_captured().await.context(WreqSnafu {
+                msg: "Failed to send request to OpenAI compatible upstream",
+            })
+        })
+        .await?;
+        let res = check_upstream(res).await?;
+        if self.stream {
+            return match self.key_handle.to_owned() {
+                Some(handle) => forward_stream_with_usage(res, handle, self.key.to_owned()),
+                None => forward_response(res),
+            };
+        }
+        let content_type = res.headers().get(CONTENT_TYPE).cloned();
+        let bytes = res.bytes().await.context(WreqSnafu {
+            msg: "Failed to read response of OpenAI compatible upstream",
+        })?;
+        if let Some(usage) = serde_json::from_slice::<Value>(&bytes)
+            .ok()
+            .as_ref()
+            .and_then(parse_usage)
+        {
+            self.record_usage(usage).await;
+        }
+        let mut res = Response::builder();
+        if let Some(content_type) = content_type {
+            res = res.header(CONTENT_TYPE, content_type);
+        }
+        Ok(res.body(bytes.into())?)
+    }
+
+    /// Reports token usage or a 429 of the current key to its pool
+    async fn record_usage(&self, usage: KeyUsage) {
+        if let (Some(handle), Some(key)) = (&self.key_handle, self.key.to_owned())
+            && let Err(e) = handle.record_usage(key, usage).await
+        {
+            error!("Failed to record key usage: {}", e);
+        }
+    }
+
+    /// Rate limits, refused keys and server errors are retried with another key
+    fn classify(e: &ClewdrError) -> Failure {
+        match e {
+            ClewdrError::UpstreamHttpError { code, .. }
+                if code.is_server_error() || matches!(code.as_u16(), 401 | 403 | 429) =>
+            {
+                Failure::Retry
+            }
+            _ => Failure::Abort,
+        }
+    }
+
+    /// Reports a rate limited or refused key to its pool
+    fn report_failure(self, e: &ClewdrError) -> impl Future<Output = ()> + use<> {
+        let code = match e {
+            ClewdrError::UpstreamHttpError { code, .. } => code.as_u16(),
+            _ => 0,
+        };
+        async move {
+            if code == 429 {
+                self.record_usage(KeyUsage {
+                    rate_limited: true,
+                    ..Default::default()
+                })
+                .await;
+            }
+            if matches!(code, 401 | 403)
+                && let (Some(handle), Some(mut key)) = (self.key_handle, self.key)
+            {
+                key.count_403 += 1;
+                spawn(async move {
+                    handle.return_key(key).await.unwrap_or_else(|e| {
+                        error!("Failed to report 403: {}", e);
+                    });
+                });
+            }
+        }
+    }
+}
+
+/// Turns a failed upstream response into an error carrying its body
+async fn check_upstream(res: wreq::Response) -> Result<wreq::Response, ClewdrError> {
+    let code = res.status();
+    if code.is_success() {
+        return Ok(res);
+    }
+    let text = res.text().await.unwrap_or_default();
+    let inner = serde_json::from_str::<Value>(&text).unwrap_or_else(|_| {
+        json!({
+            "error": {
+                "message": text,
+                "type": "upstream_error",
+                "code": code.as_u16(),
+            }
+        })
+    });
+    Err(ClewdrError::UpstreamHttpError { code, inner })
+}
+
+/// Checks a key with a `models` call, the cheapest authenticated call
+///
+/// # Returns
+/// * `Err(ClewdrError::UpstreamHttpError)` - The upstream refused the key
+pub async fn check_key(name: &str, key: &KeyStatus) -> Result<(), ClewdrError> {
+    let Some(upstream) = CLEWDR_CONFIG.load().openai_upstream(name).cloned() else {
+        return Err(ClewdrError::PathNotFound {
+            msg: format!("Unknown upstream: {name}"),
+        });
+    };
+    let client = client_pool::client(
+        ClientKind::OpenAiUpstream,
+        current_proxy(ProxyTarget::OpenAiUpstream),
+        &current_timeouts(ProxyTarget::OpenAiUpstream),
+    )?;
+    let mut req = client.get(upstream.url("models"));
+    if let Some(version) = &upstream.api_version {
+        req = req.query(&[("api-version", version)]);
+    }
+    req = if upstream.azure {
+        req.header("api-key", key.key.inner.as_str())
+    } else {
+        req.header(AUTHORIZATION, format!("Bearer {}", key.key))
+    };
+    let res = req.send().await.context(WreqSnafu {
+        msg: "Failed to send key check to OpenAI compatible upstream",
+    })?;
+    check_upstream(res).await?;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl LLMProvider for OpenAiUpstreamProvider {
+    type Request = OpenAiUpstreamInvocation;
+    type Output = Response;
+
+    async fn invoke(&self, request: Self::Request) -> Result<Self::Output, ClewdrError> {
+        breaker::check(Provider::OpenAiUpstream)?;
+        let res = self.send(request).await;
+        breaker::record(Provider::OpenAiUpstream, &res);
+        res
+    }
+}
@@ -0,0 +1,85 @@
+use serde_json::{Map, Value, json};
+
+use crate::error::{ClaudeError, ClaudeErrorBody, ClewdrError};
+
+/// Anthropic API request body, passed nearly verbatim to a cloud hosting Claude
+pub struct AnthropicInvocation {
+    pub body: Value,
+    /// Client `anthropic-beta` flags let through by the `anthropic_betas` allowlist
+    pub betas: Vec<String>,
+}
+
+/// Fields of a passthrough body, with the model and streaming mode read from them
+pub(super) struct PassthroughBody<'a> {
+    pub fields: &'a mut Map<String, Value>,
+    pub model: String,
+    pub stream: bool,
+}
+
+impl<'a> PassthroughBody<'a> {
+    /// # Returns
+    /// * `Err(BadRequest)` - The body is not an object or has no `model`
+    pub fn parse(body: &'a mut Value) -> Result<Self, ClewdrError> {
+        let Some(fields) = body.as_object_mut() else {
+            return Err(ClewdrError::BadRequest {
+                msg: "Request body must be a JSON object",
+            });
+        };
+        let Some(model) = fields
+            .get("model")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+        else {
+            return Err(ClewdrError::BadRequest {
+                msg: "model is required",
+            });
+        };
+        let stream = fields.get("stream").and_then(Value::as_bool) == Some(true);
+        Ok(Self {
+            fields,
+            model,
+            stream,
+        })
+    }
+
+    /// Sets the `anthropic_version` the cloud expects in the body, unless the client sent one
+    pub fn default_version(&mut self, version: &str) {
+        self.fields
+            .entry("anthropic_version")
+            .or_insert(json!(version));
+    }
+}
+
+/// Turns a failed response of a cloud hosting Claude into a Claude error
+///
+/// Errors of the model come in the Anthropic shape, errors of the cloud itself in its own.
+///
+/// # Arguments
+/// * `cloud_error` - Message and type of an error in the cloud's shape
+pub(super) async fn check_response(
+    res: wreq::Response,
+    cloud_error: impl FnOnce(&Value) -> (Option<String>, Option<String>),
+) -> Result<wreq::Response, ClewdrError> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+    let text = res.text().await.unwrap_or_default();
+    let inner = match serde_json::from_str::<ClaudeError>(&text) {
+        Ok(err) => err.error,
+        Err(_) => {
+            let (message, r#type) =
+                cloud_error(&serde_json::from_str::<Value>(&text).unwrap_or_default());
+            ClaudeErrorBody {
+                message: message
+                    .map_or_else(|| json!(format!("Unknown error: {text}")), |m| json!(m)),
+                r#type: r#type.unwrap_or_else(|| "api_error".to_string()),
+                code: Some(status.as_u16()),
+            }
+        }
+    };
+    Err(ClewdrError::ClaudeHttpError {
+        code: status,
+        inner,
+    })
+}
@@ -0,0 +1,147 @@
+use axum::response::Response;
+use colored::Colorize;
+use snafu::ResultExt;
+use tracing::info;
+use wreq::header::AUTHORIZATION;
+
+use super::{
+    LLMProvider,
+    gemini::VertexCredentialPool,
+    passthrough::{AnthropicInvocation, PassthroughBody, check_response},
+};
+use crate::{
+    config::{CLEWDR_CONFIG, ProxyTarget},
+    error::{ClewdrError, WreqSnafu},
+    gemini_state::get_token,
+    middleware::{proxy::current_proxy, timeout::current_timeouts, timeout::first_byte},
+    services::{
+        breaker::{self, Provider},
+        capture::CaptureExt,
+        client_pool::{self, ClientKind},
+        recent_errors,
+    },
+    utils::{enabled, forward_response},
+};
+
+/// `anthropic_version` Vertex expects in the body instead of the header
+const VERTEX_ANTHROPIC_VERSION: &str = "vertex-2023-10-16";
+
+/// Operation of the Anthropic API served through Vertex
+#[derive(Clone, Copy)]
+pub enum VertexClaudeOperation {
+    Messages,
+    CountTokens,
+}
+
+/// Anthropic request passed to Vertex, for one of the operations Vertex serves
+pub struct VertexClaudeInvocation {
+    pub invocation: AnthropicInvocation,
+    pub operation: VertexClaudeOperation,
+}
+
+/// Claude models hosted by Vertex AI, called with the configured service accounts
+#[derive(Default)]
+pub struct VertexClaudeProvider {
+    credentials: VertexCredentialPool,
+}
+
+impl VertexClaudeProvider {
+    /// URL of a model method, `global` is served from the unprefixed host
+    fn url(project: &str, model: &str, method: &str) -> String {
+        let location = CLEWDR_CONFIG
+            .load()
+            .vertex
+            .claude_location
+            .to_owned()
+            .unwrap_or_else(|| "global".to_string());
+        let host = if location == "global" {
+            "aiplatform.googleapis.com".to_string()
+        } else {
+            format!("{location}-aiplatform.googleapis.com")
+        };
+        format!(
+            "https://{host}/v1/projects/{project}/locations/{location}/publishers/anthropic/models/{model}:{method}"
+        )
+    }
+
+    async fn send(&self, request: VertexClaudeInvocation) -> Result<Response, ClewdrError> {
+        let VertexClaudeInvocation {
+            invocation: AnthropicInvocation { mut body, betas },
+            operation,
+        } = request;
+        let mut passthrough = PassthroughBody::parse(&mut body)?;
+        let (model, stream) = (passthrough.model.to_owned(), passthrough.stream);
+        passthrough.default_version(VERTEX_ANTHROPIC_VERSION);
+        let cred = self.credentials.next().ok_or(ClewdrError::BadRequest {
+            msg: "Vertex credential not found",
+        })?;
+        let project = cred.project_id.to_owned().unwrap_or_default();
+        let url = match operation {
+            VertexClaudeOperation::Messages => {
+                info!(
+                    "[REQ] stream: {}, vertex: {}, format: {}, model: {}",
+                    enabled(stream),
+                    enabled(true),
+                    "Claude".green(),
+                    model.green()
+                );
+                // the model goes in the URL, the API version in the body
+                passthrough.fields.remove("model");
+                let method = if stream {
+                    "streamRawPredict"
+                } else {
+                    "rawPredict"
+                };
+                Self::url(&project, &model, method)
+            }
+            VertexClaudeOperation::CountTokens => {
+                passthrough.fields.remove("stream");
+                Self::url(&project, "count-tokens", "rawPredict")
+            }
+        };
+
+        let timeouts = current_timeouts(ProxyTarget::Vertex);
+        let client = client_pool::client(
+            ClientKind::Gemini,
+            current_proxy(ProxyTarget::Vertex),
+            &timeouts,
+        )?;
+        let access_token = get_token(cred).await?;
+        let mut req = client
+            .post(url)
+            .header(AUTHORIZATION, format!("Bearer {access_token}"))
+            .json(&body);
+        if !betas.is_empty() {
+            req = req.header("anthropic-beta", betas.join(","));
+        }
+        let res = first_byte(&timeouts, async {
+            req.send_captured().await.context(WreqSnafu {
+                msg: "Failed to send request to Vertex Claude API",
+            })
+        })
+        .await?;
+        // errors of Vertex itself come in the Google shape
+        let res = check_response(res, |google| {
+            let field = |name: &str| google["error"][name].as_str().map(str::to_string);
+            (field("message"), field("status"))
+        })
+        .await?;
+        forward_response(res)
+    }
+}
+
+#[async_trait::async_trait]
+impl LLMProvider for VertexClaudeProvider {
+    type Request = VertexClaudeInvocation;
+    type Output = Response;
+
+    async fn invoke(&self, request: Self::Request) -> Result<Self::Output, ClewdrError> {
+        breaker::check(Provider::VertexClaude)?;
+        let res = self.send(request).await;
+        breaker::record(Provider::VertexClaude, &res);
+        if let Err(ref e) = res {
+            recent_errors::record(Provider::VertexClaude, None, e);
+        }
+        res
+    }
+}
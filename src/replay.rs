@@ -0,0 +1,209 @@
+//! Replays recorded upstream event streams through the response transformers, offline
+//!
+//! A fixture is a stream recorded from an upstream, named `<name>.<format>.sse` where the
+//! format is `claude` (Claude API), `claude-web` (Claude.ai) or `gemini` (the OpenAI compatible
+//! Gemini endpoint). Next to it, `<name>.<format>.sse.golden` holds the OpenAI format stream
+//! ClewdR sends to clients for it, so transformer changes can be checked without live accounts.
+//! Goldens are written with the default config, `check` pins it so the local config file and
+//! `CLEWDR_` variables, with settings like `extract_artifacts`, cannot change them.
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::{
+    body::{Body, to_bytes},
+    response::{IntoResponse, Response, Sse},
+};
+use eventsource_stream::Eventsource;
+use futures::{TryStreamExt, stream};
+use http::header::CONTENT_TYPE;
+use serde_json::Value;
+
+use crate::{
+    claude_web_state::artifacts::ArtifactStream,
+    config::{CLEWDR_CONFIG, ClewdrConfig},
+    error::ClewdrError,
+    middleware::{claude::transform_stream, rules::apply_response_rules},
+};
+
+/// Upstream a fixture was recorded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixtureFormat {
+    Claude,
+    ClaudeWeb,
+    Gemini,
+}
+
+impl FixtureFormat {
+    /// Format of a fixture from its `<name>.<format>.sse` file name
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.strip_suffix(".sse")?;
+        match name.rsplit_once('.')?.1 {
+            "claude" => Some(Self::Claude),
+            "claude-web" => Some(Self::ClaudeWeb),
+            "gemini" => Some(Self::Gemini),
+            _ => None,
+        }
+    }
+}
+
+fn fixture_error(message: String) -> ClewdrError {
+    ClewdrError::Whatever {
+        message,
+        source: None,
+    }
+}
+
+/// Golden file holding the expected output of `fixture`
+pub fn golden_path(fixture: &Path) -> PathBuf {
+    let mut name = fixture.as_os_str().to_owned();
+    name.push(".golden");
+    PathBuf::from(name)
+}
+
+/// OpenAI format stream ClewdR sends for the recorded upstream stream `input`
+///
+/// Claude and Claude.ai streams go through the same conversion as `/v1/chat/completions`,
+/// Claude.ai artifacts are extracted when `extract_artifacts` is set. Gemini streams are
+/// already in OpenAI format and only go through the `response_rules`.
+pub async fn replay(format: FixtureFormat, input: Vec<u8>) -> Result<String, ClewdrError> {
+    let res = match format {
+        FixtureFormat::Claude => {
+            let events = Body::from(input).into_data_stream().eventsource();
+            let coalesce = CLEWDR_CONFIG.load().coalesce_tool_json;
+            Sse::new(transform_stream(events, 0, coalesce)).into_response()
+        }
+        FixtureFormat::ClaudeWeb => {
+            let mut artifacts = CLEWDR_CONFIG
+                .load()
+                .extract_artifacts
+                .then(ArtifactStream::default);
+            let events = Body::from(input)
+                .into_data_stream()
+                .eventsource()
+                .map_ok(move |event| match artifacts.as_mut() {
+                    Some(artifacts) => artifacts.relay(event),
+                    None => vec![event],
+                })
+                .map_ok(|events| stream::iter(events.into_iter().map(Ok)))
+                .try_flatten();
+            Sse::new(transform_stream(events, 0, false)).into_response()
+        }
+        FixtureFormat::Gemini => {
+            let res = Response::builder()
+                .header(CONTENT_TYPE, "text/event-stream")
+                .body(Body::from(input))?;
+            apply_response_rules(res).await
+        }
+    };
+    let body = to_bytes(res.into_body(), usize::MAX)
+        .await
+        .map_err(|e| fixture_error(format!("Failed to read the replayed stream: {e}")))?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Choice 0 of an OpenAI format stream, put back together
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OaiStream {
+    pub content: String,
+    pub reasoning_content: String,
+    /// Name and arguments of each tool call
+    pub tool_calls: Vec<(String, String)>,
+    pub finish_reason: Option<String>,
+}
+
+/// Reads an OpenAI format stream, failing on chunks a client would reject
+///
+/// Every event but the final `[DONE]` must be a chunk with a `choices` array,
+/// whose entries have an `index` and a `delta` object.
+pub fn parse_oai(output: &str) -> Result<OaiStream, ClewdrError> {
+    let mut parsed = OaiStream::default();
+    for data in output.lines().filter_map(|l| l.strip_prefix("data:")) {
+        let data = data.trim();
+        if data == "[DONE]" {
+            continue;
+        }
+        let chunk = serde_json::from_str::<Value>(data)
+            .map_err(|e| fixture_error(format!("Chunk is not JSON ({e}): {data}")))?;
+        let choices = chunk["choices"]
+            .as_array()
+            .ok_or_else(|| fixture_error(format!("Chunk without choices: {data}")))?;
+        for choice in choices {
+            let (Some(index), Some(delta)) = (choice["index"].as_u64(), choice.get("delta")) else {
+                return Err(fixture_error(format!(
+                    "Choice without index or delta: {data}"
+                )));
+            };
+            if !delta.is_object() {
+                return Err(fixture_error(format!("Delta is not an object: {data}")));
+            }
+            if index != 0 {
+                continue;
+            }
+            if let Some(content) = delta["content"].as_str() {
+                parsed.content.push_str(content);
+            }
+            if let Some(reasoning) = delta["reasoning_content"].as_str() {
+                parsed.reasoning_content.push_str(reasoning);
+            }
+            for call in delta["tool_calls"].as_array().into_iter().flatten() {
+                let i = call["index"].as_u64().unwrap_or_default() as usize;
+                if parsed.tool_calls.len() <= i {
+                    parsed.tool_calls.resize(i + 1, Default::default());
+                }
+                let (name, arguments) = &mut parsed.tool_calls[i];
+                if let Some(n) = call["function"]["name"].as_str() {
+                    name.push_str(n);
+                }
+                if let Some(a) = call["function"]["arguments"].as_str() {
+                    arguments.push_str(a);
+                }
+            }
+            if let Some(reason) = choice["finish_reason"].as_str() {
+                parsed.finish_reason = Some(reason.to_string());
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+/// Replays `fixture` and compares the output with its golden file, or writes it when `bless`
+///
+/// The process config is replaced by the default one first.
+///
+/// # Returns
+/// The replayed output, which is also checked to be a valid OpenAI format stream
+pub async fn check(fixture: &Path, bless: bool) -> Result<String, ClewdrError> {
+    let format = FixtureFormat::from_path(fixture).ok_or_else(|| {
+        fixture_error(format!(
+            "{} is not named <name>.<claude|claude-web|gemini>.sse",
+            fixture.display()
+        ))
+    })?;
+    CLEWDR_CONFIG.store(Arc::new(ClewdrConfig::default()));
+    let output = replay(format, tokio::fs::read(fixture).await?).await?;
+    parse_oai(&output)?;
+    let golden = golden_path(fixture);
+    if bless {
+        tokio::fs::write(&golden, &output).await?;
+        return Ok(output);
+    }
+    let expected = tokio::fs::read_to_string(&golden).await?;
+    if let Some((line, (want, got))) = expected
+        .lines()
+        .chain(std::iter::repeat(""))
+        .zip(output.lines().chain(std::iter::repeat("")))
+        .take(expected.lines().count().max(output.lines().count()))
+        .enumerate()
+        .find(|(_, (want, got))| want != got)
+    {
+        return Err(fixture_error(format!(
+            "{} differs from {} at line {}\nexpected: {want}\n     got: {got}",
+            fixture.display(),
+            golden.display(),
+            line + 1
+        )));
+    }
+    Ok(output)
+}
@@ -1,20 +1,56 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
 use axum::{
-    Router,
+    Extension, Router,
+    extract::DefaultBodyLimit,
     http::Method,
-    middleware::{from_extractor, map_response},
-    routing::{delete, get, post},
+    middleware::{from_extractor, from_fn, from_fn_with_state, map_response},
+    routing::{delete, get, post, put},
 };
 use tower::ServiceBuilder;
-use tower_http::{compression::CompressionLayer, cors::CorsLayer};
+use tower_http::{
+    compression::CompressionLayer,
+    cors::{AllowOrigin, CorsLayer},
+};
 
 use crate::{
     api::*,
+    claude_web_state::{
+        bootstrap::ORG_HEADER, conversation::CONVERSATION_HEADER, files::MAX_FILE_SIZE,
+    },
+    config::{CLEWDR_CONFIG, LogFormat, SAFETY_HEADER},
+    gemini_state::files::MAX_UPLOAD_CHUNK,
     middleware::{
-        RequireAdminAuth, RequireBearerAuth, RequireQueryKeyAuth, RequireXApiKeyAuth,
-        claude::{add_usage_info, apply_stop_sequences, check_overloaded, to_oai},
+        RequireAdminRead, RequireAdminWrite, RequireBearerAuth, RequireQueryKeyAuth,
+        RequireXApiKeyAuth,
+        access::access_control,
+        claude::{
+            COOKIE_TAGS_HEADER, PRIORITY_HEADER, TEMPLATE_HEADER, add_usage_info,
+            apply_stop_sequences, check_overloaded, to_oai,
+        },
+        limits::limit_body_size,
+        oai_error::to_oai_error,
+        params::sanitize_oai_params,
+        proxy::{PROXY_HEADER, proxy_override},
+        routing::apply_routing_rules,
+        rules::apply_response_rules,
+        scrub::scrub_oai_request,
+        tenant::{Tenant, TenantRouters, dispatch_tenant},
+    },
+    providers::{
+        bedrock::BedrockProvider,
+        claude::ClaudeProviders,
+        gemini::GeminiProviders,
+        openai_upstream::{OpenAiUpstreamProvider, OpenAiUpstreamRegistry},
+        vertex_claude::VertexClaudeProvider,
+    },
+    services::{
+        batch::BatchTarget,
+        cookie_actor::CookieActorHandle,
+        key_actor::KeyActorHandle,
+        models::ModelRegistry,
+        tenant::{TenantActors, TenantRegistry},
     },
-    providers::{claude::ClaudeProviders, gemini::GeminiProviders},
-    services::{cookie_actor::CookieActorHandle, key_actor::KeyActorHandle},
 };
 
 /// RouterBuilder for the application
@@ -23,6 +59,15 @@ pub struct RouterBuilder {
     cookie_actor_handle: CookieActorHandle,
     key_actor_handle: KeyActorHandle,
     gemini_providers: GeminiProviders,
+    /// Called with the global Vertex credentials, also on the routes of tenants
+    vertex_claude_provider: Arc<VertexClaudeProvider>,
+    /// Called with the global Bedrock credentials, also on the routes of tenants
+    bedrock_provider: Arc<BedrockProvider>,
+    /// Shared with the tenants, upstream pools have no tenant
+    openai_upstream_provider: Arc<OpenAiUpstreamProvider>,
+    /// Tenant the routes serve, `None` for the shared pools
+    tenant: Option<Tenant>,
+    tenants: TenantRegistry,
     inner: Router,
 }
 
@@ -43,11 +88,55 @@ impl RouterBuilder {
         let gemini_providers = GeminiProviders::new(key_tx.clone());
         // Background DB sync (keys/cookies) for multi-instance eventual consistency
         let _bg = crate::services::sync::spawn(cookie_handle.clone(), key_tx.clone());
+        // Background cookie health probing, proactively evicts dead cookies
+        let _probe = crate::services::cookie_prober::spawn(cookie_handle.clone());
+        // Background probing of quarantined Gemini keys
+        let _key_probe = crate::services::key_prober::spawn(key_tx.clone());
+        // Background sweep for conversations left behind on Claude.ai
+        let _cleanup = crate::services::chat_cleaner::spawn(cookie_handle.clone());
+        // Background refresh of Claude Code tokens close to expiry
+        let _token_refresh = crate::services::token_refresher::spawn(cookie_handle.clone());
+        // Scheduled backups of the config, cookies and keys
+        let _backup = crate::services::backup::spawn();
+        // Separate pools of the configured tenants
+        let tenants = TenantRegistry::start().await;
+        // Key pools of the OpenAI compatible upstreams
+        let upstreams = OpenAiUpstreamRegistry::start().await;
         RouterBuilder {
             claude_providers,
             cookie_actor_handle: cookie_handle,
             key_actor_handle: key_tx,
             gemini_providers,
+            vertex_claude_provider: Arc::new(VertexClaudeProvider::default()),
+            bedrock_provider: Arc::new(BedrockProvider),
+            openai_upstream_provider: Arc::new(OpenAiUpstreamProvider::new(upstreams)),
+            tenant: None,
+            tenants,
+            inner: Router::new(),
+        }
+    }
+
+    /// Creates a RouterBuilder serving the pools of a tenant
+    ///
+    /// Tenants have no cloud credentials of their own, their Vertex and Bedrock Claude routes
+    /// use the global ones.
+    fn for_tenant(
+        name: &str,
+        actors: &TenantActors,
+        openai_upstream_provider: Arc<OpenAiUpstreamProvider>,
+    ) -> Self {
+        RouterBuilder {
+            claude_providers: crate::providers::claude::build_providers(
+                actors.cookie_actor_handle.to_owned(),
+            ),
+            cookie_actor_handle: actors.cookie_actor_handle.to_owned(),
+            key_actor_handle: actors.key_actor_handle.to_owned(),
+            gemini_providers: GeminiProviders::new(actors.key_actor_handle.to_owned()),
+            vertex_claude_provider: Arc::new(VertexClaudeProvider::default()),
+            bedrock_provider: Arc::new(BedrockProvider),
+            openai_upstream_provider,
+            tenant: Some(Tenant(name.to_string())),
+            tenants: TenantRegistry::default(),
             inner: Router::new(),
         }
     }
@@ -61,25 +150,133 @@ impl RouterBuilder {
             .route_claude_web_oai_endpoints()
             .route_claude_code_oai_endpoints()
             .route_gemini_endpoints()
+            .route_vertex_claude_endpoints()
+            .route_bedrock_endpoints()
+            .route_openai_upstream_endpoints()
+            .route_health_endpoints()
             .setup_static_serving()
+            .with_routing_rules()
+            .with_tenants()
+            .with_proxy_override()
+            .with_access_control()
             .with_tower_trace()
             .with_cors()
     }
 
+    /// Sets up the API routes of a tenant, and the admin routes of its pools
+    fn with_tenant_setup(self) -> Self {
+        self.route_claude_code_endpoints()
+            .route_claude_web_endpoints()
+            .route_pool_endpoints()
+            .route_claude_web_oai_endpoints()
+            .route_claude_code_oai_endpoints()
+            .route_gemini_endpoints()
+            .route_vertex_claude_endpoints()
+            .route_bedrock_endpoints()
+            .route_openai_upstream_endpoints()
+            .with_routing_rules()
+    }
+
     fn route_gemini_endpoints(mut self) -> Self {
         let router_gemini = Router::new()
-            .route("/v1/v1beta/{*path}", post(api_post_gemini))
-            .route("/v1/vertex/v1beta/{*path}", post(api_post_gemini))
+            .route(
+                "/v1/v1beta/{*path}",
+                post(api_post_gemini).get(api_get_gemini),
+            )
+            .route(
+                "/v1/vertex/v1beta/{*path}",
+                post(api_post_gemini).get(api_get_gemini),
+            )
+            .layer(map_response(apply_response_rules))
+            .layer(DefaultBodyLimit::disable())
+            .layer(from_fn(limit_body_size))
             .layer(from_extractor::<RequireQueryKeyAuth>())
             .layer(CompressionLayer::new())
             .with_state(self.gemini_providers.clone());
+        let router_upload = Router::new()
+            .route("/v1/upload/v1beta/files", post(api_post_gemini_upload))
+            .layer(DefaultBodyLimit::max(MAX_UPLOAD_CHUNK))
+            .layer(from_extractor::<RequireQueryKeyAuth>())
+            .with_state(self.gemini_providers.clone());
         let router_oai = Router::new()
-            .route("/gemini/chat/completions", post(api_post_gemini_oai))
-            .route("/gemini/vertex/chat/completions", post(api_post_gemini_oai))
+            .route(
+                "/gemini/chat/completions",
+                post(api_post_gemini_oai)
+                    .layer(from_fn(sanitize_oai_params))
+                    .layer(from_fn(scrub_oai_request)),
+            )
+            .route(
+                "/gemini/vertex/chat/completions",
+                post(api_post_gemini_oai)
+                    .layer(from_fn(sanitize_oai_params))
+                    .layer(from_fn(scrub_oai_request)),
+            )
+            .route("/gemini/v1/images/generations", post(api_post_gemini_image))
+            .route("/gemini/v1/countTokens", post(api_post_gemini_count_tokens))
+            .layer(map_response(apply_response_rules))
+            .layer(DefaultBodyLimit::disable())
+            .layer(from_fn(limit_body_size))
             .layer(from_extractor::<RequireBearerAuth>())
+            .layer(map_response(to_oai_error))
             .layer(CompressionLayer::new())
             .with_state(self.gemini_providers.clone());
-        let router = router_gemini.merge(router_oai);
+        let router = router_gemini.merge(router_upload).merge(router_oai);
+        self.inner = self.inner.merge(router);
+        self
+    }
+
+    /// Sets up the Anthropic API routes of the Claude models on Vertex AI
+    fn route_vertex_claude_endpoints(mut self) -> Self {
+        let router = Router::new()
+            .route("/vertex/v1/messages", post(api_vertex_claude))
+            .route(
+                "/vertex/v1/messages/count_tokens",
+                post(api_vertex_claude_count_tokens),
+            )
+            .layer(
+                ServiceBuilder::new()
+                    .layer(from_extractor::<RequireXApiKeyAuth>())
+                    .layer(from_fn(limit_body_size))
+                    .layer(DefaultBodyLimit::disable())
+                    .layer(CompressionLayer::new())
+                    .layer(map_response(apply_response_rules)),
+            )
+            .with_state(self.vertex_claude_provider.to_owned());
+        self.inner = self.inner.merge(router);
+        self
+    }
+
+    /// Sets up the Anthropic API route of the Claude models on AWS Bedrock
+    fn route_bedrock_endpoints(mut self) -> Self {
+        let router = Router::new()
+            .route("/bedrock/v1/messages", post(api_bedrock))
+            .layer(
+                ServiceBuilder::new()
+                    .layer(from_extractor::<RequireXApiKeyAuth>())
+                    .layer(from_fn(limit_body_size))
+                    .layer(DefaultBodyLimit::disable())
+                    .layer(CompressionLayer::new())
+                    .layer(map_response(apply_response_rules)),
+            )
+            .with_state(self.bedrock_provider.to_owned());
+        self.inner = self.inner.merge(router);
+        self
+    }
+
+    /// Sets up the passthrough routes of the OpenAI compatible upstreams
+    fn route_openai_upstream_endpoints(mut self) -> Self {
+        let router = Router::new()
+            .route("/openai/{upstream}/v1/{*path}", post(api_openai_upstream))
+            .layer(
+                ServiceBuilder::new()
+                    .layer(CompressionLayer::new())
+                    .layer(map_response(to_oai_error))
+                    .layer(from_extractor::<RequireBearerAuth>())
+                    .layer(from_fn(limit_body_size))
+                    .layer(DefaultBodyLimit::disable())
+                    .layer(map_response(apply_response_rules)),
+            )
+            .with_state(self.openai_upstream_provider.to_owned());
         self.inner = self.inner.merge(router);
         self
     }
@@ -91,7 +288,10 @@ impl RouterBuilder {
             .layer(
                 ServiceBuilder::new()
                     .layer(from_extractor::<RequireXApiKeyAuth>())
+                    .layer(from_fn(limit_body_size))
+                    .layer(DefaultBodyLimit::disable())
                     .layer(CompressionLayer::new())
+                    .layer(map_response(apply_response_rules))
                     .layer(map_response(add_usage_info))
                     .layer(map_response(apply_stop_sequences))
                     .layer(map_response(check_overloaded)),
@@ -112,80 +312,281 @@ impl RouterBuilder {
             .layer(
                 ServiceBuilder::new()
                     .layer(from_extractor::<RequireXApiKeyAuth>())
-                    .layer(CompressionLayer::new()),
+                    .layer(from_fn(limit_body_size))
+                    .layer(DefaultBodyLimit::disable())
+                    .layer(CompressionLayer::new())
+                    .layer(map_response(apply_response_rules)),
             )
             .with_state(self.claude_providers.code());
         self.inner = self.inner.merge(router);
         self
     }
 
+    /// Models of every provider, for the `/v1/models` endpoints
+    fn model_registry(&self) -> ModelRegistry {
+        ModelRegistry {
+            cookie_actor_handle: self.cookie_actor_handle.to_owned(),
+            key_actor_handle: self.key_actor_handle.to_owned(),
+        }
+    }
+
+    /// Sets up unauthenticated liveness and readiness probes
+    fn route_health_endpoints(mut self) -> Self {
+        let router = Router::new()
+            .route("/healthz", get(api_healthz))
+            .route("/readyz", get(api_readyz))
+            .with_state(HealthState {
+                cookie_actor_handle: self.cookie_actor_handle.to_owned(),
+                key_actor_handle: self.key_actor_handle.to_owned(),
+            });
+        self.inner = self.inner.merge(router);
+        self
+    }
+
     /// Sets up routes for API endpoints
+    ///
+    /// Status routes accept the viewer password, everything else needs the admin password
     fn route_admin_endpoints(mut self) -> Self {
-        let cookie_router = Router::new()
-            .route("/cookies", get(api_get_cookies))
-            .route("/cookie", delete(api_delete_cookie).post(api_post_cookie))
-            .with_state(self.cookie_actor_handle.to_owned());
-        let key_router = Router::new()
-            .route("/key", post(api_post_key).delete(api_delete_key))
-            .route("/keys", get(api_get_keys))
-            .with_state(self.key_actor_handle.to_owned());
-        let vertex_router = Router::new()
+        let read_router = self
+            .pool_read_router()
+            .route("/auth", get(api_auth))
             .route("/vertex/credentials", get(api_get_vertex_credentials))
-            .route(
-                "/vertex/credential",
-                post(api_post_vertex_credential).delete(api_delete_vertex_credential),
-            );
+            .route("/templates", get(api_get_templates))
+            .route("/admin/log_level", get(api_get_log_level))
+            .route("/storage/status", get(api_storage_status))
+            .route("/breakers", get(api_get_breakers))
+            .route("/endpoints", get(api_get_endpoints))
+            .route("/errors/recent", get(api_get_recent_errors))
+            .route("/tokenize", post(api_post_tokenize))
+            .layer(from_extractor::<RequireAdminRead>());
+        let vertex_router = Router::new().route(
+            "/vertex/credential",
+            post(api_post_vertex_credential).delete(api_delete_vertex_credential),
+        );
+        // the config, transcripts and captures carry secrets, so reading them is admin only too
         let admin_router = Router::new()
-            .route("/auth", get(api_auth))
             .route("/config", get(api_get_config).post(api_post_config))
+            .route("/config/validate", post(api_post_config_validate))
+            .route("/config/effective", get(api_get_config_effective))
+            .route(
+                "/access_control",
+                get(api_get_access_control).post(api_post_access_control),
+            )
+            .route(
+                "/response_rules",
+                get(api_get_response_rules).put(api_put_response_rules),
+            )
+            .route(
+                "/routing_rules",
+                get(api_get_routing_rules).put(api_put_routing_rules),
+            )
+            .route(
+                "/template/{name}",
+                put(api_put_template).delete(api_delete_template),
+            )
+            .route("/admin/log_level", put(api_put_log_level))
             .route("/storage/import", post(api_storage_import))
             .route("/storage/export", post(api_storage_export))
-            .route("/storage/status", get(api_storage_status));
+            .route("/storage/backup", post(api_storage_backup))
+            .route("/webhooks/test", post(api_test_webhooks))
+            .route(
+                "/debug/captures",
+                get(api_get_captures)
+                    .post(api_post_captures)
+                    .delete(api_delete_captures),
+            )
+            .route("/transcripts", get(api_get_transcripts));
+        let snapshot_router = Router::new()
+            .route("/pool/snapshot", get(api_get_pool_snapshot))
+            .route("/pool/restore", post(api_post_pool_restore))
+            .with_state((
+                self.cookie_actor_handle.to_owned(),
+                self.key_actor_handle.to_owned(),
+            ));
+        let write_router = self
+            .pool_write_router()
+            .merge(vertex_router)
+            .merge(snapshot_router)
+            .merge(admin_router)
+            .layer(from_extractor::<RequireAdminWrite>());
         let router = Router::new()
-            .nest(
-                "/api",
-                cookie_router
-                    .merge(key_router)
-                    .merge(vertex_router)
-                    .merge(admin_router)
-                    .layer(from_extractor::<RequireAdminAuth>()),
-            )
-            .route("/api/version", get(api_version));
+            .nest("/api", read_router.merge(write_router))
+            .route("/api/version", get(api_version))
+            // the password or session token in the request is the credential
+            .route("/api/auth/login", post(api_login))
+            .route("/api/auth/refresh", post(api_refresh))
+            .route("/api/auth/logout", post(api_logout))
+            // WebSocket clients cannot send auth headers, the handler checks the token itself
+            .route("/api/logs/stream", get(api_logs_stream));
         self.inner = self.inner.merge(router);
         self
     }
 
+    /// Status routes of the cookie and key pools
+    fn pool_read_router(&self) -> Router {
+        Router::new()
+            .route("/cookies", get(api_get_cookies))
+            .with_state(self.cookie_actor_handle.to_owned())
+            .merge(
+                Router::new()
+                    .route("/keys", get(api_get_keys))
+                    .with_state(self.key_actor_handle.to_owned()),
+            )
+    }
+
+    /// Routes managing the cookie and key pools and exporting their usage
+    fn pool_write_router(&self) -> Router {
+        let cookie_router = Router::new()
+            .route("/cookie", delete(api_delete_cookie).post(api_post_cookie))
+            .route("/cookies/disable", post(api_disable_cookie))
+            .route("/cookies/enable", post(api_enable_cookie))
+            .route("/cookies/org", post(api_pin_cookie_org))
+            .route("/cookies/tags", post(api_tag_cookie))
+            .route(
+                "/cookies/{id}/refresh_token",
+                post(api_refresh_cookie_token),
+            )
+            .route("/cookies/{id}/settings", post(api_cookie_settings))
+            .route("/cookies/{id}/clear_flags", post(api_clear_cookie_flags))
+            .with_state(self.cookie_actor_handle.to_owned());
+        let key_router = Router::new()
+            .route("/key", post(api_post_key).delete(api_delete_key))
+            .route("/keys/disable", post(api_disable_key))
+            .route("/keys/enable", post(api_enable_key))
+            .with_state(self.key_actor_handle.to_owned());
+        cookie_router.merge(key_router).route(
+            "/usage/export",
+            get(api_usage_export).with_state((
+                self.cookie_actor_handle.to_owned(),
+                self.key_actor_handle.to_owned(),
+            )),
+        )
+    }
+
+    /// Sets up the admin routes of a tenant's pools, `/t/{tenant}/api/...`
+    fn route_pool_endpoints(mut self) -> Self {
+        let read_router = self
+            .pool_read_router()
+            .layer(from_extractor::<RequireAdminRead>());
+        let write_router = self
+            .pool_write_router()
+            .layer(from_extractor::<RequireAdminWrite>());
+        self.inner = self.inner.nest("/api", read_router.merge(write_router));
+        self
+    }
+
+    /// Service of the chat routes for requests made on a client's behalf, e.g. by a batch
+    ///
+    /// These requests do not pass `dispatch_tenant`, so the tenant of the routes is attached.
+    fn forwarded(&self, router: &Router) -> Router {
+        match self.tenant.to_owned() {
+            Some(tenant) => router.to_owned().layer(Extension(tenant)),
+            None => router.to_owned(),
+        }
+    }
+
     /// Sets up routes for OpenAI compatible endpoints
     fn route_claude_web_oai_endpoints(mut self) -> Self {
         let router = Router::new()
-            .route("/v1/chat/completions", post(api_claude_web))
-            .route("/v1/models", get(api_get_models))
+            .route(
+                "/v1/chat/completions",
+                post(api_claude_web)
+                    .layer(from_fn(sanitize_oai_params))
+                    .layer(from_fn(scrub_oai_request)),
+            )
+            .route(
+                "/v1/models",
+                get(api_get_models).with_state(self.model_registry()),
+            )
             .layer(
                 ServiceBuilder::new()
-                    .layer(from_extractor::<RequireBearerAuth>())
                     .layer(CompressionLayer::new())
+                    .layer(map_response(to_oai_error))
+                    .layer(from_extractor::<RequireBearerAuth>())
+                    .layer(from_fn(limit_body_size))
+                    .layer(DefaultBodyLimit::disable())
+                    .layer(map_response(apply_response_rules))
                     .layer(map_response(to_oai))
                     .layer(map_response(apply_stop_sequences))
                     .layer(map_response(check_overloaded)),
             )
             .with_state(self.claude_providers.web());
-        self.inner = self.inner.merge(router);
+        // uploads have their own, larger limit
+        let files = Router::new()
+            .route(
+                "/v1/files",
+                post(api_post_file).layer(DefaultBodyLimit::max(MAX_FILE_SIZE)),
+            )
+            .layer(
+                ServiceBuilder::new()
+                    .layer(CompressionLayer::new())
+                    .layer(map_response(to_oai_error))
+                    .layer(from_extractor::<RequireBearerAuth>())
+                    .layer(map_response(to_oai)),
+            )
+            .with_state(self.claude_providers.web());
+        let socket = Router::new()
+            .route("/v1/chat/ws", get(api_chat_ws))
+            .with_state(ChatSocketTarget::new(
+                self.forwarded(&router),
+                "/v1/chat/completions",
+            ));
+        let batch = Router::new()
+            .route("/v1/batch", post(api_post_batch))
+            .route("/v1/batch/{id}", get(api_get_batch))
+            .layer(from_extractor::<RequireBearerAuth>())
+            .with_state(BatchTarget::new(
+                self.forwarded(&router),
+                "/v1/chat/completions",
+            ));
+        self.inner = self
+            .inner
+            .merge(router)
+            .merge(files)
+            .merge(socket)
+            .merge(batch);
         self
     }
 
     /// Sets up routes for OpenAI compatible endpoints
     fn route_claude_code_oai_endpoints(mut self) -> Self {
         let router = Router::new()
-            .route("/code/v1/chat/completions", post(api_claude_code))
-            .route("/code/v1/models", get(api_get_models))
+            .route(
+                "/code/v1/chat/completions",
+                post(api_claude_code)
+                    .layer(from_fn(sanitize_oai_params))
+                    .layer(from_fn(scrub_oai_request)),
+            )
+            .route(
+                "/code/v1/models",
+                get(api_get_models).with_state(self.model_registry()),
+            )
             .layer(
                 ServiceBuilder::new()
-                    .layer(from_extractor::<RequireBearerAuth>())
                     .layer(CompressionLayer::new())
+                    .layer(map_response(to_oai_error))
+                    .layer(from_extractor::<RequireBearerAuth>())
+                    .layer(from_fn(limit_body_size))
+                    .layer(DefaultBodyLimit::disable())
+                    .layer(map_response(apply_response_rules))
                     .layer(map_response(to_oai)),
             )
             .with_state(self.claude_providers.code());
-        self.inner = self.inner.merge(router);
+        let socket = Router::new()
+            .route("/code/v1/chat/ws", get(api_chat_ws))
+            .with_state(ChatSocketTarget::new(
+                self.forwarded(&router),
+                "/code/v1/chat/completions",
+            ));
+        let batch = Router::new()
+            .route("/code/v1/batch", post(api_post_batch))
+            .route("/code/v1/batch/{id}", get(api_get_batch))
+            .layer(from_extractor::<RequireBearerAuth>())
+            .with_state(BatchTarget::new(
+                self.forwarded(&router),
+                "/code/v1/chat/completions",
+            ));
+        self.inner = self.inner.merge(router).merge(socket).merge(batch);
         self
     }
 
@@ -199,6 +600,10 @@ impl RouterBuilder {
                 .inner
                 .fallback_service(tower_serve_static::ServeDir::new(&INCLUDE_STATIC));
         }
+        #[cfg(feature = "embed-frontend")]
+        {
+            self.inner = self.inner.fallback(serve_frontend);
+        }
         #[cfg(feature = "external-resource")]
         {
             use const_format::formatc;
@@ -216,28 +621,153 @@ impl RouterBuilder {
         use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
         use http::header::HeaderName;
 
+        let config = CLEWDR_CONFIG.load();
         let cors = CorsLayer::new()
-            .allow_origin(tower_http::cors::Any)
-            .allow_methods([Method::GET, Method::POST, Method::DELETE])
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
             .allow_headers([
                 AUTHORIZATION,
                 CONTENT_TYPE,
                 HeaderName::from_static("x-api-key"),
+                HeaderName::from_static("x-request-id"),
+                HeaderName::from_static(PROXY_HEADER),
+                HeaderName::from_static(CONVERSATION_HEADER),
+                HeaderName::from_static(TEMPLATE_HEADER),
+                HeaderName::from_static(ORG_HEADER),
+                HeaderName::from_static(COOKIE_TAGS_HEADER),
+                HeaderName::from_static(PRIORITY_HEADER),
+                HeaderName::from_static(SAFETY_HEADER),
+                HeaderName::from_static("anthropic-version"),
+                HeaderName::from_static("anthropic-beta"),
             ]);
+        // credentials cannot be combined with `*`, so a wildcard then echoes the origin
+        let cors = match (config.cors.any_origin(), config.cors.allow_credentials) {
+            (true, false) => cors.allow_origin(AllowOrigin::any()),
+            (true, true) => cors.allow_origin(AllowOrigin::mirror_request()),
+            (false, _) => cors.allow_origin(AllowOrigin::list(config.cors.origins())),
+        }
+        .allow_credentials(config.cors.allow_credentials);
+        let cors = match config.cors.max_age {
+            Some(secs) => cors.max_age(Duration::from_secs(secs)),
+            None => cors,
+        };
 
         self.inner = self.inner.layer(cors);
         self
     }
 
+    /// Applies the configured routing rules to API requests
+    ///
+    /// A rule can send a request to another provider by rewriting its path, and a layer on the
+    /// router only runs once the route is picked, so the router is wrapped as a whole instead.
+    fn with_routing_rules(mut self) -> Self {
+        self.inner = Router::new().fallback_service(
+            ServiceBuilder::new()
+                .layer(from_fn(apply_routing_rules))
+                .service(self.inner),
+        );
+        self
+    }
+
+    /// Sends the requests of tenants to routes served by the tenant's own pools
+    ///
+    /// Like routing rules, the shared router is wrapped as a whole, so requests of a tenant
+    /// never reach the shared pools.
+    fn with_tenants(mut self) -> Self {
+        let routers: TenantRouters = Arc::new(
+            self.tenants
+                .iter()
+                .map(|(name, actors)| {
+                    let router = RouterBuilder::for_tenant(
+                        name,
+                        actors,
+                        self.openai_upstream_provider.to_owned(),
+                    )
+                    .with_tenant_setup()
+                    .build();
+                    (name.to_owned(), router)
+                })
+                .collect::<HashMap<_, _>>(),
+        );
+        self.inner = Router::new().fallback_service(
+            ServiceBuilder::new()
+                .layer(from_fn_with_state(routers, dispatch_tenant))
+                .service(self.inner),
+        );
+        self
+    }
+
+    /// Lets requests pick their upstream proxy through the `x-clewdr-proxy` header
+    fn with_proxy_override(mut self) -> Self {
+        self.inner = self.inner.layer(from_fn(proxy_override));
+        self
+    }
+
+    /// Rejects clients outside the configured allowlist or inside the denylist, before any auth
+    fn with_access_control(mut self) -> Self {
+        self.inner = self.inner.layer(from_fn(access_control));
+        self
+    }
+
+    /// Wraps every request in a span carrying its request id, and echoes the id back
+    ///
+    /// A client supplied `x-request-id` is kept, otherwise a UUID is generated.
+    /// Handlers fill in the `provider` and `cookie` fields once they are known.
     fn with_tower_trace(mut self) -> Self {
-        use tower_http::trace::TraceLayer;
+        use tower_http::{
+            LatencyUnit,
+            request_id::{MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer},
+            trace::{DefaultOnResponse, TraceLayer},
+        };
+        use tracing::{Level, field::Empty};
 
-        let layer = TraceLayer::new_for_http();
+        // JSON logs are meant for ingestion, so every response is logged with its latency
+        let level = match CLEWDR_CONFIG.load().log_format {
+            LogFormat::Json => Level::INFO,
+            LogFormat::Text => Level::DEBUG,
+        };
+        let trace = TraceLayer::new_for_http()
+            .make_span_with(|req: &axum::extract::Request| {
+                let request_id = req
+                    .extensions()
+                    .get::<RequestId>()
+                    .and_then(|id| id.header_value().to_str().ok())
+                    .unwrap_or_default();
+                tracing::info_span!(
+                    "request",
+                    request_id,
+                    method = %req.method(),
+                    uri = %req.uri().path(),
+                    provider = Empty,
+                    cookie = Empty,
+                )
+            })
+            .on_response(
+                DefaultOnResponse::new()
+                    .level(level)
+                    .latency_unit(LatencyUnit::Millis),
+            );
 
-        self.inner = self.inner.layer(layer);
+        self.inner = self.inner.layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(PropagateRequestIdLayer::x_request_id())
+                .layer(trace),
+        );
         self
     }
 
+    /// Serves the gRPC services next to the HTTP server, sharing its actors and routes
+    ///
+    /// Call after the routes are set up, chat calls are dispatched to them.
+    #[cfg(feature = "grpc")]
+    pub fn spawn_grpc(&self) -> Option<tokio::task::JoinHandle<()>> {
+        crate::grpc::spawn(
+            self.inner.to_owned(),
+            self.cookie_actor_handle.to_owned(),
+            self.key_actor_handle.to_owned(),
+        )
+    }
+
     /// Returns the configured router
     /// Finalizes the router configuration for use with axum
     pub fn build(self) -> Router {
@@ -0,0 +1,167 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use axum::Router;
+use axum_server::{Handle, tls_rustls::RustlsConfig};
+use colored::Colorize;
+use tracing::{error, info};
+
+use crate::{
+    config::{CLEWDR_CONFIG, ListenAddr},
+    error::ClewdrError,
+};
+
+/// How often certificate files are checked for changes
+const CERT_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+async fn shutdown_signal() {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Failed to install Ctrl-C handler");
+}
+
+/// A bound listener, before it is handed to the HTTP server
+enum Listener {
+    Tcp(std::net::TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener),
+}
+
+/// Takes over the socket passed by systemd socket activation, if any
+///
+/// Only the first socket (fd 3) is used, see `sd_listen_fds(3)`.
+#[cfg(unix)]
+fn systemd_listener() -> Option<Listener> {
+    use std::os::fd::{FromRawFd, IntoRawFd};
+
+    const SD_LISTEN_FDS_START: i32 = 3;
+    let pid = std::env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+    let fds = std::env::var("LISTEN_FDS").ok()?.parse::<u32>().ok()?;
+    if pid != std::process::id() || fds == 0 {
+        return None;
+    }
+    // SAFETY: systemd hands the socket over to this process and nothing else owns it
+    let tcp = unsafe { std::net::TcpListener::from_raw_fd(SD_LISTEN_FDS_START) };
+    if tcp.local_addr().is_ok() {
+        return Some(Listener::Tcp(tcp));
+    }
+    // not an inet socket, release the fd without closing it
+    let fd = tcp.into_raw_fd();
+    // SAFETY: same fd as above, ownership is moved rather than duplicated
+    Some(Listener::Unix(unsafe {
+        std::os::unix::net::UnixListener::from_raw_fd(fd)
+    }))
+}
+
+/// Binds the configured address, preferring a socket passed by systemd
+fn bind() -> Result<Listener, ClewdrError> {
+    #[cfg(unix)]
+    if let Some(listener) = systemd_listener() {
+        info!("Using socket from systemd socket activation");
+        return Ok(listener);
+    }
+    let listener = match CLEWDR_CONFIG.load().listen_addr() {
+        ListenAddr::Tcp(addr) => Listener::Tcp(std::net::TcpListener::bind(addr)?),
+        #[cfg(unix)]
+        ListenAddr::Unix(path) => {
+            // remove the socket left behind by a previous run
+            if path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+            Listener::Unix(std::os::unix::net::UnixListener::bind(&path)?)
+        }
+        #[cfg(not(unix))]
+        ListenAddr::Unix(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Unix domain sockets are not supported on this platform",
+            )
+            .into());
+        }
+    };
+    Ok(listener)
+}
+
+/// Serves the router until Ctrl-C is received
+///
+/// Listens on a systemd activated socket, a Unix domain socket or TCP,
+/// and uses HTTPS when `tls_cert` and `tls_key` are configured.
+pub async fn serve(router: Router) -> Result<(), ClewdrError> {
+    let config = CLEWDR_CONFIG.load();
+    let tls = config.tls_cert.to_owned().zip(config.tls_key.to_owned());
+    drop(config);
+
+    match (bind()?, tls) {
+        (Listener::Tcp(listener), None) => {
+            listener.set_nonblocking(true)?;
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            Ok(axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown_signal())
+            .await?)
+        }
+        (Listener::Tcp(listener), Some((cert, key))) => {
+            listener.set_nonblocking(true)?;
+            let tls = RustlsConfig::from_pem_file(&cert, &key).await?;
+            tokio::spawn(watch_certificates(tls.to_owned(), cert, key));
+            let handle = Handle::new();
+            let shutdown = handle.to_owned();
+            tokio::spawn(async move {
+                shutdown_signal().await;
+                shutdown.graceful_shutdown(None);
+            });
+            Ok(axum_server::from_tcp_rustls(listener, tls)
+                .handle(handle)
+                .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+                .await?)
+        }
+        #[cfg(unix)]
+        (Listener::Unix(listener), None) => {
+            listener.set_nonblocking(true)?;
+            let listener = tokio::net::UnixListener::from_std(listener)?;
+            Ok(axum::serve(listener, router)
+                .with_graceful_shutdown(shutdown_signal())
+                .await?)
+        }
+        #[cfg(unix)]
+        (Listener::Unix(_), Some(_)) => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "TLS is only supported on TCP listeners",
+        )
+        .into()),
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Reloads the TLS certificate whenever the certificate or key file changes
+async fn watch_certificates(tls: RustlsConfig, cert: PathBuf, key: PathBuf) {
+    let mut last = (modified(&cert), modified(&key));
+    let mut interval = tokio::time::interval(CERT_RELOAD_INTERVAL);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let current = (modified(&cert), modified(&key));
+        if current == last {
+            continue;
+        }
+        match tls.reload_from_pem_file(&cert, &key).await {
+            Ok(()) => {
+                info!(
+                    "Reloaded TLS certificate {}",
+                    cert.display().to_string().green()
+                );
+                last = current;
+            }
+            // files may be mid-write, retry on the next tick
+            Err(e) => error!("Failed to reload TLS certificate: {}", e),
+        }
+    }
+}
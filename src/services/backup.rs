@@ -0,0 +1,252 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicI64, Ordering},
+    time::Duration,
+};
+
+use chrono::{NaiveDateTime, Utc};
+use colored::Colorize;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    config::{CLEWDR_CONFIG, ClewdrConfig, seal_config},
+    error::ClewdrError,
+    persistence,
+    services::object_store::ObjectStore,
+};
+
+/// Backup names are this prefix, the UTC time of the snapshot and `BACKUP_EXT`
+const BACKUP_PREFIX: &str = "clewdr-backup-";
+const BACKUP_EXT: &str = ".toml";
+/// Sorts chronologically as text, so names can be ordered without parsing
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+/// Longest sleep between schedule checks, so config changes apply within it
+const RECHECK: u64 = 600;
+/// Seconds before a failed scheduled backup is retried
+const FAILURE_RETRY: i64 = 3600;
+
+/// Unix timestamp of the newest backup, 0 until known
+static LAST_BACKUP: AtomicI64 = AtomicI64::new(0);
+
+/// Outcome of a backup
+#[derive(Debug, Serialize)]
+pub struct Backup {
+    pub name: String,
+    /// Directory or bucket the backup was written to
+    pub location: String,
+    /// Size in bytes
+    pub size: usize,
+    /// Older backups deleted by retention pruning
+    pub pruned: usize,
+}
+
+/// Where backups go
+enum Target {
+    Dir(PathBuf),
+    Bucket(ObjectStore, String),
+}
+
+impl Target {
+    fn new(config: &ClewdrConfig) -> Result<Self, ClewdrError> {
+        match config.backup.object_store {
+            Some(ref store) => Ok(Self::Bucket(
+                ObjectStore::new(store)?,
+                format!("{}/{}", store.endpoint, store.bucket),
+            )),
+            None if config.no_fs => Err(ClewdrError::Whatever {
+                message: "Backups need an object store when no_fs is enabled".into(),
+                source: None,
+            }),
+            None => Ok(Self::Dir(config.backup.dir())),
+        }
+    }
+
+    fn location(&self) -> String {
+        match self {
+            Self::Dir(dir) => dir.display().to_string(),
+            Self::Bucket(_, location) => location.to_owned(),
+        }
+    }
+
+    async fn put(&self, name: &str, data: Vec<u8>) -> Result<(), ClewdrError> {
+        match self {
+            Self::Dir(dir) => {
+                tokio::fs::create_dir_all(dir).await?;
+                Ok(tokio::fs::write(dir.join(name), data).await?)
+            }
+            Self::Bucket(store, _) => store.put(name, data).await,
+        }
+    }
+
+    /// Names of the stored backups, in no particular order
+    async fn list(&self) -> Result<Vec<String>, ClewdrError> {
+        match self {
+            Self::Dir(dir) => {
+                let mut names = vec![];
+                let mut entries = match tokio::fs::read_dir(dir).await {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(names),
+                    Err(e) => return Err(e.into()),
+                };
+                while let Some(entry) = entries.next_entry().await? {
+                    names.extend(entry.file_name().to_str().map(str::to_string));
+                }
+                Ok(names)
+            }
+            Self::Bucket(store, _) => store.list(BACKUP_PREFIX).await,
+        }
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), ClewdrError> {
+        match self {
+            Self::Dir(dir) => Ok(tokio::fs::remove_file(dir.join(name)).await?),
+            Self::Bucket(store, _) => store.delete(name).await,
+        }
+    }
+}
+
+/// Unix timestamp of a backup from its name, `None` for other files
+fn backup_time(name: &str) -> Option<i64> {
+    let time = name.strip_prefix(BACKUP_PREFIX)?.strip_suffix(BACKUP_EXT)?;
+    NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT)
+        .ok()
+        .map(|t| t.and_utc().timestamp())
+}
+
+/// Backups to delete so only the newest `keep` remain, 0 keeps all
+fn expired(mut names: Vec<String>, keep: usize) -> Vec<String> {
+    names.retain(|n| backup_time(n).is_some());
+    names.sort();
+    if keep == 0 || names.len() <= keep {
+        return vec![];
+    }
+    names.truncate(names.len() - keep);
+    names
+}
+
+/// Snapshots the config with its cookies and keys, then prunes old backups
+///
+/// Secrets in the snapshot are encrypted like in the config file when `CLEWDR_MASTER_KEY` is set.
+/// The file is a complete `clewdr.toml`, restored by putting it in place of the config.
+pub async fn backup_now() -> Result<Backup, ClewdrError> {
+    let config = CLEWDR_CONFIG.load_full();
+    let target = Target::new(&config)?;
+    let snapshot = persistence::storage().snapshot_config().await?;
+    let data = seal_config(&snapshot)?.into_bytes();
+    let now = Utc::now();
+    let name = format!(
+        "{BACKUP_PREFIX}{}{BACKUP_EXT}",
+        now.format(BACKUP_TIME_FORMAT)
+    );
+    let size = data.len();
+    target.put(&name, data).await?;
+    LAST_BACKUP.store(now.timestamp(), Ordering::Relaxed);
+
+    let mut pruned = 0;
+    match target.list().await {
+        Ok(names) => {
+            for old in expired(names, config.backup.keep) {
+                match target.delete(&old).await {
+                    Ok(()) => pruned += 1,
+                    Err(e) => warn!("[BACKUP] failed to delete {}: {}", old, e),
+                }
+            }
+        }
+        Err(e) => warn!("[BACKUP] failed to list backups for pruning: {}", e),
+    }
+    let location = target.location();
+    info!(
+        "[BACKUP] {} written to {}, {} old backups pruned",
+        name.green(),
+        location,
+        pruned.to_string().green()
+    );
+    Ok(Backup {
+        name,
+        location,
+        size,
+        pruned,
+    })
+}
+
+/// Unix timestamp of the newest stored backup
+async fn latest_backup() -> Result<Option<i64>, ClewdrError> {
+    let target = Target::new(&CLEWDR_CONFIG.load())?;
+    Ok(target
+        .list()
+        .await?
+        .iter()
+        .filter_map(|n| backup_time(n))
+        .max())
+}
+
+/// Spawn the scheduled backups
+///
+/// A backup is taken `backup.interval_hours` after the newest one, found in the backup
+/// directory or bucket on startup, so restarts do not reset the schedule.
+/// An interval of 0 disables them; the settings are hot reloadable.
+pub fn spawn() -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut retry_at = 0;
+        loop {
+            let hours = CLEWDR_CONFIG.load().backup.interval_hours;
+            if hours == 0 {
+                tokio::time::sleep(Duration::from_secs(RECHECK)).await;
+                continue;
+            }
+            let interval = i64::try_from(hours.saturating_mul(3600)).unwrap_or(i64::MAX);
+            if LAST_BACKUP.load(Ordering::Relaxed) == 0 {
+                match latest_backup().await {
+                    Ok(Some(t)) => LAST_BACKUP.store(t, Ordering::Relaxed),
+                    Ok(None) => {}
+                    Err(e) => warn!("[BACKUP] failed to list backups: {}", e),
+                }
+            }
+            let now = Utc::now().timestamp();
+            let last = LAST_BACKUP.load(Ordering::Relaxed);
+            let due = match last {
+                0 => now,
+                last => last.saturating_add(interval),
+            }
+            .max(retry_at);
+            if due > now {
+                let wait = u64::try_from(due - now).unwrap_or_default().min(RECHECK);
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+                continue;
+            }
+            if let Err(e) = backup_now().await {
+                warn!("[BACKUP] scheduled backup failed: {}", e);
+                retry_at = now + FAILURE_RETRY;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prunes_oldest_backups() {
+        assert_eq!(
+            backup_time("clewdr-backup-20261015-030000.toml"),
+            Some(1792033200)
+        );
+        assert_eq!(backup_time("clewdr.toml"), None);
+        let names = [
+            "clewdr-backup-20261014-030000.toml",
+            "notes.txt",
+            "clewdr-backup-20261015-030000.toml",
+            "clewdr-backup-20261013-030000.toml",
+        ]
+        .map(str::to_string)
+        .to_vec();
+        assert_eq!(
+            expired(names.to_owned(), 2),
+            ["clewdr-backup-20261013-030000.toml"]
+        );
+        assert!(expired(names.to_owned(), 0).is_empty());
+        assert!(expired(names, 3).is_empty());
+    }
+}
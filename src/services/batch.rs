@@ -0,0 +1,262 @@
+use std::{
+    sync::{Arc, LazyLock, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    extract::Request,
+    http::HeaderMap,
+};
+use futures::{StreamExt, stream};
+use moka::sync::Cache;
+use serde_json::Value;
+use tower::ServiceExt;
+use tracing::{info, warn};
+
+use crate::{
+    config::CLEWDR_CONFIG,
+    error::ClewdrError,
+    persistence::{self, BatchItem, BatchJob, BatchStatus},
+    services::sigv4::sha256_hex,
+};
+
+/// Most requests accepted in a single batch
+pub const MAX_BATCH_ITEMS: usize = 1000;
+
+/// How often a running job stores its progress
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(30);
+/// Seconds without stored progress after which a running job is taken as orphaned
+const ORPHAN_AFTER: i64 = 5 * 60;
+
+/// Jobs submitted to this instance, finished ones stay readable for a day
+static JOBS: LazyLock<Cache<String, Arc<Mutex<BatchJob>>>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(1000)
+        .time_to_live(Duration::from_secs(24 * 60 * 60))
+        .build()
+});
+
+/// Chat completions endpoint the requests of a batch are sent to
+///
+/// Requests are dispatched to the endpoint's regular HTTP service, so they pass the same
+/// parameter handling, format conversion and response rules as interactive requests.
+#[derive(Clone)]
+pub struct BatchTarget {
+    service: Router,
+    path: &'static str,
+}
+
+impl BatchTarget {
+    /// # Arguments
+    /// * `service` - Router serving the chat completions endpoint
+    /// * `path` - Path of the chat completions endpoint within `service`
+    pub fn new(service: Router, path: &'static str) -> Self {
+        Self { service, path }
+    }
+}
+
+async fn save(job: &BatchJob) {
+    let storage = persistence::storage();
+    if !storage.is_enabled() {
+        return;
+    }
+    if let Err(e) = storage.persist_batch_job(job).await {
+        warn!("Failed to persist batch job {}: {}", job.id, e);
+    }
+}
+
+/// Starts a job running `requests` against `target` in the background
+///
+/// Requests are sent without streaming, with `headers`, at most `batch_concurrency` at a time.
+/// Owner of the jobs submitted with `key` for `tenant`, a hash so keys are never stored
+pub fn owner(tenant: Option<&str>, key: &str) -> String {
+    sha256_hex(format!("{}\n{key}", tenant.unwrap_or_default()).as_bytes())
+}
+
+pub fn submit(
+    target: BatchTarget,
+    owner: String,
+    headers: HeaderMap,
+    requests: Vec<Value>,
+) -> BatchJob {
+    let pending = BatchItem {
+        status: BatchStatus::Pending,
+        status_code: None,
+        response: None,
+    };
+    let now = chrono::Utc::now().timestamp();
+    let job = BatchJob {
+        id: format!("batch_{}", uuid::Uuid::new_v4().simple()),
+        owner,
+        created_at: now,
+        completed_at: None,
+        updated_at: Some(now),
+        status: BatchStatus::InProgress,
+        items: vec![pending; requests.len()],
+    };
+    let shared = Arc::new(Mutex::new(job.to_owned()));
+    JOBS.insert(job.id.to_owned(), shared.to_owned());
+    info!(
+        "[BATCH] {} started with {} requests",
+        job.id,
+        requests.len()
+    );
+    let stored = job.to_owned();
+    tokio::spawn(async move {
+        save(&stored).await;
+        run(target, headers, requests, shared).await;
+    });
+    job
+}
+
+async fn run(
+    target: BatchTarget,
+    headers: HeaderMap,
+    requests: Vec<Value>,
+    job: Arc<Mutex<BatchJob>>,
+) {
+    let concurrency = CLEWDR_CONFIG.load().batch_concurrency.max(1);
+    let mut results = stream::iter(requests.into_iter().enumerate())
+        .map(|(i, body)| {
+            let target = target.to_owned();
+            let headers = headers.to_owned();
+            async move { (i, send(target, headers, body).await) }
+        })
+        .buffer_unordered(concurrency);
+    // the progress doubles as a heartbeat, so other instances can tell the job is alive
+    let mut progress = tokio::time::interval(PROGRESS_INTERVAL);
+    progress.tick().await;
+    loop {
+        tokio::select! {
+            result = results.next() => {
+                let Some((i, item)) = result else {
+                    break;
+                };
+                job.lock().unwrap_or_else(|e| e.into_inner()).items[i] = item;
+            }
+            _ = progress.tick() => {
+                let snapshot = {
+                    let mut job = job.lock().unwrap_or_else(|e| e.into_inner());
+                    job.updated_at = Some(chrono::Utc::now().timestamp());
+                    job.to_owned()
+                };
+                save(&snapshot).await;
+            }
+        }
+    }
+    let job = {
+        let mut job = job.lock().unwrap_or_else(|e| e.into_inner());
+        let now = chrono::Utc::now().timestamp();
+        job.status = BatchStatus::Completed;
+        job.completed_at = Some(now);
+        job.updated_at = Some(now);
+        job.to_owned()
+    };
+    let failed = job
+        .items
+        .iter()
+        .filter(|i| i.status == BatchStatus::Failed)
+        .count();
+    info!(
+        "[BATCH] {} finished, {} of {} requests failed",
+        job.id,
+        failed,
+        job.items.len()
+    );
+    save(&job).await;
+}
+
+/// Marks running jobs failed once they stop storing progress, their instance went down
+///
+/// Requests that had not been answered yet are failed as well, the job is not resumed as
+/// the submission headers are not stored.
+///
+/// # Returns
+/// * Number of jobs marked failed
+pub async fn fail_orphaned() -> Result<usize, ClewdrError> {
+    let storage = persistence::storage();
+    let now = chrono::Utc::now().timestamp();
+    let mut orphaned = 0;
+    for mut job in storage.load_unfinished_batch_jobs().await? {
+        if JOBS.contains_key(&job.id)
+            || job.updated_at.unwrap_or(job.created_at) > now.saturating_sub(ORPHAN_AFTER)
+        {
+            continue;
+        }
+        for item in job
+            .items
+            .iter_mut()
+            .filter(|i| i.status == BatchStatus::Pending)
+        {
+            *item = BatchItem {
+                status: BatchStatus::Failed,
+                status_code: None,
+                response: Some(serde_json::json!({
+                    "error": {
+                        "type": "Interrupted",
+                        "message": "The instance running the batch stopped"
+                    }
+                })),
+            };
+        }
+        job.status = BatchStatus::Failed;
+        job.completed_at = Some(now);
+        job.updated_at = Some(now);
+        storage.persist_batch_job(&job).await?;
+        warn!("[BATCH] {} was orphaned, marked failed", job.id);
+        orphaned += 1;
+    }
+    Ok(orphaned)
+}
+
+/// Sends one request of a batch and records its answer
+async fn send(target: BatchTarget, headers: HeaderMap, body: Value) -> BatchItem {
+    let failed = |message: &str| BatchItem {
+        status: BatchStatus::Failed,
+        status_code: None,
+        response: Some(serde_json::json!({
+            "error": { "type": "BadRequest", "message": message }
+        })),
+    };
+    let Value::Object(mut body) = body else {
+        return failed("Requests must be JSON objects");
+    };
+    body.insert("stream".to_string(), Value::Bool(false));
+    let body = Body::from(Value::Object(body).to_string());
+    let Ok(mut req) = Request::post(target.path).body(body) else {
+        return failed("Invalid request");
+    };
+    req.headers_mut().extend(headers);
+    let res = target
+        .service
+        .oneshot(req)
+        .await
+        .unwrap_or_else(|e| match e {});
+    let status = res.status();
+    let body = match to_bytes(res.into_body(), usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return failed(&format!("Failed to read the response: {e}")),
+    };
+    let response = serde_json::from_slice(&body)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()));
+    BatchItem {
+        status: if status.is_success() {
+            BatchStatus::Completed
+        } else {
+            BatchStatus::Failed
+        },
+        status_code: Some(status.as_u16()),
+        response: Some(response),
+    }
+}
+
+/// A batch job of `owner` by id, from storage when this instance does not hold it
+pub async fn get(id: &str, owner: &str) -> Result<Option<BatchJob>, ClewdrError> {
+    let job = match JOBS.get(id) {
+        Some(job) => Some(job.lock().unwrap_or_else(|e| e.into_inner()).to_owned()),
+        None => persistence::storage().load_batch_job(id).await?,
+    };
+    Ok(job.filter(|j| j.owner == owner))
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde_json::{Value, json};
+use strum::{Display, IntoStaticStr};
+use tracing::warn;
+
+use crate::{
+    config::{BreakerConfig, CLEWDR_CONFIG},
+    error::ClewdrError,
+};
+
+/// Upstream providers with a circuit breaker each
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum Provider {
+    ClaudeWeb,
+    ClaudeCode,
+    GeminiAiStudio,
+    Vertex,
+    /// Claude models hosted by Vertex AI
+    VertexClaude,
+    /// Claude models hosted by AWS Bedrock
+    Bedrock,
+    /// OpenAI compatible upstreams, all of them share the breaker
+    #[strum(serialize = "openai_upstream")]
+    OpenAiUpstream,
+}
+
+impl Provider {
+    const ALL: [Provider; 7] = [
+        Provider::ClaudeWeb,
+        Provider::ClaudeCode,
+        Provider::GeminiAiStudio,
+        Provider::Vertex,
+        Provider::VertexClaude,
+        Provider::Bedrock,
+        Provider::OpenAiUpstream,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Requests pass, failures are counted
+    Closed,
+    /// Requests are rejected until the cooldown ends
+    Open { until: Instant },
+    /// One probe request is in flight, its result closes or reopens the breaker,
+    /// another probe is let through if it never reports back within the cooldown
+    HalfOpen { since: Instant },
+}
+
+#[derive(Debug)]
+struct Breaker {
+    state: State,
+    window_start: Instant,
+    requests: u32,
+    failures: u32,
+}
+
+impl Breaker {
+    fn new(now: Instant) -> Self {
+        Self {
+            state: State::Closed,
+            window_start: now,
+            requests: 0,
+            failures: 0,
+        }
+    }
+
+    fn reset_window(&mut self, now: Instant) {
+        self.window_start = now;
+        self.requests = 0;
+        self.failures = 0;
+    }
+
+    /// Whether a request may be sent
+    ///
+    /// # Returns
+    /// * `Err(Duration)` - The breaker is open, retry after this long
+    fn allow(&mut self, config: &BreakerConfig, now: Instant) -> Result<(), Duration> {
+        match self.state {
+            State::Closed => Ok(()),
+            State::Open { until } if now >= until => {
+                self.state = State::HalfOpen { since: now };
+                Ok(())
+            }
+            State::Open { until } => Err(until - now),
+            State::HalfOpen { since }
+                if now.duration_since(since) > Duration::from_secs(config.cooldown) =>
+            {
+                self.state = State::HalfOpen { since: now };
+                Ok(())
+            }
+            State::HalfOpen { .. } => Err(Duration::from_secs(config.cooldown.clamp(1, 5))),
+        }
+    }
+
+    /// Counts the outcome of a request
+    ///
+    /// # Returns
+    /// * `bool` - Whether the breaker just opened
+    fn record(&mut self, failed: bool, config: &BreakerConfig, now: Instant) -> bool {
+        let open = State::Open {
+            until: now + Duration::from_secs(config.cooldown),
+        };
+        match self.state {
+            State::HalfOpen { .. } if failed => {
+                self.state = open;
+                true
+            }
+            State::HalfOpen { .. } => {
+                self.state = State::Closed;
+                self.reset_window(now);
+                false
+            }
+            // requests sent before the breaker opened
+            State::Open { .. } => false,
+            State::Closed => {
+                if now.duration_since(self.window_start) > Duration::from_secs(config.window) {
+                    self.reset_window(now);
+                }
+                self.requests += 1;
+                self.failures += failed as u32;
+                let rate = self.failures as f64 / self.requests as f64;
+                if self.requests >= config.min_requests.max(1) && rate >= config.error_rate {
+                    self.state = open;
+                    self.reset_window(now);
+                    return true;
+                }
+                false
+            }
+        }
+    }
+}
+
+static BREAKERS: LazyLock<Mutex<HashMap<Provider, Breaker>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Whether an error means the provider is failing, rather than the request being bad
+fn is_failure(err: &ClewdrError) -> bool {
+    match err {
+        ClewdrError::ClaudeHttpError { code, .. }
+        | ClewdrError::GeminiHttpError { code, .. }
+        | ClewdrError::UpstreamHttpError { code, .. } => {
+            code.is_server_error() || code.as_u16() == 429
+        }
+        ClewdrError::WreqError { .. }
+        | ClewdrError::TooManyRetries
+        | ClewdrError::NoCookieAvailable
+        | ClewdrError::NoKeyAvailable
+        | ClewdrError::EmptyChoices
+        | ClewdrError::EventSourceRquestError { .. } => true,
+        _ => false,
+    }
+}
+
+/// Checks the breaker of a provider before a request
+///
+/// # Returns
+/// * `Err(ClewdrError::CircuitOpen)` - The provider is failing, nothing should be sent
+pub fn check(provider: Provider) -> Result<(), ClewdrError> {
+    let config = CLEWDR_CONFIG.load();
+    if !config.circuit_breaker.enabled {
+        return Ok(());
+    }
+    let now = Instant::now();
+    let mut breakers = BREAKERS.lock().unwrap_or_else(|e| e.into_inner());
+    let breaker = breakers
+        .entry(provider)
+        .or_insert_with(|| Breaker::new(now));
+    breaker
+        .allow(&config.circuit_breaker, now)
+        .map_err(|wait| ClewdrError::CircuitOpen {
+            provider: provider.into(),
+            retry_after: wait.as_secs().max(1),
+        })
+}
+
+/// Counts the result of a request in the breaker of its provider
+pub fn record<T>(provider: Provider, result: &Result<T, ClewdrError>) {
+    let config = CLEWDR_CONFIG.load();
+    if !config.circuit_breaker.enabled {
+        return;
+    }
+    let failed = result.as_ref().err().is_some_and(is_failure);
+    let now = Instant::now();
+    let mut breakers = BREAKERS.lock().unwrap_or_else(|e| e.into_inner());
+    let breaker = breakers
+        .entry(provider)
+        .or_insert_with(|| Breaker::new(now));
+    if breaker.record(failed, &config.circuit_breaker, now) {
+        warn!(
+            "[BREAKER] {} opened for {}s",
+            provider, config.circuit_breaker.cooldown
+        );
+    }
+}
+
+/// State of every breaker, for the status API
+pub fn status() -> Value {
+    let now = Instant::now();
+    let breakers = BREAKERS.lock().unwrap_or_else(|e| e.into_inner());
+    let states = Provider::ALL
+        .iter()
+        .map(|p| {
+            let (state, retry_after, requests, failures) = match breakers.get(p) {
+                None => ("closed", 0, 0, 0),
+                Some(b) => {
+                    let (state, retry_after) = match b.state {
+                        State::Closed => ("closed", 0),
+                        State::Open { until } => {
+                            ("open", until.saturating_duration_since(now).as_secs())
+                        }
+                        State::HalfOpen { .. } => ("half_open", 0),
+                    };
+                    (state, retry_after, b.requests, b.failures)
+                }
+            };
+            (
+                p.to_string(),
+                json!({
+                    "state": state,
+                    "retry_after": retry_after,
+                    "requests": requests,
+                    "failures": failures,
+                }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>();
+    json!({
+        "enabled": CLEWDR_CONFIG.load().circuit_breaker.enabled,
+        "providers": states,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_half_opens_and_closes() {
+        let config = BreakerConfig {
+            enabled: true,
+            error_rate: 0.5,
+            min_requests: 4,
+            window: 60,
+            cooldown: 30,
+        };
+        let start = Instant::now();
+        let mut breaker = Breaker::new(start);
+        assert!(!breaker.record(false, &config, start));
+        assert!(!breaker.record(true, &config, start));
+        assert!(!breaker.record(false, &config, start));
+        assert!(breaker.record(true, &config, start));
+        assert_eq!(
+            breaker.allow(&config, start + Duration::from_secs(10)),
+            Err(Duration::from_secs(20))
+        );
+
+        // the cooldown lets a single probe through
+        let later = start + Duration::from_secs(31);
+        assert!(breaker.allow(&config, later).is_ok());
+        assert!(breaker.allow(&config, later).is_err());
+        assert!(breaker.record(true, &config, later));
+
+        let much_later = later + Duration::from_secs(31);
+        assert!(breaker.allow(&config, much_later).is_ok());
+        assert!(!breaker.record(false, &config, much_later));
+        assert_eq!(breaker.state, State::Closed);
+        assert!(breaker.allow(&config, much_later).is_ok());
+    }
+}
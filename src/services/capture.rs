@@ -0,0 +1,237 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use futures::TryStreamExt;
+use http::HeaderMap;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{Value, json};
+use tracing::info;
+use wreq::{Body, RequestBuilder, Response};
+
+use crate::utils::{print_out_json, transcript};
+
+/// Captures kept in memory, older ones are dropped first
+const MAX_CAPTURES: usize = 50;
+/// Longest request or response body kept in a capture, in bytes
+const MAX_BODY_BYTES: usize = 256 << 10;
+/// Headers whose values are never captured
+const SECRET_HEADERS: [&str; 6] = [
+    "authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+    "x-goog-api-key",
+    "proxy-authorization",
+];
+
+/// Gemini API keys
+static GEMINI_KEY_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"AIza[0-9A-Za-z_-]{35}").unwrap());
+
+/// One upstream exchange, with secrets redacted
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Capture {
+    pub id: u64,
+    pub created_at: i64,
+    pub method: String,
+    pub url: String,
+    pub request_headers: Vec<(String, String)>,
+    pub request_body: String,
+    pub status: Option<u16>,
+    pub response_headers: Vec<(String, String)>,
+    pub response_body: String,
+    /// Transport error, when no response was received
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct Captures {
+    /// Requests still to be captured
+    remaining: u32,
+    entries: VecDeque<Capture>,
+}
+
+static CAPTURES: LazyLock<Mutex<Captures>> = LazyLock::new(Default::default);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Masks cookies and API keys in `text`
+fn redact(text: &str) -> String {
+    let text = transcript::redact(text);
+    GEMINI_KEY_RE.replace_all(&text, "AIza***").into_owned()
+}
+
+fn headers(map: &HeaderMap) -> Vec<(String, String)> {
+    map.iter()
+        .map(|(k, v)| {
+            let value = if SECRET_HEADERS.contains(&k.as_str()) {
+                "***".to_string()
+            } else {
+                redact(&String::from_utf8_lossy(v.as_bytes()))
+            };
+            (k.to_string(), value)
+        })
+        .collect()
+}
+
+fn body_text(bytes: &[u8]) -> String {
+    let bytes = &bytes[..bytes.len().min(MAX_BODY_BYTES)];
+    redact(&String::from_utf8_lossy(bytes))
+}
+
+/// Captures the next `count` upstream requests, 0 stops capturing
+pub fn arm(count: u32) {
+    CAPTURES.lock().unwrap_or_else(|e| e.into_inner()).remaining = count;
+    info!("[CAPTURE] capturing the next {} upstream requests", count);
+}
+
+/// Drops every stored capture
+pub fn clear() {
+    CAPTURES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entries
+        .clear();
+}
+
+/// Stored captures, newest first, for the debug API
+pub fn status() -> Value {
+    let captures = CAPTURES.lock().unwrap_or_else(|e| e.into_inner());
+    json!({
+        "remaining": captures.remaining,
+        "items": captures.entries.iter().rev().collect::<Vec<_>>(),
+    })
+}
+
+/// Takes one capture slot, `None` when capturing is off
+fn claim() -> Option<u64> {
+    let mut captures = CAPTURES.lock().unwrap_or_else(|e| e.into_inner());
+    if captures.remaining == 0 {
+        return None;
+    }
+    captures.remaining -= 1;
+    Some(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Stores a finished capture in memory and in the log directory
+fn store(capture: Capture) {
+    print_out_json(&capture, &format!("captures/{}.json", capture.id));
+    let mut captures = CAPTURES.lock().unwrap_or_else(|e| e.into_inner());
+    if captures.entries.len() >= MAX_CAPTURES {
+        captures.entries.pop_front();
+    }
+    captures.entries.push_back(capture);
+}
+
+pub trait CaptureExt {
+    /// Sends the request, recording it and its response when capturing is on
+    fn send_captured(self) -> impl Future<Output = Result<Response, wreq::Error>>;
+}
+
+impl CaptureExt for RequestBuilder {
+    async fn send_captured(self) -> Result<Response, wreq::Error> {
+        let Some(id) = claim() else {
+            return self.send().await;
+        };
+        let (client, request) = self.build_split();
+        let request = request?;
+        let mut url = request.url().to_owned();
+        let query = url
+            .query_pairs()
+            .map(|(k, v)| match k.as_ref() {
+                "key" => (k.into_owned(), "***".to_string()),
+                _ => (k.into_owned(), v.into_owned()),
+            })
+            .collect::<Vec<_>>();
+        if !query.is_empty() {
+            url.query_pairs_mut().clear().extend_pairs(query);
+        }
+        let mut capture = Capture {
+            id,
+            created_at: chrono::Utc::now().timestamp(),
+            method: request.method().to_string(),
+            url: url.to_string(),
+            request_headers: headers(request.headers()),
+            request_body: request
+                .body()
+                .and_then(Body::as_bytes)
+                .map(body_text)
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        let response = match client.execute(request).await {
+            Ok(response) => response,
+            Err(e) => {
+                capture.error = Some(redact(&e.to_string()));
+                store(capture);
+                return Err(e);
+            }
+        };
+        capture.status = Some(response.status().as_u16());
+        capture.response_headers = headers(response.headers());
+
+        // the body is recorded while the caller reads it and stored once it is dropped
+        let mut builder = http::Response::builder()
+            .status(response.status())
+            .version(response.version());
+        if let Some(map) = builder.headers_mut() {
+            *map = response.headers().to_owned();
+        }
+        let mut tap = CaptureTap {
+            capture,
+            buf: Vec::new(),
+        };
+        let stream = response.bytes_stream().inspect_ok(move |b| tap.feed(b));
+        let response = builder
+            .body(Body::wrap_stream(stream))
+            .expect("response parts are valid");
+        Ok(Response::from(response))
+    }
+}
+
+/// Collects a response body for a capture
+struct CaptureTap {
+    capture: Capture,
+    buf: Vec<u8>,
+}
+
+impl CaptureTap {
+    fn feed(&mut self, bytes: &[u8]) {
+        let room = MAX_BODY_BYTES.saturating_sub(self.buf.len());
+        self.buf.extend_from_slice(&bytes[..bytes.len().min(room)]);
+    }
+}
+
+impl Drop for CaptureTap {
+    fn drop(&mut self) {
+        let mut capture = std::mem::take(&mut self.capture);
+        capture.response_body = body_text(&self.buf);
+        store(capture);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let mut map = HeaderMap::new();
+        map.insert("cookie", "sessionKey=sk-ant-sid01-abc".parse().unwrap());
+        map.insert("x-trace", "sk-ant-api03-xyz".parse().unwrap());
+        assert_eq!(
+            headers(&map),
+            [
+                ("cookie".to_string(), "***".to_string()),
+                ("x-trace".to_string(), "sk-ant-***".to_string()),
+            ]
+        );
+        let key = format!("AIza{}", "a".repeat(35));
+        assert_eq!(body_text(format!("key={key}").as_bytes()), "key=AIza***");
+    }
+}
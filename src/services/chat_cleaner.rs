@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use serde_json::Value;
+use snafu::ResultExt;
+use tracing::{info, warn};
+use wreq::Method;
+
+use crate::{
+    claude_web_state::{
+        ClaudeWebState,
+        conversation::{CONVERSATION_NAME_PREFIX, is_continued},
+    },
+    config::{CLEWDR_CONFIG, CookieStatus},
+    error::{CheckClaudeErr, ClewdrError, WreqSnafu},
+    services::cookie_actor::CookieActorHandle,
+};
+
+/// How long to wait before re-reading the config while the sweep is disabled
+const DISABLED_RECHECK: u64 = 60;
+
+/// Spawn the background sweep for leftover conversations.
+///
+/// Every `chat_cleanup_interval` seconds the conversations of each valid cookie are
+/// listed, and those created by ClewdR and untouched for `chat_cleanup_max_age` seconds
+/// are deleted. They pile up when a stream is aborted before `clean_chat` runs or the
+/// process exits mid-request.
+/// An interval of 0 or `preserve_chats` disables the sweep; the settings are hot reloadable.
+pub fn spawn(handle: CookieActorHandle) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let config = CLEWDR_CONFIG.load();
+            let interval = config.chat_cleanup_interval;
+            if interval == 0 || config.preserve_chats {
+                drop(config);
+                tokio::time::sleep(Duration::from_secs(DISABLED_RECHECK)).await;
+                continue;
+            }
+            drop(config);
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            let Ok(status) = handle.get_status().await else {
+                continue;
+            };
+            for cookie in status.valid {
+                let display = cookie.cookie.ellipse();
+                match sweep(&handle, cookie).await {
+                    Ok(0) => {}
+                    Ok(n) => info!(
+                        "[CLEANUP] {} deleted {} leftover conversations",
+                        display.green(),
+                        n.to_string().green()
+                    ),
+                    Err(e) => warn!("[CLEANUP] {} sweep failed: {}", display, e),
+                }
+            }
+        }
+    })
+}
+
+/// Whether a listed conversation was created by ClewdR and has been idle too long
+fn is_orphaned(conversation: &Value, now: DateTime<Utc>, max_age: Duration) -> bool {
+    if !conversation["name"]
+        .as_str()
+        .is_some_and(|n| n.starts_with(CONVERSATION_NAME_PREFIX))
+    {
+        return false;
+    }
+    conversation["updated_at"]
+        .as_str()
+        .or_else(|| conversation["created_at"].as_str())
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .and_then(|t| (now - t.with_timezone(&Utc)).to_std().ok())
+        .is_some_and(|age| age > max_age)
+}
+
+/// Deletes the leftover conversations in the organization of one cookie
+///
+/// # Returns
+/// * `Result<usize, ClewdrError>` - Number of deleted conversations
+async fn sweep(handle: &CookieActorHandle, cookie: CookieStatus) -> Result<usize, ClewdrError> {
+    let mut state = ClaudeWebState::new(handle.to_owned());
+    state.use_cookie(cookie)?;
+    state.bootstrap().await?;
+    let org_uuid = state
+        .org_uuid
+        .to_owned()
+        .ok_or(ClewdrError::UnexpectedNone {
+            msg: "Organization UUID is not set",
+        })?;
+    let endpoint = state
+        .endpoint
+        .join(&format!(
+            "api/organizations/{}/chat_conversations",
+            org_uuid
+        ))
+        .expect("Url parse error");
+    let conversations = state
+        .build_request(Method::GET, endpoint)
+        .send()
+        .await
+        .context(WreqSnafu {
+            msg: "Failed to list conversations",
+        })?
+        .check_claude()
+        .await?
+        .json::<Vec<Value>>()
+        .await
+        .context(WreqSnafu {
+            msg: "Failed to parse conversations",
+        })?;
+    let max_age = Duration::from_secs(CLEWDR_CONFIG.load().chat_cleanup_max_age);
+    let now = Utc::now();
+    let mut deleted = 0;
+    for conversation in conversations {
+        if !is_orphaned(&conversation, now, max_age) {
+            continue;
+        }
+        let Some(uuid) = conversation["uuid"].as_str() else {
+            continue;
+        };
+        // still open for a client continuing it
+        if is_continued(uuid) {
+            continue;
+        }
+        state.delete_conversation(uuid).await;
+        deleted += 1;
+    }
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn orphaned_conversations() {
+        let now = DateTime::parse_from_rfc3339("2025-01-02T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let day = Duration::from_secs(86400);
+        let old =
+            json!({ "name": "ClewdR-2024-12-31 00:00:00", "updated_at": "2024-12-31T00:00:00Z" });
+        let fresh =
+            json!({ "name": "ClewdR-2025-01-01 12:00:00", "updated_at": "2025-01-01T12:00:00Z" });
+        let user = json!({ "name": "My chat", "updated_at": "2024-12-31T00:00:00Z" });
+        assert!(is_orphaned(&old, now, day));
+        assert!(!is_orphaned(&fresh, now, day));
+        assert!(!is_orphaned(&user, now, day));
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use moka::sync::Cache;
+use snafu::ResultExt;
+use tracing::debug;
+use wreq::{
+    Client, ClientBuilder, Response, Url,
+    cookie::{CookieStore, Jar},
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::{HeaderValue, SET_COOKIE},
+    redirect::Policy,
+};
+use wreq_util::Emulation;
+
+use crate::{
+    config::{Timeouts, proxy_from_str},
+    error::{ClewdrError, WreqSnafu},
+    middleware::timeout::apply_timeouts,
+};
+
+/// Most distinct clients kept, one per proxy, provider and timeouts in use
+const MAX_CLIENTS: u64 = 64;
+/// Clients unused for this long are dropped with their connections
+const IDLE: Duration = Duration::from_secs(10 * 60);
+
+/// Upstream a pooled client talks to, deciding how it is built
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClientKind {
+    /// claude.ai and the Anthropic API, with a browser TLS fingerprint
+    Claude,
+    /// Gemini and Vertex AI
+    Gemini,
+    /// AWS Bedrock
+    Bedrock,
+    /// OpenAI compatible upstreams
+    OpenAiUpstream,
+    /// Remote images of client requests, connecting directly and only to public addresses
+    Image,
+}
+
+/// Whether `ip` is reachable from the internet, rather than a loopback, private,
+/// link-local or otherwise reserved address
+///
+/// IPv6 addresses embedding an IPv4 address, mapped, compatible, NAT64 or 6to4, are judged by
+/// the IPv4 address they embed.
+pub fn is_public(ip: IpAddr) -> bool {
+    let v4 = |ip: Ipv4Addr| {
+        let [a, b, c, _] = ip.octets();
+        !(ip.is_private()
+            || ip.is_loopback()
+            || ip.is_link_local()
+            || ip.is_unspecified()
+            || ip.is_broadcast()
+            || ip.is_documentation()
+            || ip.is_multicast()
+            // shared address space and the `0.0.0.0/8` block
+            || (a == 100 && (64..128).contains(&b))
+            || a == 0
+            // IETF protocol assignments `192.0.0.0/24` and benchmarking `198.18.0.0/15`
+            || (a == 192 && b == 0 && c == 0)
+            || (a == 198 && (b & 0xfe) == 18)
+            // reserved `240.0.0.0/4`
+            || a >= 240)
+    };
+    match ip {
+        IpAddr::V4(ip) => v4(ip),
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            let [first, second, ..] = segments;
+            let embedded = |hi: u16, lo: u16| Ipv4Addr::from((u32::from(hi) << 16) | u32::from(lo));
+            if let Some(ip) = ip.to_ipv4() {
+                // mapped `::ffff:a.b.c.d` and compatible `::a.b.c.d`
+                v4(ip)
+            } else if first == 0x64 && second == 0xff9b {
+                // NAT64 `64:ff9b::/96` and the local-use `64:ff9b:1::/48`
+                segments[2..6] == [0; 4] && v4(embedded(segments[6], segments[7]))
+            } else if first == 0x2002 {
+                // 6to4 `2002::/16`
+                v4(embedded(segments[1], segments[2]))
+            } else {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // unique local `fc00::/7` and link-local `fe80::/10`
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80
+                    // documentation `2001:db8::/32`
+                    || (first == 0x2001 && second == 0xdb8))
+            }
+        }
+    }
+}
+
+/// Resolver of image clients, failing for names with any address that is not public
+///
+/// Connections go to the addresses checked here, so a name cannot pass a check and then
+/// resolve to a local address when connecting.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .collect::<Vec<_>>();
+            if addrs.is_empty() || addrs.iter().any(|a| !is_public(a.ip())) {
+                return Err(format!("host {host} is not a public address").into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(addrs)
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PoolKey {
+    kind: ClientKind,
+    /// URL of the proxy, with its credentials so proxies differing in them get their own client
+    proxy: Option<String>,
+    timeouts: Timeouts,
+}
+
+/// Clients shared by all requests with the same key, so connections and HTTP/2 sessions
+/// are reused instead of opening a new TLS connection per request
+static CLIENTS: LazyLock<Cache<PoolKey, Client>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(MAX_CLIENTS)
+        .time_to_idle(IDLE)
+        .build()
+});
+
+/// Client for upstream requests, shared with other requests of the same kind, proxy and
+/// timeouts
+///
+/// Pooled clients keep no cookies, credentials are added to each request: the cookie in the
+/// `Cookie` header, keys and tokens in their query parameter or header.
+///
+/// # Arguments
+/// * `kind` - Upstream the client talks to
+/// * `proxy` - URL of the proxy, as validated by `proxy_from_str`
+/// * `timeouts` - Timeouts of the client
+pub fn client(
+    kind: ClientKind,
+    proxy: Option<String>,
+    timeouts: &Timeouts,
+) -> Result<Client, ClewdrError> {
+    let key = PoolKey {
+        kind,
+        proxy,
+        timeouts: timeouts.to_owned(),
+    };
+    if let Some(client) = CLIENTS.get(&key) {
+        return Ok(client);
+    }
+    let mut builder = ClientBuilder::new();
+    if kind == ClientKind::Claude {
+        builder = builder.emulation(Emulation::Chrome136);
+    }
+    if kind == ClientKind::Image {
+        // a proxy would resolve the name itself, past the checks of the resolver
+        builder = builder
+            .no_proxy()
+            .dns_resolver(Arc::new(PublicResolver))
+            .redirect(Policy::none());
+    } else if let Some(url) = key.proxy.as_deref() {
+        let proxy = proxy_from_str(url).map_err(|msg| ClewdrError::Whatever {
+            message: format!("Invalid proxy: {msg}"),
+            source: None,
+        })?;
+        builder = builder.proxy(proxy);
+    }
+    let client = apply_timeouts(builder, timeouts)
+        .build()
+        .context(WreqSnafu {
+            msg: "Failed to build client",
+        })?;
+    debug!("New {:?} client pooled", kind);
+    CLIENTS.insert(key, client.to_owned());
+    Ok(client)
+}
+
+/// Cookies an upstream sets during one session, such as Cloudflare's, pooled clients keep none
+///
+/// Responses handed to [`SessionJar::keep`] add theirs, requests send them after the account
+/// cookie, which wins over a cookie of the same name.
+#[derive(Clone, Default)]
+pub struct SessionJar(Arc<Jar>);
+
+impl SessionJar {
+    /// `Cookie` header of a request to `url`
+    ///
+    /// # Arguments
+    /// * `account` - The account cookie, e.g. `sessionKey=...`
+    /// * `url` - URL of the request
+    pub fn header(&self, account: &HeaderValue, url: &Url) -> HeaderValue {
+        let Some(set) = self.0.cookies(url) else {
+            return account.to_owned();
+        };
+        let (Ok(account_str), Ok(set)) = (account.to_str(), set.to_str()) else {
+            return account.to_owned();
+        };
+        let name = account_str.split('=').next().unwrap_or_default().trim();
+        let mut header = account_str.to_string();
+        for cookie in set.split("; ") {
+            if cookie.split('=').next() != Some(name) {
+                header.push_str("; ");
+                header.push_str(cookie);
+            }
+        }
+        HeaderValue::from_str(&header).unwrap_or_else(|_| account.to_owned())
+    }
+
+    /// Stores the cookies a response sets
+    pub fn keep(&self, res: &Response) {
+        self.0
+            .set_cookies(res.url(), &mut res.headers().get_all(SET_COOKIE).iter());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "198.18.0.1",
+            "240.0.0.1",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:192.168.1.1",
+            "::127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "2002:7f00:1::",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+        assert!(is_public("1.1.1.1".parse().unwrap()));
+        assert!(is_public("2606:4700::1111".parse().unwrap()));
+        assert!(is_public("64:ff9b::101:101".parse().unwrap()));
+        assert!(is_public("2002:101:101::".parse().unwrap()));
+    }
+
+    #[test]
+    fn session_cookies_follow_the_account_cookie() {
+        let url = Url::parse("https://claude.ai/api/bootstrap").unwrap();
+        let jar = SessionJar::default();
+        let account = HeaderValue::from_static("sessionKey=sk-ant-account");
+        assert_eq!(jar.header(&account, &url), account);
+
+        jar.0.add_cookie_str("__cf_bm=abc; Path=/", &url);
+        jar.0
+            .add_cookie_str("sessionKey=sk-ant-other; Path=/", &url);
+        assert_eq!(
+            jar.header(&account, &url),
+            "sessionKey=sk-ant-account; __cf_bm=abc"
+        );
+    }
+}
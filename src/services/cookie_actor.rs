@@ -1,26 +1,75 @@
-use std::collections::{HashSet, VecDeque};
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{
+        Arc,
+        atomic::{AtomicI64, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
-use moka::sync::Cache;
-use ractor::{Actor, ActorProcessingErr, ActorRef, RpcReplyPort};
-use serde::Serialize;
+use moka::{Expiry, sync::Cache};
+use ractor::{Actor, ActorProcessingErr, ActorRef, RactorErr, RpcReplyPort};
+use serde::{Deserialize, Serialize};
 use snafu::{GenerateImplicitData, Location};
 use tracing::{error, info, warn};
 
 use crate::{
-    config::{CLEWDR_CONFIG, ClewdrConfig, CookieStatus, Reason, UselessCookie},
+    config::{
+        CLEWDR_CONFIG, Capability, ClewdrConfig, CookieStatus, HealthEvent, Priority, Reason,
+        UselessCookie, WebhookEvent,
+    },
     error::ClewdrError,
-    persistence::StorageLayer,
+    persistence::{Affinity, StorageBatch, StorageLayer},
+    services::{
+        quota,
+        wait_queue::{self, WaitQueue},
+        webhook,
+    },
 };
 
 const INTERVAL: u64 = 300;
+/// How often exhausted cookies are re-checked while requests are queued
+const QUEUE_RECHECK: Duration = Duration::from_secs(1);
+/// How long a prompt hash sticks to its cookie without requests, in seconds
+const AFFINITY_TTL: i64 = 60 * 60;
+/// Stored pins are renewed at most this often, in seconds, the in-memory one on every hit
+const AFFINITY_RENEW: i64 = 15 * 60;
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CookieStatusInfo {
     pub valid: Vec<CookieStatus>,
     pub exhausted: Vec<CookieStatus>,
     pub invalid: Vec<UselessCookie>,
 }
 
+/// What a request needs from the cookie it is dispatched
+#[derive(Debug, Clone, Default)]
+pub struct CookieRequest {
+    /// Prompt or conversation hash the cookie sticks to
+    pub hash: Option<u64>,
+    /// Tags the cookie must carry
+    pub tags: Vec<String>,
+    pub priority: Priority,
+    /// Capability the cookie must have, cookies not bootstrapped yet are assumed to have it
+    pub capability: Option<Capability>,
+}
+
+impl CookieRequest {
+    /// Whether `cookie` may serve the request
+    ///
+    /// Paused cookies never do, nor cookies lacking the required capability. Batch requests
+    /// skip cookies whose last usage probe left less than `batch_min_quota` percent, cookies
+    /// not probed recently are not skipped.
+    fn accepts(&self, cookie: &CookieStatus) -> bool {
+        !cookie.disabled
+            && cookie.has_tags(&self.tags)
+            && self.capability.is_none_or(|c| cookie.has_capability(c))
+            && (self.priority != Priority::Batch
+                || quota::remaining(&cookie.cookie.to_string())
+                    .is_none_or(|r| r >= CLEWDR_CONFIG.load().batch_min_quota))
+    }
+}
+
 /// Messages that the CookieActor can handle
 #[derive(Debug)]
 enum CookieActorMessage {
@@ -31,20 +80,86 @@ enum CookieActorMessage {
     /// Check for timed out Cookies
     CheckReset,
     /// Request to get a Cookie
-    Request(Option<u64>, RpcReplyPort<Result<CookieStatus, ClewdrError>>),
+    Request(
+        CookieRequest,
+        RpcReplyPort<Result<CookieStatus, ClewdrError>>,
+    ),
     /// Get all Cookie status information
     GetStatus(RpcReplyPort<CookieStatusInfo>),
     /// Delete a Cookie
     Delete(CookieStatus, RpcReplyPort<Result<(), ClewdrError>>),
+    /// Pause or resume a Cookie
+    SetDisabled(CookieStatus, bool, RpcReplyPort<Result<(), ClewdrError>>),
+    /// Pin an organization of a Cookie, or unpin it
+    PinOrg(
+        CookieStatus,
+        Option<String>,
+        RpcReplyPort<Result<(), ClewdrError>>,
+    ),
+    /// Replace the tags of a Cookie
+    SetTags(
+        CookieStatus,
+        Vec<String>,
+        RpcReplyPort<Result<(), ClewdrError>>,
+    ),
+    /// Append a health event to a Cookie
+    RecordHealth(CookieStatus, HealthEvent),
+    /// Replace the whole pool
+    Restore(CookieStatusInfo, RpcReplyPort<()>),
+}
+
+/// Cookie a prompt hash sticks to
+#[derive(Debug, Clone)]
+struct Sticky {
+    cookie: CookieStatus,
+    /// Expiry of the stored pin, unix timestamp
+    stored_until: i64,
+}
+
+/// Pins reloaded from storage keep their stored expiry, every hit renews them for `AFFINITY_TTL`
+struct AffinityExpiry;
+
+impl Expiry<u64, Sticky> for AffinityExpiry {
+    fn expire_after_create(&self, _: &u64, sticky: &Sticky, _: Instant) -> Option<Duration> {
+        let left = sticky.stored_until - chrono::Utc::now().timestamp();
+        Some(Duration::from_secs(left.clamp(0, AFFINITY_TTL) as u64))
+    }
+
+    fn expire_after_read(
+        &self,
+        _: &u64,
+        _: &Sticky,
+        _: Instant,
+        _: Option<Duration>,
+        _: Instant,
+    ) -> Option<Duration> {
+        Some(Duration::from_secs(AFFINITY_TTL as u64))
+    }
+
+    fn expire_after_update(
+        &self,
+        _: &u64,
+        _: &Sticky,
+        _: Instant,
+        _: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(Duration::from_secs(AFFINITY_TTL as u64))
+    }
 }
 
 /// CookieActor state - manages collections of cookies
 #[derive(Debug)]
 struct CookieActorState {
+    /// Tenant owning the pool, `None` for the shared pool
+    tenant: Option<String>,
+    /// Whether the tenant's pool lives in its own storage rows instead of the config
+    stored: bool,
     valid: VecDeque<CookieStatus>,
     exhausted: HashSet<CookieStatus>,
     invalid: HashSet<UselessCookie>,
-    moka: Cache<u64, CookieStatus>,
+    moka: Cache<u64, Sticky>,
+    /// Queued requests, higher priorities first
+    waiters: WaitQueue<CookieRequest, CookieStatus>,
 }
 
 /// Cookie actor that handles cookie distribution, collection, and status tracking using Ractor
@@ -54,16 +169,33 @@ struct CookieActor {
 
 impl CookieActor {
     /// Saves the current state of cookies to the configuration
+    ///
+    /// A tenant pool with its own storage is written through batches only.
     fn save(state: &CookieActorState) {
+        if state.stored {
+            return;
+        }
         CLEWDR_CONFIG.rcu(|config| {
             let mut config = ClewdrConfig::clone(config);
-            config.cookie_array = state
+            let cookies = state
                 .valid
                 .iter()
                 .chain(state.exhausted.iter())
                 .cloned()
                 .collect();
-            config.wasted_cookie = state.invalid.clone();
+            let wasted = state.invalid.clone();
+            match state.tenant.as_deref() {
+                Some(name) => {
+                    if let Some(tenant) = config.tenant_mut(name) {
+                        tenant.cookie_array = cookies;
+                        tenant.wasted_cookie = wasted;
+                    }
+                }
+                None => {
+                    config.cookie_array = cookies;
+                    config.wasted_cookie = wasted;
+                }
+            }
             config
         });
 
@@ -102,44 +234,220 @@ impl CookieActor {
         if reset_cookies.is_empty() {
             return;
         }
-        // 将重置的 cookies 放回 valid，并进行增量 upsert
-        for c in reset_cookies.into_iter() {
-            state.valid.push_back(c.clone());
-            if storage.is_enabled() {
-                tokio::spawn(async move {
-                    let _ = storage.persist_cookie_upsert(&c).await;
-                });
-            }
-        }
+        // 将重置的 cookies 放回 valid，并在一个事务中增量 upsert
+        state.valid.extend(reset_cookies.iter().cloned());
+        Self::persist(
+            storage,
+            StorageBatch {
+                cookies: reset_cookies,
+                ..Default::default()
+            },
+        );
         Self::log(state);
     }
 
+    /// Writes a batch to storage in the background, a no-op in file mode
+    fn persist(storage: &'static dyn StorageLayer, batch: StorageBatch) {
+        if !storage.is_enabled() || batch.is_empty() {
+            return;
+        }
+        tokio::spawn(async move {
+            if let Err(e) = storage.persist_batch(&batch).await {
+                error!("Failed to persist cookies: {}", e);
+            }
+        });
+    }
+
     /// Dispatches a cookie for use
+    ///
+    /// A sticky cookie cached for the request's hash wins, then `preferred` when it is
+    /// still valid, then the head of the local rotation. Cookies the request does not
+    /// accept are skipped.
+    ///
+    /// # Returns
+    /// * `Err(NoCapableCookie)` - No cookie of the pool, exhausted ones included, has the
+    ///   required capability, waiting for a reset would not help
     fn dispatch(
         &self,
         state: &mut CookieActorState,
-        hash: Option<u64>,
+        request: &CookieRequest,
+        preferred: Option<&str>,
     ) -> Result<CookieStatus, ClewdrError> {
         Self::reset(state, self.storage);
-        if let Some(hash) = hash
-            && let Some(cookie) = state.moka.get(&hash)
-            && let Some(cookie) = state.valid.iter().find(|&c| c == &cookie)
-        {
-            // renew moka cache
-            state.moka.insert(hash, cookie.clone());
-            return Ok(cookie.clone());
+        if let Some(cookie) = Self::sticky(state, self.storage, request) {
+            return Ok(cookie);
         }
+        let index = preferred
+            .and_then(|p| {
+                state
+                    .valid
+                    .iter()
+                    .position(|c| request.accepts(c) && c.cookie.to_string() == p)
+            })
+            .or_else(|| state.valid.iter().position(|c| request.accepts(c)))
+            .ok_or_else(|| {
+                if let Some(capability) = request.capability
+                    && !state
+                        .valid
+                        .iter()
+                        .chain(state.exhausted.iter())
+                        .any(|c| c.has_capability(capability))
+                {
+                    return ClewdrError::NoCapableCookie { capability };
+                }
+                let reset = state.exhausted.iter().filter_map(|c| c.reset_time).min();
+                quota::record_pool_reset(reset);
+                if state.valid.iter().all(|c| c.disabled) {
+                    webhook::emit(
+                        WebhookEvent::PoolEmpty,
+                        "cookies",
+                        "No cookie is available",
+                        serde_json::json!({ "pool": "cookies", "resets_at": reset }),
+                    );
+                }
+                ClewdrError::NoCookieAvailable
+            })?;
         let cookie = state
             .valid
-            .pop_front()
+            .remove(index)
             .ok_or(ClewdrError::NoCookieAvailable)?;
         state.valid.push_back(cookie.clone());
-        if let Some(hash) = hash {
-            state.moka.insert(hash, cookie.clone());
+        if let Some(hash) = request.hash {
+            Self::pin(state, self.storage, hash, &cookie);
         }
         Ok(cookie)
     }
 
+    /// The cookie cached for the request's hash, if it is still valid and accepted
+    fn sticky(
+        state: &CookieActorState,
+        storage: &'static dyn StorageLayer,
+        request: &CookieRequest,
+    ) -> Option<CookieStatus> {
+        let hash = request.hash?;
+        let sticky = state.moka.get(&hash)?;
+        let cookie = state
+            .valid
+            .iter()
+            .find(|&c| c == &sticky.cookie && request.accepts(c))?
+            .clone();
+        Self::pin(state, storage, hash, &cookie);
+        Some(cookie)
+    }
+
+    /// Sticks `hash` to `cookie`, storing the pin when it is new or due for renewal
+    fn pin(
+        state: &CookieActorState,
+        storage: &'static dyn StorageLayer,
+        hash: u64,
+        cookie: &CookieStatus,
+    ) {
+        let now = chrono::Utc::now().timestamp();
+        let stored_until = match state.moka.get(&hash) {
+            Some(s)
+                if s.cookie == *cookie && s.stored_until - now > AFFINITY_TTL - AFFINITY_RENEW =>
+            {
+                s.stored_until
+            }
+            _ => {
+                let affinity = Affinity {
+                    hash,
+                    cookie: cookie.cookie.to_string(),
+                    expires_at: now + AFFINITY_TTL,
+                };
+                if storage.is_enabled() {
+                    tokio::spawn(async move {
+                        if let Err(e) = storage.persist_affinity(&affinity).await {
+                            warn!("Failed to persist prompt affinity: {}", e);
+                        }
+                    });
+                }
+                now + AFFINITY_TTL
+            }
+        };
+        state.moka.insert(
+            hash,
+            Sticky {
+                cookie: cookie.clone(),
+                stored_until,
+            },
+        );
+    }
+
+    /// Next cookie of the rotation shared with other instances, if the storage shares one
+    async fn shared_next(
+        &self,
+        state: &CookieActorState,
+        request: &CookieRequest,
+    ) -> Option<String> {
+        if Self::sticky(state, self.storage, request).is_some() {
+            return None;
+        }
+        self.storage.next_cookie().await.unwrap_or_else(|e| {
+            warn!("Shared cookie rotation unavailable: {}", e);
+            None
+        })
+    }
+
+    /// Takes the cluster lease on a dispatched cookie when `cluster_mode` is on
+    ///
+    /// Cookies leased by another instance are skipped in favour of the next ones in
+    /// the rotation. Storage errors are logged and the cookie is used anyway.
+    async fn lease(
+        &self,
+        state: &mut CookieActorState,
+        mut cookie: CookieStatus,
+        request: &CookieRequest,
+    ) -> Result<CookieStatus, ClewdrError> {
+        let config = CLEWDR_CONFIG.load();
+        if !config.cluster_mode {
+            return Ok(cookie);
+        }
+        let retry = CookieRequest {
+            hash: None,
+            ..request.to_owned()
+        };
+        for _ in 0..state.valid.len() {
+            match self
+                .storage
+                .acquire_lease(
+                    &cookie.cookie.to_string(),
+                    config.instance_id(),
+                    config.cluster_lease_ttl,
+                )
+                .await
+            {
+                Ok(true) => {
+                    if let Some(hash) = request.hash {
+                        Self::pin(state, self.storage, hash, &cookie);
+                    }
+                    return Ok(cookie);
+                }
+                Ok(false) => cookie = self.dispatch(state, &retry, None)?,
+                Err(e) => {
+                    warn!("Cookie lease unavailable, using cookie unleased: {}", e);
+                    return Ok(cookie);
+                }
+            }
+        }
+        Err(ClewdrError::NoCookieAvailable)
+    }
+
+    /// Gives up this instance's cluster lease on a cookie in the background
+    fn release(storage: &'static dyn StorageLayer, cookie: &CookieStatus) {
+        let config = CLEWDR_CONFIG.load();
+        if !config.cluster_mode {
+            return;
+        }
+        let cookie = cookie.cookie.to_string();
+        let holder = config.instance_id().to_string();
+        tokio::spawn(async move {
+            if let Err(e) = storage.release_lease(&cookie, &holder).await {
+                warn!("Failed to release cookie lease: {}", e);
+            }
+        });
+    }
+
     /// Collects a returned cookie and processes it based on the return reason
     fn collect(state: &mut CookieActorState, mut cookie: CookieStatus, reason: Option<Reason>) {
         let Some(reason) = reason else {
@@ -149,6 +457,7 @@ impl CookieActor {
             }
             return;
         };
+        Self::notify(&cookie, &reason);
         let mut find_remove = |cookie: &CookieStatus| {
             state.valid.retain(|c| c != cookie);
         };
@@ -199,14 +508,58 @@ impl CookieActor {
         Self::log(state);
     }
 
+    /// Notifies webhooks of a banned or rate limited cookie
+    fn notify(cookie: &CookieStatus, reason: &Reason) {
+        let (event, reset) = match reason {
+            Reason::Banned | Reason::Disabled => (WebhookEvent::CookieBanned, None),
+            Reason::TooManyRequest(ts) => (WebhookEvent::CookieRateLimited, Some(*ts)),
+            _ => return,
+        };
+        webhook::emit(
+            event,
+            &cookie.cookie,
+            format!("Cookie {}: {}", cookie.cookie.ellipse(), reason),
+            serde_json::json!({
+                "cookie": cookie.cookie.ellipse(),
+                "reason": reason.to_string(),
+                "resets_at": reset,
+            }),
+        );
+    }
+
+    /// Hands freed cookies to queued requests in queue order
+    ///
+    /// A waiter no cookie can serve, e.g. for its tags, stays queued without holding up
+    /// the ones behind it. Keeps re-checking resets while requests are still waiting
+    async fn serve_waiters(
+        &self,
+        myself: &ActorRef<CookieActorMessage>,
+        state: &mut CookieActorState,
+    ) {
+        let mut index = 0;
+        state.waiters.prune();
+        while let Some(request) = state.waiters.get(index).cloned() {
+            let result = match self.dispatch(state, &request, None) {
+                Ok(cookie) => self.lease(state, cookie, &request).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(cookie) => state.waiters.answer(index, Ok(cookie)),
+                Err(e @ ClewdrError::NoCapableCookie { .. }) => state.waiters.answer(index, Err(e)),
+                Err(_) if state.valid.iter().all(|c| c.disabled) => break,
+                Err(_) => index += 1,
+            }
+        }
+        if !state.waiters.is_empty() {
+            myself.send_after(QUEUE_RECHECK, || CookieActorMessage::CheckReset);
+        }
+    }
+
     /// Accepts a new cookie into the valid collection
     fn accept(state: &mut CookieActorState, cookie: CookieStatus) {
-        if CLEWDR_CONFIG.load().cookie_array.contains(&cookie)
-            || CLEWDR_CONFIG
-                .load()
-                .wasted_cookie
-                .iter()
-                .any(|c| *c == cookie)
+        if state.valid.contains(&cookie)
+            || state.exhausted.contains(&cookie)
+            || state.invalid.iter().any(|c| *c == cookie)
         {
             warn!("Cookie already exists");
             return;
@@ -216,6 +569,142 @@ impl CookieActor {
         Self::log(state);
     }
 
+    /// Pauses or resumes a valid or exhausted cookie
+    ///
+    /// # Returns
+    /// * `Option<CookieStatus>` - The updated cookie, `None` if it is not in the pool
+    fn set_disabled(
+        state: &mut CookieActorState,
+        cookie: &CookieStatus,
+        disabled: bool,
+    ) -> Option<CookieStatus> {
+        let updated = if let Some(c) = state.valid.iter_mut().find(|c| *c == cookie) {
+            c.disabled = disabled;
+            c.clone()
+        } else {
+            let mut c = state.exhausted.take(cookie)?;
+            c.disabled = disabled;
+            state.exhausted.insert(c.clone());
+            c
+        };
+        info!(
+            "Cookie {}: {}",
+            if disabled { "paused" } else { "resumed" },
+            updated.cookie.ellipse()
+        );
+        Self::save(state);
+        Some(updated)
+    }
+
+    /// Pins an organization of a valid or exhausted cookie, `None` goes back to the default pick
+    ///
+    /// A Claude Code token of another organization is dropped, so the next request exchanges one
+    /// for the pinned organization.
+    ///
+    /// # Returns
+    /// * `Option<CookieStatus>` - The updated cookie, `None` if it is not in the pool
+    fn pin_org(
+        state: &mut CookieActorState,
+        cookie: &CookieStatus,
+        org: Option<String>,
+    ) -> Option<CookieStatus> {
+        let pin = |c: &mut CookieStatus| {
+            if org
+                .as_ref()
+                .is_some_and(|o| c.token.as_ref().is_some_and(|t| t.organization.uuid != *o))
+            {
+                c.token = None;
+            }
+            c.pinned_org = org.to_owned();
+        };
+        let updated = if let Some(c) = state.valid.iter_mut().find(|c| *c == cookie) {
+            pin(c);
+            c.clone()
+        } else {
+            let mut c = state.exhausted.take(cookie)?;
+            pin(&mut c);
+            state.exhausted.insert(c.clone());
+            c
+        };
+        info!(
+            "Cookie {} pinned to organization: {}",
+            updated.cookie.ellipse(),
+            updated.pinned_org.as_deref().unwrap_or("default")
+        );
+        Self::save(state);
+        Some(updated)
+    }
+
+    /// Replaces the tags of a valid or exhausted cookie
+    ///
+    /// # Returns
+    /// * `Option<CookieStatus>` - The updated cookie, `None` if it is not in the pool
+    fn set_tags(
+        state: &mut CookieActorState,
+        cookie: &CookieStatus,
+        tags: Vec<String>,
+    ) -> Option<CookieStatus> {
+        let updated = if let Some(c) = state.valid.iter_mut().find(|c| *c == cookie) {
+            c.tags = tags;
+            c.clone()
+        } else {
+            let mut c = state.exhausted.take(cookie)?;
+            c.tags = tags;
+            state.exhausted.insert(c.clone());
+            c
+        };
+        info!(
+            "Cookie {} tagged: {}",
+            updated.cookie.ellipse(),
+            if updated.tags.is_empty() {
+                "none".to_string()
+            } else {
+                updated.tags.join(", ")
+            }
+        );
+        Self::save(state);
+        Some(updated)
+    }
+
+    /// Appends a health event to a valid or exhausted cookie
+    ///
+    /// # Returns
+    /// * `Option<CookieStatus>` - The updated cookie, `None` if it is not in the pool
+    fn record_health(
+        state: &mut CookieActorState,
+        cookie: &CookieStatus,
+        event: HealthEvent,
+    ) -> Option<CookieStatus> {
+        let updated = if let Some(c) = state.valid.iter_mut().find(|c| *c == cookie) {
+            c.record_health(event);
+            c.clone()
+        } else {
+            let mut c = state.exhausted.take(cookie)?;
+            c.record_health(event);
+            state.exhausted.insert(c.clone());
+            c
+        };
+        Self::save(state);
+        Some(updated)
+    }
+
+    /// Replaces the whole pool, cookies are sorted into valid and exhausted by their reset time
+    fn restore(state: &mut CookieActorState, pool: CookieStatusInfo) {
+        let (exhausted, valid): (Vec<_>, Vec<_>) = pool
+            .valid
+            .into_iter()
+            .chain(pool.exhausted)
+            .partition(|c| c.reset_time.is_some());
+        state.valid = valid.into();
+        state.exhausted = exhausted.into_iter().collect();
+        state.invalid = pool.invalid.into_iter().collect();
+        // pins may point at cookies that are gone
+        state.moka.invalidate_all();
+        info!("Cookie pool restored");
+        Self::save(state);
+        Self::log(state);
+    }
+
     /// Creates a report of all cookie statuses
     fn report(state: &CookieActorState) -> CookieStatusInfo {
         CookieStatusInfo {
@@ -250,41 +739,83 @@ impl CookieActor {
 impl Actor for CookieActor {
     type Msg = CookieActorMessage;
     type State = CookieActorState;
-    type Arguments = ();
+    /// Tenant owning the pool, `None` for the shared pool
+    type Arguments = Option<String>;
 
     async fn pre_start(
         &self,
         _myself: ActorRef<Self::Msg>,
-        _arguments: Self::Arguments,
+        tenant: Self::Arguments,
     ) -> Result<Self::State, ActorProcessingErr> {
-        let valid = VecDeque::from_iter(
-            CLEWDR_CONFIG
-                .load()
-                .cookie_array
-                .iter()
-                .filter(|c| c.reset_time.is_none())
-                .cloned(),
-        );
-        let exhausted = HashSet::from_iter(
-            CLEWDR_CONFIG
-                .load()
-                .cookie_array
-                .iter()
-                .filter(|c| c.reset_time.is_some())
-                .cloned(),
-        );
-        let invalid = HashSet::from_iter(CLEWDR_CONFIG.load().wasted_cookie.iter().cloned());
+        let stored = tenant.is_some() && self.storage.is_enabled();
+        let (cookies, wasted) = if stored {
+            let (valid, exhausted, invalid) = self.storage.load_cookies().await?;
+            (
+                valid.into_iter().chain(exhausted).collect(),
+                invalid.into_iter().collect(),
+            )
+        } else {
+            let config = CLEWDR_CONFIG.load();
+            match tenant.as_deref() {
+                Some(name) => config
+                    .tenant(name)
+                    .map(|t| (t.cookie_array.to_owned(), t.wasted_cookie.to_owned()))
+                    .unwrap_or_default(),
+                None => (
+                    config.cookie_array.to_owned(),
+                    config.wasted_cookie.to_owned(),
+                ),
+            }
+        };
+        let valid = VecDeque::from_iter(cookies.iter().filter(|c| c.reset_time.is_none()).cloned());
+        let exhausted =
+            HashSet::from_iter(cookies.iter().filter(|c| c.reset_time.is_some()).cloned());
+        let invalid = wasted;
 
         let moka = Cache::builder()
             .max_capacity(1000)
-            .time_to_idle(std::time::Duration::from_secs(60 * 60))
+            .expire_after(AffinityExpiry)
             .build();
+        if self.storage.is_enabled() {
+            // prompt caching of Claude Code agents keeps hitting the same cookie after a restart
+            match self
+                .storage
+                .load_affinity(chrono::Utc::now().timestamp())
+                .await
+            {
+                Ok(pins) => {
+                    let mut restored = 0;
+                    for pin in pins {
+                        let Some(cookie) = valid
+                            .iter()
+                            .chain(exhausted.iter())
+                            .find(|c| c.cookie.to_string() == pin.cookie)
+                        else {
+                            continue;
+                        };
+                        moka.insert(
+                            pin.hash,
+                            Sticky {
+                                cookie: cookie.clone(),
+                                stored_until: pin.expires_at,
+                            },
+                        );
+                        restored += 1;
+                    }
+                    info!("Restored {} prompt affinities", restored);
+                }
+                Err(e) => warn!("Failed to load prompt affinities: {}", e),
+            }
+        }
 
         let state = CookieActorState {
+            tenant,
+            stored,
             valid,
             exhausted,
             invalid,
             moka,
+            waiters: WaitQueue::default(),
         };
 
         CookieActor::log(&state);
@@ -293,69 +824,181 @@ impl Actor for CookieActor {
 
     async fn handle(
         &self,
-        _myself: ActorRef<Self::Msg>,
+        myself: ActorRef<Self::Msg>,
         message: Self::Msg,
         state: &mut Self::State,
     ) -> Result<(), ActorProcessingErr> {
         match message {
-            CookieActorMessage::Return(cookie, reason) => {
-                let orig = cookie.clone();
-                let r = reason.clone();
-                Self::collect(state, cookie, reason);
-                let storage = self.storage;
-                if storage.is_enabled() {
-                    tokio::spawn(async move {
-                        match r {
-                            None => {
-                                let _ = storage.persist_cookie_upsert(&orig).await;
-                            }
-                            Some(Reason::TooManyRequest(ts)) | Some(Reason::Restricted(ts)) => {
-                                let mut c = orig.clone();
-                                c.reset_time = Some(ts);
-                                let _ = storage.persist_cookie_upsert(&c).await;
-                            }
-                            Some(reason) => {
-                                let u = UselessCookie::new(orig.cookie.clone(), reason);
-                                let _ = storage.persist_wasted_upsert(&u).await;
-                            }
+            CookieActorMessage::Return(mut cookie, reason) => {
+                // the pause flag, tags and pin may have changed while the cookie was in use
+                if let Some(c) = state
+                    .valid
+                    .iter()
+                    .chain(state.exhausted.iter())
+                    .find(|c| **c == cookie)
+                {
+                    cookie.disabled = c.disabled;
+                    cookie.tags = c.tags.to_owned();
+                    cookie.health = c.health.to_owned();
+                    if cookie.pinned_org != c.pinned_org {
+                        cookie.pinned_org = c.pinned_org.to_owned();
+                        cookie.token = c.token.to_owned();
+                    }
+                    // the background refresh may have renewed the token meanwhile, keep the newest
+                    let expiry = |c: &CookieStatus| c.token.as_ref().map(|t| t.expires_at);
+                    if expiry(c) > expiry(&cookie) {
+                        cookie.token = c.token.to_owned();
+                    }
+                }
+                let batch = match reason {
+                    None => StorageBatch {
+                        cookies: vec![cookie.clone()],
+                        ..Default::default()
+                    },
+                    Some(Reason::TooManyRequest(ts)) | Some(Reason::Restricted(ts)) => {
+                        let mut c = cookie.clone();
+                        c.reset_time = Some(ts);
+                        StorageBatch {
+                            cookies: vec![c],
+                            ..Default::default()
                         }
-                    });
+                    }
+                    Some(ref reason) => StorageBatch {
+                        wasted: vec![UselessCookie::new(cookie.cookie.clone(), reason.clone())],
+                        ..Default::default()
+                    },
+                };
+                if reason.as_ref().is_some_and(|r| *r != Reason::NormalPro) {
+                    Self::release(self.storage, &cookie);
                 }
+                Self::collect(state, cookie, reason);
+                Self::persist(self.storage, batch);
             }
             CookieActorMessage::Submit(cookie) => {
-                let c = cookie.clone();
+                let batch = StorageBatch {
+                    cookies: vec![cookie.clone()],
+                    ..Default::default()
+                };
                 Self::accept(state, cookie);
-                let storage = self.storage;
-                if storage.is_enabled() {
-                    tokio::spawn(async move {
-                        if let Err(e) = storage.persist_cookie_upsert(&c).await {
-                            error!("Failed to upsert cookie: {}", e);
-                        }
-                    });
-                }
+                Self::persist(self.storage, batch);
+                self.serve_waiters(&myself, state).await;
             }
             CookieActorMessage::CheckReset => {
                 Self::reset(state, self.storage);
+                self.serve_waiters(&myself, state).await;
             }
-            CookieActorMessage::Request(cache_hash, reply_port) => {
-                let result = self.dispatch(state, cache_hash);
-                reply_port.send(result)?;
+            CookieActorMessage::Request(request, reply_port) => {
+                let preferred = self.shared_next(state, &request).await;
+                let result = match self.dispatch(state, &request, preferred.as_deref()) {
+                    Ok(cookie) => self.lease(state, cookie, &request).await,
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Err(ClewdrError::NoCookieAvailable) => {
+                        let was_empty = state.waiters.is_empty();
+                        match state
+                            .waiters
+                            .push_ranked(request, reply_port, |r| r.priority)
+                        {
+                            Ok(()) if was_empty => {
+                                myself.send_after(QUEUE_RECHECK, || CookieActorMessage::CheckReset);
+                            }
+                            Ok(()) => {}
+                            Err(reply_port) => {
+                                reply_port.send(Err(ClewdrError::NoCookieAvailable))?;
+                            }
+                        }
+                    }
+                    result => reply_port.send(result)?,
+                }
             }
             CookieActorMessage::GetStatus(reply_port) => {
                 let status_info = Self::report(state);
                 reply_port.send(status_info)?;
             }
+            CookieActorMessage::SetDisabled(cookie, disabled, reply_port) => {
+                let Some(updated) = Self::set_disabled(state, &cookie, disabled) else {
+                    reply_port.send(Err(ClewdrError::UnexpectedNone {
+                        msg: "Cookie not found in valid or exhausted cookies",
+                    }))?;
+                    return Ok(());
+                };
+                reply_port.send(Ok(()))?;
+                Self::persist(
+                    self.storage,
+                    StorageBatch {
+                        cookies: vec![updated],
+                        ..Default::default()
+                    },
+                );
+                if !disabled {
+                    self.serve_waiters(&myself, state).await;
+                }
+            }
+            CookieActorMessage::PinOrg(cookie, org, reply_port) => {
+                let Some(updated) = Self::pin_org(state, &cookie, org) else {
+                    reply_port.send(Err(ClewdrError::UnexpectedNone {
+                        msg: "Cookie not found in valid or exhausted cookies",
+                    }))?;
+                    return Ok(());
+                };
+                reply_port.send(Ok(()))?;
+                Self::persist(
+                    self.storage,
+                    StorageBatch {
+                        cookies: vec![updated],
+                        ..Default::default()
+                    },
+                );
+            }
+            CookieActorMessage::SetTags(cookie, tags, reply_port) => {
+                let Some(updated) = Self::set_tags(state, &cookie, tags) else {
+                    reply_port.send(Err(ClewdrError::UnexpectedNone {
+                        msg: "Cookie not found in valid or exhausted cookies",
+                    }))?;
+                    return Ok(());
+                };
+                reply_port.send(Ok(()))?;
+                Self::persist(
+                    self.storage,
+                    StorageBatch {
+                        cookies: vec![updated],
+                        ..Default::default()
+                    },
+                );
+                self.serve_waiters(&myself, state).await;
+            }
+            CookieActorMessage::RecordHealth(cookie, event) => {
+                let Some(updated) = Self::record_health(state, &cookie, event) else {
+                    warn!("Health event for a cookie no longer in the pool");
+                    return Ok(());
+                };
+                Self::persist(
+                    self.storage,
+                    StorageBatch {
+                        cookies: vec![updated],
+                        ..Default::default()
+                    },
+                );
+            }
+            CookieActorMessage::Restore(pool, reply_port) => {
+                Self::restore(state, pool);
+                reply_port.send(())?;
+                self.serve_waiters(&myself, state).await;
+            }
             CookieActorMessage::Delete(cookie, reply_port) => {
-                let storage = self.storage;
                 let result = Self::delete(state, cookie.clone());
-                let should_cleanup = result.is_ok() && storage.is_enabled();
+                let deleted = result.is_ok();
                 reply_port.send(result)?;
-                if should_cleanup {
-                    tokio::spawn(async move {
-                        if let Err(e) = storage.delete_cookie_row(&cookie).await {
-                            error!("Failed to delete cookie row: {}", e);
-                        }
-                    });
+                if deleted {
+                    Self::release(self.storage, &cookie);
+                    Self::persist(
+                        self.storage,
+                        StorageBatch {
+                            deleted: vec![cookie],
+                            ..Default::default()
+                        },
+                    );
                 }
             }
         }
@@ -368,6 +1011,15 @@ impl Actor for CookieActor {
         state: &mut Self::State,
     ) -> Result<(), ActorProcessingErr> {
         CookieActor::save(state);
+        // file mode is covered by the config save above; shared storages get upserts only, as
+        // other instances still serve from the stored pool
+        let valid = Vec::from(state.valid.to_owned());
+        let exhausted = state.exhausted.iter().cloned().collect::<Vec<_>>();
+        let invalid = state.invalid.iter().cloned().collect::<Vec<_>>();
+        Self::persist(
+            self.storage,
+            StorageBatch::upsert(&valid, &exhausted, &invalid),
+        );
         Ok(())
     }
 }
@@ -376,6 +1028,8 @@ impl Actor for CookieActor {
 #[derive(Clone)]
 pub struct CookieActorHandle {
     actor_ref: ActorRef<CookieActorMessage>,
+    /// Unix timestamp of the latest cookie request
+    last_request: Arc<AtomicI64>,
 }
 
 impl CookieActorHandle {
@@ -388,11 +1042,31 @@ impl CookieActorHandle {
     pub async fn start_with_storage(
         storage: &'static dyn StorageLayer,
     ) -> Result<Self, ractor::SpawnErr> {
-        let (actor_ref, _join_handle) = Actor::spawn(None, CookieActor { storage }, ()).await?;
+        Self::spawn(storage, None).await
+    }
+
+    /// Create a CookieActor serving the pool of a tenant
+    ///
+    /// The pool is read from and written to the tenant's storage, or its entry of the config
+    /// in file mode.
+    pub async fn start_tenant(name: &str) -> Result<Self, ractor::SpawnErr> {
+        Self::spawn(
+            crate::persistence::tenant_storage(name),
+            Some(name.to_string()),
+        )
+        .await
+    }
+
+    async fn spawn(
+        storage: &'static dyn StorageLayer,
+        tenant: Option<String>,
+    ) -> Result<Self, ractor::SpawnErr> {
+        let (actor_ref, _join_handle) = Actor::spawn(None, CookieActor { storage }, tenant).await?;
 
         // Start the timeout checker
         let handle = Self {
             actor_ref: actor_ref.clone(),
+            last_request: Arc::new(AtomicI64::new(chrono::Utc::now().timestamp())),
         };
         handle.spawn_timeout_checker().await;
 
@@ -414,15 +1088,39 @@ impl CookieActorHandle {
     }
 
     /// Request a cookie from the cookie actor
-    pub async fn request(&self, cache_hash: Option<u64>) -> Result<CookieStatus, ClewdrError> {
-        ractor::call!(self.actor_ref, CookieActorMessage::Request, cache_hash).map_err(|e| {
-            ClewdrError::RactorError {
+    ///
+    /// With queueing enabled the request waits up to `queue_timeout` for a cookie to free up,
+    /// or `batch_queue_timeout` for batch requests. Higher priorities are served first.
+    pub async fn request(&self, request: CookieRequest) -> Result<CookieStatus, ClewdrError> {
+        self.last_request
+            .store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+        let timeout = wait_queue::wait_timeout().map(|t| match request.priority {
+            Priority::Batch => CLEWDR_CONFIG.load().batch_queue_timeout.max(1) * 1000,
+            _ => t,
+        });
+        let result = match timeout {
+            Some(timeout) => ractor::call_t!(
+                self.actor_ref,
+                CookieActorMessage::Request,
+                timeout,
+                request
+            ),
+            None => ractor::call!(self.actor_ref, CookieActorMessage::Request, request),
+        };
+        result.map_err(|e| match e {
+            RactorErr::Timeout => ClewdrError::NoCookieAvailable,
+            e => ClewdrError::RactorError {
                 loc: Location::generate(),
                 msg: format!("Failed to communicate with CookieActor for request operation: {e}"),
-            }
+            },
         })?
     }
 
+    /// Seconds since a cookie was last requested
+    pub fn idle_secs(&self) -> i64 {
+        chrono::Utc::now().timestamp() - self.last_request.load(Ordering::Relaxed)
+    }
+
     /// Return a cookie to the cookie actor
     pub async fn return_cookie(
         &self,
@@ -468,4 +1166,78 @@ impl CookieActorHandle {
             }
         })?
     }
+
+    /// Pause or resume a cookie, a paused cookie stays in the pool but is never dispatched
+    pub async fn set_disabled(
+        &self,
+        cookie: CookieStatus,
+        disabled: bool,
+    ) -> Result<(), ClewdrError> {
+        ractor::call!(
+            self.actor_ref,
+            CookieActorMessage::SetDisabled,
+            cookie,
+            disabled
+        )
+        .map_err(|e| ClewdrError::RactorError {
+            loc: Location::generate(),
+            msg: format!("Failed to communicate with CookieActor for pause operation: {e}"),
+        })?
+    }
+
+    /// Pin an organization of a cookie, `None` unpins it
+    pub async fn pin_org(
+        &self,
+        cookie: CookieStatus,
+        org: Option<String>,
+    ) -> Result<(), ClewdrError> {
+        ractor::call!(self.actor_ref, CookieActorMessage::PinOrg, cookie, org).map_err(|e| {
+            ClewdrError::RactorError {
+                loc: Location::generate(),
+                msg: format!("Failed to communicate with CookieActor for pin operation: {e}"),
+            }
+        })?
+    }
+
+    /// Replace the tags of a cookie, an empty list removes them
+    pub async fn set_tags(
+        &self,
+        cookie: CookieStatus,
+        tags: Vec<String>,
+    ) -> Result<(), ClewdrError> {
+        ractor::call!(self.actor_ref, CookieActorMessage::SetTags, cookie, tags).map_err(|e| {
+            ClewdrError::RactorError {
+                loc: Location::generate(),
+                msg: format!("Failed to communicate with CookieActor for tag operation: {e}"),
+            }
+        })?
+    }
+
+    /// Replace the whole pool with `pool`
+    ///
+    /// Only the in-memory pool and the config are updated, storage has to be written first.
+    pub async fn restore(&self, pool: CookieStatusInfo) -> Result<(), ClewdrError> {
+        ractor::call!(self.actor_ref, CookieActorMessage::Restore, pool).map_err(|e| {
+            ClewdrError::RactorError {
+                loc: Location::generate(),
+                msg: format!("Failed to communicate with CookieActor for restore operation: {e}"),
+            }
+        })
+    }
+
+    /// Append a health event to a cookie, the oldest events are dropped past the limit
+    pub async fn record_health(
+        &self,
+        cookie: CookieStatus,
+        event: HealthEvent,
+    ) -> Result<(), ClewdrError> {
+        ractor::cast!(
+            self.actor_ref,
+            CookieActorMessage::RecordHealth(cookie, event)
+        )
+        .map_err(|e| ClewdrError::RactorError {
+            loc: Location::generate(),
+            msg: format!("Failed to communicate with CookieActor for health operation: {e}"),
+        })
+    }
 }
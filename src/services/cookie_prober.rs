@@ -0,0 +1,109 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use colored::Colorize;
+use tracing::{info, warn};
+
+use crate::{
+    claude_web_state::ClaudeWebState,
+    config::{CLEWDR_CONFIG, CookieStatus},
+    error::ClewdrError,
+    services::cookie_actor::CookieActorHandle,
+};
+
+/// How long to wait before re-reading the config while probing is disabled
+const DISABLED_RECHECK: u64 = 60;
+
+/// Spawn the background cookie health prober.
+///
+/// Every `cookie_probe_interval` seconds a rotating sample of valid cookies is
+/// bootstrapped against Claude.ai, so banned, restricted or expired cookies are
+/// moved out of the valid pool before a user request lands on them.
+/// An interval of 0 disables probing; the setting is hot reloadable.
+pub fn spawn(handle: CookieActorHandle) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut cursor = 0usize;
+        let mut capabilities: HashMap<String, Vec<String>> = HashMap::new();
+        loop {
+            let interval = CLEWDR_CONFIG.load().cookie_probe_interval;
+            if interval == 0 {
+                tokio::time::sleep(Duration::from_secs(DISABLED_RECHECK)).await;
+                continue;
+            }
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            let Ok(status) = handle.get_status().await else {
+                continue;
+            };
+            let valid = status.valid;
+            if valid.is_empty() {
+                capabilities.clear();
+                continue;
+            }
+            let sample = CLEWDR_CONFIG
+                .load()
+                .cookie_probe_sample
+                .clamp(1, valid.len());
+            cursor %= valid.len();
+            let picked = valid
+                .iter()
+                .cycle()
+                .skip(cursor)
+                .take(sample)
+                .cloned()
+                .collect::<Vec<_>>();
+            cursor += sample;
+            for cookie in picked {
+                probe(&handle, cookie, &mut capabilities).await;
+            }
+            // forget cookies that left the valid pool
+            let alive: HashSet<_> = valid.iter().map(|c| c.cookie.to_string()).collect();
+            capabilities.retain(|k, _| alive.contains(k));
+        }
+    })
+}
+
+/// Bootstraps a single cookie and returns it to the actor with a reason if it is unusable
+async fn probe(
+    handle: &CookieActorHandle,
+    cookie: CookieStatus,
+    capabilities: &mut HashMap<String, Vec<String>>,
+) {
+    let mut state = ClaudeWebState::new(handle.to_owned());
+    if let Err(e) = state.use_cookie(cookie.to_owned()) {
+        warn!(
+            "[PROBE] {} failed to prepare: {}",
+            cookie.cookie.ellipse(),
+            e
+        );
+        return;
+    }
+    match state.bootstrap().await {
+        Ok(()) => {
+            let key = cookie.cookie.to_string();
+            let current = state.capabilities.to_owned();
+            if let Some(previous) = capabilities.insert(key, current.to_owned())
+                && previous != current
+            {
+                info!(
+                    "[PROBE] {} capabilities changed: {} -> {}",
+                    cookie.cookie.ellipse().green(),
+                    previous.join(", ").yellow(),
+                    current.join(", ").blue()
+                );
+            }
+        }
+        Err(ClewdrError::InvalidCookie { reason }) => {
+            warn!(
+                "[PROBE] {} is no longer usable: {}",
+                cookie.cookie.ellipse().red(),
+                reason
+            );
+            state.return_cookie(Some(reason)).await;
+        }
+        Err(e) => {
+            warn!("[PROBE] {} probe failed: {}", cookie.cookie.ellipse(), e);
+        }
+    }
+}
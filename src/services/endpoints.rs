@@ -0,0 +1,218 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde_json::{Value, json};
+use strum::Display;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::{config::CLEWDR_CONFIG, error::ClewdrError};
+
+/// Providers whose upstream endpoint can fail over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Upstream {
+    Claude,
+    Gemini,
+}
+
+/// Consecutive failures that take an endpoint out of rotation
+const DOWN_AFTER: u32 = 3;
+
+/// Health of one endpoint
+#[derive(Debug, Clone, Copy)]
+struct Health {
+    /// Moving average of successes, from 0 to 1
+    score: f64,
+    failures: u32,
+    down_since: Option<Instant>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            score: 1.0,
+            failures: 0,
+            down_since: None,
+        }
+    }
+}
+
+impl Health {
+    /// Whether the endpoint is in rotation, a down endpoint is retried after `failback`
+    fn usable(&self, now: Instant, failback: Duration) -> bool {
+        self.down_since
+            .is_none_or(|since| now.duration_since(since) >= failback)
+    }
+
+    /// Counts a request, returns whether the endpoint just went down
+    fn record(&mut self, ok: bool, now: Instant) -> bool {
+        self.score = self.score * 0.8 + if ok { 0.2 } else { 0.0 };
+        if ok {
+            self.failures = 0;
+            self.down_since = None;
+            return false;
+        }
+        self.failures += 1;
+        if self.failures < DOWN_AFTER {
+            return false;
+        }
+        // a failed retry after the failback period keeps it down for another period
+        let went_down = self.down_since.is_none();
+        self.down_since = Some(now);
+        went_down
+    }
+}
+
+static HEALTH: LazyLock<Mutex<HashMap<(Upstream, String), Health>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn upstreams(upstream: Upstream) -> Vec<Url> {
+    let config = CLEWDR_CONFIG.load();
+    match upstream {
+        Upstream::Claude => config.claude_upstreams(),
+        Upstream::Gemini => config.gemini_upstreams(),
+    }
+}
+
+/// Index of the endpoint to use: the first usable one in order, or the healthiest
+fn select(healths: &[Health], now: Instant, failback: Duration) -> usize {
+    healths
+        .iter()
+        .position(|h| h.usable(now, failback))
+        .or_else(|| {
+            healths
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.score.total_cmp(&b.1.score))
+                .map(|(i, _)| i)
+        })
+        .unwrap_or_default()
+}
+
+/// Picks the upstream endpoint of a provider for the next request
+///
+/// Endpoints are tried in their configured order, an endpoint taken out of rotation after
+/// repeated failures comes back once `endpoint_failback` seconds have passed.
+pub fn pick(upstream: Upstream) -> Url {
+    let mut list = upstreams(upstream);
+    if list.len() == 1 {
+        return list.swap_remove(0);
+    }
+    let failback = Duration::from_secs(CLEWDR_CONFIG.load().endpoint_failback);
+    let health = HEALTH.lock().unwrap_or_else(|e| e.into_inner());
+    let healths = list
+        .iter()
+        .map(|u| {
+            health
+                .get(&(upstream, u.to_string()))
+                .copied()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    let index = select(&healths, Instant::now(), failback);
+    list.swap_remove(index)
+}
+
+/// Whether an error points at the endpoint itself rather than the account or request
+fn is_endpoint_failure(err: &ClewdrError) -> bool {
+    match err {
+        ClewdrError::WreqError { .. } => true,
+        ClewdrError::ClaudeHttpError { code, .. } | ClewdrError::GeminiHttpError { code, .. } => {
+            matches!(code.as_u16(), 500 | 502 | 503 | 504)
+        }
+        _ => false,
+    }
+}
+
+/// Counts the result of a request sent to an endpoint
+///
+/// Errors unrelated to the endpoint, like an invalid cookie, are not counted.
+pub fn report<T>(upstream: Upstream, url: &Url, result: &Result<T, ClewdrError>) {
+    let ok = match result {
+        Ok(_) => true,
+        Err(e) if is_endpoint_failure(e) => false,
+        Err(_) => return,
+    };
+    let mut health = HEALTH.lock().unwrap_or_else(|e| e.into_inner());
+    let entry = health.entry((upstream, url.to_string())).or_default();
+    let was_down = entry.down_since.is_some();
+    if entry.record(ok, Instant::now()) {
+        warn!("[ENDPOINT] {} {} is down, failing over", upstream, url);
+    } else if ok && was_down {
+        info!("[ENDPOINT] {} {} is back", upstream, url);
+    }
+}
+
+/// Health of every configured endpoint, for the status API
+pub fn status() -> Value {
+    let now = Instant::now();
+    let failback = Duration::from_secs(CLEWDR_CONFIG.load().endpoint_failback);
+    let health = HEALTH.lock().unwrap_or_else(|e| e.into_inner());
+    let describe = |upstream: Upstream| {
+        let list = upstreams(upstream);
+        let healths = list
+            .iter()
+            .map(|u| {
+                health
+                    .get(&(upstream, u.to_string()))
+                    .copied()
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        let active = select(&healths, now, failback);
+        list.iter()
+            .zip(&healths)
+            .enumerate()
+            .map(|(i, (url, h))| {
+                json!({
+                    "url": url.to_string(),
+                    "score": h.score,
+                    "healthy": h.down_since.is_none(),
+                    "active": i == active,
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    json!({
+        "claude": describe(Upstream::Claude),
+        "gemini": describe(Upstream::Gemini),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fails_over_and_back() {
+        let failback = Duration::from_secs(300);
+        let start = Instant::now();
+        let mut healths = [Health::default(), Health::default()];
+        assert_eq!(select(&healths, start, failback), 0);
+
+        assert!(!healths[0].record(false, start));
+        assert!(!healths[0].record(false, start));
+        assert!(healths[0].record(false, start));
+        assert_eq!(select(&healths, start, failback), 1);
+
+        // the primary is retried after the failback period, a failure keeps it down
+        let later = start + failback;
+        assert_eq!(select(&healths, later, failback), 0);
+        assert!(!healths[0].record(false, later));
+        assert_eq!(select(&healths, later, failback), 1);
+        assert!(!healths[0].record(true, later + failback));
+        assert_eq!(select(&healths, later + failback, failback), 0);
+
+        // with every endpoint down the healthiest one is used
+        for _ in 0..DOWN_AFTER {
+            healths[0].record(false, start);
+            healths[1].record(false, start);
+        }
+        healths[0].record(false, start);
+        assert_eq!(select(&healths, start, failback), 1);
+    }
+}
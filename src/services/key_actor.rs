@@ -1,19 +1,50 @@
 use std::collections::{HashSet, VecDeque};
 
-use ractor::{Actor, ActorProcessingErr, ActorRef, RpcReplyPort};
-use serde::Serialize;
+use colored::Colorize;
+use ractor::{Actor, ActorProcessingErr, ActorRef, RactorErr, RpcReplyPort};
+use serde::{Deserialize, Serialize};
 use snafu::{GenerateImplicitData, Location};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::{
-    config::{CLEWDR_CONFIG, ClewdrConfig, KeyStatus},
+    config::{CLEWDR_CONFIG, ClewdrConfig, KeyStatus, WebhookEvent, quarantine_backoff},
     error::ClewdrError,
-    persistence::StorageLayer,
+    persistence::{StorageBatch, StorageLayer},
+    services::{
+        wait_queue::{self, WaitQueue},
+        webhook,
+    },
 };
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct KeyStatusInfo {
     pub valid: Vec<KeyStatus>,
+    /// Keys dropped after failing every probe while quarantined
+    pub invalid: Vec<KeyStatus>,
+}
+
+/// Result of probing a quarantined key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyProbe {
+    /// The key answered, it is dispatched again
+    Alive,
+    /// Gemini refused the key
+    Dead,
+    /// The probe said nothing about the key, such as a network error or a 429
+    Inconclusive,
+}
+
+/// Usage reported back after a key has been used
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeyUsage {
+    /// Tokens consumed by the request
+    pub tokens: u64,
+    /// Prompt tokens of the request
+    pub input_tokens: u64,
+    /// Output tokens of the request
+    pub output_tokens: u64,
+    /// Whether the request was answered with 429
+    pub rate_limited: bool,
 }
 
 /// Messages that the KeyActor can handle
@@ -23,16 +54,48 @@ enum KeyActorMessage {
     Return(KeyStatus),
     /// Submit a new Key
     Submit(KeyStatus),
+    /// Record usage of a Key
+    Record(KeyStatus, KeyUsage),
     /// Request to get a Key
     Request(RpcReplyPort<Result<KeyStatus, ClewdrError>>),
     /// Get all Key status information
     GetStatus(RpcReplyPort<KeyStatusInfo>),
     /// Delete a Key
     Delete(KeyStatus, RpcReplyPort<Result<(), ClewdrError>>),
+    /// Pause or resume a Key
+    SetDisabled(KeyStatus, bool, RpcReplyPort<Result<(), ClewdrError>>),
+    /// Result of probing a quarantined Key
+    Probed(KeyStatus, KeyProbe),
+    /// Replace the whole pool
+    Restore(KeyStatusInfo, RpcReplyPort<()>),
+}
+
+/// Collection of valid keys in dispatch order
+type KeyPool = VecDeque<KeyStatus>;
+
+/// Config entry a key pool is read from and saved to
+#[derive(Debug, Clone)]
+pub enum KeyPoolOwner {
+    /// The shared Gemini pool
+    Shared,
+    /// The Gemini pool of a tenant
+    Tenant(String),
+    /// The pool of an OpenAI compatible upstream
+    Upstream(String),
 }
 
 /// KeyActor state - manages the collection of valid keys
-type KeyActorState = VecDeque<KeyStatus>;
+#[derive(Debug)]
+struct KeyActorState {
+    owner: KeyPoolOwner,
+    /// Whether the valid keys of a tenant live in its own storage rows instead of the config
+    stored: bool,
+    /// Whether the dropped keys live in storage rows instead of the config
+    invalid_stored: bool,
+    valid: KeyPool,
+    invalid: HashSet<KeyStatus>,
+    waiters: WaitQueue<(), KeyStatus>,
+}
 
 /// Key actor that handles key distribution and status tracking using Ractor
 struct KeyActor {
@@ -41,13 +104,35 @@ struct KeyActor {
 
 impl KeyActor {
     /// Saves the current state of keys to the configuration
+    ///
+    /// Valid keys of a tenant with its own storage are written through upserts only.
     fn save(state: &KeyActorState) {
+        if state.stored {
+            return;
+        }
         CLEWDR_CONFIG.rcu(|config| {
             let mut config = ClewdrConfig::clone(config);
-            config.gemini_keys = state.iter().cloned().collect();
+            let keys = state.valid.iter().cloned().collect();
+            match &state.owner {
+                KeyPoolOwner::Shared => config.gemini_keys = keys,
+                KeyPoolOwner::Tenant(name) => {
+                    if let Some(tenant) = config.tenant_mut(name) {
+                        tenant.gemini_keys = keys;
+                    }
+                }
+                KeyPoolOwner::Upstream(name) => {
+                    if let Some(upstream) = config.openai_upstream_mut(name) {
+                        upstream.keys = keys;
+                    }
+                }
+            }
             config
         });
+        Self::write_config();
+    }
 
+    /// Writes the config to the file or storage in the background
+    fn write_config() {
         tokio::spawn(async move {
             let result = CLEWDR_CONFIG.load().save().await;
             match result {
@@ -57,45 +142,246 @@ impl KeyActor {
         });
     }
 
-    /// Dispatches a key for use
-    fn dispatch(state: &mut KeyActorState) -> Result<KeyStatus, ClewdrError> {
-        let key = state.pop_front().ok_or(ClewdrError::NoKeyAvailable)?;
-        state.push_back(key.to_owned());
-        Ok(key)
+    /// Saves the dropped keys to the configuration
+    ///
+    /// They are written along with the valid keys by [`Self::save`], or on their own when
+    /// the valid keys are stored elsewhere. Dropped keys kept in storage rows are written
+    /// where they change instead.
+    fn save_invalid(state: &KeyActorState) {
+        if state.invalid_stored {
+            return;
+        }
+        Self::set_invalid(&state.owner, &state.invalid);
+        if state.stored {
+            Self::write_config();
+        }
+    }
+
+    /// Sets the dropped keys of the owner's config entry
+    fn set_invalid(owner: &KeyPoolOwner, invalid: &HashSet<KeyStatus>) {
+        CLEWDR_CONFIG.rcu(|config| {
+            let mut config = ClewdrConfig::clone(config);
+            let invalid = invalid.to_owned();
+            match owner {
+                KeyPoolOwner::Shared => config.invalid_keys = invalid,
+                KeyPoolOwner::Tenant(name) => {
+                    if let Some(tenant) = config.tenant_mut(name) {
+                        tenant.invalid_keys = invalid;
+                    }
+                }
+                KeyPoolOwner::Upstream(name) => {
+                    if let Some(upstream) = config.openai_upstream_mut(name) {
+                        upstream.invalid_keys = invalid;
+                    }
+                }
+            }
+            config
+        });
+    }
+
+    /// Dispatches a key for use, skipping paused, quarantined and over quota keys
+    ///
+    /// The daily quotas only apply to Gemini keys.
+    fn dispatch(owner: &KeyPoolOwner, state: &mut KeyPool) -> Result<KeyStatus, ClewdrError> {
+        let config = CLEWDR_CONFIG.load();
+        let (request_quota, token_quota) = match owner {
+            KeyPoolOwner::Upstream(_) => (0, 0),
+            _ => (
+                config.gemini_daily_request_quota,
+                config.gemini_daily_token_quota,
+            ),
+        };
+        for _ in 0..state.len() {
+            let mut key = state.pop_front().ok_or(ClewdrError::NoKeyAvailable)?;
+            key.rollover();
+            if key.disabled || key.is_quarantined() || key.exceeded(request_quota, token_quota) {
+                state.push_back(key);
+                continue;
+            }
+            key.count_requests += 1;
+            key.total_requests += 1;
+            state.push_back(key.to_owned());
+            return Ok(key);
+        }
+        if state.iter().any(|k| !k.disabled && !k.is_quarantined()) {
+            warn!("All keys exceeded their daily quota");
+        }
+        match owner {
+            KeyPoolOwner::Upstream(name) => webhook::emit(
+                WebhookEvent::PoolEmpty,
+                "keys",
+                format!("No key of upstream {name} is available"),
+                serde_json::json!({ "pool": "keys", "upstream": name }),
+            ),
+            _ => webhook::emit(
+                WebhookEvent::PoolEmpty,
+                "keys",
+                "No Gemini key is available",
+                serde_json::json!({ "pool": "keys" }),
+            ),
+        }
+        Err(ClewdrError::NoKeyAvailable)
     }
 
     /// Collects (returns) a key back to the pool
-    fn collect(state: &mut KeyActorState, key: KeyStatus) {
-        let Some(pos) = state.iter().position(|k| *k == key) else {
+    ///
+    /// Daily counters are owned by the actor, only the 403 counter is taken from the returned key
+    fn collect(state: &mut KeyPool, key: KeyStatus) -> Option<KeyStatus> {
+        let Some(existing) = state.iter_mut().find(|k| **k == key) else {
             error!("Key not found in valid keys");
+            return None;
+        };
+        let failed = key.count_403 > existing.count_403;
+        existing.count_403 = key.count_403;
+        if failed {
+            Self::fail(existing);
+        }
+        Some(existing.to_owned())
+    }
+
+    /// Counts a 403 or 429 of a key towards its quarantine
+    fn fail(key: &mut KeyStatus) {
+        if key.record_failure(CLEWDR_CONFIG.load().key_quarantine_after) {
+            warn!(
+                "[KEY] {} quarantined after {} failures in a row",
+                key.key.ellipse().red(),
+                key.consecutive_failures
+            );
+        }
+    }
+
+    /// Adds reported usage to the daily counters of a key
+    fn record(state: &mut KeyPool, key: KeyStatus, usage: KeyUsage) -> Option<KeyStatus> {
+        let existing = state.iter_mut().find(|k| **k == key)?;
+        existing.rollover();
+        existing.count_tokens += usage.tokens;
+        existing.input_tokens += usage.input_tokens;
+        existing.output_tokens += usage.output_tokens;
+        if usage.rate_limited {
+            existing.count_429 += 1;
+            existing.total_429 += 1;
+            Self::fail(existing);
+        } else {
+            existing.record_success();
+        }
+        Some(existing.to_owned())
+    }
+
+    /// Persists the latest counters of a key
+    fn persist(storage: &'static dyn StorageLayer, key: Option<KeyStatus>) {
+        let Some(key) = key else {
             return;
         };
-        state[pos] = key;
+        if !storage.is_enabled() {
+            return;
+        }
+        tokio::spawn(async move {
+            if let Err(e) = storage.persist_key_upsert(&key).await {
+                error!("Failed to upsert key: {}", e);
+            }
+        });
     }
 
-    /// Accepts a new key into the valid collection
+    /// Accepts a new key into the valid collection, a dropped key submitted again is reinstated
     fn accept(state: &mut KeyActorState, key: KeyStatus) {
-        if CLEWDR_CONFIG.load().gemini_keys.contains(&key) {
+        if state.valid.contains(&key) {
             info!("Key already exists");
             return;
         }
-        state.push_back(key);
+        if state.invalid.remove(&key) {
+            Self::save_invalid(state);
+        }
+        state.valid.push_back(key);
+        Self::save(state);
+    }
+
+    /// Applies the result of probing a quarantined key
+    ///
+    /// # Returns
+    /// * `Option<KeyStatus>` - The updated key, `None` if it left the pool
+    fn probed(state: &mut KeyActorState, key: &KeyStatus, probe: KeyProbe) -> Option<KeyStatus> {
+        let pos = state.valid.iter().position(|k| k == key)?;
+        let existing = &mut state.valid[pos];
+        if !existing.is_quarantined() {
+            // lifted meanwhile, e.g. by a successful request
+            return Some(existing.to_owned());
+        }
+        match probe {
+            KeyProbe::Alive => {
+                existing.record_success();
+                info!("[KEY] {} recovered", existing.key.ellipse().green());
+            }
+            KeyProbe::Dead => {
+                if existing.record_failed_probe(CLEWDR_CONFIG.load().key_invalid_after) {
+                    let key = state.valid.remove(pos)?;
+                    warn!(
+                        "[KEY] {} dropped after {} failed probes",
+                        key.key.ellipse().red(),
+                        key.failed_probes
+                    );
+                    state.invalid.insert(key);
+                    Self::save_invalid(state);
+                    Self::save(state);
+                    return None;
+                }
+            }
+            KeyProbe::Inconclusive => {
+                existing.quarantined_until = Some(
+                    chrono::Utc::now().timestamp() + quarantine_backoff(existing.failed_probes),
+                );
+            }
+        }
+        Some(existing.to_owned())
+    }
+
+    /// Pauses or resumes a key
+    ///
+    /// # Returns
+    /// * `Option<KeyStatus>` - The updated key, `None` if it is not in the pool
+    fn set_disabled(
+        state: &mut KeyActorState,
+        key: &KeyStatus,
+        disabled: bool,
+    ) -> Option<KeyStatus> {
+        let existing = state.valid.iter_mut().find(|k| *k == key)?;
+        existing.disabled = disabled;
+        let updated = existing.to_owned();
+        info!(
+            "Key {}: {}",
+            if disabled { "paused" } else { "resumed" },
+            updated.key.ellipse()
+        );
         Self::save(state);
+        Some(updated)
     }
 
     /// Creates a report of all key statuses
     fn report(state: &KeyActorState) -> KeyStatusInfo {
         KeyStatusInfo {
-            valid: state.iter().cloned().collect(),
+            valid: state.valid.iter().cloned().collect(),
+            invalid: state.invalid.iter().cloned().collect(),
         }
     }
 
+    /// Replaces the whole pool
+    fn restore(state: &mut KeyActorState, pool: KeyStatusInfo) {
+        state.valid = pool.valid.into();
+        state.invalid = pool.invalid.into_iter().collect();
+        info!("Key pool restored, {} valid", state.valid.len());
+        Self::save_invalid(state);
+        Self::save(state);
+    }
+
     /// Deletes a key from the collection
     fn delete(state: &mut KeyActorState, key: KeyStatus) -> Result<(), ClewdrError> {
-        let size_before = state.len();
-        state.retain(|k| *k != key);
+        let size_before = state.valid.len();
+        state.valid.retain(|k| *k != key);
 
-        if state.len() < size_before {
+        if state.valid.len() < size_before {
+            Self::save(state);
+            Ok(())
+        } else if state.invalid.remove(&key) {
+            Self::save_invalid(state);
             Self::save(state);
             Ok(())
         } else {
@@ -109,15 +395,49 @@ impl KeyActor {
 impl Actor for KeyActor {
     type Msg = KeyActorMessage;
     type State = KeyActorState;
-    type Arguments = HashSet<KeyStatus>;
+    /// Owner of the pool, valid and dropped keys
+    type Arguments = (KeyPoolOwner, HashSet<KeyStatus>, HashSet<KeyStatus>);
 
     async fn pre_start(
         &self,
         _myself: ActorRef<Self::Msg>,
-        args: Self::Arguments,
+        (owner, valid, invalid): Self::Arguments,
     ) -> Result<Self::State, ActorProcessingErr> {
-        let state: Self::State = VecDeque::from_iter(args);
-        Ok(state)
+        let stored = matches!(owner, KeyPoolOwner::Tenant(_)) && self.storage.is_enabled();
+        let valid = if stored {
+            self.storage.load_keys().await?.into_iter().collect()
+        } else {
+            valid
+        };
+        let invalid_stored = self.storage.is_enabled();
+        let invalid = if invalid_stored {
+            if !invalid.is_empty() {
+                // dropped keys were kept in the config before, they are moved once
+                self.storage
+                    .persist_batch(&StorageBatch {
+                        invalid_keys: invalid.iter().cloned().collect(),
+                        ..Default::default()
+                    })
+                    .await?;
+                Self::set_invalid(&owner, &HashSet::new());
+                Self::write_config();
+            }
+            self.storage
+                .load_invalid_keys()
+                .await?
+                .into_iter()
+                .collect()
+        } else {
+            invalid
+        };
+        Ok(KeyActorState {
+            owner,
+            stored,
+            invalid_stored,
+            valid: VecDeque::from_iter(valid),
+            invalid,
+            waiters: WaitQueue::default(),
+        })
     }
 
     async fn handle(
@@ -128,13 +448,18 @@ impl Actor for KeyActor {
     ) -> Result<(), ActorProcessingErr> {
         match message {
             KeyActorMessage::Return(key) => {
-                Self::collect(state, key);
+                let updated = Self::collect(&mut state.valid, key);
+                Self::persist(self.storage, updated);
+            }
+            KeyActorMessage::Record(key, usage) => {
+                let updated = Self::record(&mut state.valid, key, usage);
+                Self::persist(self.storage, updated);
             }
             KeyActorMessage::Submit(key) => {
                 Self::accept(state, key);
                 let storage = self.storage;
                 if storage.is_enabled() {
-                    let k = state.back().cloned();
+                    let k = state.valid.back().cloned();
                     if let Some(k) = k {
                         tokio::spawn(async move {
                             if let Err(e) = storage.persist_key_upsert(&k).await {
@@ -143,15 +468,87 @@ impl Actor for KeyActor {
                         });
                     }
                 }
+                let KeyActorState {
+                    owner,
+                    valid,
+                    waiters,
+                    ..
+                } = state;
+                waiters.drain(|_| Self::dispatch(owner, valid).ok());
             }
             KeyActorMessage::Request(reply_port) => {
-                let result = Self::dispatch(state);
-                reply_port.send(result)?;
+                match Self::dispatch(&state.owner, &mut state.valid) {
+                    Err(ClewdrError::NoKeyAvailable) => {
+                        if let Err(reply_port) = state.waiters.push((), reply_port) {
+                            reply_port.send(Err(ClewdrError::NoKeyAvailable))?;
+                        }
+                    }
+                    result => reply_port.send(result)?,
+                }
             }
             KeyActorMessage::GetStatus(reply_port) => {
                 let status_info = Self::report(state);
                 reply_port.send(status_info)?;
             }
+            KeyActorMessage::Restore(pool, reply_port) => {
+                Self::restore(state, pool);
+                reply_port.send(())?;
+                let KeyActorState {
+                    owner,
+                    valid,
+                    waiters,
+                    ..
+                } = state;
+                waiters.drain(|_| Self::dispatch(owner, valid).ok());
+            }
+            KeyActorMessage::SetDisabled(key, disabled, reply_port) => {
+                let updated = Self::set_disabled(state, &key, disabled);
+                let result = match updated {
+                    Some(_) => Ok(()),
+                    None => Err(ClewdrError::UnexpectedNone {
+                        msg: "Key not found in valid keys",
+                    }),
+                };
+                reply_port.send(result)?;
+                Self::persist(self.storage, updated);
+                if !disabled {
+                    let KeyActorState {
+                        owner,
+                        valid,
+                        waiters,
+                        ..
+                    } = state;
+                    waiters.drain(|_| Self::dispatch(owner, valid).ok());
+                }
+            }
+            KeyActorMessage::Probed(key, probe) => match Self::probed(state, &key, probe) {
+                Some(updated) => {
+                    let recovered = !updated.is_quarantined();
+                    Self::persist(self.storage, Some(updated));
+                    if recovered {
+                        let KeyActorState {
+                            owner,
+                            valid,
+                            waiters,
+                            ..
+                        } = state;
+                        waiters.drain(|_| Self::dispatch(owner, valid).ok());
+                    }
+                }
+                None if state.invalid_stored && state.invalid.contains(&key) => {
+                    let storage = self.storage;
+                    let batch = StorageBatch {
+                        invalid_keys: state.invalid.get(&key).cloned().into_iter().collect(),
+                        ..Default::default()
+                    };
+                    tokio::spawn(async move {
+                        if let Err(e) = storage.persist_batch(&batch).await {
+                            error!("Failed to store dropped key: {}", e);
+                        }
+                    });
+                }
+                None => {}
+            },
             KeyActorMessage::Delete(key, reply_port) => {
                 let result = Self::delete(state, key.clone());
                 let ok = result.is_ok();
@@ -198,19 +595,71 @@ impl KeyActorHandle {
         let (actor_ref, _join_handle) = Actor::spawn(
             None,
             KeyActor { storage },
-            CLEWDR_CONFIG.load().gemini_keys.clone(),
+            (
+                KeyPoolOwner::Shared,
+                CLEWDR_CONFIG.load().gemini_keys.clone(),
+                CLEWDR_CONFIG.load().invalid_keys.clone(),
+            ),
+        )
+        .await?;
+        Ok(Self { actor_ref })
+    }
+
+    /// Create a KeyActor serving the pool of a tenant
+    ///
+    /// The valid and dropped keys are read from and written to the tenant's storage, or its
+    /// entry of the config in file mode.
+    pub async fn start_tenant(name: &str) -> Result<Self, ractor::SpawnErr> {
+        let (valid, invalid) = CLEWDR_CONFIG
+            .load()
+            .tenant(name)
+            .map(|t| (t.gemini_keys.to_owned(), t.invalid_keys.to_owned()))
+            .unwrap_or_default();
+        let (actor_ref, _join_handle) = Actor::spawn(
+            None,
+            KeyActor {
+                storage: crate::persistence::tenant_storage(name),
+            },
+            (KeyPoolOwner::Tenant(name.to_string()), valid, invalid),
+        )
+        .await?;
+        Ok(Self { actor_ref })
+    }
+
+    /// Create a KeyActor serving the pool of an OpenAI compatible upstream
+    ///
+    /// The pool is read from and saved to the upstream's entry of the config.
+    pub async fn start_upstream(name: &str) -> Result<Self, ractor::SpawnErr> {
+        let (valid, invalid) = CLEWDR_CONFIG
+            .load()
+            .openai_upstream(name)
+            .map(|u| (u.keys.to_owned(), u.invalid_keys.to_owned()))
+            .unwrap_or_default();
+        let (actor_ref, _join_handle) = Actor::spawn(
+            None,
+            KeyActor {
+                storage: crate::persistence::detached(),
+            },
+            (KeyPoolOwner::Upstream(name.to_string()), valid, invalid),
         )
         .await?;
         Ok(Self { actor_ref })
     }
 
     /// Request a key from the key actor
+    ///
+    /// With queueing enabled the request waits up to `queue_timeout` for a key to be submitted
     pub async fn request(&self) -> Result<KeyStatus, ClewdrError> {
-        ractor::call!(self.actor_ref, KeyActorMessage::Request).map_err(|e| {
-            ClewdrError::RactorError {
+        let result = match wait_queue::wait_timeout() {
+            Some(timeout) => ractor::call_t!(self.actor_ref, KeyActorMessage::Request, timeout),
+            None => ractor::call!(self.actor_ref, KeyActorMessage::Request),
+        };
+        result.map_err(|e| match e {
+            RactorErr::Timeout => ClewdrError::NoKeyAvailable,
+            e => ClewdrError::RactorError {
                 loc: Location::generate(),
                 msg: format!("Failed to communicate with KeyActor for request operation: {e}"),
-            }
+            },
         })?
     }
 
@@ -224,6 +673,16 @@ impl KeyActorHandle {
         })
     }
 
+    /// Report usage of a key to the key actor
+    pub async fn record_usage(&self, key: KeyStatus, usage: KeyUsage) -> Result<(), ClewdrError> {
+        ractor::cast!(self.actor_ref, KeyActorMessage::Record(key, usage)).map_err(|e| {
+            ClewdrError::RactorError {
+                loc: Location::generate(),
+                msg: format!("Failed to communicate with KeyActor for record operation: {e}"),
+            }
+        })
+    }
+
     /// Submit a new key to the key actor
     pub async fn submit(&self, key: KeyStatus) -> Result<(), ClewdrError> {
         ractor::cast!(self.actor_ref, KeyActorMessage::Submit(key)).map_err(|e| {
@@ -244,6 +703,18 @@ impl KeyActorHandle {
         })
     }
 
+    /// Replace the whole pool with `pool`
+    ///
+    /// Only the in-memory pool and the config are updated, storage has to be written first.
+    pub async fn restore(&self, pool: KeyStatusInfo) -> Result<(), ClewdrError> {
+        ractor::call!(self.actor_ref, KeyActorMessage::Restore, pool).map_err(|e| {
+            ClewdrError::RactorError {
+                loc: Location::generate(),
+                msg: format!("Failed to communicate with KeyActor for restore operation: {e}"),
+            }
+        })
+    }
+
     /// Delete a key from the key actor
     pub async fn delete_key(&self, key: KeyStatus) -> Result<(), ClewdrError> {
         ractor::call!(self.actor_ref, KeyActorMessage::Delete, key).map_err(|e| {
@@ -253,4 +724,24 @@ impl KeyActorHandle {
             }
         })?
     }
+
+    /// Report the result of probing a quarantined key
+    pub async fn report_probe(&self, key: KeyStatus, probe: KeyProbe) -> Result<(), ClewdrError> {
+        ractor::cast!(self.actor_ref, KeyActorMessage::Probed(key, probe)).map_err(|e| {
+            ClewdrError::RactorError {
+                loc: Location::generate(),
+                msg: format!("Failed to communicate with KeyActor for probe operation: {e}"),
+            }
+        })
+    }
+
+    /// Pause or resume a key, a paused key stays in the pool but is never dispatched
+    pub async fn set_disabled(&self, key: KeyStatus, disabled: bool) -> Result<(), ClewdrError> {
+        ractor::call!(self.actor_ref, KeyActorMessage::SetDisabled, key, disabled).map_err(|e| {
+            ClewdrError::RactorError {
+                loc: Location::generate(),
+                msg: format!("Failed to communicate with KeyActor for pause operation: {e}"),
+            }
+        })?
+    }
 }
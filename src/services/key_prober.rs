@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::{
+    config::KeyStatus,
+    error::ClewdrError,
+    gemini_state,
+    providers::openai_upstream,
+    services::key_actor::{KeyActorHandle, KeyProbe},
+};
+
+/// How often quarantined keys are checked for a due probe, in seconds
+const PROBE_TICK: u64 = 60;
+
+/// Spawn the background prober for quarantined Gemini keys.
+///
+/// A key is quarantined after `key_quarantine_after` 403 or 429 responses in a row.
+/// Once its backoff elapses it is probed with a cheap `models.list` call: a key that
+/// answers is dispatched again, a key refused `key_invalid_after` times is moved to
+/// `invalid_keys`. The backoff doubles with every failed probe.
+pub fn spawn(handle: KeyActorHandle) -> tokio::task::JoinHandle<()> {
+    spawn_with(handle, |key| async move {
+        probe(&key, gemini_state::check_key(&key.key).await)
+    })
+}
+
+/// Spawn the background prober for the quarantined keys of an OpenAI compatible upstream,
+/// probed with a `models` call
+pub fn spawn_upstream(handle: KeyActorHandle, name: String) -> tokio::task::JoinHandle<()> {
+    spawn_with(handle, move |key| {
+        let name = name.to_owned();
+        async move { probe(&key, openai_upstream::check_key(&name, &key).await) }
+    })
+}
+
+fn spawn_with<F, Fut>(handle: KeyActorHandle, check: F) -> tokio::task::JoinHandle<()>
+where
+    F: Fn(KeyStatus) -> Fut + Send + 'static,
+    Fut: Future<Output = KeyProbe> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(PROBE_TICK));
+        loop {
+            interval.tick().await;
+            let Ok(status) = handle.get_status().await else {
+                continue;
+            };
+            let now = chrono::Utc::now().timestamp();
+            for key in status.valid.into_iter().filter(|k| k.probe_due(now)) {
+                let result = check(key.to_owned()).await;
+                if let Err(e) = handle.report_probe(key, result).await {
+                    warn!("[PROBE] failed to report key probe: {}", e);
+                }
+            }
+        }
+    })
+}
+
+/// Classifies the result of a key check
+fn probe(key: &KeyStatus, result: Result<(), ClewdrError>) -> KeyProbe {
+    match result {
+        Ok(()) => KeyProbe::Alive,
+        // rate limited keys are alive, but not ready to come back yet
+        Err(
+            ClewdrError::GeminiHttpError { code, .. } | ClewdrError::UpstreamHttpError { code, .. },
+        ) if code.as_u16() == 429 => KeyProbe::Inconclusive,
+        Err(
+            ClewdrError::GeminiHttpError { code, .. } | ClewdrError::UpstreamHttpError { code, .. },
+        ) => {
+            warn!("[PROBE] {} refused with {}", key.key.ellipse(), code);
+            KeyProbe::Dead
+        }
+        Err(e) => {
+            warn!("[PROBE] {} probe failed: {}", key.key.ellipse(), e);
+            KeyProbe::Inconclusive
+        }
+    }
+}
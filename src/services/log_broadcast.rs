@@ -0,0 +1,106 @@
+use std::{
+    fmt::{self, Write},
+    sync::LazyLock,
+};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{Layer, layer::Context};
+
+/// Number of log entries a slow subscriber may fall behind before it starts skipping
+const CAPACITY: usize = 1024;
+
+static LOG_CHANNEL: LazyLock<broadcast::Sender<LogEntry>> =
+    LazyLock::new(|| broadcast::channel(CAPACITY).0);
+
+/// A single log line as delivered to live log subscribers
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEntry {
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+/// Subscribe to the live log stream
+pub fn subscribe() -> broadcast::Receiver<LogEntry> {
+    LOG_CHANNEL.subscribe()
+}
+
+/// Tracing layer that fans every event out to live log subscribers
+///
+/// Events are dropped without formatting when nobody is listening.
+pub struct LogBroadcastLayer;
+
+impl<S> Layer<S> for LogBroadcastLayer
+where
+    S: Subscriber,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if LOG_CHANNEL.receiver_count() == 0 {
+            return;
+        }
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let meta = event.metadata();
+        let entry = LogEntry {
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            level: meta.level().to_string(),
+            target: meta.target().to_string(),
+            message: strip_ansi(&visitor.0),
+        };
+        let _ = LOG_CHANNEL.send(entry);
+    }
+}
+
+/// Collects the message and any extra fields of an event into one line
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{value:?}");
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Removes ANSI escape sequences added by `colored`
+pub(crate) fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        // skip CSI sequence: ESC [ params final-byte
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strip_ansi;
+
+    #[test]
+    fn strips_color_codes() {
+        assert_eq!(strip_ansi("\x1b[32mgreen\x1b[0m text"), "green text");
+        assert_eq!(strip_ansi("\x1b[1;31mbold red\x1b[0m"), "bold red");
+        assert_eq!(strip_ansi("plain"), "plain");
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::{LazyLock, Mutex};
+
+use tracing_subscriber::{EnvFilter, filter::LevelFilter, reload};
+
+type Reloader = Box<dyn Fn(EnvFilter) -> Result<(), reload::Error> + Send + Sync>;
+
+/// Runtime adjustable filters of the log outputs
+struct Filters {
+    reloaders: Vec<Reloader>,
+    /// Level used for targets without a directive
+    default: LevelFilter,
+    /// Directives currently applied, in `RUST_LOG` syntax
+    directives: String,
+}
+
+static FILTERS: LazyLock<Mutex<Filters>> = LazyLock::new(|| {
+    Mutex::new(Filters {
+        reloaders: Vec::new(),
+        default: LevelFilter::INFO,
+        directives: std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_default(),
+    })
+});
+
+fn build_filter(default: LevelFilter, directives: &str) -> Result<EnvFilter, String> {
+    EnvFilter::builder()
+        .with_default_directive(default.into())
+        .parse(directives)
+        .map_err(|e| e.to_string())
+}
+
+/// Creates a filter from `RUST_LOG` whose directives can be changed at runtime
+///
+/// # Arguments
+/// * `default` - Level for targets without a directive
+pub fn reloadable<S: 'static>(default: LevelFilter) -> reload::Layer<EnvFilter, S> {
+    let filter = EnvFilter::builder()
+        .with_default_directive(default.into())
+        .from_env_lossy();
+    let (layer, handle) = reload::Layer::new(filter);
+    let mut filters = FILTERS.lock().unwrap_or_else(|e| e.into_inner());
+    filters.default = default;
+    filters
+        .reloaders
+        .push(Box::new(move |filter| handle.reload(filter)));
+    layer
+}
+
+/// Directives currently applied to the log outputs
+pub fn current() -> String {
+    FILTERS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .directives
+        .to_owned()
+}
+
+/// Replaces the directives of every log output, e.g. `info,clewdr::claude_code_state=debug`
+///
+/// # Returns
+/// * `Err(String)` - The directives are invalid or a filter could not be reloaded
+pub fn set(directives: &str) -> Result<(), String> {
+    let mut filters = FILTERS.lock().unwrap_or_else(|e| e.into_inner());
+    // validate before touching any output
+    build_filter(filters.default, directives)?;
+    for reload in filters.reloaders.iter() {
+        reload(build_filter(filters.default, directives)?).map_err(|e| e.to_string())?;
+    }
+    filters.directives = directives.to_string();
+    Ok(())
+}
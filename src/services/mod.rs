@@ -1,5 +1,28 @@
+pub mod backup;
+pub mod batch;
+pub mod breaker;
+pub mod capture;
+pub mod chat_cleaner;
+pub mod client_pool;
 pub mod cookie_actor;
+pub mod cookie_prober;
+pub mod endpoints;
 pub mod key_actor;
+pub mod key_prober;
+pub mod log_broadcast;
+pub mod log_level;
+pub mod models;
+pub mod notify;
+pub mod object_store;
+pub mod quota;
+pub mod recent_errors;
+pub mod retry;
+pub mod session;
+pub mod sigv4;
 pub mod sync;
+pub mod tenant;
+pub mod token_refresher;
 #[cfg(feature = "portable")]
 pub mod update;
+pub mod wait_queue;
+pub mod webhook;
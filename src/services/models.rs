@@ -0,0 +1,158 @@
+use std::{sync::LazyLock, time::Duration};
+
+use moka::sync::Cache;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{
+    config::{BEDROCK_MODEL_SUFFIX, CLEWDR_CONFIG, KeyStatus},
+    gemini_state,
+    services::{cookie_actor::CookieActorHandle, key_actor::KeyActorHandle},
+};
+
+/// Claude models served through the cookie pool, each with its thinking variant
+const CLAUDE_MODELS: [&str; 10] = [
+    "claude-3-7-sonnet-20250219",
+    "claude-3-7-sonnet-20250219-thinking",
+    "claude-sonnet-4-20250514",
+    "claude-sonnet-4-20250514-thinking",
+    "claude-sonnet-4-5-20250929",
+    "claude-sonnet-4-5-20250929-thinking",
+    "claude-opus-4-20250514",
+    "claude-opus-4-20250514-thinking",
+    "claude-opus-4-1-20250805",
+    "claude-opus-4-1-20250805-thinking",
+];
+
+/// Gemini models listed with a pooled key, refreshed hourly
+static GEMINI_MODELS: LazyLock<Cache<(), Vec<String>>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(1)
+        .time_to_live(Duration::from_secs(60 * 60))
+        .build()
+});
+
+/// Entry of the OpenAI `/v1/models` list
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelEntry {
+    pub id: String,
+    pub object: &'static str,
+    pub created: u64,
+    /// Provider serving the model: `claude`, `gemini`, `vertex`, `bedrock` or `openai` for the
+    /// OpenAI compatible upstreams
+    pub owned_by: &'static str,
+    /// Whether the provider has a usable cookie, key or credential right now
+    pub available: bool,
+    /// Model an alias resolves to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+}
+
+impl ModelEntry {
+    fn new(id: impl Into<String>, owned_by: &'static str, available: bool) -> Self {
+        Self {
+            id: id.into(),
+            object: "model",
+            created: 0,
+            owned_by,
+            available,
+            root: None,
+        }
+    }
+}
+
+/// Models of every provider, with their availability from the health of its pool
+#[derive(Clone)]
+pub struct ModelRegistry {
+    pub cookie_actor_handle: CookieActorHandle,
+    pub key_actor_handle: KeyActorHandle,
+}
+
+/// Gemini models, fetched once per hour with `key`
+///
+/// Nothing is listed while the pool has no usable key or the call fails.
+async fn gemini_models(key: Option<&KeyStatus>) -> Vec<String> {
+    if let Some(models) = GEMINI_MODELS.get(&()) {
+        return models;
+    }
+    let Some(key) = key else {
+        return vec![];
+    };
+    match gemini_state::list_models(&key.key).await {
+        Ok(models) => {
+            GEMINI_MODELS.insert((), models.to_owned());
+            models
+        }
+        Err(e) => {
+            warn!("Failed to list Gemini models: {}", e);
+            vec![]
+        }
+    }
+}
+
+impl ModelRegistry {
+    /// Claude, Gemini and Vertex models, followed by the configured aliases
+    ///
+    /// An alias takes the provider and availability of its model, or is unavailable when
+    /// the model is not listed.
+    pub async fn list(&self) -> Vec<ModelEntry> {
+        let claude_ok = self
+            .cookie_actor_handle
+            .get_status()
+            .await
+            .is_ok_and(|s| !s.valid.is_empty());
+        let mut models = CLAUDE_MODELS
+            .iter()
+            .map(|m| ModelEntry::new(*m, "claude", claude_ok))
+            .collect::<Vec<_>>();
+        let keys = self
+            .key_actor_handle
+            .get_status()
+            .await
+            .map(|s| s.valid)
+            .unwrap_or_default();
+        models.extend(
+            gemini_models(keys.first())
+                .await
+                .into_iter()
+                .map(|m| ModelEntry::new(m, "gemini", !keys.is_empty())),
+        );
+        let config = CLEWDR_CONFIG.load();
+        let vertex_ok = !config.vertex.credential_list().is_empty();
+        models.extend(
+            config
+                .vertex
+                .models
+                .iter()
+                .map(|m| ModelEntry::new(m.to_owned(), "vertex", vertex_ok)),
+        );
+        let bedrock_ok = config.bedrock.is_enabled();
+        models.extend(
+            config.bedrock.models.keys().map(|m| {
+                ModelEntry::new(format!("{m}{BEDROCK_MODEL_SUFFIX}"), "bedrock", bedrock_ok)
+            }),
+        );
+        models.extend(config.openai_upstreams.iter().flat_map(|u| {
+            u.models
+                .iter()
+                .map(|m| ModelEntry::new(format!("{}/{m}", u.name), "openai", true))
+        }));
+        let aliases = config
+            .model_aliases
+            .iter()
+            .map(|(alias, model)| {
+                let target = models.iter().find(|m| m.id == *model);
+                ModelEntry {
+                    root: Some(model.to_owned()),
+                    ..ModelEntry::new(
+                        alias.to_owned(),
+                        target.map_or("clewdr", |m| m.owned_by),
+                        target.is_some_and(|m| m.available),
+                    )
+                }
+            })
+            .collect::<Vec<_>>();
+        models.extend(aliases);
+        models
+    }
+}
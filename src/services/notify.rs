@@ -0,0 +1,142 @@
+use std::{
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use serde_json::json;
+use tracing::{info, warn};
+use wreq::Client;
+
+use crate::{
+    config::{CLEWDR_CONFIG, DiscordNotifier, TelegramNotifier, WebhookEvent},
+    services::webhook,
+};
+
+/// Most alerts held for the next message, later ones are only counted
+const MAX_PENDING: usize = 20;
+/// Longest message Discord accepts, Telegram allows more
+const MAX_MESSAGE_CHARS: usize = 2000;
+
+/// Alerts gathered for the next message, and how many were dropped past `MAX_PENDING`
+static PENDING: LazyLock<Mutex<(Vec<String>, usize)>> =
+    LazyLock::new(|| Mutex::new((Vec::new(), 0)));
+/// Whether a flush of the pending alerts is scheduled
+static SCHEDULED: AtomicBool = AtomicBool::new(false);
+
+/// Queues a chat alert of a critical event
+///
+/// Alerts are gathered for `notify.interval` seconds and sent as a single message to the
+/// configured Discord channel and Telegram chat, so a burst of bans posts once.
+pub fn alert(event: WebhookEvent, message: &str) {
+    if !event.is_critical() || !CLEWDR_CONFIG.load().notify.is_enabled() {
+        return;
+    }
+    {
+        let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+        if pending.0.len() < MAX_PENDING {
+            pending.0.push(message.to_string());
+        } else {
+            pending.1 += 1;
+        }
+    }
+    if SCHEDULED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    tokio::spawn(async {
+        let interval = CLEWDR_CONFIG.load().notify.interval;
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        SCHEDULED.store(false, Ordering::Release);
+        flush().await;
+    });
+}
+
+/// Sends the pending alerts as one message
+async fn flush() {
+    let (alerts, dropped) = {
+        let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+        std::mem::take(&mut *pending)
+    };
+    if alerts.is_empty() {
+        return;
+    }
+    let mut text = alerts
+        .iter()
+        .map(|a| format!("• {a}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if dropped > 0 {
+        text.push_str(&format!("\n… and {dropped} more"));
+    }
+    let text = format!("⚠️ ClewdR alerts\n{text}")
+        .chars()
+        .take(MAX_MESSAGE_CHARS)
+        .collect::<String>();
+    let client = match webhook::client() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("[NOTIFY] failed to create client: {}", e);
+            return;
+        }
+    };
+    let notify = CLEWDR_CONFIG.load().notify.to_owned();
+    if let Some(ref discord) = notify.discord {
+        report("Discord", send_discord(&client, discord, &text).await);
+    }
+    if let Some(ref telegram) = notify.telegram {
+        report("Telegram", send_telegram(&client, telegram, &text).await);
+    }
+}
+
+fn report(target: &str, res: Result<(), String>) {
+    match res {
+        Ok(()) => info!("[NOTIFY] alerts sent to {}", target),
+        Err(e) => warn!("[NOTIFY] failed to send alerts to {}: {}", target, e),
+    }
+}
+
+async fn send_discord(
+    client: &Client,
+    discord: &DiscordNotifier,
+    text: &str,
+) -> Result<(), String> {
+    let url = format!(
+        "https://discord.com/api/v10/channels/{}/messages",
+        discord.channel_id
+    );
+    let res = client
+        .post(url)
+        .header("authorization", format!("Bot {}", discord.bot_token))
+        .json(&json!({ "content": text }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !res.status().is_success() {
+        return Err(format!("Discord answered {}", res.status()));
+    }
+    Ok(())
+}
+
+async fn send_telegram(
+    client: &Client,
+    telegram: &TelegramNotifier,
+    text: &str,
+) -> Result<(), String> {
+    let url = format!(
+        "https://api.telegram.org/bot{}/sendMessage",
+        telegram.bot_token
+    );
+    let res = client
+        .post(url)
+        .json(&json!({ "chat_id": telegram.chat_id, "text": text }))
+        .send()
+        .await
+        // the URL holds the bot token
+        .map_err(|e| e.without_url().to_string())?;
+    if !res.status().is_success() {
+        return Err(format!("Telegram answered {}", res.status()));
+    }
+    Ok(())
+}
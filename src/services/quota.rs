@@ -0,0 +1,44 @@
+use std::{
+    sync::{
+        LazyLock,
+        atomic::{AtomicI64, Ordering},
+    },
+    time::Duration,
+};
+
+use moka::sync::Cache;
+
+/// Quota percentage left per cookie at its last usage probe
+static REMAINING: LazyLock<Cache<String, u32>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(10_000)
+        .time_to_live(Duration::from_secs(60 * 60))
+        .build()
+});
+
+/// Records the utilization percentages of a cookie's quota windows from a usage probe
+///
+/// What is left of the most used window counts.
+pub fn record(cookie: &str, utilizations: impl IntoIterator<Item = u32>) {
+    let used = utilizations.into_iter().max().unwrap_or_default().min(100);
+    REMAINING.insert(cookie.to_string(), 100 - used);
+}
+
+/// Quota percentage `cookie` had left when last probed, `None` when not probed in the last hour
+pub fn remaining(cookie: &str) -> Option<u32> {
+    REMAINING.get(cookie)
+}
+
+/// Earliest reset of the exhausted cookies when the pool last ran dry, 0 when unknown
+static POOL_RESET: AtomicI64 = AtomicI64::new(0);
+
+/// Records the earliest reset of the exhausted cookies, when no cookie could be dispatched
+pub fn record_pool_reset(reset: Option<i64>) {
+    POOL_RESET.store(reset.unwrap_or_default(), Ordering::Relaxed);
+}
+
+/// Earliest time a cookie of the drained pool becomes usable again, `None` when it has passed
+pub fn pool_reset() -> Option<i64> {
+    let reset = POOL_RESET.load(Ordering::Relaxed);
+    (reset > chrono::Utc::now().timestamp()).then_some(reset)
+}
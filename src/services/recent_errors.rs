@@ -0,0 +1,61 @@
+use std::{
+    collections::VecDeque,
+    sync::{LazyLock, Mutex},
+};
+
+use serde::Serialize;
+
+use crate::{
+    error::ClewdrError,
+    services::{breaker::Provider, log_broadcast::strip_ansi},
+};
+
+/// Failures kept, older ones are dropped first
+const MAX_RECENT_ERRORS: usize = 100;
+
+static RECENT: LazyLock<Mutex<VecDeque<RecentError>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::with_capacity(MAX_RECENT_ERRORS)));
+
+/// A failed upstream attempt
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentError {
+    /// Epoch seconds
+    pub at: i64,
+    pub provider: &'static str,
+    /// Ellipsis of the cookie or key the attempt used
+    pub credential: Option<String>,
+    /// Kind of the error, e.g. `claude_http_error`
+    pub kind: &'static str,
+    /// Status answered by the upstream, if it answered
+    pub code: Option<u16>,
+    pub message: String,
+}
+
+/// Remembers a failed attempt for the admin dashboard
+pub fn record(provider: Provider, credential: Option<String>, e: &ClewdrError) {
+    let code = match e {
+        ClewdrError::ClaudeHttpError { code, .. }
+        | ClewdrError::GeminiHttpError { code, .. }
+        | ClewdrError::UpstreamHttpError { code, .. } => Some(code.as_u16()),
+        _ => None,
+    };
+    let error = RecentError {
+        at: chrono::Utc::now().timestamp(),
+        provider: provider.into(),
+        credential,
+        kind: e.into(),
+        code,
+        message: strip_ansi(&e.to_string()),
+    };
+    let mut recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    if recent.len() >= MAX_RECENT_ERRORS {
+        recent.pop_front();
+    }
+    recent.push_back(error);
+}
+
+/// Recent failures, newest first
+pub fn recent() -> Vec<RecentError> {
+    let recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    recent.iter().rev().cloned().collect()
+}
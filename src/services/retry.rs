@@ -0,0 +1,224 @@
+use colored::Colorize;
+use tracing::{error, info};
+
+use crate::{config::CLEWDR_CONFIG, error::ClewdrError, utils::retry};
+
+/// How a failed attempt is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// Hand the credential back and try again with another one
+    Retry,
+    /// Give up and return the error to the client
+    Abort,
+}
+
+/// Error returned once every attempt failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exhausted {
+    /// `ClewdrError::TooManyRetries`
+    TooManyRetries,
+    /// The error of the last attempt
+    LastError,
+}
+
+/// Retry settings of a request to an upstream provider
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts after the first one
+    pub max_retries: usize,
+    /// Whether to wait with `utils::retry::backoff` between attempts
+    pub backoff: bool,
+    /// Error returned when the retries run out
+    pub exhausted: Exhausted,
+    /// Log prefix of retry attempts
+    pub label: &'static str,
+}
+
+impl RetryPolicy {
+    /// Policy with the configured `max_retries`, backing off between attempts
+    pub fn from_config() -> Self {
+        Self {
+            max_retries: CLEWDR_CONFIG.load().max_retries,
+            backoff: true,
+            exhausted: Exhausted::TooManyRetries,
+            label: "[RETRY]",
+        }
+    }
+
+    /// Retries immediately without waiting, attempts still count against `max_retries`
+    pub fn without_backoff(self) -> Self {
+        Self {
+            backoff: false,
+            ..self
+        }
+    }
+
+    /// Returns the error of the last attempt instead of `TooManyRetries`
+    pub fn surface_last_error(self) -> Self {
+        Self {
+            exhausted: Exhausted::LastError,
+            ..self
+        }
+    }
+
+    /// Uses `label` as log prefix of retry attempts
+    pub fn label(self, label: &'static str) -> Self {
+        Self { label, ..self }
+    }
+
+    /// Builds an executor for this policy
+    ///
+    /// # Arguments
+    /// * `classify` - Decides whether an error is worth another attempt
+    /// * `on_failure` - Hands the credential of a retried attempt back, e.g. returns the cookie with its reason.
+    ///   It receives the state of the attempt, the returned future must not borrow the error
+    pub fn executor<C, F>(self, classify: C, on_failure: F) -> RetryExecutor<C, F> {
+        RetryExecutor {
+            policy: self,
+            classify,
+            on_failure,
+        }
+    }
+}
+
+/// Runs attempts on fresh copies of a provider state according to a `RetryPolicy`
+pub struct RetryExecutor<C, F> {
+    policy: RetryPolicy,
+    classify: C,
+    on_failure: F,
+}
+
+impl<C, F> RetryExecutor<C, F> {
+    /// Runs `attempt` until it succeeds, fails with an error classified `Abort`, or the retries run out
+    ///
+    /// Every attempt gets its own clone of `state` and hands it back with its result,
+    /// so `on_failure` sees the credential the failed attempt used.
+    ///
+    /// # Arguments
+    /// * `state` - State every attempt starts from
+    /// * `attempt` - A single request, acquires its own credential from the state
+    ///
+    /// # Returns
+    /// * `Result<T, ClewdrError>` - Output of the first successful attempt or error
+    pub async fn run<S, T, A, AFut, FFut>(
+        &self,
+        state: &S,
+        mut attempt: A,
+    ) -> Result<T, ClewdrError>
+    where
+        S: Clone,
+        C: Fn(&ClewdrError) -> Failure,
+        F: Fn(S, &ClewdrError) -> FFut,
+        FFut: Future<Output = ()>,
+        A: FnMut(S) -> AFut,
+        AFut: Future<Output = (S, Result<T, ClewdrError>)>,
+    {
+        let policy = self.policy;
+        let mut last = None;
+        for i in 0..policy.max_retries + 1 {
+            if i > 0 {
+                info!("{} attempt: {}", policy.label, i.to_string().green());
+                if policy.backoff && !retry::backoff(i).await {
+                    break;
+                }
+            }
+            match attempt(state.to_owned()).await {
+                (_, Ok(res)) => return Ok(res),
+                (_, Err(e)) if (self.classify)(&e) == Failure::Abort => return Err(e),
+                (state, Err(e)) => {
+                    (self.on_failure)(state, &e).await;
+                    last = Some(e);
+                }
+            }
+        }
+        error!("Max retries exceeded");
+        match (policy.exhausted, last) {
+            (Exhausted::LastError, Some(e)) => Err(e),
+            _ => Err(ClewdrError::TooManyRetries),
+        }
+    }
+}
+
+/// Retries with another cookie when the current one was rejected
+pub fn invalid_cookie(e: &ClewdrError) -> Failure {
+    match e {
+        ClewdrError::InvalidCookie { .. } => Failure::Retry,
+        _ => Failure::Abort,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_retries: usize) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            backoff: false,
+            exhausted: Exhausted::TooManyRetries,
+            label: "[TEST]",
+        }
+    }
+
+    fn classify(e: &ClewdrError) -> Failure {
+        match e {
+            ClewdrError::EmptyChoices => Failure::Retry,
+            _ => Failure::Abort,
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_until_success_and_returns_credentials() {
+        let returned = std::sync::Mutex::new(Vec::new());
+        let executor = policy(3).executor(classify, |s: u32, _: &ClewdrError| {
+            returned.lock().unwrap().push(s);
+            async {}
+        });
+        let mut calls = 0;
+        let res = executor
+            .run(&0u32, |_| {
+                calls += 1;
+                let calls = calls;
+                async move {
+                    if calls < 3 {
+                        (calls, Err(ClewdrError::EmptyChoices))
+                    } else {
+                        (calls, Ok(calls))
+                    }
+                }
+            })
+            .await;
+        assert_eq!(res.unwrap(), 3);
+        assert_eq!(*returned.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn aborts_and_exhausts() {
+        let executor = policy(2).executor(classify, |_: (), _: &ClewdrError| async {});
+        let res: Result<(), _> = executor
+            .run(
+                &(),
+                |s| async move { (s, Err(ClewdrError::TooManyRetries)) },
+            )
+            .await;
+        assert!(matches!(res, Err(ClewdrError::TooManyRetries)));
+
+        let mut calls = 0;
+        let res: Result<(), _> = executor
+            .run(&(), |s| {
+                calls += 1;
+                async move { (s, Err(ClewdrError::EmptyChoices)) }
+            })
+            .await;
+        assert!(matches!(res, Err(ClewdrError::TooManyRetries)));
+        assert_eq!(calls, 3);
+
+        let executor = policy(1)
+            .surface_last_error()
+            .executor(classify, |_: (), _: &ClewdrError| async {});
+        let res: Result<(), _> = executor
+            .run(&(), |s| async move { (s, Err(ClewdrError::EmptyChoices)) })
+            .await;
+        assert!(matches!(res, Err(ClewdrError::EmptyChoices)));
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex, MutexGuard},
+};
+
+use aws_lc_rs::{
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tracing::info;
+
+/// Lifetime of an admin session token, in seconds
+pub const SESSION_TTL: i64 = 15 * 60;
+/// Age at which a new signing secret is generated, in seconds
+///
+/// The previous secret keeps verifying the tokens it signed, which expire well before the next rotation.
+const SECRET_ROTATION: i64 = 6 * 3600;
+
+/// What a session token may do on the admin API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    Viewer,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    alg: String,
+    typ: String,
+    /// Signing secret the token was signed with
+    kid: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub role: Role,
+    pub iat: i64,
+    pub exp: i64,
+    /// Token id, used to revoke it on logout
+    pub jti: String,
+    /// Session generation of the role the token was issued in, see `end_sessions`
+    #[serde(default)]
+    pub generation: u64,
+}
+
+/// Token handed out by login and refresh
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub token: String,
+    pub role: Role,
+    pub expires_at: i64,
+}
+
+struct SigningKey {
+    id: u64,
+    key: hmac::Key,
+    created_at: i64,
+}
+
+#[derive(Default)]
+struct Sessions {
+    current: Option<SigningKey>,
+    previous: Option<SigningKey>,
+    last_id: u64,
+    /// Logged out tokens by id, kept until they expire
+    revoked: HashMap<String, i64>,
+    /// Current session generation of each role, tokens of an earlier one are no longer accepted
+    generations: HashMap<Role, u64>,
+}
+
+impl Sessions {
+    /// Key new tokens are signed with, rotated once it is older than `SECRET_ROTATION`
+    fn signing_key(&mut self, now: i64) -> &SigningKey {
+        if self
+            .current
+            .as_ref()
+            .is_none_or(|k| now - k.created_at >= SECRET_ROTATION)
+        {
+            self.last_id += 1;
+            let key = SigningKey {
+                id: self.last_id,
+                key: hmac::Key::new(hmac::HMAC_SHA256, &random_bytes::<32>()),
+                created_at: now,
+            };
+            self.previous = self.current.replace(key);
+        }
+        self.current.as_ref().expect("signing key was just set")
+    }
+
+    fn verifying_key(&self, id: u64) -> Option<&SigningKey> {
+        [&self.current, &self.previous]
+            .into_iter()
+            .flatten()
+            .find(|k| k.id == id)
+    }
+
+    fn generation(&self, role: Role) -> u64 {
+        self.generations.get(&role).copied().unwrap_or_default()
+    }
+
+    fn issue(&mut self, role: Role) -> Session {
+        let now = now();
+        let claims = Claims {
+            role,
+            iat: now,
+            exp: now + SESSION_TTL,
+            jti: BASE64_URL_SAFE_NO_PAD.encode(random_bytes::<16>()),
+            generation: self.generation(role),
+        };
+        let key = self.signing_key(now);
+        let header = Header {
+            alg: "HS256".to_string(),
+            typ: "JWT".to_string(),
+            kid: key.id,
+        };
+        let signing_input = format!("{}.{}", encode_part(&header), encode_part(&claims));
+        let signature = hmac::sign(&key.key, signing_input.as_bytes());
+        Session {
+            token: format!(
+                "{signing_input}.{}",
+                BASE64_URL_SAFE_NO_PAD.encode(signature)
+            ),
+            role,
+            expires_at: claims.exp,
+        }
+    }
+
+    fn verify(&self, token: &str) -> Option<Claims> {
+        let (signing_input, signature) = token.rsplit_once('.')?;
+        let (header, claims) = signing_input.split_once('.')?;
+        let header = decode_part::<Header>(header)?;
+        if header.alg != "HS256" {
+            return None;
+        }
+        let signature = BASE64_URL_SAFE_NO_PAD.decode(signature).ok()?;
+        let key = self.verifying_key(header.kid)?;
+        hmac::verify(&key.key, signing_input.as_bytes(), &signature).ok()?;
+        let claims = decode_part::<Claims>(claims)?;
+        (claims.exp > now()
+            && claims.generation >= self.generation(claims.role)
+            && !self.revoked.contains_key(&claims.jti))
+        .then_some(claims)
+    }
+
+    /// Revokes the token of `claims`, returns whether it was not revoked yet
+    fn revoke(&mut self, claims: Claims) -> bool {
+        let now = now();
+        self.revoked.retain(|_, exp| *exp > now);
+        self.revoked.insert(claims.jti, claims.exp).is_none()
+    }
+}
+
+static SESSIONS: LazyLock<Mutex<Sessions>> = LazyLock::new(Default::default);
+
+fn sessions() -> MutexGuard<'static, Sessions> {
+    SESSIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut buf = [0; N];
+    SystemRandom::new()
+        .fill(&mut buf)
+        .expect("system random source is available");
+    buf
+}
+
+fn encode_part(value: &impl Serialize) -> String {
+    let json = serde_json::to_vec(value).expect("token parts serialize");
+    BASE64_URL_SAFE_NO_PAD.encode(json)
+}
+
+fn decode_part<T: DeserializeOwned>(part: &str) -> Option<T> {
+    let json = BASE64_URL_SAFE_NO_PAD.decode(part).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Issues an HS256 JWT for `role`, valid for `SESSION_TTL`
+pub fn issue(role: Role) -> Session {
+    sessions().issue(role)
+}
+
+/// Claims of a valid session token, `None` if it is forged, expired or logged out
+pub fn verify(token: &str) -> Option<Claims> {
+    sessions().verify(token)
+}
+
+/// Revokes a session token until it expires, returns whether it was valid
+pub fn revoke(token: &str) -> bool {
+    let mut sessions = sessions();
+    sessions
+        .verify(token)
+        .is_some_and(|claims| sessions.revoke(claims))
+}
+
+/// Swaps a valid session token for a fresh one with the same role, revoking the old one
+///
+/// The token is checked and revoked under one lock, so concurrent refreshes of the same token
+/// issue a single new one.
+pub fn refresh(token: &str) -> Option<Session> {
+    let mut sessions = sessions();
+    let claims = sessions.verify(token)?;
+    let role = claims.role;
+    sessions.revoke(claims).then(|| sessions.issue(role))
+}
+
+/// Ends every session of `role`, e.g. after its password changed
+///
+/// Tokens issued afterwards are accepted, even within the same second.
+pub fn end_sessions(role: Role) {
+    *sessions().generations.entry(role).or_default() += 1;
+    info!("[SESSION] all {:?} sessions ended", role);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_lifecycle() {
+        let session = issue(Role::Viewer);
+        let claims = verify(&session.token).unwrap();
+        assert_eq!(claims.role, Role::Viewer);
+        assert_eq!(claims.exp, session.expires_at);
+
+        let (body, _) = session.token.rsplit_once('.').unwrap();
+        assert!(verify(&format!("{body}.AAAA")).is_none());
+        assert!(verify("not a token").is_none());
+
+        let refreshed = refresh(&session.token).unwrap();
+        assert!(verify(&session.token).is_none());
+        assert!(revoke(&refreshed.token));
+        assert!(verify(&refreshed.token).is_none());
+        assert!(refresh(&refreshed.token).is_none());
+    }
+
+    #[test]
+    fn ended_sessions_spare_later_tokens() {
+        let old = issue(Role::Admin);
+        end_sessions(Role::Admin);
+        let new = issue(Role::Admin);
+        assert!(verify(&old.token).is_none());
+        assert!(verify(&new.token).is_some());
+    }
+}
@@ -1,12 +1,43 @@
 use std::collections::HashSet;
 
+use tokio::{
+    sync::broadcast::{Receiver, error::RecvError},
+    time::Interval,
+};
+use tracing::{info, warn};
+
 use crate::{
-    config::{CookieStatus, Reason},
-    persistence,
-    services::{cookie_actor::CookieActorHandle, key_actor::KeyActorHandle},
+    config::{CLEWDR_CONFIG, CookieStatus, Reason},
+    persistence::{self, StorageEvent},
+    services::{batch, cookie_actor::CookieActorHandle, key_actor::KeyActorHandle},
 };
 
-/// Spawn background sync tasks for keys and cookies when DB storage is enabled.
+/// Waits for the next sync tick, or less when another instance reports a change of `kind`
+async fn next_round(
+    interval: &mut Interval,
+    events: &mut Option<Receiver<StorageEvent>>,
+    kind: StorageEvent,
+) {
+    loop {
+        let Some(rx) = events.as_mut() else {
+            interval.tick().await;
+            return;
+        };
+        tokio::select! {
+            _ = interval.tick() => return,
+            event = rx.recv() => match event {
+                Ok(event) if event == kind => return,
+                Ok(_) => continue,
+                // missed some events, resync to be safe
+                Err(RecvError::Lagged(_)) => return,
+                Err(RecvError::Closed) => *events = None,
+            },
+        }
+    }
+}
+
+/// Spawn background sync tasks for keys, cookies and config when DB storage is enabled,
+/// so edits made to the database by other instances or by hand apply without a restart.
 /// Returns join handles if tasks were spawned.
 pub fn spawn(
     cookie_handle: CookieActorHandle,
@@ -21,9 +52,11 @@ pub fn spawn(
     let k = key_handle.clone();
     handles.push(tokio::spawn(async move {
         let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+        let mut events = persistence::storage().subscribe();
         loop {
-            interval.tick().await;
+            next_round(&mut interval, &mut events, StorageEvent::Keys).await;
             if let Ok(db_keys) = persistence::load_all_keys().await
+                && let Ok(dropped) = persistence::storage().load_invalid_keys().await
                 && let Ok(cur) = k.get_status().await
             {
                 let db_set: HashSet<_> = db_keys.iter().cloned().collect();
@@ -31,19 +64,23 @@ pub fn spawn(
                 for x in db_set.difference(&cur_set) {
                     let _ = k.submit(x.clone()).await;
                 }
-                for x in cur_set.difference(&db_set) {
+                // deleting would also drop the stored row of a key another instance dropped
+                for x in cur_set.difference(&db_set).filter(|x| !dropped.contains(x)) {
                     let _ = k.delete_key(x.clone()).await;
                 }
             }
         }
     }));
 
-    // Cookies conservative sync: add missing; reclassify exhausted/invalid; never hard-delete
+    // Cookies conservative sync: add missing; reclassify exhausted/invalid; remove only
+    // cookies missing from the database for two rounds, a fresh submit may not be written yet
     let c_handle = cookie_handle.clone();
     handles.push(tokio::spawn(async move {
         let mut interval = tokio::time::interval(std::time::Duration::from_secs(45));
+        let mut events = persistence::storage().subscribe();
+        let mut missing = HashSet::new();
         loop {
-            interval.tick().await;
+            next_round(&mut interval, &mut events, StorageEvent::Cookies).await;
             let Ok((db_valid, db_exhausted, db_invalid)) = persistence::load_all_cookies().await
             else {
                 continue;
@@ -89,8 +126,126 @@ pub fn spawn(
                     let _ = c_handle.return_cookie(tmp, Some(u.reason.clone())).await;
                 }
             }
+
+            // Remove cookies deleted from the database
+            let db_all: HashSet<_> = db_valid
+                .iter()
+                .chain(db_exhausted.iter())
+                .map(|x| x.cookie.to_string())
+                .chain(db_invalid.iter().map(|x| x.cookie.to_string()))
+                .collect();
+            let gone: HashSet<_> = cur
+                .valid
+                .iter()
+                .chain(cur.exhausted.iter())
+                .map(|x| x.cookie.to_owned())
+                .chain(cur.invalid.iter().map(|x| x.cookie.to_owned()))
+                .filter(|c| !db_all.contains(&c.to_string()))
+                .collect();
+            for c in gone.intersection(&missing) {
+                info!("Cookie removed from database, dropping: {}", c.ellipse());
+                let tmp = CookieStatus {
+                    cookie: c.to_owned(),
+                    ..Default::default()
+                };
+                let _ = c_handle.delete_cookie(tmp).await;
+            }
+            missing = gone;
+        }
+    }));
+
+    // Drop transcripts past their retention
+    handles.push(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            let days = {
+                let config = CLEWDR_CONFIG.load();
+                if !config.transcripts_enabled() {
+                    continue;
+                }
+                config.transcript_retention_days
+            };
+            if days == 0 {
+                continue;
+            }
+            let retention = i64::try_from(days)
+                .unwrap_or(i64::MAX)
+                .saturating_mul(86_400);
+            let before = chrono::Utc::now().timestamp().saturating_sub(retention);
+            match persistence::storage().prune_transcripts(before).await {
+                Ok(0) => {}
+                Ok(n) => info!("Pruned {} expired transcripts", n),
+                Err(e) => warn!("Failed to prune transcripts: {}", e),
+            }
+        }
+    }));
+
+    // Drop prompt hash pins past their expiry, live ones are renewed while in use
+    handles.push(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            let now = chrono::Utc::now().timestamp();
+            match persistence::storage().prune_affinity(now).await {
+                Ok(0) => {}
+                Ok(n) => info!("Pruned {} expired prompt affinities", n),
+                Err(e) => warn!("Failed to prune prompt affinities: {}", e),
+            }
+        }
+    }));
+
+    // Fail batch jobs whose instance stopped before finishing them
+    handles.push(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(300));
+        loop {
+            interval.tick().await;
+            if let Err(e) = batch::fail_orphaned().await {
+                warn!("Failed to check orphaned batch jobs: {}", e);
+            }
+        }
+    }));
+
+    // Config edited in the database directly, reload it once its write time moves
+    handles.push(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        let mut seen = None;
+        loop {
+            interval.tick().await;
+            let updated_at = match persistence::storage().config_updated_at().await {
+                Ok(Some(t)) => t,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Failed to check stored config: {}", e);
+                    continue;
+                }
+            };
+            if seen.is_some_and(|seen| seen != updated_at) {
+                info!("Stored config changed, reloading");
+                if let Err(e) = persistence::storage().spawn_bootstrap().await {
+                    warn!("Failed to reload stored config: {}", e);
+                }
+            }
+            seen = Some(updated_at);
         }
     }));
 
+    // Config changed by another instance, reload it
+    if let Some(mut events) = persistence::storage().subscribe() {
+        handles.push(tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(StorageEvent::Config) | Err(RecvError::Lagged(_)) => {
+                        if let Err(e) = persistence::storage().spawn_bootstrap().await {
+                            warn!("Failed to reload shared config: {}", e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }));
+    }
+
     Some(handles)
 }
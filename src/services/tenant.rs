@@ -0,0 +1,71 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tracing::{error, info};
+
+use crate::{
+    config::CLEWDR_CONFIG,
+    services::{cookie_actor::CookieActorHandle, key_actor::KeyActorHandle},
+};
+
+/// Cookie and key actors serving the pools of a tenant
+#[derive(Clone)]
+pub struct TenantActors {
+    pub cookie_actor_handle: CookieActorHandle,
+    pub key_actor_handle: KeyActorHandle,
+}
+
+/// Actors of every tenant, by name
+///
+/// Tenants are read from the config once at startup, so adding or removing one applies on
+/// restart.
+#[derive(Clone, Default)]
+pub struct TenantRegistry(Arc<HashMap<String, TenantActors>>);
+
+impl TenantRegistry {
+    /// Starts the actors and background tasks of every configured tenant
+    ///
+    /// A tenant whose actors fail to start is left out, its requests are answered with 404.
+    pub async fn start() -> Self {
+        let names = CLEWDR_CONFIG
+            .load()
+            .tenants
+            .iter()
+            .map(|t| t.name.to_owned())
+            .collect::<Vec<_>>();
+        let mut tenants = HashMap::new();
+        for name in names {
+            if let Err(e) = crate::persistence::import_tenant_pools(&name).await {
+                error!(
+                    "Failed to move the pools of tenant {} to storage: {}",
+                    name, e
+                );
+                continue;
+            }
+            let started = async {
+                Ok::<_, ractor::SpawnErr>(TenantActors {
+                    cookie_actor_handle: CookieActorHandle::start_tenant(&name).await?,
+                    key_actor_handle: KeyActorHandle::start_tenant(&name).await?,
+                })
+            };
+            let actors = match started.await {
+                Ok(actors) => actors,
+                Err(e) => {
+                    error!("Failed to start tenant {}: {}", name, e);
+                    continue;
+                }
+            };
+            let cookies = actors.cookie_actor_handle.to_owned();
+            let _probe = crate::services::cookie_prober::spawn(cookies.clone());
+            let _key_probe = crate::services::key_prober::spawn(actors.key_actor_handle.clone());
+            let _cleanup = crate::services::chat_cleaner::spawn(cookies.clone());
+            let _token_refresh = crate::services::token_refresher::spawn(cookies);
+            info!("Tenant {} started", name);
+            tenants.insert(name, actors);
+        }
+        Self(Arc::new(tenants))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &TenantActors)> {
+        self.0.iter()
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use colored::Colorize;
+use tracing::{info, warn};
+
+use crate::{
+    claude_code_state::ClaudeCodeState,
+    config::{CLEWDR_CONFIG, CookieStatus, HealthEvent, HealthEventKind},
+    services::cookie_actor::CookieActorHandle,
+};
+
+/// How long to wait before re-reading the config while the refresh is disabled
+const DISABLED_RECHECK: u64 = 60;
+/// Seconds without cookie requests after which the pool counts as idle
+const IDLE_AFTER: i64 = 30;
+
+/// Spawn the background Claude Code token refresh.
+///
+/// Tokens are otherwise only refreshed by the first request that finds them expired,
+/// which then waits for the OAuth round trip. Every `token_refresh_interval` seconds,
+/// while no cookie has been requested for a while, the tokens of valid cookies expiring
+/// within `token_refresh_lead` seconds are refreshed ahead of time. Failures are logged
+/// and recorded as health events of the cookie.
+/// An interval of 0 disables the refresh; the settings are hot reloadable.
+pub fn spawn(handle: CookieActorHandle) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let interval = CLEWDR_CONFIG.load().token_refresh_interval;
+            if interval == 0 {
+                tokio::time::sleep(Duration::from_secs(DISABLED_RECHECK)).await;
+                continue;
+            }
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            if handle.idle_secs() < IDLE_AFTER {
+                continue;
+            }
+            let Ok(status) = handle.get_status().await else {
+                continue;
+            };
+            let deadline = chrono::Utc::now()
+                + chrono::Duration::seconds(CLEWDR_CONFIG.load().token_refresh_lead as i64);
+            let due = status.valid.into_iter().filter(|c| {
+                !c.disabled && c.token.as_ref().is_some_and(|t| t.expires_at <= deadline)
+            });
+            for cookie in due {
+                // leave the rest for later once requests come in
+                if handle.idle_secs() < IDLE_AFTER {
+                    break;
+                }
+                refresh(&handle, cookie).await;
+            }
+        }
+    })
+}
+
+/// Refreshes the token of a single cookie, recording a health event when it fails
+///
+/// The cookie may be in use meanwhile, its return keeps whichever token expires last.
+async fn refresh(handle: &CookieActorHandle, cookie: CookieStatus) {
+    let mut state = ClaudeCodeState::new(handle.to_owned());
+    if let Err(e) = state.use_cookie(cookie.to_owned()) {
+        warn!(
+            "[TOKEN] {} failed to prepare: {}",
+            cookie.cookie.ellipse(),
+            e
+        );
+        return;
+    }
+    match state.force_refresh_token().await {
+        Ok(token) => info!(
+            "[TOKEN] {} refreshed, expires at {}",
+            cookie.cookie.ellipse().green(),
+            token.expires_at.to_rfc3339().blue()
+        ),
+        Err(e) => {
+            warn!(
+                "[TOKEN] {} refresh failed: {}",
+                cookie.cookie.ellipse().red(),
+                e
+            );
+            let event = HealthEvent::new(HealthEventKind::TokenRefreshFailed, e.to_string());
+            if let Err(e) = handle.record_health(cookie, event).await {
+                warn!("[TOKEN] failed to record health event: {}", e);
+            }
+        }
+    }
+}
@@ -2,11 +2,15 @@ use std::{
     env,
     fs::File,
     io::{BufReader, copy},
+    path::PathBuf,
 };
 
 use colored::Colorize;
 use http::header::USER_AGENT;
+use semver::Version;
 use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
 use snafu::ResultExt;
 use tracing::info;
 use wreq::Client;
@@ -14,13 +18,18 @@ use zip::ZipArchive;
 
 use crate::{
     Args,
-    config::CLEWDR_CONFIG,
+    config::{CLEWDR_CONFIG, UpdateChannel, WebhookEvent},
     error::{ClewdrError, WreqSnafu},
+    services::webhook,
 };
 
 #[derive(Debug, Deserialize)]
 struct GitHubRelease {
     tag_name: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
     assets: Vec<GitHubAsset>,
 }
 
@@ -28,6 +37,9 @@ struct GitHubRelease {
 struct GitHubAsset {
     name: String,
     browser_download_url: String,
+    /// `sha256:<hex>`, filled in by GitHub for newer uploads
+    #[serde(default)]
+    digest: Option<String>,
 }
 
 /// Updater for the ClewdR application
@@ -91,8 +103,55 @@ impl ClewdrUpdater {
         info!("Checking for updates...");
         // info!("User-Agent: {}", self.user_agent);
 
+        let release = self
+            .fetch_release(CLEWDR_CONFIG.load().update_channel)
+            .await?;
+        let latest_version = release.tag_name.trim_start_matches('v');
+        let current_version = env!("CARGO_PKG_VERSION");
+
+        let update_available = self.compare_versions(current_version, latest_version)?;
+
+        if !update_available {
+            info!("Already at the latest version {}", current_version.green());
+            return Ok(false);
+        }
+        info!(
+            "New version {} available (current: {})",
+            latest_version.green().italic(),
+            current_version.yellow()
+        );
+        webhook::emit(
+            WebhookEvent::UpdateAvailable,
+            latest_version,
+            format!("ClewdR {latest_version} is available (current: {current_version})"),
+            json!({ "current": current_version, "latest": latest_version }),
+        );
+        // Auto update if enabled
+        if args.update || CLEWDR_CONFIG.load().auto_update {
+            if let Err(e) = self.perform_update(&release).await {
+                webhook::emit(
+                    WebhookEvent::UpdateFailed,
+                    latest_version,
+                    format!("Updating ClewdR to {latest_version} failed: {e}"),
+                    json!({ "current": current_version, "latest": latest_version, "error": e.to_string() }),
+                );
+                return Err(e);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Fetches the newest release of the given channel
+    ///
+    /// # Arguments
+    /// * `channel` - Stable takes the newest release not marked as a pre-release, beta any release
+    ///
+    /// # Returns
+    /// * `Result<GitHubRelease, ClewdrError>` - Newest release or error
+    async fn fetch_release(&self, channel: UpdateChannel) -> Result<GitHubRelease, ClewdrError> {
         let url = format!(
-            "https://api.github.com/repos/{}/{}/releases/latest",
+            "https://api.github.com/repos/{}/{}/releases?per_page=20",
             self.repo_owner, self.repo_name
         );
 
@@ -110,29 +169,20 @@ impl ClewdrUpdater {
                 msg: "Fetch latest release from GitHub returned an error",
             })?;
 
-        let release: GitHubRelease = response.json().await.context(WreqSnafu {
+        let releases: Vec<GitHubRelease> = response.json().await.context(WreqSnafu {
             msg: "Failed to parse GitHub release response",
         })?;
-        let latest_version = release.tag_name.trim_start_matches('v');
-        let current_version = env!("CARGO_PKG_VERSION");
-
-        let update_available = self.compare_versions(current_version, latest_version)?;
-
-        if !update_available {
-            info!("Already at the latest version {}", current_version.green());
-            return Ok(false);
-        }
-        info!(
-            "New version {} available (current: {})",
-            latest_version.green().italic(),
-            current_version.yellow()
-        );
-        // Auto update if enabled
-        if args.update || CLEWDR_CONFIG.load().auto_update {
-            self.perform_update(&release).await?;
-        }
-
-        Ok(true)
+        releases
+            .into_iter()
+            .filter(|r| !r.draft && (channel == UpdateChannel::Beta || !r.prerelease))
+            .filter_map(|r| Some((parse_version(r.tag_name.trim_start_matches('v')).ok()?, r)))
+            // nor a `-pre` tag on a release not flagged as such
+            .filter(|(v, _)| channel == UpdateChannel::Beta || v.pre.is_empty())
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, r)| r)
+            .ok_or(ClewdrError::AssetError {
+                msg: format!("No release found on the {channel:?} channel"),
+            })
     }
 
     /// Performs the update process
@@ -174,6 +224,17 @@ impl ClewdrUpdater {
         let content = response.bytes().await.context(WreqSnafu {
             msg: "Failed to read response bytes from update asset",
         })?;
+        let expected = self.expected_sha256(release, asset).await?;
+        let actual = hex::encode(Sha256::digest(&content));
+        if !actual.eq_ignore_ascii_case(&expected) {
+            return Err(ClewdrError::AssetError {
+                msg: format!(
+                    "Checksum mismatch for {}: expected {}, got {}",
+                    asset.name, expected, actual
+                ),
+            });
+        }
+        info!("Verified SHA-256 of {}", asset.name);
         let mut file = File::create(&zip_path)?;
         copy(&mut content.as_ref(), &mut file)?;
 
@@ -229,6 +290,11 @@ impl ClewdrUpdater {
             }
         }
 
+        // Keep the running binary for `--rollback`
+        let backup = previous_binary_path()?;
+        std::fs::copy(env::current_exe()?, &backup)?;
+        info!("Previous binary kept at {}", backup.display());
+
         // Replace the current binary
         self_replace::self_replace(&binary_path)?;
 
@@ -237,6 +303,81 @@ impl ClewdrUpdater {
         std::process::exit(0);
     }
 
+    /// Looks up the published SHA-256 of an asset
+    /// Uses the digest GitHub reports for the asset, or a `<asset>.sha256` file of the release
+    ///
+    /// # Arguments
+    /// * `release` - Release the asset belongs to
+    /// * `asset` - Asset to be verified
+    ///
+    /// # Returns
+    /// * `Result<String, ClewdrError>` - Hex encoded checksum, error if none is published
+    async fn expected_sha256(
+        &self,
+        release: &GitHubRelease,
+        asset: &GitHubAsset,
+    ) -> Result<String, ClewdrError> {
+        if let Some(digest) = asset
+            .digest
+            .as_deref()
+            .and_then(|d| d.strip_prefix("sha256:"))
+        {
+            return Ok(digest.to_string());
+        }
+        let sidecar = format!("{}.sha256", asset.name);
+        let Some(sidecar) = release.assets.iter().find(|a| a.name == sidecar) else {
+            return Err(ClewdrError::AssetError {
+                msg: format!(
+                    "No checksum published for {}, refusing to update",
+                    asset.name
+                ),
+            });
+        };
+        let text = self
+            .client
+            .get(&sidecar.browser_download_url)
+            .header(USER_AGENT, &self.user_agent)
+            .send()
+            .await
+            .context(WreqSnafu {
+                msg: "Failed to download checksum",
+            })?
+            .error_for_status()
+            .context(WreqSnafu {
+                msg: "Download checksum returned an error",
+            })?
+            .text()
+            .await
+            .context(WreqSnafu {
+                msg: "Failed to read checksum",
+            })?;
+        // `sha256sum` format, the hash comes first
+        text.split_whitespace()
+            .next()
+            .map(ToString::to_string)
+            .ok_or(ClewdrError::AssetError {
+                msg: format!("Empty checksum file for {}", asset.name),
+            })
+    }
+
+    /// Restores the binary replaced by the last update
+    /// Meant for `--rollback` after the updated binary failed to start
+    ///
+    /// # Returns
+    /// * `Result<(), ClewdrError>` - Success or error if no previous binary is kept
+    pub fn rollback() -> Result<(), ClewdrError> {
+        let backup = previous_binary_path()?;
+        if !backup.exists() {
+            return Err(ClewdrError::AssetError {
+                msg: format!("No previous binary found at {}", backup.display()),
+            });
+        }
+        self_replace::self_replace(&backup)?;
+        std::fs::remove_file(&backup)?;
+        println!("{}", "Rolled back to the previous binary".green());
+        Ok(())
+    }
+
     /// Finds the appropriate asset for the current platform and architecture
     ///
     /// # Arguments
@@ -297,17 +438,34 @@ impl ClewdrUpdater {
     /// # Returns
     /// * `Result<bool, ClewdrError>` - True if latest is newer than current, false otherwise
     fn compare_versions(&self, current: &str, latest: &str) -> Result<bool, ClewdrError> {
-        let parse_version = |v: &str| -> Result<(u32, u32, u32), ClewdrError> {
-            let vec = v.split('.').collect::<Vec<_>>();
-            let [major, minor, patch, ..] = vec.as_slice() else {
-                return Err(ClewdrError::InvalidVersion {
-                    version: v.to_string(),
-                });
-            };
-            Ok((major.parse()?, minor.parse()?, patch.parse()?))
-        };
-        let current = parse_version(current)?;
-        let latest = parse_version(latest)?;
-        Ok(current < latest)
+        Ok(parse_version(current)? < parse_version(latest)?)
+    }
+}
+
+/// Parses a semantic version, a pre-release orders before the release of the same version
+fn parse_version(v: &str) -> Result<Version, ClewdrError> {
+    Version::parse(v).map_err(|_| ClewdrError::InvalidVersion {
+        version: v.to_string(),
+    })
+}
+
+/// Where the binary replaced by an update is kept, next to the current one
+fn previous_binary_path() -> Result<PathBuf, ClewdrError> {
+    let mut path = env::current_exe()?.into_os_string();
+    path.push(".old");
+    Ok(path.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pre_releases_order_before_releases() {
+        assert!(parse_version("0.11.0").unwrap() < parse_version("0.11.1-beta.1").unwrap());
+        assert!(parse_version("0.11.1-beta.1").unwrap() < parse_version("0.11.1-beta.2").unwrap());
+        assert!(parse_version("0.11.1-beta.2").unwrap() < parse_version("0.11.1").unwrap());
+        assert!(parse_version("0.11.1-beta.9").unwrap() < parse_version("0.11.1-beta.10").unwrap());
+        assert!(parse_version("0.11").is_err());
     }
 }
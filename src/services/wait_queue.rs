@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+
+use ractor::RpcReplyPort;
+
+use crate::{config::CLEWDR_CONFIG, error::ClewdrError};
+
+type Reply<T> = RpcReplyPort<Result<T, ClewdrError>>;
+
+/// FIFO queue of requests waiting for a cookie or key to become available, by rank when ranked
+///
+/// Waiters give up on their own once `queue_timeout` elapses, their closed
+/// reply ports are pruned lazily.
+pub struct WaitQueue<A, T> {
+    waiters: VecDeque<(A, Reply<T>)>,
+}
+
+impl<A, T> Default for WaitQueue<A, T> {
+    fn default() -> Self {
+        Self {
+            waiters: VecDeque::new(),
+        }
+    }
+}
+
+impl<A, T> std::fmt::Debug for WaitQueue<A, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WaitQueue")
+            .field("waiters", &self.waiters.len())
+            .finish()
+    }
+}
+
+impl<A, T> WaitQueue<A, T> {
+    /// Drops waiters whose caller already timed out
+    pub fn prune(&mut self) {
+        self.waiters.retain(|(_, port)| !port.is_closed());
+    }
+
+    /// Whether any caller is still waiting
+    pub fn is_empty(&mut self) -> bool {
+        self.prune();
+        self.waiters.is_empty()
+    }
+
+    /// Queues a waiter
+    ///
+    /// # Returns
+    /// * `Err(port)` - Queueing is disabled or the queue is full, the caller should be answered directly
+    pub fn push(&mut self, arg: A, port: Reply<T>) -> Result<(), Reply<T>> {
+        self.push_ranked(arg, port, |_| ())
+    }
+
+    /// Queues a waiter behind every waiter of the same or a higher rank
+    ///
+    /// When the queue is full, the newest waiter of a lower rank is answered with
+    /// `NoCookieAvailable` to make room.
+    ///
+    /// # Returns
+    /// * `Err(port)` - Queueing is disabled or the queue is full, the caller should be answered directly
+    pub fn push_ranked<R: Ord>(
+        &mut self,
+        arg: A,
+        port: Reply<T>,
+        rank: impl Fn(&A) -> R,
+    ) -> Result<(), Reply<T>> {
+        let config = CLEWDR_CONFIG.load();
+        if !config.enable_queue {
+            return Err(port);
+        }
+        self.prune();
+        let own = rank(&arg);
+        if self.waiters.len() >= config.queue_size {
+            let Some(lowest) = self.waiters.iter().rposition(|(a, _)| rank(a) < own) else {
+                return Err(port);
+            };
+            if let Some((_, dropped)) = self.waiters.remove(lowest) {
+                let _ = dropped.send(Err(ClewdrError::NoCookieAvailable));
+            }
+        }
+        let at = self
+            .waiters
+            .iter()
+            .position(|(a, _)| rank(a) < own)
+            .unwrap_or(self.waiters.len());
+        self.waiters.insert(at, (arg, port));
+        Ok(())
+    }
+
+    /// Argument of the waiter at `index`, in queue order
+    pub fn get(&self, index: usize) -> Option<&A> {
+        self.waiters.get(index).map(|(arg, _)| arg)
+    }
+
+    /// Answers the waiter at `index` with `result` and removes it from the queue
+    pub fn answer(&mut self, index: usize, result: Result<T, ClewdrError>) {
+        if let Some((_, port)) = self.waiters.remove(index) {
+            let _ = port.send(result);
+        }
+    }
+
+    /// Serves waiters in arrival order for as long as `serve` produces a resource
+    pub fn drain(&mut self, mut serve: impl FnMut(&A) -> Option<T>) {
+        self.prune();
+        while let Some((arg, _)) = self.waiters.front() {
+            let Some(item) = serve(arg) else {
+                return;
+            };
+            if let Some((_, port)) = self.waiters.pop_front() {
+                let _ = port.send(Ok(item));
+            }
+        }
+    }
+}
+
+/// How long a caller waits for a queued request, in milliseconds
+///
+/// # Returns
+/// * `Option<u64>` - `None` when queueing is disabled
+pub fn wait_timeout() -> Option<u64> {
+    let config = CLEWDR_CONFIG.load();
+    config
+        .enable_queue
+        .then(|| config.queue_timeout.max(1) * 1000)
+}
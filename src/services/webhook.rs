@@ -0,0 +1,203 @@
+use std::{sync::LazyLock, time::Duration};
+
+use aws_lc_rs::hmac;
+use moka::sync::Cache;
+use serde::Serialize;
+use serde_json::{Value, json};
+use tracing::{info, warn};
+use wreq::Client;
+
+use crate::{
+    config::{CLEWDR_CONFIG, WebhookConfig, WebhookEvent},
+    services::notify,
+};
+
+/// Header naming the event of a delivery
+pub const EVENT_HEADER: &str = "x-clewdr-event";
+/// Header carrying `sha256=<hex>`, the HMAC-SHA256 of the body under the endpoint's secret
+pub const SIGNATURE_HEADER: &str = "x-clewdr-signature";
+/// Attempts of a delivery before it is given up
+const ATTEMPTS: u32 = 3;
+/// Delay before the first retry, doubled for each further one
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+/// Longest wait for an endpoint to answer
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Repeats of an event for the same subject are dropped for this long
+const COOLDOWN: Duration = Duration::from_secs(5 * 60);
+
+/// Events sent recently, by event and subject
+static RECENT: LazyLock<Cache<(WebhookEvent, String), ()>> =
+    LazyLock::new(|| Cache::builder().time_to_live(COOLDOWN).build());
+
+/// Outcome of a delivery to one endpoint
+#[derive(Debug, Serialize)]
+pub struct Delivery {
+    pub url: String,
+    /// Status of the last answer, `None` if the endpoint could not be reached
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// Body of a delivery
+///
+/// `text` and `content` repeat the message, so Slack and Discord incoming webhooks
+/// can post it as is.
+fn payload(event: WebhookEvent, message: &str, data: Value) -> String {
+    json!({
+        "event": event,
+        "at": chrono::Utc::now().timestamp(),
+        "message": message,
+        "text": message,
+        "content": message,
+        "data": data,
+    })
+    .to_string()
+}
+
+fn sign(secret: &str, body: &str) -> String {
+    let tag = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+        body.as_bytes(),
+    );
+    let hex = tag
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    format!("sha256={hex}")
+}
+
+/// HTTP client of deliveries, through the configured proxy
+pub(crate) fn client() -> Result<Client, wreq::Error> {
+    let mut builder = Client::builder();
+    if let Some(proxy) = CLEWDR_CONFIG.load().wreq_proxy.to_owned() {
+        builder = builder.proxy(proxy);
+    }
+    builder.build()
+}
+
+/// Notifies the configured endpoints of an event in the background
+///
+/// The same event about the same `subject`, e.g. a cookie, is sent at most once per
+/// `COOLDOWN`, so a flapping pool does not flood the channel. Critical events are
+/// passed on to the chat alerts of `notify` too.
+///
+/// # Arguments
+/// * `subject` - What the event is about, empty for service wide events
+/// * `message` - Human readable summary
+/// * `data` - Details of the event
+pub fn emit(event: WebhookEvent, subject: &str, message: impl Into<String>, data: Value) {
+    let config = CLEWDR_CONFIG.load();
+    if config.webhooks.is_empty() && !config.notify.is_enabled() {
+        return;
+    }
+    let hooks = config
+        .webhooks
+        .iter()
+        .filter(|h| h.accepts(event))
+        .cloned()
+        .collect::<Vec<_>>();
+    drop(config);
+    let key = (event, subject.to_string());
+    if RECENT.contains_key(&key) {
+        return;
+    }
+    RECENT.insert(key, ());
+    let message = message.into();
+    notify::alert(event, &message);
+    let body = payload(event, &message, data);
+    for hook in hooks {
+        let body = body.to_owned();
+        tokio::spawn(async move {
+            deliver(&hook, event, &body).await;
+        });
+    }
+}
+
+/// Sends a test event to every configured endpoint and waits for the outcomes
+pub async fn test_fire() -> Vec<Delivery> {
+    let hooks = CLEWDR_CONFIG.load().webhooks.to_owned();
+    let body = payload(
+        WebhookEvent::Test,
+        "ClewdR webhook test",
+        json!({ "version": env!("CARGO_PKG_VERSION") }),
+    );
+    futures::future::join_all(
+        hooks
+            .iter()
+            .map(|hook| deliver(hook, WebhookEvent::Test, &body)),
+    )
+    .await
+}
+
+/// Posts `body` to an endpoint, retrying failed attempts
+///
+/// Network errors, 429 and 5xx answers are retried, other answers are final.
+async fn deliver(hook: &WebhookConfig, event: WebhookEvent, body: &str) -> Delivery {
+    let mut delivery = Delivery {
+        url: hook.url.to_owned(),
+        status: None,
+        error: None,
+    };
+    let client = match client() {
+        Ok(client) => client,
+        Err(e) => {
+            delivery.error = Some(e.to_string());
+            return delivery;
+        }
+    };
+    let event_name = serde_json::to_value(event)
+        .ok()
+        .and_then(|v| v.as_str().map(ToOwned::to_owned))
+        .unwrap_or_default();
+    for attempt in 0..ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(RETRY_DELAY * 2u32.pow(attempt - 1)).await;
+        }
+        let mut req = client
+            .post(&hook.url)
+            .header("content-type", "application/json")
+            .header(EVENT_HEADER, &event_name);
+        if let Some(secret) = hook.secret.as_deref().filter(|s| !s.is_empty()) {
+            req = req.header(SIGNATURE_HEADER, sign(secret, body));
+        }
+        match tokio::time::timeout(TIMEOUT, req.body(body.to_owned()).send()).await {
+            Ok(Ok(res)) => {
+                let status = res.status();
+                delivery.status = Some(status.as_u16());
+                delivery.error = None;
+                if status.is_success() {
+                    info!("[WEBHOOK] {} delivered to {}", event_name, hook.url);
+                    return delivery;
+                }
+                delivery.error = Some(format!("Endpoint answered {status}"));
+                if status != 429 && !status.is_server_error() {
+                    break;
+                }
+            }
+            Ok(Err(e)) => delivery.error = Some(e.to_string()),
+            Err(_) => delivery.error = Some("Endpoint timed out".to_string()),
+        }
+    }
+    warn!(
+        "[WEBHOOK] {} could not be delivered to {}: {}",
+        event_name,
+        hook.url,
+        delivery.error.as_deref().unwrap_or_default()
+    );
+    delivery
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_matches_known_vector() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
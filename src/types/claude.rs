@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Value, json};
 use serde_with::{DefaultOnError, serde_as};
-use tiktoken_rs::o200k_base;
+
+use crate::utils::tokenizer::Tokenizer;
 
 #[derive(Debug)]
 pub struct RequiredMessageParams {
@@ -10,7 +11,7 @@ pub struct RequiredMessageParams {
     pub max_tokens: u32,
 }
 
-pub(super) fn default_max_tokens() -> u32 {
+pub(crate) fn default_max_tokens() -> u32 {
     8192
 }
 /// Parameters for creating a message
@@ -62,8 +63,9 @@ pub struct CreateMessageParams {
 }
 
 impl CreateMessageParams {
+    /// Estimated prompt tokens, with the tokenizer of the requested model
     pub fn count_tokens(&self) -> u32 {
-        let bpe = o200k_base().expect("Failed to get encoding");
+        let tokenizer = Tokenizer::for_model(&self.model);
         let systems = match self.system {
             Some(Value::String(ref s)) => s.to_string(),
             Some(Value::Array(ref arr)) => arr.iter().filter_map(|v| v["text"].as_str()).collect(),
@@ -84,8 +86,35 @@ impl CreateMessageParams {
             })
             .collect::<Vec<_>>()
             .join("\n");
-        bpe.encode_with_special_tokens(&systems).len() as u32
-            + bpe.encode_with_special_tokens(&messages).len() as u32
+        tokenizer.count(&systems) + tokenizer.count(&messages)
+    }
+
+    /// Marks the end of the system prompt as a prompt cache breakpoint
+    ///
+    /// Skipped when the system prompt is smaller than `min_tokens` or the client
+    /// already placed its own `cache_control` markers.
+    ///
+    /// # Returns
+    /// * `bool` - Whether a marker was added
+    pub fn cache_system_prompt(&mut self, min_tokens: u32) -> bool {
+        let Some(Value::Array(ref mut blocks)) = self.system else {
+            return false;
+        };
+        if blocks.iter().any(|b| b.get("cache_control").is_some()) {
+            return false;
+        }
+        let text = blocks
+            .iter()
+            .filter_map(|b| b["text"].as_str())
+            .collect::<String>();
+        if Tokenizer::for_model(&self.model).count(&text) < min_tokens {
+            return false;
+        }
+        let Some(last) = blocks.last_mut().and_then(|b| b.as_object_mut()) else {
+            return false;
+        };
+        last.insert("cache_control".to_string(), json!({ "type": "ephemeral" }));
+        true
     }
 }
 
@@ -224,6 +253,9 @@ pub enum ContentBlock {
         tool_use_id: String,
         content: serde_json::Value,
     },
+    /// oai file reference
+    #[serde(rename = "file")]
+    File { file: FileRef },
 }
 
 /// Source of an image
@@ -244,6 +276,16 @@ pub struct ImageUrl {
     pub url: String,
 }
 
+// oai file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct FileRef {
+    /// Id returned by the files API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
+
 /// Tool definition
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Tool {
@@ -269,6 +311,9 @@ pub enum ToolChoice {
     /// Model must use a specific tool
     #[serde(rename = "tool")]
     Tool { name: String },
+    /// Model must not use tools
+    #[serde(rename = "none")]
+    None,
 }
 
 /// Message metadata
@@ -302,8 +347,8 @@ pub struct CreateMessageResponse {
 }
 
 impl CreateMessageResponse {
+    /// Estimated output tokens, with the tokenizer of the model
     pub fn count_tokens(&self) -> u32 {
-        let bpe = o200k_base().expect("Failed to get encoding");
         let content = self
             .content
             .iter()
@@ -314,10 +359,34 @@ impl CreateMessageResponse {
             })
             .collect::<Vec<_>>()
             .join("\n");
-        bpe.encode_with_special_tokens(&content).len() as u32
+        Tokenizer::for_model(&self.model).count(&content)
     }
 }
 
+/// Token usage of a non-stream Claude response body
+///
+/// # Returns
+/// * `Option<(u64, u64)>` - Input and output tokens, the input is 0 when the body reports no
+///   usage and the output is estimated from the content
+pub fn parse_usage(body: &[u8]) -> Option<(u64, u64)> {
+    // Prefer explicit usage if present
+    if let Ok(value) = serde_json::from_slice::<Value>(body)
+        && let Some(usage) = value.get("usage")
+    {
+        let input = usage
+            .get("input_tokens")
+            .and_then(|v| v.as_u64().or_else(|| v.as_i64().map(|n| n.max(0) as u64)));
+        let output = usage
+            .get("output_tokens")
+            .and_then(|v| v.as_u64().or_else(|| v.as_i64().map(|n| n.max(0) as u64)));
+        if let (Some(i), Some(o)) = (input, output) {
+            return Some((i, o));
+        }
+    }
+    let parsed = serde_json::from_slice::<CreateMessageResponse>(body).ok()?;
+    Some((0, parsed.count_tokens() as u64))
+}
+
 impl CreateMessageResponse {
     /// Create a new response with the given content blocks
     pub fn text(content: String, model: String, usage: Usage) -> Self {
@@ -352,6 +421,12 @@ pub struct Usage {
     pub input_tokens: u32,
     /// Output tokens used
     pub output_tokens: u32,
+    /// Input tokens written to the prompt cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<u32>,
+    /// Input tokens read from the prompt cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<u32>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Default)]
@@ -361,6 +436,12 @@ pub struct StreamUsage {
     pub input_tokens: u32,
     /// Output tokens used
     pub output_tokens: u32,
+    /// Input tokens written to the prompt cache, cumulative for the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<u32>,
+    /// Input tokens read from the prompt cache, cumulative for the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<u32>,
 }
 
 impl Message {
@@ -471,6 +552,26 @@ pub enum ContentBlockDelta {
     ThinkingDelta { thinking: String },
     #[serde(rename = "signature_delta")]
     SignatureDelta { signature: String },
+    /// Claude.ai web search, the following text cites a source
+    #[serde(rename = "citation_start_delta")]
+    CitationStartDelta { citation: Citation },
+    /// Claude.ai web search, end of the text citing a source
+    #[serde(rename = "citation_end_delta")]
+    CitationEndDelta {
+        #[serde(default)]
+        citation_uuid: String,
+    },
+}
+
+/// Source cited by Claude.ai web search results
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct Citation {
+    #[serde(default)]
+    pub uuid: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub url: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, Default)]
@@ -40,8 +40,14 @@ pub struct WebRequestBody {
     pub rendering_mode: String,
     pub prompt: String,
     pub timezone: String,
+    /// Message the prompt replies to when continuing a conversation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_message_uuid: Option<String>,
     #[serde(skip)]
     pub images: Vec<ImageSource>,
+    /// Files API ids, resolved to Claude.ai file uuids before sending
+    #[serde(skip)]
+    pub file_ids: Vec<String>,
     pub tools: Vec<Tool>,
 }
 
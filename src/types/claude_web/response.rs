@@ -1,24 +1,31 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
 use async_stream::try_stream;
 use axum::{
     BoxError, Json,
     response::{IntoResponse, Sse, sse::Event as SseEvent},
 };
 use bytes::Bytes;
-use eventsource_stream::{EventStream, Eventsource};
+use eventsource_stream::{Event, EventStream, Eventsource};
 use futures::{Stream, TryStreamExt};
 use serde::Deserialize;
 use url::Url;
-use wreq::Proxy;
 
 use crate::{
     claude_code_state::ClaudeCodeState,
-    claude_web_state::ClaudeWebState,
+    claude_web_state::{
+        ClaudeWebState,
+        artifacts::{self, Artifact, ArtifactStream},
+    },
     error::{CheckClaudeErr, ClewdrError},
     types::claude::{
         ContentBlock, CountMessageTokensResponse, CreateMessageParams, CreateMessageResponse,
         Message, Role,
     },
-    utils::print_out_text,
+    utils::{backpressure, print_out_text, tokenizer::Tokenizer},
 };
 
 /// Merges server-sent events (SSE) from a stream into a single string
@@ -46,6 +53,21 @@ pub async fn merge_sse(
         .await?)
 }
 
+/// Sends an upstream event on, fields the upstream left empty stay unset
+fn to_sse(event: Event) -> SseEvent {
+    let mut e = SseEvent::default();
+    if !event.event.is_empty() {
+        e = e.event(event.event);
+    }
+    if !event.id.is_empty() {
+        e = e.id(event.id);
+    }
+    if let Some(retry) = event.retry {
+        e = e.retry(retry);
+    }
+    e.data(event.data)
+}
+
 impl<S> From<S> for Message
 where
     S: Into<String>,
@@ -91,6 +113,10 @@ impl ClaudeWebState {
             let endpoint = self.endpoint.clone();
             let proxy = self.proxy.clone();
             let client = self.client.clone();
+            let mut artifacts = crate::config::CLEWDR_CONFIG
+                .load()
+                .extract_artifacts
+                .then(ArtifactStream::default);
             // try to get precise input tokens via Claude Code count_tokens if enabled
             if crate::config::CLEWDR_CONFIG.load().enable_web_count_tokens
                 && let Some(tokens) = self.try_code_count_tokens().await
@@ -98,6 +124,19 @@ impl ClaudeWebState {
                 input_tokens = tokens as u64;
             }
 
+            let returned = Arc::new(AtomicBool::new(false));
+            // a client too slow to read the stream gets its upstream request dropped
+            let on_abort = {
+                let (handle, cookie, returned) = (handle.clone(), cookie.clone(), returned.clone());
+                async move {
+                    if let Some(cookie) = cookie
+                        && !returned.load(Ordering::Relaxed)
+                    {
+                        let _ = handle.return_cookie(cookie, None).await;
+                    }
+                }
+            };
+
             let stream = wreq_res
                 .bytes_stream()
                 .eventsource()
@@ -111,9 +150,13 @@ impl ClaudeWebState {
                     if let Ok(d) = serde_json::from_str::<Data>(&event.data) {
                         acc.push_str(&d.completion);
                     }
-                    let e = SseEvent::default().event(event.event).id(event.id);
-                    let e = if let Some(retry) = event.retry { e.retry(retry) } else { e };
-                    yield e.data(event.data);
+                    let events = match artifacts.as_mut() {
+                        Some(artifacts) => artifacts.relay(event),
+                        None => vec![event],
+                    };
+                    for event in events {
+                        yield to_sse(event);
+                    }
                 }
                 // on end of stream, compute output tokens and persist totals
                 if !acc.is_empty() {
@@ -127,11 +170,7 @@ impl ClaudeWebState {
                             model, acc.clone(), handle.clone()
                         ).await.map(|v| v as u64);
                     }
-                    let out = out.unwrap_or_else(|| {
-                        let usage = crate::types::claude::Usage { input_tokens: input_tokens as u32, output_tokens: 0 };
-                        let resp = crate::types::claude::CreateMessageResponse::text(acc.clone(), Default::default(), usage);
-                        resp.count_tokens() as u64
-                    });
+                    let out = out.unwrap_or_else(|| Tokenizer::Claude.count(&acc) as u64);
                     if let Some(mut c) = cookie.clone() {
                         let family = last_params
                             .as_ref()
@@ -148,6 +187,7 @@ impl ClaudeWebState {
                             })
                             .unwrap_or(crate::config::ModelFamily::Other);
                         c.add_and_bucket_usage(input_tokens, out, family);
+                        returned.store(true, Ordering::Relaxed);
                         let _ = handle.return_cookie(c, None).await;
                     }
                 } else if let Some(mut c) = cookie.clone() {
@@ -167,12 +207,13 @@ impl ClaudeWebState {
                         })
                         .unwrap_or(crate::config::ModelFamily::Other);
                     c.add_and_bucket_usage(input_tokens, 0, family);
+                    returned.store(true, Ordering::Relaxed);
                     let _ = handle.return_cookie(c, None).await;
                 }
             };
             // normalize error type for axum SSE
             let stream = stream.map_err(|e: axum::Error| -> BoxError { e.into() });
-            return Ok(Sse::new(stream)
+            return Ok(Sse::new(backpressure::relay(stream, on_abort))
                 .keep_alive(Default::default())
                 .into_response());
         }
@@ -183,6 +224,14 @@ impl ClaudeWebState {
         print_out_text(text.to_owned(), "claude_web_non_stream.txt");
         let mut response =
             CreateMessageResponse::text(text.clone(), Default::default(), self.usage.to_owned());
+        if crate::config::CLEWDR_CONFIG.load().extract_artifacts {
+            let (rest, artifacts) = artifacts::extract(&text);
+            response.content = (!rest.is_empty())
+                .then(|| ContentBlock::text(rest))
+                .into_iter()
+                .chain(artifacts.iter().map(Artifact::to_tool_use))
+                .collect();
+        }
 
         // Prefer official counting if enabled
         let enable_precise = crate::config::CLEWDR_CONFIG.load().enable_web_count_tokens;
@@ -190,7 +239,7 @@ impl ClaudeWebState {
         if enable_precise && let Some(inp) = self.try_code_count_tokens().await {
             usage.input_tokens = inp;
         }
-        let mut output_tokens = response.count_tokens();
+        let mut output_tokens = Tokenizer::Claude.count(&text);
         if enable_precise && let Some(model) = self.last_params.as_ref().map(|p| p.model.clone()) {
             let out = count_code_output_tokens_for_text(
                 self.cookie.clone(),
@@ -245,12 +294,13 @@ impl ClaudeWebState {
         code.cookie = self.cookie.clone();
         code.endpoint = self.endpoint.clone();
         code.proxy = self.proxy.clone();
+        code.jar = self.jar.clone();
         code.client = self.client.clone();
-        // populate cookie into client's jar for API domain
+        // the pooled client keeps no cookies, send it with each request
         if let Some(ref c) = self.cookie
             && let Ok(val) = http::HeaderValue::from_str(&c.cookie.to_string())
         {
-            code.client.set_cookie(&self.endpoint, &val);
+            code.set_cookie_header_value(val);
         }
 
         // OAuth exchange to get access token
@@ -271,7 +321,7 @@ impl ClaudeWebState {
 async fn count_code_output_tokens_for_text(
     cookie: Option<crate::config::CookieStatus>,
     endpoint: Url,
-    proxy: Option<Proxy>,
+    proxy: Option<String>,
     client: wreq::Client,
     model: String,
     text: String,
@@ -285,7 +335,7 @@ async fn count_code_output_tokens_for_text(
     if let Some(ref c) = cookie
         && let Ok(val) = http::HeaderValue::from_str(&c.cookie.to_string())
     {
-        code.client.set_cookie(&code.endpoint, &val);
+        code.set_cookie_header_value(val);
     }
     let org = code.get_organization().await.ok()?;
     let exch = code.exchange_code(&org).await.ok()?;
@@ -0,0 +1,547 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value, json};
+
+use super::request::SystemInstruction;
+use crate::{
+    types::{
+        claude::{ContentBlock, FileRef},
+        oai::{
+            CreateMessageParams, OaiContent, OaiRole, OaiToolChoice, ResponseFormat, url_citation,
+        },
+    },
+    utils::json_schema,
+};
+
+/// Gemini grounding tools, in both casings of the native API
+const GROUNDING_TOOLS: [&str; 4] = [
+    "google_search",
+    "googleSearch",
+    "google_search_retrieval",
+    "googleSearchRetrieval",
+];
+
+/// Native grounding tool of an OpenAI `tools` entry, `{"type": "google_search"}` or
+/// `{"google_search": {}}`
+fn grounding_tool(tool: &Value) -> Option<Value> {
+    if let Some(name) = tool["type"].as_str() {
+        return GROUNDING_TOOLS.contains(&name).then(|| {
+            let config = tool.get(name).cloned().unwrap_or_else(|| json!({}));
+            json!({ name: config })
+        });
+    }
+    let map = tool.as_object().filter(|m| m.len() == 1)?;
+    let (name, _) = map.iter().next()?;
+    GROUNDING_TOOLS
+        .contains(&name.as_str())
+        .then(|| tool.to_owned())
+}
+
+/// Takes the grounding tools out of a raw OpenAI request
+///
+/// `tools` entries naming a grounding tool become native tools, OpenAI's
+/// `web_search_options` turns on Google Search. Function tools are left in place.
+pub fn take_grounding(body: &mut Value) -> Vec<Value> {
+    let mut grounding = Vec::new();
+    if let Some(tools) = body.get_mut("tools").and_then(Value::as_array_mut) {
+        tools.retain(|tool| match grounding_tool(tool) {
+            Some(native) => {
+                grounding.push(native);
+                false
+            }
+            None => true,
+        });
+    }
+    let Some(body) = body.as_object_mut() else {
+        return grounding;
+    };
+    if body["tools"].as_array().is_some_and(Vec::is_empty) {
+        body.remove("tools");
+    }
+    if body.remove("web_search_options").is_some() && grounding.is_empty() {
+        grounding.push(json!({ "google_search": {} }));
+    }
+    grounding
+}
+
+/// Native part of an image URL, inline for data URLs
+fn url_part(url: &str) -> Value {
+    match url.strip_prefix("data:").and_then(|u| u.split_once(',')) {
+        Some((meta, data)) => json!({ "inline_data": {
+            "mime_type": meta.split(';').next().unwrap_or_default(),
+            "data": data,
+        }}),
+        None => json!({ "file_data": { "file_uri": url } }),
+    }
+}
+
+fn content_parts(content: &OaiContent) -> Vec<Value> {
+    match content {
+        OaiContent::Text(text) => vec![json!({ "text": text })],
+        OaiContent::Parts(parts) => parts
+            .iter()
+            .filter_map(|part| match part {
+                ContentBlock::Text { text } => Some(json!({ "text": text })),
+                ContentBlock::ImageUrl { image_url } => Some(url_part(&image_url.url)),
+                ContentBlock::Image { source } => Some(json!({ "inline_data": {
+                    "mime_type": source.media_type,
+                    "data": source.data,
+                }})),
+                ContentBlock::File {
+                    file:
+                        FileRef {
+                            file_id: Some(id), ..
+                        },
+                } => Some(json!({ "file_data": { "file_uri": id } })),
+                _ => None,
+            })
+            .collect(),
+    }
+}
+
+impl CreateMessageParams {
+    /// Native Gemini request of an OpenAI request using grounding tools
+    ///
+    /// The OpenAI compatible endpoint does not return grounding metadata, so such requests
+    /// are sent to `generateContent` instead. Messages, function tools, sampling, thinking
+    /// and `extra_body.google` settings are carried over.
+    pub fn to_gemini(&self) -> Value {
+        let mut call_names = HashMap::new();
+        let mut contents: Vec<Value> = Vec::new();
+        for message in &self.messages {
+            let (role, parts) = match message.role {
+                OaiRole::System | OaiRole::Developer => continue,
+                OaiRole::User => (
+                    "user",
+                    message.content.iter().flat_map(content_parts).collect(),
+                ),
+                OaiRole::Assistant => {
+                    let mut parts = message
+                        .content
+                        .iter()
+                        .flat_map(content_parts)
+                        .collect::<Vec<_>>();
+                    for call in message.tool_calls.iter().flatten() {
+                        call_names.insert(call.id.as_str(), call.function.name.as_str());
+                        let args = serde_json::from_str::<Value>(&call.function.arguments)
+                            .unwrap_or_else(|_| json!({}));
+                        parts.push(json!({ "functionCall": {
+                            "name": call.function.name,
+                            "args": args,
+                        }}));
+                    }
+                    ("model", parts)
+                }
+                OaiRole::Tool => {
+                    let name = message
+                        .tool_call_id
+                        .as_deref()
+                        .and_then(|id| call_names.get(id).copied())
+                        .or(message.name.as_deref())
+                        .unwrap_or_default();
+                    let text = message.content.as_ref().map(OaiContent::text);
+                    let response = text
+                        .as_deref()
+                        .and_then(|t| serde_json::from_str::<Value>(t).ok())
+                        .filter(Value::is_object)
+                        .unwrap_or_else(|| json!({ "content": text.unwrap_or_default() }));
+                    (
+                        "user",
+                        vec![json!({ "functionResponse": {
+                            "name": name,
+                            "response": response,
+                        }})],
+                    )
+                }
+            };
+            if parts.is_empty() {
+                continue;
+            }
+            // parallel tool results and split turns go into one turn
+            match contents.last_mut() {
+                Some(last) if last["role"] == role => {
+                    if let Some(last) = last["parts"].as_array_mut() {
+                        last.extend(parts);
+                    }
+                }
+                _ => contents.push(json!({ "role": role, "parts": parts })),
+            }
+        }
+
+        let mut tools = self.grounding.to_owned();
+        let functions = self
+            .tools
+            .iter()
+            .flatten()
+            .map(|tool| {
+                let mut function = json!({ "name": tool.function.name });
+                if let Some(ref description) = tool.function.description {
+                    function["description"] = json!(description);
+                }
+                if let Some(mut parameters) = tool.function.parameters.to_owned() {
+                    json_schema::strip_for_gemini(&mut parameters);
+                    function["parameters"] = parameters;
+                }
+                function
+            })
+            .collect::<Vec<_>>();
+        if !functions.is_empty() {
+            tools.push(json!({ "functionDeclarations": functions }));
+        }
+
+        let google = self
+            .extra_body
+            .as_ref()
+            .map(|b| b["google"].to_owned())
+            .unwrap_or_default();
+        let thinking = google.get("thinking_config").cloned().or_else(|| {
+            let budget = self.thinking.as_ref().map(|t| t.budget_tokens);
+            budget
+                .or_else(|| self.reasoning_effort.clone().map(|e| e as u64))
+                .map(|budget| json!({ "thinkingBudget": budget }))
+        });
+        let (mime_type, schema) = match self.response_format {
+            Some(ResponseFormat::JsonObject) => (Some(json!("application/json")), None),
+            Some(ResponseFormat::JsonSchema { ref json_schema }) => (
+                Some(json!("application/json")),
+                json_schema.schema.to_owned(),
+            ),
+            _ => (None, None),
+        };
+        let config = [
+            ("temperature", self.temperature.map(|v| json!(v))),
+            ("topP", self.top_p.map(|v| json!(v))),
+            ("topK", self.top_k.map(|v| json!(v))),
+            (
+                "maxOutputTokens",
+                self.max_completion_tokens
+                    .or(self.max_tokens)
+                    .map(|v| json!(v)),
+            ),
+            ("stopSequences", self.stop.as_ref().map(|v| json!(v))),
+            ("candidateCount", self.n.map(|v| json!(v))),
+            ("thinkingConfig", thinking),
+            ("responseMimeType", mime_type),
+            ("responseSchema", schema),
+        ]
+        .into_iter()
+        .filter_map(|(k, v)| Some((k.to_string(), v?)))
+        .collect::<Map<_, _>>();
+
+        let mut body = json!({ "contents": contents });
+        if let Some(system) = SystemInstruction::from_oai(&self.messages) {
+            body["systemInstruction"] = json!(system);
+        }
+        if !tools.is_empty() {
+            body["tools"] = json!(tools);
+        }
+        let mode = match self.tool_choice {
+            Some(OaiToolChoice::Mode(ref mode)) => match mode.as_str() {
+                "none" => Some(json!({ "mode": "NONE" })),
+                "required" => Some(json!({ "mode": "ANY" })),
+                _ => None,
+            },
+            Some(OaiToolChoice::Function { ref function, .. }) => Some(json!({
+                "mode": "ANY",
+                "allowedFunctionNames": [function.name],
+            })),
+            None => None,
+        };
+        if let Some(mode) = mode.filter(|_| !functions.is_empty()) {
+            body["toolConfig"] = json!({ "functionCallingConfig": mode });
+        }
+        if !config.is_empty() {
+            body["generationConfig"] = Value::Object(config);
+        }
+        if let Some(safety) = google.get("safety_settings") {
+            body["safetySettings"] = safety.to_owned();
+        }
+        if let Some(cached) = google.get("cached_content") {
+            body["cachedContent"] = cached.to_owned();
+        }
+        body
+    }
+}
+
+/// Maps a Gemini finish reason to an OpenAI finish reason
+fn finish_reason(reason: &str, tool_calls: bool) -> &'static str {
+    match reason {
+        "MAX_TOKENS" => "length",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY" => {
+            "content_filter"
+        }
+        _ if tool_calls => "tool_calls",
+        _ => "stop",
+    }
+}
+
+/// Character index of a byte offset of `text`, Gemini positions segments in UTF-8 bytes
+fn char_index(text: &str, byte: u64) -> usize {
+    let byte = usize::try_from(byte).unwrap_or(usize::MAX);
+    text.char_indices().take_while(|(i, _)| *i < byte).count()
+}
+
+/// `url_citation` annotations of the grounding metadata of a candidate
+///
+/// Each source backing a segment of `text`, the candidate's answer, is positioned on that
+/// segment. Sources no segment refers to are listed without a position.
+pub fn citations(metadata: &Value, text: &str) -> Vec<Value> {
+    let chunks = metadata["groundingChunks"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let source = |index: usize| {
+        let chunk = chunks.get(index)?;
+        let source = chunk.get("web").or(chunk.get("retrievedContext"))?;
+        let url = source["uri"].as_str().filter(|u| !u.is_empty())?;
+        Some((url, source["title"].as_str().unwrap_or_default()))
+    };
+    let mut cited = vec![false; chunks.len()];
+    let mut annotations = Vec::new();
+    for support in metadata["groundingSupports"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        let segment = &support["segment"];
+        let start = char_index(text, segment["startIndex"].as_u64().unwrap_or_default());
+        let end = char_index(text, segment["endIndex"].as_u64().unwrap_or_default());
+        let indices = support["groundingChunkIndices"].as_array();
+        for index in indices.into_iter().flatten().filter_map(Value::as_u64) {
+            let index = index as usize;
+            let Some((url, title)) = source(index) else {
+                continue;
+            };
+            cited[index] = true;
+            annotations.push(url_citation(url, title, Some((start, end))));
+        }
+    }
+    for (index, _) in cited.iter().enumerate().filter(|(_, cited)| !**cited) {
+        if let Some((url, title)) = source(index) {
+            annotations.push(url_citation(url, title, None));
+        }
+    }
+    annotations
+}
+
+/// Answer text, thought summary and function calls of the parts of a candidate
+fn split_parts(candidate: &Value) -> (String, String, Vec<&Value>) {
+    let (mut text, mut reasoning, mut calls) = (String::new(), String::new(), Vec::new());
+    for part in candidate["content"]["parts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        if let Some(call) = part.get("functionCall") {
+            calls.push(call);
+        } else if let Some(t) = part["text"].as_str() {
+            if part["thought"] == true {
+                reasoning.push_str(t);
+            } else {
+                text.push_str(t);
+            }
+        }
+    }
+    (text, reasoning, calls)
+}
+
+fn tool_call(call: &Value, id: String) -> Value {
+    json!({
+        "id": call["id"].as_str().map(ToOwned::to_owned).unwrap_or(id),
+        "type": "function",
+        "function": {
+            "name": call["name"],
+            "arguments": call.get("args").unwrap_or(&json!({})).to_string(),
+        },
+    })
+}
+
+fn usage(res: &Value) -> Option<Value> {
+    let usage = res.get("usageMetadata")?;
+    let prompt = usage["promptTokenCount"].as_u64().unwrap_or_default();
+    let completion = usage["candidatesTokenCount"].as_u64().unwrap_or_default()
+        + usage["thoughtsTokenCount"].as_u64().unwrap_or_default();
+    Some(json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": completion,
+        "total_tokens": usage["totalTokenCount"].as_u64().unwrap_or(prompt + completion),
+    }))
+}
+
+/// Maps a native `generateContent` response to an OpenAI chat completion
+///
+/// Grounding sources become `url_citation` annotations of the message, the Google Search
+/// suggestions Gemini asks clients to display are kept as `search_entry_point` HTML.
+pub fn oai_response(res: &Value, model: &str) -> Value {
+    let choices = res["candidates"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, candidate)| {
+            let (text, reasoning, calls) = split_parts(candidate);
+            let metadata = &candidate["groundingMetadata"];
+            let annotations = citations(metadata, &text);
+            let mut message = json!({ "role": "assistant", "content": text });
+            if !reasoning.is_empty() {
+                message["reasoning_content"] = json!(reasoning);
+            }
+            if !calls.is_empty() {
+                let calls = calls
+                    .iter()
+                    .enumerate()
+                    .map(|(j, call)| tool_call(call, format!("call_{i}_{j}")))
+                    .collect::<Vec<_>>();
+                message["tool_calls"] = json!(calls);
+            }
+            if !annotations.is_empty() {
+                message["annotations"] = json!(annotations);
+            }
+            if let Some(entry) = metadata["searchEntryPoint"]["renderedContent"].as_str() {
+                message["search_entry_point"] = json!(entry);
+            }
+            let reason = candidate["finishReason"].as_str().unwrap_or_default();
+            json!({
+                "index": candidate["index"].as_u64().unwrap_or(i as u64),
+                "message": message,
+                "finish_reason": finish_reason(reason, !calls.is_empty()),
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut completion = json!({
+        "id": res["responseId"].as_str().unwrap_or_default(),
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": res["modelVersion"].as_str().unwrap_or(model),
+        "choices": choices,
+    });
+    if let Some(usage) = usage(res) {
+        completion["usage"] = usage;
+    }
+    completion
+}
+
+/// Maps the chunks of a native `streamGenerateContent` stream to OpenAI stream chunks
+///
+/// Gemini sends the grounding metadata of a candidate once its answer is complete, so the
+/// annotations arrive in a delta of their own, positioned on the text streamed before.
+#[derive(Default)]
+pub struct OaiStreamMapper {
+    /// Answer text streamed so far, by candidate
+    text: HashMap<u64, String>,
+    /// Function calls streamed so far, by candidate
+    calls: HashMap<u64, usize>,
+}
+
+impl OaiStreamMapper {
+    /// OpenAI chunk of a Gemini chunk, `None` if it carries nothing to forward
+    pub fn map(&mut self, chunk: &Value) -> Option<Value> {
+        let mut choices = Vec::new();
+        for (i, candidate) in chunk["candidates"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            let index = candidate["index"].as_u64().unwrap_or(i as u64);
+            let (text, reasoning, calls) = split_parts(candidate);
+            let streamed = self.text.entry(index).or_default();
+            streamed.push_str(&text);
+            let mut delta = Map::new();
+            if !text.is_empty() {
+                delta.insert("content".into(), json!(text));
+            }
+            if !reasoning.is_empty() {
+                delta.insert("reasoning_content".into(), json!(reasoning));
+            }
+            let metadata = &candidate["groundingMetadata"];
+            let annotations = citations(metadata, streamed);
+            if !annotations.is_empty() {
+                delta.insert("annotations".into(), json!(annotations));
+            }
+            if let Some(entry) = metadata["searchEntryPoint"]["renderedContent"].as_str() {
+                delta.insert("search_entry_point".into(), json!(entry));
+            }
+            let count = self.calls.entry(index).or_default();
+            if !calls.is_empty() {
+                let calls = calls
+                    .iter()
+                    .map(|call| {
+                        let mut call = tool_call(call, format!("call_{index}_{count}"));
+                        call["index"] = json!(*count);
+                        *count += 1;
+                        call
+                    })
+                    .collect::<Vec<_>>();
+                delta.insert("tool_calls".into(), json!(calls));
+            }
+            let reason = candidate["finishReason"]
+                .as_str()
+                .map(|r| finish_reason(r, *count > 0));
+            if delta.is_empty() && reason.is_none() {
+                continue;
+            }
+            choices.push(json!({
+                "index": index,
+                "delta": delta,
+                "finish_reason": reason,
+            }));
+        }
+        if choices.is_empty() {
+            return None;
+        }
+        let mut data = json!({ "object": "chat.completion.chunk", "choices": choices });
+        if let Some(usage) = usage(chunk) {
+            data["usage"] = usage;
+        }
+        Some(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grounding_metadata_becomes_annotations() {
+        let mut body = json!({
+            "model": "gemini-2.5-flash",
+            "messages": [{ "role": "user", "content": "news?" }],
+            "tools": [{ "type": "google_search" }],
+        });
+        assert_eq!(
+            take_grounding(&mut body),
+            vec![json!({ "google_search": {} })]
+        );
+        assert!(body.get("tools").is_none());
+
+        let res = json!({
+            "candidates": [{
+                "content": { "role": "model", "parts": [{ "text": "Café opens. It rains." }] },
+                "finishReason": "STOP",
+                "groundingMetadata": {
+                    "groundingChunks": [
+                        { "web": { "uri": "https://a.example", "title": "a.example" } },
+                        { "web": { "uri": "https://b.example", "title": "b.example" } }
+                    ],
+                    "groundingSupports": [{
+                        "segment": { "startIndex": 13, "endIndex": 22, "text": "It rains." },
+                        "groundingChunkIndices": [0]
+                    }],
+                    "searchEntryPoint": { "renderedContent": "<div></div>" }
+                }
+            }],
+            "modelVersion": "gemini-2.5-flash"
+        });
+        let completion = oai_response(&res, "gemini-2.5-flash");
+        let message = &completion["choices"][0]["message"];
+        let annotations = message["annotations"].as_array().unwrap();
+        assert_eq!(annotations.len(), 2);
+        // "é" is two bytes, the segment starts at character 12
+        assert_eq!(annotations[0]["url_citation"]["start_index"], 12);
+        assert_eq!(annotations[0]["url_citation"]["end_index"], 21);
+        assert_eq!(annotations[1]["url_citation"]["url"], "https://b.example");
+        assert_eq!(message["search_entry_point"], "<div></div>");
+        assert_eq!(completion["choices"][0]["finish_reason"], "stop");
+    }
+}
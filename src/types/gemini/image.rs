@@ -0,0 +1,222 @@
+//! Mapping between the OpenAI images API and Gemini image generation
+//!
+//! `imagen-*` models are served by the `:predict` method, every other model is
+//! asked for image output through `:generateContent`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+/// Model used when the client does not specify one
+pub const DEFAULT_IMAGE_MODEL: &str = "imagen-4.0-generate-001";
+
+/// Aspect ratios accepted by Imagen
+const ASPECT_RATIOS: [(&str, f64); 5] = [
+    ("1:1", 1.0),
+    ("3:4", 0.75),
+    ("4:3", 4.0 / 3.0),
+    ("9:16", 9.0 / 16.0),
+    ("16:9", 16.0 / 9.0),
+];
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageResponseFormat {
+    /// Returned as a `data:` URL, Gemini does not host generated images
+    #[default]
+    Url,
+    B64Json,
+}
+
+/// Body of `POST /v1/images/generations`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageGenerationRequest {
+    pub prompt: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub n: Option<u32>,
+    /// `WIDTHxHEIGHT`, mapped to the closest supported aspect ratio
+    #[serde(default)]
+    pub size: Option<String>,
+    #[serde(default)]
+    pub response_format: Option<ImageResponseFormat>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub b64_json: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revised_prompt: Option<String>,
+}
+
+/// OpenAI images API response
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageGenerationResponse {
+    pub created: i64,
+    pub data: Vec<ImageData>,
+}
+
+impl ImageGenerationRequest {
+    pub fn model(&self) -> &str {
+        self.model.as_deref().unwrap_or(DEFAULT_IMAGE_MODEL)
+    }
+
+    /// Whether the model is served by the Imagen `:predict` method
+    pub fn is_imagen(&self) -> bool {
+        self.model().starts_with("imagen")
+    }
+
+    /// Gemini method to call for the selected model
+    pub fn method(&self) -> &'static str {
+        if self.is_imagen() {
+            "predict"
+        } else {
+            "generateContent"
+        }
+    }
+
+    fn aspect_ratio(&self) -> Option<&'static str> {
+        let (w, h) = self.size.as_deref()?.split_once('x')?;
+        let (w, h) = (w.trim().parse::<f64>().ok()?, h.trim().parse::<f64>().ok()?);
+        if w <= 0.0 || h <= 0.0 {
+            return None;
+        }
+        let ratio = w / h;
+        ASPECT_RATIOS
+            .iter()
+            .min_by(|a, b| (a.1 - ratio).abs().total_cmp(&(b.1 - ratio).abs()))
+            .map(|(r, _)| *r)
+    }
+
+    /// Builds the Gemini request body for the selected model
+    pub fn to_gemini(&self) -> Value {
+        let n = self.n.unwrap_or(1).max(1);
+        if self.is_imagen() {
+            let mut parameters = json!({ "sampleCount": n });
+            if let Some(ratio) = self.aspect_ratio() {
+                parameters["aspectRatio"] = json!(ratio);
+            }
+            return json!({
+                "instances": [{ "prompt": self.prompt }],
+                "parameters": parameters,
+            });
+        }
+        let mut generation_config = json!({
+            "responseModalities": ["TEXT", "IMAGE"],
+            "candidateCount": n,
+        });
+        if let Some(ratio) = self.aspect_ratio() {
+            generation_config["imageConfig"] = json!({ "aspectRatio": ratio });
+        }
+        json!({
+            "contents": [{ "role": "user", "parts": [{ "text": self.prompt }] }],
+            "generationConfig": generation_config,
+        })
+    }
+
+    /// Converts a Gemini response into the OpenAI images format
+    pub fn convert_response(&self, res: &Value) -> ImageGenerationResponse {
+        let images = if self.is_imagen() {
+            res["predictions"]
+                .as_array()
+                .map(|p| {
+                    p.iter()
+                        .filter_map(|p| {
+                            let data = p["bytesBase64Encoded"].as_str()?;
+                            let mime = p["mimeType"].as_str().unwrap_or("image/png");
+                            Some((mime.to_string(), data.to_string()))
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        } else {
+            res["candidates"]
+                .as_array()
+                .map(|c| {
+                    c.iter()
+                        .filter_map(|c| c["content"]["parts"].as_array())
+                        .flatten()
+                        .filter_map(|p| {
+                            let inline = p.get("inlineData").or_else(|| p.get("inline_data"))?;
+                            let data = inline["data"].as_str()?;
+                            let mime = inline["mimeType"]
+                                .as_str()
+                                .or_else(|| inline["mime_type"].as_str())
+                                .unwrap_or("image/png");
+                            Some((mime.to_string(), data.to_string()))
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+        let format = self.response_format.unwrap_or_default();
+        let data = images
+            .into_iter()
+            .map(|(mime, data)| match format {
+                ImageResponseFormat::B64Json => ImageData {
+                    b64_json: Some(data),
+                    url: None,
+                    revised_prompt: None,
+                },
+                ImageResponseFormat::Url => ImageData {
+                    b64_json: None,
+                    url: Some(format!("data:{mime};base64,{data}")),
+                    revised_prompt: None,
+                },
+            })
+            .collect();
+        ImageGenerationResponse {
+            created: chrono::Utc::now().timestamp(),
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(model: &str, size: Option<&str>) -> ImageGenerationRequest {
+        ImageGenerationRequest {
+            prompt: "a cat".to_string(),
+            model: Some(model.to_string()),
+            n: Some(2),
+            size: size.map(|s| s.to_string()),
+            response_format: Some(ImageResponseFormat::B64Json),
+        }
+    }
+
+    #[test]
+    fn imagen_request_and_response() {
+        let req = request("imagen-4.0-generate-001", Some("1792x1024"));
+        assert_eq!(req.method(), "predict");
+        let body = req.to_gemini();
+        assert_eq!(body["parameters"]["sampleCount"], 2);
+        assert_eq!(body["parameters"]["aspectRatio"], "16:9");
+        let res = req.convert_response(&json!({
+            "predictions": [{ "bytesBase64Encoded": "AAAA", "mimeType": "image/png" }]
+        }));
+        assert_eq!(res.data.len(), 1);
+        assert_eq!(res.data[0].b64_json.as_deref(), Some("AAAA"));
+    }
+
+    #[test]
+    fn generate_content_response_as_url() {
+        let mut req = request("gemini-2.5-flash-image", None);
+        req.response_format = None;
+        assert_eq!(req.method(), "generateContent");
+        let res = req.convert_response(&json!({
+            "candidates": [{ "content": { "parts": [
+                { "text": "here you go" },
+                { "inlineData": { "mimeType": "image/jpeg", "data": "BBBB" } }
+            ] } }]
+        }));
+        assert_eq!(
+            res.data[0].url.as_deref(),
+            Some("data:image/jpeg;base64,BBBB")
+        );
+    }
+}
@@ -1,2 +1,6 @@
+pub mod grounding;
+pub mod image;
 pub mod request;
 pub mod response;
+pub mod system;
+pub mod tokens;
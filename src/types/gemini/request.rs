@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use serde_json::{Value, json};
+use serde_json::Value;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Hash, Default)]
 #[allow(non_camel_case_types)]
@@ -7,6 +7,8 @@ pub enum Role {
     #[default]
     user,
     model,
+    /// Not accepted by Gemini, moved to `systemInstruction` before sending
+    system,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Hash)]
@@ -49,11 +51,12 @@ pub struct FunctionResponse {
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Hash)]
-#[allow(non_snake_case)]
+#[serde(rename_all = "camelCase")]
 pub struct FileData {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    mimeType: Option<String>,
-    fileUrl: String,
+    #[serde(skip_serializing_if = "Option::is_none", alias = "mime_type")]
+    mime_type: Option<String>,
+    #[serde(alias = "file_uri")]
+    file_uri: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Hash)]
@@ -88,6 +91,7 @@ pub enum Part {
     code_execution_result(CodeExecuteResult),
     functionCall(FunctionCall),
     functionResponse(FunctionResponse),
+    #[serde(alias = "file_data")]
     fileData(FileData),
     #[serde(untagged)]
     Text {
@@ -100,13 +104,13 @@ pub enum Part {
 #[derive(Serialize, Deserialize, Debug, Clone, Hash)]
 pub struct Chat {
     #[serde(default)]
-    role: Role,
-    parts: Vec<Part>,
+    pub role: Role,
+    pub parts: Vec<Part>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Hash)]
 pub struct SystemInstruction {
-    parts: Vec<Part>,
+    pub parts: Vec<Part>,
 }
 impl SystemInstruction {
     pub fn from_string(prompt: impl Into<String>) -> Self {
@@ -133,25 +137,11 @@ pub struct GeminiRequestBody {
     pub safety_settings: Option<Value>,
 }
 
-impl GeminiRequestBody {
-    pub fn safety_off(&mut self) {
-        self.safety_settings = Some(json!([
-          { "category": "HARM_CATEGORY_HARASSMENT", "threshold": "OFF" },
-          { "category": "HARM_CATEGORY_HATE_SPEECH", "threshold": "OFF" },
-          { "category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": "OFF" },
-          { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "OFF" },
-          {
-            "category": "HARM_CATEGORY_CIVIC_INTEGRITY",
-            "threshold": "OFF"
-          }
-        ]));
-    }
-}
-
 #[derive(Serialize, Deserialize, Debug, Clone, Hash)]
 #[allow(non_camel_case_types)]
 pub enum Tool {
-    /// Generally it can be `Tool::google_search(json!({}))`
+    /// Generally it can be `Tool::google_search(json!({}))`, grounds answers on Google Search
+    #[serde(alias = "googleSearch")]
     google_search(Value),
     /// It is of form `Tool::function_calling(`[functionDeclaration](https://ai.google.dev/gemini-api/docs/function-calling?example=meeting)`)`
     functionDeclarations(Vec<Value>),
@@ -0,0 +1,125 @@
+use super::request::{Chat, GeminiRequestBody, Part, Role, SystemInstruction};
+use crate::types::oai::{CreateMessageParams, OaiContent, OaiMessage, OaiRole};
+
+impl SystemInstruction {
+    /// Joins the text parts of the instruction
+    pub fn text(&self) -> String {
+        self.parts
+            .iter()
+            .filter_map(|p| match p {
+                Part::Text { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Collects the `system` and `developer` messages of an OpenAI request, in order
+    ///
+    /// # Returns
+    /// * `Option<Self>` - The instruction, `None` without any system text
+    pub fn from_oai(messages: &[OaiMessage]) -> Option<Self> {
+        let parts = messages
+            .iter()
+            .filter(|m| matches!(m.role, OaiRole::System | OaiRole::Developer))
+            .filter_map(|m| m.content.as_ref().map(OaiContent::text))
+            .filter(|t| !t.trim().is_empty())
+            .map(|text| Part::Text {
+                text,
+                thought: None,
+            })
+            .collect::<Vec<_>>();
+        (!parts.is_empty()).then_some(Self { parts })
+    }
+
+    /// The instruction as a single OpenAI `system` message
+    pub fn to_oai(&self) -> OaiMessage {
+        OaiMessage {
+            role: OaiRole::System,
+            content: Some(OaiContent::Text(self.text())),
+            ..Default::default()
+        }
+    }
+}
+
+impl GeminiRequestBody {
+    /// Moves `system` turns of `contents` into `systemInstruction`
+    ///
+    /// Gemini only accepts `user` and `model` turns, the moved text is appended to an
+    /// existing instruction.
+    pub fn hoist_system(&mut self) {
+        let (system, contents): (Vec<Chat>, Vec<Chat>) = std::mem::take(&mut self.contents)
+            .into_iter()
+            .partition(|c| matches!(c.role, Role::system));
+        self.contents = contents;
+        let parts = system
+            .into_iter()
+            .flat_map(|c| c.parts)
+            .filter(|p| matches!(p, Part::Text { .. }));
+        match self.system_instruction.as_mut() {
+            Some(instruction) => instruction.parts.extend(parts),
+            None => {
+                let parts = parts.collect::<Vec<_>>();
+                if !parts.is_empty() {
+                    self.system_instruction = Some(SystemInstruction { parts });
+                }
+            }
+        }
+    }
+}
+
+impl CreateMessageParams {
+    /// Merges all `system` and `developer` messages into one leading `system` message
+    ///
+    /// The Vertex OpenAI endpoint maps that message to `systemInstruction`, while later or
+    /// `developer` messages are rejected.
+    pub fn merge_system(&mut self) {
+        let system = SystemInstruction::from_oai(&self.messages);
+        self.messages
+            .retain(|m| !matches!(m.role, OaiRole::System | OaiRole::Developer));
+        if let Some(system) = system {
+            self.messages.insert(0, system.to_oai());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn system_prompts_map_both_ways() {
+        let mut params: CreateMessageParams = serde_json::from_value(json!({
+            "model": "gemini-2.5-pro",
+            "messages": [
+                { "role": "system", "content": "be brief" },
+                { "role": "user", "content": "hi" },
+                { "role": "developer", "content": [{ "type": "text", "text": "no emoji" }] }
+            ]
+        }))
+        .unwrap();
+        params.merge_system();
+        assert_eq!(params.messages.len(), 2);
+        assert_eq!(params.messages[0].role, OaiRole::System);
+        assert_eq!(
+            params.messages[0].content,
+            Some(OaiContent::Text("be brief\nno emoji".into()))
+        );
+
+        let mut body: GeminiRequestBody = serde_json::from_value(json!({
+            "systemInstruction": { "parts": [{ "text": "be brief" }] },
+            "contents": [
+                { "role": "system", "parts": [{ "text": "no emoji" }] },
+                { "role": "user", "parts": [{ "text": "hi" }] }
+            ]
+        }))
+        .unwrap();
+        body.hoist_system();
+        assert_eq!(body.contents.len(), 1);
+        let instruction = body.system_instruction.unwrap();
+        assert_eq!(instruction.text(), "be brief\nno emoji");
+        assert_eq!(instruction.to_oai().role, OaiRole::System);
+    }
+}
@@ -1,9 +1,11 @@
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
-use tiktoken_rs::o200k_base;
 
 use super::claude::{CreateMessageParams as ClaudeCreateMessageParams, *};
-use crate::types::claude::Message;
+use crate::{
+    config::GeminiSafetyConfig,
+    utils::{json_schema, tokenizer::Tokenizer},
+};
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 #[serde(rename_all = "snake_case")]
@@ -14,23 +16,318 @@ pub enum Effort {
     High = 256 * 8 * 8,
 }
 
+/// Role of an OpenAI chat message
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OaiRole {
+    System,
+    Developer,
+    #[default]
+    User,
+    Assistant,
+    Tool,
+}
+
+/// Content of an OpenAI chat message, either plain text or content parts
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum OaiContent {
+    Text(String),
+    Parts(Vec<ContentBlock>),
+}
+
+impl OaiContent {
+    /// Joins all text parts of the content
+    pub fn text(&self) -> String {
+        match self {
+            OaiContent::Text(text) => text.to_owned(),
+            OaiContent::Parts(parts) => parts
+                .iter()
+                .filter_map(|p| match p {
+                    ContentBlock::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+impl From<OaiContent> for MessageContent {
+    fn from(content: OaiContent) -> Self {
+        match content {
+            OaiContent::Text(content) => MessageContent::Text { content },
+            OaiContent::Parts(content) => MessageContent::Blocks { content },
+        }
+    }
+}
+
+/// Message in an OpenAI chat completion request
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct OaiMessage {
+    pub role: OaiRole,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<OaiContent>,
+    /// Function calls made by the assistant
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// Call answered by a `tool` message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Function call made by the assistant
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_type")]
+    pub type_: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FunctionCall {
+    pub name: String,
+    /// JSON encoded arguments
+    #[serde(default)]
+    pub arguments: String,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+/// Tool definition in OpenAI format
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OaiTool {
+    #[serde(rename = "type", default = "function_type")]
+    pub type_: String,
+    pub function: FunctionDefinition,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// JSON schema of the arguments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+impl From<OaiTool> for Tool {
+    fn from(tool: OaiTool) -> Self {
+        Self {
+            name: tool.function.name,
+            description: tool.function.description,
+            input_schema: tool
+                .function
+                .parameters
+                .unwrap_or_else(|| json!({ "type": "object", "properties": {} })),
+        }
+    }
+}
+
+/// Tool choice in OpenAI format, `auto`, `none`, `required` or a specific function
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum OaiToolChoice {
+    Mode(String),
+    Function {
+        #[serde(rename = "type")]
+        type_: String,
+        function: FunctionName,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FunctionName {
+    pub name: String,
+}
+
+impl From<OaiToolChoice> for ToolChoice {
+    fn from(choice: OaiToolChoice) -> Self {
+        match choice {
+            OaiToolChoice::Mode(mode) => match mode.as_str() {
+                "required" => ToolChoice::Any,
+                "none" => ToolChoice::None,
+                _ => ToolChoice::Auto,
+            },
+            OaiToolChoice::Function { function, .. } => ToolChoice::Tool {
+                name: function.name,
+            },
+        }
+    }
+}
+
+/// Tool Claude is forced to call when the client asks for structured output
+///
+/// The name is reserved, requests bringing a tool of that name are rejected.
+pub const STRUCTURED_OUTPUT_TOOL: &str = "clewdr_json_response";
+
+/// OpenAI `response_format`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    JsonObject,
+    JsonSchema { json_schema: JsonSchemaFormat },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JsonSchemaFormat {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+impl ResponseFormat {
+    /// Schema the response has to match, `None` for plain text
+    pub fn schema(&self) -> Option<Value> {
+        match self {
+            ResponseFormat::Text => None,
+            ResponseFormat::JsonObject => Some(json!({ "type": "object" })),
+            ResponseFormat::JsonSchema { json_schema } => Some(
+                json_schema
+                    .schema
+                    .to_owned()
+                    .unwrap_or_else(|| json!({ "type": "object" })),
+            ),
+        }
+    }
+
+    /// Claude tool that carries the structured response
+    fn tool(&self) -> Option<Tool> {
+        let description = match self {
+            ResponseFormat::JsonSchema { json_schema } => json_schema.description.to_owned(),
+            _ => None,
+        };
+        Some(Tool {
+            name: STRUCTURED_OUTPUT_TOOL.to_string(),
+            description: Some(
+                description.unwrap_or_else(|| "Respond to the user with this JSON".to_string()),
+            ),
+            input_schema: self.schema()?,
+        })
+    }
+}
+
+/// Builds an OpenAI `url_citation` annotation, positioned in the content when the range is known
+pub fn url_citation(url: &str, title: &str, range: Option<(usize, usize)>) -> Value {
+    let mut citation = json!({ "url": url, "title": title });
+    if let Some((start, end)) = range {
+        citation["start_index"] = json!(start);
+        citation["end_index"] = json!(end);
+    }
+    json!({ "type": "url_citation", "url_citation": citation })
+}
+
+/// Converts OpenAI messages into a Claude system prompt and message list
+///
+/// Assistant `tool_calls` become `tool_use` blocks and `tool` messages become
+/// `tool_result` blocks, consecutive results are merged into one user turn.
+fn convert_messages(msgs: Vec<OaiMessage>) -> (Vec<ContentBlock>, Vec<Message>) {
+    let mut systems = vec![];
+    let mut messages: Vec<Message> = vec![];
+    for m in msgs {
+        match m.role {
+            OaiRole::System | OaiRole::Developer => {
+                let Some(content) = m.content else {
+                    continue;
+                };
+                match content {
+                    OaiContent::Text(text) => systems.push(ContentBlock::Text { text }),
+                    OaiContent::Parts(parts) => systems.extend(
+                        parts
+                            .into_iter()
+                            .filter(|b| matches!(b, ContentBlock::Text { .. })),
+                    ),
+                }
+            }
+            OaiRole::User => {
+                let content = m.content.unwrap_or(OaiContent::Text(String::new()));
+                messages.push(Message {
+                    role: Role::User,
+                    content: content.into(),
+                });
+            }
+            OaiRole::Assistant => {
+                let calls = m.tool_calls.unwrap_or_default();
+                if calls.is_empty() {
+                    let content = m.content.unwrap_or(OaiContent::Text(String::new()));
+                    messages.push(Message {
+                        role: Role::Assistant,
+                        content: content.into(),
+                    });
+                    continue;
+                }
+                let mut blocks = match m.content {
+                    Some(OaiContent::Text(text)) if !text.trim().is_empty() => {
+                        vec![ContentBlock::Text { text }]
+                    }
+                    Some(OaiContent::Parts(parts)) => parts,
+                    _ => vec![],
+                };
+                blocks.extend(calls.into_iter().map(|c| ContentBlock::ToolUse {
+                    id: c.id,
+                    name: c.function.name,
+                    input:
+                        serde_json::from_str(&c.function.arguments).unwrap_or_else(|_| json!({})),
+                }));
+                messages.push(Message::new_blocks(Role::Assistant, blocks));
+            }
+            OaiRole::Tool => {
+                let result = ContentBlock::ToolResult {
+                    tool_use_id: m.tool_call_id.unwrap_or_default(),
+                    content: json!(m.content.map(|c| c.text()).unwrap_or_default()),
+                };
+                if let Some(Message {
+                    role: Role::User,
+                    content: MessageContent::Blocks { content },
+                }) = messages.last_mut()
+                    && content
+                        .iter()
+                        .all(|b| matches!(b, ContentBlock::ToolResult { .. }))
+                {
+                    content.push(result);
+                } else {
+                    messages.push(Message::new_blocks(Role::User, vec![result]));
+                }
+            }
+        }
+    }
+    (systems, messages)
+}
+
 impl From<CreateMessageParams> for ClaudeCreateMessageParams {
     fn from(params: CreateMessageParams) -> Self {
-        let (systems, messages): (Vec<Message>, Vec<Message>) = params
-            .messages
-            .into_iter()
-            .partition(|m| m.role == Role::System);
-        let systems = systems
-            .into_iter()
-            .map(|m| m.content)
-            .flat_map(|c| match c {
-                MessageContent::Text { content } => vec![ContentBlock::Text { text: content }],
-                MessageContent::Blocks { content } => content,
-            })
-            .filter(|b| matches!(b, ContentBlock::Text { .. }))
-            .map(|b| json!(b))
-            .collect::<Vec<_>>();
+        let (systems, messages) = convert_messages(params.messages);
+        let systems = systems.into_iter().map(|b| json!(b)).collect::<Vec<_>>();
         let system = (!systems.is_empty()).then(|| json!(systems));
+        let mut tools = params
+            .tools
+            .map(|tools| tools.into_iter().map(Tool::from).collect::<Vec<_>>());
+        let mut tool_choice = params.tool_choice.map(ToolChoice::from);
+        let mut thinking = params
+            .thinking
+            .or_else(|| params.reasoning_effort.map(|e| Thinking::new(e as u64)));
+        // Structured output is emulated by forcing a call to a tool with the requested schema,
+        // forced tool use cannot be combined with extended thinking
+        if let Some(tool) = params.response_format.as_ref().and_then(|f| f.tool()) {
+            tools.get_or_insert_default().push(tool);
+            tool_choice = Some(ToolChoice::Tool {
+                name: STRUCTURED_OUTPUT_TOOL.to_string(),
+            });
+            thinking = None;
+        }
         Self {
             max_tokens: (params.max_tokens.or(params.max_completion_tokens))
                 .unwrap_or_else(default_max_tokens),
@@ -38,15 +335,13 @@ impl From<CreateMessageParams> for ClaudeCreateMessageParams {
             messages,
             model: params.model,
             stop_sequences: params.stop,
-            thinking: params
-                .thinking
-                .or_else(|| params.reasoning_effort.map(|e| Thinking::new(e as u64))),
+            thinking,
             temperature: params.temperature,
             stream: params.stream,
             top_k: params.top_k,
             top_p: params.top_p,
-            tools: params.tools,
-            tool_choice: params.tool_choice,
+            tools,
+            tool_choice,
             metadata: params.metadata,
             n: params.n,
         }
@@ -61,7 +356,7 @@ pub struct CreateMessageParams {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<u32>,
     /// Input messages for the conversation
-    pub messages: Vec<Message>,
+    pub messages: Vec<OaiMessage>,
     /// Model to use
     pub model: String,
     /// Reasoning effort for response generation
@@ -93,10 +388,13 @@ pub struct CreateMessageParams {
     pub logit_bias: Option<Value>,
     /// Tools that the model may use
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tools: Option<Vec<Tool>>,
+    pub tools: Option<Vec<OaiTool>>,
     /// How the model should use tools
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tool_choice: Option<ToolChoice>,
+    pub tool_choice: Option<OaiToolChoice>,
+    /// Structured output format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
     /// Request metadata
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<Metadata>,
@@ -106,48 +404,98 @@ pub struct CreateMessageParams {
     /// Number of completions to generate
     #[serde(skip_serializing_if = "Option::is_none")]
     pub n: Option<u32>,
+    /// Gemini grounding tools, taken out of `tools` before parsing
+    #[serde(skip)]
+    pub grounding: Vec<Value>,
 }
 
 impl CreateMessageParams {
+    /// Estimated prompt tokens, with the tokenizer of the requested model
     pub fn count_tokens(&self) -> u32 {
-        let bpe = o200k_base().expect("Failed to get encoding");
         let messages = self
             .messages
             .iter()
-            .map(|msg| match msg.content {
-                MessageContent::Text { ref content } => content.to_string(),
-                MessageContent::Blocks { ref content } => content
-                    .iter()
-                    .map(|block| match block {
-                        ContentBlock::Text { text } => text,
-                        _ => "",
-                    })
-                    .collect::<String>(),
-            })
+            .map(|msg| msg.content.as_ref().map(|c| c.text()).unwrap_or_default())
             .collect::<Vec<_>>()
             .join("\n");
-        bpe.encode_with_special_tokens(&messages).len() as u32
+        Tokenizer::for_model(&self.model).count(&messages)
     }
 
     fn optimize_for_gemini(&mut self) {
-        let mut extra_body = json!({});
-        extra_body["google"]["safety_settings"] = json!([
-          { "category": "HARM_CATEGORY_HARASSMENT", "threshold": "OFF" },
-          { "category": "HARM_CATEGORY_HATE_SPEECH", "threshold": "OFF" },
-          { "category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": "OFF" },
-          { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "OFF" },
-          {
-            "category": "HARM_CATEGORY_CIVIC_INTEGRITY",
-            "threshold": "OFF"
-          }
-        ]);
-        self.extra_body = Some(extra_body);
         self.frequency_penalty = None;
     }
 
+    /// Applies the Gemini safety policy to `extra_body.google.safety_settings`
+    ///
+    /// Other fields of the client's `extra_body` are kept.
+    pub fn apply_safety_settings(&mut self, config: &GeminiSafetyConfig, header: Option<&str>) {
+        let extra_body = self.extra_body.get_or_insert_with(|| json!({}));
+        if !extra_body.is_object() {
+            *extra_body = json!({});
+        }
+        if !extra_body["google"].is_object() {
+            extra_body["google"] = json!({});
+        }
+        let google = &mut extra_body["google"];
+        let mut settings = google.get("safety_settings").cloned();
+        config.apply(&mut settings, header);
+        if let Some(settings) = settings {
+            google["safety_settings"] = settings;
+        }
+    }
+
+    /// Makes the requested JSON schema acceptable to Gemini's `responseSchema`
+    pub fn preprocess_response_format(&mut self) {
+        if let Some(ResponseFormat::JsonSchema { json_schema }) = self.response_format.as_mut()
+            && let Some(schema) = json_schema.schema.as_mut()
+        {
+            json_schema::strip_for_gemini(schema);
+        }
+    }
+
     pub fn preprocess_vertex(&mut self) {
         self.optimize_for_gemini();
+        self.merge_system();
         self.model = self.model.trim_start_matches("google/").to_string();
         self.model = format!("google/{}", self.model);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tool_calls_map_to_tool_blocks() {
+        let msgs: Vec<OaiMessage> = serde_json::from_value(json!([
+            { "role": "system", "content": "be brief" },
+            { "role": "user", "content": "weather in Paris and Rome?" },
+            { "role": "assistant", "content": null, "tool_calls": [
+                { "id": "a", "type": "function", "function": { "name": "weather", "arguments": "{\"city\":\"Paris\"}" } },
+                { "id": "b", "type": "function", "function": { "name": "weather", "arguments": "{\"city\":\"Rome\"}" } }
+            ] },
+            { "role": "tool", "tool_call_id": "a", "content": "sunny" },
+            { "role": "tool", "tool_call_id": "b", "content": "rain" }
+        ]))
+        .unwrap();
+        let (systems, messages) = convert_messages(msgs);
+        assert_eq!(systems, vec![ContentBlock::text("be brief")]);
+        assert_eq!(messages.len(), 3);
+        let MessageContent::Blocks { content } = &messages[1].content else {
+            panic!("assistant turn should be blocks");
+        };
+        assert_eq!(
+            content[0],
+            ContentBlock::ToolUse {
+                id: "a".to_string(),
+                name: "weather".to_string(),
+                input: json!({ "city": "Paris" }),
+            }
+        );
+        let MessageContent::Blocks { content } = &messages[2].content else {
+            panic!("tool results should be blocks");
+        };
+        assert_eq!(messages[2].role, Role::User);
+        assert_eq!(content.len(), 2);
+    }
+}
@@ -0,0 +1,103 @@
+use std::{future::Future, time::Duration};
+
+use async_stream::stream;
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::config::CLEWDR_CONFIG;
+
+/// Relays an upstream response stream to the client through a bounded buffer
+///
+/// A task reads the upstream into a buffer of `stream_buffer` items, so the upstream is only read
+/// as fast as the client drains the buffer. When the buffer stays full for `stream_max_lag`
+/// seconds, or the client goes away, the upstream stream is dropped, closing its connection,
+/// and `on_abort` runs to give back what the request holds.
+pub fn relay<S>(
+    upstream: S,
+    on_abort: impl Future<Output = ()> + Send + 'static,
+) -> impl Stream<Item = S::Item> + Send + 'static
+where
+    S: Stream + Send + 'static,
+    S::Item: Send + 'static,
+{
+    let config = CLEWDR_CONFIG.load();
+    let lag = match config.stream_max_lag {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+    relay_with(upstream, config.stream_buffer, lag, on_abort)
+}
+
+fn relay_with<S>(
+    upstream: S,
+    buffer: usize,
+    lag: Option<Duration>,
+    on_abort: impl Future<Output = ()> + Send + 'static,
+) -> impl Stream<Item = S::Item> + Send + 'static
+where
+    S: Stream + Send + 'static,
+    S::Item: Send + 'static,
+{
+    let (tx, mut rx) = mpsc::channel(buffer.max(1));
+    tokio::spawn(async move {
+        let mut upstream = Box::pin(upstream);
+        loop {
+            let permit = match lag {
+                Some(lag) => match tokio::time::timeout(lag, tx.reserve()).await {
+                    Ok(permit) => permit.ok(),
+                    Err(_) => {
+                        warn!(
+                            "Client read nothing for {}s, aborting upstream stream",
+                            lag.as_secs()
+                        );
+                        None
+                    }
+                },
+                None => tx.reserve().await.ok(),
+            };
+            let Some(permit) = permit else {
+                break;
+            };
+            let next = tokio::select! {
+                next = upstream.next() => next,
+                // the client went away while the upstream was quiet
+                _ = tx.closed() => break,
+            };
+            match next {
+                Some(item) => permit.send(item),
+                None => return,
+            }
+        }
+        drop(upstream);
+        on_abort.await;
+    });
+    stream! {
+        while let Some(item) = rx.recv().await {
+            yield item;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn aborts_upstream_of_stalled_client() {
+        let (aborted_tx, aborted_rx) = tokio::sync::oneshot::channel();
+        let upstream = futures::stream::iter(0..);
+        let relayed = relay_with(upstream, 2, Some(Duration::from_millis(20)), async {
+            let _ = aborted_tx.send(());
+        });
+        futures::pin_mut!(relayed);
+        assert_eq!(relayed.next().await, Some(0));
+        // the client stops reading, the buffer fills up and the upstream is dropped
+        tokio::time::timeout(Duration::from_secs(1), aborted_rx)
+            .await
+            .expect("upstream was not aborted")
+            .unwrap();
+        let rest = relayed.collect::<Vec<_>>().await;
+        assert!(rest.len() <= 2);
+    }
+}
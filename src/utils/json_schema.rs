@@ -0,0 +1,156 @@
+//! Lightweight JSON schema checks for structured output
+//!
+//! Only the keywords models are usually asked to honour are checked:
+//! `type`, `enum`, `const`, `required`, `properties`, `additionalProperties` and `items`.
+
+use serde_json::{Value, json};
+
+/// Keywords Gemini's `responseSchema` rejects
+const GEMINI_UNSUPPORTED: [&str; 4] = ["$schema", "$id", "additionalProperties", "examples"];
+
+/// Parses JSON from model output, tolerating surrounding whitespace and Markdown code fences
+pub fn extract_json(text: &str) -> Option<Value> {
+    let text = text.trim();
+    let text = text
+        .strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|t| t.trim_end().strip_suffix("```"))
+        .unwrap_or(text);
+    serde_json::from_str(text.trim()).ok()
+}
+
+fn type_matches(value: &Value, ty: &str) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// Validates `value` against `schema`
+///
+/// # Returns
+/// * `Err(String)` - Description of the first violation, with its JSON path
+pub fn validate(value: &Value, schema: &Value) -> Result<(), String> {
+    validate_at(value, schema, "$")
+}
+
+fn validate_at(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    let types = match &schema["type"] {
+        Value::String(t) => vec![t.as_str()],
+        Value::Array(t) => t.iter().filter_map(|t| t.as_str()).collect(),
+        _ => vec![],
+    };
+    if !types.is_empty() && !types.iter().any(|t| type_matches(value, t)) {
+        return Err(format!("{path} should be of type {}", types.join(" or ")));
+    }
+    if let Some(options) = schema["enum"].as_array()
+        && !options.contains(value)
+    {
+        return Err(format!("{path} should be one of {}", json!(options)));
+    }
+    if let Some(expected) = schema.get("const")
+        && expected != value
+    {
+        return Err(format!("{path} should be {expected}"));
+    }
+    if let Some(object) = value.as_object() {
+        for key in schema["required"].as_array().into_iter().flatten() {
+            if let Some(key) = key.as_str()
+                && !object.contains_key(key)
+            {
+                return Err(format!("{path} is missing required property `{key}`"));
+            }
+        }
+        let properties = schema["properties"].as_object();
+        for (key, item) in object {
+            match properties.and_then(|p| p.get(key)) {
+                Some(sub) => validate_at(item, sub, &format!("{path}.{key}"))?,
+                None if schema["additionalProperties"] == Value::Bool(false) => {
+                    return Err(format!("{path} has unexpected property `{key}`"));
+                }
+                None => {}
+            }
+        }
+    }
+    if let Some(array) = value.as_array()
+        && let Some(items) = schema.get("items").filter(|i| i.is_object())
+    {
+        for (i, item) in array.iter().enumerate() {
+            validate_at(item, items, &format!("{path}[{i}]"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Instruction for models that cannot be forced into structured output
+pub fn instruction(schema: &Value) -> String {
+    format!(
+        "Respond only with a JSON value that matches this JSON schema, without any other text or Markdown:\n{schema}"
+    )
+}
+
+/// Follow-up message asking the model to fix invalid structured output
+pub fn repair_prompt(error: &str) -> String {
+    format!(
+        "Your previous response did not match the requested JSON schema: {error}. Respond again with only the corrected JSON."
+    )
+}
+
+/// Removes keywords Gemini's `responseSchema` does not accept
+pub fn strip_for_gemini(schema: &mut Value) {
+    match schema {
+        Value::Object(map) => {
+            for key in GEMINI_UNSUPPORTED {
+                map.remove(key);
+            }
+            map.values_mut().for_each(strip_for_gemini);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_for_gemini),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_nested_schema() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "tags"],
+            "additionalProperties": false,
+            "properties": {
+                "name": { "type": "string" },
+                "tags": { "type": "array", "items": { "enum": ["a", "b"] } }
+            }
+        });
+        assert!(validate(&json!({ "name": "x", "tags": ["a"] }), &schema).is_ok());
+        assert_eq!(
+            validate(&json!({ "name": "x" }), &schema).unwrap_err(),
+            "$ is missing required property `tags`"
+        );
+        assert!(
+            validate(&json!({ "name": "x", "tags": ["c"] }), &schema)
+                .unwrap_err()
+                .starts_with("$.tags[0]")
+        );
+        assert!(validate(&json!({ "name": 1, "tags": [] }), &schema).is_err());
+    }
+
+    #[test]
+    fn extracts_fenced_json() {
+        assert_eq!(
+            extract_json("```json\n{\"a\": 1}\n```"),
+            Some(json!({ "a": 1 }))
+        );
+        assert_eq!(extract_json(" [1, 2] "), Some(json!([1, 2])));
+        assert_eq!(extract_json("not json"), None);
+    }
+}
@@ -1,7 +1,19 @@
-use axum::body::Body;
+pub mod backpressure;
+pub mod json_schema;
+pub mod retry;
+pub mod throttle;
+pub mod tokenizer;
+pub mod transcript;
+
+use std::time::Duration;
+
+use async_stream::stream;
+use axum::body::{Body, Bytes};
 use colored::{ColoredString, Colorize};
+use futures::StreamExt;
+use serde_json::json;
 use tokio::spawn;
-use tracing::error;
+use tracing::{error, warn};
 
 use crate::{
     config::{CLEWDR_CONFIG, LOG_DIR},
@@ -56,18 +68,94 @@ pub fn print_out_text(text: String, file_name: &str) {
 /// Timezone for the API
 pub const TIME_ZONE: &str = "America/New_York";
 
+/// Headers describing a single connection, never forwarded (RFC 9110, section 7.6.1)
+const HOP_BY_HOP: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Forwards an upstream response to the client
+///
+/// Hop-by-hop headers, and the headers `Connection` names, are dropped. The body fails when the
+/// upstream sends nothing for `stream_idle_timeout` seconds. An event stream ends with an SSE
+/// `error` event on such failures, so clients can tell it from a complete response. The
+/// upstream is dropped when the client falls behind for `stream_max_lag` seconds.
 pub fn forward_response(in_: wreq::Response) -> Result<http::Response<Body>, ClewdrError> {
     let status = in_.status();
     let header = in_.headers().to_owned();
-    let stream = in_.bytes_stream();
     let mut res = http::Response::builder().status(status);
 
+    let connection = header
+        .get_all(http::header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect::<Vec<_>>();
     let headers = res.headers_mut().unwrap();
-    for (key, value) in header {
-        if let Some(key) = key {
-            headers.insert(key, value);
+    for (key, value) in header.iter() {
+        let name = key.as_str();
+        if HOP_BY_HOP.contains(&name) || connection.iter().any(|c| c == name) {
+            continue;
         }
+        headers.append(key, value.to_owned());
     }
+    let is_sse = header
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
 
-    Ok(res.body(Body::from_stream(stream))?)
+    let idle = match CLEWDR_CONFIG.load().stream_idle_timeout {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+    let mut upstream = in_.bytes_stream();
+    let stream = stream! {
+        loop {
+            let next = match idle {
+                Some(idle) => match tokio::time::timeout(idle, upstream.next()).await {
+                    Ok(next) => next,
+                    Err(_) => Some(Err(format!(
+                        "Upstream sent nothing for {}s",
+                        idle.as_secs()
+                    ))),
+                },
+                None => upstream.next().await,
+            }
+            .map(|r| r.map_err(|e| format!("Upstream stream failed: {e}")));
+            match next {
+                Some(Ok(bytes)) => yield Ok(bytes),
+                Some(Err(message)) => {
+                    warn!("{}", message);
+                    if is_sse {
+                        yield Ok(sse_error(&message));
+                    } else {
+                        yield Err(std::io::Error::other(message));
+                    }
+                    break;
+                }
+                None => break,
+            }
+        }
+    };
+
+    Ok(res.body(Body::from_stream(backpressure::relay(stream, async {})))?)
+}
+
+/// SSE `error` event, shaped like the errors of the Claude API
+pub(crate) fn sse_error(message: &str) -> Bytes {
+    let error = json!({
+        "type": "error",
+        "error": {
+            "type": "UpstreamStreamError",
+            "message": message,
+        },
+    });
+    Bytes::from(format!("\n\nevent: error\ndata: {error}\n\n"))
 }
@@ -0,0 +1,109 @@
+use std::{
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use tracing::{debug, warn};
+
+use crate::config::CLEWDR_CONFIG;
+
+/// Length of the window the retry budget applies to
+const BUDGET_WINDOW: Duration = Duration::from_secs(60);
+
+/// Retries spent in the current budget window, shared by every request
+static BUDGET: LazyLock<Mutex<(Instant, u32)>> = LazyLock::new(|| Mutex::new((Instant::now(), 0)));
+
+/// Computes the delay before a retry
+///
+/// # Arguments
+/// * `attempt` - Retry number, starting at 1
+/// * `base` - Delay of the first retry in milliseconds
+/// * `multiplier` - Growth factor per attempt
+/// * `max` - Upper bound of the delay in milliseconds
+/// * `jitter` - Fraction of the delay to randomize, between 0 and 1
+/// * `roll` - Random value in `[0, 1)`
+///
+/// # Returns
+/// * `Duration` - The delay to wait
+fn compute_delay(
+    attempt: usize,
+    base: u64,
+    multiplier: f64,
+    max: u64,
+    jitter: f64,
+    roll: f64,
+) -> Duration {
+    let exp = attempt.saturating_sub(1).min(i32::MAX as usize) as i32;
+    let delay = (base as f64 * multiplier.max(1.0).powi(exp)).min(max as f64);
+    let jitter = jitter.clamp(0.0, 1.0);
+    // keep (1 - jitter) of the delay and randomize the rest
+    let delay = delay * (1.0 - jitter) + delay * jitter * roll;
+    Duration::from_millis(delay as u64)
+}
+
+/// Takes one retry from the global budget
+///
+/// # Returns
+/// * `bool` - Whether the retry is allowed
+fn take_budget(limit: u32) -> bool {
+    if limit == 0 {
+        return true;
+    }
+    let mut budget = BUDGET.lock().unwrap_or_else(|e| e.into_inner());
+    if budget.0.elapsed() >= BUDGET_WINDOW {
+        *budget = (Instant::now(), 0);
+    }
+    if budget.1 >= limit {
+        return false;
+    }
+    budget.1 += 1;
+    true
+}
+
+/// Waits before a retry according to the configured backoff policy
+///
+/// # Arguments
+/// * `attempt` - Retry number, starting at 1
+///
+/// # Returns
+/// * `bool` - `false` if the global retry budget is exhausted and the caller should give up
+pub async fn backoff(attempt: usize) -> bool {
+    let config = CLEWDR_CONFIG.load();
+    if !take_budget(config.retry_budget) {
+        warn!(
+            "[RETRY] budget of {} retries per minute exhausted",
+            config.retry_budget
+        );
+        return false;
+    }
+    let delay = compute_delay(
+        attempt,
+        config.retry_base_delay,
+        config.retry_multiplier,
+        config.retry_max_delay,
+        config.retry_jitter,
+        rand::random(),
+    );
+    drop(config);
+    debug!("[RETRY] attempt {} backing off for {:?}", attempt, delay);
+    tokio::time::sleep(delay).await;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_grows_and_caps() {
+        let d = |attempt, roll| compute_delay(attempt, 100, 2.0, 1000, 0.5, roll);
+        assert_eq!(d(1, 1.0), Duration::from_millis(100));
+        assert_eq!(d(2, 1.0), Duration::from_millis(200));
+        assert_eq!(d(3, 0.0), Duration::from_millis(200));
+        assert_eq!(d(10, 1.0), Duration::from_millis(1000));
+        assert_eq!(
+            compute_delay(3, 100, 2.0, 1000, 0.0, 0.3),
+            Duration::from_millis(400)
+        );
+    }
+}
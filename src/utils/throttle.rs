@@ -0,0 +1,117 @@
+use std::{
+    sync::{Arc, LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use moka::sync::Cache;
+use tracing::debug;
+
+use crate::{config::CLEWDR_CONFIG, error::ClewdrError};
+
+/// Token bucket refilling at `rpm` tokens per minute, holding at most `rpm` tokens
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(rpm: u32, now: Instant) -> Self {
+        Self {
+            tokens: rpm as f64,
+            updated: now,
+        }
+    }
+
+    /// Takes one token, going into debt when the bucket is empty
+    ///
+    /// # Returns
+    /// * `Duration` - How long the caller must wait until its token is refilled
+    fn take(&mut self, rpm: u32, now: Instant) -> Duration {
+        let rate = rpm as f64 / 60.0;
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rpm as f64);
+        self.updated = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
+    }
+
+    /// Returns a token taken by a caller that gave up
+    fn give_back(&mut self) {
+        self.tokens += 1.0;
+    }
+}
+
+/// Buckets per Claude organization UUID, idle organizations are forgotten
+static BUCKETS: LazyLock<Cache<String, Arc<Mutex<Bucket>>>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(10_000)
+        .time_to_idle(Duration::from_secs(10 * 60))
+        .build()
+});
+
+/// Waits for a request slot of a Claude organization according to `org_rpm`
+///
+/// Waits of up to `org_max_wait` seconds are slept through, longer ones fail.
+///
+/// # Arguments
+/// * `org_uuid` - Organization the upstream request is sent to
+///
+/// # Returns
+/// * `Err(ClewdrError::OrgThrottled)` - The organization is over its limit
+pub async fn acquire(org_uuid: &str) -> Result<(), ClewdrError> {
+    let config = CLEWDR_CONFIG.load();
+    let (rpm, max_wait) = (config.org_rpm, Duration::from_secs(config.org_max_wait));
+    drop(config);
+    if rpm == 0 {
+        return Ok(());
+    }
+    let now = Instant::now();
+    let bucket = BUCKETS.get_with_by_ref(org_uuid, || Arc::new(Mutex::new(Bucket::new(rpm, now))));
+    let wait = {
+        let mut bucket = bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let wait = bucket.take(rpm, now);
+        if wait > max_wait {
+            bucket.give_back();
+            return Err(ClewdrError::OrgThrottled {
+                retry_after: wait.as_secs().max(1),
+            });
+        }
+        wait
+    };
+    if !wait.is_zero() {
+        debug!("[THROTTLE] org {} waits {:?}", org_uuid, wait);
+        tokio::time::sleep(wait).await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(60, start);
+        for _ in 0..60 {
+            assert_eq!(bucket.take(60, start), Duration::ZERO);
+        }
+        // empty, the next token arrives a second later
+        assert_eq!(bucket.take(60, start), Duration::from_secs(1));
+        bucket.give_back();
+        let next = start + Duration::from_secs(1);
+        assert_eq!(bucket.take(60, next), Duration::ZERO);
+        assert_eq!(bucket.take(60, next), Duration::from_secs(1));
+        // refills never exceed the limit
+        let later = start + Duration::from_secs(3600);
+        for _ in 0..60 {
+            assert_eq!(bucket.take(60, later), Duration::ZERO);
+        }
+        assert!(bucket.take(60, later) > Duration::ZERO);
+    }
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use strum::Display;
+use tiktoken_rs::{CoreBPE, cl100k_base_singleton, o200k_base_singleton};
+
+/// Claude tokens per cl100k token
+///
+/// Claude's tokenizer is not public. Its counts run about a tenth above cl100k for English
+/// text and code, as reported by `count_tokens`.
+const CLAUDE_RATIO: f64 = 1.1;
+
+/// Tokenizers used to estimate tokens when the upstream cannot count them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Tokenizer {
+    /// GPT-4o and later OpenAI models
+    O200k,
+    /// GPT-3.5 and GPT-4
+    Cl100k,
+    /// Approximation of Claude's tokenizer
+    Claude,
+    /// Approximation of the tokenizer of Gemini and Gemma, counted with o200k
+    ///
+    /// No SentencePiece model ships with ClewdR, so the counts and pieces are o200k's,
+    /// which run close to the SentencePiece vocabulary for English text.
+    #[serde(alias = "gemma")]
+    GeminiApprox,
+}
+
+impl Tokenizer {
+    /// Tokenizer of the family of `model`, o200k for unknown models
+    pub fn for_model(model: &str) -> Self {
+        let model = model.to_ascii_lowercase();
+        if ["claude", "opus", "sonnet", "haiku"]
+            .iter()
+            .any(|f| model.contains(f))
+        {
+            return Self::Claude;
+        }
+        if model.contains("gemini") || model.contains("gemma") {
+            return Self::GeminiApprox;
+        }
+        match tiktoken_rs::tokenizer::get_tokenizer(&model) {
+            Some(tiktoken_rs::tokenizer::Tokenizer::Cl100kBase) => Self::Cl100k,
+            _ => Self::O200k,
+        }
+    }
+
+    fn bpe(self) -> &'static CoreBPE {
+        match self {
+            Self::O200k | Self::GeminiApprox => o200k_base_singleton(),
+            Self::Cl100k | Self::Claude => cl100k_base_singleton(),
+        }
+    }
+
+    /// Estimated tokens of `text`
+    pub fn count(self, text: &str) -> u32 {
+        let tokens = self.bpe().encode_with_special_tokens(text).len() as u32;
+        match self {
+            Self::Claude => (tokens as f64 * CLAUDE_RATIO).ceil() as u32,
+            _ => tokens,
+        }
+    }
+
+    /// Pieces `text` is split into by the underlying encoding
+    ///
+    /// For approximated tokenizers, these are the pieces of the encoding standing in for it,
+    /// so their number can differ from `count`.
+    pub fn split(self, text: &str) -> Vec<String> {
+        self.bpe()
+            .split_by_token_iter(text, true)
+            .flatten()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenizer_follows_model_family() {
+        assert_eq!(
+            Tokenizer::for_model("claude-sonnet-4-20250514"),
+            Tokenizer::Claude
+        );
+        assert_eq!(
+            Tokenizer::for_model("gemini-2.5-pro"),
+            Tokenizer::GeminiApprox
+        );
+        assert_eq!(Tokenizer::for_model("gpt-4-0613"), Tokenizer::Cl100k);
+        assert_eq!(Tokenizer::for_model("gpt-4o-mini"), Tokenizer::O200k);
+        assert_eq!(Tokenizer::for_model("my-alias"), Tokenizer::O200k);
+        let text = "Hello, world! This is a test.";
+        assert!(Tokenizer::Claude.count(text) > Tokenizer::Cl100k.count(text));
+        assert_eq!(Tokenizer::O200k.split(text).concat(), text);
+    }
+}
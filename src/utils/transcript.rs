@@ -0,0 +1,160 @@
+use std::sync::LazyLock;
+
+use axum::{body::Body, response::Response};
+use futures::TryStreamExt;
+use regex::Regex;
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    config::CLEWDR_CONFIG,
+    persistence::{self, Transcript},
+    types::claude::{
+        ContentBlock, ContentBlockDelta, CreateMessageParams, CreateMessageResponse, StreamEvent,
+        parse_usage,
+    },
+};
+
+/// Longest non-stream response body buffered for a transcript, in bytes
+const MAX_BODY_BYTES: usize = 4 << 20;
+/// Longest streamed completion kept in a transcript, in bytes
+const MAX_COMPLETION_BYTES: usize = 1 << 20;
+
+/// Claude cookies and API keys
+static SECRET_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"sk-ant-[A-Za-z0-9_-]+").unwrap());
+
+/// Masks Claude cookies and API keys in `text`
+pub fn redact(text: &str) -> String {
+    SECRET_RE.replace_all(text, "sk-ant-***").into_owned()
+}
+
+/// Starts the transcript of a Claude request, `None` unless transcripts are enabled
+///
+/// # Arguments
+/// * `provider` - Name of the provider serving the request
+/// * `params` - Request as sent upstream
+/// * `input_tokens` - Estimated prompt size, replaced by the upstream count when reported
+pub fn begin(
+    provider: &str,
+    params: &CreateMessageParams,
+    input_tokens: u32,
+) -> Option<Transcript> {
+    if !CLEWDR_CONFIG.load().transcripts_enabled() {
+        return None;
+    }
+    let prompt = json!({ "system": params.system, "messages": params.messages });
+    Some(Transcript {
+        created_at: chrono::Utc::now().timestamp(),
+        provider: provider.to_string(),
+        model: params.model.to_owned(),
+        prompt: redact(&prompt.to_string()),
+        input_tokens: input_tokens as u64,
+        ..Default::default()
+    })
+}
+
+/// Completes the transcript from the response while it is forwarded, it is stored once the body is dropped
+pub fn attach(transcript: Option<Transcript>, stream: bool, response: Response) -> Response {
+    let Some(transcript) = transcript.filter(|_| response.status().is_success()) else {
+        return response;
+    };
+    let mut tap = TranscriptTap {
+        transcript,
+        stream,
+        buf: Vec::new(),
+    };
+    response.map(|body| Body::from_stream(body.into_data_stream().inspect_ok(move |b| tap.feed(b))))
+}
+
+/// Collects the completion of a Claude response, the whole body or the text deltas of a stream
+struct TranscriptTap {
+    transcript: Transcript,
+    stream: bool,
+    buf: Vec<u8>,
+}
+
+impl TranscriptTap {
+    fn feed(&mut self, bytes: &[u8]) {
+        if !self.stream {
+            if self.buf.len() < MAX_BODY_BYTES {
+                self.buf.extend_from_slice(bytes);
+            }
+            return;
+        }
+        for &b in bytes {
+            if b != b'\n' {
+                self.buf.push(b);
+                continue;
+            }
+            let line = std::mem::take(&mut self.buf);
+            let Some(data) = line.strip_prefix(b"data:") else {
+                continue;
+            };
+            match serde_json::from_slice::<StreamEvent>(data.trim_ascii()) {
+                Ok(StreamEvent::MessageStart { message }) => {
+                    if let Some(usage) = message.usage {
+                        self.transcript.input_tokens = usage.input_tokens as u64;
+                    }
+                }
+                Ok(StreamEvent::ContentBlockDelta {
+                    delta: ContentBlockDelta::TextDelta { text },
+                    ..
+                }) if self.transcript.completion.len() < MAX_COMPLETION_BYTES => {
+                    self.transcript.completion.push_str(&text);
+                }
+                Ok(StreamEvent::MessageDelta {
+                    usage: Some(usage), ..
+                }) => {
+                    self.transcript.output_tokens = usage.output_tokens as u64;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for TranscriptTap {
+    fn drop(&mut self) {
+        let mut transcript = std::mem::take(&mut self.transcript);
+        if !self.stream {
+            let Ok(response) = serde_json::from_slice::<CreateMessageResponse>(&self.buf) else {
+                return;
+            };
+            transcript.completion = response
+                .content
+                .iter()
+                .filter_map(|b| match b {
+                    ContentBlock::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect();
+            if let Some((input, output)) = parse_usage(&self.buf) {
+                // without reported usage the estimated prompt size stays
+                if input > 0 {
+                    transcript.input_tokens = input;
+                }
+                transcript.output_tokens = output;
+            }
+        }
+        transcript.completion = redact(&transcript.completion);
+        tokio::spawn(async move {
+            if let Err(e) = persistence::storage().persist_transcript(&transcript).await {
+                warn!("Failed to store transcript: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_cookies_and_keys() {
+        assert_eq!(
+            redact("cookie sk-ant-sid01-abc_DEF-123 and key sk-ant-api03-xyz."),
+            "cookie sk-ant-*** and key sk-ant-***."
+        );
+        assert_eq!(redact("nothing secret"), "nothing secret");
+    }
+}
@@ -0,0 +1,39 @@
+//! Replays the recorded upstream streams in `tests/fixtures` and compares the output
+//! with their golden files, run with `cargo test --features test-fixtures`
+#![cfg(feature = "test-fixtures")]
+
+use std::path::Path;
+
+use clewdr::replay::{FixtureFormat, check, parse_oai};
+
+#[tokio::test]
+async fn fixtures_match_goldens() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut replayed = 0;
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if FixtureFormat::from_path(&path).is_none() {
+            continue;
+        }
+        if let Err(e) = check(&path, false).await {
+            panic!("{e}");
+        }
+        replayed += 1;
+    }
+    assert!(replayed > 0, "no fixtures found");
+}
+
+#[tokio::test]
+async fn tool_calls_are_reassembled() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tool_use.claude.sse");
+    let parsed = parse_oai(&check(&fixture, false).await.unwrap()).unwrap();
+    assert_eq!(parsed.content, "Let me check the weather.");
+    assert_eq!(
+        parsed.tool_calls,
+        [(
+            "get_weather".to_string(),
+            r#"{"location": "San Francisco, CA"}"#.to_string()
+        )]
+    );
+    assert_eq!(parsed.finish_reason.as_deref(), Some("tool_calls"));
+}